@@ -0,0 +1,118 @@
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Targets whose compilers (GCC and Clang) accept the private-member access in nds_wifi.cpp
+const WIFI_TARGETS: [&str; 2] = ["linux", "macos"];
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/nds_slot2.cpp");
+    println!("cargo:rerun-if-changed=src/nds_wifi.cpp");
+    println!("cargo:rerun-if-env-changed=DESMUME_SRC_DIR");
+    println!("cargo::rustc-check-cfg=cfg(nds_wifi)");
+    cc::Build::new()
+        .cpp(true)
+        .file("src/nds_slot2.cpp")
+        .compile("nds_slot2");
+
+    if env::var_os("CARGO_FEATURE_NDS_WIFI").is_some() {
+        build_wifi_shim();
+    }
+}
+
+/// Builds the local wireless shim and sets `cfg(nds_wifi)`, or leaves the build without it
+/// with a warning when the target or DeSmuME's sources rule it out
+fn build_wifi_shim() {
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    if !WIFI_TARGETS.contains(&target_os.as_str()) {
+        println!("cargo:warning=nds-wifi is not supported on {target_os}, building without it");
+        return;
+    }
+    let desmume = match desmume_source_dir() {
+        Ok(dir) => dir,
+        Err(err) => {
+            println!("cargo:warning=building without nds-wifi: {err}");
+            return;
+        }
+    };
+    // DeSmuME's headers are included as system headers so their warnings stay out of the
+    // build, the shim's own are still reported
+    cc::Build::new()
+        .cpp(true)
+        .file("src/nds_wifi.cpp")
+        .flag(format!("-isystem{}", desmume.display()))
+        .flag(format!(
+            "-isystem{}",
+            desmume.join("libretro-common/include").display()
+        ))
+        .compile("nds_wifi");
+    println!("cargo:rustc-cfg=nds_wifi");
+}
+
+/// The Wi-Fi shim needs DeSmuME's headers, which desmume-sys does not export. Use
+/// `DESMUME_SRC_DIR` when set, otherwise the sources of the desmume-sys that Cargo.lock
+/// resolved, wherever cargo keeps them (registry, vendor directory or git checkout).
+fn desmume_source_dir() -> Result<PathBuf, String> {
+    if let Some(dir) = env::var_os("DESMUME_SRC_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+
+    let cargo = env::var_os("CARGO").ok_or("CARGO is not set")?;
+    let manifest = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").ok_or("no manifest dir")?)
+        .join("Cargo.toml");
+    let output = Command::new(cargo)
+        .args([
+            "metadata",
+            "--format-version",
+            "1",
+            "--frozen",
+            "--manifest-path",
+        ])
+        .arg(&manifest)
+        .output()
+        .map_err(|err| format!("failed to run cargo metadata: {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "cargo metadata failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .map_err(|err| format!("unreadable cargo metadata: {err}"))?;
+
+    let packages = metadata["packages"].as_array().ok_or("no packages")?;
+    let desmume_rs = packages
+        .iter()
+        .find(|package| package["name"] == "desmume-rs")
+        .map(|package| &package["id"])
+        .ok_or("desmume-rs is not a dependency")?;
+    // the desmume-sys desmume-rs was resolved against, not just any version cargo has seen
+    let desmume_sys = metadata["resolve"]["nodes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|node| node["id"] == *desmume_rs)
+        .and_then(|node| node["deps"].as_array())
+        .into_iter()
+        .flatten()
+        .find(|dep| dep["name"] == "desmume_sys")
+        .map(|dep| &dep["pkg"])
+        .ok_or("desmume-sys is not in the dependency graph")?;
+    let manifest_path = packages
+        .iter()
+        .find(|package| package["id"] == *desmume_sys)
+        .and_then(|package| package["manifest_path"].as_str())
+        .ok_or("desmume-sys has no manifest path")?;
+
+    let src = PathBuf::from(manifest_path)
+        .with_file_name("desmume")
+        .join("desmume/src");
+    if src.join("NDSSystem.h").is_file() {
+        Ok(src)
+    } else {
+        Err(format!(
+            "DeSmuME headers not found in {}, set DESMUME_SRC_DIR to desmume/desmume/src",
+            src.display()
+        ))
+    }
+}
@@ -29,6 +29,19 @@ const CB_INSTRUCTION_TIMINGS: [i32; 256] = [
     8, 8, 8, 8, 8, 8, 16, 8, 8, 8, 8, 8, 8, 8, 16, 8,
 ];
 
+/// Snapshot of the CPU registers for debuggers
+#[derive(Clone, Copy, Debug)]
+pub struct CpuState {
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub sp: u16,
+    pub pc: u16,
+    pub halted: bool,
+    pub interrupts_enabled: bool,
+}
+
 pub struct Cpu {
     registers: Registers,
     halted: bool,
@@ -74,6 +87,19 @@ impl Cpu {
         }
     }
 
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            af: self.registers.get_af(),
+            bc: self.registers.get_bc(),
+            de: self.registers.get_de(),
+            hl: self.registers.get_hl(),
+            sp: self.registers.sp,
+            pc: self.registers.pc,
+            halted: self.halted,
+            interrupts_enabled: self.interrupt_enabled,
+        }
+    }
+
     pub fn are_interrupts_enabled(&self) -> bool {
         self.interrupt_enabled
     }
@@ -3,7 +3,7 @@ pub mod traits;
 
 use self::step_result::StepResult;
 use self::traits::{PixelMapper, RTC};
-use crate::cpu::Cpu;
+use crate::cpu::{Cpu, CpuState};
 use crate::gpu::GPU;
 use crate::joypad::Controller;
 use crate::mmu::cartridge::Cartridge;
@@ -82,6 +82,10 @@ impl Emulator {
         self.memory.get_cartridge_mut()
     }
 
+    pub fn is_rumbling(&self) -> bool {
+        self.memory.is_rumbling()
+    }
+
     pub fn get_audio_buffer(&self) -> &[f32] {
         self.memory.get_sound().get_audio_buffer()
     }
@@ -90,6 +94,14 @@ impl Emulator {
         self.memory.read_byte(address)
     }
 
+    pub fn cpu_state(&self) -> CpuState {
+        self.cpu.state()
+    }
+
+    pub fn poke_byte(&mut self, address: u16, value: u8) {
+        self.memory.write_byte(address, value)
+    }
+
     pub fn peek_block(&self, start: u16, buf: &mut [u8]) {
         for (offset, byte) in buf.iter_mut().enumerate() {
             let addr = start.wrapping_add(offset as u16);
@@ -12,6 +12,7 @@ mod timer;
 
 pub use crate::button::Button;
 pub use crate::controller_event::ControllerEvent;
+pub use crate::cpu::CpuState;
 pub use crate::emulator::step_result::StepResult;
 pub use crate::emulator::traits::{PixelMapper, RTC};
 
@@ -50,9 +51,21 @@ impl Gameboy {
     pub fn set_ram_change_callback(&mut self, f: Box<dyn FnMut(usize, u8)>) {
         self.emulator.set_ram_change_callback(f)
     }
+    /// Whether a rumble cartridge has its motor switched on
+    pub fn is_rumbling(&self) -> bool {
+        self.emulator.is_rumbling()
+    }
+    /// Registers of the instruction about to run
+    pub fn cpu_state(&self) -> CpuState {
+        self.emulator.cpu_state()
+    }
     pub fn peek_byte(&self, address: u16) -> u8 {
         self.emulator.peek_byte(address)
     }
+    /// Writes a byte the way the CPU would, so I/O registers and bank switches react to it
+    pub fn poke_byte(&mut self, address: u16, value: u8) {
+        self.emulator.poke_byte(address, value)
+    }
     pub fn peek_block(&self, start: u16, buf: &mut [u8]) {
         self.emulator.peek_block(start, buf)
     }
@@ -7,6 +7,7 @@ pub struct Cartridge {
     ram_size: usize,
     has_rtc: bool,
     has_battery: bool,
+    has_rumble: bool,
     rom: Vec<u8>,
     ram: Vec<u8>,
     name: String,
@@ -52,6 +53,7 @@ impl Cartridge {
             | 0xFF => true,
             _ => false,
         };
+        let has_rumble = matches!(cartridge_type, 0x1C..=0x1E);
 
         let mut name = String::new();
         let mut name_index = 0x0134;
@@ -75,6 +77,7 @@ impl Cartridge {
             ram_size,
             has_rtc,
             has_battery,
+            has_rumble,
             rom,
             ram,
             name,
@@ -115,6 +118,10 @@ impl Cartridge {
         self.has_battery
     }
 
+    pub fn has_rumble(&self) -> bool {
+        self.has_rumble
+    }
+
     pub fn get_rom(&self) -> &[u8] {
         self.rom.as_ref()
     }
@@ -6,4 +6,8 @@ pub trait Mbc {
     fn get_cartridge(&self) -> &Cartridge;
     fn get_cartridge_mut(&mut self) -> &mut Cartridge;
     fn set_ram_change_callback(&mut self, f: Box<dyn FnMut(usize, u8)>);
+    /// Whether the cartridge's rumble motor is spinning
+    fn is_rumbling(&self) -> bool {
+        false
+    }
 }
@@ -7,6 +7,7 @@ pub struct Mbc5 {
     selected_rom_bank_high: usize,
     selected_eram_bank: usize,
     external_ram_enabled: bool,
+    rumbling: bool,
     ram_change_callback: Box<dyn FnMut(usize, u8)>,
 }
 
@@ -49,8 +50,17 @@ impl Mbc for Mbc5 {
                 self.selected_rom_bank &= self.cartridge.get_rom_banks() - 1
             }
             0x4000..=0x5FFF => {
-                self.selected_eram_bank = usize::from(value) & 0x0F;
-                self.selected_eram_bank &= self.cartridge.get_ram_banks() - 1;
+                // rumble carts wire bit 3 to the motor instead of the RAM bank
+                if self.cartridge.has_rumble() {
+                    self.rumbling = value & 0x08 != 0;
+                    self.selected_eram_bank = usize::from(value) & 0x07;
+                } else {
+                    self.selected_eram_bank = usize::from(value) & 0x0F;
+                }
+                // a cart without RAM (like rumble type 0x1C) has no banks to mask with
+                if let Some(mask) = self.cartridge.get_ram_banks().checked_sub(1) {
+                    self.selected_eram_bank &= mask;
+                }
             }
             0x6000..=0x7FFF => (),
             0xA000..=0xBFFF => {
@@ -78,6 +88,10 @@ impl Mbc for Mbc5 {
     fn set_ram_change_callback(&mut self, f: Box<dyn FnMut(usize, u8)>) {
         self.ram_change_callback = f;
     }
+
+    fn is_rumbling(&self) -> bool {
+        self.rumbling
+    }
 }
 
 impl Mbc5 {
@@ -88,6 +102,7 @@ impl Mbc5 {
             selected_rom_bank_high: 0,
             selected_eram_bank: 0,
             external_ram_enabled: false,
+            rumbling: false,
             ram_change_callback: Box::new(|_, _| {}),
         }
     }
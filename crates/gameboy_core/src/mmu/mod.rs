@@ -798,6 +798,10 @@ impl Memory {
         self.mbc.get_cartridge_mut()
     }
 
+    pub fn is_rumbling(&self) -> bool {
+        self.mbc.is_rumbling()
+    }
+
     pub fn get_sound(&self) -> &Sound {
         &self.sound
     }
@@ -1,14 +1,21 @@
+mod disc;
+
 use crate::{
     memory::{interrupts::InterruptRequester, BusLine, Result},
     spu::Spu,
+    state::{
+        impl_savestate, impl_savestate_bits, invalid, Savestate, StateReader, StateResult,
+        StateWriter,
+    },
     PsxError,
 };
 use bitflags::bitflags;
+use disc::{Disc, TrackKind, LEAD_IN_SECTORS, SECTOR_SIZE};
+
+pub use disc::{DiscInfo, DiscRegion};
 
 use std::{
     collections::VecDeque,
-    fs,
-    io::Read,
     path::{Path, PathBuf},
 };
 
@@ -61,10 +68,41 @@ enum ActionStatus {
         second_delivery_attempt: bool,
     },
     Seek,
-    #[allow(dead_code)]
     Play,
 }
 
+impl Savestate for ActionStatus {
+    fn save(&self, w: &mut StateWriter) {
+        let (kind, second_delivery_attempt) = match *self {
+            ActionStatus::None => (0u8, false),
+            ActionStatus::Read {
+                second_delivery_attempt,
+            } => (1, second_delivery_attempt),
+            ActionStatus::Seek => (2, false),
+            ActionStatus::Play => (3, false),
+        };
+        kind.save(w);
+        second_delivery_attempt.save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        let mut kind = 0u8;
+        let mut second_delivery_attempt = false;
+        kind.load(r)?;
+        second_delivery_attempt.load(r)?;
+        *self = match kind {
+            0 => ActionStatus::None,
+            1 => ActionStatus::Read {
+                second_delivery_attempt,
+            },
+            2 => ActionStatus::Seek,
+            3 => ActionStatus::Play,
+            _ => return Err(invalid(format!("invalid cdrom action {}", kind))),
+        };
+        Ok(())
+    }
+}
+
 #[derive(Default, Debug)]
 struct CdromStatus {
     bit_status: BitCdromStatus,
@@ -73,6 +111,13 @@ struct CdromStatus {
     shell_open: bool,
 }
 
+impl_savestate!(CdromStatus {
+    bit_status,
+    action_status,
+    error,
+    shell_open,
+});
+
 impl CdromStatus {
     fn bits(&self) -> u8 {
         if self.error {
@@ -159,6 +204,8 @@ bitflags! {
     }
 }
 
+impl_savestate_bits!(FifosStatus, BitCdromStatus, CdromMode);
+
 const ADPCM_TABLE_POS: &[i32; 4] = &[0, 60, 115, 98];
 const ADPCM_TABLE_NEG: &[i32; 4] = &[0, 0, -52, -55];
 
@@ -170,6 +217,8 @@ struct AdpcmDecoder {
     older: i32,
 }
 
+impl_savestate!(AdpcmDecoder { old, older });
+
 impl AdpcmDecoder {
     /// Decode XA-ADPCM block
     ///
@@ -286,6 +335,12 @@ struct AdpcmInterpolator {
     sixstep_counter: usize,
 }
 
+impl_savestate!(AdpcmInterpolator {
+    samples_ringbuf,
+    samples_i,
+    sixstep_counter,
+});
+
 impl Default for AdpcmInterpolator {
     fn default() -> Self {
         Self {
@@ -341,11 +396,30 @@ fn from_bcd(arg: u8) -> u8 {
     ((arg & 0xF0) >> 4) * 10 + (arg & 0x0F)
 }
 
+/// Converts a sector count into (minutes, seconds, sectors)
+fn sector_to_msf(sectors: usize) -> (u8, u8, u8) {
+    let total_seconds = sectors / 75;
+    (
+        (total_seconds / 60) as u8,
+        (total_seconds % 60) as u8,
+        (sectors % 75) as u8,
+    )
+}
+
 /// Utility function to convert value from from normal format to bcd
 fn to_bcd(arg: u8) -> u8 {
     ((arg / 10) << 4) | (arg % 10)
 }
 
+/// Reads the first sectors of the disc image at `path` to identify it
+pub(crate) fn probe_disc(path: &Path) -> std::result::Result<DiscInfo, PsxError> {
+    let disc = Disc::open_head(path)?;
+    Ok(DiscInfo {
+        region: disc.region(),
+        serial: disc.serial(),
+    })
+}
+
 pub struct Cdrom {
     index: u8,
     fifo_status: FifosStatus,
@@ -365,9 +439,8 @@ pub struct Cdrom {
     /// The type and design might change later
     command_state: Option<u8>,
 
-    cue_file: Option<PathBuf>,
-    cue_file_content: String,
-    disk_data: Vec<u8>,
+    disc_file: Option<PathBuf>,
+    disc: Disc,
 
     // commands save buffer
     // params: minutes, seconds, sector (on entire disk)
@@ -406,6 +479,45 @@ pub struct Cdrom {
     cd_mute: bool,
 }
 
+// the disc itself is not saved, only checked to be the same one
+impl_savestate!(Cdrom {
+    index,
+    fifo_status,
+    status,
+    interrupt_enable,
+    interrupt_flag,
+    parameter_fifo,
+    response_fifo,
+    command,
+    command_delay_timer,
+    read_play_delay_timer,
+    command_state,
+    disc,
+    set_loc_params,
+    cursor_sector_position,
+    mode,
+    data_fifo_buffer,
+    read_data_buffer,
+    data_fifo_buffer_index,
+    filter_file,
+    filter_channel,
+    last_locl_response,
+    adpcm_decoder_left_mono,
+    adpcm_decoder_right,
+    adpcm_interpolator_left_mono,
+    adpcm_interpolator_right,
+    input_cd_left_to_spu_left,
+    input_cd_left_to_spu_right,
+    input_cd_right_to_spu_left,
+    input_cd_right_to_spu_right,
+    vol_cd_left_to_spu_left,
+    vol_cd_left_to_spu_right,
+    vol_cd_right_to_spu_left,
+    vol_cd_right_to_spu_right,
+    adpcm_mute,
+    cd_mute,
+});
+
 impl Default for Cdrom {
     fn default() -> Self {
         Self {
@@ -420,10 +532,9 @@ impl Default for Cdrom {
             command_delay_timer: 0,
             read_play_delay_timer: 0,
             command_state: None,
-            cue_file: None,
+            disc_file: None,
             // empty vectors are not allocated
-            cue_file_content: String::new(),
-            disk_data: Vec::new(),
+            disc: Disc::default(),
 
             set_loc_params: None,
             cursor_sector_position: 0,
@@ -462,76 +573,24 @@ impl Default for Cdrom {
 // file reading and handling
 impl Cdrom {
     pub fn reset(&mut self) {
-        let cue_file = self.cue_file.take();
+        // keep the disc inserted, no need to load it again
+        let disc_file = self.disc_file.take();
+        let disc = std::mem::take(&mut self.disc);
         let _ = std::mem::take(self);
-        if let Some(cue_file) = cue_file {
-            let _ = self.set_cue_file(cue_file);
+        if disc_file.is_some() {
+            self.status.start_motor();
         }
+        self.disc_file = disc_file;
+        self.disc = disc;
     }
 
-    pub fn set_cue_file<P: AsRef<Path>>(&mut self, cue_file: P) -> Result<(), PsxError> {
-        let a = cue_file.as_ref().to_path_buf();
-        self.load_cue_file(&a)?;
-        self.cue_file = Some(a);
-        Ok(())
-    }
-
-    fn load_cue_file(&mut self, cue_file: &Path) -> Result<(), PsxError> {
-        macro_rules! parse_expect {
-            ($var:ident, $expected:expr) => {
-                if $var.next().unwrap_or("") != $expected {
-                    return Err(PsxError::CouldNotLoadDisk(
-                        concat!("Invalid cue file: at ", $expected).to_string(),
-                    ));
-                }
-            };
-        }
-
-        // TODO: support parsing and loading the data based on the cue file
+    /// Loads a `.cue`, `.pbp`, or a loose `.bin`/`.img`/`.iso` image
+    pub fn set_disc_file<P: AsRef<Path>>(&mut self, disc_file: P) -> Result<(), PsxError> {
         // TODO: since some Cds can be large, try to do mmap
+        let path = disc_file.as_ref().to_path_buf();
+        self.disc = Disc::open(&path)?;
+        self.disc_file = Some(path);
         self.status.start_motor();
-
-        // read cue file
-        let mut file =
-            fs::File::open(cue_file).map_err(|e| PsxError::CouldNotLoadDisk(e.to_string()))?;
-        let mut cue_content = String::new();
-        file.read_to_string(&mut cue_content)
-            .map_err(|e| PsxError::CouldNotLoadDisk(e.to_string()))?;
-        // parse cue format
-        let mut parts = cue_content.split_whitespace();
-        parse_expect!(parts, "FILE");
-        let mut bin_file_name = parts
-            .next()
-            .ok_or_else(|| PsxError::CouldNotLoadDisk("Doesn't have bin filename".to_string()))?
-            .to_string();
-        // must be in quotes
-        assert!(bin_file_name.starts_with('"'));
-        while !bin_file_name.ends_with('"') {
-            bin_file_name.push(' ');
-            bin_file_name.push_str(parts.next().unwrap());
-        }
-        // remove quotes
-        bin_file_name = bin_file_name.trim_matches('"').to_string();
-        parse_expect!(parts, "BINARY");
-        parse_expect!(parts, "TRACK");
-        parse_expect!(parts, "01");
-        parse_expect!(parts, "MODE2/2352");
-        parse_expect!(parts, "INDEX");
-        parse_expect!(parts, "01");
-        parse_expect!(parts, "00:00:00");
-
-        // load bin file
-        let bin_file_path = cue_file.parent().unwrap().join(bin_file_name);
-        log::info!("Loading bin file: {:?}", bin_file_path);
-        let mut file =
-            fs::File::open(bin_file_path).map_err(|e| PsxError::CouldNotLoadDisk(e.to_string()))?;
-        // read to new vector
-        let mut bin_file_content = Vec::new();
-        file.read_to_end(&mut bin_file_content)
-            .map_err(|e| PsxError::CouldNotLoadDisk(e.to_string()))?;
-        self.cue_file_content = cue_content;
-        self.disk_data = bin_file_content;
-
         Ok(())
     }
 
@@ -564,7 +623,11 @@ impl Cdrom {
         }
 
         if self.handle_reading_delay(cycles) {
-            self.handle_reading_data(spu);
+            if self.status.action_status == ActionStatus::Play {
+                self.handle_playing_audio(spu);
+            } else {
+                self.handle_reading_data(spu);
+            }
         }
 
         // fire irq only if the interrupt is enabled
@@ -632,6 +695,39 @@ impl Cdrom {
 
                 self.reset_command();
             }
+            0x03 => {
+                // Play
+
+                // optional track number, `0` or missing means the `SetLoc` position
+                let track = self.read_next_parameter().map(from_bcd).unwrap_or(0);
+                log::info!("cdrom cmd: Play(track={})", track);
+
+                let start = self
+                    .disc
+                    .tracks()
+                    .iter()
+                    .find(|t| t.number == track)
+                    .map(|t| t.start);
+                if let Some(start) = start {
+                    self.set_loc_params = None;
+                    self.cursor_sector_position = start;
+                } else {
+                    self.do_seek();
+                }
+                self.status.reset_action_status();
+                self.status.action_status = ActionStatus::Play;
+
+                self.set_response(self.status.bits());
+                self.request_interrupt_0_7(3);
+
+                self.read_play_delay_timer = if self.mode.intersects(CdromMode::DOUBLE_SPEED) {
+                    CDROM_READ_PLAY_DELAY / 2
+                } else {
+                    CDROM_READ_PLAY_DELAY
+                };
+
+                self.reset_command();
+            }
             0x06 | 0x1B => {
                 // ReadN/ReadS
 
@@ -803,30 +899,39 @@ impl Cdrom {
             0x11 => {
                 // GetLocP
 
-                // TODO: fix when supporting multiple tracks
-                //       also, min, second, sectors below as well
-
                 log::info!("cdrom cmd: GetLocP");
-                let track = 1;
-                let index = 1;
-
-                let sector = self.cursor_sector_position % 75;
-                let total_seconds = (self.cursor_sector_position / 75) + 2;
-                let minutes = total_seconds / 60;
-                let seconds = total_seconds % 60;
-
-                self.set_response_slice(&[
-                    to_bcd(track),
-                    to_bcd(index),
-                    // track
-                    to_bcd(minutes as u8),
-                    to_bcd(seconds as u8),
-                    to_bcd(sector as u8),
-                    // whole disk
-                    to_bcd(minutes as u8),
-                    to_bcd(seconds as u8),
-                    to_bcd(sector as u8),
-                ]);
+                let position = self.cursor_sector_position;
+                // LibCrypt checks for the sectors it modified on the disc
+                let response = if let Some(&q) = self.disc.subchannel_q(position) {
+                    log::info!("cdrom: modified subchannel Q at sector {}", position);
+                    // the control byte and the zero between the MSFs are not returned
+                    [q[1], q[2], q[3], q[4], q[5], q[7], q[8], q[9]]
+                } else {
+                    let (track, track_start) = self
+                        .disc
+                        .track_at(position)
+                        .map_or((1, 0), |t| (t.number, t.start));
+                    // index 0 is the pregap before the track starts
+                    let index = if position < track_start { 0 } else { 1 };
+
+                    let (track_minutes, track_seconds, track_sector) =
+                        sector_to_msf(position.abs_diff(track_start));
+                    let (minutes, seconds, sector) = sector_to_msf(position + LEAD_IN_SECTORS);
+
+                    [
+                        to_bcd(track),
+                        to_bcd(index),
+                        // track
+                        to_bcd(track_minutes),
+                        to_bcd(track_seconds),
+                        to_bcd(track_sector),
+                        // whole disk
+                        to_bcd(minutes),
+                        to_bcd(seconds),
+                        to_bcd(sector),
+                    ]
+                };
+                self.set_response_slice(&response);
 
                 self.request_interrupt_0_7(3);
 
@@ -834,11 +939,10 @@ impl Cdrom {
             }
             0x13 => {
                 // GetTN
-                // TODO: fix when supporting multiple tracks
 
                 log::info!("cdrom cmd: GetTN");
-                let first_track = 1;
-                let last_track = 1;
+                let first_track = self.disc.tracks().first().map_or(1, |t| t.number);
+                let last_track = self.disc.tracks().last().map_or(1, |t| t.number);
 
                 self.set_response_slice(&[
                     self.status.bits(),
@@ -852,35 +956,35 @@ impl Cdrom {
             }
             0x14 => {
                 // GetTD
-                // TODO: fix when supporting multiple tracks
 
                 let track = from_bcd(self.read_next_parameter().unwrap());
 
                 log::info!("cdrom cmd: GetTD: track = {}", track);
 
-                let res_minutes;
-                let res_seconds;
-                // return the end of the last track
-                if track == 0 {
-                    let total_disk_size = self.disk_data.len();
-                    let total_sectors = total_disk_size / 2352;
-                    let total_seconds = total_sectors / 75;
-                    res_minutes = (total_seconds / 60) as u8;
-                    res_seconds = (total_seconds % 60) as u8;
-                } else if track == 1 {
-                    res_minutes = 0;
-                    res_seconds = 2;
+                // track 0 is the end of the last track
+                let position = if track == 0 {
+                    Some(self.disc.sector_count())
                 } else {
-                    todo!("Doesn't support more than 1 track");
-                }
-
-                self.set_response_slice(&[
-                    self.status.bits(),
-                    to_bcd(res_minutes),
-                    to_bcd(res_seconds),
-                ]);
+                    self.disc
+                        .tracks()
+                        .iter()
+                        .find(|t| t.number == track)
+                        .map(|t| t.start)
+                };
 
-                self.request_interrupt_0_7(3);
+                if let Some(position) = position {
+                    let (res_minutes, res_seconds, _) = sector_to_msf(position + LEAD_IN_SECTORS);
+                    self.set_response_slice(&[
+                        self.status.bits(),
+                        to_bcd(res_minutes),
+                        to_bcd(res_seconds),
+                    ]);
+                    self.request_interrupt_0_7(3);
+                } else {
+                    // invalid parameter
+                    self.set_response_slice(&[self.status.bits() | 1, 0x10]);
+                    self.request_interrupt_0_7(5);
+                }
 
                 self.reset_command();
             }
@@ -950,7 +1054,7 @@ impl Cdrom {
                     // SECOND
                     // TODO: rewrite GetID implementation to fill
                     //       all the details correctly from the state of the cdrom
-                    let (response, interrupt) = if self.cue_file.is_some() {
+                    let (response, interrupt) = if self.disc_file.is_some() {
                         // last byte is the region code identifier
                         // A(0x41): NTSC
                         // E(0x45): PAL
@@ -988,9 +1092,12 @@ impl Cdrom {
     }
 
     fn handle_reading_delay(&mut self, cycles: u32) -> bool {
-        let ActionStatus::Read { .. } = &mut self.status.action_status else {
+        if !matches!(
+            self.status.action_status,
+            ActionStatus::Read { .. } | ActionStatus::Play
+        ) {
             return false;
-        };
+        }
 
         // delay
         if self.read_play_delay_timer > cycles + 1 {
@@ -1022,10 +1129,10 @@ impl Cdrom {
             unreachable!()
         };
 
-        let sector_start = self.cursor_sector_position * 2352;
+        let sector_start = self.cursor_sector_position * SECTOR_SIZE;
 
         // skip the sync bytes
-        let whole_sector = &self.disk_data[sector_start + 12..sector_start + 0x930];
+        let whole_sector = &self.disc.data()[sector_start + 12..sector_start + 0x930];
 
         // TODO: add filtering and coding info handling
         let mode = whole_sector[3];
@@ -1183,8 +1290,8 @@ impl Cdrom {
         coding_info: CodingInfo,
         spu: &mut Spu,
     ) {
-        let sector_start = sector_position * 2352;
-        let data = &self.disk_data[sector_start + 24..sector_start + 24 + 0x900];
+        let sector_start = sector_position * SECTOR_SIZE;
+        let data = &self.disc.data()[sector_start + 24..sector_start + 24 + 0x900];
 
         let sample_8bit = coding_info.intersects(CodingInfo::BITS_PER_SAMPLE);
 
@@ -1234,17 +1341,88 @@ impl Cdrom {
                 }
             }
         }
-        let mut spu_audio_left = vec![0; cd_audio_left.len()];
-        let mut spu_audio_right = vec![0; cd_audio_left.len()];
-
         let (audio_left, audio_right) = if coding_info.intersects(CodingInfo::STEREO) {
             (&cd_audio_left, &cd_audio_right)
         } else {
             (&cd_audio_left, &cd_audio_left)
         };
 
-        if !self.cd_mute && !self.adpcm_mute {
-            for (i, (&left, &right)) in audio_left.iter().zip(audio_right.iter()).enumerate() {
+        let muted = self.cd_mute || self.adpcm_mute;
+        self.mix_cd_audio_to_spu(audio_left, audio_right, muted, spu);
+    }
+
+    /// Plays one sector of a CD-DA track, 588 stereo samples at 44100Hz
+    fn handle_playing_audio(&mut self, spu: &mut Spu) {
+        let position = self.cursor_sector_position;
+        if position >= self.disc.sector_count() {
+            log::info!("cdrom: Play: reached the end of the disc");
+            self.status.reset_action_status();
+            self.set_response(self.status.bits());
+            self.request_interrupt_0_7(4);
+            return;
+        }
+
+        let (track, kind) = self
+            .disc
+            .track_at(position)
+            .map_or((None, TrackKind::Data), |t| (Some(t.number), t.kind));
+        if self.mode.intersects(CdromMode::AUTO_PAUSE)
+            && position > 0
+            && self.disc.track_at(position - 1).map(|t| t.number) != track
+        {
+            log::info!("cdrom: Play: auto pause at the end of the track");
+            self.status.reset_action_status();
+            self.set_response(self.status.bits());
+            self.request_interrupt_0_7(4);
+            return;
+        }
+
+        let sector_start = position * SECTOR_SIZE;
+        let (left, right): (Vec<i16>, Vec<i16>) = self.disc.data()
+            [sector_start..sector_start + SECTOR_SIZE]
+            .chunks_exact(4)
+            .map(|s| {
+                (
+                    i16::from_le_bytes([s[0], s[1]]),
+                    i16::from_le_bytes([s[2], s[3]]),
+                )
+            })
+            .unzip();
+        // data tracks are not sent to the audio output
+        let muted = self.cd_mute || kind == TrackKind::Data;
+        self.mix_cd_audio_to_spu(&left, &right, muted, spu);
+
+        self.cursor_sector_position += 1;
+
+        // report the position every 10 sectors
+        if self.mode.intersects(CdromMode::REPORT_INTERRUPT_ENABLE) && position.is_multiple_of(10) {
+            let (minutes, seconds, sector) = sector_to_msf(position + LEAD_IN_SECTORS);
+            let peak = left
+                .iter()
+                .chain(right.iter())
+                .map(|s| s.unsigned_abs())
+                .max()
+                .unwrap_or(0);
+            self.set_response_slice(&[
+                self.status.bits(),
+                to_bcd(track.unwrap_or(1)),
+                1,
+                to_bcd(minutes),
+                to_bcd(seconds),
+                to_bcd(sector),
+                peak as u8,
+                (peak >> 8) as u8,
+            ]);
+            self.request_interrupt_0_7(1);
+        }
+    }
+
+    fn mix_cd_audio_to_spu(&self, left: &[i16], right: &[i16], muted: bool, spu: &mut Spu) {
+        let mut spu_audio_left = vec![0; left.len()];
+        let mut spu_audio_right = vec![0; left.len()];
+
+        if !muted {
+            for (i, (&left, &right)) in left.iter().zip(right.iter()).enumerate() {
                 let l = (left as i32 * self.vol_cd_left_to_spu_left as i32 / 0x80)
                     + (right as i32 * self.vol_cd_right_to_spu_left as i32 / 0x80);
                 let r = (left as i32 * self.vol_cd_left_to_spu_right as i32 / 0x80)
@@ -0,0 +1,827 @@
+//! Disc image loading.
+//!
+//! Supports cue sheets (one or more `BINARY` files, data and audio tracks),
+//! loose raw (2352) or cooked (2048) `.bin`/`.img`/`.iso` images detected
+//! by their ISO9660 header, and PSP `.pbp` eboots (PSISOIMG).
+//!
+//! Every format is converted into a flat list of raw 2352-byte sectors,
+//! where sector 0 is at `00:02:00` (LBA 0).
+//!
+//! Images don't keep the subchannels, LibCrypt protected discs need the
+//! subchannel Q of their modified sectors from an `.sbi` file next to the image.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    state::{invalid, Savestate, StateReader, StateResult, StateWriter},
+    PsxError,
+};
+
+pub const SECTOR_SIZE: usize = 2352;
+const COOKED_SECTOR_SIZE: usize = 2048;
+/// The 2 seconds of lead-in before LBA 0
+pub const LEAD_IN_SECTORS: usize = 150;
+
+const SYNC_PATTERN: [u8; 12] = [
+    0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00,
+];
+const ISO9660_PVD_SECTOR: usize = 16;
+/// Sector with the license text the BIOS shows while booting
+const LICENSE_SECTOR: usize = 4;
+const LICENSE_PREFIX: &[u8] = b"Sony Computer Entertainment";
+/// Enough sectors for the license and the root directory of most discs
+const HEAD_SECTORS: usize = 512;
+const ROOT_RECORD_OFFSET: usize = 156;
+const ISO9660_MAGIC: &[u8; 6] = b"\x01CD001";
+
+const PBP_MAGIC: &[u8; 4] = b"\0PBP";
+const PSISOIMG_MAGIC: &[u8; 12] = b"PSISOIMG0000";
+const PSTITLEIMG_MAGIC: &[u8; 16] = b"PSTITLEIMG000000";
+const PSISOIMG_TOC_OFFSET: usize = 0x800;
+const PSISOIMG_INDEX_OFFSET: usize = 0x4000;
+const PSISOIMG_DATA_OFFSET: usize = 0x100000;
+const PSISOIMG_INDEX_ENTRY_SIZE: usize = 32;
+/// Each compressed block holds 16 raw sectors
+const PSISOIMG_BLOCK_SIZE: usize = SECTOR_SIZE * 16;
+
+const SBI_MAGIC: &[u8; 4] = b"SBI\0";
+/// The only entry type used by LibCrypt dumps, a whole subchannel Q without its CRC
+const SBI_TYPE_FULL_Q: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackKind {
+    Data,
+    Audio,
+}
+
+/// Market a disc was licensed for, taken from the license text in sector 4
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscRegion {
+    Japan,
+    NorthAmerica,
+    Europe,
+}
+
+/// What can be told about a disc from its first sectors
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiscInfo {
+    pub region: Option<DiscRegion>,
+    /// Serial from the `BOOT` line of `SYSTEM.CNF`, like `SLUS-00594`
+    pub serial: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub number: u8,
+    pub kind: TrackKind,
+    /// Sector of `INDEX 01`, relative to LBA 0
+    pub start: usize,
+    /// First sector of the pregap (`INDEX 00` or `PREGAP`), `start` for tracks without one
+    pub pregap_start: usize,
+}
+
+#[derive(Default)]
+pub struct Disc {
+    data: Vec<u8>,
+    tracks: Vec<Track>,
+    /// Subchannel Q replacing the generated one, by sector
+    subchannel_q: HashMap<usize, [u8; 10]>,
+}
+
+/// Only the size is saved, so a state can't be loaded with a different disc
+impl Savestate for Disc {
+    fn save(&self, w: &mut StateWriter) {
+        self.sector_count().save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        let mut sector_count = 0usize;
+        sector_count.load(r)?;
+        if sector_count != self.sector_count() {
+            return Err(invalid("save state was made with a different disc"));
+        }
+        Ok(())
+    }
+}
+
+impl Disc {
+    pub fn open(path: &Path) -> Result<Self, PsxError> {
+        let mut disc = Self::open_with_limit(path, None)?;
+        let sbi_path = path.with_extension("sbi");
+        if sbi_path.is_file() {
+            disc.subchannel_q = parse_sbi(&Self::read_file(&sbi_path)?).map_err(|e| {
+                PsxError::CouldNotLoadDisk(format!("Invalid {}: {}", sbi_path.display(), e))
+            })?;
+            log::info!(
+                "Loaded {} modified subchannel sectors from {}",
+                disc.subchannel_q.len(),
+                sbi_path.display()
+            );
+        }
+        log::info!(
+            "Loaded disc {}: {} sectors, {} track(s)",
+            path.display(),
+            disc.sector_count(),
+            disc.tracks.len()
+        );
+        Ok(disc)
+    }
+
+    /// Reads only the start of the data track, enough to identify the disc
+    /// without loading the whole image
+    pub fn open_head(path: &Path) -> Result<Self, PsxError> {
+        Self::open_with_limit(path, Some(HEAD_SECTORS))
+    }
+
+    /// `limit` is the number of sectors to load from the first track, `None` loads everything
+    fn open_with_limit(path: &Path, limit: Option<usize>) -> Result<Self, PsxError> {
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase())
+            .unwrap_or_default();
+
+        match ext.as_str() {
+            "cue" => Self::from_cue(path, limit),
+            "pbp" => Self::from_pbp(path, limit),
+            "bin" | "img" | "iso" => Self::from_image(path, limit),
+            _ => Err(PsxError::DiskTypeNotSupported),
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn sector_count(&self) -> usize {
+        self.data.len() / SECTOR_SIZE
+    }
+
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    /// Subchannel Q of `sector` from the `.sbi` file, if the sector has one
+    pub fn subchannel_q(&self, sector: usize) -> Option<&[u8; 10]> {
+        self.subchannel_q.get(&sector)
+    }
+
+    /// The track containing `sector`, sectors in a pregap belong to the track after it
+    pub fn track_at(&self, sector: usize) -> Option<&Track> {
+        self.tracks
+            .iter()
+            .rev()
+            .find(|track| track.pregap_start <= sector)
+            .or(self.tracks.first())
+    }
+
+    /// `None` for discs without the license text, like homebrew and PC discs
+    pub fn region(&self) -> Option<DiscRegion> {
+        let start = LICENSE_SECTOR * SECTOR_SIZE + 24;
+        let text = self.data.get(start..start + COOKED_SECTOR_SIZE)?;
+        let prefix = text
+            .windows(LICENSE_PREFIX.len())
+            .position(|window| window == LICENSE_PREFIX)?;
+        // the text is padded with spaces, e.g. "Entertainment Amer  ica "
+        let market: Vec<u8> = text[prefix + LICENSE_PREFIX.len()..]
+            .iter()
+            .copied()
+            .filter(|&c| c != b' ')
+            .take(4)
+            .collect();
+        match &market[..] {
+            b"Inc." => Some(DiscRegion::Japan),
+            b"Amer" => Some(DiscRegion::NorthAmerica),
+            b"Euro" => Some(DiscRegion::Europe),
+            _ => None,
+        }
+    }
+
+    /// Serial of the executable `SYSTEM.CNF` boots, `None` when the file is missing
+    /// or lies past the loaded sectors
+    pub fn serial(&self) -> Option<String> {
+        let pvd = self.user_data(ISO9660_PVD_SECTOR)?;
+        if pvd.get(..ISO9660_MAGIC.len()) != Some(&ISO9660_MAGIC[..]) {
+            return None;
+        }
+        let root = &pvd[ROOT_RECORD_OFFSET..];
+        let (lba, size) = self.find_file(
+            read_u32(root, 2)? as usize,
+            read_u32(root, 10)? as usize,
+            b"SYSTEM.CNF",
+        )?;
+
+        let mut text = Vec::with_capacity(size);
+        for sector in lba..lba + size.div_ceil(COOKED_SECTOR_SIZE) {
+            text.extend_from_slice(self.user_data(sector)?);
+        }
+        text.truncate(size);
+        parse_boot_serial(&String::from_utf8_lossy(&text))
+    }
+
+    /// Looks `name` up in a directory, returns the file's sector and size
+    fn find_file(&self, dir_lba: usize, dir_size: usize, name: &[u8]) -> Option<(usize, usize)> {
+        for sector in dir_lba..dir_lba + dir_size.div_ceil(COOKED_SECTOR_SIZE) {
+            let data = self.user_data(sector)?;
+            let mut offset = 0;
+            // records never cross sectors, a zero length pads to the next one
+            while let Some(&length) = data.get(offset).filter(|&&length| length != 0) {
+                let record = data.get(offset..offset + length as usize)?;
+                let name_length = *record.get(32)? as usize;
+                let record_name = record.get(33..33 + name_length)?;
+                // drop the `;1` version suffix
+                let record_name = record_name.split(|&c| c == b';').next()?;
+                if record_name.eq_ignore_ascii_case(name) {
+                    return Some((
+                        read_u32(record, 2)? as usize,
+                        read_u32(record, 10)? as usize,
+                    ));
+                }
+                offset += length as usize;
+            }
+        }
+        None
+    }
+
+    /// The 2048 data bytes of a Mode 2 Form 1 sector
+    fn user_data(&self, sector: usize) -> Option<&[u8]> {
+        let start = sector * SECTOR_SIZE + 24;
+        self.data.get(start..start + COOKED_SECTOR_SIZE)
+    }
+
+    fn read_file(path: &Path) -> Result<Vec<u8>, PsxError> {
+        fs::read(path).map_err(|e| {
+            PsxError::CouldNotLoadDisk(format!("Could not read {}: {}", path.display(), e))
+        })
+    }
+
+    /// Reads up to `limit` bytes, along with the full length of the file
+    fn read_file_head(path: &Path, limit: Option<usize>) -> Result<(Vec<u8>, usize), PsxError> {
+        let Some(limit) = limit else {
+            let content = Self::read_file(path)?;
+            let length = content.len();
+            return Ok((content, length));
+        };
+        let error = |e: std::io::Error| {
+            PsxError::CouldNotLoadDisk(format!("Could not read {}: {}", path.display(), e))
+        };
+        let file = fs::File::open(path).map_err(error)?;
+        let length = file.metadata().map_err(error)?.len() as usize;
+        let mut content = Vec::with_capacity(limit.min(length));
+        file.take(limit as u64)
+            .read_to_end(&mut content)
+            .map_err(error)?;
+        Ok((content, length))
+    }
+
+    fn from_cue(cue_path: &Path, limit: Option<usize>) -> Result<Self, PsxError> {
+        let invalid = |line: &str| PsxError::CouldNotLoadDisk(format!("Invalid cue line: {line}"));
+
+        let content =
+            fs::read_to_string(cue_path).map_err(|e| PsxError::CouldNotLoadDisk(e.to_string()))?;
+        let base = cue_path.parent().unwrap_or(Path::new("."));
+
+        let mut files: Vec<CueFile> = Vec::new();
+        for line in content.lines() {
+            let line = line.trim();
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+
+            match keyword.to_ascii_uppercase().as_str() {
+                "FILE" => {
+                    let (name, file_type) = if let Some(quoted) = rest.strip_prefix('"') {
+                        quoted.split_once('"').ok_or_else(|| invalid(line))?
+                    } else {
+                        rest.split_once(char::is_whitespace)
+                            .ok_or_else(|| invalid(line))?
+                    };
+                    if !file_type.trim().eq_ignore_ascii_case("BINARY") {
+                        return Err(PsxError::CouldNotLoadDisk(format!(
+                            "Unsupported cue file type {} for {}",
+                            file_type.trim(),
+                            name
+                        )));
+                    }
+                    files.push(CueFile {
+                        path: base.join(name),
+                        tracks: Vec::new(),
+                    });
+                }
+                "TRACK" => {
+                    let file = files.last_mut().ok_or_else(|| invalid(line))?;
+                    let mut parts = rest.split_whitespace();
+                    let number = parts
+                        .next()
+                        .and_then(|n| n.parse::<u8>().ok())
+                        .ok_or_else(|| invalid(line))?;
+                    let (kind, sector_size) = match parts
+                        .next()
+                        .map(|mode| mode.to_ascii_uppercase())
+                        .as_deref()
+                    {
+                        Some("AUDIO") => (TrackKind::Audio, SECTOR_SIZE),
+                        Some("MODE1/2352") | Some("MODE2/2352") => (TrackKind::Data, SECTOR_SIZE),
+                        Some("MODE1/2048") | Some("MODE2/2048") => {
+                            (TrackKind::Data, COOKED_SECTOR_SIZE)
+                        }
+                        _ => {
+                            return Err(PsxError::CouldNotLoadDisk(format!(
+                                "Unsupported track mode: {line}"
+                            )))
+                        }
+                    };
+                    file.tracks.push(CueTrack {
+                        number,
+                        kind,
+                        sector_size,
+                        pregap: 0,
+                        index0: None,
+                        index1: None,
+                    });
+                }
+                "INDEX" | "PREGAP" => {
+                    let track = files
+                        .last_mut()
+                        .and_then(|file| file.tracks.last_mut())
+                        .ok_or_else(|| invalid(line))?;
+                    let mut parts = rest.split_whitespace();
+                    if keyword.eq_ignore_ascii_case("PREGAP") {
+                        track.pregap = parts
+                            .next()
+                            .and_then(parse_msf)
+                            .ok_or_else(|| invalid(line))?;
+                        continue;
+                    }
+                    let index = parts.next().and_then(|i| i.parse::<u8>().ok());
+                    let position = parts.next().and_then(parse_msf);
+                    match (index, position) {
+                        (Some(0), Some(position)) => track.index0 = Some(position),
+                        (Some(1), Some(position)) => track.index1 = Some(position),
+                        (Some(_), Some(_)) => {}
+                        _ => return Err(invalid(line)),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if files.iter().all(|file| file.tracks.is_empty()) {
+            return Err(PsxError::CouldNotLoadDisk(
+                "Cue sheet has no tracks".to_string(),
+            ));
+        }
+
+        let mut disc = Self::default();
+        // the data track is always first
+        let file_count = if limit.is_some() { 1 } else { files.len() };
+        for file in files.into_iter().take(file_count) {
+            log::info!("Loading bin file: {:?}", file.path);
+            // all tracks in one file share the same sector size
+            let sector_size = file
+                .tracks
+                .first()
+                .map_or(SECTOR_SIZE, |track| track.sector_size);
+            let (content, _) =
+                Self::read_file_head(&file.path, limit.map(|sectors| sectors * sector_size))?;
+            let file_sectors = content.len() / sector_size;
+
+            for (i, track) in file.tracks.iter().enumerate() {
+                let index1 = track.index1.ok_or_else(|| {
+                    PsxError::CouldNotLoadDisk(format!("Track {} has no INDEX 01", track.number))
+                })?;
+                let first = track.index0.unwrap_or(index1);
+                let end = file
+                    .tracks
+                    .get(i + 1)
+                    .and_then(|next| next.index0.or(next.index1))
+                    .unwrap_or(file_sectors)
+                    .min(file_sectors);
+
+                let pregap_start = disc.sector_count();
+                // pregaps are not stored in the file, generate silence for them
+                disc.data
+                    .resize(disc.data.len() + track.pregap * SECTOR_SIZE, 0);
+                let start = disc.sector_count() + index1.saturating_sub(first);
+                for sector in first..end {
+                    let raw = &content[sector * sector_size..(sector + 1) * sector_size];
+                    disc.push_sector(raw);
+                }
+                disc.tracks.push(Track {
+                    number: track.number,
+                    kind: track.kind,
+                    start,
+                    pregap_start,
+                });
+            }
+        }
+
+        Ok(disc)
+    }
+
+    fn from_image(path: &Path, limit: Option<usize>) -> Result<Self, PsxError> {
+        let (content, file_length) =
+            Self::read_file_head(path, limit.map(|sectors| sectors * SECTOR_SIZE))?;
+
+        let sector_size = [SECTOR_SIZE, COOKED_SECTOR_SIZE]
+            .into_iter()
+            .find(|&size| {
+                let pvd = ISO9660_PVD_SECTOR * size;
+                // skip sync, header, and subheader for raw sectors
+                let data = if size == SECTOR_SIZE { pvd + 24 } else { pvd };
+                file_length % size == 0
+                    && content.get(data..data + ISO9660_MAGIC.len()) == Some(&ISO9660_MAGIC[..])
+                    && (size != SECTOR_SIZE || content[pvd..pvd + 12] == SYNC_PATTERN)
+            })
+            .ok_or_else(|| {
+                PsxError::CouldNotLoadDisk(format!(
+                    "{} is not an ISO9660 disc image",
+                    path.display()
+                ))
+            })?;
+
+        let mut disc = Self::default();
+        let sectors = limit.unwrap_or(usize::MAX);
+        for raw in content.chunks_exact(sector_size).take(sectors) {
+            disc.push_sector(raw);
+        }
+        disc.tracks.push(Track {
+            number: 1,
+            kind: TrackKind::Data,
+            start: 0,
+            pregap_start: 0,
+        });
+
+        let pvd = &disc.data[ISO9660_PVD_SECTOR * SECTOR_SIZE + 24..];
+        if &pvd[8..19] != b"PLAYSTATION" {
+            log::warn!(
+                "{} has no PLAYSTATION system identifier, booting anyway",
+                path.display()
+            );
+        }
+
+        Ok(disc)
+    }
+
+    fn from_pbp(path: &Path, limit: Option<usize>) -> Result<Self, PsxError> {
+        let invalid = |msg: &str| PsxError::CouldNotLoadDisk(format!("Invalid PBP: {msg}"));
+        let content = Self::read_file(path)?;
+
+        if content.get(..4) != Some(&PBP_MAGIC[..]) {
+            return Err(invalid("bad magic"));
+        }
+        // the last of the 8 section offsets is DATA.PSAR
+        let psar = read_u32(&content, 0x24).ok_or_else(|| invalid("truncated header"))? as usize;
+
+        let base = if content.get(psar..psar + 16) == Some(&PSTITLEIMG_MAGIC[..]) {
+            // multi-disc eboot, offsets of every disc start at 0x200
+            let discs = (0..5)
+                .filter_map(|i| read_u32(&content, psar + 0x200 + i * 4))
+                .take_while(|&offset| offset != 0)
+                .count();
+            log::info!("PBP contains {} discs, loading the first", discs);
+            psar + read_u32(&content, psar + 0x200).ok_or_else(|| invalid("no discs"))? as usize
+        } else {
+            psar
+        };
+        if content.get(base..base + 12) != Some(&PSISOIMG_MAGIC[..]) {
+            return Err(invalid("missing PSISOIMG header"));
+        }
+
+        // TOC entries use the subchannel Q format, the first three are
+        // the A0 (first track), A1 (last track), and A2 (lead-out) pointers.
+        let toc_entry = |i: usize| {
+            let start = base + PSISOIMG_TOC_OFFSET + i * 10;
+            content.get(start..start + 10)
+        };
+        let last_track = toc_entry(1)
+            .map(|entry| from_bcd(entry[7]))
+            .ok_or_else(|| invalid("truncated TOC"))?;
+        let lead_out = toc_entry(2)
+            .map(|entry| msf_to_sector(entry[7], entry[8], entry[9]))
+            .ok_or_else(|| invalid("truncated TOC"))?;
+
+        let mut tracks = Vec::new();
+        for i in 0..last_track as usize {
+            let entry = toc_entry(3 + i).ok_or_else(|| invalid("truncated TOC"))?;
+            let start = msf_to_sector(entry[7], entry[8], entry[9]);
+            // the TOC only has INDEX 01, pregaps stay with the track before
+            tracks.push(Track {
+                number: from_bcd(entry[2]),
+                kind: if entry[0] & 0x40 != 0 {
+                    TrackKind::Data
+                } else {
+                    TrackKind::Audio
+                },
+                start,
+                pregap_start: start,
+            });
+        }
+
+        let data_base = base + PSISOIMG_DATA_OFFSET;
+        let index_start = base + PSISOIMG_INDEX_OFFSET;
+        let lead_out = limit.map_or(lead_out, |sectors| sectors.min(lead_out));
+        let mut data = Vec::with_capacity(lead_out * SECTOR_SIZE);
+        for entry_start in
+            (index_start..base + PSISOIMG_DATA_OFFSET).step_by(PSISOIMG_INDEX_ENTRY_SIZE)
+        {
+            let (Some(offset), Some(size)) = (
+                read_u32(&content, entry_start),
+                read_u16(&content, entry_start + 4),
+            ) else {
+                break;
+            };
+            if size == 0 || data.len() >= lead_out * SECTOR_SIZE {
+                break;
+            }
+            let start = data_base + offset as usize;
+            let block = content
+                .get(start..start + size as usize)
+                .ok_or_else(|| invalid("block out of range"))?;
+            if size as usize == PSISOIMG_BLOCK_SIZE {
+                data.extend_from_slice(block);
+            } else {
+                let block = miniz_oxide::inflate::decompress_to_vec(block)
+                    .map_err(|e| invalid(&format!("corrupt block: {e:?}")))?;
+                data.extend_from_slice(&block);
+            }
+        }
+        data.truncate(lead_out * SECTOR_SIZE);
+
+        Ok(Self {
+            data,
+            tracks,
+            ..Self::default()
+        })
+    }
+
+    /// Appends a sector, cooked 2048-byte sectors are rebuilt as Mode 2 Form 1
+    fn push_sector(&mut self, raw: &[u8]) {
+        if raw.len() == SECTOR_SIZE {
+            self.data.extend_from_slice(raw);
+            return;
+        }
+
+        let lba = self.sector_count() + LEAD_IN_SECTORS;
+        self.data.extend_from_slice(&SYNC_PATTERN);
+        self.data.extend_from_slice(&[
+            to_bcd((lba / 75 / 60) as u8),
+            to_bcd((lba / 75 % 60) as u8),
+            to_bcd((lba % 75) as u8),
+            2,
+        ]);
+        // subheader (twice): file, channel, submode (data), coding info
+        self.data.extend_from_slice(&[0, 0, 0x08, 0, 0, 0, 0x08, 0]);
+        self.data.extend_from_slice(raw);
+        // EDC/ECC are not checked by the drive emulation
+        self.data
+            .resize(self.data.len() + SECTOR_SIZE - 24 - COOKED_SECTOR_SIZE, 0);
+    }
+}
+
+struct CueFile {
+    path: PathBuf,
+    tracks: Vec<CueTrack>,
+}
+
+struct CueTrack {
+    number: u8,
+    kind: TrackKind,
+    sector_size: usize,
+    /// in sectors
+    pregap: usize,
+    /// in sectors, relative to the start of the file
+    index0: Option<usize>,
+    index1: Option<usize>,
+}
+
+/// Takes the serial from a `BOOT = cdrom:\SLUS_005.94;1` line, as `SLUS-00594`
+fn parse_boot_serial(system_cnf: &str) -> Option<String> {
+    let boot = system_cnf.lines().find_map(|line| {
+        let (key, value) = line.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case("BOOT")
+            .then_some(value.trim())
+    })?;
+    let file = boot.rsplit(['\\', '/', ':']).next()?;
+    let file = file.split(';').next()?;
+    let id: String = file
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_ascii_uppercase();
+    let (prefix, number) = id.split_at_checked(4)?;
+    if prefix.chars().all(|c| c.is_ascii_alphabetic())
+        && !number.is_empty()
+        && number.chars().all(|c| c.is_ascii_digit())
+    {
+        Some(format!("{prefix}-{number}"))
+    } else {
+        None
+    }
+}
+
+/// Entries are a BCD MSF, a type, and the data of that type
+fn parse_sbi(content: &[u8]) -> Result<HashMap<usize, [u8; 10]>, String> {
+    let mut entries = content
+        .strip_prefix(SBI_MAGIC)
+        .ok_or_else(|| "bad magic".to_string())?;
+    let mut subchannel_q = HashMap::new();
+    while !entries.is_empty() {
+        let [minutes, seconds, frames, kind, ref rest @ ..] = *entries else {
+            return Err("truncated entry".to_string());
+        };
+        if kind != SBI_TYPE_FULL_Q {
+            return Err(format!("unsupported entry type {kind}"));
+        }
+        let q = rest
+            .get(..10)
+            .ok_or_else(|| "truncated entry".to_string())?;
+        subchannel_q.insert(
+            msf_to_sector(minutes, seconds, frames),
+            q.try_into().unwrap(),
+        );
+        entries = &rest[10..];
+    }
+    Ok(subchannel_q)
+}
+
+/// Parses `mm:ss:ff` into a sector count
+fn parse_msf(msf: &str) -> Option<usize> {
+    let mut parts = msf.split(':').map(|part| part.parse::<usize>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    Some((minutes * 60 + seconds) * 75 + frames)
+}
+
+/// Converts an absolute BCD MSF into a sector relative to LBA 0
+fn msf_to_sector(minutes: u8, seconds: u8, frames: u8) -> usize {
+    let absolute = (from_bcd(minutes) as usize * 60 + from_bcd(seconds) as usize) * 75
+        + from_bcd(frames) as usize;
+    absolute.saturating_sub(LEAD_IN_SECTORS)
+}
+
+fn from_bcd(arg: u8) -> u8 {
+    ((arg >> 4) * 10) + (arg & 0xF)
+}
+
+fn to_bcd(arg: u8) -> u8 {
+    ((arg / 10) << 4) | (arg % 10)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn msf_parsing() {
+        assert_eq!(parse_msf("00:00:00"), Some(0));
+        assert_eq!(parse_msf("00:02:00"), Some(150));
+        assert_eq!(parse_msf("01:00:74"), Some(4574));
+        assert_eq!(parse_msf("bad"), None);
+        assert_eq!(msf_to_sector(0x00, 0x02, 0x00), 0);
+        assert_eq!(msf_to_sector(0x01, 0x00, 0x00), 4350);
+    }
+
+    #[test]
+    fn multi_track_cue() {
+        let dir = std::env::temp_dir().join(format!("trapezoid-disc-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("data.bin"), vec![1; SECTOR_SIZE * 10]).unwrap();
+        // 2 seconds of pregap stored in the file (INDEX 00) before the audio
+        fs::write(dir.join("audio.bin"), vec![2; SECTOR_SIZE * 160]).unwrap();
+        fs::write(
+            dir.join("game.cue"),
+            "FILE \"data.bin\" BINARY\r\n  TRACK 01 MODE2/2352\r\n    INDEX 01 00:00:00\r\n\
+             FILE \"audio.bin\" BINARY\r\n  TRACK 02 AUDIO\r\n    INDEX 00 00:00:00\r\n    INDEX 01 00:02:00\r\n",
+        )
+        .unwrap();
+
+        let disc = Disc::open(&dir.join("game.cue")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(disc.sector_count(), 170);
+        assert_eq!(disc.tracks().len(), 2);
+        assert_eq!(disc.tracks()[1].kind, TrackKind::Audio);
+        assert_eq!(disc.tracks()[1].start, 160);
+        assert_eq!(disc.tracks()[1].pregap_start, 10);
+        assert_eq!(disc.track_at(9).unwrap().number, 1);
+        // the pregap between INDEX 00 and INDEX 01 belongs to the track after it
+        assert_eq!(disc.track_at(10).unwrap().number, 2);
+        assert_eq!(disc.track_at(100).unwrap().number, 2);
+        assert_eq!(disc.track_at(165).unwrap().number, 2);
+    }
+
+    #[test]
+    fn cooked_image_is_rebuilt_as_raw() {
+        let mut content = vec![0; COOKED_SECTOR_SIZE * 20];
+        let pvd = ISO9660_PVD_SECTOR * COOKED_SECTOR_SIZE;
+        content[pvd..pvd + 6].copy_from_slice(ISO9660_MAGIC);
+        content[pvd + 8..pvd + 19].copy_from_slice(b"PLAYSTATION");
+        let path = std::env::temp_dir().join(format!("trapezoid-{}.iso", std::process::id()));
+        fs::write(&path, &content).unwrap();
+
+        let disc = Disc::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(disc.sector_count(), 20);
+        let sector = &disc.data()[ISO9660_PVD_SECTOR * SECTOR_SIZE..];
+        assert_eq!(sector[..12], SYNC_PATTERN);
+        // 00:02:16, mode 2
+        assert_eq!(sector[12..16], [0x00, 0x02, 0x16, 2]);
+        assert_eq!(&sector[24..30], ISO9660_MAGIC);
+    }
+
+    #[test]
+    fn license_region() {
+        let mut disc = Disc::default();
+        for _ in 0..LICENSE_SECTOR {
+            disc.push_sector(&[0; COOKED_SECTOR_SIZE]);
+        }
+        let mut license = [b' '; COOKED_SECTOR_SIZE];
+        let text = b"          Licensed  by          Sony Computer Entertainment Euro pe   ";
+        license[..text.len()].copy_from_slice(text);
+        disc.push_sector(&license);
+        assert_eq!(disc.region(), Some(DiscRegion::Europe));
+
+        let mut homebrew = Disc::default();
+        for _ in 0..=LICENSE_SECTOR {
+            homebrew.push_sector(&[0; COOKED_SECTOR_SIZE]);
+        }
+        assert_eq!(homebrew.region(), None);
+    }
+
+    #[test]
+    fn sbi_subchannel() {
+        let q = [0x41, 0x01, 0x01, 0x00, 0x18, 0x35, 0x00, 0x00, 0x20, 0x35];
+        let mut sbi = SBI_MAGIC.to_vec();
+        sbi.extend_from_slice(&[0x00, 0x20, 0x35, SBI_TYPE_FULL_Q]);
+        sbi.extend_from_slice(&q);
+        // 00:20:35 is 20 seconds and 35 frames after 00:02:00
+        assert_eq!(parse_sbi(&sbi).unwrap().get(&(18 * 75 + 35)), Some(&q));
+
+        sbi[7] = 2;
+        assert!(parse_sbi(&sbi).is_err());
+        assert!(parse_sbi(&sbi[..10]).is_err());
+        assert!(parse_sbi(b"SBI").is_err());
+    }
+
+    fn directory_record(lba: u32, size: u32, name: &[u8]) -> Vec<u8> {
+        let mut record = vec![0; 33 + name.len() + (name.len() + 1) % 2];
+        record[0] = record.len() as u8;
+        record[2..6].copy_from_slice(&lba.to_le_bytes());
+        record[10..14].copy_from_slice(&size.to_le_bytes());
+        record[32] = name.len() as u8;
+        record[33..33 + name.len()].copy_from_slice(name);
+        record
+    }
+
+    #[test]
+    fn system_cnf_serial() {
+        let system_cnf = b"BOOT = cdrom:\\SLUS_005.94;1\r\nTCB = 4\r\n";
+        let mut content = vec![0; COOKED_SECTOR_SIZE * 40];
+        let pvd = ISO9660_PVD_SECTOR * COOKED_SECTOR_SIZE;
+        content[pvd..pvd + 6].copy_from_slice(ISO9660_MAGIC);
+        let root = directory_record(18, COOKED_SECTOR_SIZE as u32, &[0]);
+        content[pvd + ROOT_RECORD_OFFSET..pvd + ROOT_RECORD_OFFSET + root.len()]
+            .copy_from_slice(&root);
+        let mut directory = root.clone();
+        directory.extend(directory_record(18, COOKED_SECTOR_SIZE as u32, &[1]));
+        directory.extend(directory_record(20, 0x1000, b"SLUS_005.94;1"));
+        directory.extend(directory_record(
+            19,
+            system_cnf.len() as u32,
+            b"SYSTEM.CNF;1",
+        ));
+        let dir = 18 * COOKED_SECTOR_SIZE;
+        content[dir..dir + directory.len()].copy_from_slice(&directory);
+        let file = 19 * COOKED_SECTOR_SIZE;
+        content[file..file + system_cnf.len()].copy_from_slice(system_cnf);
+        let path =
+            std::env::temp_dir().join(format!("trapezoid-serial-{}.iso", std::process::id()));
+        fs::write(&path, &content).unwrap();
+
+        let disc = Disc::open_head(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(disc.serial().as_deref(), Some("SLUS-00594"));
+        assert_eq!(parse_boot_serial("BOOT=cdrom:PSX.EXE;1"), None);
+        assert_eq!(
+            parse_boot_serial("boot = cdrom:\\sces_123.45;1").as_deref(),
+            Some("SCES-12345")
+        );
+    }
+}
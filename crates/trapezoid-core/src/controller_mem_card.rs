@@ -1,4 +1,7 @@
 use crate::memory::{interrupts::InterruptRequester, BusLine, Result};
+use crate::state::{
+    impl_savestate, impl_savestate_bits, invalid, Savestate, StateReader, StateResult, StateWriter,
+};
 use bitflags::bitflags;
 
 use std::collections::VecDeque;
@@ -29,6 +32,48 @@ impl DigitalControllerKey {
     }
 }
 
+/// DualShock stick positions, `0x00` is left/up, `0x80` is centered and `0xFF` is right/down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnalogSticks {
+    pub left_x: u8,
+    pub left_y: u8,
+    pub right_x: u8,
+    pub right_y: u8,
+}
+
+impl Default for AnalogSticks {
+    fn default() -> Self {
+        Self {
+            left_x: 0x80,
+            left_y: 0x80,
+            right_x: 0x80,
+            right_y: 0x80,
+        }
+    }
+}
+
+/// PlayStation Mouse input, `dx` and `dy` add up the motion since the game last read them,
+/// positive being right and down
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MouseState {
+    pub dx: i16,
+    pub dy: i16,
+    pub left: bool,
+    pub right: bool,
+}
+
+/// DualShock motor speeds requested by the game, the small motor is either off or full speed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RumbleState {
+    pub small_motor: u8,
+    pub large_motor: u8,
+}
+
+impl_savestate!(RumbleState {
+    small_motor,
+    large_motor,
+});
+
 const JOY_CTRL_ACKKNOWLEDGE: u16 = 0b0000000000010000;
 const JOY_CTRL_RESET: u16 = 0b0000000001000000;
 bitflags! {
@@ -50,6 +95,8 @@ bitflags! {
     }
 }
 
+impl_savestate_bits!(JoyControl, JoyMode, JoyStat);
+
 impl JoyControl {
     fn tx_enable(&self) -> bool {
         self.intersects(Self::TX_ENABLE)
@@ -124,6 +171,9 @@ bitflags! {
 }
 
 mod controller {
+    use super::{AnalogSticks, MouseState, RumbleState};
+    use crate::state::{impl_savestate, impl_savestate_enum};
+
     #[derive(Debug, Clone, Copy)]
     pub enum ControllerMode {
         ReadButtons,
@@ -141,35 +191,79 @@ mod controller {
         Unknown4010,
     }
 
-    /// Emulate Digital pad controller communication
+    impl_savestate_enum!(ControllerMode {
+        ReadButtons,
+        Config,
+        SetLed,
+        GetLed,
+        SetRumble,
+        GetWhateverValues,
+        GetVariableResponseA,
+        GetVariableResponseB,
+        Unknown60,
+        Unknown4010,
+    });
+
+    const DIGITAL_DEVICE_ID: u16 = 0x5A41;
+    const ANALOG_DEVICE_ID: u16 = 0x5A73;
+    const MOUSE_DEVICE_ID: u16 = 0x5A12;
+
+    /// Emulate DualShock controller communication, starts in digital mode
+    /// until the game or the user switches it to analog
     pub struct Controller {
         state: u8,
         device_id: u16,
         digital_switches: u16,
+        sticks: AnalogSticks,
         connected: bool,
+        /// Set when a mouse is plugged in instead of a DualShock
+        mouse: Option<MouseState>,
         current_mode: ControllerMode,
         in_config: bool,
 
+        /// The analog mode LED, on when the sticks are reported
         led: bool,
+        /// Set by the game to stop the user from switching modes
+        led_locked: bool,
+        /// Maps the bytes of the read command to motors,
+        /// `0x00` for the small motor, `0x01` for the large one
         rumble_config: [u8; 6],
+        rumble: RumbleState,
 
         /// Internal value with many purposes in the input state flow
         /// Used to store a value that may be used later in the flow
         cache_value: u8,
     }
 
+    // the pressed buttons and sticks belong to the frontend, they are not restored
+    impl_savestate!(Controller {
+        state,
+        device_id,
+        current_mode,
+        in_config,
+        led,
+        led_locked,
+        rumble_config,
+        rumble,
+        cache_value,
+    });
+
     impl Controller {
         pub fn new(connected: bool) -> Self {
             Self {
                 state: 0,
                 in_config: false,
                 current_mode: ControllerMode::ReadButtons,
-                device_id: 0x5A41,        // digital controller
+                device_id: DIGITAL_DEVICE_ID,
                 digital_switches: 0xFFFF, // all released
+                sticks: AnalogSticks::default(),
                 connected,
+                mouse: None,
 
                 led: false,
+                led_locked: false,
                 rumble_config: [0xFF; 6],
+                rumble: RumbleState::default(),
                 cache_value: 0,
             }
         }
@@ -184,6 +278,83 @@ mod controller {
             }
         }
 
+        pub fn change_analog_state(&mut self, sticks: AnalogSticks) {
+            self.sticks = sticks;
+        }
+
+        /// Swaps the DualShock for a mouse, which only answers the read command
+        pub fn connect_mouse(&mut self) {
+            self.set_analog_mode(false);
+            self.mouse = Some(MouseState::default());
+            self.device_id = MOUSE_DEVICE_ID;
+        }
+
+        /// Adds the motion of `state` to what the game has not read yet and takes its buttons
+        pub fn change_mouse_state(&mut self, state: MouseState) {
+            if let Some(mouse) = &mut self.mouse {
+                mouse.dx = mouse.dx.saturating_add(state.dx);
+                mouse.dy = mouse.dy.saturating_add(state.dy);
+                mouse.left = state.left;
+                mouse.right = state.right;
+            }
+        }
+
+        /// Emulates pressing the `Analog` button, does nothing if the game locked the mode
+        pub fn toggle_analog_mode(&mut self) -> bool {
+            if !self.led_locked && self.mouse.is_none() {
+                self.set_analog_mode(!self.led);
+            }
+            self.led
+        }
+
+        pub fn analog_mode(&self) -> bool {
+            self.led
+        }
+
+        pub fn is_mouse(&self) -> bool {
+            self.mouse.is_some()
+        }
+
+        pub fn rumble(&self) -> RumbleState {
+            self.rumble
+        }
+
+        fn set_analog_mode(&mut self, analog: bool) {
+            self.led = analog;
+            self.device_id = if analog {
+                ANALOG_DEVICE_ID
+            } else {
+                DIGITAL_DEVICE_ID
+            };
+        }
+
+        /// `index` is the position of `inp` after the 3 header bytes
+        fn apply_rumble(&mut self, index: usize, inp: u8) {
+            match self.rumble_config[index] {
+                0x00 => self.rumble.small_motor = if inp & 1 != 0 { 0xFF } else { 0 },
+                0x01 => self.rumble.large_motor = inp,
+                _ => {}
+            }
+        }
+
+        /// The button bytes, a mouse reports its buttons in bits 10 (right) and 11 (left)
+        fn switches(&self) -> u16 {
+            match self.mouse {
+                Some(mouse) => !(((mouse.left as u16) << 11) | ((mouse.right as u16) << 10)),
+                None => self.digital_switches,
+            }
+        }
+
+        /// Analog stick bytes in transfer order, `index` 0 being the 5th byte
+        fn stick_byte(&self, index: u8) -> u8 {
+            match index {
+                0 => self.sticks.right_x,
+                1 => self.sticks.right_y,
+                2 => self.sticks.left_x,
+                _ => self.sticks.left_y,
+            }
+        }
+
         pub fn start_access(&mut self) -> u8 {
             if self.connected {
                 self.state = 1;
@@ -197,6 +368,8 @@ mod controller {
             match self.state {
                 1 => {
                     self.current_mode = match inp {
+                        // a mouse has no config mode, it answers everything with its state
+                        _ if self.mouse.is_some() => ControllerMode::ReadButtons,
                         0x42 => ControllerMode::ReadButtons,
                         0x43 => ControllerMode::Config,
                         _ => todo!("Controller first input {:02X} is not supported", inp),
@@ -215,9 +388,7 @@ mod controller {
                 }
                 3 => {
                     match self.current_mode {
-                        ControllerMode::ReadButtons => {
-                            // TODO: handle rumble
-                        }
+                        ControllerMode::ReadButtons => self.apply_rumble(0, inp),
                         ControllerMode::Config => {
                             assert!(inp == 1 || inp == 0);
                             self.cache_value = inp;
@@ -225,27 +396,67 @@ mod controller {
                         _ => unreachable!(),
                     }
                     self.state = 4;
-                    ((self.digital_switches & 0xFF) as u8, false)
+                    ((self.switches() & 0xFF) as u8, false)
                 }
                 4 => {
-                    match self.current_mode {
-                        ControllerMode::ReadButtons => {
-                            // TODO: handle rumble
-                        }
-                        ControllerMode::Config => {
-                            assert_eq!(inp, 0);
-                            self.in_config = self.cache_value == 1;
-                        }
-                        _ => unreachable!(),
+                    if let ControllerMode::ReadButtons = self.current_mode {
+                        self.apply_rumble(1, inp);
+                    }
+                    let ret = ((self.switches() >> 8) & 0xFF) as u8;
+                    if self.led || self.mouse.is_some() {
+                        self.state = 5;
+                        (ret, false)
+                    } else {
+                        self.finish_normal();
+                        (ret, true)
+                    }
+                }
+                // mouse only, motion beyond a byte is left for the next read
+                5 | 6 if self.mouse.is_some() => {
+                    let state = self.state;
+                    let mouse = self.mouse.get_or_insert_default();
+                    let motion = if state == 5 {
+                        &mut mouse.dx
+                    } else {
+                        &mut mouse.dy
+                    };
+                    let ret = (*motion).clamp(i8::MIN.into(), i8::MAX.into()) as i8;
+                    *motion -= i16::from(ret);
+                    if state == 6 {
+                        self.finish_normal();
+                        (ret as u8, true)
+                    } else {
+                        self.state += 1;
+                        (ret as u8, false)
+                    }
+                }
+                // analog mode only
+                5..=8 => {
+                    let index = self.state - 5;
+                    if let ControllerMode::ReadButtons = self.current_mode {
+                        self.apply_rumble(index as usize + 2, inp);
+                    }
+                    let ret = self.stick_byte(index);
+                    if self.state == 8 {
+                        self.finish_normal();
+                        (ret, true)
+                    } else {
+                        self.state += 1;
+                        (ret, false)
                     }
-                    self.state = 0;
-                    (((self.digital_switches >> 8) & 0xFF) as u8, true)
                 }
-                // TODO: handle for analog input
                 _ => unreachable!(),
             }
         }
 
+        /// Entering config mode only takes effect once the whole response is sent
+        fn finish_normal(&mut self) {
+            if let ControllerMode::Config = self.current_mode {
+                self.in_config = self.cache_value == 1;
+            }
+            self.state = 0;
+        }
+
         fn exchange_bytes_config(&mut self, inp: u8) -> (u8, bool) {
             match self.state {
                 1 => {
@@ -276,17 +487,13 @@ mod controller {
                 }
                 3 => {
                     let ret = match self.current_mode {
-                        ControllerMode::ReadButtons => {
-                            // TODO: handle rumble
-                            (self.digital_switches & 0xFF) as u8
-                        }
+                        ControllerMode::ReadButtons => (self.digital_switches & 0xFF) as u8,
                         ControllerMode::Config => {
                             assert!(inp == 1 || inp == 0);
                             self.cache_value = inp;
                             0
                         }
                         ControllerMode::SetLed => {
-                            assert!(inp == 1 || inp == 0);
                             self.cache_value = inp;
                             0
                         }
@@ -324,21 +531,23 @@ mod controller {
                 }
                 4 => {
                     let ret = match self.current_mode {
-                        ControllerMode::ReadButtons => {
-                            // TODO: handle rumble
-                            ((self.digital_switches >> 8) & 0xFF) as u8
-                        }
+                        ControllerMode::ReadButtons => ((self.digital_switches >> 8) & 0xFF) as u8,
                         ControllerMode::Config => {
                             assert_eq!(inp, 0);
                             0
                         }
                         ControllerMode::SetLed => {
-                            // only apply LED if `inp == 2`
-                            if inp == 2 {
-                                self.led = self.cache_value == 1;
+                            // `0` is digital and `1` is analog, anything else is ignored
+                            if self.cache_value <= 1 {
+                                self.set_analog_mode(self.cache_value == 1);
+                            }
+                            // `3` locks the `Analog` button, `2` unlocks it
+                            if inp == 2 || inp == 3 {
+                                self.led_locked = inp == 3;
                             }
                             // Side effect reset rumble to 0xFF
                             self.rumble_config = [0xFF; 6];
+                            self.rumble = RumbleState::default();
                             0
                         }
                         ControllerMode::GetLed => {
@@ -364,6 +573,7 @@ mod controller {
                 }
                 5 => {
                     let ret = match self.current_mode {
+                        ControllerMode::ReadButtons => self.stick_byte(0),
                         ControllerMode::GetLed => self.led as u8,
                         ControllerMode::GetWhateverValues => 2,
                         ControllerMode::GetVariableResponseA => match self.cache_value {
@@ -382,6 +592,7 @@ mod controller {
                 }
                 6 => {
                     let ret = match self.current_mode {
+                        ControllerMode::ReadButtons => self.stick_byte(1),
                         ControllerMode::GetLed => 2,
                         ControllerMode::GetVariableResponseA => match self.cache_value {
                             0 => 2,
@@ -401,6 +612,7 @@ mod controller {
                 }
                 7 => {
                     let ret = match self.current_mode {
+                        ControllerMode::ReadButtons => self.stick_byte(2),
                         ControllerMode::GetLed => 1,
                         ControllerMode::GetWhateverValues => 1,
                         ControllerMode::GetVariableResponseA => match self.cache_value {
@@ -420,6 +632,7 @@ mod controller {
                 }
                 8 => {
                     let ret = match self.current_mode {
+                        ControllerMode::ReadButtons => self.stick_byte(3),
                         ControllerMode::GetVariableResponseA => match self.cache_value {
                             0 => 0x0a,
                             1 => 0x14,
@@ -468,8 +681,13 @@ mod controller {
     }
 }
 
+pub const MEMORY_CARD_SIZE: usize = 0x400 * 128;
+
 mod memcard {
-    use std::{fmt::Write, fs};
+    use std::fmt::Write;
+
+    use super::MEMORY_CARD_SIZE;
+    use crate::state::{impl_savestate, impl_savestate_enum};
 
     #[derive(Debug, Clone, Copy, PartialEq)]
     pub enum CardReadStage {
@@ -492,6 +710,25 @@ mod memcard {
         CmdIdEnd4,
     }
 
+    impl_savestate_enum!(CardReadStage {
+        Command,
+        MemoryCardId1,
+        MemoryCardId2,
+        SendAddressMsb,
+        SendAddressLsb,
+        ConfirmAddressMsb,
+        ConfirmAddressLsb,
+        Data,
+        Checksum,
+        CommandAck1,
+        CommandAck2,
+        End,
+        CmdIdEnd1,
+        CmdIdEnd2,
+        CmdIdEnd3,
+        CmdIdEnd4,
+    });
+
     pub enum CardCmd {
         Read,
         Write,
@@ -499,6 +736,13 @@ mod memcard {
         Invalid,
     }
 
+    impl_savestate_enum!(CardCmd {
+        Read,
+        Write,
+        Id,
+        Invalid,
+    });
+
     pub struct MemoryCard {
         id: u8,
         stage: CardReadStage,
@@ -509,12 +753,26 @@ mod memcard {
         checksum: u8,
         status: u8,
         previous: u8,
-        data: Box<[u8; 0x400 * 128]>,
+        /// Set when a write command completes, cleared by the frontend once persisted
+        dirty: bool,
+        data: Box<[u8; MEMORY_CARD_SIZE]>,
     }
 
+    // the card contents are saved by the frontend as memory card files
+    impl_savestate!(MemoryCard {
+        stage,
+        cmd,
+        flag,
+        address,
+        read_pointer,
+        checksum,
+        status,
+        previous,
+    });
+
     impl MemoryCard {
         pub fn new(id: u8) -> Self {
-            let mut data = Box::new([0; 0x400 * 128]);
+            let mut data = Box::new([0; MEMORY_CARD_SIZE]);
 
             let block0 = &mut data[0..0x400 * 8];
 
@@ -522,16 +780,6 @@ mod memcard {
             block0[1] = b'C';
             block0[0x7F] = 0xE;
 
-            // TODO: move to managed folder with resources
-            fs::read(format!("memcard{}.mcd", id))
-                .map(|m| {
-                    if m.len() == 0x400 * 128 {
-                        println!("Loaded memory card {}", id);
-                        data.copy_from_slice(&m);
-                    }
-                })
-                .ok();
-
             Self {
                 id,
                 stage: CardReadStage::Command,
@@ -542,10 +790,36 @@ mod memcard {
                 checksum: 0,
                 status: 0,
                 previous: 0,
+                dirty: false,
                 data,
             }
         }
 
+        pub fn data(&self) -> &[u8] {
+            &self.data[..]
+        }
+
+        /// Replaces the card contents, `data` must be exactly [`MEMORY_CARD_SIZE`] bytes
+        pub fn load(&mut self, data: &[u8]) -> bool {
+            if data.len() != MEMORY_CARD_SIZE {
+                return false;
+            }
+            self.data.copy_from_slice(data);
+            self.dirty = false;
+            true
+        }
+
+        /// Returns `true` if the card was written since the last call
+        pub fn take_dirty(&mut self) -> bool {
+            std::mem::take(&mut self.dirty)
+        }
+
+        /// Moves the card contents out of `other`, used to keep cards inserted across resets
+        pub fn take_contents_from(&mut self, other: &mut MemoryCard) {
+            std::mem::swap(&mut self.data, &mut other.data);
+            self.dirty = other.dirty;
+        }
+
         pub fn start_access(&mut self) -> u8 {
             log::trace!("Memory card {} started access", self.id);
             self.stage = CardReadStage::Command;
@@ -746,9 +1020,10 @@ mod memcard {
                 CardReadStage::End => {
                     assert_eq!(inp, 0);
 
-                    // if we finished a write command successfully, flush it to disk.
+                    // if we finished a write command successfully, let the frontend know
+                    // it needs to be persisted.
                     if let CardCmd::Write = self.cmd {
-                        self.flush();
+                        self.dirty = true;
                     }
 
                     self.stage = CardReadStage::Command;
@@ -786,10 +1061,94 @@ mod memcard {
             );
             r
         }
+    }
+}
+
+mod multitap {
+    use super::controller::Controller;
+    use crate::state::impl_savestate;
+
+    const MULTITAP_ID_LOW: u8 = 0x80;
+    const SLOT_BYTES: usize = 8;
+
+    /// Emulates the SCPH-1070 multitap, which connects 4 controllers to one port.
+    /// Slot A is the controller the port already has, this holds slots B-D.
+    ///
+    /// Games ask for all 4 controllers by sending `0x01` as the third byte of a
+    /// read, which the multitap applies from the next transfer on. Until then,
+    /// transfers go to slot A as if there was no multitap.
+    pub struct Multitap {
+        pub controllers: [Controller; 3],
+        read_all: bool,
+        next_read_all: bool,
+        position: usize,
+        /// whether the slot controller is still sending its response
+        slot_active: bool,
+    }
+
+    impl_savestate!(Multitap {
+        controllers,
+        read_all,
+        next_read_all,
+        position,
+        slot_active,
+    });
+
+    impl Multitap {
+        pub fn new() -> Self {
+            Self {
+                controllers: [(); 3].map(|_| Controller::new(true)),
+                read_all: false,
+                next_read_all: false,
+                position: 0,
+                slot_active: false,
+            }
+        }
 
-        /// Saves the data to disk
-        fn flush(&mut self) {
-            fs::write(format!("memcard{}.mcd", self.id), &self.data[..]).unwrap();
+        /// Returns `true` if this transfer reads all the slots
+        pub fn start_access(&mut self) -> bool {
+            self.read_all = self.next_read_all;
+            self.position = 0;
+            self.read_all
+        }
+
+        /// Watches a transfer passed through to slot A for the `read all` request
+        pub fn snoop(&mut self, inp: u8) {
+            if self.position == 1 {
+                self.next_read_all = inp == 0x01;
+            }
+            self.position += 1;
+        }
+
+        pub fn exchange_bytes(&mut self, slot_a: &mut Controller, inp: u8) -> (u8, bool) {
+            let position = self.position;
+            self.position += 1;
+            match position {
+                0 => (MULTITAP_ID_LOW, false),
+                1 => {
+                    self.next_read_all = inp == 0x01;
+                    (0x5A, false)
+                }
+                _ => {
+                    let index = position - 2;
+                    let slot = index / SLOT_BYTES;
+                    let controller = match slot {
+                        0 => slot_a,
+                        _ => &mut self.controllers[slot - 1],
+                    };
+                    if index.is_multiple_of(SLOT_BYTES) {
+                        self.slot_active = controller.start_access() != 0xFF;
+                    }
+                    let out = if self.slot_active {
+                        let (out, done) = controller.exchange_bytes(inp);
+                        self.slot_active = !done;
+                        out
+                    } else {
+                        0xFF
+                    };
+                    (out, index == SLOT_BYTES * 4 - 1)
+                }
+            }
         }
     }
 }
@@ -800,15 +1159,44 @@ struct CommunicationHandler {
     state: u8,
     controller: controller::Controller,
     memory_card: memcard::MemoryCard,
+    multitap: Option<multitap::Multitap>,
+}
+
+impl Savestate for CommunicationHandler {
+    fn save(&self, w: &mut StateWriter) {
+        self.state.save(w);
+        self.controller.save(w);
+        self.memory_card.save(w);
+        self.multitap.is_some().save(w);
+        if let Some(multitap) = &self.multitap {
+            multitap.save(w);
+        }
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        self.state.load(r)?;
+        self.controller.load(r)?;
+        Savestate::load(&mut self.memory_card, r)?;
+        let mut has_multitap = false;
+        has_multitap.load(r)?;
+        match (&mut self.multitap, has_multitap) {
+            (Some(multitap), true) => multitap.load(r),
+            (None, false) => Ok(()),
+            _ => Err(invalid(
+                "save state was made with a different multitap setting",
+            )),
+        }
+    }
 }
 
 impl CommunicationHandler {
-    /// `id` is used to indicate which memory card file to save/load from
-    fn new(id: u8, controller_connected: bool) -> Self {
+    /// `id` is used to identify the memory card in logs
+    fn new(id: u8, controller_connected: bool, multitap: bool) -> Self {
         Self {
             state: 0,
             controller: controller::Controller::new(controller_connected),
             memory_card: memcard::MemoryCard::new(id),
+            multitap: multitap.then(multitap::Multitap::new),
         }
     }
 }
@@ -818,6 +1206,12 @@ impl CommunicationHandler {
         match self.state {
             0 => match inp {
                 0x01 => {
+                    if let Some(multitap) = &mut self.multitap {
+                        if multitap.start_access() {
+                            self.state = 3;
+                            return 0;
+                        }
+                    }
                     let out = self.controller.start_access();
                     if out != 0xFF {
                         self.state = 1;
@@ -838,6 +1232,9 @@ impl CommunicationHandler {
                 }
             },
             1 => {
+                if let Some(multitap) = &mut self.multitap {
+                    multitap.snoop(inp);
+                }
                 let (result, done) = self.controller.exchange_bytes(inp);
                 if done {
                     self.state = 0;
@@ -851,14 +1248,18 @@ impl CommunicationHandler {
                 }
                 result
             }
+            3 => {
+                let multitap = self.multitap.as_mut().unwrap();
+                let (result, done) = multitap.exchange_bytes(&mut self.controller, inp);
+                if done {
+                    self.state = 0;
+                }
+                result
+            }
             _ => unreachable!(),
         }
     }
 
-    fn change_controller_key_state(&mut self, key: DigitalControllerKey, pressed: bool) {
-        self.controller.change_key_state(key, pressed);
-    }
-
     fn has_more(&self) -> bool {
         self.state != 0
     }
@@ -878,8 +1279,23 @@ pub struct ControllerAndMemoryCard {
     communication_handlers: [CommunicationHandler; 2],
 }
 
-impl Default for ControllerAndMemoryCard {
-    fn default() -> Self {
+impl_savestate!(ControllerAndMemoryCard {
+    ctrl,
+    mode,
+    stat,
+    baudrate_timer_reload,
+    baudrate_timer,
+    clk_position_high,
+    transfered_bits,
+    tx_fifo,
+    rx_fifo,
+    communication_handlers,
+});
+
+impl ControllerAndMemoryCard {
+    /// A controller is plugged into both ports, `multitap` puts a multitap between
+    /// port 1 and its controller to connect 3 more
+    pub fn new(multitap: bool) -> Self {
         let baudrate_timer_reload = 0x0088;
         let baudrate_timer = baudrate_timer_reload / 2;
         Self {
@@ -894,8 +1310,8 @@ impl Default for ControllerAndMemoryCard {
             rx_fifo: VecDeque::new(),
 
             communication_handlers: [
-                CommunicationHandler::new(0, true),
-                CommunicationHandler::new(1, false),
+                CommunicationHandler::new(0, true, multitap),
+                CommunicationHandler::new(1, true, false),
             ],
         }
     }
@@ -958,8 +1374,111 @@ impl ControllerAndMemoryCard {
         }
     }
 
-    pub fn change_controller_key_state(&mut self, key: DigitalControllerKey, pressed: bool) {
-        self.communication_handlers[0].change_controller_key_state(key, pressed);
+    /// Players 1-4 are on the multitap when it is connected, the last player is always on port 2
+    fn controller(&self, player: usize) -> Option<&controller::Controller> {
+        let [port_1, port_2] = &self.communication_handlers;
+        match (&port_1.multitap, player) {
+            (_, 0) => Some(&port_1.controller),
+            (Some(multitap), 1..=3) => Some(&multitap.controllers[player - 1]),
+            (Some(_), 4) | (None, 1) => Some(&port_2.controller),
+            _ => None,
+        }
+    }
+
+    fn controller_mut(&mut self, player: usize) -> Option<&mut controller::Controller> {
+        let [port_1, port_2] = &mut self.communication_handlers;
+        match (&mut port_1.multitap, player) {
+            (_, 0) => Some(&mut port_1.controller),
+            (Some(multitap), 1..=3) => Some(&mut multitap.controllers[player - 1]),
+            (Some(_), 4) | (None, 1) => Some(&mut port_2.controller),
+            _ => None,
+        }
+    }
+
+    pub fn controller_count(&self) -> usize {
+        if self.communication_handlers[0].multitap.is_some() {
+            5
+        } else {
+            2
+        }
+    }
+
+    pub fn change_controller_key_state(
+        &mut self,
+        player: usize,
+        key: DigitalControllerKey,
+        pressed: bool,
+    ) {
+        if let Some(controller) = self.controller_mut(player) {
+            controller.change_key_state(key, pressed);
+        }
+    }
+
+    pub fn change_controller_analog_state(&mut self, player: usize, sticks: AnalogSticks) {
+        if let Some(controller) = self.controller_mut(player) {
+            controller.change_analog_state(sticks);
+        }
+    }
+
+    pub fn toggle_controller_analog_mode(&mut self, player: usize) -> bool {
+        self.controller_mut(player)
+            .is_some_and(|controller| controller.toggle_analog_mode())
+    }
+
+    pub fn controller_analog_mode(&self, player: usize) -> bool {
+        self.controller(player)
+            .is_some_and(|controller| controller.analog_mode())
+    }
+
+    pub fn connect_controller_mouse(&mut self, player: usize) {
+        if let Some(controller) = self.controller_mut(player) {
+            controller.connect_mouse();
+        }
+    }
+
+    pub fn change_controller_mouse_state(&mut self, player: usize, state: MouseState) {
+        if let Some(controller) = self.controller_mut(player) {
+            controller.change_mouse_state(state);
+        }
+    }
+
+    pub fn controller_rumble(&self, player: usize) -> RumbleState {
+        self.controller(player)
+            .map(|controller| controller.rumble())
+            .unwrap_or_default()
+    }
+
+    /// Resets the port state while keeping the memory card contents inserted
+    pub fn reset(&mut self) {
+        let mut fresh = Self::new(self.communication_handlers[0].multitap.is_some());
+        for player in 0..self.controller_count() {
+            if self
+                .controller(player)
+                .is_some_and(|controller| controller.is_mouse())
+            {
+                fresh.connect_controller_mouse(player);
+            }
+        }
+        for (new, old) in fresh
+            .communication_handlers
+            .iter_mut()
+            .zip(self.communication_handlers.iter_mut())
+        {
+            new.memory_card.take_contents_from(&mut old.memory_card);
+        }
+        *self = fresh;
+    }
+
+    pub fn memory_card(&self, slot: usize) -> &[u8] {
+        self.communication_handlers[slot].memory_card.data()
+    }
+
+    pub fn load_memory_card(&mut self, slot: usize, data: &[u8]) -> bool {
+        self.communication_handlers[slot].memory_card.load(data)
+    }
+
+    pub fn take_memory_card_dirty(&mut self, slot: usize) -> bool {
+        self.communication_handlers[slot].memory_card.take_dirty()
     }
 }
 
@@ -1093,3 +1612,127 @@ impl BusLine for ControllerAndMemoryCard {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::controller::Controller;
+    use super::{
+        AnalogSticks, CommunicationHandler, ControllerAndMemoryCard, DigitalControllerKey,
+        MouseState, RumbleState,
+    };
+
+    fn transfer(controller: &mut Controller, command: &[u8]) -> Vec<u8> {
+        assert_eq!(controller.start_access(), 0);
+        let mut response = Vec::new();
+        for &inp in command {
+            let (out, done) = controller.exchange_bytes(inp);
+            response.push(out);
+            if done {
+                break;
+            }
+        }
+        response
+    }
+
+    #[test]
+    fn dualshock_analog_mode_and_rumble() {
+        let mut controller = Controller::new(true);
+        controller.change_analog_state(AnalogSticks {
+            left_x: 0x00,
+            left_y: 0x40,
+            right_x: 0xC0,
+            right_y: 0xFF,
+        });
+
+        // digital mode only sends the buttons
+        let response = transfer(&mut controller, &[0x42, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(response, [0x41, 0x5A, 0xFF, 0xFF]);
+
+        // enter config, switch to analog, map both motors, then exit config
+        transfer(&mut controller, &[0x43, 0, 1, 0]);
+        transfer(&mut controller, &[0x44, 0, 1, 3, 0, 0, 0, 0]);
+        transfer(&mut controller, &[0x4D, 0, 0, 1, 0xFF, 0xFF, 0xFF, 0xFF]);
+        transfer(&mut controller, &[0x43, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(controller.analog_mode());
+
+        let response = transfer(&mut controller, &[0x42, 0, 1, 0x80, 0, 0, 0, 0]);
+        assert_eq!(response, [0x73, 0x5A, 0xFF, 0xFF, 0xC0, 0xFF, 0x00, 0x40]);
+        assert_eq!(
+            controller.rumble(),
+            RumbleState {
+                small_motor: 0xFF,
+                large_motor: 0x80,
+            }
+        );
+
+        // the game locked the mode, so the `Analog` button is ignored
+        assert!(controller.toggle_analog_mode());
+    }
+
+    #[test]
+    fn mouse_reports_motion_since_last_read() {
+        let mut controller = Controller::new(true);
+        controller.connect_mouse();
+        controller.change_mouse_state(MouseState {
+            dx: 200,
+            dy: -3,
+            left: true,
+            right: false,
+        });
+
+        let response = transfer(&mut controller, &[0x42, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(response, [0x12, 0x5A, 0xFF, 0xF7, 0x7F, 0xFD]);
+
+        // the motion that did not fit a byte comes with the next read
+        let response = transfer(&mut controller, &[0x42, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(response, [0x12, 0x5A, 0xFF, 0xF7, 0x49, 0x00]);
+
+        // a mouse has no analog mode to switch to
+        assert!(!controller.toggle_analog_mode());
+    }
+
+    fn port_transfer(handler: &mut CommunicationHandler, command: &[u8]) -> Vec<u8> {
+        let mut response = Vec::new();
+        for &inp in command {
+            response.push(handler.exchange_bytes(inp));
+            if !handler.has_more() {
+                break;
+            }
+        }
+        response
+    }
+
+    #[test]
+    fn multitap_reads_all_slots_after_request() {
+        let mut ports = ControllerAndMemoryCard::new(true);
+        assert_eq!(ports.controller_count(), 5);
+        ports.change_controller_key_state(0, DigitalControllerKey::Start, true);
+        ports.change_controller_key_state(2, DigitalControllerKey::X, true);
+        ports.change_controller_key_state(4, DigitalControllerKey::Circle, true);
+
+        let handler = &mut ports.communication_handlers[0];
+        let mut read_all = vec![0x01, 0x42, 0x01];
+        for _ in 0..4 {
+            read_all.extend([0x42, 0, 0, 0, 0, 0, 0, 0]);
+        }
+
+        // the request only applies from the next transfer, until then slot A answers alone
+        let response = port_transfer(handler, &read_all);
+        assert_eq!(response, [0x00, 0x41, 0x5A, 0xF7, 0xFF]);
+
+        let response = port_transfer(handler, &read_all);
+        assert_eq!(response.len(), 3 + 32);
+        assert_eq!(response[1..3], [0x80, 0x5A]);
+        let slot_a = [0x41, 0x5A, 0xF7, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+        let slot_b = [0x41, 0x5A, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+        let slot_c = [0x41, 0x5A, 0xFF, 0xBF, 0xFF, 0xFF, 0xFF, 0xFF];
+        assert_eq!(response[3..11], slot_a);
+        assert_eq!(response[11..19], slot_b);
+        assert_eq!(response[19..27], slot_c);
+        assert_eq!(response[27..35], slot_b);
+
+        // the last player is on port 2
+        let response = port_transfer(&mut ports.communication_handlers[1], &[0x01, 0x42, 0, 0, 0]);
+        assert_eq!(response, [0x00, 0x41, 0x5A, 0xFF, 0xDF]);
+    }
+}
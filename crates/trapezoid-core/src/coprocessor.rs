@@ -2,4 +2,4 @@ mod cop0;
 mod cop2;
 
 pub use cop0::SystemControlCoprocessor;
-pub use cop2::Gte;
+pub use cop2::{Gte, PreciseVertex};
@@ -1,3 +1,5 @@
+use crate::state::impl_savestate;
+
 /// CXD8606CQ CPU ID
 const PRID: u32 = 0x2;
 
@@ -15,6 +17,19 @@ pub struct SystemControlCoprocessor {
     epc: u32,
 }
 
+impl_savestate!(SystemControlCoprocessor {
+    bpc,
+    bda,
+    jmp_dest,
+    dcic,
+    bad_vaddr,
+    bdam,
+    bpcm,
+    sr,
+    cause,
+    epc,
+});
+
 impl SystemControlCoprocessor {
     pub fn is_cache_isolated(&self) -> bool {
         self.sr & 0x10000 != 0
@@ -1,3 +1,5 @@
+use crate::state::{impl_savestate, impl_savestate_bits};
+
 #[derive(Debug)]
 enum GteCommandOpcode {
     Na,
@@ -124,6 +126,8 @@ bitflags::bitflags! {
     }
 }
 
+impl_savestate_bits!(Flag);
+
 impl Flag {
     fn bits_with_error(&self) -> u32 {
         // error bit is set if any of the bits (30-22, 18-13)
@@ -141,6 +145,17 @@ fn get_rgb(rgb: u32) -> (i64, i64, i64) {
     (r, g, b)
 }
 
+/// A vertex projected by RTPS/RTPT before it is rounded into `SXY`, kept so the
+/// GPU can draw it with sub-pixel precision and perspective correct textures
+#[derive(Debug, Clone, Copy)]
+pub struct PreciseVertex {
+    pub sx: i16,
+    pub sy: i16,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
 #[derive(Default)]
 pub struct Gte {
     vectors: [[i16; 3]; 3],
@@ -171,9 +186,67 @@ pub struct Gte {
     zsf3: i16,
     zsf4: i16,
     flag: Flag,
+
+    /// Squeezes projected X by 3/4 so 4:3 games fill a 16:9 screen
+    widescreen: bool,
+    /// Records [`PreciseVertex`]s for every projected vertex
+    precise_geometry: bool,
+    precise_vertices: Vec<PreciseVertex>,
 }
 
+// the widescreen hack and precise geometry are settings, not console state
+impl_savestate!(Gte {
+    vectors,
+    rgbc,
+    otz,
+    ir,
+    res1,
+    mac,
+    sxy,
+    sz,
+    rgb,
+    irgb,
+    orgb,
+    lzcs,
+    lzcr,
+    rotation_matrix,
+    translation_vector,
+    light_source_matrix,
+    light_color_matrix,
+    background_color,
+    far_color,
+    screen_offset,
+    projection_plain_distance,
+    dqa,
+    dqb,
+    zsf3,
+    zsf4,
+    flag,
+});
+
 impl Gte {
+    pub fn set_widescreen_hack(&mut self, enabled: bool) {
+        self.widescreen = enabled;
+    }
+
+    pub fn widescreen(&self) -> bool {
+        self.widescreen
+    }
+
+    pub fn set_precise_geometry(&mut self, enabled: bool) {
+        self.precise_geometry = enabled;
+        self.precise_vertices.clear();
+    }
+
+    pub fn precise_geometry(&self) -> bool {
+        self.precise_geometry
+    }
+
+    /// The vertices projected since the last call
+    pub fn take_precise_vertices(&mut self) -> std::vec::Drain<'_, PreciseVertex> {
+        self.precise_vertices.drain(..)
+    }
+
     /// updates the ir 1, 2, 3 registers on any write/change to irgb
     fn update_ir123(&mut self) {
         let r = (self.irgb) & 0x1F;
@@ -514,7 +587,12 @@ impl Gte {
         let n = self.rtp_unr_division();
 
         // MAC0=(((H*20000h/SZ3)+1)/2)*IR1+OFX, SX2=MAC0/10000h
-        let mac0 = n * self.ir[1] as i64 + self.screen_offset[0] as i64;
+        let ir1 = if self.widescreen {
+            self.ir[1] as i64 * 3 / 4
+        } else {
+            self.ir[1] as i64
+        };
+        let mac0 = n * ir1 + self.screen_offset[0] as i64;
         self.set_mac0(mac0);
 
         let sx = self.saturate_put_flag(
@@ -536,6 +614,22 @@ impl Gte {
         ) as i16;
         self.push_sxy_fifo(sx, sy);
 
+        // the division overflowed if `SZ3` is not above `H/2`, keep the rounded result then
+        if self.precise_geometry && sz as u32 * 2 > self.projection_plain_distance as u32 {
+            let scale = self.projection_plain_distance as f64 / sz as f64;
+            let x = ir1 as f64 * scale + self.screen_offset[0] as f64 / 65536.0;
+            let y = self.ir[2] as f64 * scale + self.screen_offset[1] as f64 / 65536.0;
+            if (-1024.0..1024.0).contains(&x) && (-1024.0..1024.0).contains(&y) {
+                self.precise_vertices.push(PreciseVertex {
+                    sx,
+                    sy,
+                    x: x as f32,
+                    y: y as f32,
+                    z: sz as f32,
+                });
+            }
+        }
+
         if last {
             // MAC0=(((H*20000h/SZ3)+1)/2)*DQA+DQB, IR0=MAC0/1000h
             let mac0 = n * self.dqa as i64 + self.dqb as i64;
@@ -4,8 +4,9 @@ mod instruction;
 mod instructions_table;
 mod register;
 
-use crate::coprocessor::{Gte, SystemControlCoprocessor};
+use crate::coprocessor::{Gte, PreciseVertex, SystemControlCoprocessor};
 pub use crate::memory::BusLine;
+use crate::state::impl_savestate;
 
 pub use instruction::{Instruction, Opcode};
 pub use register::{RegisterType, Registers};
@@ -153,6 +154,18 @@ pub struct Cpu {
     debugger: Debugger,
 }
 
+// the debugger keeps its breakpoints across loads
+impl_savestate!(Cpu {
+    regs,
+    cop0,
+    cop2,
+    jump_dest_next,
+    elapsed_cycles,
+    shell_reached_before,
+    shell_reached_now,
+    current_instr_pc,
+});
+
 impl Cpu {
     pub fn new() -> Self {
         Self {
@@ -172,15 +185,33 @@ impl Cpu {
     }
 
     pub fn reset(&mut self) {
+        let widescreen = self.cop2.widescreen();
+        let precise_geometry = self.cop2.precise_geometry();
         self.regs = Registers::new();
         self.cop0 = SystemControlCoprocessor::default();
         self.cop2 = Gte::default();
+        self.cop2.set_widescreen_hack(widescreen);
+        self.cop2.set_precise_geometry(precise_geometry);
         self.jump_dest_next = None;
         self.elapsed_cycles = 0;
         self.shell_reached_before = false;
         self.current_instr_pc = 0;
     }
 
+    /// Projects geometry for a 16:9 screen, kept across resets
+    pub fn set_widescreen_hack(&mut self, enabled: bool) {
+        self.cop2.set_widescreen_hack(enabled);
+    }
+
+    /// Keeps the GTE's projected vertices before rounding, kept across resets
+    pub fn set_precise_geometry(&mut self, enabled: bool) {
+        self.cop2.set_precise_geometry(enabled);
+    }
+
+    pub(crate) fn take_precise_vertices(&mut self) -> impl Iterator<Item = PreciseVertex> + '_ {
+        self.cop2.take_precise_vertices()
+    }
+
     pub fn registers(&self) -> &Registers {
         &self.regs
     }
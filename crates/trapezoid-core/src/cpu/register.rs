@@ -1,4 +1,6 @@
-use std::fmt;
+use std::{fmt, str::FromStr};
+
+use crate::state::impl_savestate;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 #[repr(u8)]
@@ -96,6 +98,33 @@ impl fmt::Display for RegisterType {
     }
 }
 
+/// Parses the names from [`ALL_REG_NAMES`], `s8` for `fp`, and `r0`-`r31`,
+/// with an optional `$` in front
+impl FromStr for RegisterType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.strip_prefix('$').unwrap_or(s).to_ascii_lowercase();
+        if let Some(index) = ALL_REG_NAMES.iter().position(|reg| *reg == name) {
+            return Ok(REG_TYPES[index]);
+        }
+        match name.as_str() {
+            "s8" => Ok(RegisterType::Fp),
+            _ => name
+                .strip_prefix('r')
+                .and_then(|index| index.parse::<u8>().ok())
+                .filter(|&index| index < 32)
+                .map(RegisterType::from)
+                .ok_or_else(|| format!("unknown register `{s}`")),
+        }
+    }
+}
+
+impl RegisterType {
+    /// Every register, general purpose ones first, then `pc`, `hi` and `lo`
+    pub const ALL: [RegisterType; 35] = REG_TYPES;
+}
+
 pub struct Registers {
     pub(crate) general_regs: [u32; 32],
     pub(crate) pc: u32,
@@ -110,6 +139,15 @@ pub struct Registers {
     load_delay_slot_committing: Option<(u8, u32)>,
 }
 
+impl_savestate!(Registers {
+    general_regs,
+    pc,
+    hi,
+    lo,
+    load_delay_slot_running,
+    load_delay_slot_committing,
+});
+
 impl Registers {
     pub(crate) fn new() -> Self {
         Self {
@@ -261,3 +299,19 @@ impl std::fmt::Debug for Registers {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_names() {
+        assert_eq!("sp".parse(), Ok(RegisterType::Sp));
+        assert_eq!("$RA".parse(), Ok(RegisterType::Ra));
+        assert_eq!("s8".parse(), Ok(RegisterType::Fp));
+        assert_eq!("r2".parse(), Ok(RegisterType::V0));
+        assert_eq!("pc".parse(), Ok(RegisterType::Pc));
+        assert!("r32".parse::<RegisterType>().is_err());
+        assert!("x1".parse::<RegisterType>().is_err());
+    }
+}
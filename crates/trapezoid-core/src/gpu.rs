@@ -1,5 +1,7 @@
 mod command;
 mod common;
+mod precision;
+mod software;
 
 mod utils;
 #[cfg(feature = "vulkan")]
@@ -15,7 +17,9 @@ mod dummy_render;
 #[cfg(not(feature = "vulkan"))]
 use dummy_render as backend;
 
+use crate::coprocessor::PreciseVertex;
 use crate::memory::{interrupts::InterruptRequester, BusLine, Result};
+use crate::state::{impl_savestate, invalid, Savestate, StateReader, StateResult, StateWriter};
 use command::{instantiate_gp0_command, Gp0CmdType, Gp0Command};
 
 use core::fmt;
@@ -31,12 +35,22 @@ use std::{
 };
 
 use common::{DrawingTextureParams, DrawingVertex};
+use precision::PreciseGeometry;
+use software::SoftwareRenderer;
 
 use backend::StandardCommandBufferAllocator;
 pub use backend::{Device, GpuFuture, Image, Queue};
+pub use software::SoftwareFrame;
 
 #[cfg(feature = "vulkan")]
-use backend::{AutoCommandBufferBuilder, BlitImageInfo, CommandBufferUsage, Filter};
+use backend::{
+    AllocationCreateInfo, AutoCommandBufferBuilder, BlitImageInfo, Buffer, BufferCreateInfo,
+    BufferUsage, ClearColorImageInfo, CommandBufferUsage, CopyImageToBufferInfo, Filter, Format,
+    ImageBlit, ImageCreateInfo, ImageType, ImageUsage, MemoryTypeFilter, StandardMemoryAllocator,
+};
+
+pub(crate) const VRAM_WIDTH: u32 = 1024;
+pub(crate) const VRAM_HEIGHT: u32 = 512;
 
 bitflags::bitflags! {
     #[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
@@ -70,6 +84,20 @@ bitflags::bitflags! {
 }
 
 #[cfg_attr(not(feature = "vulkan"), allow(dead_code))]
+impl Savestate for GpuStat {
+    fn save(&self, w: &mut StateWriter) {
+        self.bits().save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        let mut bits = 0u32;
+        bits.load(r)?;
+        // `AtomicGpuStat` expects only known bits
+        *self = Self::from_bits(bits).ok_or_else(|| invalid("invalid GPUSTAT"))?;
+        Ok(())
+    }
+}
+
 impl GpuStat {
     fn _texture_page_coords(&self) -> (u32, u32) {
         let x = (self.bits() & Self::TEXTURE_PAGE_X_BASE.bits()) * 64;
@@ -224,10 +252,87 @@ pub(crate) struct GpuStateSnapshot {
     cached_gp0_e5: u32,
 }
 
+impl_savestate!(GpuStateSnapshot {
+    gpu_stat,
+    allow_texture_disable,
+    textured_rect_flip,
+    drawing_area_top_left,
+    drawing_area_bottom_right,
+    drawing_offset,
+    texture_window_mask,
+    texture_window_offset,
+    vram_display_area_start,
+    display_horizontal_range,
+    display_vertical_range,
+    cached_gp0_e2,
+    cached_gp0_e3,
+    cached_gp0_e4,
+    cached_gp0_e5,
+});
+
+/// The video mode of the last presented frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayMode {
+    /// Size of the display area, in VRAM pixels
+    pub width: u32,
+    pub height: u32,
+    /// Width of a pixel relative to its height on a 4:3 TV, 256 pixel wide modes
+    /// have wide pixels and 640 pixel wide ones narrow pixels
+    pub pixel_aspect: f32,
+}
+
+/// How the frame is filtered when it is scaled into the frontend's image
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ScalingFilter {
+    /// Sharp pixels, which come out in uneven sizes at fractional scales
+    #[default]
+    Nearest,
+    /// Smooth, but blurs the whole picture
+    Bilinear,
+    /// Nearest up to the largest integer scale that fits, then bilinear for the
+    /// rest, so only the pixel edges are smoothed
+    SharpBilinear,
+}
+
+impl GpuStateSnapshot {
+    /// Size of the display area in VRAM pixels, as configured by the display ranges
+    fn display_size(&self) -> [u32; 2] {
+        let gpu_stat = self.gpu_stat;
+        // (((X2-X1)/cycles_per_pix)+2) AND NOT 3
+        let mut horizontal_size = ((self
+            .display_horizontal_range
+            .1
+            .saturating_sub(self.display_horizontal_range.0)
+            / gpu_stat.horizontal_dots_divider())
+            + 2)
+            & !3;
+
+        if horizontal_size == 0 {
+            horizontal_size = gpu_stat.horizontal_resolution();
+        }
+
+        let should_double = gpu_stat.vertical_resolution() == 480;
+
+        // Y2-Y1, double if we are interlacing
+        let mut vertical_size = self
+            .display_vertical_range
+            .1
+            .saturating_sub(self.display_vertical_range.0)
+            << should_double as u32;
+
+        if vertical_size == 0 {
+            vertical_size = gpu_stat.vertical_resolution();
+        }
+
+        [horizontal_size, vertical_size]
+    }
+}
+
 #[cfg_attr(not(feature = "vulkan"), allow(dead_code))]
 pub(crate) enum BackendCommand {
     BlitFront {
         full_vram: bool,
+        gamma: f32,
         state_snapshot: GpuStateSnapshot,
     },
     DrawPolyline {
@@ -259,24 +364,31 @@ pub(crate) enum BackendCommand {
         size: (u32, u32),
         color: (u8, u8, u8),
     },
+    /// Reads the whole VRAM for a save state. The reply comes after every
+    /// earlier command is done, so this also waits for the backend to catch up.
+    DumpVram { reply: mpsc::Sender<Vec<u16>> },
+}
+
+/// Selects which backend executes the drawing commands
+#[derive(Clone)]
+pub(crate) enum Renderer {
+    /// Draw with Vulkan on a separate backend thread
+    Vulkan {
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+    },
+    /// Draw on the CPU, inline with emulation
+    Software,
 }
 
 #[cfg_attr(not(feature = "vulkan"), allow(dead_code))]
-pub(crate) struct Gpu {
+struct VulkanOutput {
     // used for blitting to frontend
     queue: Arc<Queue>,
-    device: Arc<Device>,
 
     // handle the backend gpu thread
     #[cfg(feature = "vulkan")]
     _gpu_backend_thread_handle: JoinHandle<()>,
-
-    /// holds commands that needs extra parameter and complex, like sending
-    /// to/from VRAM, and rendering
-    current_command: Option<Box<dyn Gp0Command>>,
-    // GPUREAD channel
-    gpu_read_sender: mpsc::Sender<u32>,
-    gpu_read_receiver: PeekableReceiver<u32>,
     // backend commands channel
     gpu_backend_sender: mpsc::Sender<BackendCommand>,
     // channel for front image coming from backend
@@ -285,6 +397,31 @@ pub(crate) struct Gpu {
     first_frame: bool,
     current_front_image: Option<Arc<Image>>,
     command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    // staging buffers for reading the front image back
+    #[cfg(feature = "vulkan")]
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    // integer scaled front image for `ScalingFilter::SharpBilinear`
+    #[cfg(feature = "vulkan")]
+    prescaled_image: Option<Arc<Image>>,
+}
+
+enum Backend {
+    Vulkan(VulkanOutput),
+    Software(Box<SoftwareRenderer>),
+}
+
+pub(crate) struct Gpu {
+    backend: Backend,
+    // kept to recreate the backend on reset
+    renderer: Renderer,
+    internal_scale: u32,
+
+    /// holds commands that needs extra parameter and complex, like sending
+    /// to/from VRAM, and rendering
+    current_command: Option<Box<dyn Gp0Command>>,
+    // GPUREAD channel
+    gpu_read_sender: mpsc::Sender<u32>,
+    gpu_read_receiver: PeekableReceiver<u32>,
 
     // shared GPUSTAT
     gpu_stat: Arc<AtomicGpuStat>,
@@ -296,15 +433,15 @@ pub(crate) struct Gpu {
     in_vblank: bool,
 
     cpu_cycles_counter: u32,
+
+    precise_geometry: PreciseGeometry,
+    scaling_filter: ScalingFilter,
+    gamma: f32,
 }
 
 impl Gpu {
-    pub fn new(device: Arc<Device>, queue: Arc<Queue>) -> Self {
+    pub fn new(renderer: Renderer, internal_scale: u32) -> Self {
         let (gpu_read_sender, gpu_read_receiver) = mpsc::channel();
-        #[allow(unused_variables)]
-        let (gpu_backend_sender, gpu_backend_receiver) = mpsc::channel();
-        #[allow(unused_variables)]
-        let (gpu_front_image_sender, gpu_front_image_receiver) = mpsc::channel();
 
         let gpu_stat = Arc::new(AtomicGpuStat::new(
             GpuStat::READY_FOR_CMD_RECV | GpuStat::READY_FOR_DMA_RECV,
@@ -331,35 +468,28 @@ impl Gpu {
             display_vertical_range: (0, 0),
         };
 
-        #[cfg(feature = "vulkan")]
-        let _gpu_backend_thread_handle = backend::GpuBackend::start(
-            device.clone(),
-            queue.clone(),
-            gpu_stat.clone(),
-            gpu_read_sender.clone(),
-            gpu_backend_receiver,
-            gpu_front_image_sender,
-        );
+        let backend = match &renderer {
+            Renderer::Vulkan { device, queue } => Backend::Vulkan(VulkanOutput::start(
+                device.clone(),
+                queue.clone(),
+                gpu_stat.clone(),
+                gpu_read_sender.clone(),
+                internal_scale,
+            )),
+            Renderer::Software => Backend::Software(Box::new(SoftwareRenderer::new(
+                gpu_stat.clone(),
+                gpu_read_sender.clone(),
+            ))),
+        };
 
         Self {
-            queue,
-            device: device.clone(),
-
-            #[cfg(feature = "vulkan")]
-            _gpu_backend_thread_handle,
+            backend,
+            renderer,
+            internal_scale,
 
             current_command: None,
             gpu_read_sender,
             gpu_read_receiver: PeekableReceiver::new(gpu_read_receiver),
-            gpu_backend_sender,
-            gpu_front_image_receiver,
-
-            first_frame: true,
-            current_front_image: None,
-            command_buffer_allocator: Arc::new(StandardCommandBufferAllocator::new(
-                device,
-                Default::default(),
-            )),
 
             gpu_stat,
             state_snapshot,
@@ -369,11 +499,55 @@ impl Gpu {
             drawing_odd: false,
             in_vblank: false,
             cpu_cycles_counter: 0,
+
+            precise_geometry: PreciseGeometry::default(),
+            scaling_filter: ScalingFilter::default(),
+            gamma: 1.0,
         }
     }
 
     pub fn reset(&mut self) {
-        let _ = std::mem::replace(self, Self::new(self.device.clone(), self.queue.clone()));
+        let precise_geometry = std::mem::take(&mut self.precise_geometry);
+        let scaling_filter = self.scaling_filter;
+        let gamma = self.gamma;
+        let _ = std::mem::replace(self, Self::new(self.renderer.clone(), self.internal_scale));
+        self.set_precise_geometry(
+            precise_geometry.sub_pixel(),
+            precise_geometry.perspective_correct(),
+        );
+        self.scaling_filter = scaling_filter;
+        self.gamma = gamma;
+    }
+
+    /// Filter used when the frame is scaled into the frontend's image
+    pub fn set_scaling_filter(&mut self, filter: ScalingFilter) {
+        self.scaling_filter = filter;
+    }
+
+    /// Gamma applied to the front image, `1.0` leaves the colours alone
+    pub fn set_gamma(&mut self, gamma: f32) {
+        self.gamma = gamma;
+    }
+
+    /// Draws polygons with the unrounded vertices from the GTE, see [`PreciseGeometry`]
+    pub fn set_precise_geometry(&mut self, sub_pixel: bool, perspective_correct: bool) {
+        self.precise_geometry = PreciseGeometry::new(sub_pixel, perspective_correct);
+    }
+
+    pub fn add_precise_vertices(&mut self, vertices: impl Iterator<Item = PreciseVertex>) {
+        if self.precise_geometry.enabled() {
+            self.precise_geometry.add_vertices(vertices);
+        }
+    }
+
+    fn send_to_backend(&mut self, mut backend_cmd: BackendCommand) {
+        if let BackendCommand::DrawPolygon { vertices, .. } = &mut backend_cmd {
+            self.precise_geometry.refine_polygon(vertices);
+        }
+        match &mut self.backend {
+            Backend::Vulkan(vulkan) => vulkan.gpu_backend_sender.send(backend_cmd).unwrap(),
+            Backend::Software(software) => software.execute(backend_cmd),
+        }
     }
 
     /// returns the number of `dot_clocks`, and if `hblank_clock` occurres
@@ -459,62 +633,200 @@ impl Gpu {
         self.in_vblank
     }
 
+    /// Waits for the backend to finish every command, then returns the whole VRAM
+    /// along with the GPUREAD words the CPU has not read yet
+    fn sync_vram(&mut self) -> (Vec<u16>, Vec<u32>) {
+        let (reply, vram) = mpsc::channel();
+        self.send_to_backend(BackendCommand::DumpVram { reply });
+        let vram = vram.recv().expect("GPU backend stopped");
+        // every earlier VRAM read has been answered by now
+        let mut pending_reads = Vec::new();
+        while let Ok(word) = self.gpu_read_receiver.try_recv() {
+            pending_reads.push(word);
+        }
+        (vram, pending_reads)
+    }
+
+    /// Saves the registers and VRAM. A GP0 command still receiving its parameters
+    /// is not saved, states are meant to be taken between frames.
+    pub fn save_state(&mut self, w: &mut StateWriter) {
+        let (vram, pending_reads) = self.sync_vram();
+        for &word in &pending_reads {
+            self.gpu_read_sender.send(word).unwrap();
+        }
+
+        self.gpu_stat.load().save(w);
+        self.state_snapshot.save(w);
+        self.scanline.save(w);
+        self.dot.save(w);
+        self.drawing_odd.save(w);
+        self.in_vblank.save(w);
+        self.cpu_cycles_counter.save(w);
+        vram.save(w);
+        pending_reads.save(w);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> StateResult {
+        let mut gpu_stat = GpuStat::empty();
+        gpu_stat.load(r)?;
+        self.state_snapshot.load(r)?;
+        self.scanline.load(r)?;
+        self.dot.load(r)?;
+        self.drawing_odd.load(r)?;
+        self.in_vblank.load(r)?;
+        self.cpu_cycles_counter.load(r)?;
+        let mut vram = Vec::<u16>::new();
+        vram.load(r)?;
+        if vram.len() != (VRAM_WIDTH * VRAM_HEIGHT) as usize {
+            return Err(invalid("VRAM has the wrong size"));
+        }
+        let mut pending_reads = Vec::<u32>::new();
+        pending_reads.load(r)?;
+
+        self.current_command = None;
+        self.send_to_backend(BackendCommand::WriteVramBlock {
+            block_range: (0..VRAM_WIDTH, 0..VRAM_HEIGHT),
+            block: vram,
+        });
+        // drop the reads of the replaced state
+        self.sync_vram();
+        for word in pending_reads {
+            self.gpu_read_sender.send(word).unwrap();
+        }
+        self.gpu_stat.store(gpu_stat);
+        Ok(())
+    }
+
+    pub fn display_mode(&self) -> DisplayMode {
+        let gpu_stat = self.state_snapshot.gpu_stat;
+        let [width, height] = self.state_snapshot.display_size();
+        // the nominal resolution of a mode fills the whole 4:3 screen
+        let lines = if gpu_stat.is_ntsc_video_mode() {
+            240
+        } else {
+            288
+        } << (gpu_stat.vertical_resolution() == 480) as u32;
+        DisplayMode {
+            width,
+            height,
+            pixel_aspect: (4 * lines) as f32 / (3 * gpu_stat.horizontal_resolution()) as f32,
+        }
+    }
+
     #[cfg(not(feature = "vulkan"))]
     pub fn sync_gpu_and_blit_to_front(
         &mut self,
         _dest_image: Arc<Image>,
         _full_vram: bool,
+        _dest_area: Option<([u32; 2], [u32; 2])>,
         in_future: Box<dyn GpuFuture>,
     ) -> Box<dyn GpuFuture> {
         in_future
     }
 
+    /// `dest_area` is the offset and extent to draw to, the rest of the image is
+    /// cleared to black. `None` fills the whole image.
     #[cfg(feature = "vulkan")]
     pub fn sync_gpu_and_blit_to_front(
         &mut self,
         dest_image: Arc<Image>,
         full_vram: bool,
+        dest_area: Option<([u32; 2], [u32; 2])>,
         in_future: Box<dyn GpuFuture>,
     ) -> Box<dyn GpuFuture> {
+        let Backend::Vulkan(vulkan) = &mut self.backend else {
+            return in_future;
+        };
         // if we have a previous image, then we are not in the first frame,
         // so there should be an image in the channel.
-        if !self.first_frame {
+        if !vulkan.first_frame {
             // `recv` is blocking, here we will wait for the GPU to finish all drawing.
             // FIXME: Do not block. Find a way to keep the GPU synced with minimal performance loss.
-            self.current_front_image = Some(self.gpu_front_image_receiver.recv().unwrap());
+            vulkan.current_front_image = Some(vulkan.gpu_front_image_receiver.recv().unwrap());
         }
-        self.first_frame = false;
+        vulkan.first_frame = false;
 
         // send command for next frame from now, so when we recv later, its mostly will be ready
         self.state_snapshot.gpu_stat = self.gpu_stat.load();
-        self.gpu_backend_sender
+        vulkan
+            .gpu_backend_sender
             .send(BackendCommand::BlitFront {
                 full_vram,
+                gamma: self.gamma,
                 state_snapshot: self.state_snapshot.clone(),
             })
             .unwrap();
 
-        if let Some(img) = self.current_front_image.as_ref() {
+        if let Some(img) = vulkan.current_front_image.clone() {
             let mut builder: AutoCommandBufferBuilder<
                 crate::gpu::vulkan::PrimaryAutoCommandBuffer,
             > = AutoCommandBufferBuilder::primary(
-                self.command_buffer_allocator.clone(),
-                self.queue.queue_family_index(),
+                vulkan.command_buffer_allocator.clone(),
+                vulkan.queue.queue_family_index(),
                 CommandBufferUsage::OneTimeSubmit,
             )
             .unwrap();
 
+            let (offset, extent) = match dest_area {
+                Some(area) => {
+                    builder
+                        .clear_color_image(ClearColorImageInfo::image(dest_image.clone()))
+                        .unwrap();
+                    area
+                }
+                None => {
+                    let [width, height, _] = dest_image.extent();
+                    ([0, 0], [width, height])
+                }
+            };
+
+            let [src_width, src_height, _] = img.extent();
+            let (source, filter) = match self.scaling_filter {
+                ScalingFilter::Nearest => (img.clone(), Filter::Nearest),
+                ScalingFilter::Bilinear => (img.clone(), Filter::Linear),
+                ScalingFilter::SharpBilinear => {
+                    let factor = [
+                        (extent[0] / src_width).max(1),
+                        (extent[1] / src_height).max(1),
+                    ];
+                    if factor == [1, 1] {
+                        (img.clone(), Filter::Linear)
+                    } else {
+                        let prescaled =
+                            vulkan.prescaled_image([src_width * factor[0], src_height * factor[1]]);
+                        builder
+                            .blit_image(BlitImageInfo {
+                                filter: Filter::Nearest,
+                                ..BlitImageInfo::images(img.clone(), prescaled.clone())
+                            })
+                            .unwrap();
+                        (prescaled, Filter::Linear)
+                    }
+                }
+            };
+            let [src_width, src_height, _] = source.extent();
             builder
                 .blit_image(BlitImageInfo {
-                    filter: Filter::Nearest,
-                    ..BlitImageInfo::images(img.clone(), dest_image)
+                    filter,
+                    regions: [ImageBlit {
+                        src_subresource: source.subresource_layers(),
+                        src_offsets: [[0, 0, 0], [src_width, src_height, 1]],
+                        dst_subresource: dest_image.subresource_layers(),
+                        dst_offsets: [
+                            [offset[0], offset[1], 0],
+                            [offset[0] + extent[0], offset[1] + extent[1], 1],
+                        ],
+                        ..Default::default()
+                    }]
+                    .into(),
+                    ..BlitImageInfo::images(source, dest_image.clone())
                 })
                 .unwrap();
             let cb = builder.build().unwrap();
 
             // TODO: remove wait
             in_future
-                .then_execute(self.queue.clone(), cb)
+                .then_execute(vulkan.queue.clone(), cb)
                 .unwrap()
                 .then_signal_fence_and_flush()
                 .unwrap()
@@ -524,6 +836,153 @@ impl Gpu {
             in_future
         }
     }
+
+    /// Renders the display area into a frame, only available with the software renderer
+    pub fn software_frame(&mut self, full_vram: bool) -> Option<&SoftwareFrame> {
+        let Backend::Software(software) = &mut self.backend else {
+            return None;
+        };
+        self.state_snapshot.gpu_stat = self.gpu_stat.load();
+        software.execute(BackendCommand::BlitFront {
+            full_vram,
+            gamma: self.gamma,
+            state_snapshot: self.state_snapshot.clone(),
+        });
+        Some(software.frame())
+    }
+
+    /// Copies the last displayed frame to the CPU, for screenshots and video capture.
+    /// `None` before the first frame was presented.
+    pub fn capture_frame(&mut self) -> Option<SoftwareFrame> {
+        match &self.backend {
+            Backend::Software(software) => {
+                let frame = software.frame();
+                (frame.width > 0 && frame.height > 0).then(|| frame.clone())
+            }
+            #[cfg(feature = "vulkan")]
+            Backend::Vulkan(vulkan) => vulkan.read_front_image(),
+            #[cfg(not(feature = "vulkan"))]
+            Backend::Vulkan(_) => None,
+        }
+    }
+}
+
+impl VulkanOutput {
+    /// The front image is `B8G8R8A8`, which reads back as `0xAARRGGBB` words
+    #[cfg(feature = "vulkan")]
+    fn read_front_image(&self) -> Option<SoftwareFrame> {
+        let image = self.current_front_image.clone()?;
+        let [width, height, _] = image.extent();
+        let buffer = Buffer::new_slice::<u32>(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            (width * height) as u64,
+        )
+        .unwrap();
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.command_buffer_allocator.clone(),
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(image, buffer.clone()))
+            .unwrap();
+        let cb = builder.build().unwrap();
+
+        backend::sync::now(self.queue.device().clone())
+            .then_execute(self.queue.clone(), cb)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+
+        let pixels = buffer.read().unwrap().to_vec();
+        Some(SoftwareFrame {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Image for the integer scaled frame of [`ScalingFilter::SharpBilinear`],
+    /// kept while the size stays the same
+    #[cfg(feature = "vulkan")]
+    fn prescaled_image(&mut self, extent: [u32; 2]) -> Arc<Image> {
+        if let Some(image) = &self.prescaled_image {
+            if image.extent()[..2] == extent {
+                return image.clone();
+            }
+        }
+        let image = Image::new(
+            self.memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: Format::B8G8R8A8_UNORM,
+                extent: [extent[0], extent[1], 1],
+                usage: ImageUsage::TRANSFER_DST | ImageUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+        self.prescaled_image = Some(image.clone());
+        image
+    }
+
+    #[cfg_attr(not(feature = "vulkan"), allow(unused_variables))]
+    fn start(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        gpu_stat: Arc<AtomicGpuStat>,
+        gpu_read_sender: mpsc::Sender<u32>,
+        internal_scale: u32,
+    ) -> Self {
+        let (gpu_backend_sender, gpu_backend_receiver) = mpsc::channel();
+        let (gpu_front_image_sender, gpu_front_image_receiver) = mpsc::channel();
+
+        #[cfg(feature = "vulkan")]
+        let _gpu_backend_thread_handle = backend::GpuBackend::start(
+            device.clone(),
+            queue.clone(),
+            gpu_stat,
+            gpu_read_sender,
+            gpu_backend_receiver,
+            gpu_front_image_sender,
+            // 4096x2048 is the largest image size every Vulkan device has to support
+            internal_scale.clamp(1, 4),
+        );
+
+        Self {
+            queue,
+
+            #[cfg(feature = "vulkan")]
+            _gpu_backend_thread_handle,
+            gpu_backend_sender,
+            gpu_front_image_receiver,
+
+            first_frame: true,
+            current_front_image: None,
+            #[cfg(feature = "vulkan")]
+            memory_allocator: Arc::new(StandardMemoryAllocator::new_default(device.clone())),
+            #[cfg(feature = "vulkan")]
+            prescaled_image: None,
+            command_buffer_allocator: Arc::new(StandardCommandBufferAllocator::new(
+                device,
+                Default::default(),
+            )),
+        }
+    }
 }
 
 impl Gpu {
@@ -578,7 +1037,7 @@ impl Gpu {
                     if let Some(backend_cmd) =
                         cmd.exec_command(self.gpu_stat.clone(), &mut self.state_snapshot)
                     {
-                        self.gpu_backend_sender.send(backend_cmd).unwrap();
+                        self.send_to_backend(backend_cmd);
                     }
 
                     // ready for next command
@@ -604,7 +1063,7 @@ impl Gpu {
                 if let Some(backend_cmd) =
                     cmd.exec_command(self.gpu_stat.clone(), &mut self.state_snapshot)
                 {
-                    self.gpu_backend_sender.send(backend_cmd).unwrap();
+                    self.send_to_backend(backend_cmd);
                 }
             }
         }
@@ -639,7 +1098,7 @@ impl Gpu {
                         if let Some(backend_cmd) =
                             cmd.exec_command(self.gpu_stat.clone(), &mut self.state_snapshot)
                         {
-                            self.gpu_backend_sender.send(backend_cmd).unwrap();
+                            self.send_to_backend(backend_cmd);
                         }
                     }
                 }
@@ -1,9 +1,23 @@
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Copy, Clone, Debug)]
 #[repr(C)]
 pub struct DrawingVertex {
     position: [f32; 2],
     color: [f32; 3],
     tex_coord: [i32; 2],
+    /// Relative depth for perspective correct texturing, `1.0` on every vertex
+    /// gives the console's affine texturing
+    w: f32,
+}
+
+impl Default for DrawingVertex {
+    fn default() -> Self {
+        Self {
+            position: [0.0; 2],
+            color: [0.0; 3],
+            tex_coord: [0; 2],
+            w: 1.0,
+        }
+    }
 }
 
 impl DrawingVertex {
@@ -33,6 +47,16 @@ impl DrawingVertex {
         self.tex_coord = tex_coord;
     }
 
+    #[inline]
+    pub fn w(&self) -> f32 {
+        self.w
+    }
+
+    #[inline]
+    pub fn set_w(&mut self, w: f32) {
+        self.w = w;
+    }
+
     #[inline]
     pub fn new_with_color(color: u32) -> Self {
         let mut s = Self::default();
@@ -0,0 +1,154 @@
+//! PGXP-style geometry precision.
+//!
+//! The GTE hands projected vertices back to games as whole pixels, which makes
+//! polygons wobble as they move, and the GPU interpolates texture coordinates
+//! without perspective, which warps large textured polygons. The GTE keeps the
+//! unrounded vertices, and polygons whose vertices match one of them are drawn
+//! with its sub-pixel position and depth instead.
+//!
+//! Vertices are matched by their rounded position only, so two vertices rounding
+//! to the same pixel will share the precise values of the latest one.
+
+use std::collections::HashMap;
+
+use super::common::DrawingVertex;
+use crate::coprocessor::PreciseVertex;
+
+/// Forget old vertices once there are this many, so the map doesn't grow forever
+const MAX_VERTICES: usize = 0x10000;
+
+#[derive(Default)]
+pub(crate) struct PreciseGeometry {
+    sub_pixel: bool,
+    perspective_correct: bool,
+    /// `(x, y, z)` by rounded position
+    vertices: HashMap<(i16, i16), (f32, f32, f32)>,
+}
+
+impl PreciseGeometry {
+    pub fn new(sub_pixel: bool, perspective_correct: bool) -> Self {
+        Self {
+            sub_pixel,
+            perspective_correct,
+            vertices: HashMap::new(),
+        }
+    }
+
+    pub fn sub_pixel(&self) -> bool {
+        self.sub_pixel
+    }
+
+    pub fn perspective_correct(&self) -> bool {
+        self.perspective_correct
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.sub_pixel || self.perspective_correct
+    }
+
+    pub fn add_vertices(&mut self, vertices: impl Iterator<Item = PreciseVertex>) {
+        for vertex in vertices {
+            if self.vertices.len() >= MAX_VERTICES {
+                self.vertices.clear();
+            }
+            self.vertices
+                .insert((vertex.sx, vertex.sy), (vertex.x, vertex.y, vertex.z));
+        }
+    }
+
+    fn lookup(&self, vertex: &DrawingVertex) -> Option<(f32, f32, f32)> {
+        let [x, y] = vertex.position();
+        self.vertices.get(&(x as i16, y as i16)).copied()
+    }
+
+    /// Replaces the positions of polygon vertices that came from the GTE, and gives
+    /// them depth when all of them did, as mixing depths with flat vertices would
+    /// warp the texture even more
+    pub fn refine_polygon(&self, vertices: &mut [DrawingVertex]) {
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        if self.perspective_correct {
+            let mut max_depth = 0f32;
+            let all_found = vertices.iter().all(|vertex| match self.lookup(vertex) {
+                Some((_, _, z)) => {
+                    max_depth = max_depth.max(z);
+                    true
+                }
+                None => false,
+            });
+            if all_found && max_depth > 0.0 {
+                for vertex in vertices.iter_mut() {
+                    let (_, _, z) = self.lookup(vertex).unwrap();
+                    // only the ratios matter, keep them around 1 for float precision
+                    vertex.set_w(z / max_depth);
+                }
+            }
+        }
+
+        if self.sub_pixel {
+            for vertex in vertices.iter_mut() {
+                if let Some((x, y, _)) = self.lookup(vertex) {
+                    vertex.set_position([x, y]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn precise(sx: i16, sy: i16, x: f32, y: f32, z: f32) -> PreciseVertex {
+        PreciseVertex { sx, sy, x, y, z }
+    }
+
+    fn vertex(x: u32, y: u32) -> DrawingVertex {
+        let mut vertex = DrawingVertex::default();
+        vertex.position_from_u32(y << 16 | x);
+        vertex
+    }
+
+    #[test]
+    fn refines_matching_vertices() {
+        let mut geometry = PreciseGeometry::new(true, true);
+        geometry.add_vertices(
+            [
+                precise(10, 20, 10.25, 20.75, 100.0),
+                precise(30, 20, 30.5, 20.0, 200.0),
+            ]
+            .into_iter(),
+        );
+
+        let mut vertices = [vertex(10, 20), vertex(30, 20), vertex(20, 40)];
+        geometry.refine_polygon(&mut vertices);
+        assert_eq!(vertices[0].position(), [10.25, 20.75]);
+        assert_eq!(vertices[1].position(), [30.5, 20.0]);
+        assert_eq!(vertices[2].position(), [20.0, 40.0]);
+        // one vertex is not from the GTE, stays affine
+        assert!(vertices.iter().all(|v| v.w() == 1.0));
+
+        geometry.add_vertices([precise(20, 40, 19.5, 40.0, 400.0)].into_iter());
+        let mut vertices = [vertex(10, 20), vertex(30, 20), vertex(20, 40)];
+        geometry.refine_polygon(&mut vertices);
+        assert_eq!(vertices.map(|v| v.w()), [0.25, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn options_are_separate() {
+        let mut geometry = PreciseGeometry::new(false, true);
+        geometry.add_vertices(
+            [
+                precise(1, 1, 1.5, 1.5, 50.0),
+                precise(5, 1, 5.5, 1.0, 100.0),
+            ]
+            .into_iter(),
+        );
+        let mut vertices = [vertex(1, 1), vertex(5, 1)];
+        geometry.refine_polygon(&mut vertices);
+        assert_eq!(vertices.map(|v| v.position()), [[1.0, 1.0], [5.0, 1.0]]);
+        assert_eq!(vertices.map(|v| v.w()), [0.5, 1.0]);
+    }
+}
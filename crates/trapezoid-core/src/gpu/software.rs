@@ -0,0 +1,709 @@
+//! A pure-software rendering backend. It executes the same [`BackendCommand`]s as the
+//! Vulkan backend, but synchronously on the emulation thread, drawing into a native
+//! resolution copy of VRAM. This is used on machines without a Vulkan capable GPU.
+
+use std::{
+    ops::Range,
+    sync::{mpsc, Arc},
+};
+
+use super::{
+    common::{DrawingTextureParams, DrawingVertex},
+    AtomicGpuStat, BackendCommand, GpuStat, GpuStateSnapshot, VRAM_HEIGHT, VRAM_WIDTH,
+};
+
+/// Same offsets the Vulkan fragment shader uses, in 8bit color steps
+const DITHER_TABLE: [[i32; 4]; 4] = [
+    [-4, 0, -3, 1],
+    [2, -2, 3, -1],
+    [-3, 1, -4, 0],
+    [3, -1, 2, -2],
+];
+
+/// A frame produced by the software renderer, stored as `0xAARRGGBB` pixels, row by row
+#[derive(Debug, Default, Clone)]
+pub struct SoftwareFrame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u32>,
+}
+
+struct TextureState {
+    params: DrawingTextureParams,
+    window_mask: (u32, u32),
+    window_offset: (u32, u32),
+    blending: bool,
+}
+
+/// State that is the same for every pixel of a primitive
+struct DrawState {
+    clip_top_left: (i32, i32),
+    clip_bottom_right: (i32, i32),
+    offset: (i32, i32),
+    dither: bool,
+    semi_transparent: bool,
+    semi_transparency_mode: u8,
+    set_mask: bool,
+    check_mask: bool,
+    texture: Option<TextureState>,
+}
+
+impl DrawState {
+    fn new(
+        state_snapshot: &GpuStateSnapshot,
+        semi_transparent: bool,
+        texture: Option<TextureState>,
+    ) -> Self {
+        let gpu_stat = state_snapshot.gpu_stat;
+        let semi_transparency_mode = match &texture {
+            Some(texture) => texture.params.semi_transparency_mode,
+            None => gpu_stat.semi_transparency_mode(),
+        };
+        let (left, top) = state_snapshot.drawing_area_top_left;
+        let (right, bottom) = state_snapshot.drawing_area_bottom_right;
+
+        Self {
+            clip_top_left: (left as i32, top as i32),
+            clip_bottom_right: (
+                right.min(VRAM_WIDTH - 1) as i32,
+                bottom.min(VRAM_HEIGHT - 1) as i32,
+            ),
+            offset: state_snapshot.drawing_offset,
+            dither: gpu_stat.dither_enabled(),
+            semi_transparent,
+            semi_transparency_mode,
+            set_mask: gpu_stat.intersects(GpuStat::DRAWING_MASK_BIT),
+            check_mask: gpu_stat.intersects(GpuStat::NO_DRAW_ON_MASK),
+            texture,
+        }
+    }
+
+    fn clipped(&self, x: i32, y: i32) -> bool {
+        x < self.clip_top_left.0
+            || y < self.clip_top_left.1
+            || x > self.clip_bottom_right.0
+            || y > self.clip_bottom_right.1
+    }
+}
+
+/// A vertex with the drawing offset applied and its attributes in integer form
+#[derive(Clone, Copy)]
+struct RasterVertex {
+    position: [i32; 2],
+    color: [i32; 3],
+    tex_coord: [i32; 2],
+    w: f32,
+}
+
+impl RasterVertex {
+    fn new(vertex: &DrawingVertex, offset: (i32, i32)) -> Self {
+        // sub-pixel positions from precise geometry snap to the nearest pixel
+        let position = vertex.position().map(|p| p.round() as i32);
+        let color = vertex.color();
+        Self {
+            position: [position[0] + offset.0, position[1] + offset.1],
+            color: color.map(|c| (c * 255.0).round() as i32),
+            tex_coord: vertex.tex_coord(),
+            w: vertex.w(),
+        }
+    }
+}
+
+/// Twice the signed area of the triangle `a, b, p`, positive when `p` is on the
+/// right side of `a -> b` (in screen coordinates, y going down)
+fn edge(a: [i32; 2], b: [i32; 2], p: [i32; 2]) -> i64 {
+    (b[0] - a[0]) as i64 * (p[1] - a[1]) as i64 - (b[1] - a[1]) as i64 * (p[0] - a[0]) as i64
+}
+
+/// Pixels exactly on an edge are only drawn for top and left edges, so
+/// triangles sharing an edge don't draw it twice
+fn is_top_left_edge(a: [i32; 2], b: [i32; 2]) -> bool {
+    let dy = b[1] - a[1];
+    (dy == 0 && b[0] > a[0]) || dy < 0
+}
+
+fn interpolate(weights: [i64; 3], values: [i32; 3], area: i64) -> i32 {
+    let sum = weights[0] * values[0] as i64
+        + weights[1] * values[1] as i64
+        + weights[2] * values[2] as i64;
+    sum.div_euclid(area) as i32
+}
+
+/// Like [`interpolate`], but divides by the vertices' depth first
+fn interpolate_perspective(weights: [i64; 3], values: [i32; 3], w: [f32; 3]) -> i32 {
+    let weights = [0, 1, 2].map(|i| weights[i] as f32 / w[i]);
+    let sum = weights[0] * values[0] as f32
+        + weights[1] * values[1] as f32
+        + weights[2] * values[2] as f32;
+    (sum / (weights[0] + weights[1] + weights[2])).floor() as i32
+}
+
+fn rgb_from_u16(pixel: u16) -> [i32; 3] {
+    [
+        (pixel & 0x1F) as i32,
+        ((pixel >> 5) & 0x1F) as i32,
+        ((pixel >> 10) & 0x1F) as i32,
+    ]
+}
+
+fn u16_from_rgb(rgb: [i32; 3]) -> u16 {
+    (rgb[0] as u16) | ((rgb[1] as u16) << 5) | ((rgb[2] as u16) << 10)
+}
+
+fn expand_5bit(c: u16) -> u32 {
+    let c = (c & 0x1F) as u32;
+    (c << 3) | (c >> 2)
+}
+
+/// Each 8bit channel value raised to `1 / gamma`, like the Vulkan front blit does
+fn gamma_table(gamma: f32) -> [u8; 256] {
+    std::array::from_fn(|value| ((value as f32 / 255.0).powf(1.0 / gamma) * 255.0).round() as u8)
+}
+
+pub(super) struct SoftwareRenderer {
+    vram: Box<[u16]>,
+    frame: SoftwareFrame,
+
+    gpu_stat: Arc<AtomicGpuStat>,
+    gpu_read_sender: mpsc::Sender<u32>,
+}
+
+impl SoftwareRenderer {
+    pub fn new(gpu_stat: Arc<AtomicGpuStat>, gpu_read_sender: mpsc::Sender<u32>) -> Self {
+        Self {
+            vram: vec![0; (VRAM_WIDTH * VRAM_HEIGHT) as usize].into_boxed_slice(),
+            frame: SoftwareFrame::default(),
+            gpu_stat,
+            gpu_read_sender,
+        }
+    }
+
+    /// The last frame produced by [`BackendCommand::BlitFront`]
+    pub fn frame(&self) -> &SoftwareFrame {
+        &self.frame
+    }
+
+    pub fn execute(&mut self, command: BackendCommand) {
+        match command {
+            BackendCommand::BlitFront {
+                full_vram,
+                gamma,
+                state_snapshot,
+            } => self.blit_to_front(full_vram, gamma, &state_snapshot),
+            BackendCommand::DrawPolyline {
+                vertices,
+                semi_transparent,
+                state_snapshot,
+            } => {
+                let state = DrawState::new(&state_snapshot, semi_transparent, None);
+                for line in vertices.chunks_exact(2) {
+                    self.draw_line(&line[0], &line[1], &state);
+                }
+            }
+            BackendCommand::DrawPolygon {
+                vertices,
+                texture_params,
+                textured,
+                texture_blending,
+                semi_transparent,
+                state_snapshot,
+            } => {
+                let texture =
+                    (textured && !texture_params.texture_disable).then_some(TextureState {
+                        params: texture_params,
+                        window_mask: state_snapshot.texture_window_mask,
+                        window_offset: state_snapshot.texture_window_offset,
+                        blending: texture_blending,
+                    });
+                let state = DrawState::new(&state_snapshot, semi_transparent, texture);
+                for triangle in vertices.chunks_exact(3) {
+                    self.draw_triangle(triangle, &state);
+                }
+            }
+            BackendCommand::WriteVramBlock { block_range, block } => {
+                self.write_vram_block(block_range, &block);
+            }
+            BackendCommand::VramVramBlit { src, dst } => {
+                let block = self.read_vram_block(src);
+                self.write_vram_block(dst, &block);
+            }
+            BackendCommand::VramReadBlock { block_range } => {
+                let block = self.read_vram_block(block_range);
+                for pair in block.chunks(2) {
+                    let d1 = pair[0];
+                    let d2 = pair.get(1).copied().unwrap_or(0);
+                    self.gpu_read_sender
+                        .send(((d2 as u32) << 16) | d1 as u32)
+                        .unwrap();
+                }
+                // after sending all the data, we set the gpu_stat bit to indicate that
+                // the data can be read now
+                self.gpu_stat
+                    .fetch_update(|s| Some(s | GpuStat::READY_FOR_TO_SEND_VRAM))
+                    .unwrap();
+            }
+            BackendCommand::FillColor {
+                top_left,
+                size,
+                color,
+            } => self.fill_color(top_left, size, color),
+            BackendCommand::DumpVram { reply } => {
+                let _ = reply.send(self.vram.to_vec());
+            }
+        }
+    }
+
+    #[inline]
+    fn index(x: u32, y: u32) -> usize {
+        ((y % VRAM_HEIGHT) * VRAM_WIDTH + (x % VRAM_WIDTH)) as usize
+    }
+
+    #[inline]
+    fn pixel(&self, x: u32, y: u32) -> u16 {
+        self.vram[Self::index(x, y)]
+    }
+
+    fn write_vram_block(&mut self, block_range: (Range<u32>, Range<u32>), block: &[u16]) {
+        let width = block_range.0.len() as u32;
+        if width == 0 {
+            return;
+        }
+        for (i, value) in block.iter().enumerate() {
+            let x = block_range.0.start + i as u32 % width;
+            let y = block_range.1.start + i as u32 / width;
+            self.vram[Self::index(x, y)] = *value;
+        }
+    }
+
+    fn read_vram_block(&self, block_range: (Range<u32>, Range<u32>)) -> Vec<u16> {
+        block_range
+            .1
+            .flat_map(|y| block_range.0.clone().map(move |x| (x, y)))
+            .map(|(x, y)| self.pixel(x, y))
+            .collect()
+    }
+
+    fn fill_color(&mut self, top_left: (u32, u32), size: (u32, u32), color: (u8, u8, u8)) {
+        let value = u16_from_rgb([
+            (color.0 >> 3) as i32,
+            (color.1 >> 3) as i32,
+            (color.2 >> 3) as i32,
+        ]);
+        for y in 0..size.1 {
+            for x in 0..size.0 {
+                self.vram[Self::index(top_left.0 + x, top_left.1 + y)] = value;
+            }
+        }
+    }
+
+    fn draw_triangle(&mut self, vertices: &[DrawingVertex], state: &DrawState) {
+        let mut v = [0, 1, 2].map(|i| RasterVertex::new(&vertices[i], state.offset));
+
+        let mut area = edge(v[0].position, v[1].position, v[2].position);
+        if area == 0 {
+            return;
+        }
+        if area < 0 {
+            v.swap(1, 2);
+            area = -area;
+        }
+
+        let min_x = v.iter().map(|v| v.position[0]).min().unwrap();
+        let max_x = v.iter().map(|v| v.position[0]).max().unwrap();
+        let min_y = v.iter().map(|v| v.position[1]).min().unwrap();
+        let max_y = v.iter().map(|v| v.position[1]).max().unwrap();
+        // the GPU skips polygons that are too large
+        if max_x - min_x >= VRAM_WIDTH as i32 || max_y - min_y >= VRAM_HEIGHT as i32 {
+            return;
+        }
+
+        let left = min_x.max(state.clip_top_left.0);
+        let top = min_y.max(state.clip_top_left.1);
+        let right = max_x.min(state.clip_bottom_right.0);
+        let bottom = max_y.min(state.clip_bottom_right.1);
+
+        let edges = [(1, 2), (2, 0), (0, 1)]
+            .map(|(a, b)| (v[a].position, v[b].position))
+            .map(|(a, b)| (a, b, if is_top_left_edge(a, b) { 0 } else { 1 }));
+
+        let colors = [0, 1, 2].map(|c| [v[0].color[c], v[1].color[c], v[2].color[c]]);
+        let tex_coords = [0, 1].map(|c| [v[0].tex_coord[c], v[1].tex_coord[c], v[2].tex_coord[c]]);
+        let w = [v[0].w, v[1].w, v[2].w];
+        let perspective = state.texture.is_some() && w.iter().any(|&w| w != 1.0);
+
+        for y in top..=bottom {
+            for x in left..=right {
+                let weights = edges.map(|(a, b, _)| edge(a, b, [x, y]));
+                if weights
+                    .iter()
+                    .zip(edges.iter())
+                    .any(|(w, (_, _, bias))| *w < *bias)
+                {
+                    continue;
+                }
+                let color = colors.map(|values| interpolate(weights, values, area));
+                let tex_coord = if perspective {
+                    tex_coords.map(|values| interpolate_perspective(weights, values, w))
+                } else {
+                    tex_coords.map(|values| interpolate(weights, values, area))
+                };
+                self.plot(x, y, color, tex_coord, state);
+            }
+        }
+    }
+
+    fn draw_line(&mut self, start: &DrawingVertex, end: &DrawingVertex, state: &DrawState) {
+        let start = RasterVertex::new(start, state.offset);
+        let end = RasterVertex::new(end, state.offset);
+
+        let dx = end.position[0] - start.position[0];
+        let dy = end.position[1] - start.position[1];
+        // the GPU skips lines that are too long
+        if dx.abs() >= VRAM_WIDTH as i32 || dy.abs() >= VRAM_HEIGHT as i32 {
+            return;
+        }
+
+        let steps = dx.abs().max(dy.abs());
+        let step = |from: i32, to: i32, i: i32| {
+            if steps == 0 {
+                from
+            } else {
+                from + ((to - from) * i * 2 + steps).div_euclid(steps * 2)
+            }
+        };
+        for i in 0..=steps {
+            let x = step(start.position[0], end.position[0], i);
+            let y = step(start.position[1], end.position[1], i);
+            let color = [0, 1, 2].map(|c| step(start.color[c], end.color[c], i));
+            self.plot(x, y, color, [0; 2], state);
+        }
+    }
+
+    fn fetch_texel(&self, tex_coord: [i32; 2], texture: &TextureState) -> u16 {
+        let params = &texture.params;
+        let mask = (texture.window_mask.0 & 0x1F, texture.window_mask.1 & 0x1F);
+        let offset = (
+            texture.window_offset.0 & 0x1F,
+            texture.window_offset.1 & 0x1F,
+        );
+
+        // flipped and repeated textures wrap inside the 256x256 page
+        let u = tex_coord[0] as u32 & 0xFF;
+        let v = tex_coord[1] as u32 & 0xFF;
+        // Texcoord = (Texcoord AND (NOT (Mask*8))) OR ((Offset AND Mask)*8)
+        let u = (u & !(mask.0 * 8)) | ((offset.0 & mask.0) * 8);
+        let v = (v & !(mask.1 * 8)) | ((offset.1 & mask.1) * 8);
+
+        let [page_x, page_y] = params.tex_page_base;
+        let [clut_x, clut_y] = params.clut_base;
+        match params.tex_page_color_mode {
+            0 => {
+                let word = self.pixel(page_x + u / 4, page_y + v);
+                let index = (word >> ((u % 4) * 4)) & 0xF;
+                self.pixel(clut_x + index as u32, clut_y)
+            }
+            1 => {
+                let word = self.pixel(page_x + u / 2, page_y + v);
+                let index = (word >> ((u % 2) * 8)) & 0xFF;
+                self.pixel(clut_x + index as u32, clut_y)
+            }
+            _ => self.pixel(page_x + u, page_y + v),
+        }
+    }
+
+    /// Shades, blends and writes one pixel, `color` is in 8bit per component
+    fn plot(&mut self, x: i32, y: i32, color: [i32; 3], tex_coord: [i32; 2], state: &DrawState) {
+        if state.clipped(x, y) {
+            return;
+        }
+        let index = Self::index(x as u32, y as u32);
+        let back = self.vram[index];
+        if state.check_mask && back & 0x8000 != 0 {
+            return;
+        }
+
+        let dither = if state.dither {
+            DITHER_TABLE[(y & 3) as usize][(x & 3) as usize]
+        } else {
+            0
+        };
+        let shade = |c: i32| ((c + dither).clamp(0, 255)) >> 3;
+
+        let (rgb, semi_transparent, mask) = if let Some(texture) = &state.texture {
+            let texel = self.fetch_texel(tex_coord, texture);
+            // fully transparent texel
+            if texel == 0 {
+                return;
+            }
+            let mut rgb = rgb_from_u16(texel);
+            if texture.blending {
+                // 0x80 is the neutral color
+                for (t, c) in rgb.iter_mut().zip(color) {
+                    *t = shade((*t << 3) * c / 128);
+                }
+            }
+            (
+                rgb,
+                state.semi_transparent && texel & 0x8000 != 0,
+                texel & 0x8000,
+            )
+        } else {
+            (color.map(shade), state.semi_transparent, 0)
+        };
+
+        let rgb = if semi_transparent {
+            let back = rgb_from_u16(back);
+            [0, 1, 2].map(|c| {
+                let (b, f) = (back[c], rgb[c]);
+                match state.semi_transparency_mode {
+                    0 => (b + f) >> 1,
+                    1 => (b + f).min(0x1F),
+                    2 => (b - f).max(0),
+                    _ => (b + (f >> 2)).min(0x1F),
+                }
+            })
+        } else {
+            rgb
+        };
+
+        let mask = if state.set_mask { 0x8000 } else { mask };
+        self.vram[index] = u16_from_rgb(rgb) | mask;
+    }
+
+    fn blit_to_front(&mut self, full_vram: bool, gamma: f32, state_snapshot: &GpuStateSnapshot) {
+        let gpu_stat = state_snapshot.gpu_stat;
+        let vram_display_area_start = state_snapshot.vram_display_area_start;
+
+        let (topleft, size) = if full_vram {
+            ([0; 2], [VRAM_WIDTH, VRAM_HEIGHT])
+        } else {
+            (
+                [vram_display_area_start.0, vram_display_area_start.1],
+                state_snapshot.display_size(),
+            )
+        };
+        let is_24bit = gpu_stat.is_24bit_color_depth() && !full_vram;
+
+        let frame = &mut self.frame;
+        frame.width = size[0];
+        frame.height = size[1];
+        frame.pixels.clear();
+        frame.pixels.reserve((size[0] * size[1]) as usize);
+
+        let vram = &self.vram;
+        for y in topleft[1]..topleft[1] + size[1] {
+            if is_24bit {
+                // 3 bytes per pixel, packed into the 16bit VRAM words
+                let byte = |offset: u32| {
+                    let word = vram[Self::index(topleft[0] + offset / 2, y)];
+                    (word >> ((offset % 2) * 8)) as u8 as u32
+                };
+                frame.pixels.extend((0..size[0]).map(|x| {
+                    let offset = x * 3;
+                    0xFF00_0000 | (byte(offset) << 16) | (byte(offset + 1) << 8) | byte(offset + 2)
+                }));
+            } else {
+                frame.pixels.extend((0..size[0]).map(|x| {
+                    let pixel = vram[Self::index(topleft[0] + x, y)];
+                    0xFF00_0000
+                        | (expand_5bit(pixel) << 16)
+                        | (expand_5bit(pixel >> 5) << 8)
+                        | expand_5bit(pixel >> 10)
+                }));
+            }
+        }
+
+        if gamma != 1.0 {
+            let table = gamma_table(gamma);
+            for pixel in &mut frame.pixels {
+                let value = *pixel;
+                let channel =
+                    |shift: u32| (table[((value >> shift) & 0xFF) as usize] as u32) << shift;
+                *pixel = 0xFF00_0000 | channel(16) | channel(8) | channel(0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renderer() -> (SoftwareRenderer, mpsc::Receiver<u32>) {
+        let (sender, receiver) = mpsc::channel();
+        let gpu_stat = Arc::new(AtomicGpuStat::new(GpuStat::empty()));
+        (SoftwareRenderer::new(gpu_stat, sender), receiver)
+    }
+
+    fn snapshot() -> GpuStateSnapshot {
+        GpuStateSnapshot {
+            drawing_area_bottom_right: (VRAM_WIDTH - 1, VRAM_HEIGHT - 1),
+            ..Default::default()
+        }
+    }
+
+    fn vertex(color: u32, x: f32, y: f32, tex_coord: [i32; 2]) -> DrawingVertex {
+        let mut vertex = DrawingVertex::new_with_color(color);
+        vertex.set_position([x, y]);
+        vertex.set_tex_coord(tex_coord);
+        vertex
+    }
+
+    /// The triangle list a rectangle command produces
+    fn rect(color: u32, x: f32, y: f32, w: f32, h: f32, tex: [i32; 2]) -> Vec<DrawingVertex> {
+        let w_tex = w as i32;
+        let h_tex = h as i32;
+        let top_right = vertex(color, x + w, y, [tex[0] + w_tex, tex[1]]);
+        let bottom_left = vertex(color, x, y + h, [tex[0], tex[1] + h_tex]);
+        vec![
+            vertex(color, x, y, tex),
+            top_right,
+            bottom_left,
+            top_right,
+            bottom_left,
+            vertex(color, x + w, y + h, [tex[0] + w_tex, tex[1] + h_tex]),
+        ]
+    }
+
+    fn draw(renderer: &mut SoftwareRenderer, vertices: Vec<DrawingVertex>, textured: bool) {
+        draw_with(renderer, vertices, textured, false, snapshot());
+    }
+
+    fn draw_with(
+        renderer: &mut SoftwareRenderer,
+        vertices: Vec<DrawingVertex>,
+        textured: bool,
+        semi_transparent: bool,
+        state_snapshot: GpuStateSnapshot,
+    ) {
+        let mut texture_params = DrawingTextureParams::default();
+        texture_params.tex_page_from_gpustat(0x1); // page x=64, 4bit
+        texture_params.clut_from_u32(0x0004_0000 | (100 << 22)); // clut x=64, y=100
+        renderer.execute(BackendCommand::DrawPolygon {
+            vertices,
+            texture_params,
+            textured,
+            texture_blending: false,
+            semi_transparent,
+            state_snapshot,
+        });
+    }
+
+    fn count_pixels(renderer: &SoftwareRenderer, value: u16) -> usize {
+        renderer.vram.iter().filter(|p| **p == value).count()
+    }
+
+    #[test]
+    fn rectangles_cover_exact_pixels() {
+        let (mut renderer, _) = renderer();
+        // pure red, 8x4 at (10, 20)
+        draw(
+            &mut renderer,
+            rect(0xFF, 10.0, 20.0, 8.0, 4.0, [0; 2]),
+            false,
+        );
+
+        assert_eq!(count_pixels(&renderer, 0x1F), 32);
+        assert_eq!(renderer.pixel(10, 20), 0x1F);
+        assert_eq!(renderer.pixel(17, 23), 0x1F);
+        assert_eq!(renderer.pixel(18, 20), 0);
+        assert_eq!(renderer.pixel(10, 24), 0);
+    }
+
+    #[test]
+    fn drawing_area_and_offset() {
+        let (mut renderer, _) = renderer();
+        let mut state_snapshot = snapshot();
+        state_snapshot.drawing_area_top_left = (100, 100);
+        state_snapshot.drawing_area_bottom_right = (103, 103);
+        state_snapshot.drawing_offset = (100, 100);
+        draw_with(
+            &mut renderer,
+            rect(0xFF, -2.0, -2.0, 16.0, 16.0, [0; 2]),
+            false,
+            false,
+            state_snapshot,
+        );
+
+        assert_eq!(count_pixels(&renderer, 0x1F), 16);
+        assert_eq!(renderer.pixel(100, 100), 0x1F);
+        assert_eq!(renderer.pixel(103, 103), 0x1F);
+    }
+
+    #[test]
+    fn textured_4bit_with_clut_and_transparency() {
+        let (mut renderer, _) = renderer();
+        // clut: index 1 is white, index 2 is semi transparent blue, index 0 is transparent
+        renderer.execute(BackendCommand::WriteVramBlock {
+            block_range: (64..67, 100..101),
+            block: vec![0, 0x7FFF, 0xFC00],
+        });
+        // texel indices 1, 0, 2, 1 in one VRAM word
+        renderer.execute(BackendCommand::WriteVramBlock {
+            block_range: (64..65, 0..1),
+            block: vec![0x1201],
+        });
+
+        draw(&mut renderer, rect(0, 0.0, 300.0, 4.0, 1.0, [0; 2]), true);
+
+        assert_eq!(renderer.pixel(0, 300), 0x7FFF);
+        assert_eq!(renderer.pixel(1, 300), 0);
+        assert_eq!(renderer.pixel(2, 300), 0xFC00);
+        assert_eq!(renderer.pixel(3, 300), 0x7FFF);
+    }
+
+    #[test]
+    fn semi_transparency_additive() {
+        let (mut renderer, _) = renderer();
+        renderer.execute(BackendCommand::FillColor {
+            top_left: (0, 0),
+            size: (16, 16),
+            color: (0x40, 0, 0),
+        });
+        let mut state_snapshot = snapshot();
+        state_snapshot.gpu_stat = GpuStat::from_bits_retain(1 << 5); // B+F
+        draw_with(
+            &mut renderer,
+            rect(0x50, 0.0, 0.0, 2.0, 2.0, [0; 2]),
+            false,
+            true,
+            state_snapshot,
+        );
+
+        assert_eq!(renderer.pixel(0, 0), 8 + 10);
+        assert_eq!(renderer.pixel(2, 0), 8);
+    }
+
+    #[test]
+    fn vram_read_and_24bit_frame() {
+        let (mut renderer, receiver) = renderer();
+        renderer.execute(BackendCommand::WriteVramBlock {
+            block_range: (1022..1025, 0..1),
+            block: vec![0x2211, 0x4433, 0x6655],
+        });
+        renderer.execute(BackendCommand::VramReadBlock {
+            block_range: (1022..1025, 0..1),
+        });
+        assert_eq!(receiver.try_recv(), Ok(0x4433_2211));
+        assert_eq!(receiver.try_recv(), Ok(0x6655));
+        // wrapped around to the left edge
+        assert_eq!(renderer.pixel(0, 0), 0x6655);
+
+        renderer.execute(BackendCommand::WriteVramBlock {
+            block_range: (0..3, 10..11),
+            block: vec![0x2211, 0x4433, 0x6655],
+        });
+        let mut state_snapshot = snapshot();
+        state_snapshot.gpu_stat = GpuStat::DISPLAY_AREA_COLOR_DEPTH;
+        state_snapshot.vram_display_area_start = (0, 10);
+        renderer.execute(BackendCommand::BlitFront {
+            full_vram: false,
+            gamma: 1.0,
+            state_snapshot,
+        });
+        let frame = renderer.frame();
+        assert_eq!((frame.width, frame.height), (256, 240));
+        assert_eq!(frame.pixels[0], 0xFF11_2233);
+        assert_eq!(frame.pixels[1], 0xFF44_5566);
+    }
+}
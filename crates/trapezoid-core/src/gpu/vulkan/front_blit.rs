@@ -3,7 +3,7 @@ use std::sync::Arc;
 use vulkano::{
     buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
     command_buffer::{
-        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, BlitImageInfo,
         CommandBufferExecFuture, CommandBufferUsage, CopyBufferToImageInfo, CopyImageToBufferInfo,
         PrimaryAutoCommandBuffer, RenderPassBeginInfo,
     },
@@ -45,6 +45,8 @@ const VRAM_HEIGHT: u32 = 512;
 const VRAM_BYTES_PER_ROW: u32 = VRAM_WIDTH_WORDS * 2;
 const VRAM_WIDTH_24BIT: u32 = VRAM_BYTES_PER_ROW / 3;
 const COMPUTE_LOCAL_SIZE_XY: u32 = 8;
+/// The fragment shader's gamma follows the vertex shader's three `uvec2`s
+const FRAGMENT_PUSH_CONSTANTS_OFFSET: u32 = 24;
 
 mod vs {
     vulkano_shaders::shader! {
@@ -85,8 +87,14 @@ layout(location = 0) out vec4 f_color;
 
 layout(set = 0, binding = 0) uniform sampler2D tex;
 
+// after the vertex shader's block
+layout(push_constant) uniform PushConstantData {
+    layout(offset = 24) float inv_gamma;
+} pc;
+
 void main() {
-    f_color = texture(tex, tex_coords);
+    vec4 color = texture(tex, tex_coords);
+    f_color = vec4(pow(color.rgb, vec3(pc.inv_gamma)), color.a);
 }"
     }
 }
@@ -164,6 +172,9 @@ pub(super) struct FrontBlit {
     descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
 
     texture_image: Arc<Image>,
+    /// Native resolution copy of `texture_image`, only needed when rendering at a higher
+    /// internal resolution, since 24bit frames are decoded from the raw VRAM bytes
+    texture_native_image: Option<Arc<Image>>,
 
     texture_24bit_image: Arc<Image>,
     texture_24bit_in_buffer: Subbuffer<[u32]>,
@@ -277,6 +288,21 @@ impl FrontBlit {
         )
         .unwrap();
 
+        let texture_native_image = (source_image.extent()[0] > VRAM_WIDTH_WORDS).then(|| {
+            Image::new(
+                memory_allocator.clone(),
+                ImageCreateInfo {
+                    image_type: ImageType::Dim2d,
+                    format: source_image.format(),
+                    usage: ImageUsage::TRANSFER_DST | ImageUsage::TRANSFER_SRC,
+                    extent: [VRAM_WIDTH_WORDS, VRAM_HEIGHT, 1],
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default(),
+            )
+            .unwrap()
+        });
+
         let texture_24bit_image = Image::new(
             memory_allocator.clone(),
             ImageCreateInfo {
@@ -363,6 +389,7 @@ impl FrontBlit {
             command_buffer_allocator,
             descriptor_set_allocator,
             texture_image: source_image,
+            texture_native_image,
             texture_24bit_image,
             texture_24bit_in_buffer,
             texture_24bit_out_buffer,
@@ -381,6 +408,7 @@ impl FrontBlit {
         sample_size: [u32; 2],
         source_extent: [u32; 2],
         is_24bit_color_depth: bool,
+        gamma: f32,
         mut in_future: IF,
     ) -> CommandBufferExecFuture<IF>
     where
@@ -400,9 +428,24 @@ impl FrontBlit {
             .unwrap();
 
         if is_24bit_color_depth {
+            let native_image = match &self.texture_native_image {
+                Some(native_image) => {
+                    builder
+                        .blit_image(BlitImageInfo {
+                            filter: Filter::Nearest,
+                            ..BlitImageInfo::images(
+                                self.texture_image.clone(),
+                                native_image.clone(),
+                            )
+                        })
+                        .unwrap();
+                    native_image.clone()
+                }
+                None => self.texture_image.clone(),
+            };
             builder
                 .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
-                    self.texture_image.clone(),
+                    native_image,
                     self.texture_24bit_in_buffer.clone(),
                 ))
                 .unwrap()
@@ -434,6 +477,8 @@ impl FrontBlit {
             source_image = self.texture_24bit_image.clone();
         }
 
+        // the front image has the size of the sampled area, so this is a plain copy,
+        // the frame is only scaled, and filtered, when blitting it to the frontend
         let sampler = Sampler::new(
             self.device.clone(),
             SamplerCreateInfo {
@@ -525,6 +570,12 @@ impl FrontBlit {
             .unwrap()
             .push_constants(self.g_pipeline.layout().clone(), 0, push_constants)
             .unwrap()
+            .push_constants(
+                self.g_pipeline.layout().clone(),
+                FRAGMENT_PUSH_CONSTANTS_OFFSET,
+                1.0 / gamma,
+            )
+            .unwrap()
             .bind_vertex_buffers(0, self.vertex_buffer.clone())
             .unwrap();
         // Safety: Shader safety, tested
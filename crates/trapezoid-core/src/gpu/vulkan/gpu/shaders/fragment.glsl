@@ -14,6 +14,12 @@ layout(set = 0, binding = 0) uniform sampler2D back_tex;
 
 const vec2 SCREEN_DIM = vec2(1024, 512);
 
+// VRAM may be stored at a higher internal resolution, in which case every
+// native pixel is a `vram_scale() x vram_scale()` block of texels
+uint vram_scale() {
+    return uint(textureSize(back_tex, 0).x) / 1024u;
+}
+
 const float dither_table[16] = {
     -4.0/255.0,  +0.0/255.0,  -3.0/255.0,  +1.0/255.0,   //\dither offsets for first two scanlines
     +2.0/255.0,  -2.0/255.0,  +3.0/255.0,  -1.0/255.0,   ///
@@ -28,7 +34,7 @@ vec3 get_color_with_semi_transparency_for_mode_3(vec3 color, bool semi_transpare
         return color;
     }
 
-    vec3 back_color = vec3(texture(back_tex, gl_FragCoord.xy / SCREEN_DIM));
+    vec3 back_color = vec3(texture(back_tex, gl_FragCoord.xy / vec2(textureSize(back_tex, 0))));
 
     return (1.0 * back_color) + (0.25 * color);
 }
@@ -66,7 +72,7 @@ vec4 fetch_color_from_texture_float(vec2 coord) {
 vec4 fetch_color_from_texture(uvec2 coord) {
     coord.x = coord.x & 1023u;
     coord.y = coord.y & 511u;
-    return texelFetch(back_tex, ivec2(coord), 0);
+    return texelFetch(back_tex, ivec2(coord * vram_scale()), 0);
 }
 
 uint u16_from_color_with_alpha(vec4 raw_color_value) {
@@ -89,8 +95,9 @@ void main() {
     bool is_texture_blended = (bool_flags & 0x8u) != 0;
 
     if (dither_enabled) {
-        uint x = uint(gl_FragCoord.x) % 4;
-        uint y = uint(gl_FragCoord.y) % 4;
+        // dither in native pixels, so the pattern doesn't get finer when upscaling
+        uint x = (uint(gl_FragCoord.x) / vram_scale()) % 4;
+        uint y = (uint(gl_FragCoord.y) / vram_scale()) % 4;
 
         float change = dither_table[y * 4 + x];
         t_color = v_color + change;
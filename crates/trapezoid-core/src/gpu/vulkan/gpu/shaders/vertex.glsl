@@ -7,6 +7,8 @@ layout(location = 2)  in ivec2 tex_coord;
 layout(location = 3)  in uvec4 tex_info;
 layout(location = 4)  in uvec4 tex_window;
 layout(location = 5)  in uvec3 extra_draw_state;
+// relative depth, `1.0` everywhere keeps the affine texturing of the console
+layout(location = 6)  in float w;
 
 
 layout(location = 0)  out vec3  v_color;
@@ -25,7 +27,8 @@ layout(push_constant) uniform PushConstantData {
 void main() {
     vec2 pos = ((position + pc.offset - pc.drawing_top_left) / pc.drawing_size) * 2 - 1;
 
-    gl_Position = vec4(pos, 0.0, 1.0);
+    // scaling by `w` makes the rasterizer interpolate with perspective
+    gl_Position = vec4(pos * w, 0.0, w);
     v_color = color;
     v_tex_coord = vec2(tex_coord);
 
@@ -8,7 +8,7 @@ use vulkano::{
 };
 
 use super::gpu_context::GpuContext;
-use crate::gpu::{AtomicGpuStat, BackendCommand, GpuStat};
+use crate::gpu::{AtomicGpuStat, BackendCommand, GpuStat, VRAM_HEIGHT, VRAM_WIDTH};
 
 pub struct GpuBackend {
     gpu_context: GpuContext,
@@ -26,10 +26,11 @@ impl GpuBackend {
         gpu_read_sender: mpsc::Sender<u32>,
         gpu_backend_receiver: mpsc::Receiver<BackendCommand>,
         gpu_front_image_sender: mpsc::Sender<Arc<Image>>,
+        internal_scale: u32,
     ) -> JoinHandle<()> {
         thread::spawn(move || {
             let b = GpuBackend {
-                gpu_context: GpuContext::new(device, queue, gpu_front_image_sender),
+                gpu_context: GpuContext::new(device, queue, gpu_front_image_sender, internal_scale),
                 gpu_stat,
                 gpu_read_sender,
                 gpu_backend_receiver,
@@ -43,9 +44,10 @@ impl GpuBackend {
             match self.gpu_backend_receiver.recv() {
                 Ok(BackendCommand::BlitFront {
                     full_vram,
+                    gamma,
                     state_snapshot,
                 }) => {
-                    self.gpu_context.blit_to_front(full_vram, state_snapshot);
+                    self.gpu_context.blit_to_front(full_vram, gamma, state_snapshot);
                 }
                 Ok(BackendCommand::DrawPolyline {
                     vertices,
@@ -122,6 +124,12 @@ impl GpuBackend {
                 }) => {
                     self.gpu_context.fill_color(top_left, size, color);
                 }
+                Ok(BackendCommand::DumpVram { reply }) => {
+                    let vram = self
+                        .gpu_context
+                        .read_vram_block((0..VRAM_WIDTH, 0..VRAM_HEIGHT));
+                    let _ = reply.send(vram);
+                }
                 Err(_) => {}
             }
         }
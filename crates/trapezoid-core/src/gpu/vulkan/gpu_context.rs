@@ -1,10 +1,10 @@
 pub use vulkano::{
     buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage},
     command_buffer::{
-        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, BufferImageCopy,
-        ClearAttachment, ClearColorImageInfo, ClearRect, CommandBufferUsage, CopyBufferToImageInfo,
-        CopyImageInfo, CopyImageToBufferInfo, ImageCopy, PrimaryAutoCommandBuffer,
-        PrimaryCommandBufferAbstract, RenderPassBeginInfo,
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, BlitImageInfo,
+        BufferImageCopy, ClearAttachment, ClearColorImageInfo, ClearRect, CommandBufferUsage,
+        CopyBufferToImageInfo, CopyImageInfo, CopyImageToBufferInfo, ImageBlit, ImageCopy,
+        PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract, RenderPassBeginInfo,
     },
     descriptor_set::{
         allocator::StandardDescriptorSetAllocator, DescriptorSet, WriteDescriptorSet,
@@ -67,7 +67,7 @@ mod fs {
     }
 }
 
-/// Contains the vertex data `position, color, tex_coord`, as well as
+/// Contains the vertex data `position, color, tex_coord, w`, as well as
 /// data that is global to the whole polygon/polyline, and were normally sent through
 /// `push_constants`, but after using polygon/polyline draw buffering, it would be better
 /// to group them into the vertex data.
@@ -83,6 +83,8 @@ struct DrawingVertexFull {
     color: [f32; 3],
     #[format(R32G32_SINT)]
     tex_coord: [i32; 2],
+    #[format(R32_SFLOAT)]
+    w: f32,
 
     /// group multiple data into one array
     /// clut_base: [u32; 2],
@@ -130,6 +132,7 @@ impl DrawingVertexFull {
             position: v.position(),
             color: v.color(),
             tex_coord: v.tex_coord(),
+            w: v.w(),
             tex_info: [
                 texture_params.clut_base[0],
                 texture_params.clut_base[1],
@@ -188,6 +191,10 @@ pub struct GpuContext {
     memory_allocator: Arc<StandardMemoryAllocator>,
     command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
 
+    /// Internal resolution multiplier, `render_image` stores VRAM at `scale` times the
+    /// native 1024x512. Drawing happens at that resolution, while CPU transfers are
+    /// resampled from/to native resolution so VRAM contents stay the same.
+    scale: u32,
     render_image: Arc<Image>,
     render_image_back_image: Arc<Image>,
     should_update_back_image: bool,
@@ -213,6 +220,7 @@ impl GpuContext {
         device: Arc<Device>,
         queue: Arc<Queue>,
         gpu_front_image_sender: mpsc::Sender<Arc<Image>>,
+        scale: u32,
     ) -> Self {
         let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
         let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
@@ -228,7 +236,7 @@ impl GpuContext {
             memory_allocator.clone(),
             ImageCreateInfo {
                 image_type: ImageType::Dim2d,
-                extent: [1024, 512, 1],
+                extent: [VRAM_WIDTH * scale, VRAM_HEIGHT * scale, 1],
                 format: Format::A1R5G5B5_UNORM_PACK16,
                 usage: ImageUsage::TRANSFER_SRC
                     | ImageUsage::TRANSFER_DST
@@ -244,7 +252,7 @@ impl GpuContext {
             memory_allocator.clone(),
             ImageCreateInfo {
                 image_type: ImageType::Dim2d,
-                extent: [1024, 512, 1],
+                extent: [VRAM_WIDTH * scale, VRAM_HEIGHT * scale, 1],
                 format: Format::A1R5G5B5_UNORM_PACK16,
                 usage: ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
                 ..Default::default()
@@ -437,6 +445,7 @@ impl GpuContext {
             memory_allocator,
             command_buffer_allocator,
 
+            scale,
             render_image,
             render_image_framebuffer,
 
@@ -486,7 +495,7 @@ impl GpuContext {
 
         let overflow_x = left + width > 1024;
         let overflow_y = top + height > 512;
-        if overflow_x || overflow_y {
+        if overflow_x || overflow_y || self.scale > 1 {
             let stage_image = Image::new(
                 self.memory_allocator.clone(),
                 ImageCreateInfo {
@@ -507,75 +516,13 @@ impl GpuContext {
                 ))
                 .unwrap();
 
-            // if we are not overflowing in a direction, just keep the old value
-            let not_overflowing_width = (1024 - left).min(width);
-            let not_overflowing_height = (512 - top).min(height);
-            let remaining_width = width - not_overflowing_width;
-            let remaining_height = height - not_overflowing_height;
-
-            // copy the not overflowing content
-            self.command_builder
-                .copy_image(CopyImageInfo {
-                    regions: [ImageCopy {
-                        src_subresource: stage_image.subresource_layers(),
-                        src_offset: [0, 0, 0],
-                        dst_subresource: self.render_image.subresource_layers(),
-                        dst_offset: [left, top, 0],
-                        extent: [not_overflowing_width, not_overflowing_height, 1],
-                        ..Default::default()
-                    }]
-                    .into(),
-                    ..CopyImageInfo::images(stage_image.clone(), self.render_image.clone())
-                })
-                .unwrap();
-
-            if overflow_x {
-                self.command_builder
-                    .copy_image(CopyImageInfo {
-                        regions: [ImageCopy {
-                            src_subresource: stage_image.subresource_layers(),
-                            src_offset: [not_overflowing_width, 0, 0],
-                            dst_subresource: self.render_image.subresource_layers(),
-                            dst_offset: [0, top, 0],
-                            extent: [remaining_width, not_overflowing_height, 1],
-                            ..Default::default()
-                        }]
-                        .into(),
-                        ..CopyImageInfo::images(stage_image.clone(), self.render_image.clone())
-                    })
-                    .unwrap();
-            }
-            if overflow_y {
-                self.command_builder
-                    .copy_image(CopyImageInfo {
-                        regions: [ImageCopy {
-                            src_subresource: stage_image.subresource_layers(),
-                            src_offset: [0, not_overflowing_height, 0],
-                            dst_subresource: self.render_image.subresource_layers(),
-                            dst_offset: [left, 0, 0],
-                            extent: [not_overflowing_width, remaining_height, 1],
-                            ..Default::default()
-                        }]
-                        .into(),
-                        ..CopyImageInfo::images(stage_image.clone(), self.render_image.clone())
-                    })
-                    .unwrap();
-            }
-            if overflow_x && overflow_y {
-                self.command_builder
-                    .copy_image(CopyImageInfo {
-                        regions: [ImageCopy {
-                            src_subresource: stage_image.subresource_layers(),
-                            src_offset: [not_overflowing_width, not_overflowing_height, 0],
-                            dst_subresource: self.render_image.subresource_layers(),
-                            dst_offset: [0, 0, 0],
-                            extent: [remaining_width, remaining_height, 1],
-                            ..Default::default()
-                        }]
-                        .into(),
-                        ..CopyImageInfo::images(stage_image, self.render_image.clone())
-                    })
-                    .unwrap();
+            for (stage_offset, vram_offset, extent) in wrapped_blocks(left, top, width, height) {
+                copy_vram_region(
+                    &mut self.command_builder,
+                    VramRegion::native(stage_image.clone(), stage_offset),
+                    VramRegion::scaled(self.render_image.clone(), vram_offset, self.scale),
+                    extent,
+                );
             }
         } else {
             self.command_builder
@@ -631,7 +578,7 @@ impl GpuContext {
 
         let overflow_x = left + width > 1024;
         let overflow_y = top + height > 512;
-        if overflow_x || overflow_y {
+        if overflow_x || overflow_y || self.scale > 1 {
             let stage_image = Image::new(
                 self.memory_allocator.clone(),
                 ImageCreateInfo {
@@ -645,75 +592,13 @@ impl GpuContext {
             )
             .unwrap();
 
-            // if we are not overflowing in a direction, just keep the old value
-            let not_overflowing_width = (1024 - left).min(width);
-            let not_overflowing_height = (512 - top).min(height);
-            let remaining_width = width - not_overflowing_width;
-            let remaining_height = height - not_overflowing_height;
-
-            // copy the not overflowing content
-            builder
-                .copy_image(CopyImageInfo {
-                    regions: [ImageCopy {
-                        src_subresource: self.render_image.subresource_layers(),
-                        src_offset: [left, top, 0],
-                        dst_subresource: stage_image.subresource_layers(),
-                        dst_offset: [0, 0, 0],
-                        extent: [not_overflowing_width, not_overflowing_height, 1],
-                        ..Default::default()
-                    }]
-                    .into(),
-                    ..CopyImageInfo::images(self.render_image.clone(), stage_image.clone())
-                })
-                .unwrap();
-
-            if overflow_x {
-                builder
-                    .copy_image(CopyImageInfo {
-                        regions: [ImageCopy {
-                            src_subresource: self.render_image.subresource_layers(),
-                            src_offset: [0, top, 0],
-                            dst_subresource: stage_image.subresource_layers(),
-                            dst_offset: [not_overflowing_width, 0, 0],
-                            extent: [remaining_width, not_overflowing_height, 1],
-                            ..Default::default()
-                        }]
-                        .into(),
-                        ..CopyImageInfo::images(self.render_image.clone(), stage_image.clone())
-                    })
-                    .unwrap();
-            }
-            if overflow_y {
-                builder
-                    .copy_image(CopyImageInfo {
-                        regions: [ImageCopy {
-                            src_subresource: self.render_image.subresource_layers(),
-                            src_offset: [left, 0, 0],
-                            dst_subresource: stage_image.subresource_layers(),
-                            dst_offset: [0, not_overflowing_height, 0],
-                            extent: [not_overflowing_width, remaining_height, 1],
-                            ..Default::default()
-                        }]
-                        .into(),
-                        ..CopyImageInfo::images(self.render_image.clone(), stage_image.clone())
-                    })
-                    .unwrap();
-            }
-            if overflow_x && overflow_y {
-                builder
-                    .copy_image(CopyImageInfo {
-                        regions: [ImageCopy {
-                            src_subresource: self.render_image.subresource_layers(),
-                            src_offset: [0, 0, 0],
-                            dst_subresource: stage_image.subresource_layers(),
-                            dst_offset: [not_overflowing_width, not_overflowing_height, 0],
-                            extent: [remaining_width, remaining_height, 1],
-                            ..Default::default()
-                        }]
-                        .into(),
-                        ..CopyImageInfo::images(self.render_image.clone(), stage_image.clone())
-                    })
-                    .unwrap();
+            for (stage_offset, vram_offset, extent) in wrapped_blocks(left, top, width, height) {
+                copy_vram_region(
+                    &mut builder,
+                    VramRegion::scaled(self.render_image.clone(), vram_offset, self.scale),
+                    VramRegion::native(stage_image.clone(), stage_offset),
+                    extent,
+                );
             }
 
             builder
@@ -810,8 +695,8 @@ impl GpuContext {
                 .into_iter()
                 .collect(),
                 [ClearRect {
-                    offset: [top_left.0, top_left.1],
-                    extent: [width, height],
+                    offset: [top_left.0 * self.scale, top_left.1 * self.scale],
+                    extent: [width * self.scale, height * self.scale],
                     array_layers: 0..1,
                 }]
                 .into_iter()
@@ -978,6 +863,7 @@ impl GpuContext {
             DrawType::Polyline => &self.polyline_pipelines,
         };
         let pipeline = &pipelines_set[current_state.semi_transparency_mode as usize];
+        let scale = self.scale as f32;
 
         let push_constants = vs::PushConstantData {
             offset: [
@@ -1000,8 +886,14 @@ impl GpuContext {
             .set_viewport(
                 0,
                 [Viewport {
-                    offset: [current_state.left as f32, current_state.top as f32],
-                    extent: [current_state.width as f32, current_state.height as f32],
+                    offset: [
+                        current_state.left as f32 * scale,
+                        current_state.top as f32 * scale,
+                    ],
+                    extent: [
+                        current_state.width as f32 * scale,
+                        current_state.height as f32 * scale,
+                    ],
                     depth_range: 0.0..=1.0,
                 }]
                 .into_iter()
@@ -1187,7 +1079,12 @@ impl GpuContext {
         );
     }
 
-    pub(super) fn blit_to_front(&mut self, full_vram: bool, state_snapshot: GpuStateSnapshot) {
+    pub(super) fn blit_to_front(
+        &mut self,
+        full_vram: bool,
+        gamma: f32,
+        state_snapshot: GpuStateSnapshot,
+    ) {
         let gpu_stat = state_snapshot.gpu_stat;
         let vram_display_area_start = state_snapshot.vram_display_area_start;
 
@@ -1197,31 +1094,9 @@ impl GpuContext {
         let (topleft, size) = if full_vram {
             ([0; 2], [VRAM_WIDTH, VRAM_HEIGHT])
         } else {
-            // (((X2-X1)/cycles_per_pix)+2) AND NOT 3
-            let mut horizontal_size = (((state_snapshot.display_horizontal_range.1
-                - state_snapshot.display_horizontal_range.0)
-                / gpu_stat.horizontal_dots_divider())
-                + 2)
-                & !3;
-
-            if horizontal_size == 0 {
-                horizontal_size = gpu_stat.horizontal_resolution();
-            }
-
-            let should_double = gpu_stat.vertical_resolution() == 480;
-
-            // Y2-Y1, double if we are interlacing
-            let mut vertical_size = (state_snapshot.display_vertical_range.1
-                - state_snapshot.display_vertical_range.0)
-                << should_double as u32;
-
-            if vertical_size == 0 {
-                vertical_size = gpu_stat.vertical_resolution();
-            }
-
             (
                 [vram_display_area_start.0, vram_display_area_start.1],
-                [horizontal_size, vertical_size],
+                state_snapshot.display_size(),
             )
         };
 
@@ -1235,11 +1110,13 @@ impl GpuContext {
             source_extent[0] = VRAM_WIDTH_24BIT;
         }
 
+        // 24bit frames are decoded from native VRAM bytes, so they can't be upscaled
+        let front_scale = if is_24bit { 1 } else { self.scale };
         let front_image = Image::new(
             self.memory_allocator.clone(),
             ImageCreateInfo {
                 image_type: ImageType::Dim2d,
-                extent: [size[0], size[1], 1],
+                extent: [size[0] * front_scale, size[1] * front_scale, 1],
                 format: Format::B8G8R8A8_UNORM,
                 usage: ImageUsage::TRANSFER_DST
                     | ImageUsage::TRANSFER_SRC
@@ -1258,6 +1135,7 @@ impl GpuContext {
                 sample_size,
                 source_extent,
                 is_24bit,
+                gamma,
                 self.gpu_future.take().unwrap(),
             )
             .then_signal_fence_and_flush()
@@ -1272,3 +1150,117 @@ impl GpuContext {
         self.gpu_future = Some(sync::now(self.device.clone()).boxed());
     }
 }
+
+/// Splits a VRAM block that may wrap around the right and bottom edges into the parts
+/// that fit, as `(block_offset, vram_offset, extent)`
+fn wrapped_blocks(
+    left: u32,
+    top: u32,
+    width: u32,
+    height: u32,
+) -> impl Iterator<Item = ([u32; 2], [u32; 2], [u32; 2])> {
+    // if we are not overflowing in a direction, just keep the old value
+    let not_overflowing_width = (VRAM_WIDTH - left).min(width);
+    let not_overflowing_height = (VRAM_HEIGHT - top).min(height);
+    let remaining_width = width - not_overflowing_width;
+    let remaining_height = height - not_overflowing_height;
+
+    [
+        (
+            [0, 0],
+            [left, top],
+            [not_overflowing_width, not_overflowing_height],
+        ),
+        (
+            [not_overflowing_width, 0],
+            [0, top],
+            [remaining_width, not_overflowing_height],
+        ),
+        (
+            [0, not_overflowing_height],
+            [left, 0],
+            [not_overflowing_width, remaining_height],
+        ),
+        (
+            [not_overflowing_width, not_overflowing_height],
+            [0, 0],
+            [remaining_width, remaining_height],
+        ),
+    ]
+    .into_iter()
+    .filter(|(_, _, extent)| extent[0] > 0 && extent[1] > 0)
+}
+
+/// A position inside an image that stores VRAM data at `scale` times the native resolution
+struct VramRegion {
+    image: Arc<Image>,
+    offset: [u32; 2],
+    scale: u32,
+}
+
+impl VramRegion {
+    fn native(image: Arc<Image>, offset: [u32; 2]) -> Self {
+        Self::scaled(image, offset, 1)
+    }
+
+    fn scaled(image: Arc<Image>, offset: [u32; 2], scale: u32) -> Self {
+        Self {
+            image,
+            offset,
+            scale,
+        }
+    }
+
+    /// Texel bounds of a block of native `extent` starting at this region
+    fn bounds(&self, extent: [u32; 2]) -> [[u32; 3]; 2] {
+        let [x, y] = self.offset.map(|v| v * self.scale);
+        [
+            [x, y, 0],
+            [x + extent[0] * self.scale, y + extent[1] * self.scale, 1],
+        ]
+    }
+}
+
+/// Records a copy of a native `extent` block from `src` to `dst`, resampling with nearest
+/// filtering when the two images are stored at different scales
+fn copy_vram_region(
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    src: VramRegion,
+    dst: VramRegion,
+    extent: [u32; 2],
+) {
+    let src_bounds = src.bounds(extent);
+    let dst_bounds = dst.bounds(extent);
+
+    if src.scale == dst.scale {
+        builder
+            .copy_image(CopyImageInfo {
+                regions: [ImageCopy {
+                    src_subresource: src.image.subresource_layers(),
+                    src_offset: src_bounds[0],
+                    dst_subresource: dst.image.subresource_layers(),
+                    dst_offset: dst_bounds[0],
+                    extent: [extent[0] * src.scale, extent[1] * src.scale, 1],
+                    ..Default::default()
+                }]
+                .into(),
+                ..CopyImageInfo::images(src.image, dst.image)
+            })
+            .unwrap();
+    } else {
+        builder
+            .blit_image(BlitImageInfo {
+                regions: [ImageBlit {
+                    src_subresource: src.image.subresource_layers(),
+                    src_offsets: src_bounds,
+                    dst_subresource: dst.image.subresource_layers(),
+                    dst_offsets: dst_bounds,
+                    ..Default::default()
+                }]
+                .into(),
+                filter: Filter::Nearest,
+                ..BlitImageInfo::images(src.image, dst.image)
+            })
+            .unwrap();
+    }
+}
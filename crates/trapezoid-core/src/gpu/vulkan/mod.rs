@@ -5,11 +5,15 @@ mod gpu_context;
 pub use gpu_backend::GpuBackend;
 
 pub use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage},
     command_buffer::{
         allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, BlitImageInfo,
-        CommandBufferUsage, PrimaryAutoCommandBuffer,
+        ClearColorImageInfo, CommandBufferUsage, CopyImageToBufferInfo, ImageBlit,
+        PrimaryAutoCommandBuffer,
     },
     device::{Device, Queue},
-    image::{sampler::Filter, Image},
-    sync::GpuFuture,
+    format::Format,
+    image::{sampler::Filter, Image, ImageCreateInfo, ImageType, ImageUsage},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    sync::{self, GpuFuture},
 };
@@ -8,6 +8,7 @@ pub mod gpu;
 mod mdec;
 mod memory;
 mod spu;
+mod state;
 mod timers;
 
 #[cfg(test)]
@@ -20,10 +21,17 @@ use std::{
 
 use cpu::RegisterType;
 use memory::{Bios, BusLine, CpuBus, Result};
+use spu::Resampler;
+use state::{Savestate, StateReader, StateWriter};
 
-pub use controller_mem_card::DigitalControllerKey;
+pub use cdrom::{DiscInfo, DiscRegion};
+pub use controller_mem_card::{
+    AnalogSticks, DigitalControllerKey, MouseState, RumbleState, MEMORY_CARD_SIZE,
+};
+pub use spu::SPU_SAMPLE_RATE;
 
-use crate::gpu::{Device, GpuFuture, Image, Queue};
+use crate::gpu::{Device, GpuFuture, Image, Queue, Renderer};
+pub use crate::gpu::{DisplayMode, ScalingFilter, SoftwareFrame};
 
 const MAX_CPU_CYCLES_TO_CLOCK: u32 = 2000;
 
@@ -32,6 +40,8 @@ pub enum PsxError {
     CouldNotLoadBios,
     CouldNotLoadDisk(String),
     DiskTypeNotSupported,
+    InvalidMemoryCard,
+    InvalidSaveState(String),
 }
 
 impl std::error::Error for PsxError {}
@@ -41,14 +51,53 @@ impl std::fmt::Display for PsxError {
             PsxError::CouldNotLoadBios => write!(f, "Could not load BIOS"),
             PsxError::CouldNotLoadDisk(s) => write!(f, "Could not load disk: {}", s),
             PsxError::DiskTypeNotSupported => write!(f, "Disk type not supported"),
+            PsxError::InvalidMemoryCard => {
+                write!(f, "Memory card image must be {} bytes", MEMORY_CARD_SIZE)
+            }
+            PsxError::InvalidSaveState(s) => write!(f, "Invalid save state: {}", s),
         }
     }
 }
 
+/// Reads the license region and serial of a disc image (`cue`, `pbp`, `bin`, `img`, `iso`),
+/// so a matching BIOS and per-game settings can be picked before booting it.
+/// Only the start of the data track is loaded.
+///
+/// Fields are `None` when the disc lacks them, like homebrew without a license.
+pub fn disc_info<P: AsRef<Path>>(disk_file: P) -> Result<DiscInfo, PsxError> {
+    cdrom::probe_disc(disk_file.as_ref())
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct PsxConfig {
     pub stdout_debug: bool,
     pub fast_boot: bool,
+    /// Renders 3D at this multiple of the native resolution (1 to 4).
+    /// VRAM transfers are still done at native resolution.
+    pub internal_resolution_scale: u32,
+    /// Plugs a multitap into port 1, for up to 4 players there and a 5th on port 2
+    pub multitap: bool,
+    /// CPU speed in percent of the real console, `100` is stock. Higher values
+    /// smooth out games that drop frames, but may break timing sensitive ones.
+    pub cpu_overclock: u32,
+    /// Renders 3D scenes wider for a 16:9 display, 2D elements stay stretched
+    pub widescreen_hack: bool,
+    /// Draws polygons at the sub-pixel positions the GTE computed instead of
+    /// whole pixels, which stops them from wobbling
+    pub precise_geometry: bool,
+    /// Interpolates texture coordinates with perspective, removing the texture
+    /// warping of large polygons. Uses the depths from the GTE as well
+    pub perspective_correct_textures: bool,
+    /// Filter for scaling the frame in [`Psx::blit_to_front`], the software
+    /// renderer leaves scaling to the frontend
+    pub scaling_filter: ScalingFilter,
+    /// Display gamma applied to the frame, by [`Psx::blit_to_front`] and in
+    /// [`Psx::software_frame`]. Above `1.0` brightens dark colours, below darkens
+    /// them, and `1.0` keeps the colours the console put out
+    pub gamma: f32,
+    /// Rate of the samples from [`Psx::take_audio_buffer`], the SPU output is
+    /// resampled unless this is [`SPU_SAMPLE_RATE`]
+    pub audio_sample_rate: u32,
 }
 
 pub struct Psx {
@@ -65,6 +114,10 @@ pub struct Psx {
     /// will crash the emulator, so we split clocking across multiple `clock` calls.
     excess_cpu_cycles: u32,
     cpu_frame_cycles: u32,
+    /// Leftover of scaling CPU cycles down for the overclock, in `1/cpu_overclock` cycles
+    overclock_remainder: u32,
+    /// `None` while audio is output at the SPU rate
+    audio_resampler: Option<Resampler>,
 }
 
 impl Psx {
@@ -75,11 +128,35 @@ impl Psx {
         config: PsxConfig,
         device: Arc<Device>,
         queue: Arc<Queue>,
+    ) -> Result<Self, PsxError> {
+        Self::with_renderer(
+            bios_file_path,
+            disk_file,
+            config,
+            Renderer::Vulkan { device, queue },
+        )
+    }
+
+    /// Creates a console that draws with the CPU instead of Vulkan, frames are
+    /// read back with [`Psx::software_frame`].
+    pub fn new_software<BiosPath: AsRef<Path>, DiskPath: AsRef<Path>>(
+        bios_file_path: BiosPath,
+        disk_file: Option<DiskPath>,
+        config: PsxConfig,
+    ) -> Result<Self, PsxError> {
+        Self::with_renderer(bios_file_path, disk_file, config, Renderer::Software)
+    }
+
+    fn with_renderer<BiosPath: AsRef<Path>, DiskPath: AsRef<Path>>(
+        bios_file_path: BiosPath,
+        disk_file: Option<DiskPath>,
+        config: PsxConfig,
+        renderer: Renderer,
     ) -> Result<Self, PsxError> {
         let bios = Bios::from_file(bios_file_path)?;
 
         // save the exe file if there is any
-        // The PSX itself is only responsible for loading normal cue files
+        // The PSX itself is only responsible for loading disc images
         let (exe_file, disk_file) = if let Some(disk_file) = disk_file {
             let path = disk_file.as_ref().to_owned();
             // if this is an exe file
@@ -92,7 +169,7 @@ impl Psx {
                 .as_str()
             {
                 "exe" => (Some(path), None),
-                "cue" => (None, Some(path)),
+                "cue" | "pbp" | "bin" | "img" | "iso" => (None, Some(path)),
                 _ => {
                     return Err(PsxError::DiskTypeNotSupported);
                 }
@@ -102,14 +179,21 @@ impl Psx {
             (None, None)
         };
 
+        let mut cpu = cpu::Cpu::new();
+        cpu.set_widescreen_hack(config.widescreen_hack);
+        cpu.set_precise_geometry(config.precise_geometry || config.perspective_correct_textures);
+
         Ok(Self {
-            cpu: cpu::Cpu::new(),
+            cpu,
             disk_available: disk_file.is_some(),
-            bus: CpuBus::new(bios, disk_file, config, device, queue)?,
+            bus: CpuBus::new(bios, disk_file, config, renderer)?,
             exe_file,
             config,
             excess_cpu_cycles: 0,
             cpu_frame_cycles: 0,
+            overclock_remainder: 0,
+            audio_resampler: (config.audio_sample_rate != SPU_SAMPLE_RATE)
+                .then(|| Resampler::new(config.audio_sample_rate as f64)),
         })
     }
 
@@ -118,6 +202,45 @@ impl Psx {
         self.bus.reset();
     }
 
+    /// Snapshots the whole console: CPU, RAM, GPU registers and VRAM, SPU, CDROM,
+    /// DMA, timers and the rest of the hardware registers.
+    ///
+    /// Memory card contents are not included, they are saved by the frontend as usual,
+    /// and neither are the held buttons. Best called between frames.
+    pub fn save_state(&mut self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        self.cpu.save(&mut w);
+        self.bus.save_state(&mut w);
+        self.excess_cpu_cycles.save(&mut w);
+        self.cpu_frame_cycles.save(&mut w);
+        self.overclock_remainder.save(&mut w);
+        w.finish()
+    }
+
+    /// Restores a state made by [`Psx::save_state`]. States only load with the
+    /// same disc inserted and the same multitap setting.
+    ///
+    /// On error the emulator is left as it was before the call.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), PsxError> {
+        let backup = self.save_state();
+        let result = self.load_state_unchecked(state);
+        if result.is_err() {
+            self.load_state_unchecked(&backup)
+                .expect("state saved by this instance should load");
+        }
+        result
+    }
+
+    fn load_state_unchecked(&mut self, state: &[u8]) -> Result<(), PsxError> {
+        let mut r = StateReader::new(state)?;
+        self.cpu.load(&mut r)?;
+        self.bus.load_state(&mut r)?;
+        self.excess_cpu_cycles.load(&mut r)?;
+        self.cpu_frame_cycles.load(&mut r)?;
+        self.overclock_remainder.load(&mut r)?;
+        r.finish()
+    }
+
     #[inline(always)]
     fn common_clock(&mut self) -> (u32, cpu::CpuState) {
         let mut cpu_state = cpu::CpuState::Normal;
@@ -130,6 +253,9 @@ impl Psx {
 
             (cpu_cycles, cpu_state) = self.cpu.clock(&mut self.bus, 56);
             shell_reached = self.cpu.is_shell_reached();
+            self.bus
+                .gpu_mut()
+                .add_precise_vertices(self.cpu.take_precise_vertices());
 
             // handle fast booting and hijacking the bios to load exe
             if shell_reached && (self.config.fast_boot || self.exe_file.is_some()) {
@@ -166,6 +292,7 @@ impl Psx {
             if cpu_cycles == 0 {
                 return (0, cpu_state);
             }
+            let cpu_cycles = self.scale_for_overclock(cpu_cycles);
             // the DMA is running of the CPU
             self.excess_cpu_cycles = cpu_cycles + self.bus.clock_dma();
             added_clock = self.excess_cpu_cycles;
@@ -178,6 +305,17 @@ impl Psx {
         (added_clock, cpu_state)
     }
 
+    /// An overclocked CPU gets the same work done in fewer cycles of the rest of the console
+    fn scale_for_overclock(&mut self, cpu_cycles: u32) -> u32 {
+        let overclock = self.config.cpu_overclock.max(1);
+        if overclock == 100 {
+            return cpu_cycles;
+        }
+        let total = cpu_cycles * 100 + self.overclock_remainder;
+        self.overclock_remainder = total % overclock;
+        total / overclock
+    }
+
     /// Return `true` if the frame is finished, `false` otherwise.
     /// Return the CPU state.
     pub fn clock_based_on_audio(&mut self, max_clocks: u32) -> (bool, cpu::CpuState) {
@@ -259,10 +397,92 @@ impl Psx {
         cpu::CpuState::Normal
     }
 
-    pub fn change_controller_key_state(&mut self, key: DigitalControllerKey, pressed: bool) {
+    /// Number of controllers that can be driven, 2 or 5 with the multitap.
+    /// Players 1-4 are on the multitap when it is connected, the last player is always on port 2.
+    pub fn controller_count(&self) -> usize {
+        self.bus.controller_mem_card().controller_count()
+    }
+
+    pub fn change_controller_key_state(
+        &mut self,
+        player: usize,
+        key: DigitalControllerKey,
+        pressed: bool,
+    ) {
+        self.bus
+            .controller_mem_card_mut()
+            .change_controller_key_state(player, key, pressed);
+    }
+
+    pub fn change_controller_analog_state(&mut self, player: usize, sticks: AnalogSticks) {
         self.bus
             .controller_mem_card_mut()
-            .change_controller_key_state(key, pressed);
+            .change_controller_analog_state(player, sticks);
+    }
+
+    /// Presses the DualShock `Analog` button, returns whether analog mode is now on.
+    /// Games can lock the mode, in which case this does nothing.
+    pub fn toggle_controller_analog_mode(&mut self, player: usize) -> bool {
+        self.bus
+            .controller_mem_card_mut()
+            .toggle_controller_analog_mode(player)
+    }
+
+    pub fn controller_analog_mode(&self, player: usize) -> bool {
+        self.bus
+            .controller_mem_card()
+            .controller_analog_mode(player)
+    }
+
+    /// Plugs a PlayStation Mouse in place of the player's controller, for games like
+    /// Lemmings or Discworld that support one
+    pub fn connect_mouse(&mut self, player: usize) {
+        self.bus
+            .controller_mem_card_mut()
+            .connect_controller_mouse(player);
+    }
+
+    /// Feeds mouse motion and buttons to a player connected with [`Self::connect_mouse`]
+    pub fn change_mouse_state(&mut self, player: usize, state: MouseState) {
+        self.bus
+            .controller_mem_card_mut()
+            .change_controller_mouse_state(player, state);
+    }
+
+    /// Motor speeds the game currently requests, to be forwarded to a physical pad
+    pub fn controller_rumble(&self, player: usize) -> RumbleState {
+        self.bus.controller_mem_card().controller_rumble(player)
+    }
+
+    /// Inserts a memory card image into `slot` (0 or 1)
+    pub fn load_memory_card(&mut self, slot: usize, data: &[u8]) -> Result<(), PsxError> {
+        if self
+            .bus
+            .controller_mem_card_mut()
+            .load_memory_card(slot, data)
+        {
+            Ok(())
+        } else {
+            Err(PsxError::InvalidMemoryCard)
+        }
+    }
+
+    /// Returns the contents of the memory card in `slot` if it was written
+    /// since the last call, so the frontend can persist it.
+    pub fn take_memory_card_changes(&mut self, slot: usize) -> Option<&[u8]> {
+        if self
+            .bus
+            .controller_mem_card_mut()
+            .take_memory_card_dirty(slot)
+        {
+            Some(self.bus.controller_mem_card().memory_card(slot))
+        } else {
+            None
+        }
+    }
+
+    pub fn memory_card(&self, slot: usize) -> &[u8] {
+        self.bus.controller_mem_card().memory_card(slot)
     }
 
     pub fn change_cdrom_shell_open_state(&mut self, open: bool) {
@@ -277,11 +497,72 @@ impl Psx {
     ) -> Box<dyn GpuFuture> {
         self.bus
             .gpu_mut()
-            .sync_gpu_and_blit_to_front(dest_image, full_vram, in_future)
+            .sync_gpu_and_blit_to_front(dest_image, full_vram, None, in_future)
+    }
+
+    /// Like [`Psx::blit_to_front`] but draws into the `extent` sized area at `offset`,
+    /// clearing the rest of `dest_image`, for letterboxing.
+    pub fn blit_to_front_area(
+        &mut self,
+        dest_image: Arc<Image>,
+        full_vram: bool,
+        offset: [u32; 2],
+        extent: [u32; 2],
+        in_future: Box<dyn GpuFuture>,
+    ) -> Box<dyn GpuFuture> {
+        self.bus.gpu_mut().sync_gpu_and_blit_to_front(
+            dest_image,
+            full_vram,
+            Some((offset, extent)),
+            in_future,
+        )
     }
 
+    /// The resolution and pixel shape of the last presented frame, pixels are
+    /// a third wider with the widescreen hack so the picture fills 16:9
+    pub fn display_mode(&self) -> DisplayMode {
+        let mut mode = self.bus.gpu().display_mode();
+        if self.config.widescreen_hack {
+            mode.pixel_aspect *= 4.0 / 3.0;
+        }
+        mode
+    }
+
+    /// The current display area drawn by the software renderer, `None` when
+    /// the console was created with Vulkan.
+    pub fn software_frame(&mut self, full_vram: bool) -> Option<&SoftwareFrame> {
+        self.bus.gpu_mut().software_frame(full_vram)
+    }
+
+    /// Reads the last displayed frame back from either renderer, for screenshots
+    /// and video capture. This waits for the GPU, so avoid calling it every frame
+    /// unless recording.
+    pub fn capture_frame(&mut self) -> Option<SoftwareFrame> {
+        self.bus.gpu_mut().capture_frame()
+    }
+
+    /// Interleaved stereo samples produced since the last call, at the
+    /// [`PsxConfig::audio_sample_rate`]
     pub fn take_audio_buffer(&mut self) -> Vec<f32> {
-        self.bus.spu_mut().take_audio_buffer()
+        let samples = self.bus.spu_mut().take_audio_buffer();
+        match &mut self.audio_resampler {
+            Some(resampler) => {
+                let mut resampled = Vec::new();
+                resampler.process(&samples, &mut resampled);
+                resampled
+            }
+            None => samples,
+        }
+    }
+
+    /// Changes the rate of [`Psx::take_audio_buffer`]. The rate can be fractional,
+    /// so frontends can follow the actual speed of their audio device and keep
+    /// its queue at a steady length.
+    pub fn set_audio_sample_rate(&mut self, rate: f64) {
+        match &mut self.audio_resampler {
+            Some(resampler) => resampler.set_output_rate(rate),
+            None => self.audio_resampler = Some(Resampler::new(rate)),
+        }
     }
 
     pub fn cpu(&mut self) -> &mut cpu::Cpu {
@@ -309,6 +590,28 @@ impl Psx {
         self.bus.read_u8(addr)
     }
 
+    pub fn bus_write_u32(&mut self, addr: u32, data: u32) -> Result<()> {
+        // make sure its aligned
+        if !addr.is_multiple_of(4) {
+            return Err("Unaligned memory access".to_string());
+        }
+
+        self.bus.write_u32(addr, data)
+    }
+
+    pub fn bus_write_u16(&mut self, addr: u32, data: u16) -> Result<()> {
+        // make sure its aligned
+        if !addr.is_multiple_of(2) {
+            return Err("Unaligned memory access".to_string());
+        }
+
+        self.bus.write_u16(addr, data)
+    }
+
+    pub fn bus_write_u8(&mut self, addr: u32, data: u8) -> Result<()> {
+        self.bus.write_u8(addr, data)
+    }
+
     pub fn print_spu_state(&self) {
         self.bus.spu().print_state();
     }
@@ -1,6 +1,10 @@
 use std::collections::VecDeque;
 
 use crate::memory::{BusLine, Result};
+use crate::state::{
+    impl_savestate, impl_savestate_bits, impl_savestate_enum, invalid, Savestate, StateReader,
+    StateResult, StateWriter,
+};
 use bitflags::bitflags;
 use byteorder::{ByteOrder, LittleEndian};
 
@@ -49,6 +53,8 @@ bitflags! {
     }
 }
 
+impl_savestate_bits!(MdecStatus);
+
 impl MdecStatus {
     fn output_depth(&self) -> u8 {
         ((self.bits() & Self::DATA_OUTPUT_DEPTH.bits()) >> 25) as u8
@@ -74,6 +80,16 @@ struct DecodeMacroBlockCommandState {
     color_decoding_state: u32,
 }
 
+impl_savestate!(DecodeMacroBlockCommandState {
+    rl_out,
+    q_scale,
+    k,
+    first,
+    cr_blk,
+    cb_blk,
+    color_decoding_state,
+});
+
 impl Default for DecodeMacroBlockCommandState {
     fn default() -> Self {
         Self {
@@ -104,6 +120,47 @@ enum MdecCommand {
     SetScaleTable,
 }
 
+impl MdecCommand {
+    fn save_option(command: &Option<Self>, w: &mut StateWriter) {
+        match command {
+            None => 0u8.save(w),
+            Some(MdecCommand::DecodeMacroBlock(state)) => {
+                1u8.save(w);
+                state.save(w);
+            }
+            Some(MdecCommand::SetQuantTable {
+                color_and_luminance,
+            }) => {
+                2u8.save(w);
+                color_and_luminance.save(w);
+            }
+            Some(MdecCommand::SetScaleTable) => 3u8.save(w),
+        }
+    }
+
+    fn load_option(r: &mut StateReader) -> StateResult<Option<Self>> {
+        let mut kind = 0u8;
+        kind.load(r)?;
+        Ok(match kind {
+            0 => None,
+            1 => {
+                let mut state = Box::<DecodeMacroBlockCommandState>::default();
+                state.load(r)?;
+                Some(MdecCommand::DecodeMacroBlock(state))
+            }
+            2 => {
+                let mut color_and_luminance = false;
+                color_and_luminance.load(r)?;
+                Some(MdecCommand::SetQuantTable {
+                    color_and_luminance,
+                })
+            }
+            3 => Some(MdecCommand::SetScaleTable),
+            _ => return Err(invalid(format!("invalid mdec command {}", kind))),
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum BlockType {
     Y1 = 0,
@@ -114,6 +171,15 @@ pub enum BlockType {
     Cb,
 }
 
+impl_savestate_enum!(BlockType {
+    Y1,
+    Y2,
+    Y3,
+    Y4,
+    YCr,
+    Cb,
+});
+
 #[derive(Clone, Copy)]
 pub struct FifoBlockState {
     pub block_type: BlockType,
@@ -121,12 +187,34 @@ pub struct FifoBlockState {
     pub is_24bit: bool,
 }
 
+impl_savestate!(FifoBlockState {
+    block_type,
+    index,
+    is_24bit,
+});
+
 struct FifoBlock {
     data: [u32; 48],
     size: usize,
     state: FifoBlockState,
 }
 
+impl Default for FifoBlock {
+    fn default() -> Self {
+        Self {
+            data: [0; 48],
+            size: 0,
+            state: FifoBlockState {
+                block_type: BlockType::Y1,
+                index: 0,
+                is_24bit: false,
+            },
+        }
+    }
+}
+
+impl_savestate!(FifoBlock { data, size, state });
+
 pub struct Mdec {
     status: MdecStatus,
     remaining_params: u16,
@@ -140,6 +228,30 @@ pub struct Mdec {
     scaletable: [u16; 64],
 }
 
+impl Savestate for Mdec {
+    fn save(&self, w: &mut StateWriter) {
+        self.status.save(w);
+        self.remaining_params.save(w);
+        MdecCommand::save_option(&self.current_cmd, w);
+        self.params_ptr.save(w);
+        self.out_fifo.save(w);
+        self.iq_y.save(w);
+        self.iq_uv.save(w);
+        self.scaletable.save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        self.status.load(r)?;
+        self.remaining_params.load(r)?;
+        self.current_cmd = MdecCommand::load_option(r)?;
+        self.params_ptr.load(r)?;
+        self.out_fifo.load(r)?;
+        self.iq_y.load(r)?;
+        self.iq_uv.load(r)?;
+        self.scaletable.load(r)
+    }
+}
+
 impl Default for Mdec {
     fn default() -> Self {
         Self {
@@ -7,17 +7,16 @@ mod ram;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
-use std::sync::Arc;
 
-use crate::gpu::{Device, Queue};
 use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
 
 use crate::cdrom::Cdrom;
 use crate::controller_mem_card::ControllerAndMemoryCard;
 use crate::cpu::CpuBusProvider;
-use crate::gpu::Gpu;
+use crate::gpu::{Gpu, Renderer};
 use crate::mdec::Mdec;
 use crate::spu::Spu;
+use crate::state::{Savestate, StateReader, StateResult, StateWriter};
 use crate::timers::Timers;
 use crate::{PsxConfig, PsxError};
 
@@ -220,8 +219,7 @@ impl CpuBus {
         bios: Bios,
         disk_file: Option<DiskPath>,
         config: PsxConfig,
-        device: Arc<Device>,
-        queue: Arc<Queue>,
+        renderer: Renderer,
     ) -> Result<Self, PsxError> {
         let mut s = Self {
             bios,
@@ -229,7 +227,7 @@ impl CpuBus {
             mem_ctrl_2: MemoryControl2::default(),
             cache_control: CacheControl::default(),
             interrupts: Interrupts::default(),
-            controller_mem_card: ControllerAndMemoryCard::default(),
+            controller_mem_card: ControllerAndMemoryCard::new(config.multitap),
 
             expansion_region_1: ExpansionRegion1::default(),
             expansion_region_2: ExpansionRegion2::new(config),
@@ -239,7 +237,7 @@ impl CpuBus {
 
             dma_bus: DmaBus {
                 cdrom: Cdrom::default(),
-                gpu: Gpu::new(device, queue),
+                gpu: Gpu::new(renderer, config.internal_resolution_scale),
                 main_ram: MainRam::default(),
                 mdec: Mdec::default(),
                 spu: Spu::default(),
@@ -248,6 +246,11 @@ impl CpuBus {
             scratchpad: Scratchpad::default(),
             config,
         };
+        s.dma_bus
+            .gpu
+            .set_precise_geometry(config.precise_geometry, config.perspective_correct_textures);
+        s.dma_bus.gpu.set_scaling_filter(config.scaling_filter);
+        s.dma_bus.gpu.set_gamma(config.gamma);
 
         // TODO: handle errors in loading
         if let Some(disk_file) = disk_file {
@@ -261,7 +264,9 @@ impl CpuBus {
                 .to_ascii_lowercase()
                 .as_str()
             {
-                "cue" => s.dma_bus.cdrom.set_cue_file(disk_file)?,
+                "cue" | "pbp" | "bin" | "img" | "iso" => {
+                    s.dma_bus.cdrom.set_disc_file(disk_file)?
+                }
                 _ => {
                     return Err(PsxError::DiskTypeNotSupported);
                 }
@@ -276,7 +281,7 @@ impl CpuBus {
         self.mem_ctrl_2 = MemoryControl2::default();
         self.cache_control = CacheControl::default();
         self.interrupts = Interrupts::default();
-        self.controller_mem_card = ControllerAndMemoryCard::default();
+        self.controller_mem_card.reset();
 
         self.expansion_region_1 = ExpansionRegion1::default();
         self.expansion_region_2 = ExpansionRegion2::new(self.config);
@@ -301,6 +306,10 @@ impl CpuBus {
         &mut self.dma_bus.gpu
     }
 
+    pub fn controller_mem_card(&self) -> &ControllerAndMemoryCard {
+        &self.controller_mem_card
+    }
+
     pub fn controller_mem_card_mut(&mut self) -> &mut ControllerAndMemoryCard {
         &mut self.controller_mem_card
     }
@@ -316,6 +325,43 @@ impl CpuBus {
     pub fn cdrom_mut(&mut self) -> &mut Cdrom {
         &mut self.dma_bus.cdrom
     }
+
+    /// The BIOS and config are not saved, they come from the running instance
+    pub fn save_state(&mut self, w: &mut StateWriter) {
+        self.mem_ctrl_1.save(w);
+        self.mem_ctrl_2.save(w);
+        self.cache_control.save(w);
+        self.interrupts.save(w);
+        self.controller_mem_card.save(w);
+        self.expansion_region_1.save(w);
+        self.expansion_region_2.save(w);
+        self.timers.save(w);
+        self.dma.save(w);
+        self.dma_bus.main_ram.save(w);
+        self.dma_bus.cdrom.save(w);
+        self.dma_bus.gpu.save_state(w);
+        self.dma_bus.mdec.save(w);
+        self.dma_bus.spu.save(w);
+        self.scratchpad.save(w);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> StateResult {
+        self.mem_ctrl_1.load(r)?;
+        self.mem_ctrl_2.load(r)?;
+        self.cache_control.load(r)?;
+        self.interrupts.load(r)?;
+        self.controller_mem_card.load(r)?;
+        self.expansion_region_1.load(r)?;
+        self.expansion_region_2.load(r)?;
+        self.timers.load(r)?;
+        self.dma.load(r)?;
+        self.dma_bus.main_ram.load(r)?;
+        self.dma_bus.cdrom.load(r)?;
+        self.dma_bus.gpu.load_state(r)?;
+        self.dma_bus.mdec.load(r)?;
+        self.dma_bus.spu.load(r)?;
+        self.scratchpad.load(r)
+    }
 }
 
 impl CpuBus {
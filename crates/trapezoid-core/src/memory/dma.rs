@@ -1,5 +1,6 @@
 use crate::mdec;
 use crate::memory::Result;
+use crate::state::{impl_savestate, impl_savestate_bits};
 
 use super::interrupts::InterruptRequester;
 use super::BusLine;
@@ -66,6 +67,8 @@ bitflags::bitflags! {
     }
 }
 
+impl_savestate_bits!(ChannelControl, DmaInterruptRegister);
+
 impl DmaInterruptRegister {
     #[inline]
     fn master_flag(&self) -> bool {
@@ -100,6 +103,12 @@ struct DmaChannel {
     channel_control: ChannelControl,
 }
 
+impl_savestate!(DmaChannel {
+    base_address,
+    block_control,
+    channel_control,
+});
+
 impl DmaChannel {
     fn read(&mut self, addr: u32) -> u32 {
         match addr {
@@ -159,6 +168,12 @@ pub struct Dma {
     channels: [DmaChannel; 7],
 }
 
+impl_savestate!(Dma {
+    control,
+    interrupt,
+    channels,
+});
+
 impl Default for Dma {
     fn default() -> Self {
         Self {
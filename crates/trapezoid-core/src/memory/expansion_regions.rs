@@ -1,4 +1,8 @@
-use crate::{memory::Result, PsxConfig};
+use crate::{
+    memory::Result,
+    state::{impl_savestate, Savestate, StateReader, StateResult, StateWriter},
+    PsxConfig,
+};
 
 use super::BusLine;
 
@@ -21,6 +25,16 @@ impl Default for ExpansionRegion1 {
     }
 }
 
+impl Savestate for ExpansionRegion1 {
+    fn save(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.data[..]);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        r.read_into(&mut self.data[..])
+    }
+}
+
 impl BusLine for ExpansionRegion1 {
     fn read_u8(&mut self, addr: u32) -> Result<u8> {
         Ok(self.data[addr as usize])
@@ -134,6 +148,9 @@ pub struct ExpansionRegion2 {
     tty_duart: DuartTTY,
 }
 
+// the TTY only buffers debug output
+impl_savestate!(ExpansionRegion2 { data });
+
 impl ExpansionRegion2 {
     pub fn new(config: PsxConfig) -> Self {
         Self {
@@ -1,4 +1,5 @@
 use crate::memory::Result;
+use crate::state::{impl_savestate, impl_savestate_bits};
 
 use super::BusLine;
 
@@ -36,6 +37,9 @@ pub struct Interrupts {
     mask: InterruptFlags,
 }
 
+impl_savestate_bits!(InterruptFlags);
+impl_savestate!(Interrupts { stat, mask });
+
 impl Interrupts {
     pub fn pending_interrupts(&self) -> bool {
         !(self.stat & self.mask).is_empty()
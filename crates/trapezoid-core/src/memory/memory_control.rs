@@ -1,4 +1,5 @@
 use crate::memory::Result;
+use crate::state::impl_savestate;
 
 use super::BusLine;
 
@@ -18,6 +19,8 @@ pub struct MemoryControl1 {
     //common_delay: u32,
 }
 
+impl_savestate!(MemoryControl1 { data });
+
 impl BusLine for MemoryControl1 {
     fn read_u32(&mut self, addr: u32) -> Result<u32> {
         let addr = addr & 0xFF;
@@ -41,6 +44,8 @@ impl BusLine for MemoryControl1 {
 #[derive(Default)]
 pub struct MemoryControl2(u32);
 
+impl_savestate!(MemoryControl2 { 0 });
+
 impl BusLine for MemoryControl2 {
     fn read_u32(&mut self, _addr: u32) -> Result<u32> {
         Ok(self.0)
@@ -61,6 +66,8 @@ impl BusLine for MemoryControl2 {
 #[derive(Default)]
 pub struct CacheControl(u32);
 
+impl_savestate!(CacheControl { 0 });
+
 impl BusLine for CacheControl {
     fn read_u32(&mut self, _addr: u32) -> Result<u32> {
         Ok(self.0)
@@ -1,6 +1,7 @@
 use byteorder::{ByteOrder, LittleEndian};
 
 use crate::memory::Result;
+use crate::state::{Savestate, StateReader, StateResult, StateWriter};
 
 use super::BusLine;
 
@@ -16,6 +17,16 @@ impl Default for MainRam {
     }
 }
 
+impl Savestate for MainRam {
+    fn save(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.data);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        r.read_into(&mut self.data)
+    }
+}
+
 impl MainRam {
     pub fn put_at_address(&mut self, block_data: &[u8], addr: u32) {
         let addr = (addr as usize) & 0x1FFFFF;
@@ -74,6 +85,16 @@ impl Default for Scratchpad {
     }
 }
 
+impl Savestate for Scratchpad {
+    fn save(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.data);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        r.read_into(&mut self.data)
+    }
+}
+
 impl BusLine for Scratchpad {
     fn read_u32(&mut self, addr: u32) -> Result<u32> {
         let index = addr as usize;
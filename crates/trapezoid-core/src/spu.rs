@@ -1,3 +1,5 @@
+mod resampler;
+
 use std::{
     cell::Cell,
     collections::VecDeque,
@@ -5,8 +7,13 @@ use std::{
 };
 
 use crate::memory::{interrupts::InterruptRequester, BusLine, Result};
+use crate::state::{impl_savestate, impl_savestate_bits, impl_savestate_enum};
+
+pub(crate) use resampler::Resampler;
 
 const CPU_CLOCKS_PER_SPU: u32 = 0x300;
+/// Rate of the SPU output, `CPU_CLOCK / 0x300`
+pub const SPU_SAMPLE_RATE: u32 = 44100;
 
 enum RamTransferMode {
     Stop,
@@ -32,6 +39,8 @@ bitflags::bitflags! {
     }
 }
 
+impl_savestate_bits!(SpuControl, SpuStat, ADSRConfig);
+
 impl SpuControl {
     fn ram_transfer_mode(&self) -> RamTransferMode {
         match self.bits() & Self::SOUND_RAM_TRANSFER_MODE.bits() {
@@ -69,6 +78,8 @@ struct AdpcmDecoder {
     older: i32,
 }
 
+impl_savestate!(AdpcmDecoder { old, older });
+
 impl AdpcmDecoder {
     /// Decode ADPCM block
     ///
@@ -123,6 +134,8 @@ struct VoicesFlag {
     bits: u32,
 }
 
+impl_savestate!(VoicesFlag { bits });
+
 impl VoicesFlag {
     fn get(&self, index: usize) -> bool {
         self.bits & (1 << index) != 0
@@ -221,6 +234,14 @@ enum ADSRState {
     Stopped,
 }
 
+impl_savestate_enum!(ADSRState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Stopped,
+});
+
 #[derive(Default, Clone, Copy)]
 struct Voice {
     volume_left: u16,
@@ -270,6 +291,27 @@ struct Voice {
     is_off: bool,
 }
 
+impl_savestate!(Voice {
+    volume_left,
+    volume_right,
+    current_vol_left,
+    current_vol_right,
+    adpcm_sample_rate,
+    i_adpcm_pitch_counter,
+    adpcm_start_address,
+    adpcm_repeat_address,
+    i_adpcm_current_address,
+    i_adpcm_decoder,
+    adsr_config,
+    i_adsr_state,
+    adsr_current_vol,
+    i_cached_28_samples_block,
+    i_cached_sample_index,
+    i_adsr_cycle_counter,
+    is_on,
+    is_off,
+});
+
 impl Voice {
     fn key_on(&mut self) {
         self.i_adpcm_current_address = self.adpcm_start_address as usize * 4;
@@ -564,6 +606,16 @@ struct SpuRam {
     voice_3_mono_capture_index: usize,
 }
 
+impl_savestate!(SpuRam {
+    data,
+    irq_address,
+    irq_flag,
+    cd_left_capture_index,
+    cd_right_capture_index,
+    voice_1_mono_capture_index,
+    voice_3_mono_capture_index,
+});
+
 impl SpuRam {
     pub fn reset_irq(&self) {
         self.irq_flag.set(false);
@@ -705,6 +757,40 @@ pub struct Spu {
     in_dma_transfer: bool,
 }
 
+// the output buffer is left for the frontend to drain
+impl_savestate!(Spu {
+    main_vol_left,
+    main_vol_right,
+    reverb_out_vol_left,
+    reverb_out_vol_right,
+    reverb_work_base,
+    cd_vol_left,
+    cd_vol_right,
+    external_vol_left,
+    external_vol_right,
+    current_main_vol_left,
+    current_main_vol_right,
+    ram_transfer_control,
+    ram_transfer_address,
+    i_ram_transfer_address,
+    write_data_fifo,
+    control,
+    stat,
+    key_on_flag,
+    key_off_flag,
+    pitch_mod_channel_flag,
+    noise_channel_mode_flag,
+    reverb_channel_mode_flag,
+    endx_flag,
+    voices,
+    reverb_config,
+    spu_ram,
+    cdrom_audio_buffer_left,
+    cdrom_audio_buffer_right,
+    cpu_clock_timer,
+    in_dma_transfer,
+});
+
 impl Spu {
     pub fn clock(&mut self, interrupt_requester: &mut impl InterruptRequester, cycles: u32) {
         self.cpu_clock_timer += cycles;
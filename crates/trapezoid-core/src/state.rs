@@ -0,0 +1,354 @@
+//! Save state serialization.
+//!
+//! Every component writes its fields in a fixed order and reads them back in the
+//! same order, there are no field names or per-component versions. Any change to
+//! what is saved must bump [`STATE_VERSION`] so old states are rejected instead of
+//! being misread.
+
+use std::{cell::Cell, collections::VecDeque};
+
+use crate::PsxError;
+
+const STATE_MAGIC: &[u8; 8] = b"TRPZSTAT";
+pub(crate) const STATE_VERSION: u32 = 1;
+
+pub(crate) type StateResult<T = ()> = std::result::Result<T, PsxError>;
+
+pub(crate) struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut data = STATE_MAGIC.to_vec();
+        data.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        Self { data }
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> StateResult<Self> {
+        let mut reader = Self { data };
+        if reader.read_bytes(STATE_MAGIC.len())? != STATE_MAGIC {
+            return Err(invalid("not a save state"));
+        }
+        let mut version = 0u32;
+        version.load(&mut reader)?;
+        if version != STATE_VERSION {
+            return Err(invalid(format!(
+                "save state version {} is not supported, expected {}",
+                version, STATE_VERSION
+            )));
+        }
+        Ok(reader)
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> StateResult<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(invalid("save state is truncated"));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// Fills `out` with the next bytes, for memories saved with [`StateWriter::write_bytes`]
+    pub fn read_into(&mut self, out: &mut [u8]) -> StateResult {
+        out.copy_from_slice(self.read_bytes(out.len())?);
+        Ok(())
+    }
+
+    /// Fails if anything is left over, which means the state was written by
+    /// a different layout
+    pub fn finish(self) -> StateResult {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid("save state has trailing data"))
+        }
+    }
+}
+
+pub(crate) fn invalid(msg: impl Into<String>) -> PsxError {
+    PsxError::InvalidSaveState(msg.into())
+}
+
+pub(crate) trait Savestate {
+    fn save(&self, w: &mut StateWriter);
+    fn load(&mut self, r: &mut StateReader) -> StateResult;
+}
+
+/// Implements [`Savestate`] for a struct by saving the listed fields in order,
+/// fields left out keep their current value on load
+macro_rules! impl_savestate {
+    ($ty:ty { $($field:tt),* $(,)? }) => {
+        impl $crate::state::Savestate for $ty {
+            fn save(&self, w: &mut $crate::state::StateWriter) {
+                $( $crate::state::Savestate::save(&self.$field, w); )*
+            }
+
+            fn load(&mut self, r: &mut $crate::state::StateReader) -> $crate::state::StateResult {
+                $( $crate::state::Savestate::load(&mut self.$field, r)?; )*
+                Ok(())
+            }
+        }
+    };
+}
+pub(crate) use impl_savestate;
+
+/// Implements [`Savestate`] for `bitflags` types through their raw bits
+macro_rules! impl_savestate_bits {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl $crate::state::Savestate for $ty {
+                fn save(&self, w: &mut $crate::state::StateWriter) {
+                    $crate::state::Savestate::save(&self.bits(), w);
+                }
+
+                fn load(&mut self, r: &mut $crate::state::StateReader) -> $crate::state::StateResult {
+                    let mut bits = self.bits();
+                    $crate::state::Savestate::load(&mut bits, r)?;
+                    *self = Self::from_bits_retain(bits);
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+pub(crate) use impl_savestate_bits;
+
+/// Implements [`Savestate`] for fieldless enums by their position in the list
+macro_rules! impl_savestate_enum {
+    ($ty:ty { $($variant:ident),* $(,)? }) => {
+        impl $crate::state::Savestate for $ty {
+            fn save(&self, w: &mut $crate::state::StateWriter) {
+                let variants = [$(<$ty>::$variant),*];
+                let index = variants
+                    .iter()
+                    .position(|variant| ::std::mem::discriminant(variant) == ::std::mem::discriminant(self))
+                    .unwrap() as u8;
+                $crate::state::Savestate::save(&index, w);
+            }
+
+            fn load(&mut self, r: &mut $crate::state::StateReader) -> $crate::state::StateResult {
+                let mut index = 0u8;
+                $crate::state::Savestate::load(&mut index, r)?;
+                let variants = [$(<$ty>::$variant),*];
+                *self = variants.into_iter().nth(index as usize).ok_or_else(|| {
+                    $crate::state::invalid(format!(
+                        "invalid {} value {}",
+                        stringify!($ty),
+                        index
+                    ))
+                })?;
+                Ok(())
+            }
+        }
+    };
+}
+pub(crate) use impl_savestate_enum;
+
+macro_rules! impl_savestate_primitive {
+    ($($ty:ty),*) => {
+        $(
+            impl Savestate for $ty {
+                fn save(&self, w: &mut StateWriter) {
+                    w.write_bytes(&self.to_le_bytes());
+                }
+
+                fn load(&mut self, r: &mut StateReader) -> StateResult {
+                    let bytes = r.read_bytes(std::mem::size_of::<$ty>())?;
+                    *self = <$ty>::from_le_bytes(bytes.try_into().unwrap());
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_savestate_primitive!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl Savestate for bool {
+    fn save(&self, w: &mut StateWriter) {
+        (*self as u8).save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        let mut value = 0u8;
+        value.load(r)?;
+        *self = value != 0;
+        Ok(())
+    }
+}
+
+// saved as `u64` so states don't depend on the pointer width
+impl Savestate for usize {
+    fn save(&self, w: &mut StateWriter) {
+        (*self as u64).save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        let mut value = 0u64;
+        value.load(r)?;
+        *self = usize::try_from(value).map_err(|_| invalid("index out of range"))?;
+        Ok(())
+    }
+}
+
+impl<T: Savestate, const N: usize> Savestate for [T; N] {
+    fn save(&self, w: &mut StateWriter) {
+        self.iter().for_each(|item| item.save(w));
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        self.iter_mut().try_for_each(|item| item.load(r))
+    }
+}
+
+impl<T: Savestate + ?Sized> Savestate for Box<T> {
+    fn save(&self, w: &mut StateWriter) {
+        (**self).save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        (**self).load(r)
+    }
+}
+
+impl<T: Savestate + Default> Savestate for Vec<T> {
+    fn save(&self, w: &mut StateWriter) {
+        self.len().save(w);
+        self.iter().for_each(|item| item.save(w));
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        let mut len = 0usize;
+        len.load(r)?;
+        self.clear();
+        for _ in 0..len {
+            let mut item = T::default();
+            item.load(r)?;
+            self.push(item);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Savestate + Default> Savestate for VecDeque<T> {
+    fn save(&self, w: &mut StateWriter) {
+        self.len().save(w);
+        self.iter().for_each(|item| item.save(w));
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        let mut len = 0usize;
+        len.load(r)?;
+        self.clear();
+        for _ in 0..len {
+            let mut item = T::default();
+            item.load(r)?;
+            self.push_back(item);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Savestate + Default> Savestate for Option<T> {
+    fn save(&self, w: &mut StateWriter) {
+        self.is_some().save(w);
+        if let Some(value) = self {
+            value.save(w);
+        }
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        let mut is_some = false;
+        is_some.load(r)?;
+        *self = if is_some {
+            let mut value = T::default();
+            value.load(r)?;
+            Some(value)
+        } else {
+            None
+        };
+        Ok(())
+    }
+}
+
+impl<T: Savestate + Copy> Savestate for Cell<T> {
+    fn save(&self, w: &mut StateWriter) {
+        self.get().save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        self.get_mut().load(r)
+    }
+}
+
+impl<A: Savestate, B: Savestate> Savestate for (A, B) {
+    fn save(&self, w: &mut StateWriter) {
+        self.0.save(w);
+        self.1.save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> StateResult {
+        self.0.load(r)?;
+        self.1.load(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut w = StateWriter::new();
+        let values = (0x1234u16, -5i32);
+        values.save(&mut w);
+        Some([1u8, 2, 3]).save(&mut w);
+        VecDeque::from([true, false]).save(&mut w);
+        let data = w.finish();
+
+        let mut r = StateReader::new(&data).unwrap();
+        let mut loaded = (0u16, 0i32);
+        let mut array: Option<[u8; 3]> = None;
+        let mut fifo = VecDeque::<bool>::new();
+        loaded.load(&mut r).unwrap();
+        array.load(&mut r).unwrap();
+        fifo.load(&mut r).unwrap();
+        r.finish().unwrap();
+
+        assert_eq!(loaded, values);
+        assert_eq!(array, Some([1, 2, 3]));
+        assert_eq!(fifo, [true, false]);
+    }
+
+    #[test]
+    fn rejects_bad_states() {
+        assert!(StateReader::new(b"not a state").is_err());
+
+        let mut data = StateWriter::new().finish();
+        data[STATE_MAGIC.len()] = 0xFF;
+        assert!(StateReader::new(&data).is_err());
+
+        let mut w = StateWriter::new();
+        1u8.save(&mut w);
+        let data = w.finish();
+        let mut r = StateReader::new(&data).unwrap();
+        let mut value = 0u32;
+        assert!(value.load(&mut r).is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use crate::memory::{interrupts::InterruptRequester, BusLine, Result};
+use crate::state::{impl_savestate, impl_savestate_bits};
 use bitflags::bitflags;
 
 bitflags! {
@@ -20,6 +21,8 @@ bitflags! {
     }
 }
 
+impl_savestate_bits!(CounterMode);
+
 impl CounterMode {
     fn sync_enable(&self) -> bool {
         self.intersects(Self::SYNC_ENABLE)
@@ -88,6 +91,15 @@ struct TimerBase {
     should_request_interrupt: bool,
 }
 
+impl_savestate!(TimerBase {
+    mode,
+    counter,
+    target,
+    paused,
+    one_shot_suppress_irqs,
+    should_request_interrupt,
+});
+
 impl TimerBase {
     fn read(&mut self, index: u32) -> u16 {
         match index {
@@ -215,6 +227,8 @@ struct Timer0 {
     base: TimerBase,
 }
 
+impl_savestate!(Timer0 { base });
+
 impl Timer0 {
     fn mode(&mut self) -> &CounterMode {
         &self.base.mode
@@ -257,6 +271,8 @@ struct Timer1 {
     base: TimerBase,
 }
 
+impl_savestate!(Timer1 { base });
+
 impl Timer1 {
     fn mode(&mut self) -> &CounterMode {
         &self.base.mode
@@ -300,6 +316,11 @@ struct Timer2 {
     divider_counter: u32,
 }
 
+impl_savestate!(Timer2 {
+    base,
+    divider_counter,
+});
+
 impl Timer2 {
     fn mode(&mut self) -> &CounterMode {
         &self.base.mode
@@ -349,6 +370,12 @@ pub struct Timers {
     timer2: Timer2,
 }
 
+impl_savestate!(Timers {
+    timer0,
+    timer1,
+    timer2,
+});
+
 impl Timers {
     pub fn clock_from_system(&mut self, cycles: u32) {
         // 0 or 2
@@ -0,0 +1,71 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use anyhow::{Context, Result};
+
+/// Plays a Game Boy game for an external agent over TCP, in lock step with the emulator.
+/// After every frame the agent gets the frame number (u64, little endian), the frame as
+/// 160x144 RGB bytes row by row, and the byte at each watched address in the order they
+/// were given. It answers with one byte of buttons, laid out like an `.rlm` movie frame,
+/// which are held for the next frame. Until it answers the game waits.
+pub struct AgentBridge {
+    stream: TcpStream,
+    watches: Vec<u16>,
+    frame: u64,
+    message: Vec<u8>,
+}
+
+impl AgentBridge {
+    /// Listens on `127.0.0.1:port` and waits for the agent to connect
+    pub fn listen(port: u16, watches: Vec<u16>) -> Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .with_context(|| format!("failed to listen on port {port}"))?;
+        log::info!("Waiting for an agent on 127.0.0.1:{port}");
+        let (stream, peer) = listener
+            .accept()
+            .context("failed to accept the agent's connection")?;
+        stream.set_nodelay(true)?;
+        log::info!("Agent {peer} connected");
+        Ok(Self {
+            stream,
+            watches,
+            frame: 0,
+            message: Vec::new(),
+        })
+    }
+
+    /// Sends the last frame and waits for the buttons of the next one, `None` once the
+    /// agent hung up
+    pub fn exchange(&mut self, pixels: &[u32], peek: impl Fn(u16) -> u8) -> Result<Option<u8>> {
+        self.message.clear();
+        self.message.extend_from_slice(&self.frame.to_le_bytes());
+        for &pixel in pixels {
+            self.message
+                .extend_from_slice(&[(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]);
+        }
+        self.message
+            .extend(self.watches.iter().map(|&address| peek(address)));
+        self.frame += 1;
+
+        let mut buttons = [0];
+        let result = self
+            .stream
+            .write_all(&self.message)
+            .and_then(|()| self.stream.read_exact(&mut buttons));
+        match result {
+            Ok(()) => Ok(Some(buttons[0])),
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    ErrorKind::UnexpectedEof
+                        | ErrorKind::BrokenPipe
+                        | ErrorKind::ConnectionReset
+                        | ErrorKind::ConnectionAborted
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(err).context("failed to talk to the agent"),
+        }
+    }
+}
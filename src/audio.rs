@@ -1,36 +1,307 @@
-use anyhow::{Result, anyhow};
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow, bail};
 use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+
+use crate::config::{AudioConfig, UnfocusedAudio};
+
+/// The device always runs at the rate most hardware mixes at, cores are resampled to it
+const OUTPUT_SAMPLE_RATE: i32 = 48_000;
+const CHANNELS: usize = 2;
+/// Bytes per stereo f32 frame
+const FRAME_BYTES: u32 = CHANNELS as u32 * 4;
+/// Queued audio past this is dropped unless `[audio] max_latency_ms` says otherwise
+const DEFAULT_MAX_LATENCY: Duration = Duration::from_secs(1);
+/// Percent a volume hotkey moves the master volume by
+const VOLUME_STEP: u8 = 10;
+/// How long the volume stays on screen after a hotkey
+const VOLUME_SHOWN: Duration = Duration::from_secs(2);
+
+/// Converts interleaved audio from a core's rate to the device rate by interpolating
+/// between neighbouring frames. The position carries over between calls so the borders
+/// of a core's audio chunks do not click.
+pub struct Resampler {
+    channels: usize,
+    /// Source frames advanced per output frame
+    step: f64,
+    /// Where the next output frame lies, 0 being `previous` and 1 the first new frame
+    position: f64,
+    /// Last frame of the previous chunk
+    previous: Vec<f32>,
+}
+
+impl Resampler {
+    pub fn new(source_rate: f64, output_rate: f64, channels: usize) -> Self {
+        Self {
+            channels,
+            step: source_rate / output_rate,
+            position: 0.0,
+            previous: vec![0.0; channels],
+        }
+    }
+
+    /// Appends `input` converted to the output rate to `output`
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        let channels = self.channels;
+        let frames = input.len() / channels;
+        if frames == 0 {
+            return;
+        }
+        output.reserve((frames as f64 / self.step) as usize * channels + channels);
+        let sample = |frame: usize, channel: usize| {
+            if frame == 0 {
+                self.previous[channel]
+            } else {
+                input[(frame - 1) * channels + channel]
+            }
+        };
+        while self.position < frames as f64 {
+            let frame = self.position as usize;
+            let fraction = (self.position - frame as f64) as f32;
+            for channel in 0..channels {
+                let from = sample(frame, channel);
+                let to = sample(frame + 1, channel);
+                output.push(from + (to - from) * fraction);
+            }
+            self.position += self.step;
+        }
+        self.position -= frames as f64;
+        self.previous
+            .copy_from_slice(&input[(frames - 1) * channels..frames * channels]);
+    }
+}
 
 pub struct AudioPlayer {
     _sdl: sdl2::Sdl,
     queue: AudioQueue<f32>,
+    source_rate: f64,
+    /// Channels the core mixes, mono is played on both speakers
+    source_channels: usize,
+    /// Unused while the core already runs at the device rate
+    resampler: Option<Resampler>,
+    scratch: Vec<f32>,
+    latency: Duration,
+    max_latency: Duration,
+    /// The `[audio]` settings the device was opened with
+    settings: AudioConfig,
+    unfocused: UnfocusedAudio,
+    focused: bool,
+    /// Master volume in percent
+    volume: u8,
+    /// Until when frontends show the volume after a hotkey changed it
+    volume_shown: Option<Instant>,
 }
 
 impl AudioPlayer {
-    pub fn new() -> Result<Self> {
+    /// Opens the device with the `[audio]` settings, with `--volume` already folded in, for a
+    /// core producing `channels` interleaved channels at `source_rate`
+    pub fn new(settings: &AudioConfig, source_rate: f64, channels: usize) -> Result<Self> {
         let sdl = sdl2::init().map_err(|e| anyhow!(e))?;
         let audio = sdl.audio().map_err(|e| anyhow!(e))?;
         let desired = AudioSpecDesired {
-            freq: Some(44_100),
-            channels: Some(2),
-            samples: Some(1024),
+            freq: Some(OUTPUT_SAMPLE_RATE),
+            channels: Some(CHANNELS as u8),
+            samples: Some(settings.buffer_samples),
         };
         let queue = audio
             .open_queue::<f32, _>(None, &desired)
             .map_err(|e| anyhow!(e))?;
         queue.resume();
-        Ok(Self { _sdl: sdl, queue })
+        let mut player = Self {
+            _sdl: sdl,
+            queue,
+            source_rate,
+            source_channels: CHANNELS,
+            resampler: None,
+            scratch: Vec::new(),
+            latency: settings.latency(),
+            max_latency: settings.max_latency(DEFAULT_MAX_LATENCY),
+            settings: *settings,
+            unfocused: settings.unfocused,
+            focused: true,
+            volume: settings.volume.min(100),
+            volume_shown: None,
+        };
+        player.set_source(source_rate, channels)?;
+        Ok(player)
     }
 
+    /// Switches to audio at another rate or channel count, for cores that change it mid-game
+    pub fn set_source(&mut self, source_rate: f64, channels: usize) -> Result<()> {
+        if !(1..=CHANNELS).contains(&channels) {
+            bail!("{channels} audio channels are not supported, only mono and stereo");
+        }
+        if !(source_rate.is_finite() && source_rate > 0.0) {
+            bail!("invalid audio sample rate {source_rate}");
+        }
+        let output_rate = f64::from(self.sample_rate());
+        self.source_rate = source_rate;
+        self.source_channels = channels;
+        self.resampler = (source_rate != output_rate)
+            .then(|| Resampler::new(source_rate, output_rate, CHANNELS));
+        Ok(())
+    }
+
+    /// Uses `default` as the most audio kept queued unless `[audio] max_latency_ms` is set
+    pub fn with_max_latency(mut self, default: Duration) -> Self {
+        self.max_latency = self.settings.max_latency(default);
+        self
+    }
+
+    /// Mutes or pauses the sound while the window is in the background, as `[audio] unfocused`
+    /// asks
+    pub fn set_focused(&mut self, focused: bool) {
+        if focused == self.focused {
+            return;
+        }
+        self.focused = focused;
+        if self.unfocused == UnfocusedAudio::Pause {
+            if focused {
+                self.queue.resume();
+            } else {
+                self.queue.pause();
+            }
+        }
+    }
+
+    /// Follows the focus of an SDL window and takes the volume hotkeys, + and - on the main
+    /// keys or the keypad
+    pub fn handle_sdl_event(&mut self, event: &Event) {
+        match event {
+            Event::KeyDown {
+                keycode: Some(Keycode::Equals | Keycode::Plus | Keycode::KpPlus),
+                ..
+            } => self.change_volume(true),
+            Event::KeyDown {
+                keycode: Some(Keycode::Minus | Keycode::KpMinus),
+                ..
+            } => self.change_volume(false),
+            _ => self.handle_focus_event(event),
+        }
+    }
+
+    /// Follows the focus of an SDL window, for frontends whose keyboard needs + and -
+    pub fn handle_focus_event(&mut self, event: &Event) {
+        match event {
+            Event::Window {
+                win_event: WindowEvent::FocusGained,
+                ..
+            } => self.set_focused(true),
+            Event::Window {
+                win_event: WindowEvent::FocusLost,
+                ..
+            } => self.set_focused(false),
+            _ => {}
+        }
+    }
+
+    /// Steps the master volume up or down and shows it for a moment
+    pub fn change_volume(&mut self, up: bool) {
+        self.volume = if up {
+            (self.volume + VOLUME_STEP).min(100)
+        } else {
+            self.volume.saturating_sub(VOLUME_STEP)
+        };
+        log::info!("Volume {}%", self.volume);
+        self.volume_shown = Some(Instant::now() + VOLUME_SHOWN);
+    }
+
+    /// What the frontend should draw over the game right now, if anything
+    pub fn osd_text(&self) -> Option<String> {
+        self.volume_shown
+            .is_some_and(|until| Instant::now() < until)
+            .then(|| format!("VOLUME {}%", self.volume))
+    }
+
+    /// Rate the device plays at
+    pub fn sample_rate(&self) -> i32 {
+        self.queue.spec().freq
+    }
+
+    /// Rate the core is expected to produce samples at
+    pub fn source_rate(&self) -> f64 {
+        self.source_rate
+    }
+
+    /// How long the audio queued so far takes to play
+    pub fn buffered_seconds(&self) -> f64 {
+        self.queue.size() as f64 / (self.sample_rate() as u32 * FRAME_BYTES) as f64
+    }
+
+    /// The most audio kept queued before the queue is dropped
+    pub fn max_latency_seconds(&self) -> f64 {
+        self.max_latency.as_secs_f64()
+    }
+
+    /// How much audio should stay queued, for cores that steer their output rate
+    pub fn target_seconds(&self) -> f64 {
+        self.latency.as_secs_f64()
+    }
+
+    /// Queues a device buffer of silence, so the first frames do not underrun
+    pub fn prefill(&mut self) {
+        let silence = vec![0.0; self.queue.spec().samples as usize * CHANNELS];
+        self.queue_output(&silence);
+    }
+
+    /// Queues interleaved samples in the source format
     pub fn push_samples(&mut self, samples: &[f32]) {
-        if let Err(err) = self.queue.queue_audio(samples) {
+        let stereo: Cow<[f32]> = if self.source_channels == 1 {
+            samples
+                .iter()
+                .flat_map(|&sample| [sample, sample])
+                .collect()
+        } else {
+            samples.into()
+        };
+        match self.resampler.as_mut() {
+            Some(resampler) => {
+                let mut scratch = std::mem::take(&mut self.scratch);
+                scratch.clear();
+                resampler.process(&stereo, &mut scratch);
+                self.queue_output(&scratch);
+                self.scratch = scratch;
+            }
+            None => self.queue_output(&stereo),
+        }
+    }
+
+    /// Queues interleaved samples in the 16 bit format most cores mix in
+    pub fn push_i16(&mut self, samples: &[i16]) {
+        let samples: Vec<f32> = samples
+            .iter()
+            .map(|&sample| f32::from(sample) / 32_768.0)
+            .collect();
+        self.push_samples(&samples);
+    }
+
+    fn queue_output(&mut self, samples: &[f32]) {
+        let gain = f32::from(self.volume) / 100.0;
+        let scaled: Vec<f32>;
+        let samples = if gain < 1.0 {
+            scaled = samples.iter().map(|sample| sample * gain).collect();
+            &scaled
+        } else {
+            samples
+        };
+        let result = match (self.focused, self.unfocused) {
+            (true, _) | (false, UnfocusedAudio::Play) => self.queue.queue_audio(samples),
+            // silence of the same length keeps cores that follow the queue level on pace
+            (false, UnfocusedAudio::Mute) => self.queue.queue_audio(&vec![0.0; samples.len()]),
+            (false, UnfocusedAudio::Pause) => return,
+        };
+        if let Err(err) = result {
             eprintln!("Audio queue error: {err}");
         }
-        // if we build up more than ~1 second of buffered audio, drop it to keep latency sane
-        const MAX_BUFFER_BYTES: u32 = 44_100 * 2 * 4; // ~1 second of stereo f32
-        if self.queue.size() > MAX_BUFFER_BYTES {
+        // if we build up more buffered audio than configured, drop it to keep latency sane
+        let max_buffer_bytes = (self.sample_rate() as f64
+            * FRAME_BYTES as f64
+            * self.max_latency.as_secs_f64()) as u32;
+        if self.queue.size() > max_buffer_bytes {
             self.queue.clear();
         }
     }
-
 }
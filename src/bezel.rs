@@ -0,0 +1,220 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use flate2::read::ZlibDecoder;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{BlendMode, Canvas, Texture};
+use sdl2::video::Window;
+
+use crate::config::DisplayConfig;
+use crate::screenshot::PNG_SIGNATURE;
+use crate::systems::GameSystem;
+
+/// Artwork is looked up as `bezels/<system>/<game>.png`, then `bezels/<system>.png`
+pub const BEZEL_DIR: &str = "bezels";
+
+/// Artwork drawn over the whole window behind the game's picture, filling the bars left
+/// around it. Transparent parts show the window's black background.
+pub struct Bezel {
+    texture: Texture,
+}
+
+impl Bezel {
+    /// Loads the artwork for `game` on `system`, `None` when there is none. Broken artwork is
+    /// reported and left out, the game runs without it.
+    pub fn load(
+        canvas: &Canvas<Window>,
+        display: &DisplayConfig,
+        system: GameSystem,
+        game: &str,
+    ) -> Option<Self> {
+        let path = find(display, system, game)?;
+        match Self::open(canvas, &path) {
+            Ok(bezel) => {
+                log::info!("Bezel {}", path.display());
+                Some(bezel)
+            }
+            Err(err) => {
+                log::warn!("Failed to load the bezel {}: {err:#}", path.display());
+                None
+            }
+        }
+    }
+
+    fn open(canvas: &Canvas<Window>, path: &Path) -> Result<Self> {
+        let png = fs::read(path)?;
+        let (width, height, pixels) = decode_png(&png)?;
+        let mut texture = canvas
+            .texture_creator()
+            .create_texture_static(PixelFormatEnum::ARGB8888, width, height)
+            .map_err(|err| anyhow!(err))?;
+        texture
+            .update(None, bytemuck::cast_slice(&pixels), width as usize * 4)
+            .map_err(|err| anyhow!(err))?;
+        texture.set_blend_mode(BlendMode::Blend);
+        Ok(Self { texture })
+    }
+
+    /// Stretches the artwork over the whole window, call after clearing and before copying
+    /// the game's picture. A logical size on the canvas is lifted for the copy and put back.
+    pub fn draw(&self, canvas: &mut Canvas<Window>) -> Result<(), String> {
+        let (width, height) = canvas.logical_size();
+        let logical = (width, height) != (0, 0);
+        if logical {
+            canvas
+                .set_logical_size(0, 0)
+                .map_err(|err| err.to_string())?;
+        }
+        let drawn = canvas.copy(&self.texture, None, None);
+        if logical {
+            canvas
+                .set_logical_size(width, height)
+                .map_err(|err| err.to_string())?;
+        }
+        drawn
+    }
+}
+
+/// The artwork for `game` on `system`: the game's own file, then `[display.systems.<system>]
+/// bezel`, then the system's file
+fn find(display: &DisplayConfig, system: GameSystem, game: &str) -> Option<PathBuf> {
+    let dir = Path::new(BEZEL_DIR);
+    let key = system.config_key();
+    let configured = display
+        .systems
+        .get(key)
+        .and_then(|settings| settings.bezel.clone());
+    [
+        Some(dir.join(key).join(format!("{game}.png"))),
+        configured,
+        Some(dir.join(format!("{key}.png"))),
+    ]
+    .into_iter()
+    .flatten()
+    .find(|path| path.is_file())
+}
+
+/// Decodes a non-interlaced 8 bit PNG of any colour type to `0xAARRGGBB` pixels, which is what
+/// image editors save artwork as
+fn decode_png(png: &[u8]) -> Result<(u32, u32, Vec<u32>)> {
+    ensure!(png.starts_with(&PNG_SIGNATURE), "not a PNG file");
+    let mut rest = &png[PNG_SIGNATURE.len()..];
+    let mut header = None;
+    let mut palette = Vec::new();
+    // alpha of the palette entries, the rest are opaque
+    let mut palette_alpha = Vec::new();
+    let mut data = Vec::new();
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let body = rest
+            .get(8..8 + length)
+            .context("PNG chunk runs past the end of the file")?;
+        match &rest[4..8] {
+            b"IHDR" => header = Some(body),
+            b"PLTE" => {
+                palette = body
+                    .chunks_exact(3)
+                    .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+                    .collect()
+            }
+            b"tRNS" => palette_alpha = body.to_vec(),
+            b"IDAT" => data.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        rest = rest.get(12 + length..).unwrap_or_default();
+    }
+    let header = header
+        .filter(|header| header.len() == 13)
+        .context("PNG has no header")?;
+    let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    let (depth, color, interlace) = (header[8], header[9], header[12]);
+    ensure!(
+        depth == 8,
+        "only 8 bit PNGs are supported, this one is {depth} bit"
+    );
+    ensure!(interlace == 0, "interlaced PNGs are not supported");
+    let channels = match color {
+        0 | 3 => 1,
+        4 => 2,
+        2 => 3,
+        6 => 4,
+        _ => bail!("unknown PNG colour type {color}"),
+    };
+
+    let mut raw = Vec::new();
+    ZlibDecoder::new(data.as_slice())
+        .read_to_end(&mut raw)
+        .context("PNG data is corrupt")?;
+    let stride = width as usize * channels;
+    let rows = height as usize;
+    ensure!(raw.len() >= (stride + 1) * rows, "PNG data is cut short");
+
+    let mut image = vec![0u8; stride * rows];
+    for (row, line) in raw.chunks_exact(stride + 1).take(rows).enumerate() {
+        let (done, current) = image.split_at_mut(row * stride);
+        let above = (row > 0).then(|| &done[(row - 1) * stride..]);
+        let current = &mut current[..stride];
+        for index in 0..stride {
+            let left = if index >= channels {
+                current[index - channels]
+            } else {
+                0
+            };
+            let up = above.map_or(0, |above| above[index]);
+            let up_left = match above {
+                Some(above) if index >= channels => above[index - channels],
+                _ => 0,
+            };
+            let predicted = match line[0] {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                filter => bail!("unknown PNG filter {filter}"),
+            };
+            current[index] = line[1 + index].wrapping_add(predicted);
+        }
+    }
+
+    let pixels = image
+        .chunks_exact(channels)
+        .map(|pixel| {
+            let [red, green, blue, alpha] = match color {
+                0 => [pixel[0], pixel[0], pixel[0], 0xFF],
+                3 => {
+                    let index = usize::from(pixel[0]);
+                    let [red, green, blue] = palette.get(index).copied().unwrap_or_default();
+                    [
+                        red,
+                        green,
+                        blue,
+                        palette_alpha.get(index).copied().unwrap_or(0xFF),
+                    ]
+                }
+                4 => [pixel[0], pixel[0], pixel[0], pixel[1]],
+                2 => [pixel[0], pixel[1], pixel[2], 0xFF],
+                _ => [pixel[0], pixel[1], pixel[2], pixel[3]],
+            };
+            u32::from_be_bytes([alpha, red, green, blue])
+        })
+        .collect();
+    Ok((width, height, pixels))
+}
+
+/// The neighbour closest to `left + up - up_left`, ties going left, then up
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = i16::from(left) + i16::from(up) - i16::from(up_left);
+    let distance = |value: u8| (estimate - i16::from(value)).abs();
+    if distance(left) <= distance(up) && distance(left) <= distance(up_left) {
+        left
+    } else if distance(up) <= distance(up_left) {
+        up
+    } else {
+        up_left
+    }
+}
@@ -1,66 +1,150 @@
 use std::cell::RefCell;
 use std::fs::{self, File};
 use std::io::{BufRead, BufReader, Cursor, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use anyhow::{Context, Result, anyhow, ensure};
-use bytemuck;
+use sdl2::controller::Button;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::render::{Canvas, Texture};
 use sdl2::video::Window;
 
-use zinc64_core::{SoundOutput, VideoOutput};
-use zinc64_emu::device::joystick::{Button as JoyButton, Mode as JoystickMode};
-use zinc64_emu::device::keyboard::{Key, KeyEvent};
-use zinc64_emu::system::{C64, C64Factory, Config};
+use zinc64_core::{SidModel, SoundOutput, SystemModel, VideoOutput};
+use zinc64_emu::device::joystick::{Button as JoyButton, Joystick, Mode as JoystickMode};
+use zinc64_emu::sound::sid::SamplingMethod;
+use zinc64_emu::system::autostart::Mode as AutostartMode;
+use zinc64_emu::system::{Autostart, C64, Config as MachineConfig};
 use zinc64_loader::{Loaders, Reader};
 
 use crate::audio::AudioPlayer;
+use crate::bezel::Bezel;
+use crate::c64_disk::{DiskAutostart, VirtualDrive};
+use crate::c64_keyboard::{HostKeyboard, VirtualKeyboard};
+use crate::c64_reu::Reu;
+use crate::c64_script::{JoystickInput, Script, StateRequest};
+use crate::c64_state::{self, MachineFactory, MachineStates};
+use crate::config::{C64Model, Config, KeyboardMapping, SidChip, SidResampling};
 use crate::controller::{ControllerManager, VirtualButton};
+use crate::focus_pause::{self, FocusPause};
+use crate::frame_pacer::FramePacer;
+use crate::perf_overlay::PerfOverlay;
+use crate::present;
+use crate::resume;
+use crate::systems::GameSystem;
 
 const C64_BIOS_SUBDIR: &str = "c64";
 const BASIC_ROM: &str = "basic.rom";
 const CHARSET_ROM: &str = "characters.rom";
 const KERNAL_ROM: &str = "kernal.rom";
 
-const FRAME_DURATION: Duration = Duration::from_micros((1_000_000f32 / 50.0) as u64);
 const PALETTE: [u32; 16] = [
     0x000000FF, 0xFFFFFFFF, 0x68372BFF, 0x70A4B2FF, 0x6F3D86FF, 0x588D43FF, 0x352879FF, 0xB8C76FFF,
     0x6F4F25FF, 0x433900FF, 0x9A6759FF, 0x444444FF, 0x6C6C6CFF, 0x9AD284FF, 0x6C5EB5FF, 0x959595FF,
 ];
 
-pub fn run(rom: &Path, bios_dir: &Path, scale: u32, limit_fps: bool) -> Result<()> {
+pub fn run(
+    rom: &Path,
+    bios_dir: &Path,
+    scale: u32,
+    limit_fps: bool,
+    config: &Config,
+    model: Option<C64Model>,
+    script: Option<&Path>,
+) -> Result<()> {
+    let settings = &config.c64;
+    let label = rom
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("c64")
+        .to_string();
+    let game = settings.game(&label);
+    let model = model
+        .or_else(|| game.and_then(|game| game.model))
+        .unwrap_or(settings.model);
+    log::info!("Emulating a {model:?} C64");
+
     let roms = load_roms(bios_dir)?;
-    let mut config = Config::new_with_roms(
-        zinc64_core::SystemModel::c64_pal(),
-        &roms.basic,
-        &roms.charset,
-        &roms.kernal,
-    );
-    config.joystick.joystick_1 = JoystickMode::None;
-    config.joystick.joystick_2 = JoystickMode::Joy1;
+    let system = if model.is_ntsc() {
+        SystemModel::c64_ntsc()
+    } else {
+        SystemModel::c64_pal()
+    };
+    let mut machine =
+        MachineConfig::new_with_roms(system, &roms.basic, &roms.charset, &roms.kernal);
+    machine.model.sid_model = match settings.sid_model.unwrap_or(model.sid()) {
+        SidChip::Mos6581 => SidModel::Mos6581,
+        SidChip::Mos8580 => SidModel::Mos8580,
+    };
+    machine.sound.sid_filters = settings.sid_filter;
+    machine.sound.sample_rate = settings.sample_rate;
+    let sampling = match settings.resampling {
+        SidResampling::Fast => SamplingMethod::Fast,
+        SidResampling::Interpolate => SamplingMethod::Interpolate,
+        SidResampling::ResampleFast => SamplingMethod::ResampleFast,
+        SidResampling::Resample => SamplingMethod::Resample,
+    };
+    // both ports get a joystick, the frontend drives the selected one
+    machine.joystick.joystick_1 = JoystickMode::Joy0;
+    machine.joystick.joystick_2 = JoystickMode::Joy1;
+    let joystick_port = game
+        .and_then(|game| game.joystick_port)
+        .unwrap_or(settings.joystick_port);
 
     let video = Rc::new(RefCell::new(C64FrameBuffer::new(
-        config.model.frame_buffer_size.0 as usize,
-        config.model.frame_buffer_size.1 as usize,
+        machine.model.frame_buffer_size.0 as usize,
+        machine.model.frame_buffer_size.1 as usize,
     )));
     let video_output = video.clone() as Rc<RefCell<dyn VideoOutput>>;
 
-    let audio_sink = Arc::new(C64AudioSink::new()?);
+    let audio_sink = Arc::new(C64AudioSink::default());
     let sound_output = audio_sink.clone() as Arc<dyn SoundOutput>;
 
-    let config_rc = Rc::new(config);
-    let factory = C64Factory::new(config_rc.clone());
-    let mut c64 = C64::build(config_rc.clone(), &factory, video_output, sound_output);
+    let reu_size = game.and_then(|game| game.reu).unwrap_or(settings.reu);
+    let reu = reu_size.bytes().map(|bytes| {
+        log::info!("RAM Expansion Unit with {} KiB", bytes / 1024);
+        Reu::new(bytes)
+    });
+
+    let machine_rc = Rc::new(machine);
+    let factory = MachineFactory::new(machine_rc.clone(), sampling, reu);
+    let mut c64 = C64::build(machine_rc.clone(), &factory, video_output, sound_output);
+    let states = factory.into_states()?;
+    let script = script
+        .map(|path| Script::load(path, states.ram()))
+        .transpose()?;
     c64.reset(true);
-    load_image(&mut c64, rom)?;
-
-    let mut frontend = C64Frontend::new(video, scale.max(1), limit_fps)?;
+    let resume_from = resume::to_load(GameSystem::C64, rom);
+    let drive = load_image(&mut c64, rom, resume_from.is_none())?;
+
+    let audio = C64Audio {
+        sink: audio_sink,
+        player: AudioPlayer::new(&config.audio, settings.sample_rate.into(), 1)?,
+    };
+    let frame = Duration::from_secs_f32(1.0 / machine_rc.model.refresh_rate);
+    let frame_limit = limit_fps.then_some(frame);
+    let mut frontend = C64Frontend::new(
+        video,
+        audio,
+        scale.max(1),
+        frame_limit,
+        states,
+        label,
+        config,
+    )?;
+    frontend.drive = drive;
+    frontend.keyboard = HostKeyboard::new(settings.keyboard);
+    frontend.joystick_port = joystick_port;
+    frontend.perf.set_target(frame);
+    frontend.script = script;
+    if let Some(path) = resume_from {
+        frontend.load_state(&mut c64, &path);
+    }
+    frontend.resume = resume::to_save(GameSystem::C64, rom);
     frontend.run(&mut c64)
 }
 
@@ -87,18 +171,35 @@ fn read_required_rom(dir: &Path, name: &str) -> Result<Vec<u8>> {
     fs::read(&path).with_context(|| format!("missing required ROM: {}", path.display()))
 }
 
-fn load_image(c64: &mut C64, rom: &Path) -> Result<()> {
+/// Disk images go into the virtual drive and are loaded with `LOAD"*",8,1`, everything
+/// else through the zinc64 loaders. Without `autostart`, for a game resumed from a state,
+/// only the disk is inserted.
+fn load_image(c64: &mut C64, rom: &Path, autostart: bool) -> Result<Option<VirtualDrive>> {
     let ext = rom
         .extension()
         .and_then(|s| s.to_str())
         .map(|s| s.to_ascii_lowercase());
+    if matches!(ext.as_deref(), Some("d64" | "g64")) {
+        let drive = VirtualDrive::new(rom)?;
+        drive.attach(c64);
+        if autostart {
+            c64.set_autostart(Some(Autostart::new(
+                AutostartMode::Run,
+                Box::new(DiskAutostart),
+            )));
+        }
+        return Ok(Some(drive));
+    }
+    if !autostart {
+        return Ok(None);
+    }
     if matches!(ext.as_deref(), Some("t64")) {
         let prg = extract_prg_from_t64(rom)?;
         let loader = Loaders::from_ext(Some("prg")).map_err(|err| anyhow!(err))?;
         let mut reader = MemoryReader::new(prg);
         let mut autostart = loader.autostart(&mut reader).map_err(|err| anyhow!(err))?;
         autostart.execute(c64);
-        return Ok(());
+        return Ok(None);
     }
 
     let loader = Loaders::from_ext(ext.as_deref()).map_err(|err| anyhow!(err))?;
@@ -106,7 +207,7 @@ fn load_image(c64: &mut C64, rom: &Path) -> Result<()> {
     let mut reader = LoaderFile::new(file);
     let mut autostart = loader.autostart(&mut reader).map_err(|err| anyhow!(err))?;
     autostart.execute(c64);
-    Ok(())
+    Ok(None)
 }
 
 struct LoaderFile(BufReader<File>);
@@ -263,237 +364,397 @@ impl VideoOutput for C64FrameBuffer {
     }
 }
 
+/// Collects SID samples during a frame, the frontend moves them to the audio device. The
+/// SDL audio queue cannot live in the `Arc` zinc64 wants for its sound output.
+#[derive(Default)]
 struct C64AudioSink {
-    player: Mutex<AudioPlayer>,
+    samples: Mutex<Vec<f32>>,
 }
 
-impl C64AudioSink {
-    fn new() -> Result<Self> {
-        Ok(Self {
-            player: Mutex::new(AudioPlayer::new()?),
-        })
+/// The sink zinc64 writes to and the device its samples are played on
+struct C64Audio {
+    sink: Arc<C64AudioSink>,
+    player: AudioPlayer,
+}
+
+impl C64Audio {
+    /// Plays the samples of the last frame
+    fn flush(&mut self) {
+        if let Ok(mut samples) = self.sink.samples.lock()
+            && !samples.is_empty()
+        {
+            self.player.push_samples(&samples);
+            samples.clear();
+        }
     }
 }
 
 impl SoundOutput for C64AudioSink {
     fn reset(&self) {
-        if let Ok(mut player) = self.player.lock() {
-            player.clear();
+        if let Ok(mut samples) = self.samples.lock() {
+            samples.clear();
         }
     }
 
     fn write(&self, samples: &[i16]) {
-        if let Ok(mut player) = self.player.lock() {
-            if samples.is_empty() {
-                return;
-            }
-            let mut stereo = Vec::with_capacity(samples.len() * 2);
-            for sample in samples {
-                let normalized = (*sample as f32) / (i16::MAX as f32);
-                stereo.push(normalized);
-                stereo.push(normalized);
-            }
-            player.push_samples(&stereo);
+        if let Ok(mut mono) = self.samples.lock() {
+            mono.extend(
+                samples
+                    .iter()
+                    .map(|&sample| (sample as f32) / (i16::MAX as f32)),
+            );
         }
     }
 }
 
 struct C64Frontend {
     video: Rc<RefCell<C64FrameBuffer>>,
+    audio: C64Audio,
     _sdl: sdl2::Sdl,
     canvas: Canvas<Window>,
     texture: Texture,
+    bezel: Option<Bezel>,
     event_pump: sdl2::EventPump,
     controller: ControllerManager,
-    limit_fps: bool,
+    pacer: FramePacer,
+    drive: Option<VirtualDrive>,
+    states: MachineStates,
+    /// Names the save states of the game
+    label: String,
+    state_slot: u32,
+    keyboard: HostKeyboard,
+    virtual_keyboard: VirtualKeyboard,
+    /// Control port the pad drives, 1 or 2
+    joystick_port: u8,
+    /// The frame with the virtual keyboard or the script's drawing over it
+    overlay: Vec<u32>,
+    script: Option<Script>,
+    /// Only opens from the config or `--perf-overlay`, its hotkey is a key of the C64
+    perf: PerfOverlay,
+    focus: FocusPause,
+    /// The auto-resume state written when the window closes
+    resume: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy)]
+enum Hotkey {
+    SaveState,
+    LoadState,
+    NextSlot,
+    SwapDisk,
+    ToggleKeyboardMapping,
+    VirtualKeyboard,
+    SwapJoystickPort,
 }
 
+/// Picked around the C64's own function keys, which F1/F3/F5/F7 stand in for
+const HOTKEYS: &[(Keycode, Hotkey)] = &[
+    (Keycode::F2, Hotkey::SaveState),
+    (Keycode::F4, Hotkey::LoadState),
+    (Keycode::F6, Hotkey::NextSlot),
+    (Keycode::F8, Hotkey::SwapJoystickPort),
+    (Keycode::F9, Hotkey::SwapDisk),
+    (Keycode::F11, Hotkey::ToggleKeyboardMapping),
+    (Keycode::F12, Hotkey::VirtualKeyboard),
+];
+const STATE_SLOTS: u32 = 10;
+
 impl C64Frontend {
-    fn new(video: Rc<RefCell<C64FrameBuffer>>, scale: u32, limit_fps: bool) -> Result<Self> {
+    fn new(
+        video: Rc<RefCell<C64FrameBuffer>>,
+        audio: C64Audio,
+        scale: u32,
+        frame_limit: Option<Duration>,
+        states: MachineStates,
+        label: String,
+        config: &Config,
+    ) -> Result<Self> {
         let sdl = sdl2::init().map_err(|err| anyhow!(err))?;
         let video_subsystem = sdl.video().map_err(|err| anyhow!(err))?;
         let frame = video.borrow();
-        let width = (frame.width as u32).saturating_mul(scale);
-        let height = (frame.height as u32).saturating_mul(scale);
+        let (width, height) = present::oriented(
+            (frame.width as u32).saturating_mul(scale),
+            (frame.height as u32).saturating_mul(scale),
+        );
         drop(frame);
         let window = video_subsystem
             .window("Commodore 64", width, height)
             .resizable()
             .position_centered()
+            .allow_highdpi()
             .build()
             .context("failed to create C64 window")?;
-        let mut canvas_builder = window.into_canvas();
-        if limit_fps {
-            canvas_builder = canvas_builder.present_vsync();
-        }
-        let mut canvas = canvas_builder.build().map_err(|err| anyhow!(err))?;
+        let mut canvas = present::canvas(window, frame_limit.is_some(), &config.display)
+            .map_err(|err| anyhow!(err))?;
+        present::apply_fullscreen(canvas.window_mut());
+        // a frame at the model's refresh rate, `None` runs unthrottled
+        let pacer = FramePacer::new(frame_limit.unwrap_or_default(), frame_limit.is_some())
+            .vsynced(canvas.window(), &config.display);
         let (logical_w, logical_h) = {
             let fb = video.borrow();
             (fb.width as u32, fb.height as u32)
         };
-        canvas
-            .set_logical_size(logical_w, logical_h)
+        present::set_logical_size(&mut canvas, logical_w, logical_h)
             .context("failed to set logical size")?;
         let texture_creator = canvas.texture_creator();
         let texture = texture_creator
             .create_texture_streaming(PixelFormatEnum::ARGB8888, logical_w, logical_h)
             .map_err(|err| anyhow!(err))?;
+        let bezel = Bezel::load(&canvas, &config.display, GameSystem::C64, &label);
         let event_pump = sdl.event_pump().map_err(|err| anyhow!(err))?;
-        let controller = ControllerManager::new(&sdl)?;
+        let controller = ControllerManager::new(&sdl, &config.controllers)?;
         Ok(Self {
             video,
+            audio,
             _sdl: sdl,
             canvas,
             texture,
+            bezel,
             event_pump,
             controller,
-            limit_fps,
+            pacer,
+            drive: None,
+            states,
+            label,
+            state_slot: 0,
+            keyboard: HostKeyboard::new(KeyboardMapping::Symbolic),
+            virtual_keyboard: VirtualKeyboard::default(),
+            joystick_port: 2,
+            overlay: Vec::new(),
+            script: None,
+            perf: PerfOverlay::new(frame_limit.unwrap_or_default(), &config.display),
+            focus: FocusPause::new(&config.display),
+            resume: None,
         })
     }
 
     fn run(&mut self, c64: &mut C64) -> Result<()> {
         let mut running = true;
         while running {
-            let frame_start = Instant::now();
+            let mut hotkeys = Vec::new();
             for event in self.event_pump.poll_iter() {
                 self.controller.handle_event(&event);
+                // + and - are keys of the C64 keyboard, the volume comes from the config or --volume
+                self.audio.player.handle_focus_event(&event);
+                self.focus.handle_sdl_event(&event);
                 match event {
                     Event::Quit { .. } => running = false,
                     Event::KeyDown {
                         keycode: Some(code),
-                        repeat,
+                        repeat: false,
                         ..
-                    } => {
-                        if !repeat {
-                            handle_key_event(c64, code, true);
-                        }
+                    } if let Some(&(_, hotkey)) = HOTKEYS.iter().find(|(key, _)| *key == code) => {
+                        hotkeys.push(hotkey)
                     }
-                    Event::KeyUp {
+                    Event::KeyDown {
                         keycode: Some(code),
+                        scancode: Some(scancode),
+                        keymod,
+                        repeat: false,
                         ..
-                    } => {
-                        handle_key_event(c64, code, false);
-                    }
+                    } => self.keyboard.key_down(c64, code, scancode, keymod),
+                    Event::KeyUp {
+                        scancode: Some(scancode),
+                        ..
+                    } => self.keyboard.key_up(c64, scancode),
+                    Event::ControllerButtonDown {
+                        button: Button::Back,
+                        ..
+                    } => hotkeys.push(Hotkey::VirtualKeyboard),
                     _ => {}
                 }
             }
             if !running {
                 break;
             }
+            for hotkey in hotkeys {
+                self.hotkey(c64, hotkey);
+            }
+            if self.focus.is_paused() {
+                focus_pause::idle();
+                continue;
+            }
 
-            update_joystick(c64, &self.controller);
-            c64.run_frame();
+            self.controller.advance_frame();
+            if self.virtual_keyboard.is_open() {
+                self.virtual_keyboard.update(c64, &self.controller);
+            } else {
+                update_joystick(c64, self.joystick_port, &self.controller);
+            }
+            if let Some(input) = self.script.as_mut().and_then(Script::take_joystick) {
+                script_joystick(c64, self.joystick_port, input);
+            }
+            self.run_frame(c64);
+            self.run_script(c64);
+            self.audio.flush();
+            self.perf.frame();
+            self.perf.set_audio(&self.audio.player);
             self.present_frame();
 
-            if self.limit_fps {
-                let elapsed = frame_start.elapsed();
-                if elapsed < FRAME_DURATION {
-                    std::thread::sleep(FRAME_DURATION - elapsed);
-                }
-            }
+            self.pacer.wait();
+        }
+        if let Some(path) = self.resume.take() {
+            self.save_state(c64, &path);
         }
         Ok(())
     }
 
-    fn present_frame(&mut self) {
-        if let Ok(buffer) = self.video.try_borrow() {
-            if self
-                .texture
-                .update(
-                    None,
-                    bytemuck::cast_slice(buffer.pixels()),
-                    buffer.width * 4,
-                )
-                .is_ok()
-            {
-                let _ = self.canvas.copy(&self.texture, None, None);
-                self.canvas.present();
+    fn run_frame(&mut self, c64: &mut C64) {
+        // typed text such as the autostart command goes in one key event per frame
+        c64.get_keyboard().drain_event();
+        // zinc64 leaves clearing the flag the VIC sets at the end of a frame to the frontend
+        c64.reset_vsync();
+        while !c64.run_frame() {
+            // the only breakpoint is the drive's LOAD trap
+            match self.drive.as_mut() {
+                Some(drive) => drive.handle_trap(c64),
+                None => break,
             }
         }
     }
-}
 
-fn handle_key_event(c64: &mut C64, keycode: Keycode, pressed: bool) {
-    if let Some(event) = map_keycode(keycode) {
-        let keyboard = c64.get_keyboard();
-        if pressed {
-            keyboard.on_key_down(event);
+    /// Calls the script's frame callbacks and takes the save state it asked for. A
+    /// failing script is unloaded and the game plays on without it.
+    fn run_script(&mut self, c64: &mut C64) {
+        let Some(script) = self.script.as_mut() else {
+            return;
+        };
+        if let Err(err) = script.run_frame(c64.get_frame_count()) {
+            log::warn!("Stopping the Lua script: {err:#}");
+            self.script = None;
+            return;
+        }
+        match script.take_state_request() {
+            Some(StateRequest::Save(slot)) => self.save_slot(c64, slot),
+            Some(StateRequest::Load(slot)) => self.load_slot(c64, slot),
+            None => {}
+        }
+    }
+
+    fn hotkey(&mut self, c64: &mut C64, hotkey: Hotkey) {
+        match hotkey {
+            Hotkey::SaveState => self.save_slot(c64, self.state_slot),
+            Hotkey::LoadState => self.load_slot(c64, self.state_slot),
+            Hotkey::NextSlot => {
+                self.state_slot = (self.state_slot + 1) % STATE_SLOTS;
+                log::info!("State slot {}", self.state_slot);
+            }
+            Hotkey::SwapDisk => self.swap_disk(),
+            Hotkey::ToggleKeyboardMapping => {
+                let mapping = self.keyboard.toggle_mapping(c64);
+                log::info!("Keyboard mapping is now {mapping:?}");
+            }
+            Hotkey::VirtualKeyboard => {
+                self.virtual_keyboard.toggle(c64);
+                // the pad drives the keyboard instead of the joystick while it is open
+                release_joystick(c64, self.joystick_port);
+            }
+            Hotkey::SwapJoystickPort => {
+                release_joystick(c64, self.joystick_port);
+                self.joystick_port = 3 - self.joystick_port;
+                log::info!("Controller now drives joystick port {}", self.joystick_port);
+            }
+        }
+    }
+
+    fn save_slot(&mut self, c64: &C64, slot: u32) {
+        let path = c64_state::state_path(&self.label, slot);
+        self.save_state(c64, &path);
+    }
+
+    fn load_slot(&mut self, c64: &mut C64, slot: u32) {
+        let path = c64_state::state_path(&self.label, slot);
+        if !path.exists() {
+            log::info!("State slot {slot} is empty");
+            return;
+        }
+        self.load_state(c64, &path);
+    }
+
+    fn save_state(&mut self, c64: &C64, path: &Path) {
+        let disk = self.drive.as_ref().map_or(0, VirtualDrive::current);
+        match self.states.save(c64, disk, path) {
+            Ok(()) => log::info!("Saved state {}", path.display()),
+            Err(err) => log::warn!("Failed to save state: {err:#}"),
+        }
+    }
+
+    fn load_state(&mut self, c64: &mut C64, path: &Path) {
+        let result = self
+            .states
+            .load(c64, path)
+            .and_then(|disk| match self.drive.as_mut() {
+                Some(drive) => drive.restore(disk),
+                None => Ok(()),
+            });
+        match result {
+            Ok(()) => log::info!("Loaded state {}", path.display()),
+            Err(err) => log::warn!("Failed to load state: {err:#}"),
+        }
+    }
+
+    fn swap_disk(&mut self) {
+        let Some(drive) = self.drive.as_mut() else {
+            return;
+        };
+        match drive.swap() {
+            Ok(label) => {
+                log::info!("Inserted {label}");
+                let _ = self
+                    .canvas
+                    .window_mut()
+                    .set_title(&format!("Commodore 64 - {label}"));
+            }
+            Err(err) => log::warn!("Disk swap failed: {err:#}"),
+        }
+    }
+
+    fn present_frame(&mut self) {
+        let Ok(buffer) = self.video.try_borrow() else {
+            return;
+        };
+        let script_drawing = self.script.as_ref().is_some_and(Script::has_drawing);
+        let pixels = if self.virtual_keyboard.is_open() || script_drawing || self.perf.is_open() {
+            self.overlay.clear();
+            self.overlay.extend_from_slice(buffer.pixels());
+            if let Some(script) = &self.script {
+                script.draw(&mut self.overlay, buffer.width);
+            }
+            if self.virtual_keyboard.is_open() {
+                self.virtual_keyboard
+                    .draw(&mut self.overlay, buffer.width, buffer.height);
+            }
+            if self.perf.is_open() {
+                self.perf.draw(&mut self.overlay, buffer.width);
+            }
+            &self.overlay
         } else {
-            keyboard.on_key_up(event);
+            buffer.pixels()
+        };
+        if self
+            .texture
+            .update(None, bytemuck::cast_slice(pixels), buffer.width * 4)
+            .is_ok()
+        {
+            if let Some(bezel) = &self.bezel {
+                self.canvas.clear();
+                let _ = bezel.draw(&mut self.canvas);
+            }
+            let _ = present::copy(&mut self.canvas, &self.texture, None);
+            self.canvas.present();
         }
     }
 }
 
-fn map_keycode(keycode: Keycode) -> Option<KeyEvent> {
-    use Key::*;
-    match keycode {
-        Keycode::A => Some(KeyEvent::new(A)),
-        Keycode::B => Some(KeyEvent::new(B)),
-        Keycode::C => Some(KeyEvent::new(C)),
-        Keycode::D => Some(KeyEvent::new(D)),
-        Keycode::E => Some(KeyEvent::new(E)),
-        Keycode::F => Some(KeyEvent::new(F)),
-        Keycode::G => Some(KeyEvent::new(G)),
-        Keycode::H => Some(KeyEvent::new(H)),
-        Keycode::I => Some(KeyEvent::new(I)),
-        Keycode::J => Some(KeyEvent::new(J)),
-        Keycode::K => Some(KeyEvent::new(K)),
-        Keycode::L => Some(KeyEvent::new(L)),
-        Keycode::M => Some(KeyEvent::new(M)),
-        Keycode::N => Some(KeyEvent::new(N)),
-        Keycode::O => Some(KeyEvent::new(O)),
-        Keycode::P => Some(KeyEvent::new(P)),
-        Keycode::Q => Some(KeyEvent::new(Q)),
-        Keycode::R => Some(KeyEvent::new(R)),
-        Keycode::S => Some(KeyEvent::new(S)),
-        Keycode::T => Some(KeyEvent::new(T)),
-        Keycode::U => Some(KeyEvent::new(U)),
-        Keycode::V => Some(KeyEvent::new(V)),
-        Keycode::W => Some(KeyEvent::new(W)),
-        Keycode::X => Some(KeyEvent::new(X)),
-        Keycode::Y => Some(KeyEvent::new(Y)),
-        Keycode::Z => Some(KeyEvent::new(Z)),
-        Keycode::Num0 => Some(KeyEvent::new(Num0)),
-        Keycode::Num1 => Some(KeyEvent::new(Num1)),
-        Keycode::Num2 => Some(KeyEvent::new(Num2)),
-        Keycode::Num3 => Some(KeyEvent::new(Num3)),
-        Keycode::Num4 => Some(KeyEvent::new(Num4)),
-        Keycode::Num5 => Some(KeyEvent::new(Num5)),
-        Keycode::Num6 => Some(KeyEvent::new(Num6)),
-        Keycode::Num7 => Some(KeyEvent::new(Num7)),
-        Keycode::Num8 => Some(KeyEvent::new(Num8)),
-        Keycode::Num9 => Some(KeyEvent::new(Num9)),
-        Keycode::Space => Some(KeyEvent::new(Space)),
-        Keycode::Return | Keycode::KpEnter => Some(KeyEvent::new(Return)),
-        Keycode::Backspace => Some(KeyEvent::new(Backspace)),
-        Keycode::Escape => Some(KeyEvent::new(RunStop)),
-        Keycode::Comma => Some(KeyEvent::new(Comma)),
-        Keycode::Period => Some(KeyEvent::new(Period)),
-        Keycode::Semicolon => Some(KeyEvent::new(Semicolon)),
-        Keycode::Slash => Some(KeyEvent::new(Slash)),
-        Keycode::Minus => Some(KeyEvent::new(Minus)),
-        Keycode::Equals => Some(KeyEvent::new(Equals)),
-        Keycode::LeftBracket => Some(KeyEvent::new(At)),
-        Keycode::RightBracket => Some(KeyEvent::new(Colon)),
-        Keycode::Quote => Some(KeyEvent::new(Plus)),
-        Keycode::LShift => Some(KeyEvent::new(LShift)),
-        Keycode::RShift => Some(KeyEvent::new(RShift)),
-        Keycode::LCtrl | Keycode::RCtrl => Some(KeyEvent::new(Ctrl)),
-        Keycode::Home => Some(KeyEvent::new(Home)),
-        Keycode::F1 => Some(KeyEvent::new(F1)),
-        Keycode::F3 => Some(KeyEvent::new(F3)),
-        Keycode::F5 => Some(KeyEvent::new(F5)),
-        Keycode::F7 => Some(KeyEvent::new(F7)),
-        Keycode::Down => Some(KeyEvent::new(CrsrDown)),
-        Keycode::Up => Some(KeyEvent::with_mod(CrsrDown, LShift)),
-        Keycode::Right => Some(KeyEvent::new(CrsrRight)),
-        Keycode::Left => Some(KeyEvent::with_mod(CrsrRight, LShift)),
-        _ => None,
+fn joystick(c64: &mut C64, port: u8) -> Option<&mut Joystick> {
+    match port {
+        1 => c64.get_joystick1_mut().as_mut(),
+        _ => c64.get_joystick2_mut().as_mut(),
     }
 }
 
-fn update_joystick(c64: &mut C64, controllers: &ControllerManager) {
-    if let Some(joy) = c64.get_joystick2_mut().as_mut() {
+fn update_joystick(c64: &mut C64, port: u8, controllers: &ControllerManager) {
+    if let Some(joy) = joystick(c64, port) {
         let up = controllers.is_pressed(VirtualButton::Up);
         if up {
             joy.on_key_down(JoyButton::Up);
@@ -529,3 +790,37 @@ fn update_joystick(c64: &mut C64, controllers: &ControllerManager) {
         }
     }
 }
+
+/// Applies what a script set over the pad's input
+fn script_joystick(c64: &mut C64, port: u8, input: JoystickInput) {
+    let Some(joy) = joystick(c64, port) else {
+        return;
+    };
+    for (held, button) in [
+        (input.up, JoyButton::Up),
+        (input.down, JoyButton::Down),
+        (input.left, JoyButton::Left),
+        (input.right, JoyButton::Right),
+        (input.fire, JoyButton::Fire),
+    ] {
+        match held {
+            Some(true) => joy.on_key_down(button),
+            Some(false) => joy.on_key_up(button),
+            None => {}
+        }
+    }
+}
+
+fn release_joystick(c64: &mut C64, port: u8) {
+    if let Some(joy) = joystick(c64, port) {
+        for button in [
+            JoyButton::Up,
+            JoyButton::Down,
+            JoyButton::Left,
+            JoyButton::Right,
+            JoyButton::Fire,
+        ] {
+            joy.on_key_up(button);
+        }
+    }
+}
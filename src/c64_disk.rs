@@ -0,0 +1,597 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail, ensure};
+use zinc64_core::Cpu;
+use zinc64_emu::system::{C64, Image};
+
+/// KERNAL LOAD after the `$0330` vector: A is the verify flag, `$C3/$C4` the address
+/// BASIC asked for
+const LOAD_TRAP: u16 = 0xF4A5;
+const DRIVE_DEVICE: u8 = 8;
+const DISK_EXTENSIONS: [&str; 2] = ["d64", "g64"];
+
+/// Zero page used by the KERNAL for LOAD
+const ZP_STATUS: u16 = 0x90;
+const ZP_FILENAME_LEN: u16 = 0xB7;
+const ZP_SECONDARY: u16 = 0xB9;
+const ZP_DEVICE: u16 = 0xBA;
+const ZP_FILENAME: u16 = 0xBB;
+const ZP_LOAD_ADDR: u16 = 0xC3;
+const ZP_END_ADDR: u16 = 0xAE;
+const STATUS_EOF: u8 = 0x40;
+const ERROR_FILE_NOT_FOUND: u8 = 4;
+const FLAG_CARRY: u8 = 0x01;
+
+const SECTOR_SIZE: usize = 256;
+const MAX_TRACKS: usize = 40;
+const DIRECTORY_TRACK: usize = 18;
+const BAM_NAME: usize = 0x90;
+const BAM_ID: usize = 0xA2;
+const BAM_DOS_TYPE: usize = 0xA5;
+/// Unused bytes in names on disk
+const PETSCII_PAD: u8 = 0xA0;
+const FILE_TYPES: [&str; 5] = ["DEL", "SEQ", "PRG", "USR", "REL"];
+const FILE_TYPE_PRG: u8 = 2;
+/// Plenty for the 144 directory entries of a real disk, stops looping chains
+const MAX_CHAIN: usize = 800;
+
+/// G64 is a dump of the raw GCR bit stream per half track
+const G64_MAGIC: &[u8] = b"GCR-1541";
+const G64_TRACK_TABLE: usize = 0x0C;
+const GCR_SYNC_BITS: usize = 10;
+const GCR_HEADER_BYTES: usize = 10;
+const GCR_DATA_BYTES: usize = 325;
+const BLOCK_HEADER: u8 = 0x08;
+const BLOCK_DATA: u8 = 0x07;
+/// 5-bit GCR codes of each nibble
+const GCR_ENCODE: [u8; 16] = [
+    0x0A, 0x0B, 0x12, 0x13, 0x0E, 0x0F, 0x16, 0x17, 0x09, 0x19, 0x1A, 0x1B, 0x0D, 0x1D, 0x1E, 0x15,
+];
+
+/// Sectors per track of the four 1541 speed zones
+fn sectors_per_track(track: usize) -> usize {
+    match track {
+        1..=17 => 21,
+        18..=24 => 19,
+        25..=30 => 18,
+        _ => 17,
+    }
+}
+
+/// Offset of the first sector of `track` in a D64 image
+fn track_offset(track: usize) -> usize {
+    (1..track).map(sectors_per_track).sum::<usize>() * SECTOR_SIZE
+}
+
+struct DirEntry {
+    file_type: u8,
+    closed: bool,
+    locked: bool,
+    name: Vec<u8>,
+    track: u8,
+    sector: u8,
+    blocks: u16,
+}
+
+/// A 1541 disk as D64 sector data, G64 images are decoded to it when opened
+pub struct Disk {
+    data: Vec<u8>,
+    tracks: usize,
+}
+
+impl Disk {
+    pub fn open(path: &Path) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let disk = if data.starts_with(G64_MAGIC) {
+            Self::from_g64(&data)
+        } else {
+            Self::from_d64(data)
+        }
+        .with_context(|| format!("{} is not a usable 1541 disk image", path.display()))?;
+        log::info!(
+            "Inserted disk \"{}\" ({} tracks) from {}",
+            disk.name(),
+            disk.tracks,
+            path.display()
+        );
+        Ok(disk)
+    }
+
+    /// 35 or 40 tracks, optionally followed by one error byte per sector
+    fn from_d64(mut data: Vec<u8>) -> Result<Self> {
+        let tracks = [35, 40]
+            .into_iter()
+            .find(|&tracks| {
+                let size = track_offset(tracks + 1);
+                data.len() == size || data.len() == size + size / SECTOR_SIZE
+            })
+            .with_context(|| format!("unexpected D64 size of {} bytes", data.len()))?;
+        data.truncate(track_offset(tracks + 1));
+        Ok(Self { data, tracks })
+    }
+
+    /// Decodes the GCR sectors of every full track. Sectors that cannot be found or
+    /// decoded are left zeroed, like an unformatted block.
+    fn from_g64(data: &[u8]) -> Result<Self> {
+        ensure!(data.len() > G64_TRACK_TABLE, "G64 header is truncated");
+        let half_tracks = data[9] as usize;
+        let mut disk = vec![0; track_offset(MAX_TRACKS + 1)];
+        let mut tracks = 0;
+        for track in 1..=MAX_TRACKS.min(half_tracks.div_ceil(2)) {
+            let entry = G64_TRACK_TABLE + (track - 1) * 2 * 4;
+            let Some(offset) = data.get(entry..entry + 4) else {
+                break;
+            };
+            let offset = u32::from_le_bytes(offset.try_into()?) as usize;
+            if offset == 0 || offset + 2 > data.len() {
+                continue;
+            }
+            let len = u16::from_le_bytes([data[offset], data[offset + 1]]) as usize;
+            let Some(gcr) = data.get(offset + 2..offset + 2 + len) else {
+                bail!("track {track} runs past the end of the file");
+            };
+            let base = track_offset(track);
+            for (sector, bytes) in decode_gcr_track(gcr, track) {
+                if sector < sectors_per_track(track) {
+                    let start = base + sector * SECTOR_SIZE;
+                    disk[start..start + SECTOR_SIZE].copy_from_slice(&bytes);
+                }
+            }
+            tracks = track;
+        }
+        ensure!(
+            tracks >= DIRECTORY_TRACK,
+            "G64 image has no directory track"
+        );
+        let tracks = if tracks > 35 { MAX_TRACKS } else { 35 };
+        disk.truncate(track_offset(tracks + 1));
+        Ok(Self { data: disk, tracks })
+    }
+
+    fn sector(&self, track: u8, sector: u8) -> Option<&[u8]> {
+        let (track, sector) = (track as usize, sector as usize);
+        if track == 0 || track > self.tracks || sector >= sectors_per_track(track) {
+            return None;
+        }
+        let start = track_offset(track) + sector * SECTOR_SIZE;
+        self.data.get(start..start + SECTOR_SIZE)
+    }
+
+    fn bam(&self) -> &[u8] {
+        self.sector(DIRECTORY_TRACK as u8, 0)
+            .expect("every image has the directory track")
+    }
+
+    fn name(&self) -> String {
+        petscii_to_string(&self.bam()[BAM_NAME..BAM_NAME + 16])
+    }
+
+    /// Follows a track/sector chain, each sector links to the next or holds the index of
+    /// its last byte
+    fn read_chain(&self, mut track: u8, mut sector: u8) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        for _ in 0..MAX_CHAIN {
+            let block = self
+                .sector(track, sector)
+                .with_context(|| format!("broken file chain at track {track} sector {sector}"))?;
+            if block[0] == 0 {
+                let last = (block[1] as usize).clamp(1, SECTOR_SIZE - 1);
+                data.extend_from_slice(&block[2..=last]);
+                return Ok(data);
+            }
+            data.extend_from_slice(&block[2..]);
+            (track, sector) = (block[0], block[1]);
+        }
+        bail!("file chain loops")
+    }
+
+    fn directory(&self) -> Vec<DirEntry> {
+        let mut entries = Vec::new();
+        let (mut track, mut sector) = (DIRECTORY_TRACK as u8, 1);
+        for _ in 0..MAX_CHAIN {
+            let Some(block) = self.sector(track, sector) else {
+                break;
+            };
+            for raw in block.chunks_exact(32) {
+                let file_type = raw[2];
+                if file_type & 0x07 == 0 && file_type & 0x80 == 0 {
+                    continue;
+                }
+                let name_end = raw[5..21]
+                    .iter()
+                    .position(|&byte| byte == PETSCII_PAD)
+                    .unwrap_or(16);
+                entries.push(DirEntry {
+                    file_type: file_type & 0x07,
+                    closed: file_type & 0x80 != 0,
+                    locked: file_type & 0x40 != 0,
+                    name: raw[5..5 + name_end].to_vec(),
+                    track: raw[3],
+                    sector: raw[4],
+                    blocks: u16::from_le_bytes([raw[30], raw[31]]),
+                });
+            }
+            if block[0] == 0 {
+                break;
+            }
+            (track, sector) = (block[0], block[1]);
+        }
+        entries
+    }
+
+    /// The first closed PRG matching `pattern`, which may use `*` and `?` like the 1541
+    fn find_program(&self, pattern: &[u8]) -> Option<DirEntry> {
+        self.directory().into_iter().find(|entry| {
+            entry.closed
+                && entry.file_type == FILE_TYPE_PRG
+                && matches_pattern(pattern, &entry.name)
+        })
+    }
+
+    /// `LOAD"$",8` turns the directory into a BASIC listing: the header with the disk
+    /// name and ID, a line per file with its size as the line number, then free blocks
+    fn directory_listing(&self) -> Vec<u8> {
+        const BASIC_START: u16 = 0x0801;
+        let bam = self.bam();
+        let mut lines: Vec<(u16, Vec<u8>)> = Vec::new();
+
+        let mut header = vec![0x12, b'"'];
+        header.extend(bam[BAM_NAME..BAM_NAME + 16].iter().map(|&byte| unpad(byte)));
+        header.extend_from_slice(b"\" ");
+        header.extend(bam[BAM_ID..BAM_ID + 2].iter().map(|&byte| unpad(byte)));
+        header.push(b' ');
+        header.extend(
+            bam[BAM_DOS_TYPE..BAM_DOS_TYPE + 2]
+                .iter()
+                .map(|&byte| unpad(byte)),
+        );
+        lines.push((0, header));
+
+        for entry in self.directory() {
+            let mut text = vec![b' '; 4usize.saturating_sub(entry.blocks.to_string().len())];
+            text.push(b'"');
+            text.extend_from_slice(&entry.name);
+            text.push(b'"');
+            text.resize(text.len() + 16 - entry.name.len(), b' ');
+            text.push(if entry.closed { b' ' } else { b'*' });
+            text.extend_from_slice(
+                FILE_TYPES
+                    .get(entry.file_type as usize)
+                    .unwrap_or(&"???")
+                    .as_bytes(),
+            );
+            if entry.locked {
+                text.push(b'<');
+            }
+            lines.push((entry.blocks, text));
+        }
+
+        let free: u16 = (1..=35usize)
+            .filter(|&track| track != DIRECTORY_TRACK)
+            .map(|track| bam[track * 4] as u16)
+            .sum();
+        lines.push((free, b"BLOCKS FREE.".to_vec()));
+
+        let mut program = BASIC_START.to_le_bytes().to_vec();
+        let mut address = BASIC_START;
+        for (number, text) in lines {
+            address += (4 + text.len() + 1) as u16;
+            program.extend_from_slice(&address.to_le_bytes());
+            program.extend_from_slice(&number.to_le_bytes());
+            program.extend_from_slice(&text);
+            program.push(0);
+        }
+        program.extend_from_slice(&[0, 0]);
+        program
+    }
+}
+
+/// Serves KERNAL LOADs from device 8 straight out of the disk image, so there is no
+/// 1541 on the serial bus: programs with their own drive code or fastloaders will not
+/// see a drive. Images with the same name apart from digits, like the disks of a
+/// multi-disk release, can be swapped in turn.
+pub struct VirtualDrive {
+    images: Vec<PathBuf>,
+    current: usize,
+    disk: Disk,
+}
+
+impl VirtualDrive {
+    pub fn new(path: &Path) -> Result<Self> {
+        let disk = Disk::open(path)?;
+        let images = disk_set(path);
+        let current = images
+            .iter()
+            .position(|image| image == path)
+            .unwrap_or_default();
+        if images.len() > 1 {
+            log::info!(
+                "Disk {} of {}, F9 inserts the next one",
+                current + 1,
+                images.len()
+            );
+        }
+        Ok(Self {
+            images,
+            current,
+            disk,
+        })
+    }
+
+    /// Hooks the drive into the machine's KERNAL LOAD
+    pub fn attach(&self, c64: &mut C64) {
+        c64.get_bpm_mut().set(LOAD_TRAP, false);
+    }
+
+    /// Inserts the next image of the set, wrapping around after the last
+    pub fn swap(&mut self) -> Result<String> {
+        let next = (self.current + 1) % self.images.len();
+        self.disk = Disk::open(&self.images[next])?;
+        self.current = next;
+        Ok(format!(
+            "disk {}/{}: {}",
+            next + 1,
+            self.images.len(),
+            self.disk.name()
+        ))
+    }
+
+    /// Index of the inserted disk in the set
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Inserts a disk of the set by index, for save states
+    pub fn restore(&mut self, index: usize) -> Result<()> {
+        if index != self.current {
+            let path = self
+                .images
+                .get(index)
+                .with_context(|| format!("the disk set has no disk {}", index + 1))?;
+            self.disk = Disk::open(path)?;
+            self.current = index;
+        }
+        Ok(())
+    }
+
+    /// Completes a LOAD the CPU stopped at and returns to its caller. Other devices are
+    /// left to the KERNAL.
+    pub fn handle_trap(&mut self, c64: &mut C64) {
+        let cpu = c64.get_cpu_mut();
+        if cpu.get_pc() != LOAD_TRAP || cpu.read(ZP_DEVICE) != DRIVE_DEVICE {
+            return;
+        }
+        let len = cpu.read(ZP_FILENAME_LEN) as u16;
+        let name_addr = u16::from_le_bytes([cpu.read(ZP_FILENAME), cpu.read(ZP_FILENAME + 1)]);
+        let name: Vec<u8> = (0..len)
+            .map(|offset| cpu.read(name_addr.wrapping_add(offset)))
+            .collect();
+        let name = strip_drive_prefix(&name).to_vec();
+
+        let file = if name == b"$" {
+            Some(self.disk.directory_listing())
+        } else {
+            self.disk
+                .find_program(&name)
+                .and_then(|entry| self.disk.read_chain(entry.track, entry.sector).ok())
+        }
+        .filter(|file| file.len() >= 2);
+
+        complete_load(cpu, &name, file);
+    }
+}
+
+/// Copies the file where the KERNAL would, or only compares it for VERIFY, and returns to
+/// the caller of LOAD
+fn complete_load(cpu: &mut dyn Cpu, name: &[u8], file: Option<Vec<u8>>) {
+    let Some(file) = file else {
+        log::warn!("LOAD \"{}\",8: file not found", petscii_to_string(name));
+        finish_load(cpu, Err(ERROR_FILE_NOT_FOUND));
+        return;
+    };
+    let verify = cpu.get_a() != 0;
+    // secondary address 0 loads to the address BASIC passed in, otherwise to the
+    // one stored in the file
+    let start = if cpu.read(ZP_SECONDARY) == 0 {
+        u16::from_le_bytes([cpu.read(ZP_LOAD_ADDR), cpu.read(ZP_LOAD_ADDR + 1)])
+    } else {
+        u16::from_le_bytes([file[0], file[1]])
+    };
+    let data = &file[2..];
+    if !verify {
+        for (offset, &byte) in data.iter().enumerate() {
+            cpu.write(start.wrapping_add(offset as u16), byte);
+        }
+    }
+    let end = start.wrapping_add(data.len() as u16);
+    log::debug!(
+        "LOAD \"{}\",8 to ${start:04X}-${end:04X}",
+        petscii_to_string(name)
+    );
+    finish_load(cpu, Ok(end));
+}
+
+/// Types `LOAD"*",8,1` once BASIC is up, the autostart then adds `RUN`
+pub struct DiskAutostart;
+
+impl Image for DiskAutostart {
+    fn mount(&mut self, c64: &mut C64) {
+        c64.get_keyboard().enqueue("LOAD\"*\",8,1\n");
+    }
+
+    fn unmount(&mut self, _c64: &mut C64) {}
+}
+
+/// Sets up the registers and zero page the way the KERNAL leaves them, then returns
+/// from the `JSR $FFD5` that started the load
+fn finish_load(cpu: &mut dyn Cpu, result: std::result::Result<u16, u8>) {
+    match result {
+        Ok(end) => {
+            let [low, high] = end.to_le_bytes();
+            cpu.write(ZP_END_ADDR, low);
+            cpu.write(ZP_END_ADDR + 1, high);
+            cpu.write(ZP_STATUS, STATUS_EOF);
+            cpu.set_x(low);
+            cpu.set_y(high);
+            cpu.set_p(cpu.get_p() & !FLAG_CARRY);
+        }
+        Err(code) => {
+            cpu.set_a(code);
+            cpu.set_p(cpu.get_p() | FLAG_CARRY);
+        }
+    }
+    let sp = cpu.get_sp();
+    let low = cpu.read(0x0100 + sp.wrapping_add(1) as u16);
+    let high = cpu.read(0x0100 + sp.wrapping_add(2) as u16);
+    cpu.set_sp(sp.wrapping_add(2));
+    cpu.set_pc(u16::from_le_bytes([low, high]).wrapping_add(1));
+}
+
+/// Disk images next to `path` whose names only differ in digits, in name order
+fn disk_set(path: &Path) -> Vec<PathBuf> {
+    let key = |path: &Path| {
+        path.file_name().map(|name| {
+            name.to_string_lossy()
+                .to_ascii_lowercase()
+                .replace(|c: char| c.is_ascii_digit(), "")
+        })
+    };
+    let own_key = key(path);
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut images: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|candidate| {
+                    candidate
+                        .extension()
+                        .and_then(|ext| ext.to_str())
+                        .is_some_and(|ext| {
+                            DISK_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())
+                        })
+                        && key(candidate) == own_key
+                })
+                .map(|candidate| {
+                    if path
+                        .parent()
+                        .is_none_or(|parent| parent.as_os_str().is_empty())
+                    {
+                        PathBuf::from(candidate.file_name().unwrap_or_default())
+                    } else {
+                        candidate
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if !images.iter().any(|image| image == path) {
+        images.push(path.to_path_buf());
+    }
+    images.sort();
+    images
+}
+
+/// `0:NAME` and `:NAME` address drive 0, which is the only one
+fn strip_drive_prefix(name: &[u8]) -> &[u8] {
+    match name.iter().position(|&byte| byte == b':') {
+        Some(colon) if colon <= 2 => &name[colon + 1..],
+        _ => name,
+    }
+}
+
+fn matches_pattern(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', _)) => true,
+        Some((&expected, rest)) => match name.split_first() {
+            Some((&actual, name_rest)) => {
+                (expected == b'?' || expected == actual) && matches_pattern(rest, name_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+fn unpad(byte: u8) -> u8 {
+    if byte == PETSCII_PAD { b' ' } else { byte }
+}
+
+fn petscii_to_string(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&byte| match unpad(byte) {
+            byte @ 0x20..=0x5F => byte as char,
+            byte @ 0xC1..=0xDA => (byte - 0x80) as char,
+            _ => '?',
+        })
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+/// Finds the sector header and data blocks after each sync mark, reading the bit stream
+/// circularly so a block may wrap past the end of the track
+fn decode_gcr_track(gcr: &[u8], track: usize) -> Vec<(usize, [u8; SECTOR_SIZE])> {
+    let total_bits = gcr.len() * 8;
+    let bit = |index: usize| (gcr[(index / 8) % gcr.len()] >> (7 - index % 8)) & 1;
+    let read_bytes = |start: usize, count: usize| -> Option<Vec<u8>> {
+        let mut out = Vec::with_capacity(count * 4 / 5);
+        let mut nibbles = Vec::with_capacity(count * 8 / 5);
+        for group in 0..count * 8 / 5 {
+            let code = (0..5).fold(0u8, |code, offset| {
+                (code << 1) | bit(start + group * 5 + offset)
+            });
+            nibbles.push(GCR_ENCODE.iter().position(|&gcr| gcr == code)? as u8);
+        }
+        for pair in nibbles.chunks_exact(2) {
+            out.push((pair[0] << 4) | pair[1]);
+        }
+        Some(out)
+    };
+
+    let mut sectors = Vec::new();
+    if total_bits == 0 {
+        return sectors;
+    }
+    let mut pending_sector = None;
+    let mut ones = 0;
+    for index in 0..total_bits + GCR_SYNC_BITS {
+        if bit(index) == 1 {
+            ones += 1;
+            continue;
+        }
+        let synced = ones >= GCR_SYNC_BITS;
+        ones = 0;
+        if !synced {
+            continue;
+        }
+        // the block starts at the first zero bit after the sync mark
+        let Some(id) = read_bytes(index, 5).and_then(|bytes| bytes.first().copied()) else {
+            continue;
+        };
+        match id {
+            BLOCK_HEADER => {
+                pending_sector = read_bytes(index, GCR_HEADER_BYTES)
+                    .filter(|header| header[3] as usize == track)
+                    .map(|header| header[2] as usize);
+            }
+            BLOCK_DATA => {
+                let Some(sector) = pending_sector.take() else {
+                    continue;
+                };
+                if let Some(block) = read_bytes(index, GCR_DATA_BYTES) {
+                    let data: [u8; SECTOR_SIZE] = block[1..=SECTOR_SIZE]
+                        .try_into()
+                        .expect("block has 256 bytes");
+                    let checksum = data.iter().fold(0, |sum, &byte| sum ^ byte);
+                    if checksum == block[SECTOR_SIZE + 1] {
+                        sectors.push((sector, data));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    sectors
+}
@@ -0,0 +1,573 @@
+use std::collections::HashMap;
+
+use font8x8::legacy::BASIC_LEGACY;
+use sdl2::keyboard::{Keycode, Mod, Scancode};
+use zinc64_emu::device::keyboard::{Key, KeyEvent};
+use zinc64_emu::system::C64;
+
+use crate::config::KeyboardMapping;
+use crate::controller::{ControllerManager, VirtualButton};
+
+/// Turns host key presses into C64 keys, either by where the key sits or by what it types
+pub struct HostKeyboard {
+    mapping: KeyboardMapping,
+    /// What each held host key pressed, so letting go of it releases the same C64 keys
+    /// even if the mapping or the shift state changed in between
+    held: HashMap<Scancode, KeyEvent>,
+}
+
+impl HostKeyboard {
+    pub fn new(mapping: KeyboardMapping) -> Self {
+        Self {
+            mapping,
+            held: HashMap::new(),
+        }
+    }
+
+    pub fn toggle_mapping(&mut self, c64: &mut C64) -> KeyboardMapping {
+        self.release_all(c64);
+        self.mapping = match self.mapping {
+            KeyboardMapping::Symbolic => KeyboardMapping::Positional,
+            KeyboardMapping::Positional => KeyboardMapping::Symbolic,
+        };
+        self.mapping
+    }
+
+    pub fn key_down(&mut self, c64: &mut C64, keycode: Keycode, scancode: Scancode, keymod: Mod) {
+        let event = match self.mapping {
+            KeyboardMapping::Positional => positional_key(scancode),
+            KeyboardMapping::Symbolic => {
+                let shifted = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                symbolic_key(keycode, shifted)
+            }
+        };
+        if let Some(event) = event {
+            c64.get_keyboard().on_key_down(event);
+            self.held.insert(scancode, event);
+        }
+    }
+
+    pub fn key_up(&mut self, c64: &mut C64, scancode: Scancode) {
+        if let Some(event) = self.held.remove(&scancode) {
+            c64.get_keyboard().on_key_up(event);
+        }
+    }
+
+    pub fn release_all(&mut self, c64: &mut C64) {
+        for (_, event) in self.held.drain() {
+            c64.get_keyboard().on_key_up(event);
+        }
+    }
+}
+
+/// The C64 key in the same spot on a US keyboard. Keys the PC lacks sit nearby: `£` on
+/// Insert, `↑` on Delete, CLR/HOME on Home, CTRL on Tab and C= on the left Ctrl.
+fn positional_key(scancode: Scancode) -> Option<KeyEvent> {
+    use Key::*;
+    let key = match scancode {
+        Scancode::Grave => Left,
+        Scancode::Minus => Plus,
+        Scancode::Equals => Minus,
+        Scancode::Insert => Dollar,
+        Scancode::Delete => Caret,
+        Scancode::Tab => Ctrl,
+        Scancode::LeftBracket => At,
+        Scancode::RightBracket => Asterisk,
+        Scancode::Semicolon => Colon,
+        Scancode::Apostrophe => Semicolon,
+        Scancode::Backslash => Equals,
+        Scancode::LCtrl => LGui,
+        Scancode::RCtrl => Ctrl,
+        Scancode::LShift => LShift,
+        Scancode::RShift => RShift,
+        _ => {
+            // by name, which is the key's US label whatever the active layout is
+            let keycode = Keycode::from_name(scancode.name())?;
+            return letter_or_digit(keycode)
+                .or_else(|| symbol_key(keycode))
+                .map(KeyEvent::new)
+                .or_else(|| control_key(keycode));
+        }
+    };
+    Some(KeyEvent::new(key))
+}
+
+/// The C64 key that types the same character, shifting or unshifting it where the two
+/// keyboards disagree, like `"` on Shift+2 or `:` without shift
+fn symbolic_key(keycode: Keycode, shifted: bool) -> Option<KeyEvent> {
+    use Key::*;
+    let shift = |key| KeyEvent::with_mod(key, LShift);
+    let unshift = KeyEvent::with_disabled_shift;
+    let event = match (keycode, shifted) {
+        (Keycode::Num2, true) => unshift(At),
+        (Keycode::Num6, true) => unshift(Caret),
+        (Keycode::Num7, true) => KeyEvent::new(Num6),
+        (Keycode::Num8, true) => unshift(Asterisk),
+        (Keycode::Num9, true) => KeyEvent::new(Num8),
+        (Keycode::Num0, true) => KeyEvent::new(Num9),
+        (Keycode::Minus, true) => unshift(Left),
+        (Keycode::Equals, true) => unshift(Plus),
+        (Keycode::Semicolon, true) => unshift(Colon),
+        (Keycode::Quote, false) => shift(Num7),
+        (Keycode::Quote, true) => KeyEvent::new(Num2),
+        (Keycode::LeftBracket, false) => shift(Colon),
+        (Keycode::RightBracket, false) => shift(Semicolon),
+        (Keycode::Backslash, false) => KeyEvent::new(Dollar),
+        (Keycode::Backquote, false) => KeyEvent::new(Left),
+        (Keycode::Asterisk | Keycode::KpMultiply, _) => unshift(Asterisk),
+        (Keycode::Plus | Keycode::KpPlus, _) => unshift(Plus),
+        (Keycode::KpMinus, _) => KeyEvent::new(Minus),
+        (Keycode::KpDivide, _) => KeyEvent::new(Slash),
+        (Keycode::KpPeriod, _) => KeyEvent::new(Period),
+        (Keycode::LCtrl | Keycode::RCtrl, _) => KeyEvent::new(Ctrl),
+        (Keycode::LAlt, _) => KeyEvent::new(LGui),
+        (Keycode::LShift, _) => KeyEvent::new(LShift),
+        (Keycode::RShift, _) => KeyEvent::new(RShift),
+        _ => {
+            return letter_or_digit(keycode)
+                .or_else(|| symbol_key(keycode))
+                .map(KeyEvent::new)
+                .or_else(|| control_key(keycode));
+        }
+    };
+    Some(event)
+}
+
+fn letter_or_digit(keycode: Keycode) -> Option<Key> {
+    use Key::*;
+    Some(match keycode {
+        Keycode::A => A,
+        Keycode::B => B,
+        Keycode::C => C,
+        Keycode::D => D,
+        Keycode::E => E,
+        Keycode::F => F,
+        Keycode::G => G,
+        Keycode::H => H,
+        Keycode::I => I,
+        Keycode::J => J,
+        Keycode::K => K,
+        Keycode::L => L,
+        Keycode::M => M,
+        Keycode::N => N,
+        Keycode::O => O,
+        Keycode::P => P,
+        Keycode::Q => Q,
+        Keycode::R => R,
+        Keycode::S => S,
+        Keycode::T => T,
+        Keycode::U => U,
+        Keycode::V => V,
+        Keycode::W => W,
+        Keycode::X => X,
+        Keycode::Y => Y,
+        Keycode::Z => Z,
+        Keycode::Num0 | Keycode::Kp0 => Num0,
+        Keycode::Num1 | Keycode::Kp1 => Num1,
+        Keycode::Num2 | Keycode::Kp2 => Num2,
+        Keycode::Num3 | Keycode::Kp3 => Num3,
+        Keycode::Num4 | Keycode::Kp4 => Num4,
+        Keycode::Num5 | Keycode::Kp5 => Num5,
+        Keycode::Num6 | Keycode::Kp6 => Num6,
+        Keycode::Num7 | Keycode::Kp7 => Num7,
+        Keycode::Num8 | Keycode::Kp8 => Num8,
+        Keycode::Num9 | Keycode::Kp9 => Num9,
+        _ => return None,
+    })
+}
+
+/// Keys that type the same on both keyboards
+fn symbol_key(keycode: Keycode) -> Option<Key> {
+    use Key::*;
+    Some(match keycode {
+        Keycode::Comma => Comma,
+        Keycode::Period => Period,
+        Keycode::Slash => Slash,
+        Keycode::Minus => Minus,
+        Keycode::Equals => Equals,
+        Keycode::Semicolon => Semicolon,
+        _ => return None,
+    })
+}
+
+/// Keys that don't type anything, the same in both mappings. Up and left are the
+/// shifted cursor keys on the C64.
+fn control_key(keycode: Keycode) -> Option<KeyEvent> {
+    use Key::*;
+    let key = match keycode {
+        Keycode::Space => Space,
+        Keycode::Return | Keycode::KpEnter => Return,
+        Keycode::Backspace => Backspace,
+        Keycode::Escape => RunStop,
+        Keycode::Home => Home,
+        Keycode::F1 => F1,
+        Keycode::F3 => F3,
+        Keycode::F5 => F5,
+        Keycode::F7 => F7,
+        Keycode::Down => CrsrDown,
+        Keycode::Right => CrsrRight,
+        Keycode::Up => return Some(KeyEvent::with_mod(CrsrDown, LShift)),
+        Keycode::Left => return Some(KeyEvent::with_mod(CrsrRight, LShift)),
+        _ => return None,
+    };
+    Some(KeyEvent::new(key))
+}
+
+/// The C64 keyboard row by row, cursor up and left are shifted cursor down and right
+const LAYOUT: &[&[(&str, Key)]] = &[
+    &[
+        ("<-", Key::Left),
+        ("1", Key::Num1),
+        ("2", Key::Num2),
+        ("3", Key::Num3),
+        ("4", Key::Num4),
+        ("5", Key::Num5),
+        ("6", Key::Num6),
+        ("7", Key::Num7),
+        ("8", Key::Num8),
+        ("9", Key::Num9),
+        ("0", Key::Num0),
+        ("+", Key::Plus),
+        ("-", Key::Minus),
+        ("PND", Key::Dollar),
+        ("HOME", Key::Home),
+        ("DEL", Key::Backspace),
+    ],
+    &[
+        ("CTRL", Key::Ctrl),
+        ("Q", Key::Q),
+        ("W", Key::W),
+        ("E", Key::E),
+        ("R", Key::R),
+        ("T", Key::T),
+        ("Y", Key::Y),
+        ("U", Key::U),
+        ("I", Key::I),
+        ("O", Key::O),
+        ("P", Key::P),
+        ("@", Key::At),
+        ("*", Key::Asterisk),
+        ("^", Key::Caret),
+        ("F1", Key::F1),
+        ("F3", Key::F3),
+    ],
+    &[
+        ("STOP", Key::RunStop),
+        ("A", Key::A),
+        ("S", Key::S),
+        ("D", Key::D),
+        ("F", Key::F),
+        ("G", Key::G),
+        ("H", Key::H),
+        ("J", Key::J),
+        ("K", Key::K),
+        ("L", Key::L),
+        (":", Key::Colon),
+        (";", Key::Semicolon),
+        ("=", Key::Equals),
+        ("RETURN", Key::Return),
+        ("F5", Key::F5),
+    ],
+    &[
+        ("C=", Key::LGui),
+        ("SHIFT", Key::LShift),
+        ("Z", Key::Z),
+        ("X", Key::X),
+        ("C", Key::C),
+        ("V", Key::V),
+        ("B", Key::B),
+        ("N", Key::N),
+        ("M", Key::M),
+        (",", Key::Comma),
+        (".", Key::Period),
+        ("/", Key::Slash),
+        ("DOWN", Key::CrsrDown),
+        ("RIGHT", Key::CrsrRight),
+        ("F7", Key::F7),
+    ],
+    &[("SPACE", Key::Space)],
+];
+
+/// Modifiers on the virtual keyboard latch and apply to the next key
+const MODIFIERS: [Key; 3] = [Key::LShift, Key::LGui, Key::Ctrl];
+/// Frames a direction is held before the cursor starts repeating, and between repeats
+const REPEAT_DELAY: u32 = 18;
+const REPEAT_RATE: u32 = 4;
+const KEY_HEIGHT: usize = 12;
+const KEY_GAP: usize = 2;
+const PANEL_COLOR: u32 = 0xFF10_1018;
+const KEY_COLOR: u32 = 0xFF40_4050;
+const LATCHED_COLOR: u32 = 0xFF6C_5EB5;
+const CURSOR_COLOR: u32 = 0xFFB8_C76F;
+const TEXT_COLOR: u32 = 0xFFFF_FFFF;
+const CURSOR_TEXT_COLOR: u32 = 0xFF10_1018;
+
+/// An on-screen C64 keyboard driven from a pad, for setups without a real keyboard.
+/// The d-pad moves, A presses the key for as long as it is held, B deletes, Y latches
+/// shift and Start is RETURN.
+#[derive(Default)]
+pub struct VirtualKeyboard {
+    open: bool,
+    row: usize,
+    column: usize,
+    latched: Vec<Key>,
+    /// The key A is holding down, with the modifiers that went with it
+    pressed: Option<(Key, Vec<Key>)>,
+    /// Shortcut keys held by B and Start
+    shortcuts: Vec<(VirtualButton, Key)>,
+    previous: Vec<VirtualButton>,
+    held_frames: u32,
+}
+
+impl VirtualKeyboard {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn toggle(&mut self, c64: &mut C64) {
+        self.release(c64);
+        self.latched.clear();
+        self.open = !self.open;
+    }
+
+    /// Reads the pad once per frame while the keyboard is open
+    pub fn update(&mut self, c64: &mut C64, controllers: &ControllerManager) {
+        let buttons: Vec<VirtualButton> = [
+            VirtualButton::Up,
+            VirtualButton::Down,
+            VirtualButton::Left,
+            VirtualButton::Right,
+            VirtualButton::A,
+            VirtualButton::B,
+            VirtualButton::Y,
+            VirtualButton::Start,
+        ]
+        .into_iter()
+        .filter(|button| controllers.is_pressed(*button))
+        .collect();
+        let previous = std::mem::take(&mut self.previous);
+        let pressed = |button| buttons.contains(&button);
+        let newly = |button| pressed(button) && !previous.contains(&button);
+
+        let direction = [
+            VirtualButton::Up,
+            VirtualButton::Down,
+            VirtualButton::Left,
+            VirtualButton::Right,
+        ]
+        .into_iter()
+        .find(|button| pressed(*button));
+        let step = match direction {
+            Some(button) if newly(button) => {
+                self.held_frames = 0;
+                Some(button)
+            }
+            Some(button) => {
+                self.held_frames += 1;
+                let repeat = self.held_frames >= REPEAT_DELAY
+                    && (self.held_frames - REPEAT_DELAY).is_multiple_of(REPEAT_RATE);
+                repeat.then_some(button)
+            }
+            None => None,
+        };
+        if let Some(button) = step {
+            self.move_cursor(button);
+        }
+
+        if newly(VirtualButton::A) {
+            self.press_selected(c64);
+        } else if !pressed(VirtualButton::A)
+            && let Some((key, modifiers)) = self.pressed.take()
+        {
+            let keyboard = c64.get_keyboard();
+            keyboard.set_key(key, false);
+            for modifier in modifiers {
+                keyboard.set_key(modifier, false);
+            }
+        }
+        if newly(VirtualButton::Y) {
+            self.toggle_latch(Key::LShift);
+        }
+        for (button, key) in [
+            (VirtualButton::B, Key::Backspace),
+            (VirtualButton::Start, Key::Return),
+        ] {
+            if newly(button) {
+                c64.get_keyboard().set_key(key, true);
+                self.shortcuts.push((button, key));
+            }
+        }
+        self.shortcuts.retain(|&(button, key)| {
+            let held = pressed(button);
+            if !held {
+                c64.get_keyboard().set_key(key, false);
+            }
+            held
+        });
+        self.previous = buttons;
+    }
+
+    fn move_cursor(&mut self, button: VirtualButton) {
+        match button {
+            VirtualButton::Up => self.row = (self.row + LAYOUT.len() - 1) % LAYOUT.len(),
+            VirtualButton::Down => self.row = (self.row + 1) % LAYOUT.len(),
+            VirtualButton::Left => {
+                let len = LAYOUT[self.row].len();
+                self.column = (self.column.min(len - 1) + len - 1) % len;
+            }
+            VirtualButton::Right => {
+                let len = LAYOUT[self.row].len();
+                self.column = (self.column.min(len - 1) + 1) % len;
+            }
+            _ => {}
+        }
+    }
+
+    fn selected(&self) -> Key {
+        let row = LAYOUT[self.row];
+        row[self.column.min(row.len() - 1)].1
+    }
+
+    fn press_selected(&mut self, c64: &mut C64) {
+        let key = self.selected();
+        if MODIFIERS.iter().any(|modifier| same_key(*modifier, key)) {
+            self.toggle_latch(key);
+            return;
+        }
+        let modifiers = std::mem::take(&mut self.latched);
+        let keyboard = c64.get_keyboard();
+        for modifier in &modifiers {
+            keyboard.set_key(*modifier, true);
+        }
+        keyboard.set_key(key, true);
+        self.pressed = Some((key, modifiers));
+    }
+
+    fn toggle_latch(&mut self, key: Key) {
+        if let Some(index) = self
+            .latched
+            .iter()
+            .position(|latched| same_key(*latched, key))
+        {
+            self.latched.remove(index);
+        } else {
+            self.latched.push(key);
+        }
+    }
+
+    fn release(&mut self, c64: &mut C64) {
+        let keyboard = c64.get_keyboard();
+        if let Some((key, modifiers)) = self.pressed.take() {
+            keyboard.set_key(key, false);
+            for modifier in modifiers {
+                keyboard.set_key(modifier, false);
+            }
+        }
+        for (_, key) in self.shortcuts.drain(..) {
+            keyboard.set_key(key, false);
+        }
+    }
+
+    /// Draws the keyboard across the bottom of an ARGB frame
+    pub fn draw(&self, pixels: &mut [u32], width: usize, height: usize) {
+        let panel_height = LAYOUT.len() * (KEY_HEIGHT + KEY_GAP) + KEY_GAP;
+        let top = height.saturating_sub(panel_height);
+        fill_rect(pixels, width, 0, top, width, panel_height, PANEL_COLOR);
+        for (row_index, row) in LAYOUT.iter().enumerate() {
+            let y = top + KEY_GAP + row_index * (KEY_HEIGHT + KEY_GAP);
+            let row_width: usize = row
+                .iter()
+                .map(|(label, _)| key_width(label) + KEY_GAP)
+                .sum();
+            let mut x = width.saturating_sub(row_width) / 2;
+            for (column, (label, key)) in row.iter().enumerate() {
+                let key_w = key_width(label);
+                let selected = row_index == self.row && column == self.column.min(row.len() - 1);
+                let (fill, text) = if selected {
+                    (CURSOR_COLOR, CURSOR_TEXT_COLOR)
+                } else if self.latched.iter().any(|latched| same_key(*latched, *key)) {
+                    (LATCHED_COLOR, TEXT_COLOR)
+                } else {
+                    (KEY_COLOR, TEXT_COLOR)
+                };
+                fill_rect(pixels, width, x, y, key_w, KEY_HEIGHT, fill);
+                draw_text(pixels, width, x + 3, y + 2, label, text);
+                x += key_w + KEY_GAP;
+            }
+        }
+    }
+}
+
+/// zinc64's `Key` has no `PartialEq`
+fn same_key(a: Key, b: Key) -> bool {
+    a as u8 == b as u8
+}
+
+fn key_width(label: &str) -> usize {
+    // the space bar gets a wide key like the real one
+    if label == "SPACE" {
+        return 160;
+    }
+    label.len() * 8 + 6
+}
+
+pub fn fill_rect(
+    pixels: &mut [u32],
+    frame_width: usize,
+    start_x: usize,
+    start_y: usize,
+    width: usize,
+    height: usize,
+    color: u32,
+) {
+    // script shapes can be anywhere, clip them to the frame before indexing
+    let rows = pixels.len().checked_div(frame_width).unwrap_or(0);
+    let max_x = start_x.saturating_add(width).min(frame_width);
+    let max_y = start_y.saturating_add(height).min(rows);
+    for y in start_y..max_y {
+        let row = y * frame_width;
+        for x in start_x..max_x {
+            pixels[row + x] = color;
+        }
+    }
+}
+
+pub fn draw_text(
+    pixels: &mut [u32],
+    frame_width: usize,
+    start_x: usize,
+    start_y: usize,
+    text: &str,
+    color: u32,
+) {
+    let rows = pixels.len().checked_div(frame_width).unwrap_or(0);
+    for (idx, ch) in text.chars().enumerate() {
+        let glyph = BASIC_LEGACY
+            .get(ch as usize)
+            .copied()
+            .unwrap_or(BASIC_LEGACY['?' as usize]);
+        let Some(left) = idx
+            .checked_mul(8)
+            .and_then(|offset| start_x.checked_add(offset))
+            .filter(|&left| left < frame_width)
+        else {
+            break;
+        };
+        for (row, row_bits) in glyph.iter().enumerate() {
+            let y = start_y.saturating_add(row);
+            if y >= rows {
+                break;
+            }
+            let offset = y * frame_width;
+            for col in 0..8 {
+                let x = left + col;
+                if (row_bits >> col) & 1 == 0 || x >= frame_width {
+                    continue;
+                }
+                if let Some(pixel) = pixels.get_mut(offset + x) {
+                    *pixel = color;
+                }
+            }
+        }
+    }
+}
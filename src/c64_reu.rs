@@ -0,0 +1,276 @@
+use anyhow::{Result, ensure};
+use zinc64_core::{AddressableFaded, IrqLine, Ram, Shared};
+
+/// The REU's registers at $DF00, repeated every 32 bytes up to $DFFF
+const IO_AREA: std::ops::RangeInclusive<u16> = 0xDF00..=0xDFFF;
+const REGISTER_MASK: u16 = 0x1F;
+/// IRQ line bits 0-2 belong to the CIAs and the VIC
+const IRQ_SOURCE: usize = 3;
+
+const STATUS_IRQ: u8 = 0x80;
+const STATUS_END_OF_BLOCK: u8 = 0x40;
+const STATUS_VERIFY_ERROR: u8 = 0x20;
+/// Set by the 1764 and 1750 with their 256 Kbit chips, clear on the 1700
+const STATUS_LARGE_CHIPS: u8 = 0x10;
+const COMMAND_EXECUTE: u8 = 0x80;
+const COMMAND_AUTOLOAD: u8 = 0x20;
+const COMMAND_FF00_DISABLED: u8 = 0x10;
+const IRQ_ENABLE: u8 = 0x80;
+const CONTROL_FIX_C64: u8 = 0x80;
+const CONTROL_FIX_REU: u8 = 0x40;
+/// Status, command, addresses, length, interrupt mask, address control and the
+/// autoload copies of the addresses and length
+pub const REGISTER_BYTES: usize = 18;
+
+#[derive(Clone, Copy)]
+enum Transfer {
+    Stash,
+    Fetch,
+    Swap,
+    Verify,
+}
+
+/// A Commodore RAM Expansion Unit in the expansion port. Transfers run all at once
+/// against the C64's RAM, so DMA into the I/O area and the cycles the CPU loses while
+/// the REU has the bus are not emulated.
+pub struct Reu {
+    memory: Vec<u8>,
+    status: u8,
+    command: u8,
+    c64_address: u16,
+    reu_address: u32,
+    length: u16,
+    irq_mask: u8,
+    address_control: u8,
+    /// Written along with the registers, autoload puts them back after a transfer
+    shadow: (u16, u32, u16),
+    ram: Option<Shared<Ram>>,
+    irq_line: Option<Shared<IrqLine>>,
+}
+
+impl Reu {
+    /// `size` is a power of two, from 128 KiB to 16 MiB
+    pub fn new(size: usize) -> Self {
+        Self {
+            memory: vec![0; size],
+            status: if size > 128 * 1024 {
+                STATUS_LARGE_CHIPS
+            } else {
+                0
+            },
+            command: COMMAND_FF00_DISABLED,
+            c64_address: 0,
+            reu_address: 0,
+            length: 0xFFFF,
+            irq_mask: 0,
+            address_control: 0,
+            shadow: (0, 0, 0xFFFF),
+            ram: None,
+            irq_line: None,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// Gives the REU the bus it transfers over
+    pub fn connect(&mut self, ram: Shared<Ram>, irq_line: Shared<IrqLine>) {
+        self.ram = Some(ram);
+        self.irq_line = Some(irq_line);
+    }
+
+    fn read_register(&mut self, reg: u8) -> u8 {
+        match reg {
+            0x00 => {
+                let status = self.status;
+                self.status &= !(STATUS_IRQ | STATUS_END_OF_BLOCK | STATUS_VERIFY_ERROR);
+                self.update_irq();
+                status
+            }
+            0x01 => self.command,
+            0x02 => self.c64_address as u8,
+            0x03 => (self.c64_address >> 8) as u8,
+            0x04 => self.reu_address as u8,
+            0x05 => (self.reu_address >> 8) as u8,
+            0x06 => (self.reu_address >> 16) as u8,
+            0x07 => self.length as u8,
+            0x08 => (self.length >> 8) as u8,
+            0x09 => self.irq_mask | 0x1F,
+            0x0A => self.address_control | 0x3F,
+            _ => 0xFF,
+        }
+    }
+
+    fn write_register(&mut self, reg: u8, value: u8) {
+        let set_low = |word: u16| (word & 0xFF00) | value as u16;
+        let set_high = |word: u16| (word & 0x00FF) | ((value as u16) << 8);
+        match reg {
+            0x01 => {
+                self.command = value;
+                // a transfer waiting for a write to $FF00 starts right away, the write
+                // follows within a few instructions in practice
+                if value & COMMAND_EXECUTE != 0 {
+                    self.execute();
+                }
+            }
+            0x02 => self.c64_address = set_low(self.c64_address),
+            0x03 => self.c64_address = set_high(self.c64_address),
+            0x04 => self.reu_address = (self.reu_address & 0xFF_FF00) | value as u32,
+            0x05 => self.reu_address = (self.reu_address & 0xFF_00FF) | ((value as u32) << 8),
+            0x06 => self.reu_address = (self.reu_address & 0x00_FFFF) | ((value as u32) << 16),
+            0x07 => self.length = set_low(self.length),
+            0x08 => self.length = set_high(self.length),
+            0x09 => {
+                self.irq_mask = value & 0xE0;
+                self.update_irq();
+            }
+            0x0A => self.address_control = value & 0xC0,
+            _ => {}
+        }
+        if (0x02..=0x08).contains(&reg) {
+            self.shadow = (self.c64_address, self.reu_address, self.length);
+        }
+    }
+
+    fn execute(&mut self) {
+        let Some(ram) = self.ram.clone() else {
+            return;
+        };
+        let mut ram = ram.borrow_mut();
+        let transfer = match self.command & 0x03 {
+            0 => Transfer::Stash,
+            1 => Transfer::Fetch,
+            2 => Transfer::Swap,
+            _ => Transfer::Verify,
+        };
+        let size_mask = self.memory.len() - 1;
+        let mut remaining = if self.length == 0 {
+            0x10000
+        } else {
+            self.length as u32
+        };
+        let (mut c64_address, mut reu_address) = (self.c64_address, self.reu_address);
+        loop {
+            let reu_index = reu_address as usize & size_mask;
+            let byte = ram.read(c64_address);
+            let mismatch = match transfer {
+                Transfer::Stash => {
+                    self.memory[reu_index] = byte;
+                    false
+                }
+                Transfer::Fetch => {
+                    ram.write(c64_address, self.memory[reu_index]);
+                    false
+                }
+                Transfer::Swap => {
+                    ram.write(c64_address, self.memory[reu_index]);
+                    self.memory[reu_index] = byte;
+                    false
+                }
+                Transfer::Verify => self.memory[reu_index] != byte,
+            };
+            if self.address_control & CONTROL_FIX_C64 == 0 {
+                c64_address = c64_address.wrapping_add(1);
+            }
+            if self.address_control & CONTROL_FIX_REU == 0 {
+                reu_address = (reu_address + 1) & 0xFF_FFFF;
+            }
+            if mismatch {
+                self.status |= STATUS_VERIFY_ERROR;
+                remaining -= 1;
+                break;
+            }
+            if remaining == 1 {
+                self.status |= STATUS_END_OF_BLOCK;
+                break;
+            }
+            remaining -= 1;
+        }
+
+        if self.command & COMMAND_AUTOLOAD != 0 {
+            (self.c64_address, self.reu_address, self.length) = self.shadow;
+        } else {
+            self.c64_address = c64_address;
+            self.reu_address = reu_address;
+            self.length = remaining.max(1) as u16;
+        }
+        self.command = (self.command & !COMMAND_EXECUTE) | COMMAND_FF00_DISABLED;
+        self.update_irq();
+    }
+
+    fn update_irq(&mut self) {
+        let pending = self.irq_mask & IRQ_ENABLE != 0
+            && self.irq_mask & self.status & (STATUS_END_OF_BLOCK | STATUS_VERIFY_ERROR) != 0;
+        if pending {
+            self.status |= STATUS_IRQ;
+        }
+        if let Some(irq_line) = &self.irq_line {
+            irq_line.borrow_mut().set_low(IRQ_SOURCE, pending);
+        }
+    }
+
+    /// Registers followed by the expansion memory, for save states
+    pub fn save(&self, out: &mut Vec<u8>) {
+        let (c64_address, reu_address, length) = self.shadow;
+        out.extend_from_slice(&[self.status, self.command]);
+        out.extend_from_slice(&self.c64_address.to_le_bytes());
+        out.extend_from_slice(&self.reu_address.to_le_bytes()[..3]);
+        out.extend_from_slice(&self.length.to_le_bytes());
+        out.extend_from_slice(&[self.irq_mask, self.address_control]);
+        out.extend_from_slice(&c64_address.to_le_bytes());
+        out.extend_from_slice(&reu_address.to_le_bytes()[..3]);
+        out.extend_from_slice(&length.to_le_bytes());
+        out.extend_from_slice(&self.memory);
+    }
+
+    pub fn load(&mut self, state: &[u8]) -> Result<()> {
+        ensure!(
+            state.len() == REGISTER_BYTES + self.memory.len(),
+            "the state was saved with a different REU size"
+        );
+        let word = |at: usize| u16::from_le_bytes([state[at], state[at + 1]]);
+        let address = |at: usize| u32::from_le_bytes([state[at], state[at + 1], state[at + 2], 0]);
+        self.status = state[0];
+        self.command = state[1];
+        self.c64_address = word(2);
+        self.reu_address = address(4);
+        self.length = word(7);
+        self.irq_mask = state[9];
+        self.address_control = state[10];
+        self.shadow = (word(11), address(13), word(16));
+        self.memory.copy_from_slice(&state[REGISTER_BYTES..]);
+        self.update_irq();
+        Ok(())
+    }
+}
+
+/// Puts the REU's registers in front of whatever else is in the expansion port
+pub struct ReuPort {
+    pub port: Shared<dyn AddressableFaded>,
+    pub reu: Shared<Reu>,
+}
+
+impl AddressableFaded for ReuPort {
+    fn read(&mut self, address: u16) -> Option<u8> {
+        if IO_AREA.contains(&address) {
+            Some(
+                self.reu
+                    .borrow_mut()
+                    .read_register((address & REGISTER_MASK) as u8),
+            )
+        } else {
+            self.port.borrow_mut().read(address)
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if IO_AREA.contains(&address) {
+            self.reu
+                .borrow_mut()
+                .write_register((address & REGISTER_MASK) as u8, value);
+        } else {
+            self.port.borrow_mut().write(address, value);
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use anyhow::{Context, Result};
+use mlua::{Function, Lua, Table};
+use zinc64_core::{Ram, Shared};
+
+use crate::c64_keyboard::{draw_text, fill_rect};
+
+const DEFAULT_COLOR: u32 = 0xFF_FFFF;
+
+/// A Lua script driving the C64 between frames, loaded with `--script`. Scripts see:
+///
+/// - `emu.on_frame(fn)`: calls `fn(frame)` after every emulated frame
+/// - `emu.frame_count()`: frames emulated so far
+/// - `memory.read(address)` / `memory.write(address, value)`: the 64 KiB of RAM
+/// - `joypad.set{up=, down=, left=, right=, fire=}`: holds or releases joystick
+///   directions for the next frame, whatever the pad says; buttons left out follow the pad
+/// - `gui.text(x, y, text [, color])` / `gui.box(x, y, w, h [, color])`: drawn over the
+///   frame until the next callback runs, colors are `0xRRGGBB`
+/// - `savestate.save(slot)` / `savestate.load(slot)`: the F2/F4 save state slots, taken
+///   once the callbacks return
+pub struct Script {
+    /// Owns the callbacks and the script's globals
+    _lua: Lua,
+    state: Rc<RefCell<ScriptState>>,
+}
+
+#[derive(Default)]
+struct ScriptState {
+    callbacks: Vec<Function>,
+    frame: u32,
+    joystick: Option<JoystickInput>,
+    shapes: Vec<Shape>,
+    state_request: Option<StateRequest>,
+}
+
+/// Joystick directions and fire a script set, `None` leaves one to the pad
+#[derive(Default, Clone, Copy)]
+pub struct JoystickInput {
+    pub up: Option<bool>,
+    pub down: Option<bool>,
+    pub left: Option<bool>,
+    pub right: Option<bool>,
+    pub fire: Option<bool>,
+}
+
+#[derive(Clone, Copy)]
+pub enum StateRequest {
+    Save(u32),
+    Load(u32),
+}
+
+enum Shape {
+    Text {
+        x: usize,
+        y: usize,
+        text: String,
+        color: u32,
+    },
+    Box {
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        color: u32,
+    },
+}
+
+impl Script {
+    /// Runs the script's main chunk, which registers its callbacks
+    pub fn load(path: &Path, ram: Shared<Ram>) -> Result<Self> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let lua = Lua::new();
+        let state = Rc::new(RefCell::new(ScriptState::default()));
+        register_api(&lua, &state, ram).context("failed to set up the Lua API")?;
+        lua.load(source)
+            .set_name(format!("@{}", path.display()))
+            .exec()
+            .with_context(|| format!("failed to run {}", path.display()))?;
+        log::info!(
+            "Loaded {} with {} frame callbacks",
+            path.display(),
+            state.borrow().callbacks.len()
+        );
+        Ok(Self { _lua: lua, state })
+    }
+
+    /// Calls the frame callbacks after `frame` was emulated
+    pub fn run_frame(&mut self, frame: u32) -> Result<()> {
+        let callbacks = {
+            let mut state = self.state.borrow_mut();
+            state.frame = frame;
+            state.shapes.clear();
+            state.callbacks.clone()
+        };
+        for callback in callbacks {
+            callback
+                .call::<()>(frame)
+                .context("Lua frame callback failed")?;
+        }
+        Ok(())
+    }
+
+    /// Input the script set for the next frame
+    pub fn take_joystick(&mut self) -> Option<JoystickInput> {
+        self.state.borrow_mut().joystick.take()
+    }
+
+    pub fn take_state_request(&mut self) -> Option<StateRequest> {
+        self.state.borrow_mut().state_request.take()
+    }
+
+    pub fn has_drawing(&self) -> bool {
+        !self.state.borrow().shapes.is_empty()
+    }
+
+    pub fn draw(&self, pixels: &mut [u32], width: usize) {
+        for shape in &self.state.borrow().shapes {
+            match shape {
+                Shape::Text { x, y, text, color } => draw_text(pixels, width, *x, *y, text, *color),
+                Shape::Box {
+                    x,
+                    y,
+                    width: w,
+                    height: h,
+                    color,
+                } => fill_rect(pixels, width, *x, *y, *w, *h, *color),
+            }
+        }
+    }
+}
+
+fn register_api(lua: &Lua, state: &Rc<RefCell<ScriptState>>, ram: Shared<Ram>) -> mlua::Result<()> {
+    let globals = lua.globals();
+
+    let emu = lua.create_table()?;
+    let callbacks = state.clone();
+    emu.set(
+        "on_frame",
+        lua.create_function(move |_, callback: Function| {
+            callbacks.borrow_mut().callbacks.push(callback);
+            Ok(())
+        })?,
+    )?;
+    let frames = state.clone();
+    emu.set(
+        "frame_count",
+        lua.create_function(move |_, ()| Ok(frames.borrow().frame))?,
+    )?;
+    globals.set("emu", emu)?;
+
+    let memory = lua.create_table()?;
+    let read_ram = ram.clone();
+    memory.set(
+        "read",
+        lua.create_function(move |_, address: u16| Ok(read_ram.borrow().read(address)))?,
+    )?;
+    memory.set(
+        "write",
+        lua.create_function(move |_, (address, value): (u16, u8)| {
+            ram.borrow_mut().write(address, value);
+            Ok(())
+        })?,
+    )?;
+    globals.set("memory", memory)?;
+
+    let joypad = lua.create_table()?;
+    let input = state.clone();
+    joypad.set(
+        "set",
+        lua.create_function(move |_, buttons: Table| {
+            input.borrow_mut().joystick = Some(JoystickInput {
+                up: buttons.get("up")?,
+                down: buttons.get("down")?,
+                left: buttons.get("left")?,
+                right: buttons.get("right")?,
+                fire: buttons.get("fire")?,
+            });
+            Ok(())
+        })?,
+    )?;
+    globals.set("joypad", joypad)?;
+
+    let gui = lua.create_table()?;
+    let text_shapes = state.clone();
+    gui.set(
+        "text",
+        lua.create_function(
+            move |_, (x, y, text, color): (usize, usize, String, Option<u32>)| {
+                text_shapes.borrow_mut().shapes.push(Shape::Text {
+                    x,
+                    y,
+                    text,
+                    color: opaque(color),
+                });
+                Ok(())
+            },
+        )?,
+    )?;
+    let box_shapes = state.clone();
+    gui.set(
+        "box",
+        lua.create_function(
+            move |_, (x, y, width, height, color): (usize, usize, usize, usize, Option<u32>)| {
+                box_shapes.borrow_mut().shapes.push(Shape::Box {
+                    x,
+                    y,
+                    width,
+                    height,
+                    color: opaque(color),
+                });
+                Ok(())
+            },
+        )?,
+    )?;
+    globals.set("gui", gui)?;
+
+    let savestate = lua.create_table()?;
+    let save = state.clone();
+    savestate.set(
+        "save",
+        lua.create_function(move |_, slot: u32| {
+            save.borrow_mut().state_request = Some(StateRequest::Save(slot));
+            Ok(())
+        })?,
+    )?;
+    let load = state.clone();
+    savestate.set(
+        "load",
+        lua.create_function(move |_, slot: u32| {
+            load.borrow_mut().state_request = Some(StateRequest::Load(slot));
+            Ok(())
+        })?,
+    )?;
+    globals.set("savestate", savestate)?;
+    Ok(())
+}
+
+/// `0xRRGGBB` from a script as a frame buffer pixel
+fn opaque(color: Option<u32>) -> u32 {
+    0xFF00_0000 | (color.unwrap_or(DEFAULT_COLOR) & 0xFF_FFFF)
+}
@@ -0,0 +1,490 @@
+use std::cell::OnceCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use anyhow::{Context, Result, anyhow, ensure};
+use zinc64_core::{
+    Addressable, AddressableFaded, Chip, ChipFactory, Clock, Cpu, IoPort, IrqLine, Mmu, Pin, Ram,
+    Rom, Shared, SharedCell, SidModel, SoundOutput, VicModel, VideoOutput, new_shared,
+};
+use zinc64_emu::sound::Sid;
+use zinc64_emu::sound::sid::SamplingMethod;
+use zinc64_emu::system::{C64, C64Factory, Config};
+
+use crate::c64_reu::{self, Reu, ReuPort};
+use crate::saves;
+use crate::systems::GameSystem;
+
+const MAGIC: &[u8; 8] = b"C64STATE";
+const VERSION: u8 = 1;
+const RAM_SIZE: usize = 0x10000;
+const COLOR_RAM_SIZE: usize = 0x400;
+/// Version and the disk in the drive follow the magic
+const HEADER_SIZE: usize = MAGIC.len() + 2;
+/// Program counter, A, X, Y, stack pointer, status and the CPU port at $00/$01
+const CPU_BYTES: usize = 9;
+/// Registers as written, then the current timer A and B counters
+const CIA_BYTES: usize = 16 + 4;
+const SID_REGISTERS: usize = 0x19;
+const VIC_REGISTERS: usize = 0x2F;
+const STATE_SIZE: usize = HEADER_SIZE
+    + CPU_BYTES
+    + RAM_SIZE
+    + COLOR_RAM_SIZE
+    + 2 * CIA_BYTES
+    + SID_REGISTERS
+    + VIC_REGISTERS;
+
+const CIA_TIMER_A_LO: u8 = 0x04;
+const CIA_ICR: u8 = 0x0D;
+const CIA_CRA: u8 = 0x0E;
+const CIA_CRB: u8 = 0x0F;
+/// Control register strobe that loads the timer from its latch
+const CIA_FORCE_LOAD: u8 = 0x10;
+/// Interrupt latch and sprite collisions, their writes acknowledge rather than set
+const VIC_ACK_REGISTERS: [u8; 3] = [0x19, 0x1E, 0x1F];
+
+#[derive(Clone, Copy)]
+enum ChipKind {
+    Cia,
+    Sid,
+    Vic,
+}
+
+/// Passes everything on to a zinc64 chip and keeps the values written to its registers,
+/// which the SID and parts of the CIAs and the VIC cannot read back
+struct LoggedChip {
+    chip: Shared<dyn Chip>,
+    kind: ChipKind,
+    registers: [u8; 64],
+}
+
+impl LoggedChip {
+    fn wrap(chip: Shared<dyn Chip>, kind: ChipKind) -> Shared<Self> {
+        let mut logged = Self {
+            chip,
+            kind,
+            registers: [0; 64],
+        };
+        logged.reset_log();
+        new_shared(logged)
+    }
+
+    fn reset_log(&mut self) {
+        self.registers = [0; 64];
+        if let ChipKind::Cia = self.kind {
+            // the timer latches come out of reset at $FFFF
+            self.registers[CIA_TIMER_A_LO as usize..CIA_TIMER_A_LO as usize + 4].fill(0xFF);
+        }
+    }
+
+    /// Reads the chip without logging, for registers whose reads have no side effects
+    fn peek(&self, reg: u8) -> u8 {
+        self.chip.borrow_mut().read(reg)
+    }
+}
+
+impl Chip for LoggedChip {
+    fn clock(&mut self) {
+        self.chip.borrow_mut().clock();
+    }
+
+    fn clock_delta(&mut self, delta: u32) {
+        self.chip.borrow_mut().clock_delta(delta);
+    }
+
+    fn process_vsync(&mut self) {
+        self.chip.borrow_mut().process_vsync();
+    }
+
+    fn reset(&mut self) {
+        self.chip.borrow_mut().reset();
+        self.reset_log();
+    }
+
+    fn read(&mut self, reg: u8) -> u8 {
+        self.chip.borrow_mut().read(reg)
+    }
+
+    fn write(&mut self, reg: u8, value: u8) {
+        let slot = &mut self.registers[reg as usize & 0x3F];
+        *slot = match (self.kind, reg) {
+            // bit 7 picks whether the other bits set or clear interrupt mask bits
+            (ChipKind::Cia, CIA_ICR) if value & 0x80 != 0 => *slot | (value & 0x7F),
+            (ChipKind::Cia, CIA_ICR) => *slot & !value,
+            (ChipKind::Cia, CIA_CRA | CIA_CRB) => value & !CIA_FORCE_LOAD,
+            _ => value,
+        };
+        self.chip.borrow_mut().write(reg, value);
+    }
+}
+
+/// `C64Factory` with a hold on the parts of the machine a save state needs, zinc64 has
+/// no state support of its own. It also builds the SID with the configured resampling,
+/// which `C64Factory` fixes at the fastest one, and plugs in the REU.
+pub struct MachineFactory {
+    factory: C64Factory,
+    config: Rc<Config>,
+    sampling: SamplingMethod,
+    reu: Option<Shared<Reu>>,
+    irq_line: OnceCell<Shared<IrqLine>>,
+    ram: OnceCell<Shared<Ram>>,
+    color_ram: OnceCell<Shared<Ram>>,
+    cia_1: OnceCell<Shared<LoggedChip>>,
+    cia_2: OnceCell<Shared<LoggedChip>>,
+    sid: OnceCell<Shared<LoggedChip>>,
+    vic: OnceCell<Shared<LoggedChip>>,
+}
+
+impl MachineFactory {
+    pub fn new(config: Rc<Config>, sampling: SamplingMethod, reu: Option<Reu>) -> Self {
+        Self {
+            factory: C64Factory::new(config.clone()),
+            config,
+            sampling,
+            reu: reu.map(new_shared),
+            irq_line: OnceCell::new(),
+            ram: OnceCell::new(),
+            color_ram: OnceCell::new(),
+            cia_1: OnceCell::new(),
+            cia_2: OnceCell::new(),
+            sid: OnceCell::new(),
+            vic: OnceCell::new(),
+        }
+    }
+
+    /// The handles collected while `C64::build` ran
+    pub fn into_states(self) -> Result<MachineStates> {
+        let missing = |part: &str| anyhow!("the C64 was built without its {part}");
+        Ok(MachineStates {
+            ram: self.ram.into_inner().ok_or_else(|| missing("RAM"))?,
+            color_ram: self
+                .color_ram
+                .into_inner()
+                .ok_or_else(|| missing("color RAM"))?,
+            cia_1: self.cia_1.into_inner().ok_or_else(|| missing("CIA 1"))?,
+            cia_2: self.cia_2.into_inner().ok_or_else(|| missing("CIA 2"))?,
+            sid: self.sid.into_inner().ok_or_else(|| missing("SID"))?,
+            vic: self.vic.into_inner().ok_or_else(|| missing("VIC"))?,
+            reu: self.reu,
+        })
+    }
+
+    fn logged(
+        cell: &OnceCell<Shared<LoggedChip>>,
+        chip: Shared<dyn Chip>,
+        kind: ChipKind,
+    ) -> Shared<dyn Chip> {
+        let logged = LoggedChip::wrap(chip, kind);
+        let _ = cell.set(logged.clone());
+        logged
+    }
+}
+
+impl ChipFactory for MachineFactory {
+    fn new_cpu(
+        &self,
+        mem: Shared<dyn Addressable>,
+        io_port: Shared<IoPort>,
+        ba_line: Shared<Pin>,
+        irq_line: Shared<IrqLine>,
+        nmi_line: Shared<IrqLine>,
+    ) -> Box<dyn Cpu> {
+        self.factory
+            .new_cpu(mem, io_port, ba_line, irq_line, nmi_line)
+    }
+
+    fn new_cia_1(
+        &self,
+        joystick_1: SharedCell<u8>,
+        joystick_2: SharedCell<u8>,
+        keyboard_matrix: Shared<[u8; 16]>,
+        port_a: Shared<IoPort>,
+        port_b: Shared<IoPort>,
+        flag_pin: Shared<Pin>,
+        irq_line: Shared<IrqLine>,
+    ) -> Shared<dyn Chip> {
+        let _ = self.irq_line.set(irq_line.clone());
+        let chip = self.factory.new_cia_1(
+            joystick_1,
+            joystick_2,
+            keyboard_matrix,
+            port_a,
+            port_b,
+            flag_pin,
+            irq_line,
+        );
+        Self::logged(&self.cia_1, chip, ChipKind::Cia)
+    }
+
+    fn new_cia_2(
+        &self,
+        port_a: Shared<IoPort>,
+        port_b: Shared<IoPort>,
+        flag_pin: Shared<Pin>,
+        nmi_line: Shared<IrqLine>,
+    ) -> Shared<dyn Chip> {
+        let chip = self.factory.new_cia_2(port_a, port_b, flag_pin, nmi_line);
+        Self::logged(&self.cia_2, chip, ChipKind::Cia)
+    }
+
+    fn new_sid(
+        &self,
+        chip_model: SidModel,
+        system_clock: Rc<Clock>,
+        sound_buffer: Arc<dyn SoundOutput>,
+    ) -> Shared<dyn Chip> {
+        let mut sid = Sid::new(chip_model, system_clock, sound_buffer);
+        sid.set_sampling_parameters(
+            self.sampling,
+            self.config.model.cpu_freq,
+            self.config.sound.sample_rate,
+        );
+        sid.enable_filter(self.config.sound.sid_filters);
+        Self::logged(&self.sid, new_shared(sid), ChipKind::Sid)
+    }
+
+    fn new_vic(
+        &self,
+        chip_model: VicModel,
+        color_ram: Shared<Ram>,
+        ram: Shared<Ram>,
+        rom_charset: Shared<Rom>,
+        vic_base_address: SharedCell<u16>,
+        frame_buffer: Shared<dyn VideoOutput>,
+        vsync_flag: SharedCell<bool>,
+        ba_line: Shared<Pin>,
+        irq_line: Shared<IrqLine>,
+    ) -> Shared<dyn Chip> {
+        let chip = self.factory.new_vic(
+            chip_model,
+            color_ram,
+            ram,
+            rom_charset,
+            vic_base_address,
+            frame_buffer,
+            vsync_flag,
+            ba_line,
+            irq_line,
+        );
+        Self::logged(&self.vic, chip, ChipKind::Vic)
+    }
+
+    fn new_memory(
+        &self,
+        mmu: Shared<dyn Mmu>,
+        cia_1: Shared<dyn Chip>,
+        cia_2: Shared<dyn Chip>,
+        color_ram: Shared<Ram>,
+        expansion_port: Shared<dyn AddressableFaded>,
+        ram: Shared<Ram>,
+        rom_basic: Shared<Rom>,
+        rom_charset: Shared<Rom>,
+        rom_kernal: Shared<Rom>,
+        sid: Shared<dyn Chip>,
+        vic: Shared<dyn Chip>,
+    ) -> Shared<dyn Addressable> {
+        let expansion_port = match (&self.reu, self.irq_line.get()) {
+            (Some(reu), Some(irq_line)) => {
+                reu.borrow_mut().connect(ram.clone(), irq_line.clone());
+                new_shared(ReuPort {
+                    port: expansion_port,
+                    reu: reu.clone(),
+                })
+            }
+            _ => expansion_port,
+        };
+        self.factory.new_memory(
+            mmu,
+            cia_1,
+            cia_2,
+            color_ram,
+            expansion_port,
+            ram,
+            rom_basic,
+            rom_charset,
+            rom_kernal,
+            sid,
+            vic,
+        )
+    }
+
+    fn new_ram(&self, capacity: usize) -> Shared<Ram> {
+        let ram = self.factory.new_ram(capacity);
+        let cell = if capacity == COLOR_RAM_SIZE {
+            &self.color_ram
+        } else {
+            &self.ram
+        };
+        let _ = cell.set(ram.clone());
+        ram
+    }
+
+    fn new_rom(&self, data: &[u8], offset: u16) -> Shared<Rom> {
+        self.factory.new_rom(data, offset)
+    }
+}
+
+/// Saves and restores the machine between frames. Memory, the CPU, the chip registers
+/// and the REU are kept; what the chips keep inside, like the SID's oscillators or the
+/// VIC's raster position, starts over from a reset, which games get past within a frame.
+/// Cartridge banking and the tape position are not part of a state.
+pub struct MachineStates {
+    ram: Shared<Ram>,
+    color_ram: Shared<Ram>,
+    cia_1: Shared<LoggedChip>,
+    cia_2: Shared<LoggedChip>,
+    sid: Shared<LoggedChip>,
+    vic: Shared<LoggedChip>,
+    /// Its registers and memory follow the rest of the state when one is plugged in
+    reu: Option<Shared<Reu>>,
+}
+
+impl MachineStates {
+    /// The machine's 64 KiB of RAM, underneath the ROMs and I/O
+    pub fn ram(&self) -> Shared<Ram> {
+        self.ram.clone()
+    }
+
+    /// Writes the machine to `path`, with the index of the disk in the drive
+    pub fn save(&self, c64: &C64, disk: usize, path: &Path) -> Result<()> {
+        let cpu = c64.get_cpu();
+        let mut state = Vec::with_capacity(STATE_SIZE);
+        state.extend_from_slice(MAGIC);
+        state.push(VERSION);
+        state.push(u8::try_from(disk).context("too many disks in the set")?);
+        state.extend_from_slice(&cpu.get_pc().to_le_bytes());
+        state.extend_from_slice(&[
+            cpu.get_a(),
+            cpu.get_x(),
+            cpu.get_y(),
+            cpu.get_sp(),
+            cpu.get_p(),
+            cpu.read(0x0000),
+            cpu.read(0x0001),
+        ]);
+        let ram = self.ram.borrow();
+        state.extend((0..RAM_SIZE).map(|address| ram.read(address as u16)));
+        let color_ram = self.color_ram.borrow();
+        state.extend((0..COLOR_RAM_SIZE).map(|address| color_ram.read(address as u16)));
+        for cia in [&self.cia_1, &self.cia_2] {
+            let cia = cia.borrow();
+            state.extend_from_slice(&cia.registers[..16]);
+            state.extend((CIA_TIMER_A_LO..CIA_TIMER_A_LO + 4).map(|reg| cia.peek(reg)));
+        }
+        state.extend_from_slice(&self.sid.borrow().registers[..SID_REGISTERS]);
+        state.extend_from_slice(&self.vic.borrow().registers[..VIC_REGISTERS]);
+        debug_assert_eq!(state.len(), STATE_SIZE);
+        if let Some(reu) = &self.reu {
+            reu.borrow().save(&mut state);
+        }
+
+        saves::write(path, &state)
+    }
+
+    /// Puts the machine back the way `save` found it and returns the disk index stored
+    /// with it. The file is checked before the machine is touched.
+    pub fn load(&self, c64: &mut C64, path: &Path) -> Result<usize> {
+        let state = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        ensure!(
+            state.len() >= STATE_SIZE && state.starts_with(MAGIC),
+            "{} is not a C64 save state",
+            path.display()
+        );
+        ensure!(
+            state[MAGIC.len()] == VERSION,
+            "{} was saved by a different version of the launcher",
+            path.display()
+        );
+        let disk = state[MAGIC.len() + 1] as usize;
+        let mut rest = &state[HEADER_SIZE..];
+        let mut take = |len: usize| {
+            let (head, tail) = rest.split_at(len);
+            rest = tail;
+            head
+        };
+        let cpu_state = take(CPU_BYTES);
+        let ram = take(RAM_SIZE);
+        let color_ram = take(COLOR_RAM_SIZE);
+        let cias = [take(CIA_BYTES), take(CIA_BYTES)];
+        let sid = take(SID_REGISTERS);
+        let vic = take(VIC_REGISTERS);
+        let reu_size = self
+            .reu
+            .as_ref()
+            .map_or(0, |reu| c64_reu::REGISTER_BYTES + reu.borrow().size());
+        ensure!(
+            rest.len() == reu_size,
+            "{} was saved with a different RAM Expansion Unit",
+            path.display()
+        );
+
+        // a soft reset settles interrupts and the chips' insides, memory is overwritten
+        // below anyway
+        c64.set_autostart(None);
+        c64.reset(false);
+        self.ram.borrow_mut().load(ram, 0);
+        self.color_ram.borrow_mut().load(color_ram, 0);
+
+        for (cia, saved) in [&self.cia_1, &self.cia_2].into_iter().zip(cias) {
+            let mut cia = cia.borrow_mut();
+            let (registers, counters) = saved.split_at(16);
+            for reg in 0..4 {
+                cia.write(reg, registers[reg as usize]);
+            }
+            // load the counters through the latches, then put the latches back
+            for (offset, &counter) in counters.iter().enumerate() {
+                cia.write(CIA_TIMER_A_LO + offset as u8, counter);
+            }
+            cia.write(CIA_CRA, registers[CIA_CRA as usize] | CIA_FORCE_LOAD);
+            cia.write(CIA_CRB, registers[CIA_CRB as usize] | CIA_FORCE_LOAD);
+            for offset in 0..4 {
+                let reg = CIA_TIMER_A_LO + offset;
+                cia.write(reg, registers[reg as usize]);
+            }
+            cia.write(CIA_ICR, 0x7F);
+            cia.write(CIA_ICR, 0x80 | registers[CIA_ICR as usize]);
+        }
+        {
+            let mut chip = self.sid.borrow_mut();
+            for (reg, &value) in sid.iter().enumerate() {
+                chip.write(reg as u8, value);
+            }
+        }
+        {
+            let mut chip = self.vic.borrow_mut();
+            for (reg, &value) in vic.iter().enumerate() {
+                if !VIC_ACK_REGISTERS.contains(&(reg as u8)) {
+                    chip.write(reg as u8, value);
+                }
+            }
+        }
+
+        if let Some(reu) = &self.reu {
+            reu.borrow_mut().load(rest)?;
+        }
+
+        let cpu = c64.get_cpu_mut();
+        cpu.write(0x0000, cpu_state[7]);
+        cpu.write(0x0001, cpu_state[8]);
+        cpu.set_pc(u16::from_le_bytes([cpu_state[0], cpu_state[1]]));
+        cpu.set_a(cpu_state[2]);
+        cpu.set_x(cpu_state[3]);
+        cpu.set_y(cpu_state[4]);
+        cpu.set_sp(cpu_state[5]);
+        cpu.set_p(cpu_state[6]);
+        Ok(disk)
+    }
+}
+
+/// `states/c64/<game>.st<slot>`
+pub fn state_path(label: &str, slot: u32) -> PathBuf {
+    saves::state_dir(GameSystem::C64).join(format!("{label}.st{slot}"))
+}
+
+/// `states/c64/<game>.resume`, written on exit and loaded next time with auto-resume
+pub fn resume_path(label: &str) -> PathBuf {
+    saves::state_dir(GameSystem::C64).join(format!("{label}.resume"))
+}
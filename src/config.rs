@@ -0,0 +1,901 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use sdl2::keyboard::Keycode;
+use serde::Deserialize;
+
+use crate::controller::{
+    StickTuning, VirtualButton, joystick_field, parse_macro_step, valid_joystick_binding,
+};
+use crate::ps1_keyboard;
+use crate::saves;
+use crate::systems::GameSystem;
+
+/// Where the settings file is looked for, relative to the working directory
+pub const CONFIG_PATH: &str = "config.toml";
+/// Allowed `overclock` percentages, underclocking only slows games down further
+const OVERCLOCK_RANGE: std::ops::RangeInclusive<u32> = 100..=400;
+const SAMPLE_RATE_RANGE: std::ops::RangeInclusive<u32> = 8_000..=192_000;
+/// Turbo presses per second, games polling at 60Hz miss presses above 30
+const TURBO_RATE_RANGE: std::ops::RangeInclusive<u32> = 1..=30;
+/// Allowed `[ps1] gamma`, past these the picture is mostly black or white
+const GAMMA_RANGE: std::ops::RangeInclusive<f32> = 0.4..=2.5;
+
+/// Launcher settings, read from `config.toml` in the working directory next to `games/`.
+/// Every key is optional, so a missing file or section keeps the built-in defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub ps1: Ps1Config,
+    pub n64: N64Config,
+    pub gamecube: GamecubeConfig,
+    pub wii: WiiConfig,
+    pub c64: C64Config,
+    pub controllers: ControllersConfig,
+    pub audio: AudioConfig,
+    pub display: DisplayConfig,
+    pub saves: SavesConfig,
+}
+
+/// Where battery saves and memory cards are kept
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SavesConfig {
+    /// Holds a folder per system, `saves/<system>/` by default. Saves in the old places are
+    /// moved over the first time a game of their system runs.
+    pub dir: PathBuf,
+    /// Copies of each save kept in the `backups/` folder next to it, the file a game's first
+    /// save of a run replaces is added and the oldest dropped. 0 keeps none.
+    pub backups: u32,
+    /// Run through the shell after saves or states are written and once more when the game
+    /// ends, to sync `saves/` and `states/` with `rclone`, `rsync`, `git` and the like
+    pub sync_command: Option<String>,
+    /// When `sync_command` runs besides the end of the game
+    pub sync_on: SyncOn,
+    /// Save a state when a game exits and load it the next time it starts, for the systems
+    /// with save states
+    pub auto_resume: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncOn {
+    /// A couple of seconds after each write, in the background
+    #[default]
+    Write,
+    /// Only when the game ends
+    Exit,
+}
+
+impl Default for SavesConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from(saves::DEFAULT_DIR),
+            backups: saves::DEFAULT_BACKUPS,
+            sync_command: None,
+            sync_on: SyncOn::default(),
+            auto_resume: false,
+        }
+    }
+}
+
+/// How every core's window is presented
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    /// The monitor has a variable refresh rate (G-SYNC, FreeSync): frames are presented as soon as
+    /// they are paced instead of on the next fixed refresh
+    pub vrr: bool,
+    /// Clockwise turn of the picture in degrees: 0, 90, 180 or 270. 90 and 270 stand vertical
+    /// (TATE) games upright or suit a monitor turned on its side.
+    pub rotate: u16,
+    /// Turn the arrow keys and pad directions along with the picture, so up on the pad moves
+    /// up on the turned screen
+    pub rotate_input: bool,
+    /// How much larger than their pixel size windows open, instead of the scale the display
+    /// reports. Mostly for X11, whose reported DPI is often off.
+    pub scale_factor: Option<f32>,
+    /// How game windows fill the screen, for systems without their own setting in `systems`
+    pub fullscreen: Fullscreen,
+    /// Start games with the performance overlay open
+    pub perf_overlay: bool,
+    /// Stop the game while its window is in the background and carry on once it is back
+    pub pause_unfocused: bool,
+    /// Settings for one system, keyed like the system sections: gb, nes, snes, nds, ps1, n64,
+    /// gamecube, wii, c64
+    pub systems: BTreeMap<String, SystemDisplayConfig>,
+}
+
+impl DisplayConfig {
+    /// The fullscreen mode games of `system` start in
+    pub fn fullscreen_for(&self, system: GameSystem) -> Fullscreen {
+        self.systems
+            .get(system.config_key())
+            .and_then(|settings| settings.fullscreen)
+            .unwrap_or(self.fullscreen)
+    }
+}
+
+/// `[display.systems.<system>]`, left out keys fall back to `[display]`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SystemDisplayConfig {
+    pub fullscreen: Option<Fullscreen>,
+    /// PNG artwork drawn around the system's games, a game's own `bezels/<system>/<game>.png`
+    /// still wins over it
+    pub bezel: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Fullscreen {
+    /// A normal window
+    #[default]
+    Off,
+    /// A window without borders covering the monitor at the desktop's resolution, switching
+    /// away from it is instant
+    Borderless,
+    /// The display switches to the video mode closest to the window's size
+    Exclusive,
+}
+
+/// Audio output of every core
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    /// Samples per SDL device buffer, a power of two. Smaller buffers cut latency but crackle on
+    /// slow machines.
+    pub buffer_samples: u16,
+    /// How much audio the PlayStation keeps queued, in milliseconds
+    pub latency_ms: u32,
+    /// Queued audio beyond this many milliseconds is dropped to catch up, unset keeps each core's
+    /// own limit (1 second, 200 ms for libretro cores)
+    pub max_latency_ms: Option<u32>,
+    /// What happens to the sound while the game window is in the background
+    pub unfocused: UnfocusedAudio,
+    /// Master volume in percent, + and - change it while playing
+    pub volume: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnfocusedAudio {
+    #[default]
+    Play,
+    /// Plays silence, the game keeps running
+    Mute,
+    /// Stops the device, what the game produces meanwhile is dropped
+    Pause,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self {
+            buffer_samples: 1024,
+            latency_ms: 100,
+            max_latency_ms: None,
+            unfocused: UnfocusedAudio::Play,
+            volume: 100,
+        }
+    }
+}
+
+impl AudioConfig {
+    /// Queue size the PlayStation steers its output rate towards
+    pub fn latency(&self) -> Duration {
+        Duration::from_millis(self.latency_ms.into())
+    }
+
+    /// Queued audio past which a core drops its queue, `default` unless configured
+    pub fn max_latency(&self, default: Duration) -> Duration {
+        self.max_latency_ms
+            .map_or(default, |ms| Duration::from_millis(ms.into()))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ps1Config {
+    /// Multiplier for the resolution 3D is rendered at, 1 (native) to 4
+    pub internal_resolution: u32,
+    pub renderer: Ps1Renderer,
+    /// Skip the BIOS boot animation and jump straight into the game
+    pub fast_boot: bool,
+    /// Plug a multitap into port 1 so up to four players can join
+    pub multitap: bool,
+    pub aspect: Ps1Aspect,
+    /// Filter for scaling frames up to the window
+    pub filter: Ps1Filter,
+    /// Scale frames by whole multiples of the console resolution, leaving a border
+    pub integer_scale: bool,
+    /// Display gamma, above 1 brightens dark scenes and below 1 darkens them
+    pub gamma: f32,
+    /// CPU speed in percent, 100 is a stock console
+    pub overclock: u32,
+    /// Widen 3D scenes for a 16:9 picture, breaks some 2D overlays
+    pub widescreen: bool,
+    /// Draw 3D at sub-pixel precision so polygons stop wobbling
+    pub precise_geometry: bool,
+    /// Perspective correct texturing, removes the texture warping on large polygons
+    pub perspective_correct: bool,
+    pub controller: Ps1Controller,
+    /// Audio output rate in Hz, the 44100Hz SPU output is resampled to it
+    pub sample_rate: u32,
+    /// Keyboard profile of each player from player 1 on, built in or from `keyboard_profiles`
+    pub keyboard: Vec<String>,
+    /// Keyboard layouts of your own, keyed by the name `keyboard` assigns them by
+    pub keyboard_profiles: BTreeMap<String, Ps1KeyboardProfile>,
+    /// Overrides for single games, keyed by disc serial like `SLUS-00594`
+    pub games: BTreeMap<String, Ps1GameConfig>,
+}
+
+/// A `[ps1.keyboard_profiles.<name>]` table, the SDL name of the key for each button
+/// (`W`, `Up`, `Return`, `Keypad 8`, ...). Buttons left out have no key.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ps1KeyboardProfile {
+    pub up: Option<String>,
+    pub down: Option<String>,
+    pub left: Option<String>,
+    pub right: Option<String>,
+    pub cross: Option<String>,
+    pub circle: Option<String>,
+    pub square: Option<String>,
+    pub triangle: Option<String>,
+    pub l1: Option<String>,
+    pub r1: Option<String>,
+    pub l2: Option<String>,
+    pub r2: Option<String>,
+    pub start: Option<String>,
+    pub select: Option<String>,
+}
+
+/// A `[ps1.games.<serial>]` table, unset keys fall back to the `[ps1]` ones
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ps1GameConfig {
+    /// Shown in the window title and launcher instead of the file name
+    pub title: Option<String>,
+    pub overclock: Option<u32>,
+    pub widescreen: Option<bool>,
+    pub controller: Option<Ps1Controller>,
+}
+
+impl Default for Ps1Config {
+    fn default() -> Self {
+        Self {
+            internal_resolution: 1,
+            renderer: Ps1Renderer::Auto,
+            fast_boot: true,
+            multitap: false,
+            aspect: Ps1Aspect::Tv,
+            filter: Ps1Filter::Nearest,
+            integer_scale: false,
+            gamma: 1.0,
+            overclock: 100,
+            widescreen: false,
+            precise_geometry: false,
+            perspective_correct: false,
+            controller: Ps1Controller::Digital,
+            sample_rate: 44_100,
+            keyboard: vec!["arrows".into(), "ijkl".into()],
+            keyboard_profiles: BTreeMap::new(),
+            games: BTreeMap::new(),
+        }
+    }
+}
+
+impl Ps1Config {
+    /// Overrides for the disc with this serial, if any are configured
+    pub fn game(&self, serial: Option<&str>) -> Option<&Ps1GameConfig> {
+        self.games.get(serial?)
+    }
+}
+
+/// How an installed Dolphin is launched, the libretro core and the stub ignore these
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GamecubeConfig {
+    pub fullscreen: bool,
+    /// Dolphin's `--user` directory, relative paths are under the launcher's config
+    /// directory, e.g. `~/.config/retro-launcher/dolphin` for `"dolphin"`
+    pub user_dir: Option<PathBuf>,
+    /// Dolphin's `--video_backend`, e.g. `OGL`, `Vulkan` or `D3D12`
+    pub video_backend: Option<String>,
+    /// Dolphin's `DSP.Backend` setting, e.g. `Cubeb`, `Pulse` or `ALSA`
+    pub audio_backend: Option<String>,
+    /// Passed to Dolphin as is, before the game
+    pub dolphin_args: Vec<String>,
+}
+
+/// Wii Remote setup for Wii games, which otherwise run on the `[gamecube]` Dolphin setup
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WiiConfig {
+    pub remote: WiiRemote,
+    /// Wii Remotes connected, 1 to 4
+    pub remotes: u32,
+    pub sensor_bar: SensorBar,
+    /// Keep looking for real Wii Remotes while a game runs, so they can connect late
+    pub continuous_scanning: bool,
+    /// Play the sound of real Wii Remotes on their speaker
+    pub speaker: bool,
+}
+
+impl Default for WiiConfig {
+    fn default() -> Self {
+        Self {
+            remote: WiiRemote::Emulated,
+            remotes: 1,
+            sensor_bar: SensorBar::Bottom,
+            continuous_scanning: false,
+            speaker: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WiiRemote {
+    /// Emulated by Dolphin from its controller settings
+    Emulated,
+    /// Real Wii Remotes paired over Bluetooth
+    Real,
+    None,
+}
+
+/// Where the sensor bar sits, tells games which way to correct the pointer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorBar {
+    Top,
+    Bottom,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct C64Config {
+    pub model: C64Model,
+    /// Overrides the SID the model comes with
+    pub sid_model: Option<SidChip>,
+    /// Run the SID's analog filter, tunes written for it sound thin without
+    pub sid_filter: bool,
+    pub resampling: SidResampling,
+    /// Audio output rate in Hz the SID is sampled at
+    pub sample_rate: u32,
+    /// RAM Expansion Unit in the expansion port, for demos and GEOS setups that use one
+    pub reu: ReuSize,
+    pub keyboard: KeyboardMapping,
+    /// Control port the pad drives, 1 or 2; most games read port 2
+    pub joystick_port: u8,
+    /// Overrides for single games, keyed by file name without the extension
+    pub games: BTreeMap<String, C64GameConfig>,
+}
+
+/// A `[c64.games.<name>]` table, unset keys fall back to the `[c64]` ones
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct C64GameConfig {
+    pub model: Option<C64Model>,
+    pub reu: Option<ReuSize>,
+    pub joystick_port: Option<u8>,
+}
+
+impl Default for C64Config {
+    fn default() -> Self {
+        Self {
+            model: C64Model::Pal,
+            sid_model: None,
+            sid_filter: true,
+            resampling: SidResampling::Fast,
+            sample_rate: 44_100,
+            reu: ReuSize::Off,
+            keyboard: KeyboardMapping::Symbolic,
+            joystick_port: 2,
+            games: BTreeMap::new(),
+        }
+    }
+}
+
+impl C64Config {
+    /// Overrides for the image with this file stem, if any are configured
+    pub fn game(&self, stem: &str) -> Option<&C64GameConfig> {
+        self.games.get(stem)
+    }
+}
+
+/// The machine to emulate. Games written for one video standard run too fast, too slow
+/// or with a broken picture on the other. The C64C models come with the 8580 SID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum C64Model {
+    Pal,
+    Ntsc,
+    C64cPal,
+    C64cNtsc,
+}
+
+impl C64Model {
+    pub fn is_ntsc(self) -> bool {
+        matches!(self, C64Model::Ntsc | C64Model::C64cNtsc)
+    }
+
+    pub fn sid(self) -> SidChip {
+        match self {
+            C64Model::Pal | C64Model::Ntsc => SidChip::Mos6581,
+            C64Model::C64cPal | C64Model::C64cNtsc => SidChip::Mos8580,
+        }
+    }
+}
+
+/// Size of the RAM Expansion Unit, the 1750 has 512 KiB and the 1764 and clones go
+/// up to 1 MiB
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReuSize {
+    Off,
+    #[serde(rename = "512k")]
+    Kib512,
+    #[serde(rename = "1m")]
+    Mib1,
+}
+
+impl ReuSize {
+    /// Bytes of expansion memory, `None` without a REU
+    pub fn bytes(self) -> Option<usize> {
+        match self {
+            ReuSize::Off => None,
+            ReuSize::Kib512 => Some(512 * 1024),
+            ReuSize::Mib1 => Some(1024 * 1024),
+        }
+    }
+}
+
+/// How the PC keyboard stands in for the C64 one, whose symbols sit elsewhere
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyboardMapping {
+    /// Keys type what is printed on them, shifting the C64 key as needed; best for typing
+    Symbolic,
+    /// Keys press the C64 key in the same spot, for games that go by the C64 layout
+    Positional,
+}
+
+/// The original SID of the breadbin C64, or the revision of the C64C with its cleaner
+/// filter and louder combined waveforms
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SidChip {
+    #[serde(rename = "6581")]
+    Mos6581,
+    #[serde(rename = "8580")]
+    Mos8580,
+}
+
+/// How the SID's output at the CPU clock is brought down to the sample rate, from
+/// cheapest to cleanest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SidResampling {
+    /// Nearest sample, aliases on high notes
+    Fast,
+    /// Linear interpolation between samples
+    Interpolate,
+    /// Band-limited with a short filter
+    ResampleFast,
+    /// Band-limited with a long filter, the heaviest on the CPU
+    Resample,
+}
+
+/// Plugins are named like `mupen64plus-video-GLideN64` and looked up in the plugin
+/// directories, or given as a path to the library
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct N64Config {
+    /// Video plugin for every game, the first installed one of a built-in list when unset
+    pub video: Option<String>,
+    pub rsp: Option<String>,
+    /// GLideN64 hi-res texture packs, one folder per game named after the ROM header
+    pub texture_packs: Option<PathBuf>,
+    /// Use the alpha channel of pack textures as is, which most current packs expect
+    pub texture_full_alpha: bool,
+    /// Match textures by the checksum of older Rice packs
+    pub texture_alt_crc: bool,
+    /// Overrides for single games, keyed by the cartridge ID in the ROM header like `NSME`
+    pub games: BTreeMap<String, N64GameConfig>,
+}
+
+/// A `[n64.games.<id>]` table, unset keys fall back to the `[n64]` ones
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct N64GameConfig {
+    pub video: Option<String>,
+    pub rsp: Option<String>,
+}
+
+impl Default for N64Config {
+    fn default() -> Self {
+        Self {
+            video: None,
+            rsp: None,
+            texture_packs: None,
+            texture_full_alpha: true,
+            texture_alt_crc: false,
+            games: BTreeMap::new(),
+        }
+    }
+}
+
+impl N64Config {
+    /// The video plugin configured for the game with this cartridge ID
+    pub fn video(&self, game_id: Option<&str>) -> Option<&str> {
+        self.game(game_id)
+            .and_then(|game| game.video.as_deref())
+            .or(self.video.as_deref())
+    }
+
+    /// The RSP plugin configured for the game with this cartridge ID
+    pub fn rsp(&self, game_id: Option<&str>) -> Option<&str> {
+        self.game(game_id)
+            .and_then(|game| game.rsp.as_deref())
+            .or(self.rsp.as_deref())
+    }
+
+    fn game(&self, game_id: Option<&str>) -> Option<&N64GameConfig> {
+        self.games.get(game_id?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ps1Renderer {
+    /// Vulkan when a usable device is found, software otherwise
+    Auto,
+    Vulkan,
+    /// CPU rasterizer presented through an SDL canvas, always native resolution
+    Software,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Ps1Aspect {
+    /// Every video mode fills a 4:3 picture, like on a TV
+    #[serde(rename = "4:3")]
+    Tv,
+    /// Fill the whole window
+    #[serde(rename = "stretch")]
+    Stretch,
+    /// Square pixels at the largest integer scale that fits
+    #[serde(rename = "raw")]
+    Raw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Ps1Filter {
+    Nearest,
+    Bilinear,
+    /// Nearest to an integer scale, then bilinear, sharp without uneven pixels
+    SharpBilinear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ps1Controller {
+    Digital,
+    /// A DualShock switched to analog mode at boot, for games that need the sticks
+    Analog,
+    /// A PlayStation Mouse in port 1 driven by the host mouse, player 2 keeps a pad
+    Mouse,
+}
+
+/// Stick response of every pad, applied before a core sees the sticks
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ControllersConfig {
+    /// Stick travel from the center that is ignored, 0 to 1
+    pub deadzone: f32,
+    /// Stick travel that already counts as pushed all the way, up to 1
+    pub saturation: f32,
+    /// Exponent of the response curve, 1 is linear and higher gives finer control near the center
+    pub response: f32,
+    /// Buttons that fire repeatedly while held, on pads and the keyboard keys mapped to them
+    pub turbo: Vec<VirtualButton>,
+    /// Turbo presses per second
+    pub turbo_rate: u32,
+    /// Extra SDL_GameControllerDB mappings, loaded after the bundled `gamecontrollerdb.txt`
+    pub mappings: Option<PathBuf>,
+    /// Overrides for single pads, keyed by the SDL GUID the log prints when a pad connects
+    pub pads: BTreeMap<String, PadConfig>,
+    /// Input sequences played back on a key or button press, keyed by a name for the log
+    pub macros: BTreeMap<String, MacroConfig>,
+    /// Layouts for joysticks SDL has no controller mapping for, keyed by GUID or `default` for
+    /// any of them. Each maps controller buttons (`a`, `start`, `up`, ...) and sticks (`left-x`,
+    /// `left-trigger`, ...) to joystick inputs in SDL mapping syntax: `b3`, `h0.1`, `a2`, `-a1`
+    pub joysticks: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// A `[controllers.macros.<name>]` table. Each step holds its buttons for one frame or the
+/// count after them, like `"down+right 2"`, and `"none 10"` waits.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MacroConfig {
+    /// SDL name of the keyboard key that starts the macro, like `F1`
+    pub key: Option<String>,
+    /// Controller button that starts the macro
+    pub button: Option<VirtualButton>,
+    pub steps: Vec<String>,
+}
+
+/// A `[controllers.pads.<guid>]` table, unset keys fall back to the `[controllers]` ones
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PadConfig {
+    pub deadzone: Option<f32>,
+    pub saturation: Option<f32>,
+    pub response: Option<f32>,
+}
+
+impl Default for ControllersConfig {
+    fn default() -> Self {
+        Self {
+            deadzone: 0.05,
+            saturation: 1.0,
+            response: 1.0,
+            turbo: Vec::new(),
+            turbo_rate: 10,
+            mappings: None,
+            pads: BTreeMap::new(),
+            macros: BTreeMap::new(),
+            joysticks: BTreeMap::new(),
+        }
+    }
+}
+
+impl ControllersConfig {
+    /// The tuning of the pad with `guid`
+    pub fn stick_tuning(&self, guid: &str) -> StickTuning {
+        let pad = self
+            .pads
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(guid))
+            .map(|(_, pad)| pad);
+        StickTuning {
+            deadzone: pad.and_then(|pad| pad.deadzone).unwrap_or(self.deadzone),
+            saturation: pad
+                .and_then(|pad| pad.saturation)
+                .unwrap_or(self.saturation),
+            response: pad.and_then(|pad| pad.response).unwrap_or(self.response),
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let config: Self =
+            toml::from_str(&text).with_context(|| format!("failed to parse {}", path.display()))?;
+        config
+            .validate()
+            .with_context(|| format!("invalid setting in {}", path.display()))?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(1..=4).contains(&self.ps1.internal_resolution) {
+            bail!(
+                "ps1.internal_resolution must be between 1 and 4, got {}",
+                self.ps1.internal_resolution
+            );
+        }
+        if !SAMPLE_RATE_RANGE.contains(&self.ps1.sample_rate) {
+            bail!(
+                "ps1.sample_rate must be between {} and {}, got {}",
+                SAMPLE_RATE_RANGE.start(),
+                SAMPLE_RATE_RANGE.end(),
+                self.ps1.sample_rate
+            );
+        }
+        if !GAMMA_RANGE.contains(&self.ps1.gamma) {
+            bail!(
+                "ps1.gamma must be between {} and {}, got {}",
+                GAMMA_RANGE.start(),
+                GAMMA_RANGE.end(),
+                self.ps1.gamma
+            );
+        }
+        let overclocks = std::iter::once(("ps1.overclock".to_string(), self.ps1.overclock)).chain(
+            self.ps1.games.iter().filter_map(|(serial, game)| {
+                Some((format!("ps1.games.{serial}.overclock"), game.overclock?))
+            }),
+        );
+        for (key, overclock) in overclocks {
+            if !OVERCLOCK_RANGE.contains(&overclock) {
+                bail!(
+                    "{key} must be between {} and {}, got {overclock}",
+                    OVERCLOCK_RANGE.start(),
+                    OVERCLOCK_RANGE.end()
+                );
+            }
+        }
+        if self.ps1.keyboard.len() > 4 {
+            bail!(
+                "ps1.keyboard assigns at most 4 players, got {}",
+                self.ps1.keyboard.len()
+            );
+        }
+        for name in &self.ps1.keyboard {
+            if !self.ps1.keyboard_profiles.contains_key(name)
+                && ps1_keyboard::built_in(name).is_none()
+            {
+                bail!(
+                    "ps1.keyboard has unknown profile `{name}`, expected arrows, ijkl, wasd, numpad or one from ps1.keyboard_profiles"
+                );
+            }
+        }
+        for (name, profile) in &self.ps1.keyboard_profiles {
+            for (key, _) in profile.bindings() {
+                if Keycode::from_name(key).is_none() {
+                    bail!("ps1.keyboard_profiles.{name} has unknown key `{key}`");
+                }
+            }
+        }
+        if !SAMPLE_RATE_RANGE.contains(&self.c64.sample_rate) {
+            bail!(
+                "c64.sample_rate must be between {} and {}, got {}",
+                SAMPLE_RATE_RANGE.start(),
+                SAMPLE_RATE_RANGE.end(),
+                self.c64.sample_rate
+            );
+        }
+        let ports = std::iter::once(("c64.joystick_port".to_string(), self.c64.joystick_port))
+            .chain(self.c64.games.iter().filter_map(|(name, game)| {
+                Some((
+                    format!("c64.games.{name}.joystick_port"),
+                    game.joystick_port?,
+                ))
+            }));
+        for (key, port) in ports {
+            if !(1..=2).contains(&port) {
+                bail!("{key} must be 1 or 2, got {port}");
+            }
+        }
+        if !(1..=4).contains(&self.wii.remotes) {
+            bail!(
+                "wii.remotes must be between 1 and 4, got {}",
+                self.wii.remotes
+            );
+        }
+        if !TURBO_RATE_RANGE.contains(&self.controllers.turbo_rate) {
+            bail!(
+                "controllers.turbo_rate must be between {} and {}, got {}",
+                TURBO_RATE_RANGE.start(),
+                TURBO_RATE_RANGE.end(),
+                self.controllers.turbo_rate
+            );
+        }
+        for guid in self.controllers.pads.keys() {
+            if guid.len() != 32 || !guid.chars().all(|c| c.is_ascii_hexdigit()) {
+                bail!("controllers.pads.{guid} must be a 32 digit SDL controller GUID");
+            }
+        }
+        let tunings =
+            std::iter::once(("controllers".to_string(), self.controllers.stick_tuning(""))).chain(
+                self.controllers.pads.keys().map(|guid| {
+                    (
+                        format!("controllers.pads.{guid}"),
+                        self.controllers.stick_tuning(guid),
+                    )
+                }),
+            );
+        for (key, tuning) in tunings {
+            if !(0.0..tuning.saturation).contains(&tuning.deadzone) || tuning.saturation > 1.0 {
+                bail!(
+                    "{key} needs 0 <= deadzone < saturation <= 1, got deadzone {} and saturation {}",
+                    tuning.deadzone,
+                    tuning.saturation
+                );
+            }
+            if tuning.response <= 0.0 {
+                bail!("{key}.response must be above 0, got {}", tuning.response);
+            }
+        }
+        for (guid, layout) in &self.controllers.joysticks {
+            if guid != "default"
+                && (guid.len() != 32 || !guid.chars().all(|c| c.is_ascii_hexdigit()))
+            {
+                bail!(
+                    "controllers.joysticks.{guid} must be a 32 digit SDL joystick GUID or default"
+                );
+            }
+            for (input, binding) in layout {
+                if joystick_field(input).is_none() {
+                    bail!("controllers.joysticks.{guid} has unknown input `{input}`");
+                }
+                if !valid_joystick_binding(binding) {
+                    bail!(
+                        "controllers.joysticks.{guid}.{input} must be like b3, h0.1, a2, +a2 or -a2, got `{binding}`"
+                    );
+                }
+            }
+        }
+        for (name, input_macro) in &self.controllers.macros {
+            if input_macro.key.is_none() && input_macro.button.is_none() {
+                bail!("controllers.macros.{name} needs a key or a button to start it");
+            }
+            if let Some(key) = &input_macro.key
+                && Keycode::from_name(key).is_none()
+            {
+                bail!("controllers.macros.{name} has unknown key `{key}`");
+            }
+            if input_macro.steps.is_empty() {
+                bail!("controllers.macros.{name} has no steps");
+            }
+            for step in &input_macro.steps {
+                parse_macro_step(step)
+                    .with_context(|| format!("controllers.macros.{name} step `{step}`"))?;
+            }
+        }
+        let audio = &self.audio;
+        if !audio.buffer_samples.is_power_of_two() || !(64..=8192).contains(&audio.buffer_samples) {
+            bail!(
+                "audio.buffer_samples must be a power of two from 64 to 8192, got {}",
+                audio.buffer_samples
+            );
+        }
+        if !(10..=1000).contains(&audio.latency_ms) {
+            bail!(
+                "audio.latency_ms must be between 10 and 1000, got {}",
+                audio.latency_ms
+            );
+        }
+        if audio.volume > 100 {
+            bail!(
+                "audio.volume must be between 0 and 100, got {}",
+                audio.volume
+            );
+        }
+        if let Some(max) = audio.max_latency_ms
+            && !(audio.latency_ms..=5000).contains(&max)
+        {
+            bail!(
+                "audio.max_latency_ms must be between latency_ms ({}) and 5000, got {max}",
+                audio.latency_ms
+            );
+        }
+        if ![0, 90, 180, 270].contains(&self.display.rotate) {
+            bail!(
+                "display.rotate must be 0, 90, 180 or 270, got {}",
+                self.display.rotate
+            );
+        }
+        for key in self.display.systems.keys() {
+            if !GameSystem::ALL
+                .iter()
+                .any(|system| system.config_key() == key)
+            {
+                bail!(
+                    "display.systems.{key} is not a system, expected gb, nes, snes, nds, ps1, \
+                     n64, gamecube, wii or c64"
+                );
+            }
+        }
+        if let Some(factor) = self.display.scale_factor
+            && !(0.5..=4.0).contains(&factor)
+        {
+            bail!("display.scale_factor must be between 0.5 and 4, got {factor}");
+        }
+        for id in self.n64.games.keys() {
+            if id.len() != 4 || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+                bail!("n64.games.{id} must be a 4 character cartridge ID like NSME");
+            }
+        }
+        Ok(())
+    }
+}
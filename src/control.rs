@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tungstenite::Message;
+
+/// How long a client's thread waits for a message before passing on events
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// A command from a remote client, one JSON object per WebSocket text message:
+///
+/// - `{"cmd": "press", "button": "a"}` / `{"cmd": "release", "button": "a"}`
+/// - `{"cmd": "read", "address": 49152, "length": 16}`
+/// - `{"cmd": "save_state"}` / `{"cmd": "load_state"}`
+/// - `{"cmd": "screenshot"}`
+/// - `{"cmd": "watch", "address": 49152}` / `{"cmd": "unwatch", "address": 49152}`
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Command {
+    Press {
+        button: String,
+    },
+    Release {
+        button: String,
+    },
+    Read {
+        address: u16,
+        #[serde(default = "one")]
+        length: u16,
+    },
+    SaveState,
+    LoadState,
+    Screenshot,
+    Watch {
+        address: u16,
+    },
+    Unwatch {
+        address: u16,
+    },
+}
+
+fn one() -> u16 {
+    1
+}
+
+/// A command and the client to answer
+pub struct Request {
+    pub command: Command,
+    client: Sender<String>,
+}
+
+impl Request {
+    /// Sends `{"ok": true, ...fields}` or `{"ok": false, "error": ...}` back to the client
+    pub fn reply(self, result: Result<Value>) {
+        let reply = match result {
+            Ok(Value::Object(mut fields)) => {
+                fields.insert("ok".into(), Value::Bool(true));
+                Value::Object(fields)
+            }
+            Ok(_) => json!({ "ok": true }),
+            Err(err) => json!({ "ok": false, "error": format!("{err:#}") }),
+        };
+        let _ = self.client.send(reply.to_string());
+    }
+}
+
+/// WebSocket server started with `--control-port`. Clients send commands the frontend
+/// answers between frames, and get a `frame` event after every frame and a `watch`
+/// event whenever a watched address changes.
+pub struct ControlServer {
+    requests: Receiver<Request>,
+    clients: Arc<Mutex<Vec<Sender<String>>>>,
+    /// Watched addresses and their values after the last frame
+    watches: BTreeMap<u16, Option<u8>>,
+}
+
+impl ControlServer {
+    /// Listens on `127.0.0.1:port`, each client is served on its own thread
+    pub fn start(port: u16) -> Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .with_context(|| format!("failed to listen on port {port}"))?;
+        log::info!("Remote control on ws://127.0.0.1:{port}");
+        let (requests_tx, requests) = mpsc::channel();
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let client_list = clients.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let requests = requests_tx.clone();
+                let clients = client_list.clone();
+                thread::spawn(move || {
+                    if let Err(err) = serve(stream, requests, clients) {
+                        log::warn!("Remote control client dropped: {err:#}");
+                    }
+                });
+            }
+        });
+        Ok(Self {
+            requests,
+            clients,
+            watches: BTreeMap::new(),
+        })
+    }
+
+    /// Commands that came in since the last call
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.try_iter().collect()
+    }
+
+    /// Adds or removes a watch, which reports its value after the next frame
+    pub fn watch(&mut self, address: u16, enabled: bool) {
+        if enabled {
+            self.watches.insert(address, None);
+        } else {
+            self.watches.remove(&address);
+        }
+    }
+
+    /// Sends the events of a finished frame, reading watched memory with `peek`
+    pub fn end_frame(&mut self, frame: u64, peek: impl Fn(u16) -> u8) {
+        let mut events = vec![json!({ "event": "frame", "frame": frame })];
+        for (&address, last) in self.watches.iter_mut() {
+            let value = peek(address);
+            if *last != Some(value) {
+                *last = Some(value);
+                events.push(
+                    json!({ "event": "watch", "frame": frame, "address": address, "value": value }),
+                );
+            }
+        }
+        let Ok(mut clients) = self.clients.lock() else {
+            return;
+        };
+        for event in events {
+            let text = event.to_string();
+            clients.retain(|client| client.send(text.clone()).is_ok());
+        }
+    }
+}
+
+fn serve(
+    stream: TcpStream,
+    requests: Sender<Request>,
+    clients: Arc<Mutex<Vec<Sender<String>>>>,
+) -> Result<()> {
+    let peer = stream.peer_addr()?;
+    let mut socket =
+        tungstenite::accept(stream).map_err(|err| anyhow!("WebSocket handshake failed: {err}"))?;
+    socket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+    log::info!("Remote control client {peer} connected");
+    let (outbox, messages) = mpsc::channel();
+    clients
+        .lock()
+        .map_err(|_| anyhow!("client list poisoned"))?
+        .push(outbox.clone());
+    loop {
+        match socket.read() {
+            Ok(message) if message.is_text() => {
+                match serde_json::from_str::<Command>(message.to_text()?) {
+                    Ok(command) => {
+                        let request = Request {
+                            command,
+                            client: outbox.clone(),
+                        };
+                        if requests.send(request).is_err() {
+                            // the game was closed
+                            return Ok(());
+                        }
+                    }
+                    Err(err) => {
+                        let reply = json!({ "ok": false, "error": format!("bad command: {err}") });
+                        outbox.send(reply.to_string())?;
+                    }
+                }
+            }
+            Ok(message) if message.is_close() => break,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => break,
+            Err(err) => return Err(err.into()),
+        }
+        for text in messages.try_iter() {
+            socket.send(Message::text(text))?;
+        }
+    }
+    log::info!("Remote control client {peer} disconnected");
+    Ok(())
+}
@@ -1,13 +1,29 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
 
-use anyhow::{Result, anyhow};
-use sdl2::GameControllerSubsystem;
+use anyhow::{Result, anyhow, bail};
 use sdl2::controller::{Axis, Button as SdlButton, GameController};
 use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::{GameControllerSubsystem, JoystickSubsystem};
+use serde::Deserialize;
+use serde::de::IntoDeserializer;
+use serde::de::value::Error as ValueError;
 
-const STICK_DEADZONE: i16 = 9_000;
+use crate::config::ControllersConfig;
+use crate::present;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// SDL_GameControllerDB mappings shipped next to `config.toml`, so generic pads SDL does not
+/// know yet still count as game controllers
+const BUNDLED_MAPPINGS: &str = "gamecontrollerdb.txt";
+/// How far the tuned left stick has to be pushed to press a d-pad direction
+const STICK_DIRECTION_THRESHOLD: f32 = 0.24;
+/// Emulated frames per second turbo counts `turbo_rate` in, the rate of NTSC machines. PAL
+/// games fire a sixth slower.
+const TURBO_FRAME_RATE: u64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum VirtualButton {
     Up,
     Down,
@@ -21,6 +37,8 @@ pub enum VirtualButton {
     R,
     Start,
     Select,
+    LeftStick,
+    RightStick,
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -33,14 +51,212 @@ pub struct ControllerAnalogState {
     pub right_trigger: f32,
 }
 
+/// Deadzone, saturation and response curve of a pad's sticks, from `[controllers]`
+#[derive(Debug, Clone, Copy)]
+pub struct StickTuning {
+    pub deadzone: f32,
+    pub saturation: f32,
+    pub response: f32,
+}
+
+impl StickTuning {
+    /// Maps a raw axis to -1..1, with the deadzone cut out and the rest stretched to fill it
+    fn apply(&self, value: i16) -> f32 {
+        let normalized = (value as f32 / i16::MAX as f32).clamp(-1.0, 1.0);
+        let travel = normalized.abs();
+        if travel < self.deadzone {
+            return 0.0;
+        }
+        let scaled = ((travel - self.deadzone) / (self.saturation - self.deadzone)).min(1.0);
+        scaled.powf(self.response).copysign(normalized)
+    }
+}
+
 pub struct ControllerManager {
     subsystem: GameControllerSubsystem,
-    controllers: HashMap<u32, ControllerDevice>,
+    /// Looks up joysticks that are not game controllers yet
+    joysticks: JoystickSubsystem,
+    config: ControllersConfig,
+    /// Keyed by instance id, which SDL hands out in connection order
+    controllers: BTreeMap<u32, ControllerDevice>,
+    /// Instance id of the pad each player slot is driven by. A new pad takes the first free
+    /// slot and one that disconnects leaves its slot free, so the other players keep theirs.
+    slots: Vec<Option<u32>>,
+    /// A pad connected or disconnected since the last [`Self::take_pads_changed`]
+    pads_changed: bool,
     pressed_counts: HashMap<VirtualButton, u32>,
+    /// Emulated frames since the manager opened, turbo buttons blink on and off in step
+    frame: u64,
+    macros: Vec<InputMacro>,
+    /// Index into `macros` and frames played of each running macro, triggered ones wait at 0
+    playing: Vec<(usize, u32)>,
+    /// Buttons the running macros hold this frame
+    macro_buttons: HashSet<VirtualButton>,
+}
+
+/// A `[controllers.macros]` sequence and what starts it
+struct InputMacro {
+    name: String,
+    key: Option<Keycode>,
+    button: Option<VirtualButton>,
+    steps: Vec<(Vec<VirtualButton>, u32)>,
+}
+
+impl InputMacro {
+    /// Buttons held `frame` frames in, `None` once the sequence is over
+    fn buttons_at(&self, frame: u32) -> Option<&[VirtualButton]> {
+        let mut start = 0;
+        for (buttons, frames) in &self.steps {
+            start += frames;
+            if frame < start {
+                return Some(buttons);
+            }
+        }
+        None
+    }
+}
+
+/// Parses a macro step like `down+right 2` or `none 10` into its buttons and frame count
+pub fn parse_macro_step(step: &str) -> Result<(Vec<VirtualButton>, u32)> {
+    let mut words = step.split_whitespace();
+    let buttons = words.next().ok_or_else(|| anyhow!("empty step"))?;
+    let frames = match words.next() {
+        Some(count) => count
+            .parse()
+            .ok()
+            .filter(|&frames| frames > 0)
+            .ok_or_else(|| anyhow!("`{count}` is not a number of frames"))?,
+        None => 1,
+    };
+    if words.next().is_some() {
+        bail!("expected buttons and an optional frame count");
+    }
+    if buttons.eq_ignore_ascii_case("none") {
+        return Ok((Vec::new(), frames));
+    }
+    let buttons = buttons
+        .split('+')
+        .map(|name| {
+            VirtualButton::deserialize(name.to_ascii_lowercase().into_deserializer())
+                .map_err(|_: ValueError| anyhow!("unknown button `{name}`"))
+        })
+        .collect::<Result<_>>()?;
+    Ok((buttons, frames))
+}
+
+/// How a pad input is wired on the underlying SDL joystick, from the pad's mapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoystickBinding {
+    Button(u32),
+    /// A whole axis, `inverted` when the pad reports it the other way around
+    Axis {
+        index: u32,
+        inverted: bool,
+    },
+    /// One direction of an axis, such as a trigger sharing an axis with another
+    HalfAxis {
+        index: u32,
+        positive: bool,
+    },
+    /// A hat direction: 1 up, 2 right, 4 down, 8 left
+    Hat {
+        index: u32,
+        mask: u8,
+    },
+}
+
+/// The SDL joystick behind a pad, for cores that open joysticks themselves
+pub struct PadJoystick {
+    /// Joystick index at the time the pad was connected
+    pub device_index: u32,
+    pub name: String,
+    pub rumble: bool,
+    mapping: String,
+}
+
+impl PadJoystick {
+    pub fn button_binding(&self, button: SdlButton) -> Option<JoystickBinding> {
+        self.binding(&button.string())
+    }
+
+    pub fn axis_binding(&self, axis: Axis) -> Option<JoystickBinding> {
+        self.binding(&axis.string())
+    }
+
+    fn binding(&self, field: &str) -> Option<JoystickBinding> {
+        // `guid,name,a:b0,leftx:a0,dpup:h0.1,lefttrigger:+a2,...`
+        let value = self.mapping.split(',').skip(2).find_map(|entry| {
+            entry
+                .split_once(':')
+                .filter(|(name, _)| *name == field)
+                .map(|(_, value)| value)
+        })?;
+        parse_binding(value)
+    }
+}
+
+/// The SDL mapping field of a `[controllers.joysticks]` input name
+pub fn joystick_field(input: &str) -> Option<&'static str> {
+    Some(match input {
+        "up" => "dpup",
+        "down" => "dpdown",
+        "left" => "dpleft",
+        "right" => "dpright",
+        "a" => "a",
+        "b" => "b",
+        "x" => "x",
+        "y" => "y",
+        "l" => "leftshoulder",
+        "r" => "rightshoulder",
+        "start" => "start",
+        "select" => "back",
+        "left-stick" => "leftstick",
+        "right-stick" => "rightstick",
+        "left-x" => "leftx",
+        "left-y" => "lefty",
+        "right-x" => "rightx",
+        "right-y" => "righty",
+        "left-trigger" => "lefttrigger",
+        "right-trigger" => "righttrigger",
+        _ => return None,
+    })
+}
+
+/// Whether `value` is a joystick input in SDL mapping syntax
+pub fn valid_joystick_binding(value: &str) -> bool {
+    parse_binding(value).is_some()
+}
+
+fn parse_binding(value: &str) -> Option<JoystickBinding> {
+    let (half, value) = match value.as_bytes().first()? {
+        b'+' => (Some(true), &value[1..]),
+        b'-' => (Some(false), &value[1..]),
+        _ => (None, value),
+    };
+    let (value, inverted) = match value.strip_suffix('~') {
+        Some(value) => (value, true),
+        None => (value, false),
+    };
+    if let Some(index) = value.strip_prefix('b') {
+        return index.parse().ok().map(JoystickBinding::Button);
+    }
+    if let Some(index) = value.strip_prefix('a') {
+        let index = index.parse().ok()?;
+        return Some(match half {
+            Some(positive) => JoystickBinding::HalfAxis { index, positive },
+            None => JoystickBinding::Axis { index, inverted },
+        });
+    }
+    let (index, mask) = value.strip_prefix('h')?.split_once('.')?;
+    Some(JoystickBinding::Hat {
+        index: index.parse().ok()?,
+        mask: mask.parse().ok()?,
+    })
 }
 
 struct ControllerDevice {
-    _controller: GameController,
+    controller: GameController,
+    device_index: u32,
     buttons: HashSet<VirtualButton>,
     axis_buttons: HashSet<VirtualButton>,
     left_x: i16,
@@ -49,15 +265,58 @@ struct ControllerDevice {
     right_y: i16,
     left_trigger: i16,
     right_trigger: i16,
+    tuning: StickTuning,
 }
 
 impl ControllerManager {
-    pub fn new(sdl: &sdl2::Sdl) -> Result<Self> {
+    /// Opens the pads with the `[controllers]` settings
+    pub fn new(sdl: &sdl2::Sdl, config: &ControllersConfig) -> Result<Self> {
         let subsystem = sdl.game_controller().map_err(|err| anyhow!(err))?;
+        let joysticks = sdl.joystick().map_err(|err| anyhow!(err))?;
+        let bundled = Path::new(BUNDLED_MAPPINGS);
+        let mappings = bundled
+            .exists()
+            .then_some(bundled)
+            .into_iter()
+            .chain(config.mappings.as_deref());
+        for path in mappings {
+            match subsystem.load_mappings(path) {
+                Ok(count) => {
+                    log::info!("Loaded {count} controller mappings from {}", path.display())
+                }
+                Err(err) => log::warn!(
+                    "Failed to load controller mappings from {}: {err}",
+                    path.display()
+                ),
+            }
+        }
+        let macros = config
+            .macros
+            .iter()
+            .map(|(name, input_macro)| InputMacro {
+                name: name.clone(),
+                key: input_macro.key.as_deref().and_then(Keycode::from_name),
+                button: input_macro.button,
+                // the config was validated on load, so every step parses
+                steps: input_macro
+                    .steps
+                    .iter()
+                    .filter_map(|step| parse_macro_step(step).ok())
+                    .collect(),
+            })
+            .collect();
         let mut manager = Self {
             subsystem,
-            controllers: HashMap::new(),
+            joysticks,
+            config: config.clone(),
+            controllers: BTreeMap::new(),
+            slots: Vec::new(),
+            pads_changed: false,
             pressed_counts: HashMap::new(),
+            frame: 0,
+            macros,
+            playing: Vec::new(),
+            macro_buttons: HashSet::new(),
         };
         manager.scan_existing()?;
         Ok(manager)
@@ -68,12 +327,17 @@ impl ControllerManager {
             Event::ControllerDeviceAdded { which, .. } => {
                 let _ = self.open_controller(which);
             }
+            // game controllers are opened on the controller event above
+            Event::JoyDeviceAdded { which, .. } if !self.subsystem.is_game_controller(which) => {
+                let _ = self.open_controller(which);
+            }
             Event::ControllerDeviceRemoved { which, .. } => {
                 self.remove_controller(which);
             }
             Event::ControllerButtonDown { which, button, .. } => {
                 if let Some(vb) = map_button(button) {
                     self.set_button_state(which, vb, true, false);
+                    self.trigger_macros(|input_macro| input_macro.button == Some(vb));
                 }
             }
             Event::ControllerButtonUp { which, button, .. } => {
@@ -86,30 +350,177 @@ impl ControllerManager {
             } => {
                 self.handle_axis(which, axis, value);
             }
+            Event::KeyDown {
+                keycode: Some(code),
+                repeat: false,
+                ..
+            } => {
+                self.trigger_macros(|input_macro| input_macro.key == Some(code));
+            }
             _ => {}
         }
     }
 
     pub fn is_pressed(&self, button: VirtualButton) -> bool {
-        self.pressed_counts
-            .get(&button)
+        let held = self
+            .pressed_counts
+            .get(&present::screen_direction(button))
             .copied()
             .unwrap_or_default()
-            > 0
+            > 0;
+        self.turbo(button, held) || self.macro_buttons.contains(&button)
+    }
+
+    /// Moves turbo on by one emulated frame. Frontends call this once before reading each
+    /// frame's input, so autofire keeps the same rhythm at any speed and repeats exactly in
+    /// `--deterministic` runs; turbo buttons count as held in cores that do not.
+    pub fn advance_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Moves the running macros on to the next frame, their buttons then count as pressed
+    /// in [`Self::is_pressed`]. Frontends call this once before reading each frame's input,
+    /// macros never start in cores that do not.
+    pub fn advance_macros(&mut self) {
+        self.macro_buttons.clear();
+        let macros = &self.macros;
+        let macro_buttons = &mut self.macro_buttons;
+        self.playing.retain_mut(|(index, frame)| {
+            let input_macro = &macros[*index];
+            match input_macro.buttons_at(*frame) {
+                Some(buttons) => {
+                    macro_buttons.extend(buttons.iter().copied());
+                    *frame += 1;
+                    true
+                }
+                None => {
+                    log::debug!("Macro {} finished", input_macro.name);
+                    false
+                }
+            }
+        });
+    }
+
+    /// Starts the macros `matches` picks, unless they are already running
+    fn trigger_macros(&mut self, matches: impl Fn(&InputMacro) -> bool) {
+        for (index, input_macro) in self.macros.iter().enumerate() {
+            if matches(input_macro) && !self.playing.iter().any(|&(playing, _)| playing == index) {
+                log::debug!("Macro {} started", input_macro.name);
+                self.playing.push((index, 0));
+            }
+        }
+    }
+
+    /// Like [`Self::is_pressed`] for the pad in one player slot, see [`Self::swap_pads`].
+    pub fn is_pad_pressed(&self, pad: usize, button: VirtualButton) -> bool {
+        let physical = present::screen_direction(button);
+        let held = self.pad(pad).is_some_and(|device| {
+            device.buttons.contains(&physical) || device.axis_buttons.contains(&physical)
+        });
+        self.turbo(button, held)
+    }
+
+    /// Like [`Self::is_pad_pressed`] but ignores directions synthesized from the left stick,
+    /// for cores that read the stick separately.
+    pub fn is_pad_button_pressed(&self, pad: usize, button: VirtualButton) -> bool {
+        let held = self
+            .pad(pad)
+            .is_some_and(|device| device.buttons.contains(&present::screen_direction(button)));
+        self.turbo(button, held)
+    }
+
+    /// Whether a `held` button counts as pressed this frame. Buttons listed in
+    /// `[controllers] turbo` are released for every other half of the turbo period, counted
+    /// in the frames [`Self::advance_frame`] moves on. Pad queries go through this already
+    /// and frontends pass their keyboard keys through it.
+    pub fn turbo(&self, button: VirtualButton, held: bool) -> bool {
+        if !held || !self.config.turbo.contains(&button) {
+            return held;
+        }
+        let half_periods = self.frame * u64::from(self.config.turbo_rate) * 2 / TURBO_FRAME_RATE;
+        half_periods.is_multiple_of(2)
+    }
+
+    /// Drives the rumble motors of one pad, `duration_ms` bounds how long the request
+    /// lasts if it is not refreshed.
+    pub fn set_pad_rumble(
+        &mut self,
+        pad: usize,
+        low_frequency: u16,
+        high_frequency: u16,
+        duration_ms: u32,
+    ) {
+        let id = self.slots.get(pad).copied().flatten();
+        if let Some(device) = id.and_then(|id| self.controllers.get_mut(&id)) {
+            // pads without motors report an error, which is fine to ignore
+            let _ = device
+                .controller
+                .set_rumble(low_frequency, high_frequency, duration_ms);
+        }
+    }
+
+    /// Drives both motors of every pad at `strength` from 0 to 1, for cores with a single
+    /// force feedback source such as a rumble cartridge. `0.0` stops them.
+    pub fn rumble(&mut self, strength: f32, duration_ms: u32) {
+        let level = (strength.clamp(0.0, 1.0) * f32::from(u16::MAX)).round() as u16;
+        for pad in 0..self.slots.len() {
+            self.set_pad_rumble(pad, level, level, duration_ms);
+        }
     }
 
     pub fn analog_state(&self) -> Option<ControllerAnalogState> {
-        self.controllers
-            .values()
-            .next()
-            .map(|device| ControllerAnalogState {
-                left_x: normalize_axis(device.left_x),
-                left_y: normalize_axis(device.left_y),
-                right_x: normalize_axis(device.right_x),
-                right_y: normalize_axis(device.right_y),
-                left_trigger: normalize_trigger(device.left_trigger),
-                right_trigger: normalize_trigger(device.right_trigger),
-            })
+        self.pad_analog_state(0)
+    }
+
+    pub fn pad_analog_state(&self, pad: usize) -> Option<ControllerAnalogState> {
+        self.pad(pad).map(|device| ControllerAnalogState {
+            left_x: device.tuning.apply(device.left_x),
+            left_y: device.tuning.apply(device.left_y),
+            right_x: device.tuning.apply(device.right_x),
+            right_y: device.tuning.apply(device.right_y),
+            left_trigger: normalize_trigger(device.left_trigger),
+            right_trigger: normalize_trigger(device.right_trigger),
+        })
+    }
+
+    pub fn pad_joystick(&self, pad: usize) -> Option<PadJoystick> {
+        self.pad(pad).map(|device| PadJoystick {
+            device_index: device.device_index,
+            name: device.controller.name(),
+            rumble: device.controller.has_rumble(),
+            mapping: device.controller.mapping(),
+        })
+    }
+
+    /// Name of the pad driving player slot `pad`, `None` for a free slot
+    pub fn pad_name(&self, pad: usize) -> Option<String> {
+        self.pad(pad).map(|device| device.controller.name())
+    }
+
+    /// Swaps the pads of two player slots, either of which may be free
+    pub fn swap_pads(&mut self, a: usize, b: usize) {
+        let len = a.max(b) + 1;
+        if self.slots.len() < len {
+            self.slots.resize(len, None);
+        }
+        self.slots.swap(a, b);
+        self.trim_slots();
+    }
+
+    /// Whether a pad connected or disconnected since the last call
+    pub fn take_pads_changed(&mut self) -> bool {
+        std::mem::take(&mut self.pads_changed)
+    }
+
+    fn pad(&self, pad: usize) -> Option<&ControllerDevice> {
+        let id = self.slots.get(pad).copied().flatten()?;
+        self.controllers.get(&id)
+    }
+
+    fn trim_slots(&mut self) {
+        while self.slots.last() == Some(&None) {
+            self.slots.pop();
+        }
     }
 
     fn scan_existing(&mut self) -> Result<()> {
@@ -121,7 +532,7 @@ impl ControllerManager {
     }
 
     fn open_controller(&mut self, device_index: u32) -> Result<()> {
-        if !self.subsystem.is_game_controller(device_index) {
+        if !self.subsystem.is_game_controller(device_index) && !self.map_joystick(device_index) {
             return Ok(());
         }
         let controller = self
@@ -129,10 +540,19 @@ impl ControllerManager {
             .open(device_index)
             .map_err(|err| anyhow!(err))?;
         let instance_id = controller.instance_id();
+        // adding a joystick mapping announces the device again
+        if self.controllers.contains_key(&instance_id) {
+            return Ok(());
+        }
+        let mapping = controller.mapping();
+        let guid = mapping.split(',').next().unwrap_or_default();
+        log::info!("Controller {} connected (GUID {guid})", controller.name());
+        let tuning = self.config.stick_tuning(guid);
         self.controllers.insert(
             instance_id,
             ControllerDevice {
-                _controller: controller,
+                controller,
+                device_index,
                 buttons: HashSet::new(),
                 axis_buttons: HashSet::new(),
                 left_x: 0,
@@ -141,16 +561,72 @@ impl ControllerManager {
                 right_y: 0,
                 left_trigger: 0,
                 right_trigger: 0,
+                tuning,
             },
         );
+        match self.slots.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => *slot = Some(instance_id),
+            None => self.slots.push(Some(instance_id)),
+        }
+        self.pads_changed = true;
         Ok(())
     }
 
+    /// Gives a joystick SDL has no controller mapping for its `[controllers.joysticks]`
+    /// layout, after which it opens as a game controller like any other pad
+    fn map_joystick(&self, device_index: u32) -> bool {
+        let Ok(guid) = self.joysticks.device_guid(device_index) else {
+            return false;
+        };
+        let guid = guid.string();
+        let name = self
+            .joysticks
+            .name_for_index(device_index)
+            .unwrap_or_default();
+        let layout = self
+            .config
+            .joysticks
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&guid))
+            .or_else(|| self.config.joysticks.get_key_value("default"))
+            .map(|(_, layout)| layout);
+        let Some(layout) = layout else {
+            log::info!(
+                "Joystick {name} is not a game controller, map it under [controllers.joysticks.{guid}] to use it"
+            );
+            return false;
+        };
+        let fields: String = layout
+            .iter()
+            .filter_map(|(input, binding)| Some(format!("{}:{binding},", joystick_field(input)?)))
+            .collect();
+        // commas separate the fields of a mapping
+        let mapping = format!("{guid},{},{fields}", name.replace(',', " "));
+        match self.subsystem.add_mapping(&mapping) {
+            Ok(_) => {
+                log::info!("Joystick {name} mapped from [controllers.joysticks]");
+                self.subsystem.is_game_controller(device_index)
+            }
+            Err(err) => {
+                log::warn!("Failed to map joystick {name}: {err}");
+                false
+            }
+        }
+    }
+
     fn remove_controller(&mut self, instance_id: u32) {
         if let Some(device) = self.controllers.remove(&instance_id) {
+            log::info!("Controller {} disconnected", device.controller.name());
             for button in device.buttons.union(&device.axis_buttons) {
                 self.decrement(*button);
             }
+            for slot in &mut self.slots {
+                if *slot == Some(instance_id) {
+                    *slot = None;
+                }
+            }
+            self.trim_slots();
+            self.pads_changed = true;
         }
     }
 
@@ -218,7 +694,10 @@ impl ControllerManager {
                     return;
                 }
             }
-            let target = axis_to_buttons(device.left_x, device.left_y);
+            let target = axis_to_buttons(
+                device.tuning.apply(device.left_x),
+                device.tuning.apply(device.left_y),
+            );
             let old = device.axis_buttons.clone();
             device.axis_buttons = target.clone();
             (old, target)
@@ -232,30 +711,21 @@ impl ControllerManager {
     }
 }
 
-fn axis_to_buttons(x: i16, y: i16) -> HashSet<VirtualButton> {
+fn axis_to_buttons(x: f32, y: f32) -> HashSet<VirtualButton> {
     let mut set = HashSet::new();
-    if x <= -STICK_DEADZONE {
+    if x <= -STICK_DIRECTION_THRESHOLD {
         set.insert(VirtualButton::Left);
-    } else if x >= STICK_DEADZONE {
+    } else if x >= STICK_DIRECTION_THRESHOLD {
         set.insert(VirtualButton::Right);
     }
-    if y <= -STICK_DEADZONE {
+    if y <= -STICK_DIRECTION_THRESHOLD {
         set.insert(VirtualButton::Up);
-    } else if y >= STICK_DEADZONE {
+    } else if y >= STICK_DIRECTION_THRESHOLD {
         set.insert(VirtualButton::Down);
     }
     set
 }
 
-fn normalize_axis(value: i16) -> f32 {
-    let normalized = (value as f32) / (i16::MAX as f32);
-    if normalized.abs() < 0.05 {
-        0.0
-    } else {
-        normalized.clamp(-1.0, 1.0)
-    }
-}
-
 fn normalize_trigger(value: i16) -> f32 {
     if value <= 0 {
         0.0
@@ -275,6 +745,8 @@ fn map_button(button: SdlButton) -> Option<VirtualButton> {
         SdlButton::Guide => Some(VirtualButton::Start),
         SdlButton::LeftShoulder => Some(VirtualButton::L),
         SdlButton::RightShoulder => Some(VirtualButton::R),
+        SdlButton::LeftStick => Some(VirtualButton::LeftStick),
+        SdlButton::RightStick => Some(VirtualButton::RightStick),
         SdlButton::DPadUp => Some(VirtualButton::Up),
         SdlButton::DPadDown => Some(VirtualButton::Down),
         SdlButton::DPadLeft => Some(VirtualButton::Left),
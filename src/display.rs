@@ -28,6 +28,10 @@ impl FrameBuffer {
         }
     }
 
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
     pub fn as_bytes(&self) -> &[u8] {
         cast_slice(&self.pixels)
     }
@@ -0,0 +1,68 @@
+use std::thread;
+use std::time::Duration;
+
+use sdl2::event::{Event, WindowEvent};
+
+use crate::config::DisplayConfig;
+
+/// How long a paused frontend sleeps before looking at its events again
+const PAUSED_POLL: Duration = Duration::from_millis(50);
+
+/// Sleeps a moment in place of a frame, frontends call this while paused so they keep
+/// handling events without spinning
+pub fn idle() {
+    thread::sleep(PAUSED_POLL);
+}
+
+/// Follows whether a frontend's windows have the keyboard focus and pauses the game while none
+/// does. A frontend with two windows sees the focus leave one and reach the other in the same
+/// batch of events, so it only pauses once every event of a frame is handled.
+#[derive(Debug)]
+pub struct FocusPause {
+    focused: bool,
+    /// `[display] pause_unfocused` or `--pause-unfocused`
+    enabled: bool,
+}
+
+impl FocusPause {
+    pub fn new(display: &DisplayConfig) -> Self {
+        Self {
+            focused: true,
+            enabled: display.pause_unfocused,
+        }
+    }
+
+    pub fn set_focused(&mut self, focused: bool) {
+        if focused != self.focused && self.enabled {
+            log::info!(
+                "{}",
+                if focused {
+                    "Resumed"
+                } else {
+                    "Paused while the window is in the background"
+                }
+            );
+        }
+        self.focused = focused;
+    }
+
+    /// Follows the focus events of SDL windows
+    pub fn handle_sdl_event(&mut self, event: &Event) {
+        match event {
+            Event::Window {
+                win_event: WindowEvent::FocusGained,
+                ..
+            } => self.set_focused(true),
+            Event::Window {
+                win_event: WindowEvent::FocusLost,
+                ..
+            } => self.set_focused(false),
+            _ => {}
+        }
+    }
+
+    /// Whether the game should wait instead of running its next frame
+    pub fn is_paused(&self) -> bool {
+        !self.focused && self.enabled
+    }
+}
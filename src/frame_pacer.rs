@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+use std::{hint, thread};
+
+use sdl2::video::Window;
+
+use crate::config::DisplayConfig;
+
+/// Frames a frontend may fall behind and still catch up by running without waiting, past that
+/// (a stall while loading, a window drag) it starts over from now
+const MAX_LAG_FRAMES: u32 = 3;
+/// Sleeps wake up this early and spin the rest, `thread::sleep` often oversleeps by that much
+const SPIN: Duration = Duration::from_millis(1);
+/// A display this close to the frame rate is left to pace a vsynced present
+const VSYNC_TOLERANCE: f64 = 0.01;
+
+/// Paces a frontend's frames to the console's rate against absolute deadlines, so waking up a
+/// little late on one frame is made up on the next instead of adding up to drift. With a
+/// vsynced present on a display refreshing at the frame rate, the present does the waiting
+/// and the pacer only steps in when vsync does not block.
+pub struct FramePacer {
+    frame: Duration,
+    limit: bool,
+    vsync: bool,
+    deadline: Instant,
+}
+
+impl FramePacer {
+    /// Paces frames of `frame` each, `limit` false runs as fast as the frontend can
+    pub fn new(frame: Duration, limit: bool) -> Self {
+        Self {
+            frame,
+            limit,
+            vsync: false,
+            deadline: Instant::now(),
+        }
+    }
+
+    /// Lets a vsynced present pace the frames when `window`'s display refreshes at the frame
+    /// rate, sleeping on top of it would only miss refreshes
+    pub fn vsynced(mut self, window: &Window, display: &DisplayConfig) -> Self {
+        let fps = 1.0 / self.frame.as_secs_f64();
+        self.vsync = self.limit
+            && !display.vrr
+            && window.display_mode().is_ok_and(|mode| {
+                (f64::from(mode.refresh_rate) - fps).abs() <= fps * VSYNC_TOLERANCE
+            });
+        self
+    }
+
+    /// Changes the frame rate, for cores that switch video modes
+    pub fn set_frame(&mut self, frame: Duration) {
+        self.frame = frame;
+    }
+
+    /// Waits until the next frame is due, call once per frame after presenting it
+    pub fn wait(&mut self) {
+        if !self.limit {
+            return;
+        }
+        self.deadline += self.frame;
+        let now = Instant::now();
+        if now > self.deadline + self.frame * MAX_LAG_FRAMES {
+            self.deadline = now;
+            return;
+        }
+        // a present that waited for vsync is on time already, this only catches a driver
+        // that ignores vsync from racing ahead
+        let wake = if self.vsync {
+            self.deadline - self.frame
+        } else {
+            self.deadline
+        };
+        loop {
+            let now = Instant::now();
+            if now >= wake {
+                break;
+            }
+            if wake - now > SPIN {
+                thread::sleep(wake - now - SPIN);
+            } else {
+                hint::spin_loop();
+            }
+        }
+    }
+}
@@ -1,17 +1,14 @@
 use std::collections::HashSet;
 use std::env;
 use std::f32::consts::TAU;
-use std::fs::{self, File};
-use std::io::Read;
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use bitflags::bitflags;
 use font8x8::legacy::BASIC_LEGACY;
-use rvz::Rvz;
-use sdl2::audio::{AudioQueue, AudioSpecDesired};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
@@ -20,20 +17,44 @@ use sdl2::video::Window;
 
 use which::which;
 
+use crate::audio::AudioPlayer;
+use crate::bezel::Bezel;
+use crate::config::{Config, DisplayConfig, Fullscreen, GamecubeConfig};
 use crate::controller::{ControllerManager, VirtualButton};
-
+use crate::focus_pause::{self, FocusPause};
+use crate::frame_pacer::FramePacer;
+use crate::gc_banner;
+use crate::gc_disc::{self, DiscImage};
+use crate::gc_hw::Homebrew;
+use crate::gc_input;
+use crate::gc_memcard;
+use crate::libretro;
+use crate::osd;
+use crate::perf_overlay::PerfOverlay;
+use crate::present;
+use crate::saves;
+use crate::systems::GameSystem;
+
+/// Dolphin's libretro core looks for its `dolphin-emu/Sys` data here
+const SYSTEM_DIR: &str = "bios/gc";
+/// `boot.bin`, the disc header with the game ID and title
+const BOOT_HEADER_SIZE: usize = 0x440;
 const DEFAULT_WIDTH: u32 = 640;
 const DEFAULT_HEIGHT: u32 = 480;
 const TARGET_FRAME: Duration = Duration::from_micros(16_667);
 const AUDIO_SAMPLE_RATE: i32 = 48_000;
 const AUDIO_CHANNELS: u16 = 2;
-const AUDIO_BUFFER_SAMPLES: u16 = 1024;
-const MAX_AUDIO_LATENCY_BYTES: u32 =
-    (AUDIO_SAMPLE_RATE as u32) * (AUDIO_CHANNELS as u32) * std::mem::size_of::<i16>() as u32;
 
-fn try_launch_dolphin(rom_path: &Path) -> Result<bool> {
+/// Hands the game to an installed Dolphin, `system_args` gives the GameCube or Wii
+/// specific settings once Dolphin is found
+pub fn try_launch_dolphin(
+    rom_path: &Path,
+    load_state: Option<&Path>,
+    config: &Config,
+    system_args: impl FnOnce() -> Result<Vec<String>>,
+) -> Result<bool> {
     let Some(binary) = resolve_dolphin_binary()? else {
-        log::debug!("Dolphin binary not found; falling back to built-in stub core");
+        log::debug!("Dolphin binary not found");
         return Ok(false);
     };
     log::info!(
@@ -45,6 +66,35 @@ fn try_launch_dolphin(rom_path: &Path) -> Result<bool> {
     if dolphin_supports_batch(&binary) {
         command.arg("-b");
     }
+    let settings = &config.gamecube;
+    if let Some(user_dir) = dolphin_user_dir(settings)? {
+        log::debug!("Using Dolphin user directory {}", user_dir.display());
+        match gc_input::write_profiles(&user_dir, &config.controllers) {
+            Ok(ports) => {
+                command.args(ports);
+            }
+            Err(err) => log::warn!("Keeping Dolphin's controller settings: {err:#}"),
+        }
+        command.arg("--user").arg(user_dir);
+    }
+    if let Some(backend) = &settings.video_backend {
+        command.arg(format!("--video_backend={backend}"));
+    }
+    if let Some(backend) = &settings.audio_backend {
+        command.arg(format!("--config=Dolphin.DSP.Backend={backend}"));
+    }
+    // Dolphin picks the kind of fullscreen itself
+    if settings.fullscreen || present::fullscreen() != Fullscreen::Off {
+        command.arg("--config=Dolphin.Display.Fullscreen=True");
+    }
+    if config.display.pause_unfocused {
+        command.arg("--config=Dolphin.Interface.PauseOnFocusLost=True");
+    }
+    if let Some(state) = load_state {
+        command.arg(format!("--save_state={}", state.display()));
+    }
+    command.args(system_args()?);
+    command.args(&settings.dolphin_args);
     let status = command
         .arg("-e")
         .arg(rom_path)
@@ -58,6 +108,23 @@ fn try_launch_dolphin(rom_path: &Path) -> Result<bool> {
     Ok(true)
 }
 
+fn dolphin_user_dir(config: &GamecubeConfig) -> Result<Option<PathBuf>> {
+    let Some(dir) = &config.user_dir else {
+        return Ok(None);
+    };
+    let dir = if dir.is_absolute() {
+        dir.clone()
+    } else {
+        dirs::config_dir()
+            .context("no config directory for gamecube.user_dir")?
+            .join("retro-launcher")
+            .join(dir)
+    };
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("failed to create Dolphin user directory {}", dir.display()))?;
+    Ok(Some(dir))
+}
+
 fn resolve_dolphin_binary() -> Result<Option<PathBuf>> {
     if let Ok(path) = env::var("DOLPHIN_BIN") {
         if !path.is_empty() {
@@ -110,54 +177,165 @@ fn dolphin_candidate_paths() -> Vec<PathBuf> {
     paths
 }
 
-fn load_gamecube_image(path: &Path) -> Result<Vec<u8>> {
-    match path
-        .extension()
-        .and_then(|s| s.to_str())
-        .map(|s| s.to_ascii_lowercase())
-        .as_deref()
+/// Runs the game in the launcher window on Dolphin's libretro core, when one is installed
+/// Runs the game on Dolphin's libretro core, `system` names the console in the window title
+pub fn try_launch_libretro(
+    rom_path: &Path,
+    scale: u32,
+    limit_fps: bool,
+    system: &str,
+    config: &Config,
+) -> Result<bool> {
+    let Some(core) = resolve_libretro_core()? else {
+        log::debug!("Dolphin libretro core not found");
+        return Ok(false);
+    };
+    log::info!(
+        "Running {} on the libretro core {}",
+        rom_path.display(),
+        core.display()
+    );
+    let title = game_title(rom_path);
+    libretro::run(
+        &core,
+        rom_path,
+        libretro::Session {
+            title: format!("{title} - {system}"),
+            scale,
+            limit_fps,
+            system_dir: PathBuf::from(SYSTEM_DIR),
+            // Dolphin keeps GameCube and Wii data in one tree, so both consoles share it
+            save_dir: saves::prepare(GameSystem::GameCube)?,
+            config,
+        },
+    )?;
+    Ok(true)
+}
+
+fn resolve_libretro_core() -> Result<Option<PathBuf>> {
+    if let Some(path) = env::var_os("DOLPHIN_LIBRETRO").filter(|path| !path.is_empty()) {
+        let core = PathBuf::from(path);
+        if !core.exists() {
+            anyhow::bail!(
+                "DOLPHIN_LIBRETRO points to {}, which does not exist",
+                core.display()
+            );
+        }
+        return Ok(Some(core));
+    }
+    let file_name = libloading::library_filename("dolphin_libretro");
+    Ok(libretro_core_dirs()
+        .into_iter()
+        .map(|dir| dir.join(&file_name))
+        .find(|path| path.exists()))
+}
+
+/// The launcher's own `cores/` directory, then where RetroArch installs cores
+fn libretro_core_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from("cores")];
+    if let Some(config) = dirs::config_dir() {
+        dirs.push(config.join("retroarch").join("cores"));
+        dirs.push(config.join("RetroArch").join("cores"));
+    }
+    #[cfg(target_os = "linux")]
+    {
+        dirs.push(PathBuf::from("/usr/lib/libretro"));
+        dirs.push(PathBuf::from("/usr/lib/x86_64-linux-gnu/libretro"));
+        dirs.push(PathBuf::from("/usr/local/lib/libretro"));
+    }
+    #[cfg(target_os = "windows")]
     {
-        Some("rvz") => load_rvz_image(path),
-        _ => fs::read(path)
-            .with_context(|| format!("failed to read GameCube image {}", path.display())),
+        dirs.push(PathBuf::from("C:\\RetroArch-Win64\\cores"));
     }
+    dirs
 }
 
-fn load_rvz_image(path: &Path) -> Result<Vec<u8>> {
-    let file =
-        File::open(path).with_context(|| format!("failed to open RVZ image {}", path.display()))?;
-    let mut rvz = Rvz::new(file).map_err(|err| anyhow::anyhow!(err))?;
-    let iso_size = rvz.metadata.header.iso_file_size;
-    if iso_size > (usize::MAX as u64) {
-        return Err(anyhow::anyhow!("RVZ image too large to load into memory"));
-    }
-    let mut buffer = Vec::with_capacity(iso_size as usize);
-    rvz.read_to_end(&mut buffer)
-        .with_context(|| format!("failed to decompress {}", path.display()))?;
-    Ok(buffer)
+/// Puts the memory cards under `saves/gamecube/` into both slots
+fn memory_card_args(rom_path: &Path) -> Result<Vec<String>> {
+    let region = read_game_code(rom_path)
+        .map(|code| gc_memcard::Region::from_game_code(&code))
+        .unwrap_or(gc_memcard::Region::Usa);
+    let [card_a, card_b] = gc_memcard::prepare_cards(region)?;
+    // Dolphin swaps the region in these names for the running game's own region
+    Ok(vec![
+        "--config=Dolphin.Core.SlotA=1".to_string(),
+        format!("--config=Dolphin.Core.MemcardAPath={}", card_a.display()),
+        "--config=Dolphin.Core.SlotB=1".to_string(),
+        format!("--config=Dolphin.Core.MemcardBPath={}", card_b.display()),
+    ])
+}
+
+/// The long title from the disc banner, the file name when the disc has none
+fn game_title(path: &Path) -> String {
+    match gc_banner::read(path) {
+        Ok(banner) if !banner.title.is_empty() => banner.title,
+        _ => path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("GameCube")
+            .to_string(),
+    }
 }
 
-pub fn run(rom_path: &Path, scale: u32, limit_fps: bool) -> Result<()> {
-    if try_launch_dolphin(rom_path)? {
+/// The game ID from the disc header
+fn read_game_code(path: &Path) -> Option<String> {
+    let mut disc = gc_disc::open(path).ok()?;
+    let code = gc_disc::read_at(disc.as_mut(), 0, 4).ok()?;
+    (code.len() == 4 && code.iter().all(u8::is_ascii_alphanumeric)).then(|| decode_ascii(&code))
+}
+
+pub fn run(
+    rom_path: &Path,
+    scale: u32,
+    limit_fps: bool,
+    load_state: Option<&Path>,
+    config: &Config,
+) -> Result<()> {
+    if let Some(state) = load_state {
+        anyhow::ensure!(
+            state.is_file(),
+            "save state {} does not exist",
+            state.display()
+        );
+    }
+    if try_launch_dolphin(rom_path, load_state, config, || memory_card_args(rom_path))? {
         return Ok(());
     }
-    let title = rom_path
+    if load_state.is_some() {
+        log::warn!("--gc-load-state only works with an installed Dolphin, ignoring it");
+    }
+    if try_launch_libretro(rom_path, scale, limit_fps, "GameCube", config)? {
+        return Ok(());
+    }
+    let title = game_title(rom_path);
+    let is_dol = rom_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("dol"));
+    let mut core = if is_dol {
+        GamecubeCore::from_dol(rom_path, &title)?
+    } else {
+        GamecubeCore::from_disc(rom_path)?
+    };
+    let meta = core.metadata();
+    if !is_dol {
+        log::info!(
+            "Loaded GameCube image \"{}\" (ID {} / maker {}) disc {} version {} streaming={}",
+            meta.title,
+            meta.game_code,
+            meta.maker_code,
+            meta.disc_number.saturating_add(1),
+            meta.version,
+            if meta.streaming { "on" } else { "off" }
+        );
+    }
+    let window_title = format!("{} ({})", title, meta.game_code);
+    // bezels are named after the file like screenshots, banner titles can hold anything
+    let game = rom_path
         .file_stem()
         .and_then(|s| s.to_str())
         .unwrap_or("GameCube");
-    let mut core = GamecubeCore::from_disc(rom_path)?;
-    let meta = core.metadata();
-    log::info!(
-        "Loaded GameCube image \"{}\" (ID {} / maker {}) disc {} version {} streaming={}",
-        meta.title,
-        meta.game_code,
-        meta.maker_code,
-        meta.disc_number.saturating_add(1),
-        meta.version,
-        if meta.streaming { "on" } else { "off" }
-    );
-    let window_title = format!("{} ({})", title, meta.game_code);
-    let mut frontend = GamecubeFrontend::new(&window_title, scale.max(1), limit_fps)?;
+    let mut frontend = GamecubeFrontend::new(&window_title, game, scale.max(1), limit_fps, config)?;
     frontend.run(&mut core)
 }
 
@@ -165,77 +343,87 @@ struct GamecubeFrontend {
     _sdl: sdl2::Sdl,
     canvas: Canvas<Window>,
     texture: Texture,
+    bezel: Option<Bezel>,
     event_pump: sdl2::EventPump,
-    audio: AudioQueue<i16>,
+    audio: AudioPlayer,
     limit_fps: bool,
+    display: DisplayConfig,
     scale: u32,
     argb_buffer: Vec<u32>,
     pressed: HashSet<Keycode>,
     controller: ControllerManager,
+    perf: PerfOverlay,
+    focus: FocusPause,
 }
 
 impl GamecubeFrontend {
-    fn new(title: &str, scale: u32, limit_fps: bool) -> Result<Self> {
+    /// Opens the window titled `title`, `game` picks the bezel
+    fn new(title: &str, game: &str, scale: u32, limit_fps: bool, config: &Config) -> Result<Self> {
         let sdl = sdl2::init().map_err(|e| anyhow::anyhow!(e))?;
         let video = sdl.video().map_err(|e| anyhow::anyhow!(e))?;
-        let audio_subsystem = sdl.audio().map_err(|e| anyhow::anyhow!(e))?;
 
-        let width = DEFAULT_WIDTH.saturating_mul(scale);
-        let height = DEFAULT_HEIGHT.saturating_mul(scale);
+        let (width, height) = present::oriented(
+            DEFAULT_WIDTH.saturating_mul(scale),
+            DEFAULT_HEIGHT.saturating_mul(scale),
+        );
         let window = video
             .window(title, width, height)
             .position_centered()
             .resizable()
+            .allow_highdpi()
             .build()
             .context("failed to create GameCube window")?;
 
-        let mut canvas_builder = window.into_canvas();
-        if limit_fps {
-            canvas_builder = canvas_builder.present_vsync();
-        }
-        let mut canvas = canvas_builder.build().map_err(|e| anyhow::anyhow!(e))?;
-        canvas
-            .set_logical_size(DEFAULT_WIDTH, DEFAULT_HEIGHT)
+        let mut canvas =
+            present::canvas(window, limit_fps, &config.display).map_err(|e| anyhow::anyhow!(e))?;
+        present::apply_fullscreen(canvas.window_mut());
+        present::set_logical_size(&mut canvas, DEFAULT_WIDTH, DEFAULT_HEIGHT)
             .context("failed to set GameCube logical size")?;
+        let bezel = Bezel::load(&canvas, &config.display, GameSystem::GameCube, game);
 
         let texture_creator = canvas.texture_creator();
         let texture = texture_creator
             .create_texture_streaming(PixelFormatEnum::ARGB8888, DEFAULT_WIDTH, DEFAULT_HEIGHT)
             .map_err(|e| anyhow::anyhow!(e))?;
 
-        let desired = AudioSpecDesired {
-            freq: Some(AUDIO_SAMPLE_RATE),
-            channels: Some(AUDIO_CHANNELS as u8),
-            samples: Some(AUDIO_BUFFER_SAMPLES),
-        };
-        let audio = audio_subsystem
-            .open_queue::<i16, _>(None, &desired)
-            .map_err(|e| anyhow::anyhow!(e))?;
-        audio.resume();
+        let audio = AudioPlayer::new(
+            &config.audio,
+            AUDIO_SAMPLE_RATE.into(),
+            AUDIO_CHANNELS.into(),
+        )
+        .context("failed to initialize SDL2 audio for GameCube")?;
 
         let event_pump = sdl.event_pump().map_err(|e| anyhow::anyhow!(e))?;
-        let controller = ControllerManager::new(&sdl)?;
+        let controller = ControllerManager::new(&sdl, &config.controllers)?;
         Ok(Self {
             _sdl: sdl,
             canvas,
             texture,
+            bezel,
             event_pump,
             audio,
             limit_fps,
+            display: config.display.clone(),
             scale,
             argb_buffer: vec![0; (DEFAULT_WIDTH as usize) * (DEFAULT_HEIGHT as usize)],
             pressed: HashSet::new(),
             controller,
+            perf: PerfOverlay::new(TARGET_FRAME, &config.display),
+            focus: FocusPause::new(&config.display),
         })
     }
 
     fn run(&mut self, core: &mut GamecubeCore) -> Result<()> {
         let mut running = true;
-        let mut last_frame = Instant::now();
+        let mut pacer = FramePacer::new(TARGET_FRAME, self.limit_fps)
+            .vsynced(self.canvas.window(), &self.display);
 
         while running {
             for event in self.event_pump.poll_iter() {
                 self.controller.handle_event(&event);
+                self.audio.handle_sdl_event(&event);
+                self.perf.handle_sdl_event(&event);
+                self.focus.handle_sdl_event(&event);
                 match event {
                     Event::Quit { .. } => running = false,
                     Event::KeyDown {
@@ -247,31 +435,33 @@ impl GamecubeFrontend {
                         repeat: false,
                         ..
                     } => {
-                        self.pressed.insert(code);
+                        self.pressed.insert(present::game_arrow_key(code));
                     }
                     Event::KeyUp {
                         keycode: Some(code),
                         ..
                     } => {
-                        self.pressed.remove(&code);
+                        self.pressed.remove(&present::game_arrow_key(code));
                     }
                     _ => {}
                 }
             }
 
+            if self.focus.is_paused() {
+                focus_pause::idle();
+                continue;
+            }
+
+            self.controller.advance_frame();
             let input = self.build_input();
             core.step(&input);
             let (width, height) = core.dimensions();
+            self.perf.frame();
+            self.perf.set_audio(&self.audio);
             self.present_frame(core.frame(), width, height)?;
-            self.push_audio(core.audio())?;
+            self.push_audio(core.audio());
 
-            if self.limit_fps {
-                let elapsed = last_frame.elapsed();
-                if elapsed < TARGET_FRAME {
-                    std::thread::sleep(TARGET_FRAME - elapsed);
-                }
-            }
-            last_frame = Instant::now();
+            pacer.wait();
         }
 
         Ok(())
@@ -295,6 +485,12 @@ impl GamecubeFrontend {
         }
 
         self.argb_buffer.copy_from_slice(frame);
+        if self.perf.is_open() {
+            self.perf.draw(&mut self.argb_buffer, width as usize);
+        }
+        if let Some(text) = self.audio.osd_text() {
+            osd::draw_message(&mut self.argb_buffer, width as usize, &text);
+        }
         self.texture
             .update(
                 None,
@@ -303,34 +499,34 @@ impl GamecubeFrontend {
             )
             .context("failed to upload GameCube frame")?;
 
-        if let Err(err) = self.canvas.set_logical_size(width, height) {
+        if let Err(err) = present::set_logical_size(&mut self.canvas, width, height) {
             log::debug!("failed to update GameCube logical size: {err}");
         }
-        let scaled_w = width.saturating_mul(self.scale);
-        let scaled_h = height.saturating_mul(self.scale);
-        if let Err(err) = self.canvas.window_mut().set_size(scaled_w, scaled_h) {
+        let (scaled_w, scaled_h) = present::oriented(
+            width.saturating_mul(self.scale),
+            height.saturating_mul(self.scale),
+        );
+        if let Err(err) =
+            present::set_window_size(self.canvas.window_mut(), scaled_w, scaled_h, &self.display)
+        {
             log::debug!("failed to resize GameCube window: {err}");
         }
 
         self.canvas.clear();
-        self.canvas
-            .copy(&self.texture, None, None)
-            .map_err(|e| anyhow::anyhow!(e))?;
+        if let Some(bezel) = &self.bezel {
+            bezel
+                .draw(&mut self.canvas)
+                .map_err(|e| anyhow::anyhow!(e))?;
+        }
+        present::copy(&mut self.canvas, &self.texture, None).map_err(|e| anyhow::anyhow!(e))?;
         self.canvas.present();
         Ok(())
     }
 
-    fn push_audio(&mut self, samples: &[i16]) -> Result<()> {
-        if samples.is_empty() {
-            return Ok(());
-        }
-        let queued = self.audio.size() as u32;
-        if queued > MAX_AUDIO_LATENCY_BYTES {
-            self.audio.clear();
+    fn push_audio(&mut self, samples: &[i16]) {
+        if !samples.is_empty() {
+            self.audio.push_i16(samples);
         }
-        self.audio
-            .queue_audio(samples)
-            .map_err(|e| anyhow::anyhow!(e))
     }
 
     fn build_input(&self) -> GamecubeInput {
@@ -338,12 +534,12 @@ impl GamecubeFrontend {
         let mut buttons = PadButton::empty();
 
         let mut left = AnalogStick {
-            x: Self::apply_deadzone(analog.left_x),
-            y: Self::apply_deadzone(analog.left_y),
+            x: analog.left_x,
+            y: analog.left_y,
         };
         let mut right = AnalogStick {
-            x: Self::apply_deadzone(analog.right_x),
-            y: Self::apply_deadzone(analog.right_y),
+            x: analog.right_x,
+            y: analog.right_y,
         };
         let mut trigger_l = analog.left_trigger;
         let mut trigger_r = analog.right_trigger;
@@ -455,14 +651,6 @@ impl GamecubeFrontend {
             (false, false) => base,
         }
     }
-
-    fn apply_deadzone(value: f32) -> f32 {
-        if value.abs() < 0.12 {
-            0.0
-        } else {
-            value.clamp(-1.0, 1.0)
-        }
-    }
 }
 
 bitflags! {
@@ -588,7 +776,9 @@ fn shorten_text(input: &str, max_chars: usize) -> String {
 }
 
 struct GamecubeCore {
-    _disc_bytes: Vec<u8>,
+    _disc: Option<Box<dyn DiscImage>>,
+    /// Set for `.dol` files, which run on the interpreter instead of the test pattern
+    homebrew: Option<Homebrew>,
     width: u32,
     height: u32,
     frame_buffer: Vec<u32>,
@@ -600,20 +790,46 @@ struct GamecubeCore {
 
 impl GamecubeCore {
     fn from_disc(path: &Path) -> Result<Self> {
-        let disc_bytes = load_gamecube_image(path)?;
-        let metadata = GamecubeMetadata::parse(&disc_bytes)?;
+        let mut disc = gc_disc::open(path)?;
+        let header = gc_disc::read_at(disc.as_mut(), 0, BOOT_HEADER_SIZE)
+            .with_context(|| format!("failed to read GameCube image {}", path.display()))?;
+        let metadata = GamecubeMetadata::parse(&header)?;
+        Ok(Self::new(Some(disc), None, metadata))
+    }
+
+    /// Homebrew has no disc header, the title comes from the file name
+    fn from_dol(path: &Path, title: &str) -> Result<Self> {
+        let homebrew = Homebrew::load(path)?;
+        let metadata = GamecubeMetadata {
+            game_code: "DOL".to_string(),
+            maker_code: String::new(),
+            disc_number: 0,
+            version: 0,
+            streaming: false,
+            title: title.to_string(),
+            overlay_lines: Default::default(),
+        };
+        Ok(Self::new(None, Some(homebrew), metadata))
+    }
+
+    fn new(
+        disc: Option<Box<dyn DiscImage>>,
+        homebrew: Option<Homebrew>,
+        metadata: GamecubeMetadata,
+    ) -> Self {
         let width = DEFAULT_WIDTH;
         let height = DEFAULT_HEIGHT;
-        Ok(Self {
-            _disc_bytes: disc_bytes,
+        Self {
+            _disc: disc,
+            homebrew,
             width,
             height,
             frame_buffer: vec![0; (width as usize) * (height as usize)],
-            audio_buffer: Vec::with_capacity((AUDIO_BUFFER_SAMPLES as usize) * 2),
+            audio_buffer: Vec::with_capacity(2048),
             audio_phase: 0.0,
             frame_counter: 0,
             metadata,
-        })
+        }
     }
 
     fn metadata(&self) -> &GamecubeMetadata {
@@ -634,6 +850,17 @@ impl GamecubeCore {
 
     fn step(&mut self, input: &GamecubeInput) {
         self.frame_counter = self.frame_counter.wrapping_add(1);
+        if let Some(homebrew) = &mut self.homebrew {
+            homebrew.set_pad(pad_status(input));
+            homebrew.run_field();
+            homebrew.render(
+                &mut self.frame_buffer,
+                self.width as usize,
+                self.height as usize,
+            );
+            self.audio_buffer.clear();
+            return;
+        }
         self.render_pattern(input);
         self.generate_audio(input);
     }
@@ -761,6 +988,38 @@ impl GamecubeCore {
     }
 }
 
+/// The controller's reply to an SI poll: buttons and the main stick, then the C-stick
+/// and analog triggers. Stick Y grows upwards on the GameCube.
+fn pad_status(input: &GamecubeInput) -> [u32; 2] {
+    let buttons = input.buttons;
+    let bits = [
+        (PadButton::A, 0x0100_0000),
+        (PadButton::B, 0x0200_0000),
+        (PadButton::X, 0x0400_0000),
+        (PadButton::Y, 0x0800_0000),
+        (PadButton::START, 0x1000_0000),
+        (PadButton::DPAD_LEFT, 0x0001_0000),
+        (PadButton::DPAD_RIGHT, 0x0002_0000),
+        (PadButton::DPAD_DOWN, 0x0004_0000),
+        (PadButton::DPAD_UP, 0x0008_0000),
+        (PadButton::Z, 0x0010_0000),
+        (PadButton::R, 0x0020_0000),
+        (PadButton::L, 0x0040_0000),
+    ]
+    .into_iter()
+    .filter(|(button, _)| buttons.contains(*button))
+    .fold(0x0080_0000, |word, (_, bit)| word | bit);
+    let axis = |value: f32| (128.0 + value.clamp(-1.0, 1.0) * 127.0) as u32;
+    let trigger = |value: f32| (value.clamp(0.0, 1.0) * 255.0) as u32;
+    [
+        bits | (axis(input.left_stick.x) << 8) | axis(-input.left_stick.y),
+        (axis(input.right_stick.x) << 24)
+            | (axis(-input.right_stick.y) << 16)
+            | (trigger(input.trigger_l) << 8)
+            | trigger(input.trigger_r),
+    ]
+}
+
 fn glyph_for(ch: char) -> [u8; 8] {
     let idx = ch as usize;
     if idx < BASIC_LEGACY.len() {
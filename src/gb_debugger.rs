@@ -0,0 +1,183 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use gameboy_core::{CpuState, Gameboy};
+
+/// What reaching a breakpoint does
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum BreakAction {
+    /// Stop the game until F5 continues or F10 steps
+    Pause,
+    /// Log the registers and keep going
+    Log,
+}
+
+/// Frames to trace, `START..END` with END left out or `START..` for the rest of the run
+#[derive(Clone, Copy, Debug)]
+pub struct FrameRange {
+    start: u64,
+    end: Option<u64>,
+}
+
+impl FrameRange {
+    pub const ALL: Self = Self {
+        start: 0,
+        end: None,
+    };
+
+    fn contains(&self, frame: u64) -> bool {
+        frame >= self.start && self.end.is_none_or(|end| frame < end)
+    }
+}
+
+impl FromStr for FrameRange {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (start, end) = text
+            .split_once("..")
+            .ok_or_else(|| format!("`{text}` is not START..END"))?;
+        let frame = |number: &str| {
+            number
+                .parse::<u64>()
+                .map_err(|_| format!("`{number}` is not a frame number"))
+        };
+        Ok(Self {
+            start: if start.is_empty() { 0 } else { frame(start)? },
+            end: if end.is_empty() {
+                None
+            } else {
+                Some(frame(end)?)
+            },
+        })
+    }
+}
+
+struct Trace {
+    writer: BufWriter<File>,
+    frames: FrameRange,
+}
+
+/// Breakpoints on the Game Boy's program counter and an instruction trace, checked
+/// before every instruction
+pub struct Debugger {
+    breakpoints: HashSet<u16>,
+    action: BreakAction,
+    trace: Option<Trace>,
+    paused: bool,
+    /// Lets the instruction the game stopped at run once without stopping again
+    resuming: bool,
+    /// Stops again after one instruction
+    stepping: bool,
+}
+
+impl Debugger {
+    pub fn new(
+        breakpoints: &[u16],
+        action: BreakAction,
+        trace: Option<&Path>,
+        frames: FrameRange,
+    ) -> Result<Self> {
+        let trace = trace
+            .map(|path| {
+                let file = File::create(path)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                log::info!("Tracing instructions to {}", path.display());
+                Ok::<_, anyhow::Error>(Trace {
+                    writer: BufWriter::new(file),
+                    frames,
+                })
+            })
+            .transpose()?;
+        Ok(Self {
+            breakpoints: breakpoints.iter().copied().collect(),
+            action,
+            trace,
+            paused: false,
+            resuming: false,
+            stepping: false,
+        })
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn resume(&mut self) {
+        if self.paused {
+            self.paused = false;
+            self.resuming = true;
+        }
+    }
+
+    pub fn step(&mut self) {
+        if self.paused {
+            self.resume();
+            self.stepping = true;
+        }
+    }
+
+    /// Checks the instruction about to run in `frame`, `false` stops the game before it
+    pub fn before_instruction(&mut self, gameboy: &Gameboy, frame: u64) -> Result<bool> {
+        let state = gameboy.cpu_state();
+        // a halted CPU runs no instructions until an interrupt wakes it
+        if state.halted {
+            return Ok(true);
+        }
+        if self.resuming {
+            self.resuming = false;
+        } else if self.stepping {
+            self.stepping = false;
+            self.paused = true;
+            log::info!("Stepped to {}", describe(&state));
+            return Ok(false);
+        } else if self.breakpoints.contains(&state.pc) {
+            log::info!("Breakpoint in frame {frame}: {}", describe(&state));
+            if self.action == BreakAction::Pause {
+                self.paused = true;
+                log::info!("Paused, F5 continues and F10 steps one instruction");
+                return Ok(false);
+            }
+        }
+        if let Some(trace) = self.trace.as_mut()
+            && trace.frames.contains(frame)
+        {
+            writeln!(
+                trace.writer,
+                "{frame:>6} {} op={:02X}",
+                describe(&state),
+                gameboy.peek_byte(state.pc)
+            )
+            .context("failed to write the instruction trace")?;
+        }
+        Ok(true)
+    }
+
+    pub fn finish(self) -> Result<()> {
+        if let Some(mut trace) = self.trace {
+            trace
+                .writer
+                .flush()
+                .context("failed to write the instruction trace")?;
+        }
+        Ok(())
+    }
+}
+
+fn describe(state: &CpuState) -> String {
+    format!(
+        "PC={:04X} AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} IME={}",
+        state.pc,
+        state.af,
+        state.bc,
+        state.de,
+        state.hl,
+        state.sp,
+        u8::from(state.interrupts_enabled)
+    )
+}
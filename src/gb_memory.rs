@@ -0,0 +1,180 @@
+use std::str::FromStr;
+
+/// A byte written to Game Boy memory, given as `ADDR=VALUE` in hex. Pokes are written
+/// before every frame to freeze a value, or once before frame `FRAME` with `ADDR=VALUE@FRAME`.
+#[derive(Clone, Copy, Debug)]
+pub struct Poke {
+    pub address: u16,
+    pub value: u8,
+    pub frame: Option<u64>,
+}
+
+impl Poke {
+    /// Whether the poke is written before `frame`
+    pub fn applies_to(&self, frame: u64) -> bool {
+        self.frame.is_none_or(|once| once == frame)
+    }
+}
+
+impl FromStr for Poke {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (write, frame) = match text.split_once('@') {
+            Some((write, frame)) => {
+                let frame = frame
+                    .parse()
+                    .map_err(|_| format!("`{frame}` is not a frame number"))?;
+                (write, Some(frame))
+            }
+            None => (text, None),
+        };
+        let (address, value) = write
+            .split_once('=')
+            .ok_or_else(|| format!("`{text}` is not ADDR=VALUE"))?;
+        Ok(Self {
+            address: parse_address(address)?,
+            value: u8::from_str_radix(strip_hex_prefix(value), 16)
+                .map_err(|_| format!("`{value}` is not a hex byte"))?,
+            frame,
+        })
+    }
+}
+
+/// Game Boy addresses on the command line, hex with an optional `0x`
+pub fn parse_address(text: &str) -> Result<u16, String> {
+    u16::from_str_radix(strip_hex_prefix(text), 16)
+        .map_err(|_| format!("`{text}` is not a 16-bit hex address"))
+}
+
+fn strip_hex_prefix(text: &str) -> &str {
+    text.strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text)
+}
+
+/// Runs actions at the end of the frame a byte changes or crosses a threshold, given as
+/// `ADDR OP prev` or `ADDR OP VALUE` with `==`, `!=`, `>`, `>=`, `<` or `<=` and hex
+/// numbers. A comparison with `prev` fires on every frame the byte moves that way, one
+/// with a value fires on the frame it starts to hold.
+#[derive(Clone, Debug)]
+pub struct Trigger {
+    condition: String,
+    address: u16,
+    comparison: Comparison,
+    operand: Operand,
+    pub actions: Vec<TriggerAction>,
+    previous: Option<u8>,
+    held: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerAction {
+    Screenshot,
+    Log,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    Previous,
+    Value(u8),
+}
+
+/// Longer operators first, so `>=` isn't read as `>`
+const COMPARISONS: [(&str, Comparison); 7] = [
+    ("==", Comparison::Equal),
+    ("!=", Comparison::NotEqual),
+    (">=", Comparison::GreaterOrEqual),
+    ("<=", Comparison::LessOrEqual),
+    (">", Comparison::Greater),
+    ("<", Comparison::Less),
+    ("=", Comparison::Equal),
+];
+
+impl Comparison {
+    fn holds(self, value: u8, operand: u8) -> bool {
+        match self {
+            Self::Equal => value == operand,
+            Self::NotEqual => value != operand,
+            Self::Greater => value > operand,
+            Self::GreaterOrEqual => value >= operand,
+            Self::Less => value < operand,
+            Self::LessOrEqual => value <= operand,
+        }
+    }
+}
+
+impl Trigger {
+    /// `actions` are `screenshot` and `log`, separated by `|` or `,`
+    pub fn parse(condition: &str, actions: &str) -> Result<Self, String> {
+        let compact: String = condition.split_whitespace().collect();
+        let (address, comparison, operand) = COMPARISONS
+            .iter()
+            .find_map(|(operator, comparison)| {
+                compact
+                    .split_once(operator)
+                    .map(|(address, operand)| (address, *comparison, operand))
+            })
+            .ok_or_else(|| format!("`{condition}` has no ==, !=, >, >=, < or <="))?;
+        let operand = if operand.eq_ignore_ascii_case("prev") {
+            Operand::Previous
+        } else {
+            Operand::Value(
+                u8::from_str_radix(strip_hex_prefix(operand), 16)
+                    .map_err(|_| format!("`{operand}` is not `prev` or a hex byte"))?,
+            )
+        };
+        let actions = actions
+            .split(['|', ','])
+            .map(|action| match action.trim().to_ascii_lowercase().as_str() {
+                "screenshot" => Ok(TriggerAction::Screenshot),
+                "log" => Ok(TriggerAction::Log),
+                "savestate" => Err("the Game Boy core has no save states to take".to_string()),
+                other => Err(format!("unknown trigger action `{other}`")),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            condition: compact.clone(),
+            address: parse_address(address)?,
+            comparison,
+            operand,
+            actions,
+            previous: None,
+            held: false,
+        })
+    }
+
+    pub fn address(&self) -> u16 {
+        self.address
+    }
+
+    pub fn condition(&self) -> &str {
+        &self.condition
+    }
+
+    /// Takes the byte's value at the end of a frame, `true` when the actions should run
+    pub fn fires(&mut self, value: u8) -> bool {
+        let previous = self.previous.replace(value);
+        match self.operand {
+            Operand::Previous => {
+                previous.is_some_and(|previous| self.comparison.holds(value, previous))
+            }
+            Operand::Value(operand) => {
+                let holds = self.comparison.holds(value, operand);
+                let crossed = holds && !self.held;
+                self.held = holds;
+                crossed
+            }
+        }
+    }
+}
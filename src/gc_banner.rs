@@ -0,0 +1,150 @@
+use std::path::Path;
+
+use anyhow::{Context, Result, bail, ensure};
+
+use crate::gc_disc::{self, DiscImage};
+
+pub const BANNER_WIDTH: usize = 96;
+pub const BANNER_HEIGHT: usize = 32;
+const BANNER_NAME: &str = "opening.bnr";
+/// Offset and size of the file system table in `boot.bin`
+const FST_OFFSET: u64 = 0x424;
+const FST_ENTRY_SIZE: usize = 12;
+/// Largest FST accepted, real ones are well under 1 MiB
+const MAX_FST_SIZE: usize = 16 * 1024 * 1024;
+const IMAGE_OFFSET: usize = 0x20;
+const IMAGE_SIZE: usize = BANNER_WIDTH * BANNER_HEIGHT * 2;
+const TEXT_OFFSET: usize = IMAGE_OFFSET + IMAGE_SIZE;
+/// `BNR1` has one text record, `BNR2` one per PAL language with English first
+const TEXT_SIZE: usize = 0x140;
+
+/// The banner the GameCube menu shows for a disc
+pub struct Banner {
+    /// 96x32 ARGB8888 pixels
+    pub image: Vec<u32>,
+    pub title: String,
+    pub maker: String,
+    pub description: String,
+}
+
+/// Reads `opening.bnr` from the root of the disc's file system
+pub fn read(path: &Path) -> Result<Banner> {
+    let mut disc = gc_disc::open(path)?;
+    let data = read_root_file(disc.as_mut(), BANNER_NAME)?
+        .with_context(|| format!("{} has no {BANNER_NAME}", path.display()))?;
+    parse(&data)
+}
+
+fn parse(data: &[u8]) -> Result<Banner> {
+    ensure!(
+        data.len() >= TEXT_OFFSET + TEXT_SIZE,
+        "{BANNER_NAME} is truncated"
+    );
+    if !matches!(&data[..4], b"BNR1" | b"BNR2") {
+        bail!("{BANNER_NAME} has an unknown format");
+    }
+    let text = &data[TEXT_OFFSET..TEXT_OFFSET + TEXT_SIZE];
+    let short_title = decode_text(&text[0x00..0x20]);
+    let short_maker = decode_text(&text[0x20..0x40]);
+    let title = decode_text(&text[0x40..0x80]);
+    let maker = decode_text(&text[0x80..0xC0]);
+    Ok(Banner {
+        image: decode_rgb5a3(&data[IMAGE_OFFSET..TEXT_OFFSET]),
+        title: if title.is_empty() { short_title } else { title },
+        maker: if maker.is_empty() { short_maker } else { maker },
+        description: decode_text(&text[0xC0..0x140]),
+    })
+}
+
+/// The banner image is stored in 4x4 pixel tiles of RGB5A3: opaque RGB555 when the top
+/// bit is set, ARGB3444 otherwise
+fn decode_rgb5a3(data: &[u8]) -> Vec<u32> {
+    let mut image = vec![0; BANNER_WIDTH * BANNER_HEIGHT];
+    let tiles_per_row = BANNER_WIDTH / 4;
+    for (index, pixel) in data.chunks_exact(2).enumerate() {
+        let tile = index / 16;
+        let x = (tile % tiles_per_row) * 4 + index % 4;
+        let y = (tile / tiles_per_row) * 4 + (index % 16) / 4;
+        let value = u16::from_be_bytes([pixel[0], pixel[1]]) as u32;
+        let (a, r, g, b) = if value & 0x8000 != 0 {
+            let expand = |bits: u32| (bits << 3) | (bits >> 2);
+            (
+                0xFF,
+                expand((value >> 10) & 0x1F),
+                expand((value >> 5) & 0x1F),
+                expand(value & 0x1F),
+            )
+        } else {
+            let expand = |bits: u32| bits * 0x11;
+            (
+                ((value >> 12) & 0x7) * 0xFF / 7,
+                expand((value >> 8) & 0xF),
+                expand((value >> 4) & 0xF),
+                expand(value & 0xF),
+            )
+        };
+        image[y * BANNER_WIDTH + x] = (a << 24) | (r << 16) | (g << 8) | b;
+    }
+    image
+}
+
+/// Latin-1 text up to the first NUL with line breaks flattened. Japanese banners are
+/// Shift-JIS, of which only the ASCII characters are kept.
+fn decode_text(raw: &[u8]) -> String {
+    let text: String = raw
+        .iter()
+        .take_while(|&&byte| byte != 0)
+        .filter_map(|&byte| match byte {
+            b'\n' | b'\r' => Some(' '),
+            0x20..=0x7E => Some(byte as char),
+            0xA0..=0xFF => Some(byte as char),
+            _ => None,
+        })
+        .collect();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Looks `name` up among the files in the root directory of the FST
+fn read_root_file(disc: &mut dyn DiscImage, name: &str) -> Result<Option<Vec<u8>>> {
+    let header = gc_disc::read_at(disc, FST_OFFSET, 8)?;
+    ensure!(header.len() == 8, "disc header is truncated");
+    let fst_offset = u32::from_be_bytes(header[0..4].try_into()?) as u64;
+    let fst_size = u32::from_be_bytes(header[4..8].try_into()?) as usize;
+    ensure!(
+        (FST_ENTRY_SIZE..=MAX_FST_SIZE).contains(&fst_size),
+        "disc has no file system table"
+    );
+    let fst = gc_disc::read_at(disc, fst_offset, fst_size)?;
+    ensure!(fst.len() == fst_size, "file system table is truncated");
+
+    let word = |offset: usize| u32::from_be_bytes(fst[offset..offset + 4].try_into().unwrap());
+    let count = word(8) as usize;
+    let strings = count * FST_ENTRY_SIZE;
+    ensure!(
+        count > 0 && strings <= fst.len(),
+        "file system table is corrupt"
+    );
+
+    // the root's direct children, skipping over the contents of each subdirectory
+    let mut index = 1;
+    while index < count {
+        let entry = index * FST_ENTRY_SIZE;
+        let is_dir = fst[entry] != 0;
+        let name_offset = strings + (word(entry) & 0x00FF_FFFF) as usize;
+        if is_dir {
+            index = (word(entry + 8) as usize).max(index + 1);
+            continue;
+        }
+        let entry_name = fst
+            .get(name_offset..)
+            .and_then(|rest| rest.split(|&byte| byte == 0).next())
+            .unwrap_or_default();
+        if entry_name.eq_ignore_ascii_case(name.as_bytes()) {
+            let offset = word(entry + 4) as u64;
+            let len = word(entry + 8) as usize;
+            return Ok(Some(gc_disc::read_at(disc, offset, len)?));
+        }
+        index += 1;
+    }
+    Ok(None)
+}
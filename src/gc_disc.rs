@@ -0,0 +1,129 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow};
+use rvz::Rvz;
+
+/// Upper bound for decompressed RVZ groups kept around, groups are 128 KiB to 2 MiB
+const CACHE_BYTES: usize = 32 * 1024 * 1024;
+/// RVZ's smallest group size, used when the image reports something smaller
+const MIN_BLOCK_SIZE: usize = 32 * 1024;
+
+/// A disc image that is read on demand instead of loaded into memory
+pub trait DiscImage: Read + Seek {}
+
+impl<T: Read + Seek> DiscImage for T {}
+
+/// Opens a GameCube/Wii disc image: ISO/GCM files are read directly, RVZ groups are
+/// decompressed when first read and kept in an LRU cache
+pub fn open(path: &Path) -> Result<Box<dyn DiscImage>> {
+    let file = File::open(path)
+        .with_context(|| format!("failed to open disc image {}", path.display()))?;
+    let is_rvz = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("rvz"));
+    if !is_rvz {
+        return Ok(Box::new(BufReader::new(file)));
+    }
+    let rvz = Rvz::new(file)
+        .map_err(|err| anyhow!(err))
+        .with_context(|| format!("failed to read RVZ image {}", path.display()))?;
+    let len = rvz.metadata.header.iso_file_size;
+    let block_size = (rvz.metadata.disc.chunk_size as usize).max(MIN_BLOCK_SIZE);
+    log::debug!(
+        "Streaming RVZ image {} ({} MiB, {} KiB groups)",
+        path.display(),
+        len / (1024 * 1024),
+        block_size / 1024
+    );
+    Ok(Box::new(BlockCache::new(rvz, len, block_size)))
+}
+
+/// Reads `len` bytes at `offset`, or fewer at the end of the image
+pub fn read_at(disc: &mut dyn DiscImage, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    disc.seek(SeekFrom::Start(offset))?;
+    let mut data = Vec::with_capacity(len);
+    Read::take(disc, len as u64).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Serves reads from whole blocks of `inner`, keeping the most recently used ones
+struct BlockCache<R> {
+    inner: R,
+    len: u64,
+    block_size: usize,
+    capacity: usize,
+    /// Most recently used first
+    blocks: VecDeque<(u64, Vec<u8>)>,
+    position: u64,
+}
+
+impl<R: Read + Seek> BlockCache<R> {
+    fn new(inner: R, len: u64, block_size: usize) -> Self {
+        let capacity = (CACHE_BYTES / block_size).max(1);
+        Self {
+            inner,
+            len,
+            block_size,
+            capacity,
+            blocks: VecDeque::with_capacity(capacity),
+            position: 0,
+        }
+    }
+
+    fn block(&mut self, index: u64) -> io::Result<&[u8]> {
+        if let Some(found) = self.blocks.iter().position(|(cached, _)| *cached == index) {
+            let entry = self
+                .blocks
+                .remove(found)
+                .expect("cached block index is valid");
+            self.blocks.push_front(entry);
+        } else {
+            let start = index * self.block_size as u64;
+            let size = (self.len - start).min(self.block_size as u64) as usize;
+            let mut data = vec![0; size];
+            self.inner.seek(SeekFrom::Start(start))?;
+            self.inner.read_exact(&mut data)?;
+            if self.blocks.len() == self.capacity {
+                self.blocks.pop_back();
+            }
+            self.blocks.push_front((index, data));
+        }
+        Ok(&self.blocks[0].1)
+    }
+}
+
+impl<R: Read + Seek> Read for BlockCache<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let block_size = self.block_size as u64;
+        let offset = (self.position % block_size) as usize;
+        let block = self.block(self.position / block_size)?;
+        let count = buf.len().min(block.len() - offset);
+        buf[..count].copy_from_slice(&block[offset..offset + count]);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<R: Read + Seek> Seek for BlockCache<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start of the disc",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
@@ -0,0 +1,475 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result, ensure};
+
+use crate::gekko::{Bus, Gekko};
+
+const RAM_SIZE: usize = 24 * 1024 * 1024;
+/// The Gekko's lockable half of the L1 data cache, mapped at 0xE0000000
+const LOCKED_CACHE_BASE: u32 = 0xE000_0000;
+const LOCKED_CACHE_SIZE: usize = 16 * 1024;
+/// CP, PE, VI, PI, MI, DSP, DI, SI, EXI and AI registers
+const MMIO_BASE: u32 = 0x0C00_0000;
+const MMIO_SIZE: usize = 0x8000;
+const STACK_TOP: u32 = 0x816F_FFF0;
+const ARENA_HIGH: u32 = 0x8170_0000;
+const BUS_CLOCK: u32 = 162_000_000;
+const CPU_CLOCK: u32 = 486_000_000;
+
+/// Instructions are run in chunks, after which timers, the VI beam and interrupts move on
+const CHUNK_INSTRUCTIONS: u32 = 1_000;
+/// About 60 MIPS, well short of the real 486 MHz but within reach of an interpreter
+const CHUNKS_PER_FIELD: u32 = 1_000;
+/// Time base ticks per chunk so that it keeps real time: bus clock / 4 / 60 per field
+const TICKS_PER_CHUNK: u32 = BUS_CLOCK / 4 / 60 / CHUNKS_PER_FIELD;
+const LINES_PER_FIELD: u32 = 263;
+
+/// DOL header: 7 text and 11 data sections, then BSS and the entry point
+const DOL_HEADER_SIZE: usize = 0x100;
+const DOL_SECTIONS: usize = 18;
+const DOL_ADDRESSES: usize = 0x48;
+const DOL_SIZES: usize = 0x90;
+const DOL_ENTRY: usize = 0xE0;
+
+mod reg {
+    pub const VI_VTR: usize = 0x2000;
+    pub const VI_DCR: usize = 0x2002;
+    pub const VI_TFBL: usize = 0x201C;
+    pub const VI_VCT: usize = 0x202C;
+    pub const VI_HCT: usize = 0x202E;
+    pub const VI_DI0: usize = 0x2030;
+    pub const VI_PICCONF: usize = 0x2048;
+    pub const PI_INTSR: usize = 0x3000;
+    pub const PI_INTMR: usize = 0x3004;
+    pub const DSP_MAILBOX_IN: usize = 0x5000;
+    pub const DSP_MAILBOX_OUT: usize = 0x5004;
+    pub const DSP_CSR: usize = 0x500A;
+    pub const SI_CHANNELS: usize = 0x6400;
+    pub const SI_COMCSR: usize = 0x6434;
+    pub const SI_SR: usize = 0x6438;
+    pub const SI_BUFFER: usize = 0x6480;
+    pub const EXI_CR: usize = 0x680C;
+}
+
+/// PI interrupt causes
+const PI_VI: u32 = 0x100;
+/// INTSR bit that reads as set while the reset button is up
+const PI_RESET_SWITCH: u32 = 0x1_0000;
+const VI_DI_STATUS: u32 = 0x8000_0000;
+const VI_DI_ENABLE: u32 = 0x1000_0000;
+/// DSP CSR: reset, and the AI, ARAM and DSP interrupts, which are write-one-to-clear
+const DSP_CSR_RESET: u16 = 0x0001;
+const DSP_CSR_INTERRUPTS: u16 = 0x00A8;
+/// The mail the DSP ROM sends once it is up
+const DSP_INIT_MAIL: u32 = 0x8071_FEED;
+const SI_TRANSFER_START: u32 = 0x1;
+const SI_TRANSFER_COMPLETE: u32 = 0x8000_0000;
+const SI_CHANNEL_STRIDE: usize = 0x0C;
+/// SI in-buffer error bit, reported for ports without a controller
+const SI_ERROR: u32 = 0x8000_0000;
+/// SI status: controller 0 has fresh input
+const SI_READ_STATUS_0: u32 = 0x2000_0000;
+const EXI_CHANNELS: usize = 3;
+const EXI_CHANNEL_STRIDE: usize = 0x14;
+/// Standard controller ID returned for the SI reset and ID commands
+const SI_CONTROLLER_ID: [u8; 3] = [0x09, 0x00, 0x00];
+
+/// A homebrew DOL on the Gekko interpreter with enough of the hardware around it for
+/// libogc's startup, VI retrace interrupts, a controller on port 1 and the external
+/// framebuffer. There is no GX, DSP or disc drive, so only programs that draw into
+/// the XFB with the CPU show anything.
+pub struct Homebrew {
+    cpu: Gekko,
+    hw: Hardware,
+    halted: bool,
+}
+
+impl Homebrew {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let mut hw = Hardware::new();
+        let entry = load_dol(&data, &mut hw.ram)
+            .with_context(|| format!("{} is not a valid DOL file", path.display()))?;
+        hw.write_boot_info();
+        log::info!("Booting DOL {} at {entry:08X}", path.display());
+        Ok(Self {
+            cpu: Gekko::new(entry, STACK_TOP),
+            hw,
+            halted: false,
+        })
+    }
+
+    /// The SI status words of port 1: buttons and main stick, then C-stick and triggers
+    pub fn set_pad(&mut self, status: [u32; 2]) {
+        self.hw.pad = status;
+    }
+
+    /// Runs one 60 Hz field. An unsupported instruction stops the program, leaving the
+    /// last picture on screen.
+    pub fn run_field(&mut self) {
+        if self.halted {
+            return;
+        }
+        for chunk in 0..CHUNKS_PER_FIELD {
+            for _ in 0..CHUNK_INSTRUCTIONS {
+                if let Err(err) = self.cpu.step(&mut self.hw) {
+                    log::error!("Homebrew stopped: {err:#}");
+                    self.halted = true;
+                    return;
+                }
+            }
+            self.cpu.tick(TICKS_PER_CHUNK);
+            let line = 1 + (chunk + 1) * LINES_PER_FIELD / CHUNKS_PER_FIELD;
+            self.hw.advance_beam(line.min(LINES_PER_FIELD));
+        }
+    }
+
+    /// Scales the XFB the VI points at into `frame`
+    pub fn render(&self, frame: &mut [u32], width: usize, height: usize) {
+        self.hw.render_xfb(frame, width, height);
+    }
+}
+
+/// Copies the text and data sections into RAM and returns the entry point. BSS is left
+/// for the program's startup code to clear, RAM starts zeroed anyway.
+fn load_dol(data: &[u8], ram: &mut [u8]) -> Result<u32> {
+    ensure!(data.len() >= DOL_HEADER_SIZE, "DOL header is truncated");
+    let word = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+    for section in 0..DOL_SECTIONS {
+        let offset = word(section * 4) as usize;
+        let addr = word(DOL_ADDRESSES + section * 4);
+        let size = word(DOL_SIZES + section * 4) as usize;
+        if size == 0 {
+            continue;
+        }
+        let phys = (addr & 0x01FF_FFFF) as usize;
+        let source = data
+            .get(offset..offset + size)
+            .with_context(|| format!("section {section} runs past the end of the file"))?;
+        let target = ram
+            .get_mut(phys..phys + size)
+            .with_context(|| format!("section {section} at {addr:08X} is outside RAM"))?;
+        target.copy_from_slice(source);
+    }
+    Ok(word(DOL_ENTRY))
+}
+
+enum Target {
+    Ram(usize),
+    LockedCache(usize),
+    Mmio(usize),
+    Unmapped,
+}
+
+struct Hardware {
+    ram: Vec<u8>,
+    locked_cache: Vec<u8>,
+    /// Last written value of every register, side effects are applied on access
+    mmio: Vec<u8>,
+    line: u32,
+    pad: [u32; 2],
+    interrupt: bool,
+}
+
+impl Hardware {
+    fn new() -> Self {
+        let mut hw = Self {
+            ram: vec![0; RAM_SIZE],
+            locked_cache: vec![0; LOCKED_CACHE_SIZE],
+            mmio: vec![0; MMIO_SIZE],
+            line: 1,
+            pad: [0x0080_8080, 0x8080_0000],
+            interrupt: false,
+        };
+        hw.set_reg(reg::DSP_MAILBOX_OUT, 4, DSP_INIT_MAIL);
+        hw
+    }
+
+    /// The globals at the start of RAM that the IPL fills in before starting a DOL
+    fn write_boot_info(&mut self) {
+        for (addr, value) in [
+            (0x20, 0x0D15_EA5E),
+            (0x24, 1),
+            (0x28, RAM_SIZE as u32),
+            (0x2C, 1),
+            (0x34, ARENA_HIGH),
+            (0xCC, 0),
+            (0xF0, RAM_SIZE as u32),
+            (0xF8, BUS_CLOCK),
+            (0xFC, CPU_CLOCK),
+        ] {
+            self.ram[addr..addr + 4].copy_from_slice(&u32::to_be_bytes(value));
+        }
+    }
+
+    fn map(addr: u32) -> Target {
+        if addr.wrapping_sub(LOCKED_CACHE_BASE) < LOCKED_CACHE_SIZE as u32 {
+            return Target::LockedCache((addr - LOCKED_CACHE_BASE) as usize);
+        }
+        // 0x80000000 and 0xC0000000 are the cached and uncached views of physical memory
+        let phys = addr & 0x3FFF_FFFF;
+        if (phys as usize) < RAM_SIZE {
+            Target::Ram(phys as usize)
+        } else if phys.wrapping_sub(MMIO_BASE) < MMIO_SIZE as u32 {
+            Target::Mmio((phys - MMIO_BASE) as usize)
+        } else {
+            Target::Unmapped
+        }
+    }
+
+    fn read(&mut self, addr: u32, size: usize) -> u32 {
+        let bytes = match Self::map(addr) {
+            Target::Ram(offset) => self.ram.get(offset..offset + size),
+            Target::LockedCache(offset) => self.locked_cache.get(offset..offset + size),
+            Target::Mmio(offset) => {
+                self.refresh(offset);
+                self.mmio.get(offset..offset + size)
+            }
+            Target::Unmapped => None,
+        };
+        bytes.map_or(0, |bytes| {
+            bytes
+                .iter()
+                .fold(0, |value, &byte| (value << 8) | u32::from(byte))
+        })
+    }
+
+    fn write(&mut self, addr: u32, size: usize, value: u32) {
+        let bytes = &u32::to_be_bytes(value)[4 - size..];
+        match Self::map(addr) {
+            Target::Ram(offset) => {
+                if let Some(target) = self.ram.get_mut(offset..offset + size) {
+                    target.copy_from_slice(bytes);
+                }
+            }
+            Target::LockedCache(offset) => {
+                if let Some(target) = self.locked_cache.get_mut(offset..offset + size) {
+                    target.copy_from_slice(bytes);
+                }
+            }
+            Target::Mmio(offset) => self.write_mmio(offset, size, value),
+            // the GX FIFO and anything else unmapped swallow writes
+            Target::Unmapped => {}
+        }
+    }
+
+    fn reg(&self, offset: usize, size: usize) -> u32 {
+        self.mmio[offset..offset + size]
+            .iter()
+            .fold(0, |value, &byte| (value << 8) | u32::from(byte))
+    }
+
+    fn set_reg(&mut self, offset: usize, size: usize, value: u32) {
+        self.mmio[offset..offset + size].copy_from_slice(&u32::to_be_bytes(value)[4 - size..]);
+    }
+
+    /// Updates the registers whose value comes from the hardware rather than the CPU
+    fn refresh(&mut self, offset: usize) {
+        match offset & !3 {
+            0x202C => {
+                self.set_reg(reg::VI_VCT, 2, self.line);
+                self.set_reg(reg::VI_HCT, 2, 1);
+            }
+            reg::PI_INTSR => {
+                let cause = self.interrupt_cause() | PI_RESET_SWITCH;
+                self.set_reg(reg::PI_INTSR, 4, cause);
+            }
+            offset
+                if (reg::SI_CHANNELS..reg::SI_CHANNELS + 4 * SI_CHANNEL_STRIDE)
+                    .contains(&offset) =>
+            {
+                let channel = (offset - reg::SI_CHANNELS) / SI_CHANNEL_STRIDE;
+                let base = reg::SI_CHANNELS + channel * SI_CHANNEL_STRIDE;
+                let status = if channel == 0 {
+                    self.pad
+                } else {
+                    [SI_ERROR, 0]
+                };
+                self.set_reg(base + 4, 4, status[0]);
+                self.set_reg(base + 8, 4, status[1]);
+            }
+            reg::SI_SR => {
+                let status = self.reg(reg::SI_SR, 4) | SI_READ_STATUS_0;
+                self.set_reg(reg::SI_SR, 4, status);
+            }
+            _ => {}
+        }
+    }
+
+    fn write_mmio(&mut self, offset: usize, size: usize, value: u32) {
+        if offset + size > MMIO_SIZE {
+            return;
+        }
+        let dsp_csr = self.reg(reg::DSP_CSR, 2) as u16;
+        let si_comcsr = self.reg(reg::SI_COMCSR, 4);
+        self.set_reg(offset, size, value);
+        match offset & !3 {
+            // the DSP takes mail at once
+            reg::DSP_MAILBOX_IN => {
+                let high = self.reg(reg::DSP_MAILBOX_IN, 2) & 0x7FFF;
+                self.set_reg(reg::DSP_MAILBOX_IN, 2, high);
+            }
+            // the DSP's mailbox to the CPU is read-only
+            reg::DSP_MAILBOX_OUT => self.set_reg(reg::DSP_MAILBOX_OUT, 4, DSP_INIT_MAIL),
+            0x5008 => {
+                let written = self.reg(reg::DSP_CSR, 2) as u16;
+                let interrupts = dsp_csr & DSP_CSR_INTERRUPTS & !written;
+                let csr = (written & !(DSP_CSR_RESET | DSP_CSR_INTERRUPTS)) | interrupts;
+                self.set_reg(reg::DSP_CSR, 2, u32::from(csr));
+            }
+            reg::SI_COMCSR => {
+                let written = self.reg(reg::SI_COMCSR, 4);
+                let mut complete = si_comcsr & SI_TRANSFER_COMPLETE & !written;
+                if written & SI_TRANSFER_START != 0 {
+                    self.si_transfer(((written >> 1) & 3) as usize);
+                    complete = SI_TRANSFER_COMPLETE;
+                }
+                let comcsr = (written & !(SI_TRANSFER_START | SI_TRANSFER_COMPLETE)) | complete;
+                self.set_reg(reg::SI_COMCSR, 4, comcsr);
+            }
+            // EXI transfers finish at once, with nothing attached
+            offset
+                if (0..EXI_CHANNELS)
+                    .any(|channel| offset == reg::EXI_CR + channel * EXI_CHANNEL_STRIDE) =>
+            {
+                let cr = self.reg(offset, 4) & !1;
+                self.set_reg(offset, 4, cr);
+            }
+            _ => {}
+        }
+        self.update_interrupt();
+    }
+
+    /// Answers an SI command from the I/O buffer. Only port 1 has a controller, the
+    /// others report no response.
+    fn si_transfer(&mut self, channel: usize) {
+        if channel != 0 {
+            let status = self.reg(reg::SI_SR, 4) | (0x0800_0000 >> (channel * 8));
+            self.set_reg(reg::SI_SR, 4, status);
+            return;
+        }
+        let response: Vec<u8> = match self.mmio[reg::SI_BUFFER] {
+            0x40 => self
+                .pad
+                .iter()
+                .flat_map(|word| word.to_be_bytes())
+                .collect(),
+            // origin and recalibrate: centred sticks and released triggers
+            0x41 | 0x42 => vec![0x00, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00, 0x00, 0x00, 0x00],
+            _ => SI_CONTROLLER_ID.to_vec(),
+        };
+        self.mmio[reg::SI_BUFFER..reg::SI_BUFFER + response.len()].copy_from_slice(&response);
+    }
+
+    /// Moves the beam to `line`, latching the display interrupts it passes
+    fn advance_beam(&mut self, line: u32) {
+        let previous = self.line;
+        self.line = line;
+        for di in 0..4 {
+            let offset = reg::VI_DI0 + di * 4;
+            let value = self.reg(offset, 4);
+            let target = (value >> 16) & 0x3FF;
+            let passed = if line >= previous {
+                (previous + 1..=line).contains(&target)
+            } else {
+                target > previous || target <= line
+            };
+            if value & VI_DI_ENABLE != 0 && passed {
+                self.set_reg(offset, 4, value | VI_DI_STATUS);
+            }
+        }
+        self.update_interrupt();
+    }
+
+    fn interrupt_cause(&self) -> u32 {
+        let vi = (0..4).any(|di| self.reg(reg::VI_DI0 + di * 4, 4) & VI_DI_STATUS != 0);
+        if vi { PI_VI } else { 0 }
+    }
+
+    fn update_interrupt(&mut self) {
+        self.interrupt = self.interrupt_cause() & self.reg(reg::PI_INTMR, 4) != 0;
+    }
+
+    /// The XFB is YUY2 with the width from the VI picture config and the height from
+    /// its active video lines, doubled unless the mode is progressive
+    fn render_xfb(&self, frame: &mut [u32], width: usize, height: usize) {
+        if self.reg(reg::VI_DCR, 2) & 1 == 0 {
+            frame.fill(0xFF00_0000);
+            return;
+        }
+        let tfbl = self.reg(reg::VI_TFBL, 4);
+        let base = if tfbl & 0x1000_0000 != 0 {
+            (tfbl & 0x00FF_FFFF) << 5
+        } else {
+            tfbl & 0x00FF_FFFF
+        } as usize
+            & 0x01FF_FFFF;
+        let words_per_line = ((self.reg(reg::VI_PICCONF, 2) >> 8) & 0x7F) as usize;
+        let xfb_width = if words_per_line == 0 {
+            width
+        } else {
+            (words_per_line * 16).min(720)
+        };
+        let active_lines = ((self.reg(reg::VI_VTR, 2) >> 4) & 0x3FF) as usize;
+        let progressive = self.reg(reg::VI_DCR, 2) & 0x4 != 0;
+        let xfb_height = match active_lines {
+            0 => height,
+            lines if progressive => lines.min(576),
+            lines => (lines * 2).min(576),
+        };
+        for (y, row) in frame.chunks_exact_mut(width).take(height).enumerate() {
+            let line = base + (y * xfb_height / height) * xfb_width * 2;
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let sx = x * xfb_width / width;
+                let pair = line + (sx & !1) * 2;
+                *pixel = match self.ram.get(pair..pair + 4) {
+                    Some(&[y0, u, y1, v]) => yuv_to_argb(if sx & 1 == 0 { y0 } else { y1 }, u, v),
+                    _ => 0xFF00_0000,
+                };
+            }
+        }
+    }
+}
+
+impl Bus for Hardware {
+    fn read8(&mut self, addr: u32) -> u8 {
+        self.read(addr, 1) as u8
+    }
+
+    fn read16(&mut self, addr: u32) -> u16 {
+        self.read(addr, 2) as u16
+    }
+
+    fn read32(&mut self, addr: u32) -> u32 {
+        self.read(addr, 4)
+    }
+
+    fn write8(&mut self, addr: u32, value: u8) {
+        self.write(addr, 1, u32::from(value));
+    }
+
+    fn write16(&mut self, addr: u32, value: u16) {
+        self.write(addr, 2, u32::from(value));
+    }
+
+    fn write32(&mut self, addr: u32, value: u32) {
+        self.write(addr, 4, value);
+    }
+
+    fn interrupt_pending(&self) -> bool {
+        self.interrupt
+    }
+}
+
+/// BT.601 studio-range YCbCr to ARGB8888
+fn yuv_to_argb(y: u8, u: u8, v: u8) -> u32 {
+    let c = (i32::from(y) - 16) * 298;
+    let d = i32::from(u) - 128;
+    let e = i32::from(v) - 128;
+    let clamp = |value: i32| ((value + 128) >> 8).clamp(0, 255) as u32;
+    let r = clamp(c + 409 * e);
+    let g = clamp(c - 100 * d - 208 * e);
+    let b = clamp(c + 516 * d);
+    0xFF00_0000 | (r << 16) | (g << 8) | b
+}
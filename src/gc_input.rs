@@ -0,0 +1,286 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow};
+use log::{debug, info};
+use sdl2::controller::{Axis, Button as SdlButton};
+
+use crate::config::ControllersConfig;
+use crate::controller::ControllerManager;
+
+const PORTS: usize = 4;
+/// `SIDevice` values in Dolphin's `[Core]` section
+const SI_NONE: u8 = 0;
+const SI_STANDARD_CONTROLLER: u8 = 6;
+
+/// A keyboard key, named per platform the way Dolphin's keyboard backend reports it
+#[derive(Clone, Copy)]
+enum Key {
+    Letter(char),
+    Enter,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy)]
+enum PadInput {
+    Button(SdlButton),
+    /// One direction of a stick, or a trigger
+    Axis(Axis, bool),
+}
+
+/// What drives one GameCube input in Dolphin: a keyboard key and a pad input
+struct Control {
+    name: &'static str,
+    key: Option<Key>,
+    pad: PadInput,
+}
+
+/// The built-in GameCube layout, see the README controls section
+const CONTROLS: &[Control] = &[
+    control(
+        "Buttons/A",
+        Some(Key::Letter('x')),
+        PadInput::Button(SdlButton::A),
+    ),
+    control(
+        "Buttons/B",
+        Some(Key::Letter('z')),
+        PadInput::Button(SdlButton::B),
+    ),
+    control(
+        "Buttons/X",
+        Some(Key::Letter('s')),
+        PadInput::Button(SdlButton::X),
+    ),
+    control(
+        "Buttons/Y",
+        Some(Key::Letter('a')),
+        PadInput::Button(SdlButton::Y),
+    ),
+    control(
+        "Buttons/Z",
+        Some(Key::Letter('e')),
+        PadInput::Button(SdlButton::Back),
+    ),
+    control(
+        "Buttons/Start",
+        Some(Key::Enter),
+        PadInput::Button(SdlButton::Start),
+    ),
+    control(
+        "Main Stick/Up",
+        Some(Key::Up),
+        PadInput::Axis(Axis::LeftY, false),
+    ),
+    control(
+        "Main Stick/Down",
+        Some(Key::Down),
+        PadInput::Axis(Axis::LeftY, true),
+    ),
+    control(
+        "Main Stick/Left",
+        Some(Key::Left),
+        PadInput::Axis(Axis::LeftX, false),
+    ),
+    control(
+        "Main Stick/Right",
+        Some(Key::Right),
+        PadInput::Axis(Axis::LeftX, true),
+    ),
+    control(
+        "C-Stick/Up",
+        Some(Key::Letter('i')),
+        PadInput::Axis(Axis::RightY, false),
+    ),
+    control(
+        "C-Stick/Down",
+        Some(Key::Letter('k')),
+        PadInput::Axis(Axis::RightY, true),
+    ),
+    control(
+        "C-Stick/Left",
+        Some(Key::Letter('j')),
+        PadInput::Axis(Axis::RightX, false),
+    ),
+    control(
+        "C-Stick/Right",
+        Some(Key::Letter('l')),
+        PadInput::Axis(Axis::RightX, true),
+    ),
+    control(
+        "Triggers/L",
+        Some(Key::Letter('q')),
+        PadInput::Button(SdlButton::LeftShoulder),
+    ),
+    control(
+        "Triggers/R",
+        Some(Key::Letter('w')),
+        PadInput::Button(SdlButton::RightShoulder),
+    ),
+    control(
+        "Triggers/L-Analog",
+        Some(Key::Letter('u')),
+        PadInput::Axis(Axis::TriggerLeft, true),
+    ),
+    control(
+        "Triggers/R-Analog",
+        Some(Key::Letter('o')),
+        PadInput::Axis(Axis::TriggerRight, true),
+    ),
+    control("D-Pad/Up", None, PadInput::Button(SdlButton::DPadUp)),
+    control("D-Pad/Down", None, PadInput::Button(SdlButton::DPadDown)),
+    control("D-Pad/Left", None, PadInput::Button(SdlButton::DPadLeft)),
+    control("D-Pad/Right", None, PadInput::Button(SdlButton::DPadRight)),
+];
+
+const fn control(name: &'static str, key: Option<Key>, pad: PadInput) -> Control {
+    Control { name, key, pad }
+}
+
+/// Writes the launcher's layout to `Config/GCPadNew.ini` in the Dolphin user directory,
+/// built from the pads SDL reports right now. Port 1 gets the first pad, or the keyboard
+/// when none is connected, ports 2-4 the next pads in connection order. Returns the
+/// `SIDevice` overrides that unplug the ports without a pad.
+pub fn write_profiles(user_dir: &Path, config: &ControllersConfig) -> Result<Vec<String>> {
+    let sdl = sdl2::init().map_err(|err| anyhow!(err))?;
+    let controllers = ControllerManager::new(&sdl, config)?;
+
+    let mut ini = String::new();
+    let mut overrides = Vec::new();
+    let mut names: Vec<String> = Vec::new();
+    for port in 0..PORTS {
+        let pad = controllers.pad_joystick(port);
+        let plugged = port == 0 || pad.is_some();
+        overrides.push(format!(
+            "--config=Dolphin.Core.SIDevice{port}={}",
+            if plugged {
+                SI_STANDARD_CONTROLLER
+            } else {
+                SI_NONE
+            }
+        ));
+        if !plugged {
+            continue;
+        }
+
+        writeln!(ini, "[GCPad{}]", port + 1)?;
+        match &pad {
+            Some(pad) => {
+                // Dolphin numbers devices with the same name from 0
+                let index = names.iter().filter(|name| **name == pad.name).count();
+                names.push(pad.name.clone());
+                writeln!(ini, "Device = SDL/{index}/{}", pad.name)?;
+                for control in CONTROLS {
+                    writeln!(ini, "{} = `{}`", control.name, pad_input_name(control.pad))?;
+                }
+                if pad.rumble {
+                    writeln!(ini, "Rumble/Motor = Motor")?;
+                }
+                info!("GameCube port {} uses {}", port + 1, pad.name);
+            }
+            None => {
+                writeln!(ini, "Device = {KEYBOARD_DEVICE}")?;
+                for control in CONTROLS {
+                    if let Some(key) = control.key {
+                        writeln!(ini, "{} = `{}`", control.name, key_name(key))?;
+                    }
+                }
+                debug!("GameCube port {} uses the keyboard", port + 1);
+            }
+        }
+        writeln!(ini, "Main Stick/Dead Zone = 15.")?;
+        writeln!(ini, "C-Stick/Dead Zone = 15.")?;
+        writeln!(ini)?;
+    }
+
+    let dir = user_dir.join("Config");
+    fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let path = dir.join("GCPadNew.ini");
+    fs::write(&path, ini).with_context(|| format!("failed to write {}", path.display()))?;
+    debug!("Wrote Dolphin controller profiles to {}", path.display());
+    Ok(overrides)
+}
+
+/// Input names of Dolphin's SDL backend for game controllers
+fn pad_input_name(input: PadInput) -> String {
+    match input {
+        PadInput::Button(button) => match button {
+            SdlButton::A => "Button S",
+            SdlButton::B => "Button E",
+            SdlButton::X => "Button W",
+            SdlButton::Y => "Button N",
+            SdlButton::Back => "Back",
+            SdlButton::Guide => "Guide",
+            SdlButton::Start => "Start",
+            SdlButton::LeftStick => "Thumb L",
+            SdlButton::RightStick => "Thumb R",
+            SdlButton::LeftShoulder => "Shoulder L",
+            SdlButton::RightShoulder => "Shoulder R",
+            SdlButton::DPadUp => "Pad N",
+            SdlButton::DPadDown => "Pad S",
+            SdlButton::DPadLeft => "Pad W",
+            SdlButton::DPadRight => "Pad E",
+            _ => "Misc 1",
+        }
+        .to_string(),
+        PadInput::Axis(Axis::TriggerLeft, _) => "Trigger L".to_string(),
+        PadInput::Axis(Axis::TriggerRight, _) => "Trigger R".to_string(),
+        PadInput::Axis(axis, positive) => {
+            let name = match axis {
+                Axis::LeftX => "Left X",
+                Axis::LeftY => "Left Y",
+                Axis::RightX => "Right X",
+                _ => "Right Y",
+            };
+            format!("{name}{}", if positive { '+' } else { '-' })
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+const KEYBOARD_DEVICE: &str = "DInput/0/Keyboard Mouse";
+#[cfg(target_os = "macos")]
+const KEYBOARD_DEVICE: &str = "Quartz/0/Keyboard & Mouse";
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const KEYBOARD_DEVICE: &str = "XInput2/0/Virtual core pointer";
+
+#[cfg(target_os = "windows")]
+fn key_name(key: Key) -> String {
+    match key {
+        Key::Letter(letter) => letter.to_ascii_uppercase().to_string(),
+        Key::Enter => "RETURN".to_string(),
+        Key::Up => "UP".to_string(),
+        Key::Down => "DOWN".to_string(),
+        Key::Left => "LEFT".to_string(),
+        Key::Right => "RIGHT".to_string(),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn key_name(key: Key) -> String {
+    match key {
+        Key::Letter(letter) => letter.to_ascii_uppercase().to_string(),
+        Key::Enter => "Return".to_string(),
+        Key::Up => "Up Arrow".to_string(),
+        Key::Down => "Down Arrow".to_string(),
+        Key::Left => "Left Arrow".to_string(),
+        Key::Right => "Right Arrow".to_string(),
+    }
+}
+
+/// X11 keysym names
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn key_name(key: Key) -> String {
+    match key {
+        Key::Letter(letter) => letter.to_string(),
+        Key::Enter => "Return".to_string(),
+        Key::Up => "Up".to_string(),
+        Key::Down => "Down".to_string(),
+        Key::Left => "Left".to_string(),
+        Key::Right => "Right".to_string(),
+    }
+}
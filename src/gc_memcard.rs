@@ -0,0 +1,454 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail, ensure};
+use log::info;
+
+use crate::saves;
+use crate::systems::GameSystem;
+
+/// Where exported `.gci` files go in the GameCube saves, the format Dolphin imports and its
+/// GCI folders use
+const EXPORT_DIR: &str = "export";
+const BLOCK_SIZE: usize = 0x2000;
+/// A Memory Card 251, 16 Mbit with 5 system blocks
+const SIZE_MBITS: u16 = 16;
+const BLOCK_COUNT: usize = SIZE_MBITS as usize * 1024 * 1024 / 8 / BLOCK_SIZE;
+const SYSTEM_BLOCKS: usize = 5;
+const DIRECTORY_BLOCKS: [usize; 2] = [1, 2];
+const BAT_BLOCKS: [usize; 2] = [3, 4];
+const ENTRY_SIZE: usize = 0x40;
+const ENTRY_COUNT: usize = 127;
+const LAST_BLOCK: u16 = 0xFFFF;
+/// Offsets of the update counter and checksums in the header and directory blocks
+const COUNTER_OFFSET: usize = 0x1FFA;
+const CHECKSUM_OFFSET: usize = 0x1FFC;
+const HEADER_CHECKSUM_OFFSET: usize = 0x1FC;
+/// GameCube time counts bus ticks (162 MHz / 4) since 2000-01-01
+const TICKS_PER_SECOND: u64 = 40_500_000;
+const GAMECUBE_EPOCH: u64 = 946_684_800;
+
+/// The region in Dolphin's card names, the game ID ends with the region letter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Usa,
+    Japan,
+    Europe,
+}
+
+impl Region {
+    pub fn from_game_code(code: &str) -> Self {
+        match code.as_bytes().get(3) {
+            Some(b'J') => Self::Japan,
+            Some(b'E' | b'N') | None => Self::Usa,
+            Some(_) => Self::Europe,
+        }
+    }
+
+    fn suffix(self) -> &'static str {
+        match self {
+            Self::Usa => "USA",
+            Self::Japan => "JAP",
+            Self::Europe => "EUR",
+        }
+    }
+}
+
+/// Creates blank `MemoryCardA/B.<region>.raw` files under `saves/gamecube/` when missing,
+/// returns the slot A and B paths
+pub fn prepare_cards(region: Region) -> Result<[PathBuf; 2]> {
+    let dir = saves::prepare(GameSystem::GameCube)?;
+    let card = |slot: &str| -> Result<PathBuf> {
+        let path = dir.join(format!("MemoryCard{slot}.{}.raw", region.suffix()));
+        std::path::absolute(&path).with_context(|| format!("failed to resolve {}", path.display()))
+    };
+    let paths = [card("A")?, card("B")?];
+    for path in &paths {
+        if !path.exists() {
+            CardImage::blank(region == Region::Japan).save(path)?;
+            info!("Created memory card {}", path.display());
+        }
+    }
+    Ok(paths)
+}
+
+/// A save as listed in the card directory
+struct SaveFile {
+    /// The raw directory entry, the first 0x40 bytes of an exported `.gci`
+    entry: Vec<u8>,
+    game_code: String,
+    maker_code: String,
+    file_name: String,
+    first_block: u16,
+    block_count: u16,
+}
+
+struct CardImage {
+    data: Vec<u8>,
+}
+
+impl CardImage {
+    fn open(path: &Path) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        ensure!(
+            data.len() >= SYSTEM_BLOCKS * BLOCK_SIZE && data.len() % BLOCK_SIZE == 0,
+            "{} is not a raw GameCube memory card ({} bytes)",
+            path.display(),
+            data.len()
+        );
+        Ok(Self { data })
+    }
+
+    /// A freshly formatted card, like the IPL or Dolphin would create
+    fn blank(shift_jis: bool) -> Self {
+        let mut card = Self {
+            data: vec![0xFF; BLOCK_COUNT * BLOCK_SIZE],
+        };
+        let format_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs().saturating_sub(GAMECUBE_EPOCH))
+            * TICKS_PER_SECOND;
+
+        let header = &mut card.data[..BLOCK_SIZE];
+        // the serial is derived from the format time like the IPL does, with a blank flash ID
+        let mut rand = format_time;
+        for byte in &mut header[..12] {
+            rand = rand.wrapping_mul(0x41C6_4E6D).wrapping_add(0x3039) >> 16;
+            *byte = rand as u8;
+            rand = (rand.wrapping_mul(0x41C6_4E6D).wrapping_add(0x3039) >> 16) & 0x7FFF;
+        }
+        header[0x0C..0x14].copy_from_slice(&format_time.to_be_bytes());
+        header[0x14..0x20].fill(0);
+        header[0x20..0x22].fill(0);
+        header[0x22..0x24].copy_from_slice(&SIZE_MBITS.to_be_bytes());
+        header[0x24..0x26].copy_from_slice(&u16::from(shift_jis).to_be_bytes());
+        header[0x1FA..0x1FC].fill(0);
+        update_checksums(&mut header[..0x200], HEADER_CHECKSUM_OFFSET);
+
+        for (counter, block) in DIRECTORY_BLOCKS.into_iter().enumerate() {
+            let directory = card.block_mut(block);
+            directory[COUNTER_OFFSET..COUNTER_OFFSET + 2]
+                .copy_from_slice(&(counter as u16).to_be_bytes());
+            update_checksums(directory, CHECKSUM_OFFSET);
+        }
+        for (counter, block) in BAT_BLOCKS.into_iter().enumerate() {
+            let bat = card.block_mut(block);
+            bat.fill(0);
+            bat[4..6].copy_from_slice(&(counter as u16).to_be_bytes());
+            bat[6..8].copy_from_slice(&((BLOCK_COUNT - SYSTEM_BLOCKS) as u16).to_be_bytes());
+            bat[8..10].copy_from_slice(&((SYSTEM_BLOCKS - 1) as u16).to_be_bytes());
+            update_bat_checksums(bat);
+        }
+        card
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        saves::write(path, &self.data)
+    }
+
+    fn block(&self, block: usize) -> &[u8] {
+        &self.data[block * BLOCK_SIZE..(block + 1) * BLOCK_SIZE]
+    }
+
+    fn block_mut(&mut self, block: usize) -> &mut [u8] {
+        &mut self.data[block * BLOCK_SIZE..(block + 1) * BLOCK_SIZE]
+    }
+
+    fn block_count(&self) -> usize {
+        self.data.len() / BLOCK_SIZE
+    }
+
+    /// The card keeps two copies of the directory and block map, the one with the
+    /// higher update counter is current
+    fn active(&self, blocks: [usize; 2], counter_offset: usize) -> &[u8] {
+        let counter = |block: usize| {
+            let data = self.block(block);
+            i16::from_be_bytes([data[counter_offset], data[counter_offset + 1]])
+        };
+        if counter(blocks[1]) > counter(blocks[0]) {
+            self.block(blocks[1])
+        } else {
+            self.block(blocks[0])
+        }
+    }
+
+    fn directory(&self) -> &[u8] {
+        self.active(DIRECTORY_BLOCKS, COUNTER_OFFSET)
+    }
+
+    fn bat(&self) -> &[u8] {
+        self.active(BAT_BLOCKS, 4)
+    }
+
+    fn free_blocks(&self) -> u16 {
+        let bat = self.bat();
+        u16::from_be_bytes([bat[6], bat[7]])
+    }
+
+    fn saves(&self) -> Vec<SaveFile> {
+        self.directory()
+            .chunks_exact(ENTRY_SIZE)
+            .take(ENTRY_COUNT)
+            .filter(|entry| entry[..4] != [0xFF; 4])
+            .map(|entry| SaveFile {
+                entry: entry.to_vec(),
+                game_code: text(&entry[0..4]),
+                maker_code: text(&entry[4..6]),
+                file_name: text(&entry[0x08..0x28]),
+                first_block: u16::from_be_bytes([entry[0x36], entry[0x37]]),
+                block_count: u16::from_be_bytes([entry[0x38], entry[0x39]]),
+            })
+            .collect()
+    }
+
+    /// The save's blocks in order, following the block map
+    fn save_data(&self, save: &SaveFile) -> Result<Vec<u8>> {
+        let bat = self.bat();
+        let mut data = Vec::with_capacity(save.block_count as usize * BLOCK_SIZE);
+        let mut block = save.first_block;
+        for index in 0..save.block_count {
+            ensure!(
+                (SYSTEM_BLOCKS..self.block_count()).contains(&(block as usize)),
+                "{} points to block {block}, outside the card",
+                save.file_name
+            );
+            data.extend_from_slice(self.block(block as usize));
+            let offset = 0x0A + (block as usize - SYSTEM_BLOCKS) * 2;
+            let next = u16::from_be_bytes([bat[offset], bat[offset + 1]]);
+            if index + 1 < save.block_count {
+                ensure!(
+                    next != LAST_BLOCK && next != 0,
+                    "{} ends after {} of {} blocks",
+                    save.file_name,
+                    index + 1,
+                    save.block_count
+                );
+                block = next;
+            }
+        }
+        Ok(data)
+    }
+
+    /// The title and description the save shows in the IPL, from its comment block
+    fn comment(&self, save: &SaveFile) -> Option<String> {
+        let data = self.save_data(save).ok()?;
+        let offset = u32::from_be_bytes(save.entry[0x3C..0x40].try_into().ok()?) as usize;
+        let comment = data.get(offset..offset + 64)?;
+        let title = text(&comment[..32]);
+        let description = text(&comment[32..]);
+        Some(match (title.is_empty(), description.is_empty()) {
+            (false, false) => format!("{title} - {description}"),
+            _ => format!("{title}{description}"),
+        })
+    }
+
+    /// A `.gci` file: the directory entry followed by the save's blocks
+    fn export(&self, save: &SaveFile) -> Result<Vec<u8>> {
+        let mut gci = save.entry.clone();
+        gci.extend(self.save_data(save)?);
+        Ok(gci)
+    }
+}
+
+/// Big-endian sums of 16-bit words before `offset`, stored at `offset` and after it
+fn update_checksums(data: &mut [u8], offset: usize) {
+    let (mut sum, mut inverse) = (0u16, 0u16);
+    for word in data[..offset].chunks_exact(2) {
+        let word = u16::from_be_bytes([word[0], word[1]]);
+        sum = sum.wrapping_add(word);
+        inverse = inverse.wrapping_add(word ^ 0xFFFF);
+    }
+    // a sum of 0xFFFF is stored as 0
+    let fix = |value: u16| if value == 0xFFFF { 0 } else { value };
+    data[offset..offset + 2].copy_from_slice(&fix(sum).to_be_bytes());
+    data[offset + 2..offset + 4].copy_from_slice(&fix(inverse).to_be_bytes());
+}
+
+/// The block map checksums come first and cover the rest of the block
+fn update_bat_checksums(bat: &mut [u8]) {
+    let (mut sum, mut inverse) = (0u16, 0u16);
+    for word in bat[4..].chunks_exact(2) {
+        let word = u16::from_be_bytes([word[0], word[1]]);
+        sum = sum.wrapping_add(word);
+        inverse = inverse.wrapping_add(word ^ 0xFFFF);
+    }
+    let fix = |value: u16| if value == 0xFFFF { 0 } else { value };
+    bat[0..2].copy_from_slice(&fix(sum).to_be_bytes());
+    bat[2..4].copy_from_slice(&fix(inverse).to_be_bytes());
+}
+
+/// Printable ASCII up to the first NUL, Shift-JIS titles lose their non-ASCII characters
+fn text(raw: &[u8]) -> String {
+    raw.iter()
+        .take_while(|&&byte| byte != 0)
+        .filter(|byte| byte.is_ascii_graphic() || **byte == b' ')
+        .map(|&byte| byte as char)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Terminal manager for listing the saves on the cards under `saves/gamecube/` and
+/// exporting them as `.gci` files.
+pub fn manage() -> Result<()> {
+    let dir = saves::prepare(GameSystem::GameCube)?;
+
+    loop {
+        let cards = list_cards(&dir)?;
+        println!("\n=== GameCube Memory Cards ({}) ===", dir.display());
+        for (idx, path) in cards.iter().enumerate() {
+            let used = CardImage::open(path)
+                .map(|card| {
+                    format!(
+                        "{} save(s), {} block(s) free",
+                        card.saves().len(),
+                        card.free_blocks()
+                    )
+                })
+                .unwrap_or_else(|err| format!("unreadable: {err}"));
+            println!("{:>2}. {} ({used})", idx + 1, file_name(path));
+        }
+        println!(" n. Create a blank card");
+        println!(" 0. Quit");
+
+        let input = prompt("Select a card: ")?;
+        match input.as_str() {
+            "0" | "q" => return Ok(()),
+            "n" => {
+                let name = prompt("New card name (without .raw): ")?;
+                if name.is_empty() {
+                    continue;
+                }
+                if let Err(err) = saves::check_file_name(&name) {
+                    println!("Error: {err:#}");
+                    continue;
+                }
+                let path = dir.join(format!("{name}.raw"));
+                if path.exists() {
+                    println!("{} already exists.", path.display());
+                } else {
+                    CardImage::blank(name.contains(".JAP")).save(&path)?;
+                    println!("Created {}.", path.display());
+                }
+            }
+            _ => match input.parse::<usize>() {
+                Ok(choice) if (1..=cards.len()).contains(&choice) => {
+                    if let Err(err) = manage_card(&cards[choice - 1]) {
+                        println!("Error: {err:#}");
+                    }
+                }
+                _ => println!("Invalid selection."),
+            },
+        }
+    }
+}
+
+fn manage_card(path: &Path) -> Result<()> {
+    loop {
+        let card = CardImage::open(path)?;
+        let saves = card.saves();
+        println!("\n-- {} --", file_name(path));
+        if saves.is_empty() {
+            println!("    (no saves)");
+        }
+        for (idx, save) in saves.iter().enumerate() {
+            println!(
+                "{:>2}. {}{} {:<32} {:>4} block(s)  {}",
+                idx + 1,
+                save.maker_code,
+                save.game_code,
+                save.file_name,
+                save.block_count,
+                card.comment(save).unwrap_or_default()
+            );
+        }
+        println!("Commands: e <save> export as .gci, a export all, 0 back");
+
+        let input = prompt("> ")?;
+        let mut parts = input.split_whitespace();
+        let command = parts.next().unwrap_or("");
+        let selected: Vec<&SaveFile> = match command {
+            "0" | "b" => return Ok(()),
+            "a" => saves.iter().collect(),
+            "e" => match parts
+                .next()
+                .and_then(|arg| arg.parse::<usize>().ok())
+                .and_then(|idx| idx.checked_sub(1))
+                .and_then(|idx| saves.get(idx))
+            {
+                Some(save) => vec![save],
+                None => {
+                    println!("Usage: e <save number> | a | 0");
+                    continue;
+                }
+            },
+            _ => {
+                println!("Unknown command '{command}'.");
+                continue;
+            }
+        };
+
+        for save in selected {
+            match export_save(&card, save) {
+                Ok(exported) => println!("Exported {} to {}.", save.file_name, exported.display()),
+                Err(err) => println!("Error: {err:#}"),
+            }
+        }
+    }
+}
+
+/// Names the file like Dolphin does, `<maker>-<game>-<file name>.gci`
+fn export_save(card: &CardImage, save: &SaveFile) -> Result<PathBuf> {
+    let dir = saves::dir(GameSystem::GameCube).join(EXPORT_DIR);
+    fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let name: String = save
+        .file_name
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || "-_.".contains(ch) {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let path = dir.join(format!("{}-{}-{name}.gci", save.maker_code, save.game_code));
+    if path.exists() {
+        bail!("{} already exists", path.display());
+    }
+    let gci = card.export(save)?;
+    fs::write(&path, gci).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+fn list_cards(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut cards: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("raw"))
+        })
+        .collect();
+    cards.sort();
+    Ok(cards)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+fn prompt(message: &str) -> Result<String> {
+    print!("{message}");
+    io::stdout().flush().ok();
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input)? == 0 {
+        bail!("standard input closed");
+    }
+    Ok(input.trim().to_string())
+}
@@ -0,0 +1,1101 @@
+use anyhow::{Result, bail};
+
+/// How the CPU reaches memory and hardware. Addresses are effective addresses, the bus
+/// folds the cached and uncached mirrors the BATs set up onto physical memory.
+pub trait Bus {
+    fn read8(&mut self, addr: u32) -> u8;
+    fn read16(&mut self, addr: u32) -> u16;
+    fn read32(&mut self, addr: u32) -> u32;
+    fn write8(&mut self, addr: u32, value: u8);
+    fn write16(&mut self, addr: u32, value: u16);
+    fn write32(&mut self, addr: u32, value: u32);
+    /// The processor interface asserts the external interrupt
+    fn interrupt_pending(&self) -> bool;
+
+    fn read64(&mut self, addr: u32) -> u64 {
+        (u64::from(self.read32(addr)) << 32) | u64::from(self.read32(addr.wrapping_add(4)))
+    }
+
+    fn write64(&mut self, addr: u32, value: u64) {
+        self.write32(addr, (value >> 32) as u32);
+        self.write32(addr.wrapping_add(4), value as u32);
+    }
+}
+
+const MSR_EE: u32 = 0x8000;
+const MSR_FP: u32 = 0x2000;
+const MSR_IP: u32 = 0x0040;
+/// Bits an exception saves in SRR1 and `rfi` restores
+const MSR_SAVED: u32 = 0x87C0_FF73;
+/// POW, EE, PR, FP, FE0, SE, BE, FE1, IR, DR, RI and LE are cleared on an exception
+const MSR_EXCEPTION_CLEAR: u32 = 0x0004_EF33;
+
+const XER_SO: u32 = 0x8000_0000;
+const XER_OV: u32 = 0x4000_0000;
+const XER_CA: u32 = 0x2000_0000;
+
+const VECTOR_EXTERNAL: u32 = 0x500;
+const VECTOR_PROGRAM: u32 = 0x700;
+const VECTOR_FP_UNAVAILABLE: u32 = 0x800;
+const VECTOR_DECREMENTER: u32 = 0x900;
+const VECTOR_SYSTEM_CALL: u32 = 0xC00;
+/// SRR1 flag of a program exception raised by a trap instruction
+const SRR1_TRAP: u32 = 0x0002_0000;
+
+mod spr {
+    pub const XER: usize = 1;
+    pub const LR: usize = 8;
+    pub const CTR: usize = 9;
+    pub const DEC: usize = 22;
+    pub const SRR0: usize = 26;
+    pub const SRR1: usize = 27;
+    pub const TBL_READ: usize = 268;
+    pub const TBU_READ: usize = 269;
+    pub const TBL_WRITE: usize = 284;
+    pub const TBU_WRITE: usize = 285;
+    pub const PVR: usize = 287;
+    pub const IBAT0U: usize = 528;
+    pub const IBAT0L: usize = 529;
+    pub const DBAT0U: usize = 536;
+    pub const DBAT0L: usize = 537;
+    pub const DBAT1U: usize = 538;
+    pub const DBAT1L: usize = 539;
+    pub const GQR0: usize = 912;
+    pub const HID2: usize = 920;
+    pub const DMAL: usize = 923;
+    pub const HID0: usize = 1008;
+    pub const L2CR: usize = 1017;
+}
+
+/// Gekko's processor version
+const PVR_GEKKO: u32 = 0x0008_3214;
+/// HID0 flash invalidate bits, which clear themselves
+const HID0_CACHE_INVALIDATE: u32 = 0x0000_0C00;
+/// L2CR global invalidate and invalidate in progress, done instantly here
+const L2CR_INVALIDATE: u32 = 0x0020_0001;
+/// DMAL trigger bit, locked cache DMA is not emulated
+const DMAL_TRIGGER: u32 = 0x2;
+
+/// Interpreter for the Gekko, the PowerPC 750 derivative in the GameCube. Covers the
+/// integer, floating point and paired single instructions compiled homebrew uses, with
+/// the external, decrementer, system call, trap and FP unavailable exceptions. There
+/// is no MMU: the bus maps addresses the way the standard BAT setup does.
+pub struct Gekko {
+    pub pc: u32,
+    gpr: [u32; 32],
+    /// Paired singles: ps0 is the regular FPR value, ps1 the second half
+    fpr: [[f64; 2]; 32],
+    cr: u32,
+    xer: u32,
+    lr: u32,
+    ctr: u32,
+    msr: u32,
+    fpscr: u32,
+    sr: [u32; 16],
+    spr: Box<[u32; 1024]>,
+    timebase: u64,
+    dec: u32,
+    dec_pending: bool,
+    reservation: Option<u32>,
+}
+
+impl Gekko {
+    /// The state the IPL leaves for a DOL: translation on through BAT0/1, FPU and
+    /// paired singles enabled, stack at the top of the arena
+    pub fn new(entry: u32, stack: u32) -> Self {
+        let mut cpu = Self {
+            pc: entry,
+            gpr: [0; 32],
+            fpr: [[0.0; 2]; 32],
+            cr: 0,
+            xer: 0,
+            lr: 0,
+            ctr: 0,
+            msr: MSR_FP | 0x30,
+            fpscr: 0,
+            sr: [0; 16],
+            spr: Box::new([0; 1024]),
+            timebase: 0,
+            dec: u32::MAX,
+            dec_pending: false,
+            reservation: None,
+        };
+        cpu.gpr[1] = stack;
+        cpu.spr[spr::IBAT0U] = 0x8000_1FFF;
+        cpu.spr[spr::IBAT0L] = 0x0000_0002;
+        cpu.spr[spr::DBAT0U] = 0x8000_1FFF;
+        cpu.spr[spr::DBAT0L] = 0x0000_0002;
+        cpu.spr[spr::DBAT1U] = 0xC000_1FFF;
+        cpu.spr[spr::DBAT1L] = 0x0000_002A;
+        cpu.spr[spr::HID0] = 0x0011_0024;
+        cpu.spr[spr::HID2] = 0xE000_0000;
+        cpu
+    }
+
+    /// Advances the time base and decrementer, both run at a quarter of the bus clock
+    pub fn tick(&mut self, ticks: u32) {
+        self.timebase = self.timebase.wrapping_add(u64::from(ticks));
+        let old = self.dec;
+        self.dec = old.wrapping_sub(ticks);
+        if (old as i32) >= 0 && (self.dec as i32) < 0 {
+            self.dec_pending = true;
+        }
+    }
+
+    /// Takes a pending interrupt or executes one instruction
+    pub fn step(&mut self, bus: &mut impl Bus) -> Result<()> {
+        if self.msr & MSR_EE != 0 {
+            if bus.interrupt_pending() {
+                self.exception(VECTOR_EXTERNAL, self.pc, 0);
+                return Ok(());
+            }
+            if self.dec_pending {
+                self.dec_pending = false;
+                self.exception(VECTOR_DECREMENTER, self.pc, 0);
+                return Ok(());
+            }
+        }
+        let pc = self.pc;
+        let instr = bus.read32(pc);
+        self.pc = pc.wrapping_add(4);
+        if self.msr & MSR_FP == 0 && uses_fpu(instr) {
+            self.exception(VECTOR_FP_UNAVAILABLE, pc, 0);
+            return Ok(());
+        }
+        self.execute(bus, pc, instr)
+    }
+
+    fn exception(&mut self, vector: u32, srr0: u32, flags: u32) {
+        self.spr[spr::SRR0] = srr0;
+        self.spr[spr::SRR1] = (self.msr & MSR_SAVED) | flags;
+        self.msr &= !MSR_EXCEPTION_CLEAR;
+        self.reservation = None;
+        self.pc = if self.msr & MSR_IP != 0 {
+            0xFFF0_0000 | vector
+        } else {
+            vector
+        };
+    }
+
+    fn execute(&mut self, bus: &mut impl Bus, pc: u32, i: u32) -> Result<()> {
+        let rd = ((i >> 21) & 31) as usize;
+        let ra = ((i >> 16) & 31) as usize;
+        let rb = ((i >> 11) & 31) as usize;
+        let simm = i as i16 as i32 as u32;
+        let uimm = i & 0xFFFF;
+        match i >> 26 {
+            3 => {
+                if trap_condition(rd as u32, self.gpr[ra], simm) {
+                    self.exception(VECTOR_PROGRAM, pc, SRR1_TRAP);
+                }
+            }
+            4 => self.paired_single(bus, pc, i)?,
+            7 => self.gpr[rd] = (self.gpr[ra] as i32).wrapping_mul(simm as i32) as u32,
+            8 => {
+                let (result, carry, _) = add_with_carry(!self.gpr[ra], simm, 1);
+                self.gpr[rd] = result;
+                self.set_carry(carry);
+            }
+            10 => {
+                let value = self.compare_unsigned(self.gpr[ra], uimm);
+                self.set_cr_field(rd >> 2, value);
+            }
+            11 => {
+                let value = self.compare_signed(self.gpr[ra], simm);
+                self.set_cr_field(rd >> 2, value);
+            }
+            12 | 13 => {
+                let (result, carry, _) = add_with_carry(self.gpr[ra], simm, 0);
+                self.gpr[rd] = result;
+                self.set_carry(carry);
+                if i >> 26 == 13 {
+                    self.update_cr0(result);
+                }
+            }
+            14 => self.gpr[rd] = self.base(ra).wrapping_add(simm),
+            15 => self.gpr[rd] = self.base(ra).wrapping_add(uimm << 16),
+            16 => {
+                let bo = rd as u32;
+                if self.branch_condition(bo, ra as u32, true) {
+                    let target = (i & 0xFFFC) as i16 as i32 as u32;
+                    self.branch(pc, i, target);
+                }
+            }
+            17 => self.exception(VECTOR_SYSTEM_CALL, self.pc, 0),
+            18 => {
+                let target = (((i & 0x03FF_FFFC) << 6) as i32 >> 6) as u32;
+                self.branch(pc, i, target);
+            }
+            19 => self.opcode19(pc, i)?,
+            20 => {
+                let (sh, mb, me) = rotate_fields(i);
+                let mask = rotate_mask(mb, me);
+                let rotated = self.gpr[rd].rotate_left(sh);
+                self.gpr[ra] = (rotated & mask) | (self.gpr[ra] & !mask);
+                self.record(i, self.gpr[ra]);
+            }
+            21 => {
+                let (sh, mb, me) = rotate_fields(i);
+                self.gpr[ra] = self.gpr[rd].rotate_left(sh) & rotate_mask(mb, me);
+                self.record(i, self.gpr[ra]);
+            }
+            23 => {
+                let (_, mb, me) = rotate_fields(i);
+                let sh = self.gpr[rb] & 31;
+                self.gpr[ra] = self.gpr[rd].rotate_left(sh) & rotate_mask(mb, me);
+                self.record(i, self.gpr[ra]);
+            }
+            24 => self.gpr[ra] = self.gpr[rd] | uimm,
+            25 => self.gpr[ra] = self.gpr[rd] | (uimm << 16),
+            26 => self.gpr[ra] = self.gpr[rd] ^ uimm,
+            27 => self.gpr[ra] = self.gpr[rd] ^ (uimm << 16),
+            28 => {
+                self.gpr[ra] = self.gpr[rd] & uimm;
+                self.update_cr0(self.gpr[ra]);
+            }
+            29 => {
+                self.gpr[ra] = self.gpr[rd] & (uimm << 16);
+                self.update_cr0(self.gpr[ra]);
+            }
+            31 => self.opcode31(bus, pc, i)?,
+            32..=55 => self.load_store(bus, i)?,
+            56 | 57 => {
+                let (w, gqr, ea) = self.quantized_d_form(i);
+                self.load_quantized(bus, rd, ea, w, gqr);
+                if i >> 26 == 57 {
+                    self.gpr[ra] = ea;
+                }
+            }
+            59 => self.opcode59(i)?,
+            60 | 61 => {
+                let (w, gqr, ea) = self.quantized_d_form(i);
+                self.store_quantized(bus, rd, ea, w, gqr);
+                if i >> 26 == 61 {
+                    self.gpr[ra] = ea;
+                }
+            }
+            63 => self.opcode63(i)?,
+            _ => bail!("unimplemented Gekko instruction {i:08X} at {pc:08X}"),
+        }
+        Ok(())
+    }
+
+    fn opcode19(&mut self, pc: u32, i: u32) -> Result<()> {
+        let bt = (i >> 21) & 31;
+        let ba = (i >> 16) & 31;
+        let bb = (i >> 11) & 31;
+        match (i >> 1) & 0x3FF {
+            0 => {
+                let value = self.cr_field((ba >> 2) as usize);
+                self.set_cr_field((bt >> 2) as usize, value);
+            }
+            16 => {
+                if self.branch_condition(bt, ba, true) {
+                    let target = self.lr & !3;
+                    if i & 1 != 0 {
+                        self.lr = pc.wrapping_add(4);
+                    }
+                    self.pc = target;
+                }
+            }
+            528 => {
+                if self.branch_condition(bt, ba, false) {
+                    let target = self.ctr & !3;
+                    if i & 1 != 0 {
+                        self.lr = pc.wrapping_add(4);
+                    }
+                    self.pc = target;
+                }
+            }
+            50 => {
+                let srr1 = self.spr[spr::SRR1];
+                self.msr = (self.msr & !MSR_SAVED) | (srr1 & MSR_SAVED);
+                self.pc = self.spr[spr::SRR0] & !3;
+            }
+            150 => {}
+            op => {
+                let a = self.cr_bit(ba);
+                let b = self.cr_bit(bb);
+                let value = match op {
+                    33 => !(a | b),
+                    129 => a & !b,
+                    193 => a ^ b,
+                    225 => !(a & b),
+                    257 => a & b,
+                    289 => !(a ^ b),
+                    417 => a | !b,
+                    449 => a | b,
+                    _ => bail!("unimplemented Gekko instruction {i:08X} at {pc:08X}"),
+                };
+                self.set_cr_bit(bt, value);
+            }
+        }
+        Ok(())
+    }
+
+    fn opcode31(&mut self, bus: &mut impl Bus, pc: u32, i: u32) -> Result<()> {
+        let rd = ((i >> 21) & 31) as usize;
+        let ra = ((i >> 16) & 31) as usize;
+        let rb = ((i >> 11) & 31) as usize;
+        let xo = (i >> 1) & 0x3FF;
+        if is_xo_form(xo) {
+            return self.arithmetic(i, rd, ra, rb);
+        }
+        let ea = self.base(ra).wrapping_add(self.gpr[rb]);
+        match xo {
+            0 => {
+                let value = self.compare_signed(self.gpr[ra], self.gpr[rb]);
+                self.set_cr_field(rd >> 2, value);
+            }
+            32 => {
+                let value = self.compare_unsigned(self.gpr[ra], self.gpr[rb]);
+                self.set_cr_field(rd >> 2, value);
+            }
+            4 => {
+                if trap_condition(rd as u32, self.gpr[ra], self.gpr[rb]) {
+                    self.exception(VECTOR_PROGRAM, pc, SRR1_TRAP);
+                }
+            }
+            19 => self.gpr[rd] = self.cr,
+            144 => {
+                let fxm = (i >> 12) & 0xFF;
+                let mask = (0..8)
+                    .filter(|field| fxm & (0x80 >> field) != 0)
+                    .fold(0u32, |mask, field| mask | (0xF000_0000 >> (field * 4)));
+                self.cr = (self.cr & !mask) | (self.gpr[rd] & mask);
+            }
+            512 => {
+                self.set_cr_field(rd >> 2, self.xer >> 28);
+                self.xer &= !(XER_SO | XER_OV | XER_CA);
+            }
+            83 => self.gpr[rd] = self.msr,
+            146 => self.msr = self.gpr[rd],
+            210 => self.sr[ra & 15] = self.gpr[rd],
+            242 => self.sr[(self.gpr[rb] >> 28) as usize] = self.gpr[rd],
+            595 => self.gpr[rd] = self.sr[ra & 15],
+            659 => self.gpr[rd] = self.sr[(self.gpr[rb] >> 28) as usize],
+            339 | 371 => self.gpr[rd] = self.read_spr(spr_number(i)),
+            467 => self.write_spr(spr_number(i), self.gpr[rd]),
+            // logical, shifts and sign extension write rA from rS
+            28 => self.logical(i, ra, self.gpr[rd] & self.gpr[rb]),
+            60 => self.logical(i, ra, self.gpr[rd] & !self.gpr[rb]),
+            124 => self.logical(i, ra, !(self.gpr[rd] | self.gpr[rb])),
+            284 => self.logical(i, ra, !(self.gpr[rd] ^ self.gpr[rb])),
+            316 => self.logical(i, ra, self.gpr[rd] ^ self.gpr[rb]),
+            412 => self.logical(i, ra, self.gpr[rd] | !self.gpr[rb]),
+            444 => self.logical(i, ra, self.gpr[rd] | self.gpr[rb]),
+            476 => self.logical(i, ra, !(self.gpr[rd] & self.gpr[rb])),
+            26 => self.logical(i, ra, self.gpr[rd].leading_zeros()),
+            922 => self.logical(i, ra, self.gpr[rd] as i16 as i32 as u32),
+            954 => self.logical(i, ra, self.gpr[rd] as i8 as i32 as u32),
+            24 => {
+                let n = self.gpr[rb] & 0x3F;
+                let value = if n > 31 { 0 } else { self.gpr[rd] << n };
+                self.logical(i, ra, value);
+            }
+            536 => {
+                let n = self.gpr[rb] & 0x3F;
+                let value = if n > 31 { 0 } else { self.gpr[rd] >> n };
+                self.logical(i, ra, value);
+            }
+            792 => {
+                let n = self.gpr[rb] & 0x3F;
+                self.shift_right_algebraic(i, ra, self.gpr[rd], n);
+            }
+            824 => self.shift_right_algebraic(i, ra, self.gpr[rd], rb as u32),
+            // indexed loads and stores
+            23 | 55 => {
+                self.gpr[rd] = bus.read32(ea);
+                self.update_index(xo == 55, ra, ea);
+            }
+            87 | 119 => {
+                self.gpr[rd] = u32::from(bus.read8(ea));
+                self.update_index(xo == 119, ra, ea);
+            }
+            279 | 311 => {
+                self.gpr[rd] = u32::from(bus.read16(ea));
+                self.update_index(xo == 311, ra, ea);
+            }
+            343 | 375 => {
+                self.gpr[rd] = bus.read16(ea) as i16 as i32 as u32;
+                self.update_index(xo == 375, ra, ea);
+            }
+            151 | 183 => {
+                bus.write32(ea, self.gpr[rd]);
+                self.update_index(xo == 183, ra, ea);
+            }
+            215 | 247 => {
+                bus.write8(ea, self.gpr[rd] as u8);
+                self.update_index(xo == 247, ra, ea);
+            }
+            407 | 439 => {
+                bus.write16(ea, self.gpr[rd] as u16);
+                self.update_index(xo == 439, ra, ea);
+            }
+            534 => self.gpr[rd] = bus.read32(ea).swap_bytes(),
+            790 => self.gpr[rd] = u32::from(bus.read16(ea).swap_bytes()),
+            662 => bus.write32(ea, self.gpr[rd].swap_bytes()),
+            918 => bus.write16(ea, (self.gpr[rd] as u16).swap_bytes()),
+            20 => {
+                self.gpr[rd] = bus.read32(ea);
+                self.reservation = Some(ea);
+            }
+            150 => {
+                let stored = self.reservation.take() == Some(ea);
+                if stored {
+                    bus.write32(ea, self.gpr[rd]);
+                }
+                let so = u32::from(self.xer & XER_SO != 0);
+                self.set_cr_field(0, (u32::from(stored) << 1) | so);
+            }
+            597 => {
+                let count = if rb == 0 { 32 } else { rb as u32 };
+                self.load_string(bus, rd, self.base(ra), count);
+            }
+            725 => {
+                let count = if rb == 0 { 32 } else { rb as u32 };
+                self.store_string(bus, rd, self.base(ra), count);
+            }
+            535 | 567 => {
+                let value = f64::from(f32::from_bits(bus.read32(ea)));
+                self.fpr[rd] = [value, value];
+                self.update_index(xo == 567, ra, ea);
+            }
+            599 | 631 => {
+                self.fpr[rd][0] = f64::from_bits(bus.read64(ea));
+                self.update_index(xo == 631, ra, ea);
+            }
+            663 | 695 => {
+                bus.write32(ea, (self.fpr[rd][0] as f32).to_bits());
+                self.update_index(xo == 695, ra, ea);
+            }
+            727 | 759 => {
+                bus.write64(ea, self.fpr[rd][0].to_bits());
+                self.update_index(xo == 759, ra, ea);
+            }
+            983 => bus.write32(ea, self.fpr[rd][0].to_bits() as u32),
+            1014 => {
+                let line = ea & !31;
+                for offset in (0..32).step_by(4) {
+                    bus.write32(line + offset, 0);
+                }
+            }
+            // cache, TLB and ordering instructions have nothing to do without caches
+            54 | 86 | 246 | 278 | 306 | 310 | 438 | 470 | 566 | 598 | 854 | 982 => {}
+            _ => bail!("unimplemented Gekko instruction {i:08X} at {pc:08X}"),
+        }
+        Ok(())
+    }
+
+    /// XO-form arithmetic, with the OE bit as the top bit of the extended opcode
+    fn arithmetic(&mut self, i: u32, rd: usize, ra: usize, rb: usize) -> Result<()> {
+        let a = self.gpr[ra];
+        let b = self.gpr[rb];
+        let carry_in = u32::from(self.xer & XER_CA != 0);
+        let (result, carry, overflow) = match (i >> 1) & 0x1FF {
+            266 => {
+                let (result, _, overflow) = add_with_carry(a, b, 0);
+                (result, None, overflow)
+            }
+            10 => with_carry(add_with_carry(a, b, 0)),
+            138 => with_carry(add_with_carry(a, b, carry_in)),
+            202 => with_carry(add_with_carry(a, 0, carry_in)),
+            234 => with_carry(add_with_carry(a, u32::MAX, carry_in)),
+            40 => {
+                let (result, _, overflow) = add_with_carry(!a, b, 1);
+                (result, None, overflow)
+            }
+            8 => with_carry(add_with_carry(!a, b, 1)),
+            136 => with_carry(add_with_carry(!a, b, carry_in)),
+            200 => with_carry(add_with_carry(!a, 0, carry_in)),
+            232 => with_carry(add_with_carry(!a, u32::MAX, carry_in)),
+            104 => (a.wrapping_neg(), None, a == 0x8000_0000),
+            235 => {
+                let product = i64::from(a as i32) * i64::from(b as i32);
+                (product as u32, None, product != i64::from(product as i32))
+            }
+            75 => {
+                let product = i64::from(a as i32) * i64::from(b as i32);
+                ((product >> 32) as u32, None, false)
+            }
+            11 => {
+                let product = u64::from(a) * u64::from(b);
+                ((product >> 32) as u32, None, false)
+            }
+            491 => {
+                let (a, b) = (a as i32, b as i32);
+                if b == 0 || (a == i32::MIN && b == -1) {
+                    (if a < 0 { u32::MAX } else { 0 }, None, true)
+                } else {
+                    ((a / b) as u32, None, false)
+                }
+            }
+            459 => match a.checked_div(b) {
+                Some(quotient) => (quotient, None, false),
+                None => (0, None, true),
+            },
+            _ => unreachable!("checked by is_xo_form"),
+        };
+        self.gpr[rd] = result;
+        if let Some(carry) = carry {
+            self.set_carry(carry);
+        }
+        if i & 0x400 != 0 {
+            if overflow {
+                self.xer |= XER_OV | XER_SO;
+            } else {
+                self.xer &= !XER_OV;
+            }
+        }
+        self.record(i, result);
+        Ok(())
+    }
+
+    fn load_store(&mut self, bus: &mut impl Bus, i: u32) -> Result<()> {
+        let rd = ((i >> 21) & 31) as usize;
+        let ra = ((i >> 16) & 31) as usize;
+        let op = i >> 26;
+        let ea = self.base(ra).wrapping_add(i as i16 as i32 as u32);
+        // odd opcodes update rA with the address, except lmw and stmw
+        let update = op % 2 == 1 && !matches!(op, 47);
+        match op {
+            32 | 33 => self.gpr[rd] = bus.read32(ea),
+            34 | 35 => self.gpr[rd] = u32::from(bus.read8(ea)),
+            40 | 41 => self.gpr[rd] = u32::from(bus.read16(ea)),
+            42 | 43 => self.gpr[rd] = bus.read16(ea) as i16 as i32 as u32,
+            36 | 37 => bus.write32(ea, self.gpr[rd]),
+            38 | 39 => bus.write8(ea, self.gpr[rd] as u8),
+            44 | 45 => bus.write16(ea, self.gpr[rd] as u16),
+            46 => {
+                for (offset, reg) in (rd..32).enumerate() {
+                    self.gpr[reg] = bus.read32(ea.wrapping_add(offset as u32 * 4));
+                }
+            }
+            47 => {
+                for (offset, reg) in (rd..32).enumerate() {
+                    bus.write32(ea.wrapping_add(offset as u32 * 4), self.gpr[reg]);
+                }
+            }
+            48 | 49 => {
+                let value = f64::from(f32::from_bits(bus.read32(ea)));
+                self.fpr[rd] = [value, value];
+            }
+            50 | 51 => self.fpr[rd][0] = f64::from_bits(bus.read64(ea)),
+            52 | 53 => bus.write32(ea, (self.fpr[rd][0] as f32).to_bits()),
+            54 | 55 => bus.write64(ea, self.fpr[rd][0].to_bits()),
+            _ => unreachable!("only called for opcodes 32 to 55"),
+        }
+        if update && ra != 0 {
+            self.gpr[ra] = ea;
+        }
+        Ok(())
+    }
+
+    fn opcode59(&mut self, i: u32) -> Result<()> {
+        let (d, a, b, c) = fp_fields(i);
+        let (fa, fb, fc) = (self.fpr[a][0], self.fpr[b][0], self.fpr[c][0]);
+        let result = match (i >> 1) & 31 {
+            18 => fa / fb,
+            20 => fa - fb,
+            21 => fa + fb,
+            24 => 1.0 / fb,
+            25 => fa * fc,
+            28 => fa.mul_add(fc, -fb),
+            29 => fa.mul_add(fc, fb),
+            30 => -fa.mul_add(fc, -fb),
+            31 => -fa.mul_add(fc, fb),
+            _ => bail!("unimplemented Gekko instruction {i:08X}"),
+        };
+        let result = round_single(result);
+        self.fpr[d] = [result, result];
+        self.record_fp(i);
+        Ok(())
+    }
+
+    fn opcode63(&mut self, i: u32) -> Result<()> {
+        let (d, a, b, c) = fp_fields(i);
+        let (fa, fb, fc) = (self.fpr[a][0], self.fpr[b][0], self.fpr[c][0]);
+        let xo5 = (i >> 1) & 31;
+        if xo5 & 0x10 != 0 {
+            self.fpr[d][0] = match xo5 {
+                18 => fa / fb,
+                20 => fa - fb,
+                21 => fa + fb,
+                23 => {
+                    if fa >= 0.0 {
+                        fc
+                    } else {
+                        fb
+                    }
+                }
+                25 => fa * fc,
+                26 => 1.0 / fb.sqrt(),
+                28 => fa.mul_add(fc, -fb),
+                29 => fa.mul_add(fc, fb),
+                30 => -fa.mul_add(fc, -fb),
+                31 => -fa.mul_add(fc, fb),
+                _ => bail!("unimplemented Gekko instruction {i:08X}"),
+            };
+            self.record_fp(i);
+            return Ok(());
+        }
+        match (i >> 1) & 0x3FF {
+            0 | 32 => self.compare_fp(d >> 2, fa, fb),
+            12 => {
+                let value = round_single(fb);
+                self.fpr[d] = [value, value];
+            }
+            14 => self.fpr[d][0] = integer_bits(self.round_to_integer(fb)),
+            15 => self.fpr[d][0] = integer_bits(fb.trunc()),
+            40 => self.fpr[d][0] = -fb,
+            72 => self.fpr[d][0] = fb,
+            136 => self.fpr[d][0] = -fb.abs(),
+            264 => self.fpr[d][0] = fb.abs(),
+            38 => self.fpscr |= 0x8000_0000 >> d,
+            70 => self.fpscr &= !(0x8000_0000 >> d),
+            64 => {
+                let value = (self.fpscr >> (28 - 4 * (a as u32 >> 2))) & 0xF;
+                self.set_cr_field(d >> 2, value);
+            }
+            134 => {
+                let shift = 28 - 4 * (d as u32 >> 2);
+                let imm = (i >> 12) & 0xF;
+                self.fpscr = (self.fpscr & !(0xF << shift)) | (imm << shift);
+            }
+            583 => self.fpr[d][0] = f64::from_bits(0xFFF8_0000_0000_0000 | u64::from(self.fpscr)),
+            711 => {
+                let fm = (i >> 17) & 0xFF;
+                let mask = (0..8)
+                    .filter(|field| fm & (0x80 >> field) != 0)
+                    .fold(0u32, |mask, field| mask | (0xF000_0000 >> (field * 4)));
+                let bits = self.fpr[b][0].to_bits() as u32;
+                self.fpscr = (self.fpscr & !mask) | (bits & mask);
+            }
+            _ => bail!("unimplemented Gekko instruction {i:08X}"),
+        }
+        self.record_fp(i);
+        Ok(())
+    }
+
+    fn paired_single(&mut self, bus: &mut impl Bus, pc: u32, i: u32) -> Result<()> {
+        let (d, a, b, c) = fp_fields(i);
+        let [a0, a1] = self.fpr[a];
+        let [b0, b1] = self.fpr[b];
+        let [c0, c1] = self.fpr[c];
+        let result = match (i >> 1) & 0x3FF {
+            0 | 32 => {
+                self.compare_fp(d >> 2, a0, b0);
+                return Ok(());
+            }
+            64 | 96 => {
+                self.compare_fp(d >> 2, a1, b1);
+                return Ok(());
+            }
+            40 => [-b0, -b1],
+            72 => [b0, b1],
+            136 => [-b0.abs(), -b1.abs()],
+            264 => [b0.abs(), b1.abs()],
+            528 => [a0, b0],
+            560 => [a0, b1],
+            592 => [a1, b0],
+            624 => [a1, b1],
+            1014 => {
+                let line = self.base(a).wrapping_add(self.gpr[b]) & !31;
+                for offset in (0..32).step_by(4) {
+                    bus.write32(line + offset, 0);
+                }
+                return Ok(());
+            }
+            xo => match xo & 0x3F {
+                6 | 38 => {
+                    let (w, gqr, ea) = self.quantized_x_form(i);
+                    self.load_quantized(bus, d, ea, w, gqr);
+                    if xo & 0x3F == 38 {
+                        self.gpr[a] = ea;
+                    }
+                    return Ok(());
+                }
+                7 | 39 => {
+                    let (w, gqr, ea) = self.quantized_x_form(i);
+                    self.store_quantized(bus, d, ea, w, gqr);
+                    if xo & 0x3F == 39 {
+                        self.gpr[a] = ea;
+                    }
+                    return Ok(());
+                }
+                _ => match xo & 31 {
+                    10 => [a0 + b1, c1],
+                    11 => [c0, a0 + b1],
+                    12 => [a0 * c0, a1 * c0],
+                    13 => [a0 * c1, a1 * c1],
+                    14 => [a0.mul_add(c0, b0), a1.mul_add(c0, b1)],
+                    15 => [a0.mul_add(c1, b0), a1.mul_add(c1, b1)],
+                    18 => [a0 / b0, a1 / b1],
+                    20 => [a0 - b0, a1 - b1],
+                    21 => [a0 + b0, a1 + b1],
+                    23 => [
+                        if a0 >= 0.0 { c0 } else { b0 },
+                        if a1 >= 0.0 { c1 } else { b1 },
+                    ],
+                    24 => [1.0 / b0, 1.0 / b1],
+                    25 => [a0 * c0, a1 * c1],
+                    26 => [1.0 / b0.sqrt(), 1.0 / b1.sqrt()],
+                    28 => [a0.mul_add(c0, -b0), a1.mul_add(c1, -b1)],
+                    29 => [a0.mul_add(c0, b0), a1.mul_add(c1, b1)],
+                    30 => [-a0.mul_add(c0, -b0), -a1.mul_add(c1, -b1)],
+                    31 => [-a0.mul_add(c0, b0), -a1.mul_add(c1, b1)],
+                    _ => bail!("unimplemented Gekko instruction {i:08X} at {pc:08X}"),
+                },
+            },
+        };
+        self.fpr[d] = [round_single(result[0]), round_single(result[1])];
+        self.record_fp(i);
+        Ok(())
+    }
+
+    /// `psq_l`/`psq_st`: W, GQR index and address from the 12-bit displacement form
+    fn quantized_d_form(&self, i: u32) -> (bool, usize, u32) {
+        let ra = ((i >> 16) & 31) as usize;
+        let offset = (((i & 0xFFF) << 20) as i32 >> 20) as u32;
+        let w = (i >> 15) & 1 != 0;
+        let gqr = ((i >> 12) & 7) as usize;
+        (w, gqr, self.base(ra).wrapping_add(offset))
+    }
+
+    fn quantized_x_form(&self, i: u32) -> (bool, usize, u32) {
+        let ra = ((i >> 16) & 31) as usize;
+        let rb = ((i >> 11) & 31) as usize;
+        let w = (i >> 10) & 1 != 0;
+        let gqr = ((i >> 7) & 7) as usize;
+        (w, gqr, self.base(ra).wrapping_add(self.gpr[rb]))
+    }
+
+    /// Loads one or two values in the GQR's load format, scaled down by its load scale
+    fn load_quantized(&mut self, bus: &mut impl Bus, d: usize, ea: u32, w: bool, gqr: usize) {
+        let gqr = self.spr[spr::GQR0 + gqr];
+        let kind = (gqr >> 16) & 7;
+        let scale = 2f64.powi(-scale_exponent(gqr >> 24));
+        let size = quantized_size(kind);
+        let mut load = |addr: u32| -> f64 {
+            match kind {
+                4 => f64::from(bus.read8(addr)) * scale,
+                5 => f64::from(bus.read16(addr)) * scale,
+                6 => f64::from(bus.read8(addr) as i8) * scale,
+                7 => f64::from(bus.read16(addr) as i16) * scale,
+                _ => f64::from(f32::from_bits(bus.read32(addr))),
+            }
+        };
+        let ps0 = load(ea);
+        let ps1 = if w { 1.0 } else { load(ea.wrapping_add(size)) };
+        self.fpr[d] = [ps0, ps1];
+    }
+
+    fn store_quantized(&mut self, bus: &mut impl Bus, s: usize, ea: u32, w: bool, gqr: usize) {
+        let gqr = self.spr[spr::GQR0 + gqr];
+        let kind = gqr & 7;
+        let scale = 2f64.powi(scale_exponent(gqr >> 8));
+        let size = quantized_size(kind);
+        let mut store = |addr: u32, value: f64| match kind {
+            4 => bus.write8(addr, (value * scale).clamp(0.0, 255.0) as u8),
+            5 => bus.write16(addr, (value * scale).clamp(0.0, 65535.0) as u16),
+            6 => bus.write8(addr, (value * scale).clamp(-128.0, 127.0) as i8 as u8),
+            7 => bus.write16(addr, (value * scale).clamp(-32768.0, 32767.0) as i16 as u16),
+            _ => bus.write32(addr, (value as f32).to_bits()),
+        };
+        let [ps0, ps1] = self.fpr[s];
+        store(ea, ps0);
+        if !w {
+            store(ea.wrapping_add(size), ps1);
+        }
+    }
+
+    fn load_string(&mut self, bus: &mut impl Bus, rd: usize, ea: u32, count: u32) {
+        let mut reg = rd;
+        for index in 0..count {
+            let shift = 24 - (index % 4) * 8;
+            if index % 4 == 0 {
+                self.gpr[reg] = 0;
+            }
+            self.gpr[reg] |= u32::from(bus.read8(ea.wrapping_add(index))) << shift;
+            if index % 4 == 3 {
+                reg = (reg + 1) % 32;
+            }
+        }
+    }
+
+    fn store_string(&mut self, bus: &mut impl Bus, rs: usize, ea: u32, count: u32) {
+        let mut reg = rs;
+        for index in 0..count {
+            let shift = 24 - (index % 4) * 8;
+            bus.write8(ea.wrapping_add(index), (self.gpr[reg] >> shift) as u8);
+            if index % 4 == 3 {
+                reg = (reg + 1) % 32;
+            }
+        }
+    }
+
+    fn read_spr(&self, number: usize) -> u32 {
+        match number {
+            spr::XER => self.xer,
+            spr::LR => self.lr,
+            spr::CTR => self.ctr,
+            spr::DEC => self.dec,
+            spr::TBL_READ => self.timebase as u32,
+            spr::TBU_READ => (self.timebase >> 32) as u32,
+            spr::PVR => PVR_GEKKO,
+            number => self.spr[number],
+        }
+    }
+
+    fn write_spr(&mut self, number: usize, value: u32) {
+        match number {
+            spr::XER => self.xer = value,
+            spr::LR => self.lr = value,
+            spr::CTR => self.ctr = value,
+            spr::DEC => {
+                self.dec = value;
+                self.dec_pending = false;
+            }
+            spr::TBL_WRITE => self.timebase = (self.timebase & !0xFFFF_FFFF) | u64::from(value),
+            spr::TBU_WRITE => {
+                self.timebase = (self.timebase & 0xFFFF_FFFF) | (u64::from(value) << 32)
+            }
+            spr::HID0 => self.spr[number] = value & !HID0_CACHE_INVALIDATE,
+            spr::L2CR => self.spr[number] = value & !L2CR_INVALIDATE,
+            spr::DMAL => self.spr[number] = value & !DMAL_TRIGGER,
+            number => self.spr[number] = value,
+        }
+    }
+
+    /// rA, or 0 when the instruction names r0
+    fn base(&self, ra: usize) -> u32 {
+        if ra == 0 { 0 } else { self.gpr[ra] }
+    }
+
+    fn update_index(&mut self, update: bool, ra: usize, ea: u32) {
+        if update && ra != 0 {
+            self.gpr[ra] = ea;
+        }
+    }
+
+    fn branch(&mut self, pc: u32, i: u32, offset: u32) {
+        if i & 1 != 0 {
+            self.lr = pc.wrapping_add(4);
+        }
+        self.pc = if i & 2 != 0 {
+            offset
+        } else {
+            pc.wrapping_add(offset)
+        };
+    }
+
+    /// `bcctr` cannot decrement CTR, so only `bc` and `bclr` pass `use_ctr`
+    fn branch_condition(&mut self, bo: u32, bi: u32, use_ctr: bool) -> bool {
+        let mut ctr_ok = true;
+        if use_ctr && bo & 0x04 == 0 {
+            self.ctr = self.ctr.wrapping_sub(1);
+            ctr_ok = (self.ctr != 0) != (bo & 0x02 != 0);
+        }
+        let cond_ok = bo & 0x10 != 0 || (self.cr_bit(bi) != 0) == (bo & 0x08 != 0);
+        ctr_ok && cond_ok
+    }
+
+    fn logical(&mut self, i: u32, ra: usize, value: u32) {
+        self.gpr[ra] = value;
+        self.record(i, value);
+    }
+
+    fn shift_right_algebraic(&mut self, i: u32, ra: usize, value: u32, n: u32) {
+        let negative = (value as i32) < 0;
+        let (result, carry) = if n > 31 {
+            (if negative { u32::MAX } else { 0 }, negative)
+        } else {
+            let lost = value & ((1u32 << n) - 1);
+            (((value as i32) >> n) as u32, negative && lost != 0)
+        };
+        self.set_carry(carry);
+        self.logical(i, ra, result);
+    }
+
+    fn record(&mut self, i: u32, result: u32) {
+        if i & 1 != 0 {
+            self.update_cr0(result);
+        }
+    }
+
+    /// The Rc bit of FP instructions copies FX, FEX, VX and OX into CR1
+    fn record_fp(&mut self, i: u32) {
+        if i & 1 != 0 {
+            self.set_cr_field(1, self.fpscr >> 28);
+        }
+    }
+
+    fn update_cr0(&mut self, result: u32) {
+        let value = self.compare_signed(result, 0);
+        self.set_cr_field(0, value);
+    }
+
+    fn compare_signed(&self, a: u32, b: u32) -> u32 {
+        let order = match (a as i32).cmp(&(b as i32)) {
+            std::cmp::Ordering::Less => 8,
+            std::cmp::Ordering::Greater => 4,
+            std::cmp::Ordering::Equal => 2,
+        };
+        order | u32::from(self.xer & XER_SO != 0)
+    }
+
+    fn compare_unsigned(&self, a: u32, b: u32) -> u32 {
+        let order = match a.cmp(&b) {
+            std::cmp::Ordering::Less => 8,
+            std::cmp::Ordering::Greater => 4,
+            std::cmp::Ordering::Equal => 2,
+        };
+        order | u32::from(self.xer & XER_SO != 0)
+    }
+
+    fn compare_fp(&mut self, field: usize, a: f64, b: f64) {
+        let value = if a.is_nan() || b.is_nan() {
+            1
+        } else if a < b {
+            8
+        } else if a > b {
+            4
+        } else {
+            2
+        };
+        self.fpscr = (self.fpscr & !0xF000) | (value << 12);
+        self.set_cr_field(field, value);
+    }
+
+    /// `fctiw` rounds by the FPSCR rounding mode
+    fn round_to_integer(&self, value: f64) -> f64 {
+        match self.fpscr & 3 {
+            1 => value.trunc(),
+            2 => value.ceil(),
+            3 => value.floor(),
+            _ => value.round_ties_even(),
+        }
+    }
+
+    fn cr_field(&self, field: usize) -> u32 {
+        (self.cr >> (28 - field * 4)) & 0xF
+    }
+
+    fn set_cr_field(&mut self, field: usize, value: u32) {
+        let shift = 28 - field * 4;
+        self.cr = (self.cr & !(0xF << shift)) | ((value & 0xF) << shift);
+    }
+
+    fn cr_bit(&self, bit: u32) -> u32 {
+        (self.cr >> (31 - bit)) & 1
+    }
+
+    fn set_cr_bit(&mut self, bit: u32, value: u32) {
+        let mask = 1 << (31 - bit);
+        self.cr = if value & 1 != 0 {
+            self.cr | mask
+        } else {
+            self.cr & !mask
+        };
+    }
+
+    fn set_carry(&mut self, carry: bool) {
+        if carry {
+            self.xer |= XER_CA;
+        } else {
+            self.xer &= !XER_CA;
+        }
+    }
+}
+
+/// FP loads and stores, opcodes 59 and 63 and the paired single group
+fn uses_fpu(i: u32) -> bool {
+    match i >> 26 {
+        4 | 48..=61 | 63 => true,
+        31 => matches!(
+            (i >> 1) & 0x3FF,
+            535 | 567 | 599 | 631 | 663 | 695 | 727 | 759 | 983
+        ),
+        _ => false,
+    }
+}
+
+/// The extended opcodes of XO-form arithmetic, with or without OE set
+fn is_xo_form(xo: u32) -> bool {
+    matches!(
+        xo & 0x1FF,
+        8 | 10 | 11 | 40 | 75 | 104 | 136 | 138 | 200 | 202 | 232 | 234 | 235 | 266 | 459 | 491
+    )
+}
+
+fn add_with_carry(a: u32, b: u32, carry_in: u32) -> (u32, bool, bool) {
+    let sum = u64::from(a) + u64::from(b) + u64::from(carry_in);
+    let result = sum as u32;
+    let overflow = ((a ^ result) & (b ^ result)) >> 31 != 0;
+    (result, sum > u64::from(u32::MAX), overflow)
+}
+
+fn with_carry((result, carry, overflow): (u32, bool, bool)) -> (u32, Option<bool>, bool) {
+    (result, Some(carry), overflow)
+}
+
+/// TO bits: signed less, signed greater, equal, unsigned less, unsigned greater
+fn trap_condition(to: u32, a: u32, b: u32) -> bool {
+    let (sa, sb) = (a as i32, b as i32);
+    (to & 0x10 != 0 && sa < sb)
+        || (to & 0x08 != 0 && sa > sb)
+        || (to & 0x04 != 0 && a == b)
+        || (to & 0x02 != 0 && a < b)
+        || (to & 0x01 != 0 && a > b)
+}
+
+fn rotate_fields(i: u32) -> (u32, u32, u32) {
+    ((i >> 11) & 31, (i >> 6) & 31, (i >> 1) & 31)
+}
+
+fn rotate_mask(mb: u32, me: u32) -> u32 {
+    let begin = u32::MAX >> mb;
+    let end = u32::MAX << (31 - me);
+    if mb <= me { begin & end } else { begin | end }
+}
+
+/// The SPR number is split into two swapped 5-bit halves
+fn spr_number(i: u32) -> usize {
+    (((i >> 16) & 31) | (((i >> 11) & 31) << 5)) as usize
+}
+
+fn fp_fields(i: u32) -> (usize, usize, usize, usize) {
+    (
+        ((i >> 21) & 31) as usize,
+        ((i >> 16) & 31) as usize,
+        ((i >> 11) & 31) as usize,
+        ((i >> 6) & 31) as usize,
+    )
+}
+
+fn round_single(value: f64) -> f64 {
+    f64::from(value as f32)
+}
+
+/// `fctiw`/`fctiwz` leave the integer in the low word of the FPR
+fn integer_bits(value: f64) -> f64 {
+    let integer = if value.is_nan() {
+        i32::MIN
+    } else {
+        value.clamp(f64::from(i32::MIN), f64::from(i32::MAX)) as i32
+    };
+    f64::from_bits(0xFFF8_0000_0000_0000 | u64::from(integer as u32))
+}
+
+/// GQR scales are 6-bit signed exponents
+fn scale_exponent(bits: u32) -> i32 {
+    (((bits & 0x3F) << 26) as i32) >> 26
+}
+
+fn quantized_size(kind: u32) -> u32 {
+    match kind {
+        4 | 6 => 1,
+        5 | 7 => 2,
+        _ => 4,
+    }
+}
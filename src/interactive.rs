@@ -1,7 +1,8 @@
 use std::collections::HashSet;
-use std::time::{Duration, Instant};
+use std::path::PathBuf;
+use std::time::Duration;
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::{Context, Result, anyhow, bail};
 use gameboy_core::Gameboy;
 use gameboy_core::button::Button;
 use gameboy_core::emulator::step_result::StepResult;
@@ -10,12 +11,33 @@ use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::render::{Canvas, Texture};
 use sdl2::video::Window;
+use serde_json::json;
 
+use crate::agent::AgentBridge;
 use crate::audio::AudioPlayer;
+use crate::bezel::Bezel;
+use crate::config::{Config, DisplayConfig};
+use crate::control::{Command, ControlServer};
 use crate::controller::{ControllerManager, VirtualButton};
 use crate::display::{FrameBuffer, HEIGHT, WIDTH};
+use crate::focus_pause::{self, FocusPause};
+use crate::frame_pacer::FramePacer;
+use crate::gb_debugger::Debugger;
+use crate::gb_memory::{Poke, Trigger, TriggerAction};
+use crate::movie::{self, InputMovie};
+use crate::osd;
+use crate::perf_overlay::PerfOverlay;
+use crate::present;
+use crate::rtc::FrameClock;
+use crate::scenario::Scenario;
+use crate::screenshot;
+use crate::systems::GameSystem;
 
 const TARGET_FRAME: Duration = Duration::from_micros(16_667);
+/// The APU mixes a stereo frame every 95 cycles of the 4.194304 MHz clock, not at 44.1 kHz
+pub const SAMPLE_RATE: f64 = 4_194_304.0 / 95.0;
+/// Rumble is refreshed every frame, this only stops the motor if the game stalls
+const RUMBLE_REFRESH_MS: u32 = 100;
 const ALL_BUTTONS: [Button; 8] = [
     Button::Left,
     Button::Right,
@@ -27,39 +49,92 @@ const ALL_BUTTONS: [Button; 8] = [
     Button::Select,
 ];
 
+/// What drives or follows a run besides the player, all off by default
+#[derive(Default)]
+pub struct Automation {
+    pub movie: Option<InputMovie>,
+    /// The cartridge clock of a movie or deterministic run
+    pub clock: Option<FrameClock>,
+    pub control: Option<ControlServer>,
+    pub agent: Option<AgentBridge>,
+    pub pokes: Vec<Poke>,
+    pub debugger: Option<Debugger>,
+    pub triggers: Vec<Trigger>,
+    pub scenario: Option<Scenario>,
+}
+
 pub struct InteractiveRunner {
     _sdl: sdl2::Sdl,
     canvas: Canvas<Window>,
     texture: Texture,
+    bezel: Option<Bezel>,
     event_pump: sdl2::EventPump,
     framebuffer: FrameBuffer,
     keyboard_buttons: HashSet<Button>,
     active_buttons: HashSet<Button>,
     controller: ControllerManager,
     limit_fps: bool,
+    display: DisplayConfig,
+    movie: Option<InputMovie>,
+    /// The cartridge clock of a movie or deterministic run
+    clock: Option<FrameClock>,
+    /// Names the game's screenshots
+    label: String,
+    control: Option<ControlServer>,
+    agent: Option<AgentBridge>,
+    pokes: Vec<Poke>,
+    debugger: Option<Debugger>,
+    triggers: Vec<Trigger>,
+    scenario: Option<Scenario>,
+    /// A breakpoint stopped the game part way through the frame
+    mid_frame: bool,
+    /// Buttons held by remote control clients
+    remote_buttons: HashSet<Button>,
+    /// A rumble cartridge had its motor on after the last frame
+    rumbling: bool,
+    perf: PerfOverlay,
+    focus: FocusPause,
+    frame: u64,
 }
 
 impl InteractiveRunner {
-    pub fn new(title: &str, scale: u32, limit_fps: bool) -> Result<Self> {
+    pub fn new(
+        title: &str,
+        scale: u32,
+        limit_fps: bool,
+        automation: Automation,
+        config: &Config,
+    ) -> Result<Self> {
+        let Automation {
+            movie,
+            clock,
+            control,
+            agent,
+            pokes,
+            debugger,
+            triggers,
+            scenario,
+        } = automation;
         let sdl = sdl2::init().map_err(|e| anyhow!(e))?;
         let video = sdl.video().map_err(|e| anyhow!(e))?;
-        let scaled_w = (WIDTH as u32).saturating_mul(scale.max(1));
-        let scaled_h = (HEIGHT as u32).saturating_mul(scale.max(1));
+        let (scaled_w, scaled_h) = present::oriented(
+            (WIDTH as u32).saturating_mul(scale.max(1)),
+            (HEIGHT as u32).saturating_mul(scale.max(1)),
+        );
         let window = video
             .window(title, scaled_w, scaled_h)
             .position_centered()
             .resizable()
+            .allow_highdpi()
             .build()
             .context("failed to create SDL window")?;
 
-        let mut canvas_builder = window.into_canvas();
-        if limit_fps {
-            canvas_builder = canvas_builder.present_vsync();
-        }
-        let mut canvas = canvas_builder.build().map_err(|e| anyhow!(e))?;
-        canvas
-            .set_logical_size(WIDTH as u32, HEIGHT as u32)
+        let mut canvas =
+            present::canvas(window, limit_fps, &config.display).map_err(|e| anyhow!(e))?;
+        present::apply_fullscreen(canvas.window_mut());
+        present::set_logical_size(&mut canvas, WIDTH as u32, HEIGHT as u32)
             .context("failed to set logical size")?;
+        let bezel = Bezel::load(&canvas, &config.display, GameSystem::GameBoy, title);
 
         let texture_creator = canvas.texture_creator();
         let texture = texture_creator
@@ -67,28 +142,49 @@ impl InteractiveRunner {
             .map_err(|e| anyhow!(e))?;
 
         let event_pump = sdl.event_pump().map_err(|e| anyhow!(e))?;
-        let controller = ControllerManager::new(&sdl)?;
+        let controller = ControllerManager::new(&sdl, &config.controllers)?;
 
         Ok(Self {
             _sdl: sdl,
             canvas,
             texture,
+            bezel,
             event_pump,
             framebuffer: FrameBuffer::new(),
             keyboard_buttons: HashSet::new(),
             active_buttons: HashSet::new(),
             controller,
             limit_fps,
+            display: config.display.clone(),
+            movie,
+            clock,
+            label: title.to_string(),
+            control,
+            agent,
+            pokes,
+            debugger,
+            triggers,
+            scenario,
+            mid_frame: false,
+            remote_buttons: HashSet::new(),
+            rumbling: false,
+            perf: PerfOverlay::new(TARGET_FRAME, &config.display),
+            focus: FocusPause::new(&config.display),
+            frame: 0,
         })
     }
 
     pub fn run(&mut self, gameboy: &mut Gameboy, audio: &mut AudioPlayer) -> Result<()> {
         let mut running = true;
-        let mut last_frame = Instant::now();
+        let mut pacer = FramePacer::new(TARGET_FRAME, self.limit_fps)
+            .vsynced(self.canvas.window(), &self.display);
         while running {
             let events: Vec<_> = self.event_pump.poll_iter().collect();
             for event in events {
                 self.controller.handle_event(&event);
+                audio.handle_sdl_event(&event);
+                self.perf.handle_sdl_event(&event);
+                self.focus.handle_sdl_event(&event);
                 match event {
                     Event::Quit { .. } => running = false,
                     Event::KeyDown {
@@ -107,25 +203,118 @@ impl InteractiveRunner {
                     _ => {}
                 }
             }
+            if self.focus.is_paused() {
+                focus_pause::idle();
+                continue;
+            }
 
-            self.sync_buttons(gameboy);
-            self.emulate_frame(gameboy, audio)?;
-            self.present_frame()?;
-
-            if self.limit_fps {
-                let elapsed = last_frame.elapsed();
-                if elapsed < TARGET_FRAME {
-                    std::thread::sleep(TARGET_FRAME - elapsed);
+            if !self.debugger.as_ref().is_some_and(Debugger::is_paused) {
+                if !self.mid_frame {
+                    if self
+                        .scenario
+                        .as_ref()
+                        .is_some_and(|scenario| scenario.quits_before(self.frame))
+                    {
+                        log::info!("Scenario finished after {} frames", self.frame);
+                        break;
+                    }
+                    self.start_frame(gameboy)?;
+                }
+                self.mid_frame = !self.emulate_frame(gameboy, audio)?;
+                if !self.mid_frame {
+                    self.end_frame(gameboy);
                 }
-                last_frame = Instant::now();
             }
+            self.perf.frame();
+            self.perf.set_audio(audio);
+            self.present_frame(audio.osd_text().as_deref())?;
+
+            pacer.wait();
+        }
+        if let Some(debugger) = self.debugger.take() {
+            debugger.finish()?;
+        }
+        match self.movie.take() {
+            Some(movie) => movie.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Input, the cartridge clock and pokes for the frame about to run
+    fn start_frame(&mut self, gameboy: &mut Gameboy) -> Result<()> {
+        self.handle_requests(gameboy);
+        self.controller.advance_frame();
+        self.controller.advance_macros();
+        self.sync_buttons(gameboy)?;
+        if let Some(clock) = &self.clock {
+            clock.advance();
+        }
+        for poke in self.pokes.iter().filter(|poke| poke.applies_to(self.frame)) {
+            gameboy.poke_byte(poke.address, poke.value);
         }
         Ok(())
     }
 
+    /// Reports the finished frame to remote clients and checks the triggers
+    fn end_frame(&mut self, gameboy: &Gameboy) {
+        let rumbling = gameboy.is_rumbling();
+        if rumbling || self.rumbling {
+            self.controller
+                .rumble(if rumbling { 1.0 } else { 0.0 }, RUMBLE_REFRESH_MS);
+            self.rumbling = rumbling;
+        }
+        if let Some(control) = self.control.as_mut() {
+            control.end_frame(self.frame + 1, |address| gameboy.peek_byte(address));
+        }
+        for trigger in &mut self.triggers {
+            let value = gameboy.peek_byte(trigger.address());
+            if !trigger.fires(value) {
+                continue;
+            }
+            for action in &trigger.actions {
+                match action {
+                    TriggerAction::Log => log::info!(
+                        "Frame {}: {} with {:04X} = {value:02X}",
+                        self.frame,
+                        trigger.condition(),
+                        trigger.address()
+                    ),
+                    TriggerAction::Screenshot => {
+                        match save_screenshot(&self.label, &self.framebuffer) {
+                            Ok(path) => log::info!(
+                                "Frame {}: {} saved {}",
+                                self.frame,
+                                trigger.condition(),
+                                path.display()
+                            ),
+                            Err(err) => log::warn!("Trigger screenshot failed: {err:#}"),
+                        }
+                    }
+                }
+            }
+        }
+        if self
+            .scenario
+            .as_ref()
+            .is_some_and(|scenario| scenario.screenshot_after(self.frame))
+        {
+            match save_screenshot(&self.label, &self.framebuffer) {
+                Ok(path) => log::info!("Scenario saved {}", path.display()),
+                Err(err) => log::warn!("Scenario screenshot failed: {err:#}"),
+            }
+        }
+        self.frame += 1;
+    }
+
     fn handle_press(&mut self, code: Keycode) {
-        if let Some(button) = map_key(code) {
-            self.keyboard_buttons.insert(button);
+        match (code, self.debugger.as_mut()) {
+            (Keycode::F5, Some(debugger)) => debugger.resume(),
+            (Keycode::F10, Some(debugger)) => debugger.step(),
+            _ => {
+                if let Some(button) = map_key(code) {
+                    self.keyboard_buttons.insert(button);
+                }
+            }
         }
     }
 
@@ -135,31 +324,78 @@ impl InteractiveRunner {
         }
     }
 
-    fn sync_buttons(&mut self, gameboy: &mut Gameboy) {
-        let mut desired = self.keyboard_buttons.clone();
-        if self.controller.is_pressed(VirtualButton::Left) {
-            desired.insert(Button::Left);
-        }
-        if self.controller.is_pressed(VirtualButton::Right) {
-            desired.insert(Button::Right);
-        }
-        if self.controller.is_pressed(VirtualButton::Up) {
-            desired.insert(Button::Up);
-        }
-        if self.controller.is_pressed(VirtualButton::Down) {
-            desired.insert(Button::Down);
-        }
-        if self.controller.is_pressed(VirtualButton::A) {
-            desired.insert(Button::A);
+    /// Answers the remote control commands that came in during the last frame
+    fn handle_requests(&mut self, gameboy: &Gameboy) {
+        let Some(control) = self.control.as_mut() else {
+            return;
+        };
+        for request in control.requests() {
+            let result = match &request.command {
+                Command::Press { button } => button_named(button).map(|button| {
+                    self.remote_buttons.insert(button);
+                    json!({})
+                }),
+                Command::Release { button } => button_named(button).map(|button| {
+                    self.remote_buttons.remove(&button);
+                    json!({})
+                }),
+                Command::Read { address, length } => {
+                    let bytes: Vec<u8> = (0..*length)
+                        .map(|offset| gameboy.peek_byte(address.wrapping_add(offset)))
+                        .collect();
+                    Ok(json!({ "address": address, "bytes": bytes }))
+                }
+                Command::SaveState | Command::LoadState => {
+                    Err(anyhow!("the Game Boy core has no save states"))
+                }
+                Command::Screenshot => save_screenshot(&self.label, &self.framebuffer)
+                    .map(|path| json!({ "path": path })),
+                Command::Watch { address } => {
+                    control.watch(*address, true);
+                    Ok(json!({}))
+                }
+                Command::Unwatch { address } => {
+                    control.watch(*address, false);
+                    Ok(json!({}))
+                }
+            };
+            request.reply(result);
         }
-        if self.controller.is_pressed(VirtualButton::B) {
-            desired.insert(Button::B);
+    }
+
+    fn sync_buttons(&mut self, gameboy: &mut Gameboy) -> Result<()> {
+        let mut desired: HashSet<Button> = PAD_BUTTONS
+            .iter()
+            .filter(|&&(button, pad)| {
+                self.controller
+                    .turbo(pad, self.keyboard_buttons.contains(&button))
+                    || self.controller.is_pressed(pad)
+            })
+            .map(|&(button, _)| button)
+            .collect();
+        desired.extend(self.remote_buttons.iter().copied());
+        if let Some(scenario) = &self.scenario {
+            desired.extend(mask_to_buttons(scenario.buttons(self.frame)));
         }
-        if self.controller.is_pressed(VirtualButton::Start) {
-            desired.insert(Button::Start);
+        if let Some(agent) = self.agent.as_mut() {
+            match agent.exchange(self.framebuffer.pixels(), |address| {
+                gameboy.peek_byte(address)
+            })? {
+                Some(mask) => desired = mask_to_buttons(mask),
+                None => {
+                    log::info!("Agent disconnected, the controls are yours");
+                    self.agent = None;
+                }
+            }
         }
-        if self.controller.is_pressed(VirtualButton::Select) {
-            desired.insert(Button::Select);
+        if let Some(movie) = self.movie.as_mut() {
+            match movie.next_frame(buttons_to_mask(&desired))? {
+                Some(mask) => desired = mask_to_buttons(mask),
+                None => {
+                    log::info!("Input movie finished, the controls are yours");
+                    self.movie = None;
+                }
+            }
         }
 
         for button in ALL_BUTTONS {
@@ -177,34 +413,111 @@ impl InteractiveRunner {
                 _ => {}
             }
         }
+        Ok(())
     }
 
-    fn emulate_frame(&mut self, gameboy: &mut Gameboy, audio: &mut AudioPlayer) -> Result<()> {
+    /// Runs to the end of the frame, `false` when a breakpoint stopped it first
+    fn emulate_frame(&mut self, gameboy: &mut Gameboy, audio: &mut AudioPlayer) -> Result<bool> {
         loop {
+            if let Some(debugger) = self.debugger.as_mut()
+                && !debugger.before_instruction(gameboy, self.frame)?
+            {
+                return Ok(false);
+            }
             match gameboy.emulate(&mut self.framebuffer) {
-                StepResult::VBlank => break,
+                StepResult::VBlank => return Ok(true),
                 StepResult::AudioBufferFull => audio.push_samples(gameboy.get_audio_buffer()),
                 StepResult::Nothing => {}
             };
         }
-        Ok(())
     }
 
-    fn present_frame(&mut self) -> Result<()> {
-        self.texture
-            .update(None, self.framebuffer.as_bytes(), WIDTH * 4)
-            .context("failed to upload frame")?;
+    fn present_frame(&mut self, osd_text: Option<&str>) -> Result<()> {
+        let uploaded = if osd_text.is_some() || self.perf.is_open() {
+            // drawn on a copy, screenshots and the agent keep seeing the game alone
+            let mut pixels = self.framebuffer.pixels().to_vec();
+            if self.perf.is_open() {
+                self.perf.draw(&mut pixels, WIDTH);
+            }
+            if let Some(text) = osd_text {
+                osd::draw_message(&mut pixels, WIDTH, text);
+            }
+            self.texture
+                .update(None, bytemuck::cast_slice(&pixels), WIDTH * 4)
+        } else {
+            self.texture
+                .update(None, self.framebuffer.as_bytes(), WIDTH * 4)
+        };
+        uploaded.context("failed to upload frame")?;
         self.canvas.clear();
-        self.canvas
-            .copy(&self.texture, None, None)
-            .map_err(|e| anyhow!(e))?;
+        if let Some(bezel) = &self.bezel {
+            bezel.draw(&mut self.canvas).map_err(|e| anyhow!(e))?;
+        }
+        present::copy(&mut self.canvas, &self.texture, None).map_err(|e| anyhow!(e))?;
         self.canvas.present();
         Ok(())
     }
 }
 
+fn save_screenshot(label: &str, framebuffer: &FrameBuffer) -> Result<PathBuf> {
+    screenshot::save(label, WIDTH as u32, HEIGHT as u32, framebuffer.pixels())
+}
+
+/// Input movie bits of each button
+/// The controller button behind each Game Boy button
+const PAD_BUTTONS: [(Button, VirtualButton); 8] = [
+    (Button::Left, VirtualButton::Left),
+    (Button::Right, VirtualButton::Right),
+    (Button::Up, VirtualButton::Up),
+    (Button::Down, VirtualButton::Down),
+    (Button::A, VirtualButton::A),
+    (Button::B, VirtualButton::B),
+    (Button::Start, VirtualButton::Start),
+    (Button::Select, VirtualButton::Select),
+];
+const MOVIE_BITS: [(Button, u8); 8] = [
+    (Button::Left, movie::LEFT),
+    (Button::Right, movie::RIGHT),
+    (Button::Up, movie::UP),
+    (Button::Down, movie::DOWN),
+    (Button::A, movie::A),
+    (Button::B, movie::B),
+    (Button::Start, movie::START),
+    (Button::Select, movie::SELECT),
+];
+
+fn buttons_to_mask(buttons: &HashSet<Button>) -> u8 {
+    MOVIE_BITS
+        .iter()
+        .filter(|(button, _)| buttons.contains(button))
+        .fold(0, |mask, (_, bit)| mask | bit)
+}
+
+fn mask_to_buttons(mask: u8) -> HashSet<Button> {
+    MOVIE_BITS
+        .iter()
+        .filter(|(_, bit)| mask & bit != 0)
+        .map(|(button, _)| *button)
+        .collect()
+}
+
+/// Buttons by the names remote control clients use
+fn button_named(name: &str) -> Result<Button> {
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "left" => Button::Left,
+        "right" => Button::Right,
+        "up" => Button::Up,
+        "down" => Button::Down,
+        "a" => Button::A,
+        "b" => Button::B,
+        "start" => Button::Start,
+        "select" => Button::Select,
+        _ => bail!("unknown button {name:?}"),
+    })
+}
+
 fn map_key(key: Keycode) -> Option<Button> {
-    match key {
+    match present::game_arrow_key(key) {
         Keycode::Left => Some(Button::Left),
         Keycode::Right => Some(Button::Right),
         Keycode::Up => Some(Button::Up),
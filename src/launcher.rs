@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
@@ -6,11 +6,17 @@ use std::path::{Path, PathBuf};
 use anyhow::{Context, Result, bail};
 use log::warn;
 
+use crate::config::{Config, Ps1Config};
+use crate::gc_banner::{self, BANNER_WIDTH};
+use crate::ps1;
+use crate::resume;
+use crate::saves;
 use crate::systems::{GameSystem, detect_system};
 
-const SUPPORTED_EXTENSIONS: [&str; 19] = [
-    "gb", "gbc", "nes", "sfc", "smc", "snes", "nds", "cue", "exe", "n64", "z64", "v64", "iso",
-    "gcm", "gcz", "gcn", "ciso", "dol", "rvz",
+const SUPPORTED_EXTENSIONS: [&str; 31] = [
+    "gb", "gbc", "nes", "sfc", "smc", "snes", "nds", "cue", "exe", "pbp", "bin", "img", "n64",
+    "z64", "v64", "ndd", "iso", "gcm", "gcz", "gcn", "ciso", "dol", "rvz", "wbfs", "prg", "t64",
+    "crt", "tap", "p00", "d64", "g64",
 ];
 
 #[derive(Clone)]
@@ -19,23 +25,42 @@ struct GameEntry {
     name: String,
 }
 
+/// What the GUI's detail pane shows for the selected game
+struct GameDetails {
+    /// ARGB8888 pixels with their width
+    image: Option<(usize, Vec<u32>)>,
+    title: String,
+    lines: Vec<String>,
+}
+
 #[derive(Clone)]
 struct SystemGroup {
     system: GameSystem,
     games: Vec<GameEntry>,
 }
 
-pub fn select_game(dir: &Path, use_gui: bool) -> Result<PathBuf> {
-    let systems = collect_games(dir)?;
+/// Lets the player pick a game, and with `pick_profile` whose saves to play with as well when
+/// there are profiles to choose from
+pub fn select_game(
+    dir: &Path,
+    use_gui: bool,
+    config: &Config,
+    pick_profile: bool,
+) -> Result<PathBuf> {
+    let systems = collect_games(dir, &config.ps1)?;
     if use_gui {
-        gui::select_game_gui(systems)
+        gui::select_game_gui(systems, pick_profile, &config.display)
     } else {
+        if pick_profile {
+            prompt_profile();
+        }
         select_game_tui(systems)
     }
 }
 
-fn collect_games(dir: &Path) -> Result<Vec<SystemGroup>> {
+fn collect_games(dir: &Path, ps1_settings: &Ps1Config) -> Result<Vec<SystemGroup>> {
     let mut games_by_system: BTreeMap<GameSystem, Vec<GameEntry>> = BTreeMap::new();
+    let cue_tracks = cue_sheet_tracks(dir);
 
     fs::read_dir(dir)
         .with_context(|| format!("failed to read games directory at {}", dir.display()))?
@@ -49,15 +74,18 @@ fn collect_games(dir: &Path) -> Result<Vec<SystemGroup>> {
                 Some(ext) => ext.to_ascii_lowercase(),
                 None => return,
             };
-            if !SUPPORTED_EXTENSIONS.contains(&ext.as_str()) {
+            if !SUPPORTED_EXTENSIONS.contains(&ext.as_str()) || cue_tracks.contains(&path) {
                 return;
             }
             match detect_system(&path) {
                 Ok(system) => {
-                    games_by_system
-                        .entry(system)
-                        .or_default()
-                        .push(GameEntry::from(path));
+                    let mut entry = GameEntry::from(path);
+                    if system == GameSystem::Ps1
+                        && let Some(title) = ps1_title(&entry.path, ps1_settings)
+                    {
+                        entry.name = title;
+                    }
+                    games_by_system.entry(system).or_default().push(entry);
                 }
                 Err(err) => warn!("Skipping {}: {}", path.display(), err),
             };
@@ -79,6 +107,73 @@ fn collect_games(dir: &Path) -> Result<Vec<SystemGroup>> {
     Ok(systems)
 }
 
+/// The `title` configured for a disc's serial in `[ps1.games]`. Discs are only
+/// opened when titles are configured at all.
+fn ps1_title(path: &Path, ps1_settings: &Ps1Config) -> Option<String> {
+    if ps1_settings.games.values().all(|game| game.title.is_none()) {
+        return None;
+    }
+    let serial = ps1::disc_info(path).serial?;
+    let title = ps1_settings.game(Some(&serial))?.title.as_deref()?;
+    Some(format!("{title} [{serial}]"))
+}
+
+/// Systems whose games have a banner or other details to show
+fn has_details(system: GameSystem) -> bool {
+    system == GameSystem::GameCube
+}
+
+/// Reads the disc banner of a GameCube game
+fn game_details(system: GameSystem, path: &Path) -> Option<GameDetails> {
+    if system != GameSystem::GameCube {
+        return None;
+    }
+    let banner = gc_banner::read(path)
+        .inspect_err(|err| log::debug!("No banner for {}: {err:#}", path.display()))
+        .ok()?;
+    let mut lines = Vec::new();
+    if !banner.maker.is_empty() {
+        lines.push(banner.maker);
+    }
+    if !banner.description.is_empty() {
+        lines.push(banner.description);
+    }
+    Some(GameDetails {
+        image: Some((BANNER_WIDTH, banner.image)),
+        title: banner.title,
+        lines,
+    })
+}
+
+/// Track files referenced by the cue sheets in `dir`, listed through their `.cue` instead
+fn cue_sheet_tracks(dir: &Path) -> HashSet<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return HashSet::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|s| s.to_str())
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+        })
+        .filter_map(|cue| fs::read_to_string(cue).ok())
+        .flat_map(|sheet| {
+            sheet
+                .lines()
+                .filter_map(|line| {
+                    let rest = line.trim().strip_prefix("FILE")?.trim();
+                    let name = match rest.strip_prefix('"') {
+                        Some(quoted) => quoted.split('"').next()?,
+                        None => rest.split_whitespace().next()?,
+                    };
+                    Some(dir.join(name))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
 fn select_game_tui(systems: Vec<SystemGroup>) -> Result<PathBuf> {
     loop {
         println!("\n=== Game Launcher ===");
@@ -106,8 +201,64 @@ fn select_game_tui(systems: Vec<SystemGroup>) -> Result<PathBuf> {
             continue;
         }
 
-        return Ok(group.games[game_choice - 1].path.clone());
+        let game = &group.games[game_choice - 1];
+        if resume::available(group.system, &game.path) && !prompt_resume(&game.name) {
+            resume::start_fresh();
+        }
+        return Ok(game.path.clone());
+    }
+}
+
+/// Asks whose saves to play with when profiles exist. A name not in the list starts a new
+/// profile.
+fn prompt_profile() {
+    let profiles = saves::profiles();
+    if profiles.is_empty() {
+        return;
+    }
+    println!("\n=== Profiles ===");
+    for (idx, name) in profiles.iter().enumerate() {
+        println!("{:>2}. {name}", idx + 1);
+    }
+    println!(" 0. Shared saves");
+    loop {
+        print!("Select a profile by number, or type a name for a new one: ");
+        io::stdout().flush().ok();
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input).is_err() {
+            return;
+        }
+        let input = input.trim();
+        match input.parse::<usize>() {
+            Ok(0) => return,
+            Ok(choice) if choice <= profiles.len() => {
+                saves::set_profile(Some(profiles[choice - 1].clone()));
+                return;
+            }
+            Ok(_) => println!(
+                "Invalid selection. Please enter a number between 0 and {}.",
+                profiles.len()
+            ),
+            Err(_) => match saves::check_profile(input) {
+                Ok(()) => {
+                    saves::set_profile(Some(input.to_string()));
+                    return;
+                }
+                Err(err) => println!("{err}"),
+            },
+        }
+    }
+}
+
+/// Asks whether to pick up where the game was left, anything but no resumes
+fn prompt_resume(name: &str) -> bool {
+    print!("Resume {name} where you left off? [Y/n] ");
+    io::stdout().flush().ok();
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input).is_err() {
+        return true;
     }
+    !matches!(input.trim().to_ascii_lowercase().as_str(), "n" | "no")
 }
 
 fn prompt_number(prompt: &str, min: usize, max: usize) -> usize {
@@ -143,7 +294,11 @@ impl GameEntry {
 }
 
 mod gui {
-    use super::{GameEntry, SystemGroup};
+    use super::{GameDetails, GameEntry, SystemGroup, game_details, has_details};
+    use crate::config::DisplayConfig;
+    use crate::present;
+    use crate::resume;
+    use crate::saves;
     use anyhow::{Context, Result, anyhow, bail};
     use bytemuck::cast_slice;
     use font8x8::legacy::BASIC_LEGACY;
@@ -153,7 +308,9 @@ mod gui {
     use sdl2::pixels::PixelFormatEnum;
     use sdl2::render::{Canvas, Texture};
     use sdl2::video::Window;
+    use std::collections::HashMap;
     use std::path::PathBuf;
+    use std::rc::Rc;
     use std::time::{Duration, Instant};
 
     const WIDTH: u32 = 960;
@@ -164,6 +321,9 @@ mod gui {
     const HEADER_GAP: usize = 34;
     const ROW_HEIGHT: usize = 28;
     const CONSOLE_PANEL_WIDTH: usize = 280;
+    /// Banners are drawn at twice their size
+    const DETAIL_IMAGE_SCALE: usize = 2;
+    const DETAIL_HEIGHT: usize = 96;
     const DOUBLE_CLICK_TIMEOUT: Duration = Duration::from_millis(350);
     const FRAME_DELAY: Duration = Duration::from_millis(16);
 
@@ -175,9 +335,14 @@ mod gui {
     const HIGHLIGHT_ACTIVE: u32 = 0xFF3B82F6;
     const HIGHLIGHT_INACTIVE: u32 = 0xFF2C354E;
     const FOOTER_TEXT: u32 = 0xFF8F97B3;
+    const PROMPT_TEXT: u32 = 0xFFFFD040;
 
-    pub(super) fn select_game_gui(systems: Vec<SystemGroup>) -> Result<PathBuf> {
-        let mut selector = GuiSelector::new(systems)?;
+    pub(super) fn select_game_gui(
+        systems: Vec<SystemGroup>,
+        pick_profile: bool,
+        display: &DisplayConfig,
+    ) -> Result<PathBuf> {
+        let mut selector = GuiSelector::new(systems, pick_profile, display)?;
         selector.run()
     }
 
@@ -194,6 +359,13 @@ mod gui {
         texture: Texture,
         event_pump: sdl2::EventPump,
         last_click: Option<ClickInfo>,
+        /// Loaded when a game is first selected, `None` when it has nothing to show
+        details: HashMap<PathBuf, Option<Rc<GameDetails>>>,
+        /// A launched game with a resume state, waiting for resume or start fresh
+        resume_prompt: Option<PathBuf>,
+        /// The profiles P switches between, empty when the profile was given on the command
+        /// line or there are none
+        profiles: Vec<String>,
     }
 
     #[derive(Clone, Copy, PartialEq, Eq)]
@@ -218,25 +390,38 @@ mod gui {
     struct Layout {
         consoles: Panel,
         games: Panel,
+        /// Below the games of systems with details
+        detail: Option<Panel>,
         visible_rows: usize,
+        game_rows: usize,
         footer_top: usize,
     }
 
     impl GuiSelector {
-        fn new(systems: Vec<SystemGroup>) -> Result<Self> {
+        fn new(
+            systems: Vec<SystemGroup>,
+            pick_profile: bool,
+            display: &DisplayConfig,
+        ) -> Result<Self> {
             let sdl = sdl2::init().map_err(|err| anyhow!(err))?;
             let video = sdl.video().map_err(|err| anyhow!(err))?;
-            let window = video
+            let mut window = video
                 .window("Retro Launcher", WIDTH, HEIGHT)
                 .position_centered()
                 .resizable()
+                .allow_highdpi()
                 .build()
                 .context("failed to create launcher window")?;
-            let canvas = window
+            present::fit_to_display(&mut window, display);
+            let mut canvas = window
                 .into_canvas()
                 .present_vsync()
                 .build()
                 .map_err(|err| anyhow!(err))?;
+            // clicks arrive in the launcher's own pixels however large the window is
+            canvas
+                .set_logical_size(WIDTH, HEIGHT)
+                .context("failed to set launcher logical size")?;
             let texture_creator = canvas.texture_creator();
             let texture = texture_creator
                 .create_texture_streaming(PixelFormatEnum::ARGB8888, WIDTH, HEIGHT)
@@ -256,6 +441,13 @@ mod gui {
                 texture,
                 event_pump,
                 last_click: None,
+                details: HashMap::new(),
+                resume_prompt: None,
+                profiles: if pick_profile {
+                    saves::profiles()
+                } else {
+                    Vec::new()
+                },
             })
         }
 
@@ -273,6 +465,22 @@ mod gui {
         fn poll_events(&mut self) -> Result<Option<PathBuf>> {
             let events: Vec<_> = self.event_pump.poll_iter().collect();
             for event in events {
+                if self.resume_prompt.is_some() {
+                    match event {
+                        Event::Quit { .. } => bail!("game selection cancelled"),
+                        Event::KeyDown {
+                            keycode: Some(key),
+                            repeat: false,
+                            ..
+                        } => {
+                            if let Some(path) = self.handle_prompt_key(key) {
+                                return Ok(Some(path));
+                            }
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
                 match event {
                     Event::Quit { .. } => bail!("game selection cancelled"),
                     Event::KeyDown {
@@ -284,14 +492,18 @@ mod gui {
                         repeat: false,
                         ..
                     } => {
-                        if let Some(path) = self.handle_key(key)? {
+                        if let Some(path) = self.handle_key(key)?.and_then(|path| self.launch(path))
+                        {
                             return Ok(Some(path));
                         }
                     }
                     Event::MouseButtonDown {
                         mouse_btn, x, y, ..
                     } => {
-                        if let Some(path) = self.handle_click(mouse_btn, x, y) {
+                        if let Some(path) = self
+                            .handle_click(mouse_btn, x, y)
+                            .and_then(|path| self.launch(path))
+                        {
                             return Ok(Some(path));
                         }
                     }
@@ -325,11 +537,54 @@ mod gui {
                         return Ok(Some(path));
                     }
                 }
+                Keycode::P => self.next_profile(),
                 _ => {}
             }
             Ok(None)
         }
 
+        /// Steps through the profiles, with the shared saves after the last one
+        fn next_profile(&mut self) {
+            if self.profiles.is_empty() {
+                return;
+            }
+            let next = match saves::profile() {
+                Some(current) => self
+                    .profiles
+                    .iter()
+                    .position(|name| *name == current)
+                    .and_then(|index| self.profiles.get(index + 1)),
+                None => self.profiles.first(),
+            };
+            saves::set_profile(next.cloned());
+        }
+
+        /// Launches the chosen game, or first asks whether to resume it when it left a
+        /// resume state
+        fn launch(&mut self, path: PathBuf) -> Option<PathBuf> {
+            if resume::available(self.systems[self.system_index].system, &path) {
+                self.resume_prompt = Some(path);
+                return None;
+            }
+            Some(path)
+        }
+
+        /// Enter or Y resumes, N starts fresh, Esc goes back to the list
+        fn handle_prompt_key(&mut self, key: Keycode) -> Option<PathBuf> {
+            match key {
+                Keycode::Return | Keycode::KpEnter | Keycode::Y => self.resume_prompt.take(),
+                Keycode::N => {
+                    resume::start_fresh();
+                    self.resume_prompt.take()
+                }
+                Keycode::Escape => {
+                    self.resume_prompt = None;
+                    None
+                }
+                _ => None,
+            }
+        }
+
         fn move_selection(&mut self, delta: isize) {
             match self.active_column {
                 Column::Systems => {
@@ -360,7 +615,11 @@ mod gui {
 
         fn page_move(&mut self, delta: isize) {
             let layout = self.layout();
-            let step = layout.visible_rows.max(1) as isize;
+            let step = match self.active_column {
+                Column::Systems => layout.visible_rows,
+                Column::Games => layout.game_rows,
+            }
+            .max(1) as isize;
             match self.active_column {
                 Column::Systems => {
                     let len = self.systems.len();
@@ -492,7 +751,7 @@ mod gui {
                     self.game_scroll,
                     self.game_index,
                     game_len,
-                    layout.visible_rows,
+                    layout.game_rows,
                 );
             } else {
                 self.game_scroll = 0;
@@ -516,18 +775,32 @@ mod gui {
             let games_width = width
                 .saturating_sub(games_x + PADDING)
                 .max(CONSOLE_PANEL_WIDTH);
+            let detail_height = if has_details(self.systems[self.system_index].system) {
+                DETAIL_HEIGHT + PANEL_GAP
+            } else {
+                0
+            };
             let games = Panel {
                 x: games_x,
                 y: PADDING,
                 width: games_width,
-                height: panel_height,
+                height: panel_height.saturating_sub(detail_height),
             };
-            let visible_rows = panel_height.saturating_sub(HEADER_GAP).max(ROW_HEIGHT) / ROW_HEIGHT;
+            let detail = (detail_height > 0).then_some(Panel {
+                x: games_x,
+                y: games.y + games.height + PANEL_GAP,
+                width: games_width,
+                height: DETAIL_HEIGHT,
+            });
+            let rows =
+                |height: usize| height.saturating_sub(HEADER_GAP).max(ROW_HEIGHT) / ROW_HEIGHT;
             let footer_top = height.saturating_sub(FOOTER_HEIGHT - 12);
             Layout {
                 consoles,
                 games,
-                visible_rows: visible_rows.max(1),
+                detail,
+                visible_rows: rows(panel_height).max(1),
+                game_rows: rows(games.height).max(1),
                 footer_top,
             }
         }
@@ -553,6 +826,9 @@ mod gui {
 
             self.draw_system_rows(&layout);
             self.draw_game_rows(&layout);
+            if let Some(panel) = layout.detail {
+                self.draw_details(&panel);
+            }
             self.draw_footer(layout.footer_top);
             self.present_frame()
         }
@@ -615,7 +891,7 @@ mod gui {
                 return;
             }
             let is_active = matches!(self.active_column, Column::Games);
-            let visible = layout.visible_rows;
+            let visible = layout.game_rows;
             for row in 0..visible {
                 let idx = self.game_scroll + row;
                 if idx >= game_len {
@@ -649,10 +925,83 @@ mod gui {
             }
         }
 
+        fn draw_details(&mut self, panel: &Panel) {
+            self.draw_panel(panel);
+            let Some(path) = self.current_game_path() else {
+                return;
+            };
+            let system = self.systems[self.system_index].system;
+            let details = self
+                .details
+                .entry(path.clone())
+                .or_insert_with(|| game_details(system, &path).map(Rc::new))
+                .clone();
+            let Some(details) = details else {
+                self.draw_text(
+                    panel.x + 14,
+                    panel.y + 14,
+                    "No details for this game.",
+                    MUTED_TEXT,
+                );
+                return;
+            };
+
+            let mut text_x = panel.x + 14;
+            if let Some((width, pixels)) = &details.image {
+                let (x, y) = (panel.x + 14, panel.y + 14);
+                self.draw_image(x, y, *width, pixels);
+                text_x = x + width * DETAIL_IMAGE_SCALE + 16;
+            }
+            let max_chars = (panel.x + panel.width).saturating_sub(text_x + 12) / 8;
+            let mut y = panel.y + 14;
+            self.draw_text(text_x, y, &truncate(&details.title, max_chars), TEXT_COLOR);
+            y += 16;
+            for line in details.lines.iter().flat_map(|line| wrap(line, max_chars)) {
+                if y + 8 > panel.y + panel.height - 8 {
+                    break;
+                }
+                self.draw_text(text_x, y, &line, MUTED_TEXT);
+                y += 12;
+            }
+        }
+
+        /// Scaled by `DETAIL_IMAGE_SCALE` and blended over the panel
+        fn draw_image(&mut self, start_x: usize, start_y: usize, width: usize, pixels: &[u32]) {
+            let frame_width = WIDTH as usize;
+            let frame_height = HEIGHT as usize;
+            for (index, &pixel) in pixels.iter().enumerate() {
+                let alpha = pixel >> 24;
+                for dy in 0..DETAIL_IMAGE_SCALE {
+                    for dx in 0..DETAIL_IMAGE_SCALE {
+                        let x = start_x + (index % width) * DETAIL_IMAGE_SCALE + dx;
+                        let y = start_y + (index / width) * DETAIL_IMAGE_SCALE + dy;
+                        if x >= frame_width || y >= frame_height {
+                            continue;
+                        }
+                        let target = &mut self.frame_buffer[y * frame_width + x];
+                        *target = blend(*target, pixel, alpha);
+                    }
+                }
+            }
+        }
+
         fn draw_footer(&mut self, y: usize) {
+            if self.resume_prompt.is_some() {
+                let prompt =
+                    "Resume where you left off? Enter resumes, N starts fresh, Esc goes back.";
+                self.draw_text(PADDING, y, prompt, PROMPT_TEXT);
+                return;
+            }
             let instructions =
                 "Arrow keys navigate, Enter launches, Esc cancels, double-click a game to launch.";
             self.draw_text(PADDING, y, instructions, FOOTER_TEXT);
+            if !self.profiles.is_empty() {
+                let profile = match saves::profile() {
+                    Some(name) => format!("Profile: {name} (P switches)"),
+                    None => "Profile: shared saves (P switches)".to_string(),
+                };
+                self.draw_text(PADDING, y + 14, &profile, TEXT_COLOR);
+            }
         }
 
         fn fill_rect(
@@ -745,6 +1094,43 @@ mod gui {
         }
     }
 
+    fn blend(background: u32, pixel: u32, alpha: u32) -> u32 {
+        let channel = |shift: u32| {
+            let bg = (background >> shift) & 0xFF;
+            let fg = (pixel >> shift) & 0xFF;
+            ((fg * alpha + bg * (255 - alpha)) / 255) << shift
+        };
+        0xFF00_0000 | channel(16) | channel(8) | channel(0)
+    }
+
+    fn truncate(text: &str, max_chars: usize) -> String {
+        if text.chars().count() <= max_chars {
+            return text.to_string();
+        }
+        let mut shortened: String = text.chars().take(max_chars.saturating_sub(3)).collect();
+        shortened.push_str("...");
+        shortened
+    }
+
+    /// Breaks `text` at spaces into lines of at most `max_chars`
+    fn wrap(text: &str, max_chars: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        for word in text.split_whitespace() {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_chars {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        lines
+    }
+
     fn clamp_scroll(mut scroll: usize, index: usize, total: usize, visible: usize) -> usize {
         if total <= visible {
             return 0;
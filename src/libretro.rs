@@ -0,0 +1,1101 @@
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_int, c_uint, c_void};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use libloading::Library;
+use log::{debug, info, warn};
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Scancode};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::{GLContext, GLProfile, Window};
+
+use crate::audio::AudioPlayer;
+use crate::config::Config;
+use crate::controller::{ControllerManager, VirtualButton};
+use crate::focus_pause::{self, FocusPause};
+use crate::frame_pacer::FramePacer;
+use crate::perf_overlay::PerfOverlay;
+use crate::present;
+
+const RETRO_API_VERSION: c_uint = 1;
+const PORTS: usize = 4;
+const AUDIO_CHANNELS: u8 = 2;
+/// Queued audio beyond this is dropped unless `[audio] max_latency_ms` says otherwise, it only
+/// builds up when frames run slow
+const MAX_AUDIO_LATENCY: Duration = Duration::from_millis(200);
+
+const RETRO_ENVIRONMENT_EXPERIMENTAL: c_uint = 0x10000;
+const RETRO_ENVIRONMENT_GET_CAN_DUPE: c_uint = 3;
+const RETRO_ENVIRONMENT_SET_PERFORMANCE_LEVEL: c_uint = 8;
+const RETRO_ENVIRONMENT_GET_SYSTEM_DIRECTORY: c_uint = 9;
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS: c_uint = 11;
+const RETRO_ENVIRONMENT_SET_HW_RENDER: c_uint = 14;
+const RETRO_ENVIRONMENT_GET_VARIABLE: c_uint = 15;
+const RETRO_ENVIRONMENT_SET_VARIABLES: c_uint = 16;
+const RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE: c_uint = 17;
+const RETRO_ENVIRONMENT_GET_SAVE_DIRECTORY: c_uint = 31;
+const RETRO_ENVIRONMENT_SET_SYSTEM_AV_INFO: c_uint = 32;
+const RETRO_ENVIRONMENT_SET_CONTROLLER_INFO: c_uint = 35;
+const RETRO_ENVIRONMENT_SET_GEOMETRY: c_uint = 37;
+const RETRO_ENVIRONMENT_GET_CORE_OPTIONS_VERSION: c_uint = 52;
+const RETRO_ENVIRONMENT_GET_PREFERRED_HW_RENDER: c_uint = 56;
+
+const RETRO_PIXEL_FORMAT_0RGB1555: c_int = 0;
+const RETRO_PIXEL_FORMAT_XRGB8888: c_int = 1;
+const RETRO_PIXEL_FORMAT_RGB565: c_int = 2;
+
+const RETRO_HW_CONTEXT_OPENGL: c_int = 1;
+const RETRO_HW_CONTEXT_OPENGL_CORE: c_int = 3;
+/// `video_refresh` data meaning the frame was rendered into the hardware framebuffer
+const RETRO_HW_FRAME_BUFFER_VALID: *const c_void = usize::MAX as *const c_void;
+
+const RETRO_DEVICE_JOYPAD: c_uint = 1;
+const RETRO_DEVICE_ANALOG: c_uint = 5;
+const RETRO_DEVICE_INDEX_ANALOG_BUTTON: c_uint = 2;
+
+const GL_FRAMEBUFFER: u32 = 0x8D40;
+const GL_READ_FRAMEBUFFER: u32 = 0x8CA8;
+const GL_DRAW_FRAMEBUFFER: u32 = 0x8CA9;
+const GL_RENDERBUFFER: u32 = 0x8D41;
+const GL_RGBA8: u32 = 0x8058;
+const GL_DEPTH24_STENCIL8: u32 = 0x88F0;
+const GL_COLOR_ATTACHMENT0: u32 = 0x8CE0;
+const GL_DEPTH_STENCIL_ATTACHMENT: u32 = 0x821A;
+const GL_COLOR_BUFFER_BIT: u32 = 0x4000;
+const GL_SCISSOR_TEST: u32 = 0x0C11;
+const GL_LINEAR: u32 = 0x2601;
+
+/// RetroPad button ids, the bit each one takes in [`PadState::buttons`]
+mod joypad {
+    pub const B: u32 = 0;
+    pub const Y: u32 = 1;
+    pub const SELECT: u32 = 2;
+    pub const START: u32 = 3;
+    pub const UP: u32 = 4;
+    pub const DOWN: u32 = 5;
+    pub const LEFT: u32 = 6;
+    pub const RIGHT: u32 = 7;
+    pub const A: u32 = 8;
+    pub const X: u32 = 9;
+    pub const L: u32 = 10;
+    pub const R: u32 = 11;
+    pub const L2: u32 = 12;
+    pub const R2: u32 = 13;
+    pub const L3: u32 = 14;
+    pub const R3: u32 = 15;
+}
+
+/// Pad buttons by position, SDL's bottom face button is the RetroPad's B
+const PAD_BUTTONS: &[(VirtualButton, u32)] = &[
+    (VirtualButton::A, joypad::B),
+    (VirtualButton::B, joypad::A),
+    (VirtualButton::X, joypad::Y),
+    (VirtualButton::Y, joypad::X),
+    (VirtualButton::Select, joypad::SELECT),
+    (VirtualButton::Start, joypad::START),
+    (VirtualButton::Up, joypad::UP),
+    (VirtualButton::Down, joypad::DOWN),
+    (VirtualButton::Left, joypad::LEFT),
+    (VirtualButton::Right, joypad::RIGHT),
+    (VirtualButton::L, joypad::L),
+    (VirtualButton::R, joypad::R),
+    (VirtualButton::LeftStick, joypad::L3),
+    (VirtualButton::RightStick, joypad::R3),
+];
+
+/// Keyboard layout of port 1, matching the built-in GameCube core
+const KEY_BUTTONS: &[(Scancode, u32)] = &[
+    (Scancode::X, joypad::B),
+    (Scancode::Z, joypad::Y),
+    (Scancode::S, joypad::A),
+    (Scancode::A, joypad::X),
+    (Scancode::Q, joypad::L2),
+    (Scancode::W, joypad::R2),
+    (Scancode::U, joypad::L2),
+    (Scancode::O, joypad::R2),
+    (Scancode::E, joypad::R),
+    (Scancode::LShift, joypad::R),
+    (Scancode::RShift, joypad::R),
+    (Scancode::Return, joypad::START),
+    (Scancode::Backspace, joypad::SELECT),
+    (Scancode::Up, joypad::UP),
+    (Scancode::Down, joypad::DOWN),
+    (Scancode::Left, joypad::LEFT),
+    (Scancode::Right, joypad::RIGHT),
+];
+
+#[repr(C)]
+struct RetroSystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct RetroGameGeometry {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct RetroSystemTiming {
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct RetroSystemAvInfo {
+    geometry: RetroGameGeometry,
+    timing: RetroSystemTiming,
+}
+
+#[repr(C)]
+struct RetroGameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[repr(C)]
+struct RetroVariable {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RetroHwRenderCallback {
+    context_type: c_int,
+    context_reset: Option<unsafe extern "C" fn()>,
+    get_current_framebuffer: Option<unsafe extern "C" fn() -> usize>,
+    get_proc_address: Option<unsafe extern "C" fn(*const c_char) -> *const c_void>,
+    depth: bool,
+    stencil: bool,
+    bottom_left_origin: bool,
+    version_major: c_uint,
+    version_minor: c_uint,
+    cache_context: bool,
+    context_destroy: Option<unsafe extern "C" fn()>,
+    debug_context: bool,
+}
+
+type EnvironmentFn = unsafe extern "C" fn(c_uint, *mut c_void) -> bool;
+type VideoRefreshFn = unsafe extern "C" fn(*const c_void, c_uint, c_uint, usize);
+type AudioSampleFn = unsafe extern "C" fn(i16, i16);
+type AudioSampleBatchFn = unsafe extern "C" fn(*const i16, usize) -> usize;
+type InputPollFn = unsafe extern "C" fn();
+type InputStateFn = unsafe extern "C" fn(c_uint, c_uint, c_uint, c_uint) -> i16;
+
+/// Entry points of a libretro core, see `libretro.h`
+struct CoreFunctions {
+    set_environment: unsafe extern "C" fn(EnvironmentFn),
+    set_video_refresh: unsafe extern "C" fn(VideoRefreshFn),
+    set_audio_sample: unsafe extern "C" fn(AudioSampleFn),
+    set_audio_sample_batch: unsafe extern "C" fn(AudioSampleBatchFn),
+    set_input_poll: unsafe extern "C" fn(InputPollFn),
+    set_input_state: unsafe extern "C" fn(InputStateFn),
+    init: unsafe extern "C" fn(),
+    deinit: unsafe extern "C" fn(),
+    api_version: unsafe extern "C" fn() -> c_uint,
+    get_system_info: unsafe extern "C" fn(*mut RetroSystemInfo),
+    get_system_av_info: unsafe extern "C" fn(*mut RetroSystemAvInfo),
+    load_game: unsafe extern "C" fn(*const RetroGameInfo) -> bool,
+    unload_game: unsafe extern "C" fn(),
+    run: unsafe extern "C" fn(),
+}
+
+/// How the core's frames reach the window
+pub struct Session<'a> {
+    pub title: String,
+    pub scale: u32,
+    pub limit_fps: bool,
+    /// BIOS files and data the core ships separately, `system` in libretro terms
+    pub system_dir: PathBuf,
+    pub save_dir: PathBuf,
+    /// The frontend settings the window, audio and pads are opened with
+    pub config: &'a Config,
+}
+
+thread_local! {
+    static FRONTEND: RefCell<Option<Frontend>> = const { RefCell::new(None) };
+}
+
+/// Everything the core's callbacks touch. libretro callbacks carry no context and
+/// are called on the thread running `retro_run`, so the state is thread local and
+/// never borrowed while the core runs.
+struct Frontend {
+    system_dir: CString,
+    save_dir: CString,
+    pixel_format: c_int,
+    hw_render: Option<RetroHwRenderCallback>,
+    /// Core options with the first value the core offers, which is its default
+    variables: Vec<(String, CString)>,
+    av_info: RetroSystemAvInfo,
+    pads: [PadState; PORTS],
+    audio_samples: Vec<i16>,
+    output: Option<Output>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct PadState {
+    buttons: u16,
+    /// Left x/y, right x/y
+    axes: [i16; 4],
+    triggers: [i16; 2],
+}
+
+struct Output {
+    _sdl: sdl2::Sdl,
+    _video: sdl2::VideoSubsystem,
+    event_pump: sdl2::EventPump,
+    controllers: ControllerManager,
+    audio: AudioPlayer,
+    screen: Screen,
+    title: String,
+    /// What the window title shows now, the game's title or it with the volume after a hotkey
+    shown_title: String,
+    perf: PerfOverlay,
+    focus: FocusPause,
+}
+
+enum Screen {
+    /// Frames handed over in memory, drawn with the SDL renderer
+    Software {
+        canvas: Canvas<Window>,
+        texture: Option<(Texture, PixelFormatEnum, u32, u32)>,
+    },
+    /// Frames rendered by the core into our framebuffer object, blitted to the window
+    Hardware {
+        gl: Gl,
+        framebuffer: u32,
+        /// Dropped before its window
+        _context: GLContext,
+        window: Window,
+        frame_size: (u32, u32),
+    },
+}
+
+/// Runs `game_path` on the libretro core at `core_path` until the window closes
+pub fn run(core_path: &Path, game_path: &Path, session: Session) -> Result<()> {
+    let library = unsafe { Library::new(core_path) }
+        .with_context(|| format!("failed to load libretro core {}", core_path.display()))?;
+    let core = CoreFunctions::load(&library)?;
+    let version = unsafe { (core.api_version)() };
+    ensure!(
+        version == RETRO_API_VERSION,
+        "{} implements libretro API {version}, expected {RETRO_API_VERSION}",
+        core_path.display()
+    );
+
+    let mut system_info = RetroSystemInfo {
+        library_name: ptr::null(),
+        library_version: ptr::null(),
+        valid_extensions: ptr::null(),
+        need_fullpath: false,
+        block_extract: false,
+    };
+    unsafe { (core.get_system_info)(&mut system_info) };
+    info!(
+        "Loaded libretro core {} {}",
+        c_text(system_info.library_name),
+        c_text(system_info.library_version)
+    );
+
+    for dir in [&session.system_dir, &session.save_dir] {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    FRONTEND.set(Some(Frontend {
+        system_dir: path_cstring(&session.system_dir)?,
+        save_dir: path_cstring(&session.save_dir)?,
+        pixel_format: RETRO_PIXEL_FORMAT_0RGB1555,
+        hw_render: None,
+        variables: Vec::new(),
+        av_info: RetroSystemAvInfo::default(),
+        pads: [PadState::default(); PORTS],
+        audio_samples: Vec::new(),
+        output: None,
+    }));
+
+    unsafe {
+        (core.set_environment)(environment);
+        (core.set_video_refresh)(video_refresh);
+        (core.set_audio_sample)(audio_sample);
+        (core.set_audio_sample_batch)(audio_sample_batch);
+        (core.set_input_poll)(input_poll);
+        (core.set_input_state)(input_state);
+        (core.init)();
+    }
+
+    let result = load_and_run(&core, &system_info, game_path, &session);
+    unsafe { (core.deinit)() };
+    // the window goes before the library its GL context belongs to
+    FRONTEND.set(None);
+    result
+}
+
+fn load_and_run(
+    core: &CoreFunctions,
+    system_info: &RetroSystemInfo,
+    game_path: &Path,
+    session: &Session,
+) -> Result<()> {
+    let path = path_cstring(game_path)?;
+    // cores that don't need the path get the whole file
+    let data = if system_info.need_fullpath {
+        Vec::new()
+    } else {
+        fs::read(game_path).with_context(|| format!("failed to read {}", game_path.display()))?
+    };
+    let game = RetroGameInfo {
+        path: path.as_ptr(),
+        data: if data.is_empty() {
+            ptr::null()
+        } else {
+            data.as_ptr().cast()
+        },
+        size: data.len(),
+        meta: ptr::null(),
+    };
+    if !unsafe { (core.load_game)(&game) } {
+        bail!("the libretro core could not load {}", game_path.display());
+    }
+
+    let mut av_info = RetroSystemAvInfo::default();
+    unsafe { (core.get_system_av_info)(&mut av_info) };
+    info!(
+        "Core runs at {}x{} {:.2} fps, {} Hz audio",
+        av_info.geometry.base_width,
+        av_info.geometry.base_height,
+        av_info.timing.fps,
+        av_info.timing.sample_rate
+    );
+    let hw_render = FRONTEND.with_borrow_mut(|frontend| {
+        let frontend = frontend.as_mut().expect("frontend is installed");
+        frontend.av_info = av_info;
+        frontend.hw_render
+    });
+    let output = Output::new(session, &av_info, hw_render.as_ref())?;
+    with_frontend(|frontend| frontend.output = Some(output));
+    if let Some(context_reset) = hw_render.and_then(|hw| hw.context_reset) {
+        unsafe { context_reset() };
+    }
+
+    let frame_time = Duration::from_secs_f64(1.0 / av_info.timing.fps.clamp(1.0, 240.0));
+    let mut pacer = FramePacer::new(frame_time, session.limit_fps);
+    loop {
+        if !with_frontend(Frontend::handle_events) {
+            break;
+        }
+        if with_frontend(|frontend| frontend.is_paused()) {
+            focus_pause::idle();
+            continue;
+        }
+        with_frontend(Frontend::advance_frame);
+        unsafe { (core.run)() };
+        with_frontend(Frontend::queue_audio);
+
+        pacer.wait();
+    }
+
+    if let Some(context_destroy) = hw_render.and_then(|hw| hw.context_destroy) {
+        unsafe { context_destroy() };
+    }
+    unsafe { (core.unload_game)() };
+    Ok(())
+}
+
+impl CoreFunctions {
+    fn load(library: &Library) -> Result<Self> {
+        macro_rules! symbol {
+            ($name:literal) => {
+                *unsafe { library.get($name) }.with_context(|| {
+                    format!(
+                        "libretro core is missing {}",
+                        String::from_utf8_lossy(&$name[..$name.len() - 1])
+                    )
+                })?
+            };
+        }
+        Ok(Self {
+            set_environment: symbol!(b"retro_set_environment\0"),
+            set_video_refresh: symbol!(b"retro_set_video_refresh\0"),
+            set_audio_sample: symbol!(b"retro_set_audio_sample\0"),
+            set_audio_sample_batch: symbol!(b"retro_set_audio_sample_batch\0"),
+            set_input_poll: symbol!(b"retro_set_input_poll\0"),
+            set_input_state: symbol!(b"retro_set_input_state\0"),
+            init: symbol!(b"retro_init\0"),
+            deinit: symbol!(b"retro_deinit\0"),
+            api_version: symbol!(b"retro_api_version\0"),
+            get_system_info: symbol!(b"retro_get_system_info\0"),
+            get_system_av_info: symbol!(b"retro_get_system_av_info\0"),
+            load_game: symbol!(b"retro_load_game\0"),
+            unload_game: symbol!(b"retro_unload_game\0"),
+            run: symbol!(b"retro_run\0"),
+        })
+    }
+}
+
+impl Output {
+    fn new(
+        session: &Session,
+        av_info: &RetroSystemAvInfo,
+        hw_render: Option<&RetroHwRenderCallback>,
+    ) -> Result<Self> {
+        let sdl = sdl2::init().map_err(|err| anyhow!(err))?;
+        let video = sdl.video().map_err(|err| anyhow!(err))?;
+        let geometry = av_info.geometry;
+        let scale = session.scale.max(1);
+        let width = geometry.base_width.max(1) * scale;
+        let height = match geometry.aspect_ratio {
+            aspect if aspect > 0.0 => (width as f32 / aspect).round() as u32,
+            _ => geometry.base_height.max(1) * scale,
+        };
+        if hw_render.is_some() {
+            present::unsupported_rotation("OpenGL rendering cores");
+        }
+        let (width, height) = present::oriented(width, height);
+
+        let screen = match hw_render {
+            Some(hw) => {
+                let attributes = video.gl_attr();
+                if hw.context_type == RETRO_HW_CONTEXT_OPENGL_CORE {
+                    attributes.set_context_profile(GLProfile::Core);
+                }
+                if hw.version_major > 0 {
+                    attributes.set_context_version(hw.version_major as u8, hw.version_minor as u8);
+                }
+                attributes.set_double_buffer(true);
+                let mut window = video
+                    .window(&session.title, width, height)
+                    .position_centered()
+                    .resizable()
+                    .opengl()
+                    .allow_highdpi()
+                    .build()
+                    .context("failed to create the game window")?;
+                present::fit_to_display(&mut window, &session.config.display);
+                present::apply_fullscreen(&mut window);
+                let context = window.gl_create_context().map_err(|err| anyhow!(err))?;
+                window
+                    .gl_make_current(&context)
+                    .map_err(|err| anyhow!(err))?;
+                present::adaptive_gl_sync(&video, &session.config.display);
+                let gl = Gl::load(&video)?;
+                let frame_size = (
+                    geometry.max_width.max(geometry.base_width).max(1),
+                    geometry.max_height.max(geometry.base_height).max(1),
+                );
+                let framebuffer = gl.create_framebuffer(frame_size, hw.depth || hw.stencil);
+                Screen::Hardware {
+                    gl,
+                    framebuffer,
+                    _context: context,
+                    window,
+                    frame_size,
+                }
+            }
+            None => {
+                let mut window = video
+                    .window(&session.title, width, height)
+                    .position_centered()
+                    .resizable()
+                    .allow_highdpi()
+                    .build()
+                    .context("failed to create the game window")?;
+                present::fit_to_display(&mut window, &session.config.display);
+                present::apply_fullscreen(&mut window);
+                let canvas = window.into_canvas().build().map_err(|err| anyhow!(err))?;
+                Screen::Software {
+                    canvas,
+                    texture: None,
+                }
+            }
+        };
+
+        let audio = AudioPlayer::new(
+            &session.config.audio,
+            av_info.timing.sample_rate,
+            AUDIO_CHANNELS.into(),
+        )?
+        .with_max_latency(MAX_AUDIO_LATENCY);
+
+        let event_pump = sdl.event_pump().map_err(|err| anyhow!(err))?;
+        let controllers = ControllerManager::new(&sdl, &session.config.controllers)?;
+        Ok(Self {
+            _sdl: sdl,
+            _video: video,
+            event_pump,
+            controllers,
+            audio,
+            screen,
+            title: session.title.clone(),
+            shown_title: session.title.clone(),
+            perf: PerfOverlay::new(
+                Duration::from_secs_f64(1.0 / av_info.timing.fps.clamp(1.0, 240.0)),
+                &session.config.display,
+            ),
+            focus: FocusPause::new(&session.config.display),
+        })
+    }
+}
+
+impl Frontend {
+    fn advance_frame(&mut self) {
+        if let Some(output) = &mut self.output {
+            output.controllers.advance_frame();
+        }
+    }
+
+    /// Returns `false` once the window closes or Esc is pressed
+    fn handle_events(&mut self) -> bool {
+        let Some(output) = &mut self.output else {
+            return false;
+        };
+        let mut running = true;
+        for event in output.event_pump.poll_iter() {
+            output.controllers.handle_event(&event);
+            output.audio.handle_sdl_event(&event);
+            output.perf.handle_sdl_event(&event);
+            output.focus.handle_sdl_event(&event);
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => running = false,
+                _ => {}
+            }
+        }
+        if !output.focus.is_paused() {
+            output.perf.frame();
+        }
+        output.perf.set_audio(&output.audio);
+        // frames may come from GL, the volume and the performance overlay are shown in the
+        // title instead of over the game
+        let mut title = output.title.clone();
+        if output.perf.is_open() {
+            title = format!("{title} | {}", output.perf.summary());
+        }
+        if let Some(text) = output.audio.osd_text() {
+            title = format!("{title} | {text}");
+        }
+        if title != output.shown_title {
+            let window = match &mut output.screen {
+                Screen::Hardware { window, .. } => window,
+                Screen::Software { canvas, .. } => canvas.window_mut(),
+            };
+            if let Err(err) = window.set_title(&title) {
+                debug!("failed to set the window title: {err}");
+            }
+            output.shown_title = title;
+        }
+        running
+    }
+
+    /// Whether the game waits for its window to come back to the foreground
+    fn is_paused(&self) -> bool {
+        self.output
+            .as_ref()
+            .is_some_and(|output| output.focus.is_paused())
+    }
+
+    fn queue_audio(&mut self) {
+        let Some(output) = &mut self.output else {
+            return;
+        };
+        output.audio.push_i16(&self.audio_samples);
+        self.audio_samples.clear();
+    }
+
+    fn poll_input(&mut self) {
+        let Some(output) = &self.output else {
+            return;
+        };
+        let keyboard = output.event_pump.keyboard_state();
+        for (port, pad) in self.pads.iter_mut().enumerate() {
+            let mut state = PadState::default();
+            for &(button, id) in PAD_BUTTONS {
+                if output.controllers.is_pad_button_pressed(port, button) {
+                    state.buttons |= 1 << id;
+                }
+            }
+            if let Some(analog) = output.controllers.pad_analog_state(port) {
+                state.axes =
+                    [analog.left_x, analog.left_y, analog.right_x, analog.right_y].map(axis_value);
+                state.triggers = [analog.left_trigger, analog.right_trigger].map(axis_value);
+            }
+
+            if port == 0 {
+                for &(key, id) in KEY_BUTTONS {
+                    if keyboard.is_scancode_pressed(present::screen_arrow_scancode(key)) {
+                        state.buttons |= 1 << id;
+                    }
+                }
+                let key_axis = |negative, positive, value: &mut i16| match (
+                    keyboard.is_scancode_pressed(present::screen_arrow_scancode(negative)),
+                    keyboard.is_scancode_pressed(present::screen_arrow_scancode(positive)),
+                ) {
+                    (true, false) => *value = -i16::MAX,
+                    (false, true) => *value = i16::MAX,
+                    _ => {}
+                };
+                // the arrows also drive the stick, which most 3D games read instead of the d-pad
+                let [left_x, left_y, right_x, right_y] = &mut state.axes;
+                key_axis(Scancode::Left, Scancode::Right, left_x);
+                key_axis(Scancode::Up, Scancode::Down, left_y);
+                key_axis(Scancode::J, Scancode::L, right_x);
+                key_axis(Scancode::I, Scancode::K, right_y);
+            }
+
+            // analog triggers double as digital L2/R2 and the other way round
+            for (index, id) in [(0, joypad::L2), (1, joypad::R2)] {
+                if state.triggers[index] > i16::MAX / 2 {
+                    state.buttons |= 1 << id;
+                } else if state.buttons & (1 << id) != 0 {
+                    state.triggers[index] = i16::MAX;
+                }
+            }
+            *pad = state;
+        }
+    }
+
+    fn input_state(&self, port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16 {
+        let Some(pad) = self.pads.get(port as usize) else {
+            return 0;
+        };
+        match (device, index) {
+            (RETRO_DEVICE_JOYPAD, _) if id < 16 => ((pad.buttons >> id) & 1) as i16,
+            (RETRO_DEVICE_ANALOG, 0 | 1) if id < 2 => pad.axes[(index * 2 + id) as usize],
+            (RETRO_DEVICE_ANALOG, RETRO_DEVICE_INDEX_ANALOG_BUTTON) => match id {
+                joypad::L2 => pad.triggers[0],
+                joypad::R2 => pad.triggers[1],
+                id if id < 16 => ((pad.buttons >> id) & 1) as i16 * i16::MAX,
+                _ => 0,
+            },
+            _ => 0,
+        }
+    }
+
+    fn present(&mut self, data: *const c_void, width: u32, height: u32, pitch: usize) {
+        let aspect = match self.av_info.geometry.aspect_ratio {
+            aspect if aspect > 0.0 => aspect,
+            _ => width.max(1) as f32 / height.max(1) as f32,
+        };
+        let bottom_left_origin = self.hw_render.is_some_and(|hw| hw.bottom_left_origin);
+        let pixel_format = self.pixel_format;
+        let Some(output) = &mut self.output else {
+            return;
+        };
+        match &mut output.screen {
+            Screen::Hardware {
+                gl,
+                framebuffer,
+                window,
+                frame_size,
+                ..
+            } => {
+                // a null frame repeats the last one, which is still on screen
+                if data != RETRO_HW_FRAME_BUFFER_VALID {
+                    return;
+                }
+                let (window_width, window_height) = window.drawable_size();
+                let target = fit(aspect, window_width, window_height);
+                let width = width.min(frame_size.0) as i32;
+                let height = height.min(frame_size.1) as i32;
+                let (src_top, src_bottom) = if bottom_left_origin {
+                    (height, 0)
+                } else {
+                    (0, height)
+                };
+                let bottom = (window_height - target.bottom() as u32) as i32;
+                unsafe {
+                    (gl.bind_framebuffer)(GL_DRAW_FRAMEBUFFER, 0);
+                    (gl.disable)(GL_SCISSOR_TEST);
+                    (gl.clear_color)(0.0, 0.0, 0.0, 1.0);
+                    (gl.clear)(GL_COLOR_BUFFER_BIT);
+                    (gl.bind_framebuffer)(GL_READ_FRAMEBUFFER, *framebuffer);
+                    // GL counts rows from the bottom, the source rows are flipped to match
+                    (gl.blit_framebuffer)(
+                        0,
+                        src_bottom,
+                        width,
+                        src_top,
+                        target.x(),
+                        bottom,
+                        target.right(),
+                        bottom + target.height() as i32,
+                        GL_COLOR_BUFFER_BIT,
+                        GL_LINEAR,
+                    );
+                }
+                window.gl_swap_window();
+            }
+            Screen::Software { canvas, texture } => {
+                if !data.is_null() {
+                    let format = match pixel_format {
+                        RETRO_PIXEL_FORMAT_XRGB8888 => PixelFormatEnum::RGB888,
+                        RETRO_PIXEL_FORMAT_RGB565 => PixelFormatEnum::RGB565,
+                        _ => PixelFormatEnum::RGB555,
+                    };
+                    if !matches!(texture, Some((_, f, w, h)) if *f == format && *w == width && *h == height)
+                    {
+                        *texture = canvas
+                            .texture_creator()
+                            .create_texture_streaming(format, width, height)
+                            .map_err(|err| warn!("failed to create the frame texture: {err}"))
+                            .ok()
+                            .map(|created| (created, format, width, height));
+                    }
+                    if let Some((texture, ..)) = texture {
+                        let frame = unsafe {
+                            std::slice::from_raw_parts(data.cast::<u8>(), pitch * height as usize)
+                        };
+                        if let Err(err) = texture.update(None, frame, pitch) {
+                            debug!("failed to upload frame: {err}");
+                        }
+                    }
+                }
+                let (window_width, window_height) = canvas.output_size().unwrap_or((width, height));
+                let aspect = if present::quarter_turn() {
+                    1.0 / aspect
+                } else {
+                    aspect
+                };
+                canvas.clear();
+                if let Some((texture, ..)) = texture {
+                    let _ = present::copy(
+                        canvas,
+                        texture,
+                        Some(fit(aspect, window_width, window_height)),
+                    );
+                }
+                canvas.present();
+            }
+        }
+    }
+
+    fn framebuffer(&self) -> usize {
+        match self.output.as_ref().map(|output| &output.screen) {
+            Some(Screen::Hardware { framebuffer, .. }) => *framebuffer as usize,
+            _ => 0,
+        }
+    }
+
+    fn environment(&mut self, command: c_uint, data: *mut c_void) -> bool {
+        match command & !RETRO_ENVIRONMENT_EXPERIMENTAL {
+            RETRO_ENVIRONMENT_GET_CAN_DUPE => {
+                unsafe { *data.cast::<bool>() = true };
+                true
+            }
+            RETRO_ENVIRONMENT_SET_PERFORMANCE_LEVEL
+            | RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS
+            | RETRO_ENVIRONMENT_SET_CONTROLLER_INFO => true,
+            RETRO_ENVIRONMENT_GET_SYSTEM_DIRECTORY => {
+                unsafe { *data.cast::<*const c_char>() = self.system_dir.as_ptr() };
+                true
+            }
+            RETRO_ENVIRONMENT_GET_SAVE_DIRECTORY => {
+                unsafe { *data.cast::<*const c_char>() = self.save_dir.as_ptr() };
+                true
+            }
+            RETRO_ENVIRONMENT_SET_PIXEL_FORMAT => {
+                let format = unsafe { *data.cast::<c_int>() };
+                if !(RETRO_PIXEL_FORMAT_0RGB1555..=RETRO_PIXEL_FORMAT_RGB565).contains(&format) {
+                    return false;
+                }
+                self.pixel_format = format;
+                true
+            }
+            RETRO_ENVIRONMENT_SET_HW_RENDER => {
+                let hw = unsafe { &mut *data.cast::<RetroHwRenderCallback>() };
+                if !matches!(
+                    hw.context_type,
+                    RETRO_HW_CONTEXT_OPENGL | RETRO_HW_CONTEXT_OPENGL_CORE
+                ) {
+                    debug!(
+                        "Declining libretro hardware context type {}",
+                        hw.context_type
+                    );
+                    return false;
+                }
+                hw.get_current_framebuffer = Some(get_current_framebuffer);
+                hw.get_proc_address = Some(get_proc_address);
+                self.hw_render = Some(*hw);
+                true
+            }
+            RETRO_ENVIRONMENT_GET_PREFERRED_HW_RENDER => {
+                unsafe { *data.cast::<c_uint>() = RETRO_HW_CONTEXT_OPENGL_CORE as c_uint };
+                true
+            }
+            RETRO_ENVIRONMENT_SET_VARIABLES => {
+                self.variables.clear();
+                let mut variable = data.cast::<RetroVariable>().cast_const();
+                while let Some(entry) = unsafe { variable.as_ref() }
+                    && !entry.key.is_null()
+                {
+                    // "Description; first|second|third"
+                    let value = c_text(entry.value);
+                    let default = value
+                        .split_once("; ")
+                        .and_then(|(_, values)| values.split('|').next())
+                        .unwrap_or_default();
+                    if let Ok(default) = CString::new(default) {
+                        self.variables.push((c_text(entry.key), default));
+                    }
+                    variable = unsafe { variable.add(1) };
+                }
+                true
+            }
+            RETRO_ENVIRONMENT_GET_VARIABLE => {
+                let variable = unsafe { &mut *data.cast::<RetroVariable>() };
+                let key = c_text(variable.key);
+                match self.variables.iter().find(|(name, _)| *name == key) {
+                    Some((_, value)) => {
+                        variable.value = value.as_ptr();
+                        true
+                    }
+                    None => false,
+                }
+            }
+            RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE => {
+                unsafe { *data.cast::<bool>() = false };
+                true
+            }
+            RETRO_ENVIRONMENT_GET_CORE_OPTIONS_VERSION => {
+                // version 0 has the core fall back to `SET_VARIABLES`
+                unsafe { *data.cast::<c_uint>() = 0 };
+                true
+            }
+            RETRO_ENVIRONMENT_SET_SYSTEM_AV_INFO => {
+                self.av_info = unsafe { *data.cast::<RetroSystemAvInfo>() };
+                if let Some(output) = &mut self.output
+                    && let Err(err) = output
+                        .audio
+                        .set_source(self.av_info.timing.sample_rate, AUDIO_CHANNELS.into())
+                {
+                    warn!("Core switched to unusable audio: {err:#}");
+                }
+                true
+            }
+            RETRO_ENVIRONMENT_SET_GEOMETRY => {
+                self.av_info.geometry = unsafe { *data.cast::<RetroGameGeometry>() };
+                true
+            }
+            other => {
+                debug!("Unsupported libretro environment call {other}");
+                false
+            }
+        }
+    }
+}
+
+type GlGen = unsafe extern "C" fn(i32, *mut u32);
+type GlBind = unsafe extern "C" fn(u32, u32);
+type GlAttach = unsafe extern "C" fn(u32, u32, u32, u32);
+type GlStorage = unsafe extern "C" fn(u32, u32, i32, i32);
+type GlBlit = unsafe extern "C" fn(i32, i32, i32, i32, i32, i32, i32, i32, u32, u32);
+type GlClearColor = unsafe extern "C" fn(f32, f32, f32, f32);
+type GlFlags = unsafe extern "C" fn(u32);
+
+/// GL functions the hardware path needs, loaded once the context is current
+struct Gl {
+    gen_framebuffers: GlGen,
+    bind_framebuffer: GlBind,
+    framebuffer_renderbuffer: GlAttach,
+    gen_renderbuffers: GlGen,
+    bind_renderbuffer: GlBind,
+    renderbuffer_storage: GlStorage,
+    blit_framebuffer: GlBlit,
+    clear_color: GlClearColor,
+    clear: GlFlags,
+    disable: GlFlags,
+}
+
+impl Gl {
+    fn load(video: &sdl2::VideoSubsystem) -> Result<Self> {
+        macro_rules! function {
+            ($name:literal as $ty:ty) => {{
+                let address = video.gl_get_proc_address($name);
+                ensure!(!address.is_null(), "the GL driver has no {}", $name);
+                unsafe { std::mem::transmute::<*const (), $ty>(address) }
+            }};
+        }
+        Ok(Self {
+            gen_framebuffers: function!("glGenFramebuffers" as GlGen),
+            bind_framebuffer: function!("glBindFramebuffer" as GlBind),
+            framebuffer_renderbuffer: function!("glFramebufferRenderbuffer" as GlAttach),
+            gen_renderbuffers: function!("glGenRenderbuffers" as GlGen),
+            bind_renderbuffer: function!("glBindRenderbuffer" as GlBind),
+            renderbuffer_storage: function!("glRenderbufferStorage" as GlStorage),
+            blit_framebuffer: function!("glBlitFramebuffer" as GlBlit),
+            clear_color: function!("glClearColor" as GlClearColor),
+            clear: function!("glClear" as GlFlags),
+            disable: function!("glDisable" as GlFlags),
+        })
+    }
+
+    /// The framebuffer object the core renders into, sized for its largest frame
+    fn create_framebuffer(&self, (width, height): (u32, u32), depth_stencil: bool) -> u32 {
+        let (width, height) = (width as i32, height as i32);
+        let mut framebuffer = 0;
+        let mut renderbuffers = [0; 2];
+        unsafe {
+            (self.gen_framebuffers)(1, &mut framebuffer);
+            (self.bind_framebuffer)(GL_FRAMEBUFFER, framebuffer);
+            (self.gen_renderbuffers)(2, renderbuffers.as_mut_ptr());
+            (self.bind_renderbuffer)(GL_RENDERBUFFER, renderbuffers[0]);
+            (self.renderbuffer_storage)(GL_RENDERBUFFER, GL_RGBA8, width, height);
+            (self.framebuffer_renderbuffer)(
+                GL_FRAMEBUFFER,
+                GL_COLOR_ATTACHMENT0,
+                GL_RENDERBUFFER,
+                renderbuffers[0],
+            );
+            if depth_stencil {
+                (self.bind_renderbuffer)(GL_RENDERBUFFER, renderbuffers[1]);
+                (self.renderbuffer_storage)(GL_RENDERBUFFER, GL_DEPTH24_STENCIL8, width, height);
+                (self.framebuffer_renderbuffer)(
+                    GL_FRAMEBUFFER,
+                    GL_DEPTH_STENCIL_ATTACHMENT,
+                    GL_RENDERBUFFER,
+                    renderbuffers[1],
+                );
+            }
+            (self.bind_framebuffer)(GL_FRAMEBUFFER, 0);
+        }
+        framebuffer
+    }
+}
+
+/// The largest rectangle of `aspect` centered in the window
+fn fit(aspect: f32, window_width: u32, window_height: u32) -> Rect {
+    let window_width = window_width.max(1);
+    let window_height = window_height.max(1);
+    let (width, height) = if window_width as f32 / window_height as f32 > aspect {
+        (
+            (window_height as f32 * aspect).round() as u32,
+            window_height,
+        )
+    } else {
+        (window_width, (window_width as f32 / aspect).round() as u32)
+    };
+    let width = width.clamp(1, window_width);
+    let height = height.clamp(1, window_height);
+    Rect::new(
+        ((window_width - width) / 2) as i32,
+        ((window_height - height) / 2) as i32,
+        width,
+        height,
+    )
+}
+
+fn axis_value(value: f32) -> i16 {
+    (value.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+fn c_text(text: *const c_char) -> String {
+    if text.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(text) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn path_cstring(path: &Path) -> Result<CString> {
+    let path = std::path::absolute(path)?;
+    Ok(CString::new(path.to_string_lossy().as_bytes())?)
+}
+
+fn with_frontend<T>(f: impl FnOnce(&mut Frontend) -> T) -> T {
+    FRONTEND.with_borrow_mut(|frontend| f(frontend.as_mut().expect("frontend is installed")))
+}
+
+/// Runs a callback against the frontend, or returns `default` when a callback arrives
+/// before it is installed or while it is borrowed
+fn callback<T>(name: &str, default: T, f: impl FnOnce(&mut Frontend) -> T) -> T {
+    FRONTEND.with(|frontend| match frontend.try_borrow_mut() {
+        Ok(mut frontend) => match frontend.as_mut() {
+            Some(frontend) => f(frontend),
+            None => default,
+        },
+        Err(_) => {
+            warn!("libretro {name} callback re-entered the frontend");
+            default
+        }
+    })
+}
+
+unsafe extern "C" fn environment(command: c_uint, data: *mut c_void) -> bool {
+    if data.is_null() {
+        return false;
+    }
+    callback("environment", false, |frontend| {
+        frontend.environment(command, data)
+    })
+}
+
+unsafe extern "C" fn video_refresh(
+    data: *const c_void,
+    width: c_uint,
+    height: c_uint,
+    pitch: usize,
+) {
+    callback("video", (), |frontend| {
+        frontend.present(data, width, height, pitch)
+    });
+}
+
+unsafe extern "C" fn audio_sample(left: i16, right: i16) {
+    callback("audio", (), |frontend| {
+        frontend.audio_samples.extend([left, right])
+    });
+}
+
+unsafe extern "C" fn audio_sample_batch(data: *const i16, frames: usize) -> usize {
+    if data.is_null() {
+        return 0;
+    }
+    let samples = unsafe { std::slice::from_raw_parts(data, frames * AUDIO_CHANNELS as usize) };
+    callback("audio", (), |frontend| {
+        frontend.audio_samples.extend_from_slice(samples)
+    });
+    frames
+}
+
+unsafe extern "C" fn input_poll() {
+    callback("input poll", (), Frontend::poll_input);
+}
+
+unsafe extern "C" fn input_state(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16 {
+    callback("input", 0, |frontend| {
+        frontend.input_state(port, device, index, id)
+    })
+}
+
+unsafe extern "C" fn get_current_framebuffer() -> usize {
+    callback("framebuffer", 0, |frontend| frontend.framebuffer())
+}
+
+unsafe extern "C" fn get_proc_address(name: *const c_char) -> *const c_void {
+    if name.is_null() {
+        return ptr::null();
+    }
+    unsafe { sdl2::sys::SDL_GL_GetProcAddress(name) }.cast_const()
+}
@@ -1,27 +1,79 @@
+mod agent;
 mod audio;
+mod bezel;
+mod c64;
+mod c64_disk;
+mod c64_keyboard;
+mod c64_reu;
+mod c64_script;
+mod c64_state;
+mod config;
+mod control;
 mod controller;
 mod display;
+mod focus_pause;
+mod frame_pacer;
 mod gamecube;
+mod gb_debugger;
+mod gb_memory;
+mod gc_banner;
+mod gc_disc;
+mod gc_hw;
+mod gc_input;
+mod gc_memcard;
+mod gekko;
 mod interactive;
 mod launcher;
+mod libretro;
+mod mouse;
+mod movie;
+mod movie_import;
 mod n64;
+mod n64_dd;
+mod n64_input;
+mod n64_video;
 mod nds;
 mod nes;
+mod osd;
+mod pad_overlay;
+mod perf_overlay;
+mod present;
 mod ps1;
+mod ps1_bios;
+mod ps1_cheats;
+mod ps1_debugger;
+mod ps1_keyboard;
+mod ps1_memcard;
+mod resume;
 mod rtc;
+mod save_convert;
+mod save_sync;
+mod save_transfer;
+mod saves;
+mod scenario;
+mod screenshot;
 mod snes;
 mod systems;
+mod wii;
 
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result, anyhow};
-use clap::Parser;
-use gameboy_core::Gameboy;
+use anyhow::{Context, Result, anyhow, ensure};
+use clap::{Parser, Subcommand};
+use gameboy_core::emulator::traits::RTC;
+use gameboy_core::{Cartridge, Gameboy};
 
+use crate::agent::AgentBridge;
 use crate::audio::AudioPlayer;
-use crate::interactive::InteractiveRunner;
-use crate::rtc::SystemRtc;
+use crate::config::{CONFIG_PATH, Config, Fullscreen};
+use crate::control::ControlServer;
+use crate::gb_debugger::{BreakAction, Debugger, FrameRange};
+use crate::gb_memory::{Poke, Trigger};
+use crate::interactive::{Automation, InteractiveRunner};
+use crate::movie::InputMovie;
+use crate::rtc::{FrameClock, SystemRtc};
+use crate::scenario::Scenario;
 use crate::systems::{GameSystem, detect_system};
 
 #[derive(Parser, Debug)]
@@ -50,33 +102,385 @@ struct Cli {
     /// Path to a PlayStation BIOS image (fallbacks to PS1_BIOS/PSX_BIOS env vars + bios/)
     #[arg(long)]
     ps1_bios: Option<PathBuf>,
+
+    /// PlayStation memory cards to insert from saves/ps1/: one pair per game or one shared pair
+    #[arg(long, value_enum, default_value_t = ps1_memcard::MemoryCardMode::PerGame)]
+    ps1_memcard: ps1_memcard::MemoryCardMode,
+
+    /// Open the PlayStation memory card manager (copy/delete saves) instead of a game
+    #[arg(long)]
+    ps1_memcards: bool,
+
+    /// Skip the PlayStation BIOS boot animation (overrides `fast_boot` in config.toml)
+    #[arg(long, value_name = "BOOL")]
+    ps1_fast_boot: Option<bool>,
+
+    /// Boot the PlayStation BIOS without a disc, to reach its memory card and CD player screens
+    #[arg(long)]
+    ps1_bios_shell: bool,
+
+    /// Plug a PlayStation multitap into port 1 for 3-4 player games
+    #[arg(long)]
+    ps1_multitap: bool,
+
+    /// Start PlayStation games paused in a debugger prompt on the terminal
+    /// (breakpoints, stepping, registers and memory), press Pause in the game to break in
+    #[arg(long)]
+    ps1_debug: bool,
+
+    /// Load a Nintendo 64 save state file once the game starts
+    #[arg(long, value_name = "PATH")]
+    n64_load_state: Option<PathBuf>,
+
+    /// Accessory in every Nintendo 64 controller: auto (Rumble Pak when the pad can rumble), memory, rumble or none
+    #[arg(long, value_enum, default_value_t = n64_input::Pak::Auto)]
+    n64_pak: n64_input::Pak,
+
+    /// Insert a 64DD disk image (.ndd) next to the Nintendo 64 cartridge, for expansion disks
+    #[arg(long, value_name = "PATH")]
+    n64_disk: Option<PathBuf>,
+
+    /// Load a Dolphin save state file when the GameCube or Wii game starts
+    #[arg(long, value_name = "PATH")]
+    gc_load_state: Option<PathBuf>,
+
+    /// Open the GameCube memory card manager (list saves, export .gci files) instead of a game
+    #[arg(long)]
+    gc_memcards: bool,
+
+    /// Nintendo DS slot-2 device: none, auto, rumble, expansion, or a GBA ROM (gba:<path>).
+    /// Defaults to a `.gba` file next to the DS ROM when one exists.
+    #[arg(long, value_name = "DEVICE")]
+    nds_slot2: Option<nds::Slot2Device>,
+
+    /// Record the Game Boy or NES buttons of every frame to an input movie (.rlm)
+    #[arg(long, value_name = "PATH", conflicts_with = "play_inputs")]
+    record_inputs: Option<PathBuf>,
+
+    /// Replay a Game Boy or NES input movie (.rlm) from power-on, then hand the controls back
+    #[arg(long, value_name = "PATH")]
+    play_inputs: Option<PathBuf>,
+
+    /// Convert a BizHawk .bk2 or FCEUX .fm2 movie for the --rom game into an .rlm movie next to it
+    #[arg(long, value_name = "PATH", requires = "rom")]
+    import_movie: Option<PathBuf>,
+
+    /// Commodore 64 model: pal, ntsc, c64c-pal or c64c-ntsc (overrides `model` in config.toml)
+    #[arg(long, value_enum, value_name = "MODEL")]
+    c64_model: Option<config::C64Model>,
+
+    /// Make Game Boy and NES runs repeat exactly: the cartridge clock starts at EPOCH (Unix
+    /// seconds, 0 when left out) and moves with emulated frames, and frames are not paced
+    #[arg(long, value_name = "EPOCH", num_args = 0..=1, default_missing_value = "0")]
+    deterministic: Option<u64>,
+
+    /// Serve a WebSocket remote control for Game Boy games on 127.0.0.1:PORT
+    #[arg(long, value_name = "PORT")]
+    control_port: Option<u16>,
+
+    /// Let an external agent play Game Boy games over TCP on 127.0.0.1:PORT, one frame at a time
+    #[arg(long, value_name = "PORT")]
+    agent_port: Option<u16>,
+
+    /// Game Boy addresses (hex) whose bytes are sent to the agent with every frame
+    #[arg(long, value_name = "ADDR", value_delimiter = ',', value_parser = gb_memory::parse_address, requires = "agent_port")]
+    agent_watch: Vec<u16>,
+
+    /// Play a Game Boy or NES input scenario, steps like `frame 120: press Start for 2 frames`
+    #[arg(long, value_name = "FILE")]
+    scenario: Option<PathBuf>,
+
+    /// Write Game Boy memory before every frame (ADDR=VALUE in hex), or once before frame N
+    /// with ADDR=VALUE@N, to freeze values and set up test scenarios
+    #[arg(long, value_name = "ADDR=VALUE[@FRAME]", value_delimiter = ',')]
+    poke: Vec<Poke>,
+
+    /// Stop a Game Boy game when the CPU reaches one of these addresses (hex), F5 continues
+    /// and F10 steps one instruction
+    #[arg(long = "break", value_name = "ADDR", value_delimiter = ',', value_parser = gb_memory::parse_address)]
+    breakpoints: Vec<u16>,
+
+    /// What a Game Boy breakpoint does: pause the game or only log the registers
+    #[arg(long, value_enum, default_value_t = BreakAction::Pause)]
+    break_action: BreakAction,
+
+    /// Log every Game Boy instruction with its registers to a file
+    #[arg(long, value_name = "FILE")]
+    trace: Option<PathBuf>,
+
+    /// Frames to trace, START..END (END excluded) or START..
+    #[arg(long, value_name = "START..END", requires = "trace")]
+    trace_frames: Option<FrameRange>,
+
+    /// Run ACTIONS (screenshot, log, joined by |) at the end of the Game Boy frame where
+    /// CONDITION starts to hold, like `C201>prev` or `D000>=63` (hex)
+    #[arg(long, num_args = 2, value_names = ["CONDITION", "ACTIONS"])]
+    on: Vec<String>,
+
+    /// Run a Lua script alongside a Commodore 64 game, for trainers, HUDs and bots
+    #[arg(long, value_name = "FILE")]
+    script: Option<PathBuf>,
+
+    /// Enable Nintendo DS local wireless, relayed to other instances over UDP port 7000 on the LAN
+    #[arg(long)]
+    nds_wifi: bool,
+
+    /// Show the Nintendo DS top and touch screens in two windows instead of one stacked window
+    #[arg(long)]
+    nds_split_screens: bool,
+
+    /// Master volume in percent, 0 to 100 (overrides `volume` under [audio] in config.toml)
+    #[arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(0..=100))]
+    volume: Option<u8>,
+
+    /// Turn the picture clockwise by 0, 90, 180 or 270 degrees, for vertical (TATE) games and
+    /// rotated monitors (overrides `rotate` under [display] in config.toml)
+    #[arg(long, value_name = "DEGREES", value_parser = ["0", "90", "180", "270"])]
+    rotate: Option<String>,
+
+    /// How the game window fills the screen (overrides `fullscreen` under [display] in
+    /// config.toml)
+    #[arg(long, value_enum, value_name = "MODE")]
+    fullscreen: Option<Fullscreen>,
+
+    /// Start with the performance overlay open: FPS, frame times, queued audio and emulation
+    /// speed. F3 toggles it while playing.
+    #[arg(long)]
+    perf_overlay: bool,
+
+    /// Pause the game while its window is in the background (same as `pause_unfocused` under
+    /// [display] in config.toml)
+    #[arg(long)]
+    pause_unfocused: bool,
+
+    /// Save a state when the game exits and pick up from it next time, for the systems with
+    /// save states (same as `auto_resume` under [saves] in config.toml). The launcher asks
+    /// whether to resume or start fresh.
+    #[arg(long)]
+    auto_resume: bool,
+
+    /// Play with the saves and states of profile NAME, kept apart from everyone else's. A new
+    /// name starts a new profile.
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Copy battery saves between saves/ and raw .sav files, for flashcarts and real cartridges
+    Save {
+        #[command(subcommand)]
+        action: SaveCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SaveCommand {
+    /// Write a game's save out as a raw SRAM image
+    Export {
+        /// The game whose save to export
+        rom: PathBuf,
+        /// Where to write it, `<game>.sav` in the working directory by default
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Replace a game's save with a raw SRAM image, the old save is kept in backups/
+    Import {
+        /// The game whose save to replace
+        rom: PathBuf,
+        /// The raw save, a flashcart's or cartridge dumper's .sav/.srm
+        save: PathBuf,
+    },
+    /// Convert a save between formats, picked by the file extensions: DeSmuME .dsv and raw DS
+    /// saves, PlayStation .gme/.mcr/.mcd cards, or a raw .srm/.sav resized to its cartridge
+    Convert {
+        input: PathBuf,
+        output: PathBuf,
+        /// The game a raw save belongs to, its save RAM size is what the save is resized to
+        #[arg(long)]
+        rom: Option<PathBuf>,
+        /// Resize a raw save to this many bytes instead of the size `--rom` has
+        #[arg(long)]
+        size: Option<usize>,
+    },
 }
 
 fn main() -> Result<()> {
     env_logger::init();
+    present::init_hidpi();
     let cli = Cli::parse();
     let games_dir = Path::new("games");
     let bios_dir = Path::new("bios");
     fs::create_dir_all(games_dir).context("failed to create games directory")?;
     fs::create_dir_all(bios_dir).context("failed to create bios directory")?;
+    let mut config = Config::load(Path::new(CONFIG_PATH))?;
+    if let Some(fast_boot) = cli.ps1_fast_boot {
+        config.ps1.fast_boot = fast_boot;
+    }
+    if cli.ps1_multitap {
+        config.ps1.multitap = true;
+    }
+    if let Some(volume) = cli.volume {
+        config.audio.volume = volume;
+    }
+    if cli.perf_overlay {
+        config.display.perf_overlay = true;
+    }
+    if cli.pause_unfocused {
+        config.display.pause_unfocused = true;
+    }
+    if let Some(rotate) = &cli.rotate {
+        config.display.rotate = rotate.parse()?;
+    }
+    present::set_rotation(config.display.rotate, config.display.rotate_input);
+    saves::set_root(config.saves.dir.clone());
+    saves::set_backups(config.saves.backups);
+    save_sync::configure(&config.saves);
+    resume::set_enabled(cli.auto_resume || config.saves.auto_resume);
+    if let Some(profile) = &cli.profile {
+        saves::check_profile(profile)?;
+        saves::set_profile(Some(profile.clone()));
+    }
+
+    if let (Some(movie), Some(rom)) = (&cli.import_movie, &cli.rom) {
+        let output = movie_import::import(movie, rom)?;
+        println!("Wrote {}, replay it with --play-inputs", output.display());
+        return Ok(());
+    }
+    let result = launch(&cli, &config, games_dir, bios_dir);
+    save_sync::finish();
+    result
+}
+
+/// Runs a save command, opens a memory card manager or runs a game, everything that may write
+/// saves
+fn launch(cli: &Cli, config: &Config, games_dir: &Path, bios_dir: &Path) -> Result<()> {
+    if let Some(Command::Save { action }) = &cli.command {
+        return save_command(action);
+    }
+    if cli.ps1_memcards {
+        return ps1_memcard::manage();
+    }
+    if cli.gc_memcards {
+        return gc_memcard::manage();
+    }
+    if cli.ps1_bios_shell {
+        set_fullscreen(cli, config, GameSystem::Ps1);
+        return run_ps1(None, cli, config);
+    }
 
     let rom_path = match &cli.rom {
         Some(path) => path.clone(),
-        None => launcher::select_game(games_dir, cli.gui)?,
+        None => launcher::select_game(games_dir, cli.gui, config, cli.profile.is_none())?,
     };
 
-    match detect_system(&rom_path)? {
-        GameSystem::GameBoy => run_gameboy(&rom_path, &cli),
-        GameSystem::Nes => nes::run(&rom_path, cli.scale, cli.limit_fps),
-        GameSystem::Snes => snes::run(&rom_path, cli.scale, cli.limit_fps),
-        GameSystem::Nds => nds::run(&rom_path, cli.scale, cli.limit_fps),
-        GameSystem::Ps1 => ps1::run(&rom_path, cli.scale, cli.limit_fps, cli.ps1_bios.clone()),
-        GameSystem::N64 => n64::run(&rom_path, cli.scale, cli.limit_fps),
-        GameSystem::GameCube => gamecube::run(&rom_path, cli.scale, cli.limit_fps),
+    let system = detect_system(&rom_path)?;
+    ensure!(
+        cli.script.is_none() || system == GameSystem::C64,
+        "--script is only supported for Commodore 64 games"
+    );
+    set_fullscreen(cli, config, system);
+    match system {
+        GameSystem::GameBoy => run_gameboy(&rom_path, cli, config),
+        GameSystem::Nes => {
+            let rom_bytes = fs::read(&rom_path)
+                .with_context(|| format!("failed to read {}", rom_path.display()))?;
+            let movie = input_movie(cli, &rom_bytes)?;
+            let scenario = cli.scenario.as_deref().map(Scenario::load).transpose()?;
+            nes::run(&rom_path, cli.scale, paced(cli), movie, scenario, config)
+        }
+        GameSystem::Snes => snes::run(&rom_path, cli.scale, cli.limit_fps, config),
+        GameSystem::Nds => nds::run(
+            &rom_path,
+            cli.scale,
+            cli.limit_fps,
+            cli.nds_slot2.clone(),
+            cli.nds_wifi,
+            cli.nds_split_screens,
+            config,
+        ),
+        GameSystem::Ps1 => run_ps1(Some(&rom_path), cli, config),
+        GameSystem::N64 => n64::run(
+            &rom_path,
+            cli.scale,
+            cli.limit_fps,
+            cli.n64_load_state.clone(),
+            cli.n64_pak,
+            cli.n64_disk.clone(),
+            config,
+        ),
+        GameSystem::GameCube => gamecube::run(
+            &rom_path,
+            cli.scale,
+            cli.limit_fps,
+            cli.gc_load_state.as_deref(),
+            config,
+        ),
+        GameSystem::Wii => wii::run(
+            &rom_path,
+            cli.scale,
+            cli.limit_fps,
+            cli.gc_load_state.as_deref(),
+            config,
+        ),
+        GameSystem::C64 => c64::run(
+            &rom_path,
+            bios_dir,
+            cli.scale,
+            cli.limit_fps,
+            config,
+            cli.c64_model,
+            cli.script.as_deref(),
+        ),
+    }
+}
+
+fn save_command(action: &SaveCommand) -> Result<()> {
+    match action {
+        SaveCommand::Export { rom, output } => {
+            let output = save_transfer::export(rom, output.as_deref())?;
+            println!("Wrote {}", output.display());
+        }
+        SaveCommand::Import { rom, save } => {
+            let path = save_transfer::import(rom, save)?;
+            println!("Imported {} as {}", save.display(), path.display());
+        }
+        SaveCommand::Convert {
+            input,
+            output,
+            rom,
+            size,
+        } => {
+            save_convert::convert(input, output, rom.as_deref(), *size)?;
+            println!("Wrote {}", output.display());
+        }
     }
+    Ok(())
 }
 
-fn run_gameboy(rom_path: &Path, cli: &Cli) -> Result<()> {
+fn set_fullscreen(cli: &Cli, config: &Config, system: GameSystem) {
+    present::set_fullscreen(
+        cli.fullscreen
+            .unwrap_or_else(|| config.display.fullscreen_for(system)),
+    );
+}
+
+fn run_ps1(rom_path: Option<&Path>, cli: &Cli, config: &Config) -> Result<()> {
+    ps1::run(
+        rom_path,
+        cli.scale,
+        cli.limit_fps,
+        cli.ps1_bios.clone(),
+        cli.ps1_memcard,
+        &config.ps1,
+        cli.ps1_debug,
+    )
+}
+
+fn run_gameboy(rom_path: &Path, cli: &Cli, config: &Config) -> Result<()> {
     let rom_bytes =
         fs::read(rom_path).with_context(|| format!("failed to read {}", rom_path.display()))?;
     let title = rom_path
@@ -84,9 +488,114 @@ fn run_gameboy(rom_path: &Path, cli: &Cli) -> Result<()> {
         .and_then(|s| s.to_str())
         .unwrap_or("Game Boy");
 
-    let rtc = Box::new(SystemRtc);
+    let movie = input_movie(cli, &rom_bytes)?;
+    // a movie replays with the clock it was recorded with
+    let clock = movie
+        .as_ref()
+        .map(InputMovie::start)
+        .or(cli.deterministic)
+        .map(FrameClock::new);
+    let rtc: Box<dyn RTC> = match &clock {
+        Some(clock) => Box::new(clock.clone()),
+        None => Box::new(SystemRtc),
+    };
     let mut gameboy = Gameboy::from_rom(rom_bytes, rtc).map_err(|err| anyhow!(err))?;
-    let mut audio = AudioPlayer::new()?;
-    let mut runner = InteractiveRunner::new(title, cli.scale, cli.limit_fps)?;
-    runner.run(&mut gameboy, &mut audio)
+    // movies and deterministic runs start from a fresh cartridge and leave the save alone
+    let battery = if gameboy.get_cartridge().has_battery() && clock.is_none() {
+        let path = saves::battery_save(GameSystem::GameBoy, rom_path)?;
+        load_battery(&mut gameboy, &path)?;
+        Some(path)
+    } else {
+        None
+    };
+    let mut audio = AudioPlayer::new(&config.audio, interactive::SAMPLE_RATE, 2)?;
+    let control = cli.control_port.map(ControlServer::start).transpose()?;
+    let agent = cli
+        .agent_port
+        .map(|port| AgentBridge::listen(port, cli.agent_watch.clone()))
+        .transpose()?;
+    let automation = Automation {
+        movie,
+        clock,
+        control,
+        agent,
+        pokes: cli.poke.clone(),
+        debugger: game_boy_debugger(cli)?,
+        triggers: cli
+            .on
+            .chunks_exact(2)
+            .map(|rule| Trigger::parse(&rule[0], &rule[1]).map_err(|err| anyhow!(err)))
+            .collect::<Result<_>>()?,
+        scenario: cli.scenario.as_deref().map(Scenario::load).transpose()?,
+    };
+    let mut runner = InteractiveRunner::new(title, cli.scale, paced(cli), automation, config)?;
+    let result = runner.run(&mut gameboy, &mut audio);
+    if let Some(path) = &battery {
+        let cartridge = gameboy.get_cartridge();
+        saves::write(path, &cartridge.get_ram()[..battery_size(cartridge)])?;
+    }
+    result
+}
+
+/// Restores the cartridge RAM from its save. A truncated save is refused, one too large for
+/// the cartridge is reported and skipped.
+fn load_battery(gameboy: &mut Gameboy, path: &Path) -> Result<()> {
+    let cartridge = gameboy.get_cartridge_mut();
+    let size = battery_size(cartridge);
+    let Some(ram) = saves::read(path, Some(size))? else {
+        return Ok(());
+    };
+    if ram.len() != size {
+        log::warn!(
+            "Ignoring the save {}, it holds {} bytes but the cartridge has {size}",
+            path.display(),
+            ram.len()
+        );
+        return Ok(());
+    }
+    cartridge.get_ram_mut()[..size].copy_from_slice(&ram);
+    Ok(())
+}
+
+/// Bytes of cartridge RAM a save holds, the banks the header declares. MBC2 has its RAM built
+/// in and declares none, so its whole buffer is saved.
+fn battery_size(cartridge: &Cartridge) -> usize {
+    let ram = cartridge.get_ram().len();
+    match cartridge.get_ram_banks() * 0x2000 {
+        0 => ram,
+        size => size.min(ram),
+    }
+}
+
+/// The debugger `--break` or `--trace` asks for, if any
+fn game_boy_debugger(cli: &Cli) -> Result<Option<Debugger>> {
+    if cli.breakpoints.is_empty() && cli.trace.is_none() {
+        return Ok(None);
+    }
+    Debugger::new(
+        &cli.breakpoints,
+        cli.break_action,
+        cli.trace.as_deref(),
+        cli.trace_frames.unwrap_or(FrameRange::ALL),
+    )
+    .map(Some)
+}
+
+/// The movie `--record-inputs` or `--play-inputs` asks for, if any
+fn input_movie(cli: &Cli, rom: &[u8]) -> Result<Option<InputMovie>> {
+    match (&cli.record_inputs, &cli.play_inputs) {
+        (Some(path), _) => {
+            let start = cli
+                .deterministic
+                .unwrap_or_else(|| SystemRtc.get_current_time());
+            Ok(Some(InputMovie::record(path, rom, start)?))
+        }
+        (None, Some(path)) => Ok(Some(InputMovie::play(path, rom)?)),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Deterministic runs go as fast as the host allows, so nothing depends on wall-clock time
+fn paced(cli: &Cli) -> bool {
+    cli.limit_fps && cli.deterministic.is_none()
 }
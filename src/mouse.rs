@@ -0,0 +1,135 @@
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::{MouseButton, MouseUtil};
+
+/// Grabs the host mouse for an emulated one and lets it go again
+pub const HOTKEY: Keycode = Keycode::F11;
+
+/// Host mouse passed through to a core's pointing device. While captured the window keeps
+/// the pointer and hides it, and only relative motion is collected so it never stops at the
+/// window edge. Frontends feed events in and [`Self::take`] the result once per frame.
+#[derive(Debug, Default)]
+pub struct MouseCapture {
+    captured: bool,
+    dx: i32,
+    dy: i32,
+    left: bool,
+    right: bool,
+}
+
+/// Motion since the last frame and the buttons held, positive is right and down
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MouseInput {
+    pub dx: i32,
+    pub dy: i32,
+    pub left: bool,
+    pub right: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerButton {
+    Left,
+    Right,
+}
+
+impl MouseCapture {
+    pub fn is_captured(&self) -> bool {
+        self.captured
+    }
+
+    /// Grabs or releases the mouse, returns whether it is captured now. Releasing lets go of
+    /// the buttons so the game does not see them stuck.
+    pub fn toggle(&mut self) -> bool {
+        self.set_captured(!self.captured);
+        self.captured
+    }
+
+    pub fn set_captured(&mut self, captured: bool) {
+        if captured != self.captured {
+            log::info!(
+                "Mouse {}",
+                if captured {
+                    "captured, F11 releases it"
+                } else {
+                    "released"
+                }
+            );
+        }
+        self.captured = captured;
+        if !captured {
+            *self = Self::default();
+        }
+    }
+
+    pub fn motion(&mut self, dx: i32, dy: i32) {
+        if self.captured {
+            self.dx = self.dx.saturating_add(dx);
+            self.dy = self.dy.saturating_add(dy);
+        }
+    }
+
+    pub fn button(&mut self, button: PointerButton, pressed: bool) {
+        if !self.captured {
+            return;
+        }
+        match button {
+            PointerButton::Left => self.left = pressed,
+            PointerButton::Right => self.right = pressed,
+        }
+    }
+
+    /// The motion collected since the last call and the buttons held now
+    pub fn take(&mut self) -> MouseInput {
+        let input = MouseInput {
+            dx: self.dx,
+            dy: self.dy,
+            left: self.left,
+            right: self.right,
+        };
+        self.dx = 0;
+        self.dy = 0;
+        input
+    }
+
+    /// Handles an event of an SDL window: [`HOTKEY`] toggles the capture, relative mouse mode
+    /// grabs the pointer, and losing focus releases it
+    pub fn handle_sdl_event(&mut self, event: &Event, mouse: &MouseUtil) {
+        match *event {
+            Event::KeyDown {
+                keycode: Some(HOTKEY),
+                repeat: false,
+                ..
+            } => {
+                let captured = self.toggle();
+                mouse.set_relative_mouse_mode(captured);
+            }
+            Event::Window {
+                win_event: WindowEvent::FocusLost,
+                ..
+            } => {
+                self.set_captured(false);
+                mouse.set_relative_mouse_mode(false);
+            }
+            Event::MouseMotion { xrel, yrel, .. } => self.motion(xrel, yrel),
+            Event::MouseButtonDown { mouse_btn, .. } => {
+                if let Some(button) = pointer_button(mouse_btn) {
+                    self.button(button, true);
+                }
+            }
+            Event::MouseButtonUp { mouse_btn, .. } => {
+                if let Some(button) = pointer_button(mouse_btn) {
+                    self.button(button, false);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn pointer_button(button: MouseButton) -> Option<PointerButton> {
+    match button {
+        MouseButton::Left => Some(PointerButton::Left),
+        MouseButton::Right => Some(PointerButton::Right),
+        _ => None,
+    }
+}
@@ -0,0 +1,146 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result, ensure};
+use sha2::{Digest, Sha256};
+
+const MAGIC: &[u8; 7] = b"RLMOVIE";
+const VERSION: u8 = 1;
+/// Magic and version, the Unix time the game's clock starts at and the ROM's SHA-256 in hex
+const HEADER_SIZE: usize = MAGIC.len() + 1 + 8 + 64;
+/// Bits of a frame's input byte, the same for every system
+pub const LEFT: u8 = 0x01;
+pub const RIGHT: u8 = 0x02;
+pub const UP: u8 = 0x04;
+pub const DOWN: u8 = 0x08;
+pub const A: u8 = 0x10;
+pub const B: u8 = 0x20;
+pub const START: u8 = 0x40;
+pub const SELECT: u8 = 0x80;
+
+/// Input movie in the `.rlm` format: a header, then one byte of buttons held per frame.
+/// Movies start from power-on without a battery save, and a Game Boy cartridge clock runs
+/// on emulated frames from the recorded start time (a `FrameClock`), so a replay sees
+/// exactly what the recording did.
+pub enum InputMovie {
+    Recording {
+        writer: BufWriter<File>,
+        frames: u64,
+        start: u64,
+    },
+    Playing {
+        inputs: Vec<u8>,
+        frames: usize,
+        start: u64,
+    },
+}
+
+impl InputMovie {
+    pub fn record(path: &Path, rom: &[u8], start: u64) -> Result<Self> {
+        let writer = create(path, rom, start)?;
+        log::info!("Recording inputs to {}", path.display());
+        Ok(Self::Recording {
+            writer,
+            frames: 0,
+            start,
+        })
+    }
+
+    pub fn play(path: &Path, rom: &[u8]) -> Result<Self> {
+        let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        ensure!(
+            bytes.len() >= HEADER_SIZE && bytes.starts_with(MAGIC),
+            "{} is not an input movie",
+            path.display()
+        );
+        ensure!(
+            bytes[MAGIC.len()] == VERSION,
+            "{} was recorded by a different version of the launcher",
+            path.display()
+        );
+        let (header, inputs) = bytes.split_at(HEADER_SIZE);
+        let start = u64::from_le_bytes(header[8..16].try_into()?);
+        ensure!(
+            header[16..] == *rom_hash(rom).as_bytes(),
+            "{} was recorded with a different ROM",
+            path.display()
+        );
+        log::info!(
+            "Playing {} frames of inputs from {}",
+            inputs.len(),
+            path.display()
+        );
+        Ok(Self::Playing {
+            inputs: inputs.to_vec(),
+            frames: 0,
+            start,
+        })
+    }
+
+    /// Unix time the cartridge clock starts at
+    pub fn start(&self) -> u64 {
+        let (Self::Recording { start, .. } | Self::Playing { start, .. }) = self;
+        *start
+    }
+
+    /// Buttons for the next frame as a bit per button. A recording stores the live ones
+    /// and hands them back, playback replaces them until the movie runs out.
+    pub fn next_frame(&mut self, live: u8) -> Result<Option<u8>> {
+        match self {
+            Self::Recording { writer, frames, .. } => {
+                writer
+                    .write_all(&[live])
+                    .context("failed to write the input movie")?;
+                *frames += 1;
+                Ok(Some(live))
+            }
+            Self::Playing { inputs, frames, .. } => {
+                let Some(&buttons) = inputs.get(*frames) else {
+                    return Ok(None);
+                };
+                *frames += 1;
+                Ok(Some(buttons))
+            }
+        }
+    }
+
+    pub fn finish(self) -> Result<()> {
+        if let Self::Recording {
+            mut writer, frames, ..
+        } = self
+        {
+            writer.flush().context("failed to write the input movie")?;
+            log::info!("Recorded {frames} frames of inputs");
+        }
+        Ok(())
+    }
+}
+
+/// Writes a whole movie at once, for movies converted from other emulators
+pub fn save(path: &Path, rom: &[u8], start: u64, inputs: &[u8]) -> Result<()> {
+    let mut writer = create(path, rom, start)?;
+    writer
+        .write_all(inputs)
+        .and_then(|()| writer.flush())
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Creates the movie file and writes its header
+fn create(path: &Path, rom: &[u8], start: u64) -> Result<BufWriter<File>> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION])?;
+    writer.write_all(&start.to_le_bytes())?;
+    writer.write_all(rom_hash(rom).as_bytes())?;
+    Ok(writer)
+}
+
+fn rom_hash(rom: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(rom))
+}
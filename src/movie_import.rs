@@ -0,0 +1,198 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail, ensure};
+
+use crate::movie;
+use crate::systems::{GameSystem, detect_system};
+
+/// Gamepad columns of an FM2 input line, left to right
+const FM2_BUTTONS: [u8; 8] = [
+    movie::RIGHT,
+    movie::LEFT,
+    movie::DOWN,
+    movie::UP,
+    movie::START,
+    movie::SELECT,
+    movie::B,
+    movie::A,
+];
+/// FM2 command bits for a soft reset and a power cycle
+const FM2_RESET: u32 = 0x03;
+
+/// Converts a BizHawk `.bk2` (Game Boy or NES) or FCEUX `.fm2` movie for `rom` into an
+/// `.rlm` movie next to it, which `--play-inputs` replays. Only player 1 and movies that
+/// start from power-on convert; resets after the first frame are refused since our cores
+/// can't be reset mid-movie.
+pub fn import(path: &Path, rom: &Path) -> Result<PathBuf> {
+    let system = detect_system(rom)?;
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    let inputs = match ext.as_deref() {
+        Some("bk2") => read_bk2(path, system)?,
+        Some("fm2") => {
+            ensure!(
+                system == GameSystem::Nes,
+                "FM2 movies are for the NES, {} is a {system} ROM",
+                rom.display()
+            );
+            read_fm2(path)?
+        }
+        _ => bail!("{} is not a .bk2 or .fm2 movie", path.display()),
+    };
+    let rom_bytes = fs::read(rom).with_context(|| format!("failed to read {}", rom.display()))?;
+    let output = path.with_extension("rlm");
+    // BizHawk starts the Game Boy clock at the epoch unless the movie says otherwise
+    movie::save(&output, &rom_bytes, 0, &inputs)?;
+    log::info!(
+        "Converted {} frames of {} to {}",
+        inputs.len(),
+        path.display(),
+        output.display()
+    );
+    Ok(output)
+}
+
+fn read_bk2(path: &Path, system: GameSystem) -> Result<Vec<u8>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .with_context(|| format!("{} is not a BizHawk movie", path.display()))?;
+    let mut read_entry = |name: &str| -> Result<String> {
+        let mut text = String::new();
+        archive
+            .by_name(name)
+            .with_context(|| format!("{} has no {name}", path.display()))?
+            .read_to_string(&mut text)?;
+        Ok(text)
+    };
+    let header = read_entry("Header.txt")?;
+    let log = read_entry("Input Log.txt")?;
+
+    let value = |key: &str| {
+        header.lines().find_map(|line| {
+            line.split_once(' ')
+                .filter(|(name, _)| *name == key)
+                .map(|(_, value)| value.trim())
+        })
+    };
+    ensure!(
+        !value("StartsFromSavestate").is_some_and(|starts| starts.eq_ignore_ascii_case("true"))
+            && !value("StartsFromSaveRam")
+                .is_some_and(|starts| starts.eq_ignore_ascii_case("true")),
+        "{} starts from a save state or save RAM, only movies from power-on can be imported",
+        path.display()
+    );
+    let platform = value("Platform").unwrap_or_default();
+    let expected = match platform {
+        "NES" => GameSystem::Nes,
+        "GB" | "GBC" | "SGB" => GameSystem::GameBoy,
+        other => bail!(
+            "{} is a {other} movie, only GB and NES ones import",
+            path.display()
+        ),
+    };
+    ensure!(
+        expected == system,
+        "{} is a {platform} movie but the ROM is for the {system}",
+        path.display()
+    );
+
+    let log_key = log
+        .lines()
+        .find_map(|line| line.strip_prefix("LogKey:"))
+        .ok_or_else(|| anyhow!("{} has no LogKey in its input log", path.display()))?;
+    // `#P1 Up|P1 Down|...|#P2 Up|...`, one group per `|`-separated column of the log
+    let groups: Vec<Vec<&str>> = log_key
+        .split('#')
+        .filter(|group| !group.is_empty())
+        .map(|group| group.split('|').filter(|name| !name.is_empty()).collect())
+        .collect();
+
+    let mut inputs = Vec::new();
+    for line in log.lines().filter(|line| line.starts_with('|')) {
+        let columns = line.trim_matches('|').split('|');
+        let mut buttons = 0;
+        for (names, column) in groups.iter().zip(columns) {
+            for (name, state) in names.iter().zip(column.chars()) {
+                if state == '.' || state == ' ' {
+                    continue;
+                }
+                let name = name.strip_prefix("P1 ").unwrap_or(name);
+                match name {
+                    "Power" | "Reset" if !inputs.is_empty() => {
+                        bail!(
+                            "{} resets the console on frame {}, which can't be replayed",
+                            path.display(),
+                            inputs.len()
+                        )
+                    }
+                    _ => buttons |= button_bit(name),
+                }
+            }
+        }
+        inputs.push(buttons);
+    }
+    Ok(inputs)
+}
+
+/// Player 1 buttons by their BizHawk names, anything else is ignored
+fn button_bit(name: &str) -> u8 {
+    match name {
+        "Left" => movie::LEFT,
+        "Right" => movie::RIGHT,
+        "Up" => movie::UP,
+        "Down" => movie::DOWN,
+        "A" => movie::A,
+        "B" => movie::B,
+        "Start" => movie::START,
+        "Select" => movie::SELECT,
+        _ => 0,
+    }
+}
+
+fn read_fm2(path: &Path) -> Result<Vec<u8>> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut inputs = Vec::new();
+    for line in text.lines() {
+        let Some(record) = line.strip_prefix('|') else {
+            match line.split_once(' ') {
+                Some(("binary", "1")) => bail!(
+                    "{} uses binary input, only text FM2 imports",
+                    path.display()
+                ),
+                Some(("savestate", _)) => {
+                    bail!(
+                        "{} starts from a save state, only movies from power-on can be imported",
+                        path.display()
+                    )
+                }
+                Some(("port0", port)) if port.trim() != "1" => {
+                    bail!("{} has no gamepad in port 0", path.display())
+                }
+                _ => {}
+            }
+            continue;
+        };
+        let mut fields = record.split('|');
+        let commands: u32 = fields.next().unwrap_or_default().parse().unwrap_or(0);
+        if commands & FM2_RESET != 0 && !inputs.is_empty() {
+            bail!(
+                "{} resets the console on frame {}, which can't be replayed",
+                path.display(),
+                inputs.len()
+            );
+        }
+        let pad = fields.next().unwrap_or_default();
+        let buttons = FM2_BUTTONS
+            .iter()
+            .zip(pad.chars())
+            .filter(|(_, state)| *state != '.' && *state != ' ')
+            .fold(0, |mask, (bit, _)| mask | bit);
+        inputs.push(buttons);
+    }
+    Ok(inputs)
+}
@@ -1,29 +1,84 @@
 use std::env;
+use std::ffi::{CString, c_char, c_int, c_void};
 use std::fs;
 use std::io::{Cursor, Read};
 use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+use std::time::Duration;
 
-use anyhow::{Context, Result, anyhow, bail};
+use anyhow::{Context, Result, anyhow, bail, ensure};
 use dirs::{cache_dir, config_dir};
 use flate2::read::GzDecoder;
-use libloading::library_filename;
-use log::{debug, info};
+use libloading::{Library, library_filename};
+use log::{debug, info, warn};
 use mupen64plus::{Core, Plugin};
+use mupen64plus_sys::*;
+use sdl2::keyboard::Scancode;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use tar::Archive;
 
-pub fn run(rom_path: &Path, scale: u32, limit_fps: bool) -> Result<()> {
-    let mut rom = fs::read(rom_path)
-        .with_context(|| format!("failed to read Nintendo 64 ROM {}", rom_path.display()))?;
+use crate::config::{CONFIG_PATH, Config, N64Config};
+use crate::controller::ControllerManager;
+use crate::n64_dd::{self, DiskDrive};
+use crate::n64_input::{self, Pak};
+use crate::n64_video::{self, Session};
+use crate::present;
+use crate::resume;
+use crate::saves;
+use crate::systems::GameSystem;
+
+/// `M64CMD_STATE_SAVE` format of the core's own save states
+const STATE_FORMAT_M64P: c_int = 1;
+/// First word of a ROM header in the cartridge's big-endian byte order
+const Z64_MAGIC: [u8; 4] = [0x80, 0x37, 0x12, 0x40];
+
+const GLIDEN64_SECTION: &str = "Video-GLideN64";
+
+/// Width and height of the N64 picture at `--scale 1`
+const BASE_WIDTH: u32 = 320;
+const BASE_HEIGHT: u32 = 240;
+
+pub fn run(
+    rom_path: &Path,
+    scale: u32,
+    limit_fps: bool,
+    load_state: Option<PathBuf>,
+    pak: Pak,
+    disk: Option<PathBuf>,
+    config: &Config,
+) -> Result<()> {
+    present::unsupported_rotation("the Nintendo 64 renderer");
+    // a disk without a cartridge boots from the IPL ROM in the 64DD drive
+    let drive = match (n64_dd::is_disk_image(rom_path), disk) {
+        (true, _) => Some(DiskDrive::new(rom_path)?),
+        (false, Some(disk)) => Some(DiskDrive::new(&disk)?),
+        (false, None) => None,
+    };
+    let cartridge = match &drive {
+        Some(drive) if n64_dd::is_disk_image(rom_path) => &drive.ipl,
+        _ => rom_path,
+    };
+    let mut rom = fs::read(cartridge)
+        .with_context(|| format!("failed to read Nintendo 64 ROM {}", cartridge.display()))?;
+    normalize_byte_order(&mut rom);
+    let game_id = game_id(&rom);
+    match &game_id {
+        Some(id) => info!("Nintendo 64 cartridge ID {id}"),
+        None => debug!("No cartridge ID in the header of {}", rom_path.display()),
+    }
     let bundled = ensure_bundled_assets()?;
     let core_setup = load_core(bundled.as_ref())?;
     let plugin_dirs = plugin_search_dirs(core_setup.library_dir.as_deref(), bundled.as_ref());
-    let plugins = resolve_plugins(&plugin_dirs)?;
+    let plugins = resolve_plugins(&plugin_dirs, &config.n64, game_id.as_deref())?;
     let config_dir = ensure_config_dir()?;
     let data_dir = resolve_data_dir(core_setup.library_dir.as_deref(), bundled.as_ref());
-
-    if scale != 4 || !limit_fps {
-        debug!("Nintendo 64 core uses the video plugin's window; --scale/--limit-fps are ignored");
-    }
+    let label = rom_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("n64")
+        .to_string();
 
     if let Some(dir) = &config_dir {
         debug!("Using Mupen64Plus config directory at {}", dir.display());
@@ -46,6 +101,50 @@ pub fn run(rom_path: &Path, scale: u32, limit_fps: bool) -> Result<()> {
         .map_err(|err| anyhow!(err))
         .context("failed to start Mupen64Plus core")?;
 
+    let api = Rc::new(CoreApi::load(&core_setup.library_path)?);
+    let scale = scale.max(1);
+    api.set_config_int("Video-General", "ScreenWidth", (BASE_WIDTH * scale) as i32)?;
+    api.set_config_int(
+        "Video-General",
+        "ScreenHeight",
+        (BASE_HEIGHT * scale) as i32,
+    )?;
+    configure_texture_packs(&api, &config.n64, &plugins.video)?;
+    // kept open while the game runs, SDL shuts down with the last handle
+    let sdl = sdl2::init().map_err(|err| anyhow!(err))?;
+    let controllers = ControllerManager::new(&sdl, &config.controllers)?;
+    let pak = n64_input::configure(&api, &controllers, pak)?;
+    // cartridge saves and Controller Paks, the core names them after the game
+    let save_dir = env::current_dir()?.join(saves::prepare(GameSystem::N64)?);
+    // the core appends file names to the path as is
+    let sram_path = format!("{}{}", save_dir.display(), std::path::MAIN_SEPARATOR);
+    api.set_config_string("Core", "SaveSRAMPath", &sram_path)?;
+    if let Some(drive) = drive {
+        n64_dd::install(&api, drive)?;
+    }
+    // a state asked for on the command line wins over the one left by the last session
+    let load_state = load_state.or_else(|| resume::to_load(GameSystem::N64, rom_path));
+    if let Some(path) = &load_state {
+        ensure!(
+            path.is_file(),
+            "save state {} does not exist",
+            path.display()
+        );
+    }
+    n64_video::install(
+        api.clone(),
+        Session {
+            title: format!("{label} - Nintendo 64"),
+            label: label.clone(),
+            limit_fps,
+            load_state,
+            resume: resume::to_save(GameSystem::N64, rom_path),
+            frame: frame_time(game_id.as_deref()),
+            pak,
+            display: config.display.clone(),
+        },
+    )?;
+
     mupen
         .open_rom(&mut rom)
         .map_err(|err| anyhow!(err))
@@ -62,21 +161,57 @@ pub fn run(rom_path: &Path, scale: u32, limit_fps: bool) -> Result<()> {
     }
 
     info!(
-        "Nintendo 64 core running for {}; close the plugin window or press Esc inside it to exit",
+        "Nintendo 64 core running for {}; press Esc or close the window to exit, F12 saves a screenshot",
         rom_path
             .file_name()
             .and_then(|s| s.to_str())
             .unwrap_or("ROM")
     );
-    mupen
+    // the core writes its saves itself, they only get their backups once it is done
+    let core_saves = saves::CoreSaves::snapshot(&save_dir);
+    let result = mupen
         .execute()
         .map_err(|err| anyhow!(err))
-        .context("mupen64plus execution failed")?;
+        .context("mupen64plus execution failed");
+    core_saves.finish();
+    result
+}
+
+/// Points GLideN64 at the `texture_packs` directory, other video plugins have no hi-res
+/// texture support
+fn configure_texture_packs(api: &CoreApi, config: &N64Config, video_plugin: &Path) -> Result<()> {
+    let Some(dir) = &config.texture_packs else {
+        return Ok(());
+    };
+    if !plugin_name(video_plugin).contains("GLideN64") {
+        warn!(
+            "Texture packs need the GLideN64 video plugin, {} ignores {}",
+            plugin_name(video_plugin),
+            dir.display()
+        );
+        return Ok(());
+    }
+    if !dir.is_dir() {
+        warn!("Texture pack directory {} does not exist", dir.display());
+    }
+    // GLideN64 resolves relative paths against its own working directory guess
+    let dir = env::current_dir()?.join(dir);
+    api.set_config_bool(GLIDEN64_SECTION, "txHiresEnable", true)?;
+    api.set_config_string(GLIDEN64_SECTION, "txPath", &dir.to_string_lossy())?;
+    api.set_config_bool(
+        GLIDEN64_SECTION,
+        "txHiresFullAlphaChannel",
+        config.texture_full_alpha,
+    )?;
+    api.set_config_bool(GLIDEN64_SECTION, "txHresAltCRC", config.texture_alt_crc)?;
+    info!("Loading hi-res textures from {}", dir.display());
     Ok(())
 }
 
 struct CoreSetup {
     core: Core,
+    /// Opened a second time for [`CoreApi`], dlopen hands back the same library
+    library_path: PathBuf,
     library_dir: Option<PathBuf>,
     origin_description: String,
 }
@@ -100,6 +235,7 @@ fn load_core(bundled: Option<&BundledAssets>) -> Result<CoreSetup> {
                 )
             })?;
         return Ok(CoreSetup {
+            library_path: bundle.core_path.clone(),
             library_dir: bundle.core_path.parent().map(|p| p.to_path_buf()),
             origin_description: format!("{} (auto-downloaded)", bundle.description),
             core,
@@ -113,6 +249,7 @@ fn load_core(bundled: Option<&BundledAssets>) -> Result<CoreSetup> {
         let dir = explicit.parent().map(|p| p.to_path_buf());
         return Ok(CoreSetup {
             core,
+            library_path: explicit.clone(),
             library_dir: dir,
             origin_description: format!("M64P_CORE_LIB ({})", explicit.display()),
         });
@@ -132,6 +269,7 @@ fn load_core(bundled: Option<&BundledAssets>) -> Result<CoreSetup> {
                         format!("failed to load core library from {}", candidate.display())
                     })?;
                 return Ok(CoreSetup {
+                    library_path: candidate.clone(),
                     library_dir: candidate.parent().map(|p| p.to_path_buf()),
                     origin_description: format!("M64P_ROOT ({})", candidate.display()),
                     core,
@@ -145,11 +283,202 @@ fn load_core(bundled: Option<&BundledAssets>) -> Result<CoreSetup> {
         .context("failed to locate libmupen64plus via system search paths")?;
     Ok(CoreSetup {
         core,
+        library_path: PathBuf::from(library_filename("mupen64plus")),
         library_dir: None,
         origin_description: "system library paths".into(),
     })
 }
 
+/// Core functions the `mupen64plus` crate keeps to itself
+pub struct CoreApi {
+    _library: Library,
+    do_command: unsafe extern "C" fn(m64p_command, c_int, *mut c_void) -> m64p_error,
+    override_vid_ext: unsafe extern "C" fn(*mut m64p_video_extension_functions) -> m64p_error,
+    config_open_section: unsafe extern "C" fn(*const c_char, *mut m64p_handle) -> m64p_error,
+    config_set_parameter:
+        unsafe extern "C" fn(m64p_handle, *const c_char, m64p_type, *const c_void) -> m64p_error,
+}
+
+impl CoreApi {
+    fn load(path: &Path) -> Result<Self> {
+        let library = unsafe { Library::new(path) }
+            .with_context(|| format!("failed to reopen core library {}", path.display()))?;
+        macro_rules! symbol {
+            ($ptr:ty, $name:literal) => {
+                unsafe { library.get::<$ptr>($name) }
+                    .ok()
+                    .and_then(|symbol| *symbol)
+                    .with_context(|| {
+                        format!(
+                            "core library is missing {}",
+                            String::from_utf8_lossy(&$name[..$name.len() - 1])
+                        )
+                    })?
+            };
+        }
+        Ok(Self {
+            do_command: symbol!(ptr_CoreDoCommand, b"CoreDoCommand\0"),
+            override_vid_ext: symbol!(ptr_CoreOverrideVidExt, b"CoreOverrideVidExt\0"),
+            config_open_section: symbol!(ptr_ConfigOpenSection, b"ConfigOpenSection\0"),
+            config_set_parameter: symbol!(ptr_ConfigSetParameter, b"ConfigSetParameter\0"),
+            _library: library,
+        })
+    }
+
+    /// Queues a save state of the running game to `path`, written at the end of the frame
+    pub fn save_state(&self, path: &Path) -> Result<()> {
+        self.state_command(m64p_command_M64CMD_STATE_SAVE, STATE_FORMAT_M64P, path)
+    }
+
+    /// Queues loading a save state in any format the core knows
+    pub fn load_state(&self, path: &Path) -> Result<()> {
+        self.state_command(m64p_command_M64CMD_STATE_LOAD, 0, path)
+    }
+
+    /// Stops the game, `execute` returns once the frame is done
+    pub fn stop(&self) -> Result<()> {
+        let result =
+            unsafe { (self.do_command)(m64p_command_M64CMD_STOP, 0, std::ptr::null_mut()) };
+        check(result).context("failed to stop the core")
+    }
+
+    /// Presses or releases a key for the input plugin, as if it came from the window
+    pub fn send_key(&self, scancode: Scancode, down: bool) -> Result<()> {
+        let command = if down {
+            m64p_command_M64CMD_SEND_SDL_KEYDOWN
+        } else {
+            m64p_command_M64CMD_SEND_SDL_KEYUP
+        };
+        let result = unsafe { (self.do_command)(command, scancode as c_int, std::ptr::null_mut()) };
+        check(result).context("failed to send a key to the core")
+    }
+
+    fn state_command(&self, command: m64p_command, param: c_int, path: &Path) -> Result<()> {
+        let path_name = CString::new(path.to_string_lossy().as_bytes())?;
+        // the core copies the file name
+        let result =
+            unsafe { (self.do_command)(command, param, path_name.as_ptr() as *mut c_void) };
+        check(result).with_context(|| format!("failed to queue save state {}", path.display()))
+    }
+
+    /// Changes a core parameter of the running game, such as the speed limiter
+    pub fn set_state(&self, param: m64p_core_param, value: c_int) -> Result<()> {
+        let mut value = value;
+        let result = unsafe {
+            (self.do_command)(
+                m64p_command_M64CMD_CORE_STATE_SET,
+                param as c_int,
+                (&mut value as *mut c_int).cast(),
+            )
+        };
+        check(result)
+    }
+
+    /// Sets the callbacks the core asks for 64DD and Transfer Pak media with, the core
+    /// copies the table
+    pub fn set_media_loader(&self, loader: &mut m64p_media_loader) -> Result<()> {
+        let result = unsafe {
+            (self.do_command)(
+                m64p_command_M64CMD_SET_MEDIA_LOADER,
+                std::mem::size_of::<m64p_media_loader>() as c_int,
+                (loader as *mut m64p_media_loader).cast(),
+            )
+        };
+        check(result).context("the core has no 64DD support, Mupen64Plus 2.6 or newer is needed")
+    }
+
+    pub fn override_video_extension(
+        &self,
+        functions: &mut m64p_video_extension_functions,
+    ) -> Result<()> {
+        check(unsafe { (self.override_vid_ext)(functions) })
+    }
+
+    pub fn set_config_int(&self, section: &str, name: &str, value: i32) -> Result<()> {
+        let value: c_int = value;
+        self.set_config(
+            section,
+            name,
+            m64p_type_M64TYPE_INT,
+            (&value as *const c_int).cast(),
+        )
+    }
+
+    pub fn set_config_float(&self, section: &str, name: &str, value: f32) -> Result<()> {
+        self.set_config(
+            section,
+            name,
+            m64p_type_M64TYPE_FLOAT,
+            (&value as *const f32).cast(),
+        )
+    }
+
+    pub fn set_config_bool(&self, section: &str, name: &str, value: bool) -> Result<()> {
+        let value = value as c_int;
+        self.set_config(
+            section,
+            name,
+            m64p_type_M64TYPE_BOOL,
+            (&value as *const c_int).cast(),
+        )
+    }
+
+    pub fn set_config_string(&self, section: &str, name: &str, value: &str) -> Result<()> {
+        let value = CString::new(value)?;
+        self.set_config(
+            section,
+            name,
+            m64p_type_M64TYPE_STRING,
+            value.as_ptr().cast(),
+        )
+    }
+
+    /// Sets a `mupen64plus.cfg` value for this session, the file is only written when
+    /// the core saves it
+    fn set_config(
+        &self,
+        section: &str,
+        name: &str,
+        kind: m64p_type,
+        value: *const c_void,
+    ) -> Result<()> {
+        let section_name = CString::new(section)?;
+        let param_name = CString::new(name)?;
+        let mut handle: m64p_handle = std::ptr::null_mut();
+        unsafe {
+            check((self.config_open_section)(
+                section_name.as_ptr(),
+                &mut handle,
+            ))?;
+            check((self.config_set_parameter)(
+                handle,
+                param_name.as_ptr(),
+                kind,
+                value,
+            ))
+        }
+        .with_context(|| format!("failed to set Mupen64Plus config {section}/{name}"))
+    }
+}
+
+fn check(result: m64p_error) -> Result<()> {
+    if result == m64p_error_M64ERR_SUCCESS {
+        Ok(())
+    } else {
+        Err(anyhow!(mupen64plus::Error::from(result)))
+    }
+}
+
+/// `states/n64/<game>.st<slot>`
+pub fn state_path(label: &str, slot: u32) -> PathBuf {
+    saves::state_dir(GameSystem::N64).join(format!("{label}.st{slot}"))
+}
+
+/// `states/n64/<game>.resume`, written on exit and loaded next time with auto-resume
+pub fn resume_path(label: &str) -> PathBuf {
+    saves::state_dir(GameSystem::N64).join(format!("{label}.resume"))
+}
+
 struct PluginSet {
     video: PathBuf,
     audio: PathBuf,
@@ -168,11 +497,85 @@ impl PluginSet {
     }
 }
 
-fn resolve_plugins(search_dirs: &[PathBuf]) -> Result<PluginSet> {
-    let video = find_plugin("video", "M64P_VIDEO", VIDEO_PLUGIN_CANDIDATES, search_dirs)?;
-    let audio = find_plugin("audio", "M64P_AUDIO", AUDIO_PLUGIN_CANDIDATES, search_dirs)?;
-    let input = find_plugin("input", "M64P_INPUT", INPUT_PLUGIN_CANDIDATES, search_dirs)?;
-    let rsp = find_plugin("RSP", "M64P_RSP", RSP_PLUGIN_CANDIDATES, search_dirs)?;
+/// Converts byteswapped (.v64) and little-endian (.n64) dumps to the cartridge's
+/// big-endian (.z64) layout, detected from the first word of the header. The file
+/// extension is ignored since dumps are often renamed.
+fn normalize_byte_order(rom: &mut [u8]) {
+    match rom.first_chunk::<4>() {
+        Some(&Z64_MAGIC) => {}
+        Some([0x37, 0x80, 0x40, 0x12]) => {
+            debug!("Converting byteswapped (.v64) ROM to big-endian");
+            rom.chunks_exact_mut(2).for_each(|half| half.swap(0, 1));
+        }
+        Some([0x40, 0x12, 0x37, 0x80]) => {
+            debug!("Converting little-endian (.n64) ROM to big-endian");
+            rom.chunks_exact_mut(4).for_each(|word| word.reverse());
+        }
+        _ => warn!("Unknown Nintendo 64 ROM header, loading it as is"),
+    }
+}
+
+/// The 4 character cartridge ID at 0x3B in the header, like `NSME` for the US release of
+/// Super Mario 64
+fn game_id(rom: &[u8]) -> Option<String> {
+    if rom.first_chunk::<4>()? != &Z64_MAGIC {
+        return None;
+    }
+    let id = rom.get(0x3B..0x3F)?;
+    id.iter()
+        .all(u8::is_ascii_alphanumeric)
+        .then(|| String::from_utf8_lossy(id).into_owned())
+}
+
+/// How long a frame lasts in the game's region, the last letter of its ID. PAL cartridges
+/// run at 50 Hz, the rest at 60.
+fn frame_time(game_id: Option<&str>) -> Duration {
+    let pal = game_id
+        .and_then(|id| id.bytes().last())
+        .is_some_and(|country| b"DFIPSUXY".contains(&country));
+    Duration::from_secs_f64(if pal { 1.0 / 50.0 } else { 1.0 / 60.0 })
+}
+
+/// Environment overrides win over `config.toml`, where a `[n64.games.<id>]` table wins
+/// over the `[n64]` section
+fn resolve_plugins(
+    search_dirs: &[PathBuf],
+    config: &N64Config,
+    game_id: Option<&str>,
+) -> Result<PluginSet> {
+    let video = find_plugin(
+        "video",
+        "M64P_VIDEO",
+        config.video(game_id),
+        VIDEO_PLUGIN_CANDIDATES,
+        search_dirs,
+    )?;
+    let audio = find_plugin(
+        "audio",
+        "M64P_AUDIO",
+        None,
+        AUDIO_PLUGIN_CANDIDATES,
+        search_dirs,
+    )?;
+    let input = find_plugin(
+        "input",
+        "M64P_INPUT",
+        None,
+        INPUT_PLUGIN_CANDIDATES,
+        search_dirs,
+    )?;
+    let rsp = find_plugin(
+        "RSP",
+        "M64P_RSP",
+        config.rsp(game_id),
+        RSP_PLUGIN_CANDIDATES,
+        search_dirs,
+    )?;
+    info!(
+        "Nintendo 64 plugins: {} video, {} RSP",
+        plugin_name(&video),
+        plugin_name(&rsp)
+    );
     Ok(PluginSet {
         video,
         audio,
@@ -249,9 +652,18 @@ fn plugin_search_dirs(core_dir: Option<&Path>, bundled: Option<&BundledAssets>)
     dirs
 }
 
+fn plugin_name(path: &Path) -> String {
+    path.file_stem().map_or_else(
+        || path.display().to_string(),
+        |stem| stem.to_string_lossy().into_owned(),
+    )
+}
+
+/// `configured` is a plugin name looked up like the candidates, or a path to the library
 fn find_plugin(
     kind: &str,
     env_var: &str,
+    configured: Option<&str>,
     candidates: &[&str],
     search_dirs: &[PathBuf],
 ) -> Result<PathBuf> {
@@ -268,6 +680,33 @@ fn find_plugin(
         }
     }
 
+    if let Some(configured) = configured {
+        let path = Path::new(configured);
+        if path.components().count() > 1 {
+            ensure!(
+                path.exists(),
+                "{kind} plugin {} from {CONFIG_PATH} does not exist",
+                path.display()
+            );
+            return Ok(path.to_path_buf());
+        }
+        return search_plugin(&[configured], search_dirs).map_err(|attempted| {
+            anyhow!(
+                "unable to locate {kind} plugin {configured} from {CONFIG_PATH} (looked in: {attempted})"
+            )
+        });
+    }
+
+    search_plugin(candidates, search_dirs).map_err(|attempted| {
+        anyhow!(
+            "unable to locate {kind} plugin; set {env_var} or install one of {:?} (looked in: {attempted})",
+            candidates
+        )
+    })
+}
+
+/// The first candidate found in the search directories, or the paths that were tried
+fn search_plugin(candidates: &[&str], search_dirs: &[PathBuf]) -> Result<PathBuf, String> {
     let mut attempts = Vec::new();
     for dir in search_dirs {
         for candidate in candidates {
@@ -286,10 +725,7 @@ fn find_plugin(
         .map(|p| p.display().to_string())
         .collect::<Vec<_>>()
         .join(", ");
-    bail!(
-        "unable to locate {kind} plugin; set {env_var} or install one of {:?} (looked in: {attempted})",
-        candidates
-    );
+    Err(attempted)
 }
 
 fn candidate_filenames(candidate: &str) -> Vec<PathBuf> {
@@ -410,48 +846,142 @@ const RSP_PLUGIN_CANDIDATES: &[&str] = &[
     "mupen64plus-rsp-z64",
 ];
 
+/// Official Mupen64Plus release bundles, only built for x86_64
+const BUNDLE_VERSION: &str = "2.5.9";
+const BUNDLE_DOWNLOAD_URL: &str =
+    "https://github.com/Mupen64Plus/mupen64plus-core/releases/download/2.5.9";
+const BUNDLE_RELEASE_API: &str =
+    "https://api.github.com/repos/mupen64plus/mupen64plus-core/releases/tags/2.5.9";
+const BUNDLES: &[Bundle] = &[
+    Bundle {
+        os: "linux",
+        platform: "linux64",
+        format: ArchiveFormat::TarGz,
+        core_file: "libmupen64plus.so",
+    },
+    Bundle {
+        os: "windows",
+        platform: "win64",
+        format: ArchiveFormat::Zip,
+        core_file: "mupen64plus.dll",
+    },
+    Bundle {
+        os: "macos",
+        platform: "osx64",
+        format: ArchiveFormat::Dmg,
+        core_file: "libmupen64plus.dylib",
+    },
+];
+
+struct Bundle {
+    /// `std::env::consts::OS` the bundle runs on
+    os: &'static str,
+    platform: &'static str,
+    format: ArchiveFormat,
+    core_file: &'static str,
+}
+
+#[derive(Clone, Copy)]
+enum ArchiveFormat {
+    TarGz,
+    Zip,
+    /// Disk image, mounted with `hdiutil`
+    Dmg,
+}
+
+impl Bundle {
+    fn asset_name(&self) -> String {
+        let extension = match self.format {
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::Dmg => "dmg",
+        };
+        format!(
+            "mupen64plus-bundle-{}-{BUNDLE_VERSION}.{extension}",
+            self.platform
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct Release {
+    assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Deserialize)]
+struct ReleaseAsset {
+    name: String,
+    /// `sha256:<hex>`, GitHub only publishes it for some assets
+    digest: Option<String>,
+}
+
 fn ensure_bundled_assets() -> Result<Option<BundledAssets>> {
-    #[cfg(target_os = "linux")]
-    {
-        const VERSION: &str = "2.5.9";
-        const DIR_NAME: &str = "mupen64plus-bundle-linux64-2.5.9";
-        const URL: &str = "https://github.com/Mupen64Plus/mupen64plus-core/releases/download/2.5.9/mupen64plus-bundle-linux64-2.5.9.tar.gz";
-
-        let cache_root = cache_dir()
-            .unwrap_or_else(|| PathBuf::from("target/cache"))
-            .join("retro-launcher")
-            .join("mupen64plus")
-            .join("linux64")
-            .join(VERSION);
-        let extracted = cache_root.join(DIR_NAME);
-        let core_path = extracted.join("libmupen64plus.so");
-        if !core_path.exists() {
-            info!(
-                "Downloading bundled Mupen64Plus {} (once per machine)...",
-                VERSION
-            );
-            download_and_extract(URL, &cache_root)
-                .context("failed to bootstrap bundled Mupen64Plus")?;
-        }
-        if core_path.exists() {
-            return Ok(Some(BundledAssets {
-                core_path,
-                plugin_dir: extracted.clone(),
-                data_dir: extracted,
-                description: format!("Mupen64Plus bundle {}", VERSION),
-            }));
-        }
-        Ok(None)
+    let Some(bundle) = BUNDLES
+        .iter()
+        .find(|bundle| bundle.os == env::consts::OS && env::consts::ARCH == "x86_64")
+    else {
+        return Ok(None);
+    };
+
+    let cache_root = cache_dir()
+        .unwrap_or_else(|| PathBuf::from("target/cache"))
+        .join("retro-launcher")
+        .join("mupen64plus")
+        .join(bundle.platform)
+        .join(BUNDLE_VERSION);
+    let mut core_path = find_file(&cache_root, bundle.core_file);
+    if core_path.is_none() {
+        info!(
+            "Downloading bundled Mupen64Plus {} (once per machine)...",
+            BUNDLE_VERSION
+        );
+        download_and_extract(bundle, &cache_root)
+            .context("failed to bootstrap bundled Mupen64Plus")?;
+        core_path = find_file(&cache_root, bundle.core_file);
     }
-    #[cfg(not(target_os = "linux"))]
-    {
-        Ok(None)
+    let Some(core_path) = core_path else {
+        warn!(
+            "Mupen64Plus bundle {} has no {}",
+            bundle.asset_name(),
+            bundle.core_file
+        );
+        return Ok(None);
+    };
+
+    // the archives differ in layout, the plugins sit next to the core everywhere
+    let plugin_dir = core_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| cache_root.clone());
+    let data_dir = find_file(&cache_root, "mupen64plus.ini")
+        .and_then(|ini| ini.parent().map(Path::to_path_buf))
+        .unwrap_or_else(|| plugin_dir.clone());
+    Ok(Some(BundledAssets {
+        core_path,
+        plugin_dir,
+        data_dir,
+        description: format!("Mupen64Plus bundle {BUNDLE_VERSION} ({})", bundle.platform),
+    }))
+}
+
+/// Depth-first search for a file by name
+fn find_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    let mut entries: Vec<_> = fs::read_dir(dir).ok()?.flatten().collect();
+    entries.sort_by_key(|entry| entry.file_name());
+    if let Some(entry) = entries.iter().find(|entry| entry.file_name() == name) {
+        return Some(entry.path());
     }
+    entries
+        .iter()
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .find_map(|entry| find_file(&entry.path(), name))
 }
 
-fn download_and_extract(url: &str, dest: &Path) -> Result<()> {
+fn download_and_extract(bundle: &Bundle, dest: &Path) -> Result<()> {
     fs::create_dir_all(dest)?;
-    let response = ureq::get(url)
+    let asset = bundle.asset_name();
+    let url = format!("{BUNDLE_DOWNLOAD_URL}/{asset}");
+    let response = ureq::get(&url)
         .call()
         .map_err(|err| anyhow!("failed to download {}: {}", url, err))?;
     let mut bytes = Vec::new();
@@ -459,11 +989,109 @@ fn download_and_extract(url: &str, dest: &Path) -> Result<()> {
         .into_reader()
         .read_to_end(&mut bytes)
         .context("failed to read downloaded bundle")?;
-    let cursor = Cursor::new(bytes);
-    let decoder = GzDecoder::new(cursor);
-    let mut archive = Archive::new(decoder);
-    archive
-        .unpack(dest)
-        .context("failed to extract Mupen64Plus bundle")?;
+    verify_checksum(&asset, &bytes)?;
+
+    match bundle.format {
+        ArchiveFormat::TarGz => Archive::new(GzDecoder::new(Cursor::new(bytes)))
+            .unpack(dest)
+            .context("failed to extract Mupen64Plus bundle")?,
+        ArchiveFormat::Zip => zip::ZipArchive::new(Cursor::new(bytes))
+            .and_then(|mut archive| archive.extract(dest))
+            .context("failed to extract Mupen64Plus bundle")?,
+        ArchiveFormat::Dmg => extract_dmg(&bytes, &asset, dest)?,
+    }
+    Ok(())
+}
+
+/// Checks the download against `M64P_BUNDLE_SHA256` or the SHA-256 digest GitHub
+/// publishes for the release asset. Without either there is nothing to check against,
+/// which is only logged so older releases without digests still install.
+fn verify_checksum(asset: &str, bytes: &[u8]) -> Result<()> {
+    let expected = match env::var("M64P_BUNDLE_SHA256") {
+        Ok(digest) if !digest.is_empty() => Some(digest.trim().to_ascii_lowercase()),
+        _ => published_digest(asset).unwrap_or_else(|err| {
+            warn!("Could not fetch the checksum of {asset}: {err:#}");
+            None
+        }),
+    };
+    let actual = format!("{:x}", Sha256::digest(bytes));
+    match expected {
+        Some(expected) if expected == actual => {
+            debug!("{asset} matches SHA-256 {actual}");
+            Ok(())
+        }
+        Some(expected) => bail!("{asset} has SHA-256 {actual}, expected {expected}"),
+        None => {
+            warn!(
+                "No checksum published for {asset} (SHA-256 {actual}); set M64P_BUNDLE_SHA256 to pin it"
+            );
+            Ok(())
+        }
+    }
+}
+
+fn published_digest(asset: &str) -> Result<Option<String>> {
+    let body = ureq::get(BUNDLE_RELEASE_API)
+        .set("User-Agent", "retro-launcher")
+        .call()
+        .map_err(|err| anyhow!("failed to query {}: {}", BUNDLE_RELEASE_API, err))?
+        .into_string()
+        .context("failed to read release metadata")?;
+    let release: Release =
+        serde_json::from_str(&body).context("failed to parse release metadata")?;
+    Ok(release
+        .assets
+        .into_iter()
+        .find(|candidate| candidate.name == asset)
+        .and_then(|candidate| candidate.digest)
+        .and_then(|digest| {
+            digest
+                .strip_prefix("sha256:")
+                .map(|hex| hex.to_ascii_lowercase())
+        }))
+}
+
+/// Copies the contents of a disk image, which only macOS can mount
+fn extract_dmg(bytes: &[u8], asset: &str, dest: &Path) -> Result<()> {
+    let image = dest.join(asset);
+    let mount_point = dest.join("mount");
+    fs::write(&image, bytes).with_context(|| format!("failed to write {}", image.display()))?;
+    fs::create_dir_all(&mount_point)?;
+    let status = Command::new("hdiutil")
+        .arg("attach")
+        .args(["-nobrowse", "-readonly", "-mountpoint"])
+        .arg(&mount_point)
+        .arg(&image)
+        .status()
+        .context("failed to run hdiutil")?;
+    ensure!(
+        status.success(),
+        "hdiutil could not mount {}",
+        image.display()
+    );
+
+    let copied = copy_dir(&mount_point, dest);
+    let _ = Command::new("hdiutil")
+        .arg("detach")
+        .arg(&mount_point)
+        .status();
+    let _ = fs::remove_dir(&mount_point);
+    let _ = fs::remove_file(&image);
+    copied.context("failed to copy the Mupen64Plus disk image")
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        let kind = entry.file_type()?;
+        if kind.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if kind.is_file() {
+            fs::copy(entry.path(), &target)?;
+        }
+        // the image's link to /Applications is skipped
+    }
     Ok(())
 }
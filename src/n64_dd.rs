@@ -0,0 +1,138 @@
+use std::cell::RefCell;
+use std::env;
+use std::ffi::{CString, c_char, c_int, c_void};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use anyhow::{Result, bail, ensure};
+use log::{debug, info};
+use mupen64plus_sys::*;
+
+use crate::n64::CoreApi;
+
+/// Retail and development IPL dumps are 4 MiB
+const IPL_SIZE: u64 = 4 * 1024 * 1024;
+const IPL_EXTENSIONS: [&str; 4] = ["n64", "z64", "v64", "bin"];
+
+/// The IPL ROM and disk the core asks for when the game is opened
+pub struct DiskDrive {
+    pub ipl: PathBuf,
+    ipl_name: CString,
+    disk_name: CString,
+}
+
+thread_local! {
+    /// Read by the media loader callbacks, which get no useful context from the core
+    static DRIVE: RefCell<Option<DiskDrive>> = const { RefCell::new(None) };
+}
+
+/// `.ndd` disk images boot without a cartridge
+pub fn is_disk_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("ndd"))
+}
+
+impl DiskDrive {
+    pub fn new(disk: &Path) -> Result<Self> {
+        ensure!(
+            disk.is_file(),
+            "64DD disk {} does not exist",
+            disk.display()
+        );
+        let ipl = find_ipl()?;
+        info!("Using 64DD IPL ROM {}", ipl.display());
+        Ok(Self {
+            ipl_name: CString::new(ipl.to_string_lossy().as_bytes())?,
+            disk_name: CString::new(disk.to_string_lossy().as_bytes())?,
+            ipl,
+        })
+    }
+}
+
+/// Hands the drive to the core through its media loader, the core asks for the IPL ROM
+/// and disk when the game is opened
+pub fn install(core: &CoreApi, drive: DiskDrive) -> Result<()> {
+    DRIVE.set(Some(drive));
+    let mut loader = m64p_media_loader {
+        cb_data: ptr::null_mut(),
+        get_gb_cart_rom: Some(no_gb_cart),
+        get_gb_cart_ram: Some(no_gb_cart),
+        set_dd_rom_region: Some(set_dd_rom_region),
+        get_dd_rom: Some(get_dd_rom),
+        get_dd_disk: Some(get_dd_disk),
+    };
+    core.set_media_loader(&mut loader)
+}
+
+/// `N64DD_IPL` when set, otherwise the first 4 MiB dump under `bios/n64dd/`
+fn find_ipl() -> Result<PathBuf> {
+    if let Some(path) = env::var_os("N64DD_IPL").filter(|value| !value.is_empty()) {
+        let path = PathBuf::from(path);
+        ensure!(
+            path.is_file(),
+            "N64DD_IPL points to {}, which does not exist",
+            path.display()
+        );
+        return Ok(path);
+    }
+
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    for base in [PathBuf::from("bios/n64dd"), manifest_dir.join("bios/n64dd")] {
+        let Ok(entries) = fs::read_dir(&base) else {
+            continue;
+        };
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| {
+                        IPL_EXTENSIONS
+                            .iter()
+                            .any(|known| ext.eq_ignore_ascii_case(known))
+                    })
+                    && fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.len() == IPL_SIZE)
+            })
+            .collect();
+        files.sort();
+        if let Some(first) = files.into_iter().next() {
+            return Ok(first);
+        }
+    }
+    bail!(
+        "64DD IPL ROM not found. Set N64DD_IPL or place the retail IPL dump (4 MiB) under bios/n64dd/."
+    )
+}
+
+/// Copies `name` into memory the core releases with `free`
+fn core_string(name: impl FnOnce(&DiskDrive) -> *const c_char) -> *mut c_char {
+    DRIVE.with_borrow(|drive| match drive {
+        Some(drive) => unsafe { strdup(name(drive)) },
+        None => ptr::null_mut(),
+    })
+}
+
+unsafe extern "C" {
+    #[cfg_attr(windows, link_name = "_strdup")]
+    fn strdup(text: *const c_char) -> *mut c_char;
+}
+
+unsafe extern "C" fn get_dd_rom(_: *mut c_void) -> *mut c_char {
+    core_string(|drive| drive.ipl_name.as_ptr())
+}
+
+unsafe extern "C" fn get_dd_disk(_: *mut c_void) -> *mut c_char {
+    core_string(|drive| drive.disk_name.as_ptr())
+}
+
+unsafe extern "C" fn set_dd_rom_region(_: *mut c_void, region: u8) {
+    debug!("64DD disk asks for the IPL ROM of system type {region}");
+}
+
+/// No Transfer Pak support, the core leaves the slot empty
+unsafe extern "C" fn no_gb_cart(_: *mut c_void, _: c_int) -> *mut c_char {
+    ptr::null_mut()
+}
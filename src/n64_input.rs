@@ -0,0 +1,315 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use log::{debug, info};
+use sdl2::controller::{Axis, Button as SdlButton};
+use sdl2::keyboard::Scancode;
+
+use crate::controller::{ControllerManager, JoystickBinding, PadJoystick};
+use crate::n64::CoreApi;
+
+/// `mupen64plus-input-sdl` rewrites sections with another version from its defaults
+const INPUT_SDL_CONFIG_VERSION: f32 = 2.0;
+/// `mode` value that takes the mapping as written instead of auto-configuring the pad
+const MODE_MANUAL: i32 = 0;
+const KEYBOARD_ONLY: i32 = -1;
+/// `plugin` values for the accessory in the controller
+const PAK_NONE: i32 = 1;
+const PAK_MEMPAK: i32 = 2;
+const PAK_RUMBLE: i32 = 5;
+const PORTS: usize = 4;
+
+/// SDL 1.2 key codes, which the plugin still uses in its config
+mod key {
+    pub const ENTER: u32 = 13;
+    pub const E: u32 = 101;
+    pub const I: u32 = 105;
+    pub const J: u32 = 106;
+    pub const K: u32 = 107;
+    pub const L: u32 = 108;
+    pub const Q: u32 = 113;
+    pub const W: u32 = 119;
+    pub const X: u32 = 120;
+    pub const Z: u32 = 122;
+    pub const UP: u32 = 273;
+    pub const DOWN: u32 = 274;
+    pub const RIGHT: u32 = 275;
+    pub const LEFT: u32 = 276;
+    pub const F13: u32 = 294;
+    pub const F14: u32 = 295;
+}
+
+/// The accessory plugged into every controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Pak {
+    /// A Rumble Pak in controllers whose pad can rumble, a Controller Pak otherwise
+    Auto,
+    /// Controller Pak, saved with the game under `saves/n64/`
+    Memory,
+    /// Rumble Pak, played on the pad through SDL haptics
+    Rumble,
+    None,
+}
+
+/// What drives one N64 button: a keyboard key and a pad input
+struct Control {
+    name: &'static str,
+    key: Option<u32>,
+    pad: &'static [PadInput],
+}
+
+#[derive(Clone, Copy)]
+enum PadInput {
+    Button(SdlButton),
+    /// One direction of a stick or a trigger
+    Axis(Axis, bool),
+}
+
+/// The launcher's N64 layout, see the README controls section
+const CONTROLS: &[Control] = &[
+    Control {
+        name: "DPad R",
+        key: Some(key::RIGHT),
+        pad: &[PadInput::Button(SdlButton::DPadRight)],
+    },
+    Control {
+        name: "DPad L",
+        key: Some(key::LEFT),
+        pad: &[PadInput::Button(SdlButton::DPadLeft)],
+    },
+    Control {
+        name: "DPad D",
+        key: Some(key::DOWN),
+        pad: &[PadInput::Button(SdlButton::DPadDown)],
+    },
+    Control {
+        name: "DPad U",
+        key: Some(key::UP),
+        pad: &[PadInput::Button(SdlButton::DPadUp)],
+    },
+    Control {
+        name: "Start",
+        key: Some(key::ENTER),
+        pad: &[PadInput::Button(SdlButton::Start)],
+    },
+    Control {
+        name: "Z Trig",
+        key: Some(key::E),
+        pad: &[
+            PadInput::Button(SdlButton::Back),
+            PadInput::Axis(Axis::TriggerLeft, true),
+        ],
+    },
+    Control {
+        name: "B Button",
+        key: Some(key::Z),
+        pad: &[PadInput::Button(SdlButton::X)],
+    },
+    Control {
+        name: "A Button",
+        key: Some(key::X),
+        pad: &[PadInput::Button(SdlButton::A)],
+    },
+    Control {
+        name: "C Button R",
+        key: Some(key::L),
+        pad: &[PadInput::Axis(Axis::RightX, true)],
+    },
+    Control {
+        name: "C Button L",
+        key: Some(key::J),
+        pad: &[PadInput::Axis(Axis::RightX, false)],
+    },
+    Control {
+        name: "C Button D",
+        key: Some(key::K),
+        pad: &[PadInput::Axis(Axis::RightY, true)],
+    },
+    Control {
+        name: "C Button U",
+        key: Some(key::I),
+        pad: &[PadInput::Axis(Axis::RightY, false)],
+    },
+    Control {
+        name: "R Trig",
+        key: Some(key::W),
+        pad: &[PadInput::Button(SdlButton::RightShoulder)],
+    },
+    Control {
+        name: "L Trig",
+        key: Some(key::Q),
+        pad: &[PadInput::Button(SdlButton::LeftShoulder)],
+    },
+    // keys no keyboard has, the window's pak hotkey presses them, see `switch_key`
+    Control {
+        name: "Mempak switch",
+        key: Some(key::F13),
+        pad: &[],
+    },
+    Control {
+        name: "Rumblepak switch",
+        key: Some(key::F14),
+        pad: &[],
+    },
+];
+
+/// The key that makes the plugin swap controller 1's accessory for `pak`, pulling the old one
+/// and inserting the new one a second later. `None` for no pak, which it cannot switch to.
+pub fn switch_key(pak: Pak) -> Option<Scancode> {
+    match pak {
+        Pak::Memory => Some(Scancode::F13),
+        Pak::Rumble => Some(Scancode::F14),
+        Pak::Auto | Pak::None => None,
+    }
+}
+
+/// Writes the launcher's controls into the `Input-SDL-Control1..4` sections for this
+/// session, so `mupen64plus-input-sdl` uses the same layout as every other system.
+/// Port 1 gets the keyboard and the first pad, ports 2-4 the next pads in connection
+/// order, and ports without a pad are unplugged. Returns the pak controller 1 starts with.
+pub fn configure(core: &CoreApi, controllers: &ControllerManager, pak: Pak) -> Result<Pak> {
+    let mut first_pak = pak;
+    for port in 0..PORTS {
+        let section = format!("Input-SDL-Control{}", port + 1);
+        let pad = controllers.pad_joystick(port);
+        let keyboard = port == 0;
+        let plugged = keyboard || pad.is_some();
+
+        core.set_config_float(&section, "version", INPUT_SDL_CONFIG_VERSION)?;
+        core.set_config_int(&section, "mode", MODE_MANUAL)?;
+        core.set_config_bool(&section, "plugged", plugged)?;
+        core.set_config_bool(&section, "mouse", false)?;
+        let pak = match pak {
+            Pak::Auto if pad.as_ref().is_some_and(|pad| pad.rumble) => Pak::Rumble,
+            Pak::Auto => Pak::Memory,
+            pak => pak,
+        };
+        if port == 0 {
+            first_pak = pak;
+        }
+        core.set_config_int(
+            &section,
+            "plugin",
+            match pak {
+                Pak::Memory | Pak::Auto => PAK_MEMPAK,
+                Pak::Rumble => PAK_RUMBLE,
+                Pak::None => PAK_NONE,
+            },
+        )?;
+        core.set_config_int(
+            &section,
+            "device",
+            pad.as_ref()
+                .map_or(KEYBOARD_ONLY, |pad| pad.device_index as i32),
+        )?;
+        core.set_config_string(
+            &section,
+            "name",
+            pad.as_ref().map_or("Keyboard", |pad| pad.name.as_str()),
+        )?;
+        if !plugged {
+            continue;
+        }
+
+        for control in CONTROLS {
+            let mut tokens = Vec::new();
+            if keyboard && let Some(key) = control.key {
+                tokens.push(format!("key({key})"));
+            }
+            if let Some(pad) = &pad {
+                tokens.extend(pad_tokens(pad, control.pad));
+            }
+            core.set_config_string(&section, control.name, &tokens.join(" "))?;
+        }
+
+        for (name, keys, axis) in [
+            ("X Axis", (key::LEFT, key::RIGHT), Axis::LeftX),
+            ("Y Axis", (key::UP, key::DOWN), Axis::LeftY),
+        ] {
+            let mut tokens = Vec::new();
+            if keyboard {
+                tokens.push(format!("key({},{})", keys.0, keys.1));
+            }
+            if let Some(binding) = pad.as_ref().and_then(|pad| pad.axis_binding(axis)) {
+                tokens.extend(stick_token(binding));
+            }
+            core.set_config_string(&section, name, &tokens.join(" "))?;
+        }
+
+        match &pad {
+            Some(pad) => info!(
+                "Nintendo 64 port {} uses {} ({pak:?} pak)",
+                port + 1,
+                pad.name
+            ),
+            None => debug!(
+                "Nintendo 64 port {} uses the keyboard ({pak:?} pak)",
+                port + 1
+            ),
+        }
+    }
+    Ok(first_pak)
+}
+
+/// The plugin reads at most one button, axis and hat per control
+fn pad_tokens(pad: &PadJoystick, inputs: &[PadInput]) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    for input in inputs {
+        let token = match *input {
+            PadInput::Button(button) => pad.button_binding(button).and_then(button_token),
+            PadInput::Axis(axis, positive) => pad
+                .axis_binding(axis)
+                .and_then(|binding| axis_direction_token(binding, positive)),
+        };
+        if let Some(token) = token {
+            let kind = token.split('(').next();
+            if !tokens.iter().any(|other| other.split('(').next() == kind) {
+                tokens.push(token);
+            }
+        }
+    }
+    tokens
+}
+
+fn button_token(binding: JoystickBinding) -> Option<String> {
+    match binding {
+        JoystickBinding::Button(index) => Some(format!("button({index})")),
+        JoystickBinding::Hat { index, mask } => {
+            let direction = match mask {
+                1 => "Up",
+                2 => "Right",
+                4 => "Down",
+                8 => "Left",
+                _ => return None,
+            };
+            Some(format!("hat({index} {direction})"))
+        }
+        JoystickBinding::Axis { .. } | JoystickBinding::HalfAxis { .. } => {
+            axis_direction_token(binding, true)
+        }
+    }
+}
+
+fn axis_direction_token(binding: JoystickBinding, positive: bool) -> Option<String> {
+    let (index, positive) = match binding {
+        JoystickBinding::Axis { index, inverted } => (index, positive != inverted),
+        JoystickBinding::HalfAxis { index, positive } => (index, positive),
+        JoystickBinding::Button(_) | JoystickBinding::Hat { .. } => {
+            return button_token(binding);
+        }
+    };
+    Some(format!("axis({index}{})", if positive { '+' } else { '-' }))
+}
+
+fn stick_token(binding: JoystickBinding) -> Option<String> {
+    match binding {
+        JoystickBinding::Axis {
+            index,
+            inverted: false,
+        } => Some(format!("axis({index}-,{index}+)")),
+        JoystickBinding::Axis {
+            index,
+            inverted: true,
+        } => Some(format!("axis({index}+,{index}-)")),
+        _ => None,
+    }
+}
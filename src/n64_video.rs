@@ -0,0 +1,821 @@
+use std::cell::RefCell;
+use std::ffi::{CStr, c_char, c_int, c_void};
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use log::{debug, info, warn};
+use mupen64plus_sys::*;
+use sdl2::keyboard::Scancode;
+use sdl2::sys as sdl_sys;
+use sdl2::video::{FullscreenType, GLContext, Window};
+
+use crate::config::DisplayConfig;
+use crate::focus_pause;
+use crate::n64::{self, CoreApi};
+use crate::n64_input::{self, Pak};
+use crate::perf_overlay::PerfOverlay;
+use crate::present;
+use crate::save_sync;
+use crate::screenshot;
+
+/// Number of callbacks in `m64p_video_extension_functions`
+const VIDEO_EXTENSION_FUNCTIONS: u32 = 17;
+/// Core hotkeys the launcher handles itself, unmapped so they don't fire twice
+const CORE_KEY_MAPPINGS: &[&str] = &[
+    "Kbd Mapping Screenshot",
+    "Kbd Mapping Save State",
+    "Kbd Mapping Load State",
+    "Kbd Mapping Increment Slot",
+    "Kbd Mapping Slot 0",
+    "Kbd Mapping Slot 1",
+    "Kbd Mapping Slot 2",
+    "Kbd Mapping Slot 3",
+    "Kbd Mapping Slot 4",
+    "Kbd Mapping Slot 5",
+    "Kbd Mapping Slot 6",
+    "Kbd Mapping Slot 7",
+    "Kbd Mapping Slot 8",
+    "Kbd Mapping Slot 9",
+];
+/// Frames Esc waits for the core to write the resume state before stopping anyway
+const RESUME_SAVE_FRAMES: u32 = 120;
+/// Frames the pak switch key is held, long enough for the plugin to poll it
+const PAK_SWITCH_FRAMES: u32 = 6;
+const HOTKEYS: &[(Scancode, Hotkey)] = &[
+    (Scancode::F12, Hotkey::Screenshot),
+    (Scancode::F3, Hotkey::PerfOverlay),
+    (Scancode::F5, Hotkey::SaveState),
+    (Scancode::F7, Hotkey::LoadState),
+    (Scancode::F6, Hotkey::SwitchPak),
+    (Scancode::Num0, Hotkey::Slot(0)),
+    (Scancode::Num1, Hotkey::Slot(1)),
+    (Scancode::Num2, Hotkey::Slot(2)),
+    (Scancode::Num3, Hotkey::Slot(3)),
+    (Scancode::Num4, Hotkey::Slot(4)),
+    (Scancode::Num5, Hotkey::Slot(5)),
+    (Scancode::Num6, Hotkey::Slot(6)),
+    (Scancode::Num7, Hotkey::Slot(7)),
+    (Scancode::Num8, Hotkey::Slot(8)),
+    (Scancode::Num9, Hotkey::Slot(9)),
+    (Scancode::Escape, Hotkey::Quit),
+];
+const GL_RGBA: u32 = 0x1908;
+const GL_UNSIGNED_BYTE: u32 = 0x1401;
+
+type ReadPixels = unsafe extern "C" fn(i32, i32, i32, i32, u32, u32, *mut c_void);
+
+thread_local! {
+    static EXTENSION: RefCell<Option<VideoExtension>> = const { RefCell::new(None) };
+}
+
+/// The window the video plugin renders into, replacing the core's own SDL window
+/// so the launcher owns the title, hotkeys and screenshots.
+///
+/// mupen64plus calls these functions on the thread running `execute`, so the state
+/// is thread local. The core's SDL event filter still sees every event first: it
+/// stops on Esc or when the window closes, forwards keys to the input plugin and
+/// resizes the plugin's output. Key presses never reach the event queue, which is
+/// why the launcher hotkeys are read from the keyboard state.
+struct VideoExtension {
+    core: Rc<CoreApi>,
+    session: Session,
+    state_slot: u32,
+    output: Option<Output>,
+    /// Frames waited so far for the resume state after Esc
+    stopping: Option<u32>,
+    /// The pak switch key held for the plugin and the frames left until it is released
+    pak_switch: Option<(Scancode, u32)>,
+}
+
+/// What the window needs to know about the running game
+pub struct Session {
+    pub title: String,
+    /// Names screenshots and save states
+    pub label: String,
+    pub limit_fps: bool,
+    /// A save state file loaded once the game runs
+    pub load_state: Option<PathBuf>,
+    /// The auto-resume state Esc writes before stopping the game. Set, Esc is taken from the
+    /// core, which would stop right away.
+    pub resume: Option<PathBuf>,
+    /// How long a frame lasts in the game's region
+    pub frame: Duration,
+    /// The accessory in controller 1, F6 swaps a Controller Pak and a Rumble Pak
+    pub pak: Pak,
+    /// `[display]`, whether the plugin's vsync turns adaptive
+    pub display: DisplayConfig,
+}
+
+#[derive(Clone, Copy)]
+enum Hotkey {
+    Screenshot,
+    PerfOverlay,
+    SaveState,
+    LoadState,
+    SwitchPak,
+    Slot(u32),
+    Quit,
+}
+
+struct Output {
+    _sdl: sdl2::Sdl,
+    video: sdl2::VideoSubsystem,
+    event_pump: sdl2::EventPump,
+    /// The context is dropped before its window
+    context: Option<GLContext>,
+    window: Option<Window>,
+    swap_interval: c_int,
+    display: DisplayConfig,
+    held_hotkeys: Vec<Scancode>,
+    screenshot_requested: bool,
+    /// Set on the first frame, some core commands only work once the game runs
+    started: bool,
+    /// Shown in the title, the plugin owns every pixel of the window
+    perf: PerfOverlay,
+}
+
+/// Hands the video extension to the core, must be called after the core started
+/// and before the video plugin is attached
+pub fn install(core: Rc<CoreApi>, session: Session) -> Result<()> {
+    // our screenshots and states replace the core's, which go to its own directories
+    for mapping in CORE_KEY_MAPPINGS {
+        core.set_config_int("CoreEvents", mapping, 0)?;
+    }
+    if session.resume.is_some() {
+        core.set_config_int("CoreEvents", "Kbd Mapping Stop", 0)?;
+    }
+
+    let mut functions = m64p_video_extension_functions {
+        Functions: VIDEO_EXTENSION_FUNCTIONS,
+        VidExtFuncInit: Some(init),
+        VidExtFuncQuit: Some(quit),
+        VidExtFuncListModes: Some(list_modes),
+        VidExtFuncListRates: Some(list_rates),
+        VidExtFuncSetMode: Some(set_mode),
+        VidExtFuncSetModeWithRate: Some(set_mode_with_rate),
+        VidExtFuncGLGetProc: Some(gl_get_proc),
+        VidExtFuncGLSetAttr: Some(gl_set_attr),
+        VidExtFuncGLGetAttr: Some(gl_get_attr),
+        VidExtFuncGLSwapBuf: Some(gl_swap_buf),
+        VidExtFuncSetCaption: Some(set_caption),
+        VidExtFuncToggleFS: Some(toggle_fullscreen),
+        VidExtFuncResizeWindow: Some(resize_window),
+        VidExtFuncGLGetDefaultFramebuffer: Some(gl_get_default_framebuffer),
+        VidExtFuncInitWithRenderMode: Some(init_with_render_mode),
+        VidExtFuncVKGetSurface: Some(vk_get_surface),
+        VidExtFuncVKGetInstanceExtensions: Some(vk_get_instance_extensions),
+    };
+    EXTENSION.with_borrow_mut(|extension| {
+        *extension = Some(VideoExtension {
+            core: core.clone(),
+            session,
+            state_slot: 0,
+            output: None,
+            stopping: None,
+            pak_switch: None,
+        })
+    });
+    // the core copies the table
+    core.override_video_extension(&mut functions)
+        .context("failed to hand the game window to the Nintendo 64 core")
+}
+
+/// Runs `f` on the open output, turning errors into a core error code. Must not be
+/// nested: pumping SDL events while the state is borrowed would let the core's event
+/// filter call back into the extension.
+fn with_output(
+    name: &str,
+    f: impl FnOnce(&mut VideoExtension, &mut Output) -> Result<()>,
+) -> m64p_error {
+    EXTENSION.with_borrow_mut(|extension| {
+        let Some(extension) = extension else {
+            return m64p_error_M64ERR_NOT_INIT;
+        };
+        let Some(mut output) = extension.output.take() else {
+            return m64p_error_M64ERR_NOT_INIT;
+        };
+        let result = f(extension, &mut output);
+        extension.output = Some(output);
+        match result {
+            Ok(()) => m64p_error_M64ERR_SUCCESS,
+            Err(err) => {
+                warn!("Nintendo 64 video {name} failed: {err:#}");
+                m64p_error_M64ERR_SYSTEM_FAIL
+            }
+        }
+    })
+}
+
+unsafe extern "C" fn init() -> m64p_error {
+    EXTENSION.with_borrow_mut(|extension| {
+        let Some(extension) = extension else {
+            return m64p_error_M64ERR_NOT_INIT;
+        };
+        match Output::new(extension.session.frame, &extension.session.display) {
+            Ok(output) => {
+                extension.output = Some(output);
+                m64p_error_M64ERR_SUCCESS
+            }
+            Err(err) => {
+                warn!("Nintendo 64 video init failed: {err:#}");
+                m64p_error_M64ERR_SYSTEM_FAIL
+            }
+        }
+    })
+}
+
+unsafe extern "C" fn init_with_render_mode(mode: m64p_render_mode) -> m64p_error {
+    if mode != m64p_render_mode_M64P_RENDER_OPENGL {
+        warn!("The Nintendo 64 video plugin asked for Vulkan, only OpenGL plugins are supported");
+        return m64p_error_M64ERR_UNSUPPORTED;
+    }
+    unsafe { init() }
+}
+
+unsafe extern "C" fn quit() -> m64p_error {
+    EXTENSION.with_borrow_mut(|extension| {
+        if let Some(extension) = extension {
+            extension.output = None;
+        }
+    });
+    m64p_error_M64ERR_SUCCESS
+}
+
+unsafe extern "C" fn list_modes(sizes: *mut m64p_2d_size, count: *mut c_int) -> m64p_error {
+    if sizes.is_null() || count.is_null() {
+        return m64p_error_M64ERR_INPUT_ASSERT;
+    }
+    with_output("mode list", |_, output| {
+        let mode = output
+            .video
+            .desktop_display_mode(0)
+            .map_err(|err| anyhow!(err))?;
+        // `count` holds the room in `sizes`, the desktop resolution is the only mode offered
+        unsafe {
+            if *count < 1 {
+                *count = 0;
+                return Ok(());
+            }
+            *sizes = m64p_2d_size {
+                uiWidth: mode.w as u32,
+                uiHeight: mode.h as u32,
+            };
+            *count = 1;
+        }
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn list_rates(
+    _size: m64p_2d_size,
+    _count: *mut c_int,
+    _rates: *mut c_int,
+) -> m64p_error {
+    m64p_error_M64ERR_UNSUPPORTED
+}
+
+unsafe extern "C" fn set_mode(
+    width: c_int,
+    height: c_int,
+    _bpp: c_int,
+    mode: c_int,
+    flags: c_int,
+) -> m64p_error {
+    with_output("mode change", |extension, output| {
+        output.set_mode(
+            &extension.session.title,
+            width,
+            height,
+            mode as m64p_video_mode,
+            flags,
+        )
+    })
+}
+
+unsafe extern "C" fn set_mode_with_rate(
+    width: c_int,
+    height: c_int,
+    _rate: c_int,
+    bpp: c_int,
+    mode: c_int,
+    flags: c_int,
+) -> m64p_error {
+    unsafe { set_mode(width, height, bpp, mode, flags) }
+}
+
+unsafe extern "C" fn gl_get_proc(name: *const c_char) -> m64p_function {
+    if name.is_null() {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
+    let mut address = std::ptr::null();
+    with_output("GL lookup", |_, output| {
+        address = output.video.gl_get_proc_address(&name);
+        Ok(())
+    });
+    // a null address becomes `None`
+    unsafe { std::mem::transmute::<*const (), m64p_function>(address) }
+}
+
+unsafe extern "C" fn gl_set_attr(attr: m64p_GLattr, value: c_int) -> m64p_error {
+    with_output("GL attribute", |_, output| {
+        if attr == m64p_GLattr_M64P_GL_SWAP_CONTROL {
+            output.swap_interval = value;
+            if output.context.is_some() {
+                output.apply_swap_interval();
+            }
+            return Ok(());
+        }
+        let (attr, value) = match sdl_gl_attr(attr) {
+            Some(sdl_sys::SDL_GLattr::SDL_GL_CONTEXT_PROFILE_MASK) => (
+                sdl_sys::SDL_GLattr::SDL_GL_CONTEXT_PROFILE_MASK,
+                sdl_gl_profile(value),
+            ),
+            Some(attr) => (attr, value),
+            None => bail!("unknown GL attribute {attr}"),
+        };
+        if unsafe { sdl_sys::SDL_GL_SetAttribute(attr, value) } != 0 {
+            bail!("{:?}={value}: {}", attr, sdl2::get_error());
+        }
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn gl_get_attr(attr: m64p_GLattr, value: *mut c_int) -> m64p_error {
+    if value.is_null() {
+        return m64p_error_M64ERR_INPUT_ASSERT;
+    }
+    with_output("GL attribute query", |_, _| {
+        if attr == m64p_GLattr_M64P_GL_SWAP_CONTROL {
+            unsafe { *value = sdl_sys::SDL_GL_GetSwapInterval() };
+            return Ok(());
+        }
+        let Some(sdl_attr) = sdl_gl_attr(attr) else {
+            bail!("unknown GL attribute {attr}");
+        };
+        let mut sdl_value = 0;
+        if unsafe { sdl_sys::SDL_GL_GetAttribute(sdl_attr, &mut sdl_value) } != 0 {
+            bail!("{:?}: {}", sdl_attr, sdl2::get_error());
+        }
+        if attr == m64p_GLattr_M64P_GL_CONTEXT_PROFILE_MASK {
+            sdl_value = m64p_gl_profile(sdl_value);
+        }
+        unsafe { *value = sdl_value };
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn gl_swap_buf() -> m64p_error {
+    let result = with_output("buffer swap", |extension, output| {
+        if std::mem::take(&mut output.screenshot_requested) {
+            match output.read_pixels().and_then(|(width, height, pixels)| {
+                screenshot::save(&extension.session.label, width, height, &pixels)
+            }) {
+                Ok(path) => info!("Saved screenshot {}", path.display()),
+                Err(err) => warn!("Failed to save screenshot: {err:#}"),
+            }
+        }
+        let window = output.window.as_mut().context("no video mode was set")?;
+        window.gl_swap_window();
+        output.perf.frame();
+        if output.perf.is_open() {
+            let title = format!("{} | {}", extension.session.title, output.perf.summary());
+            window.set_title(&title)?;
+        }
+        Ok(())
+    });
+    if result != m64p_error_M64ERR_SUCCESS {
+        return result;
+    }
+
+    // not borrowed, the core's event filter may resize the window from here
+    unsafe { sdl_sys::SDL_PumpEvents() };
+
+    let mut pause_unfocused = false;
+    let result = with_output("event handling", |extension, output| {
+        pause_unfocused = extension.session.display.pause_unfocused;
+        flush_events();
+        let keyboard = output.event_pump.keyboard_state();
+        let held: Vec<Scancode> = HOTKEYS
+            .iter()
+            .map(|&(scancode, _)| scancode)
+            .filter(|&scancode| keyboard.is_scancode_pressed(scancode))
+            .collect();
+        for &(scancode, hotkey) in HOTKEYS {
+            if held.contains(&scancode) && !output.held_hotkeys.contains(&scancode) {
+                extension.hotkey(output, hotkey);
+            }
+        }
+        output.held_hotkeys = held;
+        extension.release_pak_switch()?;
+
+        if !std::mem::replace(&mut output.started, true) {
+            extension.start()?;
+        }
+        extension.stop_when_resume_saved()?;
+        Ok(())
+    });
+
+    // the core only runs on from here, so holding the buffer swap pauses it. Nothing is
+    // borrowed while waiting, the core's event filter still sees the pumped events.
+    if pause_unfocused && !window_focused() {
+        info!("Paused while the window is in the background");
+        while !window_focused() {
+            focus_pause::idle();
+            unsafe { sdl_sys::SDL_PumpEvents() };
+            flush_events();
+        }
+        info!("Resumed");
+    }
+    result
+}
+
+/// Drops the events the core's filter let through, they are of no use to the launcher
+fn flush_events() {
+    unsafe {
+        sdl_sys::SDL_FlushEvents(
+            sdl_sys::SDL_EventType::SDL_FIRSTEVENT as u32,
+            sdl_sys::SDL_EventType::SDL_LASTEVENT as u32,
+        )
+    };
+}
+
+/// Whether the game window has the keyboard focus. The window's flags are asked, focus events
+/// are flushed with the rest.
+fn window_focused() -> bool {
+    EXTENSION.with_borrow(|extension| {
+        extension
+            .as_ref()
+            .and_then(|extension| extension.output.as_ref())
+            .and_then(|output| output.window.as_ref())
+            .is_none_or(|window| {
+                window.window_flags() & sdl_sys::SDL_WindowFlags::SDL_WINDOW_INPUT_FOCUS as u32 != 0
+            })
+    })
+}
+
+impl VideoExtension {
+    fn start(&mut self) -> Result<()> {
+        if !self.session.limit_fps {
+            self.core
+                .set_state(m64p_core_param_M64CORE_SPEED_LIMITER, 0)
+                .context("failed to disable the speed limiter")?;
+        }
+        if let Some(path) = self.session.load_state.take() {
+            self.core.load_state(&path)?;
+            info!("Loading state {}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Stops the game once the state Esc asked for is on disk. The core finishes writing it
+    /// before it shuts down.
+    fn stop_when_resume_saved(&mut self) -> Result<()> {
+        let Some(frames) = self.stopping.as_mut() else {
+            return Ok(());
+        };
+        *frames += 1;
+        let saved = self
+            .session
+            .resume
+            .as_ref()
+            .is_some_and(|path| path.is_file());
+        if saved || *frames >= RESUME_SAVE_FRAMES {
+            if !saved {
+                warn!("The resume state was not written in time, stopping without it");
+            }
+            self.stopping = None;
+            self.core.stop()?;
+        }
+        Ok(())
+    }
+
+    /// Swaps controller 1's Controller Pak and Rumble Pak, as games that save to one and rumble
+    /// with the other ask for. The plugin does the swap on its switch keys, held here for a few
+    /// frames so it sees them.
+    fn switch_pak(&mut self) {
+        if self.pak_switch.is_some() {
+            return;
+        }
+        let pak = match self.session.pak {
+            Pak::Rumble => Pak::Memory,
+            _ => Pak::Rumble,
+        };
+        let Some(key) = n64_input::switch_key(pak) else {
+            return;
+        };
+        match self.core.send_key(key, true) {
+            Ok(()) => {
+                info!("Controller 1: switching to the {pak:?} pak");
+                self.session.pak = pak;
+                self.pak_switch = Some((key, PAK_SWITCH_FRAMES));
+            }
+            Err(err) => warn!("Failed to switch the pak: {err:#}"),
+        }
+    }
+
+    fn release_pak_switch(&mut self) -> Result<()> {
+        let Some((key, frames)) = self.pak_switch.as_mut() else {
+            return Ok(());
+        };
+        *frames -= 1;
+        if *frames == 0 {
+            let key = *key;
+            self.pak_switch = None;
+            self.core.send_key(key, false)?;
+        }
+        Ok(())
+    }
+
+    fn hotkey(&mut self, output: &mut Output, hotkey: Hotkey) {
+        match hotkey {
+            Hotkey::Screenshot => output.screenshot_requested = true,
+            Hotkey::PerfOverlay => {
+                output.perf.toggle();
+                if let Some(window) = output.window.as_mut()
+                    && !output.perf.is_open()
+                    && let Err(err) = window.set_title(&self.session.title)
+                {
+                    debug!("Failed to reset the window title: {err}");
+                }
+            }
+            Hotkey::SwitchPak => self.switch_pak(),
+            Hotkey::Slot(slot) => {
+                self.state_slot = slot;
+                info!("State slot {slot}");
+            }
+            Hotkey::SaveState => {
+                let path = n64::state_path(&self.session.label, self.state_slot);
+                let result = path
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .context("failed to create the states directory")
+                    .and_then(|()| self.core.save_state(&path));
+                match result {
+                    // the core writes it at the end of the frame
+                    Ok(()) => {
+                        info!("Saving state {}", path.display());
+                        save_sync::written();
+                    }
+                    Err(err) => warn!("Failed to save state: {err:#}"),
+                }
+            }
+            Hotkey::LoadState => {
+                let path = n64::state_path(&self.session.label, self.state_slot);
+                if !path.exists() {
+                    info!("State slot {} is empty", self.state_slot);
+                    return;
+                }
+                match self.core.load_state(&path) {
+                    Ok(()) => info!("Loading state {}", path.display()),
+                    Err(err) => warn!("Failed to load state: {err:#}"),
+                }
+            }
+            // without a resume state the core stops on Esc by itself
+            Hotkey::Quit => {
+                let Some(path) = self.session.resume.clone() else {
+                    return;
+                };
+                if self.stopping.is_some() {
+                    return;
+                }
+                // the stale state goes first, the new one showing up is what Esc waits for
+                let _ = fs::remove_file(&path);
+                let result = path
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .context("failed to create the states directory")
+                    .and_then(|()| self.core.save_state(&path));
+                match result {
+                    Ok(()) => {
+                        info!("Saving the resume state {}", path.display());
+                        save_sync::written();
+                        self.stopping = Some(0);
+                    }
+                    Err(err) => {
+                        warn!("Failed to save the resume state: {err:#}");
+                        if let Err(err) = self.core.stop() {
+                            warn!("{err:#}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn set_caption(caption: *const c_char) -> m64p_error {
+    if !caption.is_null() {
+        let caption = unsafe { CStr::from_ptr(caption) }.to_string_lossy();
+        debug!("Nintendo 64 video plugin caption: {caption}");
+    }
+    m64p_error_M64ERR_SUCCESS
+}
+
+unsafe extern "C" fn toggle_fullscreen() -> m64p_error {
+    with_output("fullscreen toggle", |_, output| {
+        let window = output.window.as_mut().context("no video mode was set")?;
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        window
+            .set_fullscreen(fullscreen)
+            .map_err(|err| anyhow!(err))
+    })
+}
+
+unsafe extern "C" fn resize_window(width: c_int, height: c_int) -> m64p_error {
+    with_output("window resize", |_, output| {
+        let window = output.window.as_mut().context("no video mode was set")?;
+        let size = (width.max(1) as u32, height.max(1) as u32);
+        if window.size() != size {
+            window.set_size(size.0, size.1)?;
+        }
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn gl_get_default_framebuffer() -> u32 {
+    0
+}
+
+unsafe extern "C" fn vk_get_surface(
+    _surface: *mut *mut c_void,
+    _instance: *mut c_void,
+) -> m64p_error {
+    m64p_error_M64ERR_UNSUPPORTED
+}
+
+unsafe extern "C" fn vk_get_instance_extensions(
+    _extensions: *mut *mut *const c_char,
+    _count: *mut u32,
+) -> m64p_error {
+    m64p_error_M64ERR_UNSUPPORTED
+}
+
+impl Output {
+    fn new(frame: Duration, display: &DisplayConfig) -> Result<Self> {
+        let sdl = sdl2::init().map_err(|err| anyhow!(err))?;
+        let video = sdl.video().map_err(|err| anyhow!(err))?;
+        let event_pump = sdl.event_pump().map_err(|err| anyhow!(err))?;
+        Ok(Self {
+            _sdl: sdl,
+            video,
+            event_pump,
+            context: None,
+            window: None,
+            swap_interval: 0,
+            display: display.clone(),
+            held_hotkeys: Vec::new(),
+            screenshot_requested: false,
+            started: false,
+            perf: PerfOverlay::new(frame, display),
+        })
+    }
+
+    fn set_mode(
+        &mut self,
+        title: &str,
+        width: c_int,
+        height: c_int,
+        mode: m64p_video_mode,
+        flags: c_int,
+    ) -> Result<()> {
+        ensure!(
+            width > 0 && height > 0,
+            "invalid video mode {width}x{height}"
+        );
+        let (width, height) = (width as u32, height as u32);
+        let resizable = flags & m64p_video_flags_M64VIDEOFLAG_SUPPORT_RESIZING as c_int != 0;
+        // the configured fullscreen wins even when the plugin asks for a window
+        let fullscreen = match present::sdl_fullscreen() {
+            FullscreenType::Off if mode == m64p_video_mode_M64VIDEO_FULLSCREEN => {
+                FullscreenType::Desktop
+            }
+            configured => configured,
+        };
+
+        if self.window.is_none() {
+            let mut builder = self.video.window(title, width, height);
+            builder.opengl().position_centered();
+            if resizable {
+                builder.resizable();
+            }
+            let window = builder
+                .build()
+                .context("failed to create Nintendo 64 window")?;
+            let context = window
+                .gl_create_context()
+                .map_err(|err| anyhow!("failed to create OpenGL context: {err}"))?;
+            window
+                .gl_make_current(&context)
+                .map_err(|err| anyhow!(err))?;
+            self.window = Some(window);
+            self.context = Some(context);
+            self.apply_swap_interval();
+        }
+
+        let window = self.window.as_mut().expect("window was just created");
+        window
+            .set_fullscreen(fullscreen)
+            .map_err(|err| anyhow!(err))?;
+        if fullscreen == FullscreenType::Off {
+            window.set_size(width, height)?;
+        }
+        debug!("Nintendo 64 video mode {width}x{height} ({fullscreen:?})");
+        Ok(())
+    }
+
+    fn apply_swap_interval(&self) {
+        // the plugin asking for vsync gets the adaptive kind on a variable refresh display
+        if self.swap_interval > 0 && present::adaptive_gl_sync(&self.video, &self.display) {
+            return;
+        }
+        if unsafe { sdl_sys::SDL_GL_SetSwapInterval(self.swap_interval) } != 0 {
+            debug!(
+                "Swap interval {} is not supported: {}",
+                self.swap_interval,
+                sdl2::get_error()
+            );
+        }
+    }
+
+    /// The finished frame in the back buffer, as `0xAARRGGBB` rows from the top
+    fn read_pixels(&self) -> Result<(u32, u32, Vec<u32>)> {
+        let window = self.window.as_ref().context("no video mode was set")?;
+        let address = self.video.gl_get_proc_address("glReadPixels");
+        ensure!(!address.is_null(), "glReadPixels is not available");
+        let read_pixels = unsafe { std::mem::transmute::<*const (), ReadPixels>(address) };
+
+        let (width, height) = window.drawable_size();
+        let mut rgba = vec![0u8; (width * height * 4) as usize];
+        unsafe {
+            read_pixels(
+                0,
+                0,
+                width as i32,
+                height as i32,
+                GL_RGBA,
+                GL_UNSIGNED_BYTE,
+                rgba.as_mut_ptr().cast(),
+            )
+        };
+        // OpenGL rows start at the bottom
+        let pixels = rgba
+            .chunks_exact((width * 4) as usize)
+            .rev()
+            .flat_map(|row| row.chunks_exact(4))
+            .map(|p| 0xFF00_0000 | (p[0] as u32) << 16 | (p[1] as u32) << 8 | p[2] as u32)
+            .collect();
+        Ok((width, height, pixels))
+    }
+}
+
+fn sdl_gl_attr(attr: m64p_GLattr) -> Option<sdl_sys::SDL_GLattr> {
+    use sdl_sys::SDL_GLattr::*;
+    #[allow(non_upper_case_globals)]
+    let attr = match attr {
+        m64p_GLattr_M64P_GL_DOUBLEBUFFER => SDL_GL_DOUBLEBUFFER,
+        m64p_GLattr_M64P_GL_BUFFER_SIZE => SDL_GL_BUFFER_SIZE,
+        m64p_GLattr_M64P_GL_DEPTH_SIZE => SDL_GL_DEPTH_SIZE,
+        m64p_GLattr_M64P_GL_RED_SIZE => SDL_GL_RED_SIZE,
+        m64p_GLattr_M64P_GL_GREEN_SIZE => SDL_GL_GREEN_SIZE,
+        m64p_GLattr_M64P_GL_BLUE_SIZE => SDL_GL_BLUE_SIZE,
+        m64p_GLattr_M64P_GL_ALPHA_SIZE => SDL_GL_ALPHA_SIZE,
+        m64p_GLattr_M64P_GL_MULTISAMPLEBUFFERS => SDL_GL_MULTISAMPLEBUFFERS,
+        m64p_GLattr_M64P_GL_MULTISAMPLESAMPLES => SDL_GL_MULTISAMPLESAMPLES,
+        m64p_GLattr_M64P_GL_CONTEXT_MAJOR_VERSION => SDL_GL_CONTEXT_MAJOR_VERSION,
+        m64p_GLattr_M64P_GL_CONTEXT_MINOR_VERSION => SDL_GL_CONTEXT_MINOR_VERSION,
+        m64p_GLattr_M64P_GL_CONTEXT_PROFILE_MASK => SDL_GL_CONTEXT_PROFILE_MASK,
+        _ => return None,
+    };
+    Some(attr)
+}
+
+/// mupen64plus numbers the profiles 0, 1, 2 where SDL uses bit flags
+fn sdl_gl_profile(profile: c_int) -> c_int {
+    use sdl_sys::SDL_GLprofile::*;
+    #[allow(non_upper_case_globals)]
+    let profile = match profile as m64p_GLContextType {
+        m64p_GLContextType_M64P_GL_CONTEXT_PROFILE_CORE => SDL_GL_CONTEXT_PROFILE_CORE,
+        m64p_GLContextType_M64P_GL_CONTEXT_PROFILE_ES => SDL_GL_CONTEXT_PROFILE_ES,
+        _ => SDL_GL_CONTEXT_PROFILE_COMPATIBILITY,
+    };
+    profile as c_int
+}
+
+fn m64p_gl_profile(profile: c_int) -> c_int {
+    use sdl_sys::SDL_GLprofile::*;
+    let profile = if profile == SDL_GL_CONTEXT_PROFILE_CORE as c_int {
+        m64p_GLContextType_M64P_GL_CONTEXT_PROFILE_CORE
+    } else if profile == SDL_GL_CONTEXT_PROFILE_ES as c_int {
+        m64p_GLContextType_M64P_GL_CONTEXT_PROFILE_ES
+    } else {
+        m64p_GLContextType_M64P_GL_CONTEXT_PROFILE_COMPATIBILITY
+    };
+    profile as c_int
+}
@@ -1,25 +1,125 @@
 use std::collections::HashSet;
-use std::path::Path;
-use std::time::{Duration, Instant};
+#[cfg(nds_wifi)]
+use std::collections::hash_map::RandomState;
+use std::ffi::CString;
+use std::fs;
+#[cfg(nds_wifi)]
+use std::hash::BuildHasher;
+use std::os::raw::{c_char, c_int, c_uint};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::{Context, Result, anyhow, bail};
 use desmume_rs::DeSmuME;
 use desmume_rs::input::{Key, keymask};
+use log::{debug, info, warn};
 use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Keycode;
 use sdl2::mouse::MouseButton;
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::render::{Canvas, Texture};
-use sdl2::video::Window;
+use sdl2::video::{FullscreenType, Window, WindowPos};
 
+use crate::bezel::Bezel;
+use crate::config::{Config, DisplayConfig};
 use crate::controller::{ControllerManager, VirtualButton};
+use crate::focus_pause::{self, FocusPause};
+use crate::frame_pacer::FramePacer;
+use crate::perf_overlay::PerfOverlay;
+use crate::present;
+use crate::saves;
+use crate::systems::GameSystem;
 
 const TARGET_FRAME: Duration = Duration::from_micros(16_667);
 const SCREEN_WIDTH: u32 = desmume_rs::SCREEN_WIDTH as u32;
 const SCREEN_HEIGHT: u32 = desmume_rs::SCREEN_HEIGHT as u32;
 const SCREEN_HEIGHT_BOTH: u32 = desmume_rs::SCREEN_HEIGHT_BOTH as u32;
+/// `SDL_TOUCH_MOUSEID`, the mouse SDL emulates from touches; fingers are handled directly
+const TOUCH_MOUSE_ID: u32 = u32::MAX;
+/// GBA cartridge saves go in `saves/nds/gba/`, apart from the DS games' own
+const GBA_SAVE_DIR: &str = "gba";
 
-pub fn run(rom: &Path, scale: u32, limit_fps: bool) -> Result<()> {
+unsafe extern "C" {
+    fn retro_nds_slot2_change(
+        device: c_int,
+        rom_path: *const c_char,
+        sram_path: *const c_char,
+    ) -> c_int;
+    #[cfg(nds_wifi)]
+    fn retro_nds_wifi_enable(mac: *const u8) -> c_int;
+    // DeSmuME's C interface, which desmume-rs does not wrap
+    fn desmume_backup_import_file(filename: *const c_char, force_size: c_uint) -> c_int;
+    fn desmume_backup_export_file(filename: *const c_char) -> c_int;
+}
+
+/// Device plugged into the emulated GBA slot (slot-2).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Slot2Device {
+    None,
+    Auto,
+    RumblePak,
+    ExpansionPak,
+    GbaCartridge(PathBuf),
+}
+
+impl Slot2Device {
+    /// Matches DeSmuME's `NDS_SLOT2_TYPE` ordering.
+    fn desmume_type(&self) -> c_int {
+        match self {
+            Slot2Device::None => 0,
+            Slot2Device::Auto => 1,
+            Slot2Device::RumblePak => 3,
+            Slot2Device::GbaCartridge(_) => 4,
+            Slot2Device::ExpansionPak => 6,
+        }
+    }
+
+    fn sibling_cartridge(rom: &Path) -> Option<Self> {
+        ["gba", "GBA"]
+            .iter()
+            .map(|ext| rom.with_extension(ext))
+            .find(|path| path.is_file())
+            .map(Slot2Device::GbaCartridge)
+    }
+}
+
+impl FromStr for Slot2Device {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "none" => Ok(Slot2Device::None),
+            "auto" => Ok(Slot2Device::Auto),
+            "rumble" | "rumblepak" => Ok(Slot2Device::RumblePak),
+            "expansion" | "exppak" | "memory" => Ok(Slot2Device::ExpansionPak),
+            _ => {
+                let path = value.strip_prefix("gba:").unwrap_or(value);
+                if Path::new(path)
+                    .extension()
+                    .and_then(|s| s.to_str())
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("gba"))
+                {
+                    Ok(Slot2Device::GbaCartridge(PathBuf::from(path)))
+                } else {
+                    Err(format!(
+                        "unknown slot-2 device '{value}' (expected none, auto, rumble, expansion, or a .gba path)"
+                    ))
+                }
+            }
+        }
+    }
+}
+
+pub fn run(
+    rom: &Path,
+    scale: u32,
+    limit_fps: bool,
+    slot2: Option<Slot2Device>,
+    wifi: bool,
+    split_screens: bool,
+    config: &Config,
+) -> Result<()> {
     let title = rom
         .file_stem()
         .and_then(|s| s.to_str())
@@ -29,77 +129,264 @@ pub fn run(rom: &Path, scale: u32, limit_fps: bool) -> Result<()> {
         .ok_or_else(|| anyhow!("ROM path contains invalid UTF-8"))?;
 
     let mut nds = DeSmuME::init().map_err(|err| anyhow!(err))?;
+    if let Some(device) = slot2.or_else(|| Slot2Device::sibling_cartridge(rom)) {
+        insert_slot2(&device)?;
+    }
+    if wifi {
+        enable_local_wireless()?;
+    }
     nds.open(rom_path, true).map_err(|err| anyhow!(err))?;
+    let save = saves::battery_save(GameSystem::Nds, rom)?;
+    import_save(&save)?;
+    // DeSmuME writes GBA cartridge saves itself
+    let gba_saves = saves::CoreSaves::snapshot(&saves::dir(GameSystem::Nds).join(GBA_SAVE_DIR));
 
-    let mut frontend = NdsFrontend::new(title, scale.max(1), limit_fps)?;
-    frontend.run(&mut nds)
+    let mut frontend = NdsFrontend::new(title, scale.max(1), limit_fps, split_screens, config)?;
+    let result = frontend.run(&mut nds);
+    if let Err(err) = export_save(&save) {
+        warn!("Failed to write the save {}: {err:#}", save.display());
+    }
+    gba_saves.finish();
+    result
+}
+
+/// Loads the game's save from `saves/nds/` over the `.dsv` DeSmuME opened itself. A game
+/// without one there yet keeps DeSmuME's, which [`export_save`] then moves over.
+fn import_save(save: &Path) -> Result<()> {
+    if saves::read(save, None)?.is_none() {
+        return Ok(());
+    }
+    let path = CString::new(save.to_string_lossy().as_bytes())
+        .context("DS save path contains a NUL byte")?;
+    // raw images are taken at their file size
+    if unsafe { desmume_backup_import_file(path.as_ptr(), 0) } == 0 {
+        bail!("DeSmuME could not load the save {}", save.display());
+    }
+    info!("Loaded the save {}", save.display());
+    Ok(())
+}
+
+/// Writes the game's save back to `saves/nds/` as a raw image, when it changed
+fn export_save(save: &Path) -> Result<()> {
+    // DeSmuME writes a raw image for a `.sav` name
+    let export = save.with_extension("export.sav");
+    let path = CString::new(export.to_string_lossy().as_bytes())
+        .context("DS save path contains a NUL byte")?;
+    let exported = unsafe { desmume_backup_export_file(path.as_ptr()) } != 0;
+    let data = fs::read(&export).unwrap_or_default();
+    let _ = fs::remove_file(&export);
+    if !exported || data.is_empty() {
+        debug!("The game has nothing saved yet");
+        return Ok(());
+    }
+    if fs::read(save).is_ok_and(|old| old == data) {
+        return Ok(());
+    }
+    saves::write(save, &data)
+}
+
+fn insert_slot2(device: &Slot2Device) -> Result<()> {
+    let (rom_path, sram_path) = match device {
+        Slot2Device::GbaCartridge(path) => {
+            if !path.is_file() {
+                bail!("GBA cartridge image {} does not exist", path.display());
+            }
+            let rom = CString::new(path.to_string_lossy().as_bytes())
+                .context("GBA cartridge path contains a NUL byte")?;
+            let sram = saves::accessory_save(GameSystem::Nds, GBA_SAVE_DIR, path)?;
+            let sram = CString::new(sram.to_string_lossy().as_bytes())
+                .context("GBA save path contains a NUL byte")?;
+            (Some(rom), Some(sram))
+        }
+        _ => (None, None),
+    };
+
+    let accepted = unsafe {
+        retro_nds_slot2_change(
+            device.desmume_type(),
+            rom_path.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+            sram_path.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+        )
+    };
+    if accepted == 0 {
+        bail!("DeSmuME rejected slot-2 device {:?}", device);
+    }
+    info!("Nintendo DS slot-2 device: {:?}", device);
+    Ok(())
+}
+
+/// Turns on DeSmuME's ad-hoc transport, which relays local wireless traffic as UDP broadcasts on
+/// port 7000. Every instance needs a distinct MAC address, so one is rolled per launch.
+#[cfg(nds_wifi)]
+fn enable_local_wireless() -> Result<()> {
+    let seed = RandomState::new()
+        .hash_one(std::process::id())
+        .to_le_bytes();
+    // Nintendo's OUI, as used by DeSmuME's default firmware.
+    let mac = [0x00, 0x09, 0xBF, seed[0], seed[1], seed[2]];
+
+    if unsafe { retro_nds_wifi_enable(mac.as_ptr()) } == 0 {
+        bail!("DeSmuME Wi-Fi is not initialised");
+    }
+    info!(
+        "Nintendo DS local wireless enabled (UDP port 7000), MAC {}",
+        mac.iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect::<Vec<_>>()
+            .join(":")
+    );
+    Ok(())
+}
+
+/// Builds without the `nds-wifi` feature, or for targets other than Linux and macOS, lack the
+/// shim that reaches into DeSmuME's Wi-Fi emulation
+#[cfg(not(nds_wifi))]
+fn enable_local_wireless() -> Result<()> {
+    bail!(
+        "this build has no Nintendo DS local wireless, build it with the nds-wifi feature on Linux or macOS"
+    )
+}
+
+/// The touch screen in a window of its own, when the screens are split
+struct BottomScreen {
+    canvas: Canvas<Window>,
+    texture: Texture,
+    window_size: (u32, u32),
 }
 
 struct NdsFrontend {
     _sdl: sdl2::Sdl,
+    /// Both screens stacked, or only the top one when `bottom` is set
     canvas: Canvas<Window>,
     texture: Texture,
+    /// Drawn around the top window's picture only, the touch screen window stays bare
+    bezel: Option<Bezel>,
+    bottom: Option<BottomScreen>,
     event_pump: sdl2::EventPump,
     pressed: HashSet<Keycode>,
     limit_fps: bool,
+    display: DisplayConfig,
     window_size: (u32, u32),
     touch_active: bool,
+    /// The finger holding the touch screen, the DS only senses one point
+    touch_finger: Option<i64>,
     pixel_buffer: Vec<u8>,
     argb_buffer: Vec<u32>,
     controller: ControllerManager,
+    perf: PerfOverlay,
+    /// Follows both windows when the screens are split
+    focus: FocusPause,
 }
 
 impl NdsFrontend {
-    fn new(title: &str, scale: u32, limit_fps: bool) -> Result<Self> {
+    fn new(
+        title: &str,
+        scale: u32,
+        limit_fps: bool,
+        split_screens: bool,
+        config: &Config,
+    ) -> Result<Self> {
         let sdl = sdl2::init().map_err(|e| anyhow!(e))?;
         let video = sdl.video().map_err(|e| anyhow!(e))?;
-        let scaled_w = SCREEN_WIDTH.saturating_mul(scale);
-        let scaled_h = SCREEN_HEIGHT_BOTH.saturating_mul(scale);
+        let screen_h = if split_screens {
+            SCREEN_HEIGHT
+        } else {
+            SCREEN_HEIGHT_BOTH
+        };
+        let (scaled_w, scaled_h) = present::oriented(
+            SCREEN_WIDTH.saturating_mul(scale),
+            screen_h.saturating_mul(scale),
+        );
         let window = video
             .window(title, scaled_w, scaled_h)
             .position_centered()
             .resizable()
+            .allow_highdpi()
             .build()
             .context("failed to create NDS window")?;
 
-        let mut canvas_builder = window.into_canvas();
-        if limit_fps {
-            canvas_builder = canvas_builder.present_vsync();
-        }
-        let mut canvas = canvas_builder.build().map_err(|e| anyhow!(e))?;
-        canvas
-            .set_logical_size(SCREEN_WIDTH, SCREEN_HEIGHT_BOTH)
+        let mut canvas =
+            present::canvas(window, limit_fps, &config.display).map_err(|e| anyhow!(e))?;
+        present::apply_fullscreen(canvas.window_mut());
+        present::set_logical_size(&mut canvas, SCREEN_WIDTH, screen_h)
             .context("failed to set NDS logical size")?;
 
         let texture_creator = canvas.texture_creator();
         let texture = texture_creator
-            .create_texture_streaming(PixelFormatEnum::RGBA32, SCREEN_WIDTH, SCREEN_HEIGHT_BOTH)
+            .create_texture_streaming(PixelFormatEnum::ARGB8888, SCREEN_WIDTH, screen_h)
             .map_err(|e| anyhow!(e))?;
+        let bezel = Bezel::load(&canvas, &config.display, GameSystem::Nds, title);
 
+        let top = canvas.window();
+        let bottom = if split_screens {
+            let window = video
+                .window(&format!("{title} - Touch screen"), scaled_w, scaled_h)
+                .resizable()
+                .allow_highdpi()
+                .build()
+                .context("failed to create NDS touch screen window")?;
+            // the top window's present already waits for vsync, waiting twice halves the rate
+            let mut canvas =
+                present::canvas(window, false, &config.display).map_err(|e| anyhow!(e))?;
+            // placed once both windows have their size for the display, below a fullscreen
+            // top window it would land off the monitor
+            if top.fullscreen_state() == FullscreenType::Off {
+                let (x, y) = top.position();
+                canvas.window_mut().set_position(
+                    WindowPos::Positioned(x),
+                    WindowPos::Positioned(y.saturating_add(top.size().1 as i32)),
+                );
+            }
+            present::set_logical_size(&mut canvas, SCREEN_WIDTH, SCREEN_HEIGHT)
+                .context("failed to set NDS logical size")?;
+            let texture = canvas
+                .texture_creator()
+                .create_texture_streaming(PixelFormatEnum::ARGB8888, SCREEN_WIDTH, SCREEN_HEIGHT)
+                .map_err(|e| anyhow!(e))?;
+            let window_size = canvas.window().size();
+            Some(BottomScreen {
+                canvas,
+                texture,
+                window_size,
+            })
+        } else {
+            None
+        };
+
+        let window_size = canvas.window().size();
         let event_pump = sdl.event_pump().map_err(|e| anyhow!(e))?;
-        let controller = ControllerManager::new(&sdl)?;
+        let controller = ControllerManager::new(&sdl, &config.controllers)?;
 
         Ok(Self {
             _sdl: sdl,
             canvas,
             texture,
+            bezel,
+            bottom,
             event_pump,
             pressed: HashSet::new(),
             limit_fps,
-            window_size: (scaled_w.max(1), scaled_h.max(1)),
+            display: config.display.clone(),
+            window_size,
             touch_active: false,
+            touch_finger: None,
             pixel_buffer: vec![0; (SCREEN_WIDTH as usize) * (SCREEN_HEIGHT_BOTH as usize) * 4],
             argb_buffer: vec![0; (SCREEN_WIDTH as usize) * (SCREEN_HEIGHT_BOTH as usize)],
             controller,
+            perf: PerfOverlay::new(TARGET_FRAME, &config.display),
+            focus: FocusPause::new(&config.display),
         })
     }
 
     fn run(&mut self, nds: &mut DeSmuME) -> Result<()> {
         let mut running = true;
-        let mut last_frame = Instant::now();
+        let mut pacer = FramePacer::new(TARGET_FRAME, self.limit_fps)
+            .vsynced(self.canvas.window(), &self.display);
         while running {
             while let Some(event) = self.event_pump.poll_event() {
                 self.controller.handle_event(&event);
+                self.perf.handle_sdl_event(&event);
+                self.focus.handle_sdl_event(&event);
                 if !self.handle_event(nds, event) {
                     running = false;
                     break;
@@ -109,18 +396,18 @@ impl NdsFrontend {
             if !running {
                 break;
             }
+            if self.focus.is_paused() {
+                focus_pause::idle();
+                continue;
+            }
 
+            self.controller.advance_frame();
             self.sync_inputs(nds);
             nds.cycle();
+            self.perf.frame();
             self.present_frame(nds)?;
 
-            if self.limit_fps {
-                let elapsed = last_frame.elapsed();
-                if elapsed < TARGET_FRAME {
-                    std::thread::sleep(TARGET_FRAME - elapsed);
-                }
-            }
-            last_frame = Instant::now();
+            pacer.wait();
         }
         Ok(())
     }
@@ -137,14 +424,30 @@ impl NdsFrontend {
                 repeat: false,
                 ..
             } => {
-                self.pressed.insert(code);
+                self.pressed.insert(present::game_arrow_key(code));
             }
             Event::KeyUp {
                 keycode: Some(code),
                 ..
             } => {
-                self.pressed.remove(&code);
+                self.pressed.remove(&present::game_arrow_key(code));
+            }
+            Event::MouseButtonDown {
+                which: TOUCH_MOUSE_ID,
+                ..
+            }
+            | Event::MouseButtonUp {
+                which: TOUCH_MOUSE_ID,
+                ..
             }
+            | Event::MouseMotion {
+                which: TOUCH_MOUSE_ID,
+                ..
+            } => {}
+            Event::MouseButtonDown { window_id, .. }
+            | Event::MouseButtonUp { window_id, .. }
+            | Event::MouseMotion { window_id, .. }
+                if !self.is_touch_window(window_id) => {}
             Event::MouseButtonDown {
                 mouse_btn: MouseButton::Left,
                 x,
@@ -154,40 +457,65 @@ impl NdsFrontend {
                 if let Some((tx, ty)) = self.touch_from_window(x, y) {
                     self.touch_active = true;
                     nds.input_mut().touch_set_pos(tx, ty);
-                } else if self.touch_active {
-                    self.touch_active = false;
-                    nds.input_mut().touch_release();
+                } else {
+                    self.release_touch(nds);
                 }
             }
             Event::MouseButtonUp {
                 mouse_btn: MouseButton::Left,
                 ..
-            } => {
+            } => self.release_touch(nds),
+            Event::MouseMotion { x, y, .. } => {
                 if self.touch_active {
-                    self.touch_active = false;
-                    nds.input_mut().touch_release();
+                    match self.touch_from_window(x, y) {
+                        Some((tx, ty)) => nds.input_mut().touch_set_pos(tx, ty),
+                        None => self.release_touch(nds),
+                    }
                 }
             }
-            Event::MouseMotion { x, y, .. } => {
-                if self.touch_active {
-                    if let Some((tx, ty)) = self.touch_from_window(x, y) {
-                        nds.input_mut().touch_set_pos(tx, ty);
-                    } else {
-                        self.touch_active = false;
-                        nds.input_mut().touch_release();
+            Event::FingerDown {
+                finger_id, x, y, ..
+            } => {
+                if self.touch_finger.is_none()
+                    && let Some((tx, ty)) = self.touch_from_normalized(x, y)
+                {
+                    self.touch_finger = Some(finger_id);
+                    self.touch_active = true;
+                    nds.input_mut().touch_set_pos(tx, ty);
+                }
+            }
+            Event::FingerMotion {
+                finger_id, x, y, ..
+            } => {
+                if self.touch_finger == Some(finger_id) {
+                    match self.touch_from_normalized(x, y) {
+                        Some((tx, ty)) => nds.input_mut().touch_set_pos(tx, ty),
+                        None => self.release_touch(nds),
                     }
                 }
             }
-            Event::Window { win_event, .. } => match win_event {
-                WindowEvent::SizeChanged(w, h) | WindowEvent::Resized(w, h) => {
-                    self.window_size = (w.max(1) as u32, h.max(1) as u32);
+            Event::FingerUp { finger_id, .. } => {
+                if self.touch_finger == Some(finger_id) {
+                    self.release_touch(nds);
                 }
-                WindowEvent::FocusLost | WindowEvent::Leave => {
-                    if self.touch_active {
-                        self.touch_active = false;
-                        nds.input_mut().touch_release();
+            }
+            Event::Window {
+                window_id,
+                win_event,
+                ..
+            } => match win_event {
+                // SDL only quits on its own once the last window is gone
+                WindowEvent::Close => return false,
+                WindowEvent::SizeChanged(w, h) | WindowEvent::Resized(w, h) => {
+                    let size = (w.max(1) as u32, h.max(1) as u32);
+                    match &mut self.bottom {
+                        Some(bottom) if bottom.canvas.window().id() == window_id => {
+                            bottom.window_size = size;
+                        }
+                        _ => self.window_size = size,
                     }
                 }
+                WindowEvent::FocusLost | WindowEvent::Leave => self.release_touch(nds),
                 _ => {}
             },
             _ => {}
@@ -254,42 +582,102 @@ impl NdsFrontend {
             let r = chunk[2] as u32;
             let g = chunk[1] as u32;
             let b = chunk[0] as u32;
-            *dst = (0xFF << 24) | (b << 16) | (g << 8) | r;
+            *dst = (0xFF << 24) | (r << 16) | (g << 8) | b;
+        }
+        if self.perf.is_open() {
+            let top = SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize;
+            self.perf
+                .draw(&mut self.argb_buffer[..top], SCREEN_WIDTH as usize);
         }
 
+        let (top, touch) = match &self.bottom {
+            Some(_) => self
+                .argb_buffer
+                .split_at(SCREEN_WIDTH as usize * SCREEN_HEIGHT as usize),
+            None => (&self.argb_buffer[..], &[][..]),
+        };
         self.texture
-            .update(
-                None,
-                bytemuck::cast_slice(&self.argb_buffer),
-                (SCREEN_WIDTH as usize) * 4,
-            )
+            .update(None, bytemuck::cast_slice(top), (SCREEN_WIDTH as usize) * 4)
             .context("failed to upload NDS frame")?;
         self.canvas.clear();
-        self.canvas
-            .copy(&self.texture, None, None)
-            .map_err(|e| anyhow!(e))?;
+        if let Some(bezel) = &self.bezel {
+            bezel.draw(&mut self.canvas).map_err(|e| anyhow!(e))?;
+        }
+        present::copy(&mut self.canvas, &self.texture, None).map_err(|e| anyhow!(e))?;
         self.canvas.present();
+
+        if let Some(bottom) = &mut self.bottom {
+            bottom
+                .texture
+                .update(
+                    None,
+                    bytemuck::cast_slice(touch),
+                    (SCREEN_WIDTH as usize) * 4,
+                )
+                .context("failed to upload NDS touch screen")?;
+            bottom.canvas.clear();
+            present::copy(&mut bottom.canvas, &bottom.texture, None).map_err(|e| anyhow!(e))?;
+            bottom.canvas.present();
+        }
         Ok(())
     }
 
+    /// Whether mouse events of `window_id` may touch, the top window alone never shows the
+    /// touch screen
+    fn is_touch_window(&self, window_id: u32) -> bool {
+        self.bottom
+            .as_ref()
+            .is_none_or(|bottom| bottom.canvas.window().id() == window_id)
+    }
+
+    /// Size of the window showing the touch screen, the logical height of its canvas and
+    /// where the touch screen starts in it
+    fn touch_area(&self) -> ((u32, u32), u32, u32) {
+        match &self.bottom {
+            Some(bottom) => (bottom.window_size, SCREEN_HEIGHT, 0),
+            None => (self.window_size, SCREEN_HEIGHT_BOTH, SCREEN_HEIGHT),
+        }
+    }
+
+    fn release_touch(&mut self, nds: &mut DeSmuME) {
+        self.touch_finger = None;
+        if self.touch_active {
+            self.touch_active = false;
+            nds.input_mut().touch_release();
+        }
+    }
+
+    /// Fingers report positions from 0 to 1 across the whole window, including the bars SDL
+    /// leaves around the logical size when the window's aspect differs. SDL does not say which
+    /// window a finger is on, with split screens it is taken to be the touch screen's.
+    fn touch_from_normalized(&self, x: f32, y: f32) -> Option<(u16, u16)> {
+        let (window_size, height, _) = self.touch_area();
+        let (logical_w, logical_h) = present::oriented(SCREEN_WIDTH, height);
+        let (win_w, win_h) = (window_size.0 as f32, window_size.1 as f32);
+        let scale = (win_w / logical_w as f32).min(win_h / logical_h as f32);
+        let left = (win_w - logical_w as f32 * scale) / 2.0;
+        let top = (win_h - logical_h as f32 * scale) / 2.0;
+        self.touch_from_logical((x * win_w - left) / scale, (y * win_h - top) / scale)
+    }
+
     fn touch_from_window(&self, x: i32, y: i32) -> Option<(u16, u16)> {
         if x < 0 || y < 0 {
             return None;
         }
 
         // SDL scales mouse coords into logical space when a logical size is set.
-        if x < SCREEN_WIDTH as i32 && y < SCREEN_HEIGHT_BOTH as i32 {
-            if y < SCREEN_HEIGHT as i32 {
-                return None;
-            }
-            return Some((x as u16, (y - SCREEN_HEIGHT as i32) as u16));
+        let (_, height, _) = self.touch_area();
+        let (logical_w, logical_h) = present::oriented(SCREEN_WIDTH, height);
+        if x < logical_w as i32 && y < logical_h as i32 {
+            // the centre of the pixel, so turning it back stays inside the picture
+            return self.touch_from_logical(x as f32 + 0.5, y as f32 + 0.5);
         }
 
         self.touch_from_physical(x, y)
     }
 
     fn touch_from_physical(&self, x: i32, y: i32) -> Option<(u16, u16)> {
-        let (win_w, win_h) = self.window_size;
+        let ((win_w, win_h), height, _) = self.touch_area();
         if win_w == 0 || win_h == 0 {
             return None;
         }
@@ -300,17 +688,23 @@ impl NdsFrontend {
             return None;
         }
 
-        let ds_x = (xf * SCREEN_WIDTH as f32)
-            .floor()
-            .clamp(0.0, (SCREEN_WIDTH - 1) as f32);
-        let ds_y_full = (yf * SCREEN_HEIGHT_BOTH as f32)
-            .floor()
-            .clamp(0.0, (SCREEN_HEIGHT_BOTH - 1) as f32);
-        if ds_y_full < SCREEN_HEIGHT as f32 {
+        let (logical_w, logical_h) = present::oriented(SCREEN_WIDTH, height);
+        let x = (xf * logical_w as f32).clamp(0.5, logical_w as f32 - 0.5);
+        let y = (yf * logical_h as f32).clamp(0.5, logical_h as f32 - 0.5);
+        self.touch_from_logical(x, y)
+    }
+
+    /// Maps a point on the touch window's logical size, turned like the picture, onto the
+    /// touch screen. The top screen above it does not take touches.
+    fn touch_from_logical(&self, x: f32, y: f32) -> Option<(u16, u16)> {
+        let (_, height, offset) = self.touch_area();
+        let (ds_x, ds_y) = present::unrotate(x, y, SCREEN_WIDTH as f32, height as f32);
+        let ds_y = ds_y - offset as f32;
+        if !(0.0..SCREEN_WIDTH as f32).contains(&ds_x)
+            || !(0.0..SCREEN_HEIGHT as f32).contains(&ds_y)
+        {
             return None;
         }
-        let ds_y = (ds_y_full - SCREEN_HEIGHT as f32).clamp(0.0, (SCREEN_HEIGHT - 1) as f32);
-
         Some((ds_x as u16, ds_y as u16))
     }
 }
@@ -0,0 +1,32 @@
+// desmume-rs only wraps DeSmuME's C interface, which has no slot-2 entry points.
+// This shim forwards to the C++ slot-2 API that is already linked in via desmume-sys.
+#include <string>
+
+enum NDS_SLOT2_TYPE
+{
+	NDS_SLOT2_NONE,
+	NDS_SLOT2_AUTO,
+	NDS_SLOT2_CFLASH,
+	NDS_SLOT2_RUMBLEPAK,
+	NDS_SLOT2_GBACART,
+	NDS_SLOT2_GUITARGRIP,
+	NDS_SLOT2_EXPMEMORY,
+	NDS_SLOT2_EASYPIANO,
+	NDS_SLOT2_PADDLE,
+	NDS_SLOT2_PASSME,
+	NDS_SLOT2_COUNT
+};
+
+extern std::string GBACartridge_RomPath;
+extern std::string GBACartridge_SRAMPath;
+bool slot2_Change(NDS_SLOT2_TYPE type);
+
+extern "C" int retro_nds_slot2_change(int device, const char *rom_path, const char *sram_path)
+{
+	if (device < 0 || device >= NDS_SLOT2_COUNT)
+		return 0;
+
+	GBACartridge_RomPath = rom_path ? rom_path : "";
+	GBACartridge_SRAMPath = sram_path ? sram_path : "";
+	return slot2_Change(static_cast<NDS_SLOT2_TYPE>(device)) ? 1 : 0;
+}
@@ -0,0 +1,32 @@
+// Local wireless (NiFi) bridge for DeSmuME.
+// DeSmuME ships an ad-hoc transport that broadcasts 802.11 frames over UDP port 7000, but
+// desmume-sys builds it without EXPERIMENTAL_WIFI_COMM, which turns SetEmulationLevel into a
+// no-op. This shim selects the emulation level directly and gives the console its own MAC
+// address so two launcher instances do not filter each other's frames as loopback traffic.
+#include "NDSSystem.h"
+#include "wifi.h"
+
+// WifiHandler::_selectedEmulationLevel is private and has no ungated setter. Explicit
+// template instantiation is exempt from access checks, which lets us name the member.
+WifiEmulationLevel WifiHandler::*selected_emulation_level();
+
+template <WifiEmulationLevel WifiHandler::*Member>
+struct SelectedEmulationLevel
+{
+	friend WifiEmulationLevel WifiHandler::*selected_emulation_level() { return Member; }
+};
+
+template struct SelectedEmulationLevel<&WifiHandler::_selectedEmulationLevel>;
+
+extern "C" int retro_nds_wifi_enable(const unsigned char *mac)
+{
+	if (wifiHandler == NULL || mac == NULL)
+		return 0;
+
+	for (int i = 0; i < 6; i++)
+		CommonSettings.fwConfig.MACAddress[i] = mac[i];
+
+	// Picked up by WifiHandler::CommStart() during the next NDS_Reset().
+	wifiHandler->*selected_emulation_level() = WifiEmulationLevel_Normal;
+	return 1;
+}
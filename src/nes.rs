@@ -1,6 +1,7 @@
+use std::borrow::Cow;
 use std::collections::HashSet;
 use std::path::Path;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use anyhow::{Context, Result, anyhow};
 use gc_nes_core::cartridge::Cartridge;
@@ -11,19 +12,48 @@ use sdl2::pixels::PixelFormatEnum;
 use sdl2::render::{Canvas, Texture};
 use sdl2::video::Window;
 
+use crate::bezel::Bezel;
+use crate::config::{Config, DisplayConfig};
 use crate::controller::{ControllerManager, VirtualButton};
+use crate::focus_pause::{self, FocusPause};
+use crate::frame_pacer::FramePacer;
+use crate::movie::{self, InputMovie};
+use crate::perf_overlay::PerfOverlay;
+use crate::present;
+use crate::scenario::Scenario;
+use crate::screenshot;
+use crate::systems::GameSystem;
 
 const WIDTH: usize = 256;
 const HEIGHT: usize = 240;
 const TARGET_FRAME: Duration = Duration::from_micros(16_667);
+/// Input movie bits of each bit of the controller byte, A in bit 0 to Right in bit 7
+const MOVIE_BITS: [u8; 8] = [
+    movie::A,
+    movie::B,
+    movie::SELECT,
+    movie::START,
+    movie::UP,
+    movie::DOWN,
+    movie::LEFT,
+    movie::RIGHT,
+];
 
-pub fn run(rom: &Path, scale: u32, limit_fps: bool) -> Result<()> {
+pub fn run(
+    rom: &Path,
+    scale: u32,
+    limit_fps: bool,
+    movie: Option<InputMovie>,
+    scenario: Option<Scenario>,
+    config: &Config,
+) -> Result<()> {
     let cartridge = Cartridge::load_from_file(rom)
         .map_err(|err| anyhow!("failed to load NES ROM {}: {err}", rom.display()))?;
     let mut nes = Nes::new(cartridge);
     let title = rom.file_stem().and_then(|s| s.to_str()).unwrap_or("NES");
 
-    let mut frontend = NesFrontend::new(title, scale.max(1), limit_fps)?;
+    let mut frontend = NesFrontend::new(title, scale.max(1), limit_fps, movie, config)?;
+    frontend.scenario = scenario;
     frontend.run(&mut nes)
 }
 
@@ -34,38 +64,55 @@ struct NesFrontend {
     event_pump: sdl2::EventPump,
     pressed: HashSet<Keycode>,
     limit_fps: bool,
+    display: DisplayConfig,
     argb_buffer: Vec<u32>,
     controller: ControllerManager,
+    movie: Option<InputMovie>,
+    scenario: Option<Scenario>,
+    /// Names the game's screenshots
+    label: String,
+    bezel: Option<Bezel>,
+    perf: PerfOverlay,
+    focus: FocusPause,
+    /// Frames run so far
+    frame: u64,
 }
 
 impl NesFrontend {
-    fn new(title: &str, scale: u32, limit_fps: bool) -> Result<Self> {
+    fn new(
+        title: &str,
+        scale: u32,
+        limit_fps: bool,
+        movie: Option<InputMovie>,
+        config: &Config,
+    ) -> Result<Self> {
         let sdl = sdl2::init().map_err(|e| anyhow!(e))?;
         let video = sdl.video().map_err(|e| anyhow!(e))?;
-        let scaled_w = (WIDTH as u32).saturating_mul(scale.max(1));
-        let scaled_h = (HEIGHT as u32).saturating_mul(scale.max(1));
+        let (scaled_w, scaled_h) = present::oriented(
+            (WIDTH as u32).saturating_mul(scale.max(1)),
+            (HEIGHT as u32).saturating_mul(scale.max(1)),
+        );
         let window = video
             .window(title, scaled_w, scaled_h)
             .position_centered()
             .resizable()
+            .allow_highdpi()
             .build()
             .context("failed to create NES window")?;
 
-        let mut canvas_builder = window.into_canvas();
-        if limit_fps {
-            canvas_builder = canvas_builder.present_vsync();
-        }
-        let mut canvas = canvas_builder.build().map_err(|e| anyhow!(e))?;
-        canvas
-            .set_logical_size(WIDTH as u32, HEIGHT as u32)
+        let mut canvas =
+            present::canvas(window, limit_fps, &config.display).map_err(|e| anyhow!(e))?;
+        present::apply_fullscreen(canvas.window_mut());
+        present::set_logical_size(&mut canvas, WIDTH as u32, HEIGHT as u32)
             .context("failed to set NES logical size")?;
+        let bezel = Bezel::load(&canvas, &config.display, GameSystem::Nes, title);
 
         let texture_creator = canvas.texture_creator();
         let texture = texture_creator
             .create_texture_streaming(PixelFormatEnum::ARGB8888, WIDTH as u32, HEIGHT as u32)
             .map_err(|e| anyhow!(e))?;
         let event_pump = sdl.event_pump().map_err(|e| anyhow!(e))?;
-        let controller = ControllerManager::new(&sdl)?;
+        let controller = ControllerManager::new(&sdl, &config.controllers)?;
 
         Ok(Self {
             _sdl: sdl,
@@ -74,17 +121,28 @@ impl NesFrontend {
             event_pump,
             pressed: HashSet::new(),
             limit_fps,
+            display: config.display.clone(),
             argb_buffer: vec![0; NES_SCREEN_DIMENSIONS],
             controller,
+            movie,
+            scenario: None,
+            label: title.to_string(),
+            bezel,
+            perf: PerfOverlay::new(TARGET_FRAME, &config.display),
+            focus: FocusPause::new(&config.display),
+            frame: 0,
         })
     }
 
     fn run(&mut self, nes: &mut Nes) -> Result<()> {
         let mut running = true;
-        let mut last_frame = Instant::now();
+        let mut pacer = FramePacer::new(TARGET_FRAME, self.limit_fps)
+            .vsynced(self.canvas.window(), &self.display);
         while running {
             for event in self.event_pump.poll_iter() {
                 self.controller.handle_event(&event);
+                self.perf.handle_sdl_event(&event);
+                self.focus.handle_sdl_event(&event);
                 match event {
                     Event::Quit { .. } => running = false,
                     Event::KeyDown {
@@ -96,77 +154,144 @@ impl NesFrontend {
                         repeat: false,
                         ..
                     } => {
-                        self.pressed.insert(code);
+                        self.pressed.insert(present::game_arrow_key(code));
                     }
                     Event::KeyUp {
                         keycode: Some(code),
                         ..
                     } => {
-                        self.pressed.remove(&code);
+                        self.pressed.remove(&present::game_arrow_key(code));
                     }
                     _ => {}
                 }
             }
+            if self.focus.is_paused() {
+                focus_pause::idle();
+                continue;
+            }
+
+            if self
+                .scenario
+                .as_ref()
+                .is_some_and(|scenario| scenario.quits_before(self.frame))
+            {
+                log::info!("Scenario finished after {} frames", self.frame);
+                break;
+            }
 
-            nes.update_controller_one(Some(controller_state(&self.pressed, &self.controller)));
+            self.controller.advance_frame();
+            self.controller.advance_macros();
+            let mut state = controller_state(&self.pressed, &self.controller);
+            if let Some(scenario) = &self.scenario {
+                state |= movie_to_state(scenario.buttons(self.frame));
+            }
+            if let Some(movie) = self.movie.as_mut() {
+                match movie.next_frame(state_to_movie(state))? {
+                    Some(recorded) => state = movie_to_state(recorded),
+                    None => {
+                        log::info!("Input movie finished, the controls are yours");
+                        self.movie = None;
+                    }
+                }
+            }
+            nes.update_controller_one(Some(state));
             let frame = nes.frame();
+            self.perf.frame();
             self.present_frame(frame)?;
-
-            if self.limit_fps {
-                let elapsed = last_frame.elapsed();
-                if elapsed < TARGET_FRAME {
-                    std::thread::sleep(TARGET_FRAME - elapsed);
+            if self
+                .scenario
+                .as_ref()
+                .is_some_and(|scenario| scenario.screenshot_after(self.frame))
+            {
+                match screenshot::save(&self.label, WIDTH as u32, HEIGHT as u32, &self.argb_buffer)
+                {
+                    Ok(path) => log::info!("Scenario saved {}", path.display()),
+                    Err(err) => log::warn!("Scenario screenshot failed: {err:#}"),
                 }
             }
-            last_frame = Instant::now();
+            self.frame += 1;
+
+            pacer.wait();
+        }
+        match self.movie.take() {
+            Some(movie) => movie.finish(),
+            None => Ok(()),
         }
-        Ok(())
     }
 
     fn present_frame(&mut self, pixels: &[u32; NES_SCREEN_DIMENSIONS]) -> Result<()> {
         for (dst, src) in self.argb_buffer.iter_mut().zip(pixels.iter()) {
             *dst = 0xFF00_0000 | *src;
         }
+        // drawn on a copy, scenario screenshots keep the game alone
+        let mut shown = Cow::Borrowed(&self.argb_buffer[..]);
+        if self.perf.is_open() {
+            self.perf.draw(shown.to_mut(), WIDTH);
+        }
         self.texture
-            .update(None, bytemuck::cast_slice(&self.argb_buffer), WIDTH * 4)
+            .update(None, bytemuck::cast_slice(&shown), WIDTH * 4)
             .context("failed to upload NES frame")?;
         self.canvas.clear();
-        self.canvas
-            .copy(&self.texture, None, None)
-            .map_err(|e| anyhow!(e))?;
+        if let Some(bezel) = &self.bezel {
+            bezel.draw(&mut self.canvas).map_err(|e| anyhow!(e))?;
+        }
+        present::copy(&mut self.canvas, &self.texture, None).map_err(|e| anyhow!(e))?;
         self.canvas.present();
         Ok(())
     }
 }
 
+fn state_to_movie(state: u8) -> u8 {
+    MOVIE_BITS
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| state & (1 << bit) != 0)
+        .fold(0, |mask, (_, movie_bit)| mask | movie_bit)
+}
+
+fn movie_to_state(mask: u8) -> u8 {
+    MOVIE_BITS
+        .iter()
+        .enumerate()
+        .filter(|(_, movie_bit)| mask & *movie_bit != 0)
+        .fold(0, |state, (bit, _)| state | (1 << bit))
+}
+
 fn controller_state(pressed: &HashSet<Keycode>, controllers: &ControllerManager) -> u8 {
     let mut state = 0u8;
-    if pressed.contains(&Keycode::X) {
+    let key = |button, keys: &[Keycode]| {
+        controllers.turbo(button, keys.iter().any(|key| pressed.contains(key)))
+    };
+    if key(VirtualButton::A, &[Keycode::X]) {
         state |= 0b0000_0001;
     }
-    if pressed.contains(&Keycode::Z) {
+    if key(VirtualButton::B, &[Keycode::Z]) {
         state |= 0b0000_0010;
     }
-    if pressed.contains(&Keycode::Space)
-        || pressed.contains(&Keycode::Backspace)
-        || pressed.contains(&Keycode::RShift)
-        || pressed.contains(&Keycode::LShift)
-    {
+    if key(
+        VirtualButton::Select,
+        &[
+            Keycode::Space,
+            Keycode::Backspace,
+            Keycode::RShift,
+            Keycode::LShift,
+        ],
+    ) {
         state |= 0b0000_0100;
     }
-    if pressed.contains(&Keycode::Return) {
+    if key(VirtualButton::Start, &[Keycode::Return]) {
         state |= 0b0000_1000;
     }
-    if pressed.contains(&Keycode::Up) {
+    if key(VirtualButton::Up, &[Keycode::Up]) {
         state |= 0b0001_0000;
     }
-    if pressed.contains(&Keycode::Down) {
+    if key(VirtualButton::Down, &[Keycode::Down]) {
         state |= 0b0010_0000;
     }
-    if pressed.contains(&Keycode::Left) {
+    if key(VirtualButton::Left, &[Keycode::Left]) {
         state |= 0b0100_0000;
     }
-    if pressed.contains(&Keycode::Right) {
+    if key(VirtualButton::Right, &[Keycode::Right]) {
         state |= 0b1000_0000;
     }
 
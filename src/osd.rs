@@ -0,0 +1,22 @@
+use crate::c64_keyboard::{draw_text, fill_rect};
+
+const BACKGROUND: u32 = 0xE010_1830;
+const TEXT: u32 = 0xFFE0_E0E0;
+
+/// Draws a short message, like the volume after a hotkey, into the bottom left corner of an
+/// ARGB frame
+pub fn draw_message(pixels: &mut [u32], width: usize, text: &str) {
+    let height = pixels.len() / width.max(1);
+    let box_height = 14;
+    let top = height.saturating_sub(box_height + 4);
+    fill_rect(
+        pixels,
+        width,
+        4,
+        top,
+        text.len() * 8 + 8,
+        box_height,
+        BACKGROUND,
+    );
+    draw_text(pixels, width, 8, top + 3, text, TEXT);
+}
@@ -0,0 +1,136 @@
+use std::time::{Duration, Instant};
+
+use crate::c64_keyboard::{draw_text, fill_rect};
+use crate::controller::ControllerManager;
+
+/// Opens and closes the overlay from the keyboard
+pub const HOTKEY_NAME: &str = "F8";
+/// How long the overlay stays up after a pad connects or disconnects, unless it is used
+const AUTO_CLOSE: Duration = Duration::from_secs(5);
+const BACKGROUND: u32 = 0xE010_1830;
+const TEXT: u32 = 0xFFE0_E0E0;
+const HIGHLIGHT: u32 = 0xFFFF_D040;
+/// Pad names are cut to fit a 320 pixel wide frame
+const NAME_CHARS: usize = 28;
+
+/// Keys the overlay reacts to, translated from SDL or winit by the frontend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayKey {
+    Up,
+    Down,
+    Left,
+    Right,
+    Close,
+}
+
+/// Shows which pad drives each player and lets them be reassigned mid-game. Up/Down pick a
+/// player and Left/Right swap its pad with the previous/next player's. It opens on its own
+/// when a pad connects or disconnects and closes again after a while if left alone.
+pub struct PadOverlay {
+    players: usize,
+    open: bool,
+    selected: usize,
+    /// Set while opened by a hotplug and not touched since
+    auto_close: Option<Instant>,
+}
+
+impl PadOverlay {
+    pub fn new(players: usize) -> Self {
+        Self {
+            players: players.max(1),
+            open: false,
+            selected: 0,
+            auto_close: None,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.auto_close = None;
+    }
+
+    /// Opens the overlay when a pad came or went, and closes an untouched one once it has
+    /// been up long enough. Frontends call this once per frame.
+    pub fn update(&mut self, controllers: &mut ControllerManager) {
+        if controllers.take_pads_changed() && (!self.open || self.auto_close.is_some()) {
+            self.open = true;
+            self.auto_close = Some(Instant::now() + AUTO_CLOSE);
+        }
+        if self
+            .auto_close
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            self.open = false;
+            self.auto_close = None;
+        }
+    }
+
+    /// Handles a key while the overlay is open, returns whether it was used
+    pub fn handle_key(&mut self, key: OverlayKey, controllers: &mut ControllerManager) -> bool {
+        if !self.open {
+            return false;
+        }
+        self.auto_close = None;
+        let last = self.players - 1;
+        match key {
+            OverlayKey::Up => self.selected = self.selected.saturating_sub(1),
+            OverlayKey::Down => self.selected = (self.selected + 1).min(last),
+            OverlayKey::Left if self.selected > 0 => {
+                controllers.swap_pads(self.selected, self.selected - 1);
+                self.selected -= 1;
+            }
+            OverlayKey::Right if self.selected < last => {
+                controllers.swap_pads(self.selected, self.selected + 1);
+                self.selected += 1;
+            }
+            OverlayKey::Close => self.open = false,
+            OverlayKey::Left | OverlayKey::Right => {}
+        }
+        true
+    }
+
+    /// One line per player, the selected one marked with `>`
+    pub fn lines(&self, controllers: &ControllerManager) -> Vec<String> {
+        (0..self.players)
+            .map(|player| {
+                let name = controllers
+                    .pad_name(player)
+                    .unwrap_or_else(|| "keyboard only".to_string());
+                format!(
+                    "{}P{} {}",
+                    if player == self.selected { '>' } else { ' ' },
+                    player + 1,
+                    name.chars().take(NAME_CHARS).collect::<String>()
+                )
+            })
+            .collect()
+    }
+
+    /// Draws the overlay into the top left corner of an ARGB frame
+    pub fn draw(&self, pixels: &mut [u32], width: usize, controllers: &ControllerManager) {
+        let mut lines = vec![format!("PADS  {HOTKEY_NAME} closes")];
+        lines.extend(self.lines(controllers));
+        let box_width = lines.iter().map(|line| line.len() * 8).max().unwrap_or(0) + 8;
+        fill_rect(
+            pixels,
+            width,
+            0,
+            0,
+            box_width,
+            lines.len() * 10 + 6,
+            BACKGROUND,
+        );
+        for (row, line) in lines.iter().enumerate() {
+            let color = if row == self.selected + 1 {
+                HIGHLIGHT
+            } else {
+                TEXT
+            };
+            draw_text(pixels, width, 4, 4 + row * 10, line, color);
+        }
+    }
+}
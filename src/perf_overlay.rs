@@ -0,0 +1,184 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+
+use crate::audio::AudioPlayer;
+use crate::c64_keyboard::{draw_text, fill_rect};
+use crate::config::DisplayConfig;
+
+/// Shows and hides the overlay, everywhere but on the C64 whose F3 is a key of its own
+pub const HOTKEY: Keycode = Keycode::F3;
+/// Frames the graph and the averages span, two seconds at 60 Hz
+const HISTORY: usize = 120;
+/// The graph's full height is two console frames, the target sits in the middle
+const GRAPH_HEIGHT: usize = 24;
+/// Frames taking this much longer than the console's are drawn as hitches
+const HITCH: f64 = 1.5;
+const BACKGROUND: u32 = 0xE010_1830;
+const TEXT: u32 = 0xFFE0_E0E0;
+const BAR: u32 = 0xFF40_C060;
+const HITCH_BAR: u32 = 0xFFE0_4040;
+const TARGET_LINE: u32 = 0xFF60_6880;
+
+/// Frame rate, a graph of recent frame times, queued audio and emulation speed, drawn into
+/// the top right corner of the frame. Renderers without a frame to draw into show
+/// [`Self::summary`] in the window title instead.
+pub struct PerfOverlay {
+    open: bool,
+    /// How long a frame lasts on the console
+    target: Duration,
+    last_frame: Option<Instant>,
+    /// Time between the latest frames, oldest first
+    frame_times: VecDeque<Duration>,
+    /// Seconds of audio queued and the most that may be, for frontends playing through an
+    /// [`AudioPlayer`]
+    audio: Option<(f64, f64)>,
+}
+
+impl PerfOverlay {
+    /// Measures against a console running frames of `target` each, open from the start when
+    /// `[display] perf_overlay` or `--perf-overlay` asks
+    pub fn new(target: Duration, display: &DisplayConfig) -> Self {
+        Self {
+            open: display.perf_overlay,
+            target,
+            last_frame: None,
+            frame_times: VecDeque::with_capacity(HISTORY),
+            audio: None,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    /// Changes the console's frame time, for cores that switch video modes
+    pub fn set_target(&mut self, target: Duration) {
+        self.target = target;
+    }
+
+    /// Toggles the overlay on [`HOTKEY`]
+    pub fn handle_sdl_event(&mut self, event: &Event) {
+        if let Event::KeyDown {
+            keycode: Some(HOTKEY),
+            repeat: false,
+            ..
+        } = event
+        {
+            self.toggle();
+        }
+    }
+
+    /// Records a finished frame, frontends call this once per frame they show. Frames are
+    /// timed while the overlay is closed too, so it opens with a full graph.
+    pub fn frame(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_frame.replace(now) {
+            if self.frame_times.len() == HISTORY {
+                self.frame_times.pop_front();
+            }
+            self.frame_times.push_back(now - last);
+        }
+    }
+
+    /// Takes the audio queue level for the next drawing
+    pub fn set_audio(&mut self, audio: &AudioPlayer) {
+        self.audio = Some((audio.buffered_seconds(), audio.max_latency_seconds()));
+    }
+
+    fn average(&self) -> Option<Duration> {
+        let frames = self.frame_times.len() as u32;
+        (frames > 0).then(|| self.frame_times.iter().sum::<Duration>() / frames)
+    }
+
+    /// Frames shown per second, averaged over the graph
+    pub fn fps(&self) -> f64 {
+        self.average()
+            .filter(|average| !average.is_zero())
+            .map_or(0.0, |average| 1.0 / average.as_secs_f64())
+    }
+
+    /// Emulated time per real time in percent, 100 is full speed
+    pub fn speed(&self) -> f64 {
+        self.fps() * self.target.as_secs_f64() * 100.0
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let frame_ms = self
+            .average()
+            .map_or(0.0, |average| average.as_secs_f64() * 1000.0);
+        let mut lines = vec![
+            format!("{:.1} FPS {:.0}%", self.fps(), self.speed()),
+            format!("{frame_ms:.1} ms frame"),
+        ];
+        if let Some((queued, max)) = self.audio {
+            lines.push(format!(
+                "audio {:.0} ms {:.0}%",
+                queued * 1000.0,
+                queued / max * 100.0
+            ));
+        }
+        lines
+    }
+
+    /// The lines on one row, for the window title
+    pub fn summary(&self) -> String {
+        self.lines().join(" | ")
+    }
+
+    /// Draws the overlay into the top right corner of an ARGB frame
+    pub fn draw(&self, pixels: &mut [u32], width: usize) {
+        let lines = self.lines();
+        let text_width = lines.iter().map(|line| line.len() * 8).max().unwrap_or(0);
+        let box_width = text_width.max(HISTORY) + 8;
+        let graph_top = lines.len() * 10 + 6;
+        let left = width.saturating_sub(box_width);
+        fill_rect(
+            pixels,
+            width,
+            left,
+            0,
+            box_width,
+            graph_top + GRAPH_HEIGHT + 4,
+            BACKGROUND,
+        );
+        for (row, line) in lines.iter().enumerate() {
+            draw_text(pixels, width, left + 4, 4 + row * 10, line, TEXT);
+        }
+
+        let graph_bottom = graph_top + GRAPH_HEIGHT;
+        fill_rect(
+            pixels,
+            width,
+            left + 4,
+            graph_bottom - GRAPH_HEIGHT / 2,
+            HISTORY,
+            1,
+            TARGET_LINE,
+        );
+        let target = self.target.as_secs_f64().max(f64::EPSILON);
+        // the newest frame on the right, a short history leaves the left empty
+        let start = left + 4 + HISTORY - self.frame_times.len();
+        for (column, frame_time) in self.frame_times.iter().enumerate() {
+            let ratio = frame_time.as_secs_f64() / target;
+            let height =
+                ((ratio * (GRAPH_HEIGHT / 2) as f64).round() as usize).clamp(1, GRAPH_HEIGHT);
+            let color = if ratio > HITCH { HITCH_BAR } else { BAR };
+            fill_rect(
+                pixels,
+                width,
+                start + column,
+                graph_bottom - height,
+                1,
+                height,
+                color,
+            );
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+
+use sdl2::IntegerOrSdlError;
+use sdl2::keyboard::{Keycode, Scancode};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::{FullscreenType, SwapInterval, VideoSubsystem, Window, WindowPos};
+use winit::dpi::{LogicalSize, PhysicalSize, Size};
+use winit::event_loop::ActiveEventLoop;
+
+use crate::config::{DisplayConfig, Fullscreen};
+use crate::controller::VirtualButton;
+
+/// Directions in clockwise order, a turn of the picture moves each along by its quarters
+const DIRECTIONS: [VirtualButton; 4] = [
+    VirtualButton::Up,
+    VirtualButton::Right,
+    VirtualButton::Down,
+    VirtualButton::Left,
+];
+const ARROW_KEYS: [Keycode; 4] = [Keycode::Up, Keycode::Right, Keycode::Down, Keycode::Left];
+const ARROW_SCANCODES: [Scancode; 4] = [
+    Scancode::Up,
+    Scancode::Right,
+    Scancode::Down,
+    Scancode::Left,
+];
+
+/// DPI a display reports at 100% scaling on Windows and X11
+const BASE_DPI: f32 = 96.0;
+
+/// Clockwise turn of every game picture in degrees, kept here so frontends and the pad code
+/// do not reload the config on each frame
+static ROTATION: AtomicU16 = AtomicU16::new(0);
+static ROTATE_INPUT: AtomicBool = AtomicBool::new(false);
+/// How the game window fills the screen, resolved for the game's system by main
+static FULLSCREEN: Mutex<Fullscreen> = Mutex::new(Fullscreen::Off);
+
+/// Renderer of a game window. A limited frame rate syncs presents to the display, except on a
+/// variable refresh display: waiting for a fixed refresh there judders on 144 Hz and faster
+/// screens, so the frame goes out once the frontend's pacing lets it and the display follows.
+/// The window is grown for a HiDPI display first, see [`fit_to_display`].
+pub fn canvas(
+    mut window: Window,
+    limit_fps: bool,
+    display: &DisplayConfig,
+) -> Result<Canvas<Window>, IntegerOrSdlError> {
+    fit_to_display(&mut window, display);
+    let builder = window.into_canvas();
+    if limit_fps && !display.vrr {
+        builder.present_vsync().build()
+    } else {
+        builder.build()
+    }
+}
+
+/// Turns on adaptive vsync for the current GL context on a variable refresh display, returns
+/// whether it took. Drivers without it keep the swap interval they had.
+pub fn adaptive_gl_sync(video: &VideoSubsystem, display: &DisplayConfig) -> bool {
+    if !display.vrr {
+        return false;
+    }
+    match video.gl_set_swap_interval(SwapInterval::LateSwapTearing) {
+        Ok(()) => true,
+        Err(err) => {
+            log::debug!("Adaptive vsync is not supported: {err}");
+            false
+        }
+    }
+}
+
+/// Sets the turn of the picture and whether directions follow it, main applies `[display]
+/// rotate` or `--rotate` at startup
+pub fn set_rotation(degrees: u16, rotate_input: bool) {
+    ROTATION.store(degrees % 360, Ordering::Relaxed);
+    ROTATE_INPUT.store(rotate_input, Ordering::Relaxed);
+}
+
+pub fn rotation() -> u16 {
+    ROTATION.load(Ordering::Relaxed)
+}
+
+/// Whether the picture lies on its side, so its width runs down the screen
+pub fn quarter_turn() -> bool {
+    rotation() % 180 == 90
+}
+
+/// Size on screen of a `width` x `height` picture once turned
+pub fn oriented(width: u32, height: u32) -> (u32, u32) {
+    if quarter_turn() {
+        (height, width)
+    } else {
+        (width, height)
+    }
+}
+
+/// Sets the logical size of a canvas showing a `width` x `height` picture, turned
+pub fn set_logical_size(
+    canvas: &mut Canvas<Window>,
+    width: u32,
+    height: u32,
+) -> Result<(), IntegerOrSdlError> {
+    let (width, height) = oriented(width, height);
+    canvas.set_logical_size(width, height)
+}
+
+/// Copies a whole texture turned into `area`, or over the logical size (the whole window
+/// without one) when `area` is `None`. `area` is the space the turned picture fills.
+pub fn copy(
+    canvas: &mut Canvas<Window>,
+    texture: &Texture,
+    area: Option<Rect>,
+) -> Result<(), String> {
+    let degrees = rotation();
+    if degrees == 0 {
+        return canvas.copy(texture, None, area);
+    }
+    let area = match area {
+        Some(area) => area,
+        None => {
+            let (width, height) = match canvas.logical_size() {
+                (0, 0) => canvas.output_size()?,
+                size => size,
+            };
+            Rect::new(0, 0, width, height)
+        }
+    };
+    // SDL turns the destination around its centre, so a picture on its side is laid out
+    // lying down first
+    let (width, height) = oriented(area.width(), area.height());
+    let mut target = Rect::new(0, 0, width, height);
+    target.center_on(area.center());
+    canvas.copy_ex(
+        texture,
+        None,
+        target,
+        f64::from(degrees),
+        None,
+        false,
+        false,
+    )
+}
+
+/// Quarter turns that directions move by, none unless `[display] rotate_input` asks
+fn input_quarters() -> usize {
+    if ROTATE_INPUT.load(Ordering::Relaxed) {
+        usize::from(rotation() / 90)
+    } else {
+        0
+    }
+}
+
+/// The pad direction that steers the game towards its own `button`, as seen on the turned
+/// screen. Anything but a direction is returned as is.
+pub fn screen_direction(button: VirtualButton) -> VirtualButton {
+    match DIRECTIONS.iter().position(|&direction| direction == button) {
+        Some(index) => DIRECTIONS[(index + input_quarters()) % 4],
+        None => button,
+    }
+}
+
+/// The arrow key a game expects for `code` pressed on the turned screen, frontends pass key
+/// presses and releases through this before looking them up. Other keys are returned as is.
+pub fn game_arrow_key(code: Keycode) -> Keycode {
+    match ARROW_KEYS.iter().position(|&key| key == code) {
+        Some(index) => ARROW_KEYS[(index + 4 - input_quarters()) % 4],
+        None => code,
+    }
+}
+
+/// The arrow key that steers the game towards its own `code` on the turned screen, for
+/// frontends that poll the keyboard state. Other keys are returned as is.
+pub fn screen_arrow_scancode(code: Scancode) -> Scancode {
+    match ARROW_SCANCODES.iter().position(|&key| key == code) {
+        Some(index) => ARROW_SCANCODES[(index + input_quarters()) % 4],
+        None => code,
+    }
+}
+
+/// Maps a point on the turned picture back onto the `width` x `height` picture, for pointers
+/// and touch screens
+pub fn unrotate(x: f32, y: f32, width: f32, height: f32) -> (f32, f32) {
+    match rotation() {
+        90 => (y, height - x),
+        180 => (width - x, height - y),
+        270 => (width - y, x),
+        _ => (x, y),
+    }
+}
+
+/// Shows the picture upright for a renderer that cannot turn it, directions then stay as
+/// they are too
+pub fn unsupported_rotation(renderer: &str) {
+    if rotation() != 0 {
+        log::warn!("{renderer} cannot rotate the picture, showing it upright");
+        set_rotation(0, false);
+    }
+}
+
+/// Makes SDL windows DPI aware, main calls this before any core starts SDL's video. Windows
+/// otherwise stretches every window as a bitmap, which blurs the picture.
+pub fn init_hidpi() {
+    sdl2::hint::set("SDL_WINDOWS_DPI_AWARENESS", "permonitorv2");
+}
+
+/// How much larger than its pixel size `window` should be on its display, `None` where window
+/// sizes count points (macOS, Wayland) and SDL gives the window a larger drawable already.
+/// Reported DPIs are rounded to quarter steps, they rarely land on the desktop's exact scale.
+fn window_scale(window: &Window, display: &DisplayConfig) -> Option<f32> {
+    if let Some(factor) = display.scale_factor {
+        return Some(factor);
+    }
+    if window.drawable_size() != window.size() {
+        return None;
+    }
+    let display = window.display_index().ok()?;
+    let (_, dpi, _) = window.subsystem().display_dpi(display).ok()?;
+    Some(((dpi / BASE_DPI * 4.0).round() / 4.0).max(1.0))
+}
+
+/// Sizes `window` to show a `width` x `height` picture, grown by the display's scale so it does
+/// not come up tiny on a HiDPI screen
+pub fn set_window_size(
+    window: &mut Window,
+    width: u32,
+    height: u32,
+    display: &DisplayConfig,
+) -> Result<(), IntegerOrSdlError> {
+    let scale = window_scale(window, display).unwrap_or(1.0);
+    let scaled = |size: u32| ((size as f32 * scale).round() as u32).max(1);
+    window.set_size(scaled(width), scaled(height))
+}
+
+/// Grows a window just built at its picture's size for the display, keeping it centred where
+/// it was. Frontends presenting through [`canvas`] get this already.
+pub fn fit_to_display(window: &mut Window, display: &DisplayConfig) {
+    let (width, height) = window.size();
+    let (x, y) = window.position();
+    if let Err(err) = set_window_size(window, width, height, display) {
+        log::debug!("Failed to size the window for the display: {err}");
+        return;
+    }
+    let (new_width, new_height) = window.size();
+    let shift = |old: u32, new: u32| (i64::from(old) - i64::from(new)) as i32 / 2;
+    window.set_position(
+        WindowPos::Positioned(x + shift(width, new_width)),
+        WindowPos::Positioned(y + shift(height, new_height)),
+    );
+}
+
+/// Inner size of a winit window showing a `width` x `height` picture. winit takes logical
+/// pixels and grows them by the display's scale itself, `[display] scale_factor` replaces that.
+pub fn winit_size(width: u32, height: u32, display: &DisplayConfig) -> Size {
+    match display.scale_factor {
+        Some(factor) => {
+            let scaled = |size: u32| ((size as f32 * factor).round() as u32).max(1);
+            PhysicalSize::new(scaled(width), scaled(height)).into()
+        }
+        None => LogicalSize::new(width, height).into(),
+    }
+}
+
+/// Sets how the game window fills the screen, main picks `--fullscreen` or the game's system's
+/// `[display]` setting
+pub fn set_fullscreen(mode: Fullscreen) {
+    *FULLSCREEN.lock().unwrap_or_else(|err| err.into_inner()) = mode;
+}
+
+pub fn fullscreen() -> Fullscreen {
+    *FULLSCREEN.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// The configured fullscreen mode as SDL names it
+pub fn sdl_fullscreen() -> FullscreenType {
+    match fullscreen() {
+        Fullscreen::Off => FullscreenType::Off,
+        Fullscreen::Borderless => FullscreenType::Desktop,
+        Fullscreen::Exclusive => FullscreenType::True,
+    }
+}
+
+/// Puts a game's main window into the configured fullscreen mode, extra windows such as the
+/// DS touch screen stay windowed
+pub fn apply_fullscreen(window: &mut Window) {
+    let mode = sdl_fullscreen();
+    if mode == FullscreenType::Off {
+        return;
+    }
+    if let Err(err) = window.set_fullscreen(mode) {
+        log::warn!("Failed to go fullscreen: {err}");
+    }
+}
+
+/// The configured fullscreen mode for a winit window. Exclusive takes the largest mode of the
+/// primary monitor at its highest refresh rate.
+pub fn winit_fullscreen(event_loop: &ActiveEventLoop) -> Option<winit::window::Fullscreen> {
+    match fullscreen() {
+        Fullscreen::Off => None,
+        Fullscreen::Borderless => Some(winit::window::Fullscreen::Borderless(None)),
+        Fullscreen::Exclusive => {
+            let monitor = event_loop
+                .primary_monitor()
+                .or_else(|| event_loop.available_monitors().next())?;
+            let mode = monitor.video_modes().max_by_key(|mode| {
+                let size = mode.size();
+                (size.width * size.height, mode.refresh_rate_millihertz())
+            });
+            match mode {
+                Some(mode) => Some(winit::window::Fullscreen::Exclusive(mode)),
+                None => Some(winit::window::Fullscreen::Borderless(Some(monitor))),
+            }
+        }
+    }
+}
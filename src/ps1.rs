@@ -1,54 +1,84 @@
 use std::{
-    env, hint,
+    borrow::Cow,
+    fs, iter,
     path::{Path, PathBuf},
     sync::Arc,
-    thread,
     time::{Duration, Instant},
 };
 
-use anyhow::{Context, Error, Result, anyhow, bail};
-use log::{info, warn};
-use trapezoid_core::{DigitalControllerKey, Psx, PsxConfig, cpu::CpuState};
+use anyhow::{Context, Error, Result, anyhow};
+use log::{debug, info, warn};
+use sdl2::{
+    event::{Event, WindowEvent as SdlWindowEvent},
+    keyboard::Keycode,
+    pixels::PixelFormatEnum,
+    rect::Rect,
+    render::{Canvas, Texture},
+    video::{VideoSubsystem, Window as SdlWindow},
+};
+use trapezoid_core::{
+    AnalogSticks, DigitalControllerKey, DiscInfo, DisplayMode, MouseState, Psx, PsxConfig,
+    RumbleState, ScalingFilter, cpu::CpuState,
+};
 use vulkano::{
     Validated, VulkanError, VulkanLibrary,
     device::{
         Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo, QueueFlags,
         physical::{PhysicalDevice, PhysicalDeviceType},
     },
+    format::{Format, FormatFeatures},
     image::{Image, ImageUsage},
     instance::{Instance, InstanceCreateInfo},
     swapchain::{
-        self, CompositeAlpha, PresentMode, Surface, Swapchain, SwapchainCreateInfo,
+        self, ColorSpace, CompositeAlpha, PresentMode, Surface, Swapchain, SwapchainCreateInfo,
         SwapchainPresentInfo,
     },
     sync::{self, GpuFuture},
 };
 use winit::{
     application::ApplicationHandler,
-    dpi::PhysicalSize,
-    event::{ElementState, KeyEvent, WindowEvent},
+    event::{DeviceEvent, DeviceId, ElementState, KeyEvent, MouseButton, WindowEvent},
     event_loop::{ActiveEventLoop, EventLoop},
     keyboard::{KeyCode, PhysicalKey},
-    window::{Window, WindowId},
+    window::{CursorGrabMode, Window, WindowId},
 };
 
 use crate::{
     audio::AudioPlayer,
+    bezel::Bezel,
+    config::{
+        Config, ControllersConfig, DisplayConfig, Ps1Aspect, Ps1Controller, Ps1Filter, Ps1Renderer,
+    },
     controller::{ControllerManager, VirtualButton},
+    focus_pause::{self, FocusPause},
+    frame_pacer::FramePacer,
+    mouse::{MouseCapture, PointerButton},
+    osd,
+    pad_overlay::{OverlayKey, PadOverlay},
+    perf_overlay::{self, PerfOverlay},
+    present, ps1_bios,
+    ps1_cheats::CheatList,
+    ps1_debugger::Ps1Debugger,
+    ps1_keyboard::KeyboardLayout,
+    ps1_memcard::{MemoryCardMode, MemoryCardSlots},
+    resume, saves, screenshot,
+    systems::GameSystem,
 };
 
 const TARGET_FPS: f64 = 59.5;
 const DEFAULT_WIDTH: u32 = 640;
 const DEFAULT_HEIGHT: u32 = 480;
 const TRIGGER_THRESHOLD: f32 = 0.3;
+/// Largest change to the audio rate, small enough not to be heard as pitch
+const AUDIO_MAX_RATE_ADJUST: f64 = 0.005;
+const VRAM_WIDTH: u32 = 1024;
+const VRAM_HEIGHT: u32 = 512;
+/// Rumble requests are refreshed every frame, this only stops the motors if the core stalls
+const RUMBLE_REFRESH_MS: u32 = 100;
+/// Players driven by the frontend, the 5th multitap player (port 2) has no input
+const MAX_PLAYERS: usize = 4;
 const DIGITAL_KEY_COUNT: usize = DigitalControllerKey::Square as usize + 1;
 const INPUT_SOURCE_COUNT: usize = 2;
-const BIOS_CANDIDATE_NAMES: [&str; 4] = [
-    "scph1001.bin",
-    "SCPH1001.BIN",
-    "scph5501.bin",
-    "SCPH5501.BIN",
-];
 const DIGITAL_KEYS: [DigitalControllerKey; DIGITAL_KEY_COUNT] = [
     DigitalControllerKey::Select,
     DigitalControllerKey::L3,
@@ -69,117 +99,351 @@ const DIGITAL_KEYS: [DigitalControllerKey; DIGITAL_KEY_COUNT] = [
 ];
 
 pub fn run(
-    rom_path: &Path,
+    rom_path: Option<&Path>,
     scale: u32,
     limit_fps: bool,
     bios_override: Option<PathBuf>,
+    memcard_mode: MemoryCardMode,
+    config: &Config,
+    debug: bool,
 ) -> Result<()> {
-    let bios_path = resolve_bios_path(bios_override)?;
-    let rom_label = rom_path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("PlayStation")
-        .to_string();
-    info!(
-        "Booting PlayStation title {} with BIOS {}",
-        rom_path.display(),
-        bios_path.display()
-    );
+    let settings = &config.ps1;
+    let disc = rom_path.map(disc_info).unwrap_or_default();
+    let bios_path = ps1_bios::resolve(bios_override, disc.region)?;
+    let game_settings = settings.game(disc.serial.as_deref());
+    let rom_label = match rom_path {
+        Some(path) => path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("PlayStation")
+            .to_string(),
+        None => "BIOS".to_string(),
+    };
+    let game = GameName {
+        title: window_title(
+            game_settings
+                .and_then(|game| game.title.as_deref())
+                .unwrap_or(&rom_label),
+            disc.serial.as_deref(),
+        ),
+        label: rom_label,
+    };
+    if let Some(serial) = &disc.serial {
+        info!(
+            "Disc serial {}{}",
+            serial,
+            if game_settings.is_some() {
+                ", using its [ps1.games] settings"
+            } else {
+                ""
+            }
+        );
+    }
+    match rom_path {
+        Some(path) => info!(
+            "Booting PlayStation title {} with BIOS {}",
+            path.display(),
+            bios_path.display()
+        ),
+        None => info!(
+            "Booting PlayStation BIOS {} without a disc",
+            bios_path.display()
+        ),
+    }
 
-    let event_loop = EventLoop::new().context("failed to create PlayStation window event loop")?;
-    let required_extensions = Surface::required_extensions(&event_loop)
-        .context("failed to query required surface extensions")?;
-    let library = VulkanLibrary::new().context("failed to load Vulkan loader")?;
-    let instance = Instance::new(
-        library,
-        InstanceCreateInfo {
-            enabled_extensions: required_extensions,
-            ..Default::default()
+    let presentation = Presentation {
+        aspect: settings.aspect,
+        integer_scale: settings.integer_scale,
+        filter: match settings.filter {
+            Ps1Filter::Nearest => ScalingFilter::Nearest,
+            Ps1Filter::Bilinear => ScalingFilter::Bilinear,
+            Ps1Filter::SharpBilinear => ScalingFilter::SharpBilinear,
         },
-    )
-    .context("failed to create Vulkan instance")?;
-
-    let device_extensions = DeviceExtensions {
-        khr_swapchain: true,
-        ..DeviceExtensions::empty()
     };
-    let (physical_device, queue_family_index) =
-        select_physical_device(&instance, &event_loop, &device_extensions)
-            .context("no Vulkan device can present to this display")?;
-
-    let (device, mut queues) = Device::new(
-        physical_device,
-        DeviceCreateInfo {
-            enabled_extensions: device_extensions,
-            queue_create_infos: vec![QueueCreateInfo {
-                queue_family_index,
-                ..Default::default()
-            }],
-            ..Default::default()
-        },
-    )
-    .context("failed to create logical device")?;
-    let queue = queues
-        .next()
-        .ok_or_else(|| anyhow!("failed to acquire graphics queue"))?;
-
-    let psx = Psx::new(
-        &bios_path,
-        Some(rom_path),
-        PsxConfig {
-            stdout_debug: false,
-            fast_boot: true,
+    let audio = AudioPlayer::new(&config.audio, settings.sample_rate.into(), 2)
+        .context("failed to initialize SDL2 audio for PlayStation")?;
+    let psx_config = PsxConfig {
+        stdout_debug: false,
+        // without a disc the BIOS always stays in its shell
+        fast_boot: settings.fast_boot,
+        internal_resolution_scale: settings.internal_resolution,
+        multitap: settings.multitap,
+        cpu_overclock: game_settings
+            .and_then(|game| game.overclock)
+            .unwrap_or(settings.overclock),
+        widescreen_hack: game_settings
+            .and_then(|game| game.widescreen)
+            .unwrap_or(settings.widescreen),
+        precise_geometry: settings.precise_geometry,
+        perspective_correct_textures: settings.perspective_correct,
+        scaling_filter: presentation.filter,
+        gamma: settings.gamma,
+        audio_sample_rate: audio.source_rate() as u32,
+    };
+    let vulkan = match settings.renderer {
+        Ps1Renderer::Software => None,
+        Ps1Renderer::Vulkan => Some(VulkanSetup::new()?),
+        Ps1Renderer::Auto => match VulkanSetup::new() {
+            Ok(setup) => Some(setup),
+            Err(err) => {
+                warn!("Vulkan is unavailable ({err:#}), using the software renderer");
+                None
+            }
         },
-        device.clone(),
-        queue.clone(),
-    )
+    };
+
+    let mut psx = match &vulkan {
+        Some(setup) => Psx::new(
+            &bios_path,
+            rom_path,
+            psx_config,
+            setup.device.clone(),
+            setup.queue.clone(),
+        ),
+        None => Psx::new_software(&bios_path, rom_path, psx_config),
+    }
     .map_err(|err| anyhow!(err))
-    .with_context(|| format!("failed to boot {}", rom_path.display()))?;
+    .with_context(|| format!("failed to boot {}", game.label))?;
+    let controller_type = game_settings
+        .and_then(|game| game.controller)
+        .unwrap_or(settings.controller);
+    if controller_type == Ps1Controller::Analog {
+        for player in 0..psx.controller_count() {
+            if !psx.controller_analog_mode(player) {
+                psx.toggle_controller_analog_mode(player);
+            }
+        }
+    }
+    // the mouse takes player 1's port, player 2 keeps a pad
+    let mouse = (controller_type == Ps1Controller::Mouse).then(|| {
+        psx.connect_mouse(0);
+        info!("PlayStation Mouse in port 1, press F11 to capture the mouse");
+        MouseCapture::default()
+    });
+    let memory_cards = MemoryCardSlots::attach(rom_path, memcard_mode, &mut psx)?;
+    if let Some(path) = rom_path.and_then(|rom| resume::to_load(GameSystem::Ps1, rom)) {
+        load_resume_state(&mut psx, &path);
+    }
+    let resume = rom_path.and_then(|rom| resume::to_save(GameSystem::Ps1, rom));
+    let debugger = debug.then(Ps1Debugger::new);
+
+    let controller = ControllerBridge::new(&config.controllers)
+        .context("failed to initialize SDL2 controller subsystem")?;
+    let keyboard = KeyboardLayout::new(settings, psx.controller_count().min(MAX_PLAYERS))?;
 
-    let audio = AudioPlayer::new().context("failed to initialize SDL2 audio for PlayStation")?;
-    let controller =
-        ControllerBridge::new().context("failed to initialize SDL2 controller subsystem")?;
+    let Some(VulkanSetup {
+        event_loop,
+        instance,
+        device,
+        queue,
+    }) = vulkan
+    else {
+        let input_latches = InputLatch::for_players(psx.controller_count().min(MAX_PLAYERS));
+        let pads = PadOverlay::new(input_latches.len());
+        let mut app = SoftwareApp {
+            psx,
+            memory_cards,
+            audio,
+            controller,
+            frame_timer: FrameTimer::new(TARGET_FPS, limit_fps),
+            input_latches,
+            keyboard,
+            cheats: CheatList::load(&game.label),
+            presentation,
+            display: config.display.clone(),
+            debugger,
+            mouse,
+            pads,
+            perf: PerfOverlay::new(Duration::from_secs_f64(1.0 / TARGET_FPS), &config.display),
+            focus: FocusPause::new(&config.display),
+        };
+        let result = app.run(&game, scale.max(1));
+        app.memory_cards.flush(&mut app.psx, true);
+        if let (Ok(()), Some(path)) = (&result, &resume) {
+            save_resume_state(&mut app.psx, path);
+        }
+        return result;
+    };
 
+    present::unsupported_rotation("the PlayStation Vulkan renderer");
     let mut app = Ps1App::new(
         instance,
         device,
         queue,
         psx,
+        memory_cards,
         audio,
         controller,
-        rom_label,
+        game,
         scale.max(1),
+        presentation,
+        &config.display,
         limit_fps,
+        debugger,
     );
+    app.keyboard = keyboard;
+    app.mouse = mouse;
 
     event_loop
         .run_app(&mut app)
         .map_err(|err| anyhow!(err))
         .context("PlayStation window loop exited abnormally")?;
+    app.memory_cards.flush(&mut app.psx, true);
 
     if let Some(err) = app.shutdown_error.take() {
         return Err(err);
     }
+    if let Some(path) = &resume {
+        save_resume_state(&mut app.psx, path);
+    }
 
     Ok(())
 }
 
+/// `states/ps1/<game>.resume`, written on exit and loaded next time with auto-resume
+pub fn resume_path(label: &str) -> PathBuf {
+    saves::state_dir(GameSystem::Ps1).join(format!("{label}.resume"))
+}
+
+/// A state that does not load, say one made with another disc or multitap setting, is
+/// skipped and the game boots from power on
+fn load_resume_state(psx: &mut Psx, path: &Path) {
+    let result = fs::read(path)
+        .with_context(|| format!("failed to read {}", path.display()))
+        .and_then(|state| psx.load_state(&state).map_err(|err| anyhow!(err)));
+    match result {
+        Ok(()) => info!("Resumed from {}", path.display()),
+        Err(err) => warn!("Failed to load the resume state: {err:#}"),
+    }
+}
+
+fn save_resume_state(psx: &mut Psx, path: &Path) {
+    match saves::write(path, &psx.save_state()) {
+        Ok(()) => info!("Saved the resume state {}", path.display()),
+        Err(err) => warn!("Failed to save the resume state: {err:#}"),
+    }
+}
+
+/// How frames are fitted into the window, from the `[ps1]` settings
+#[derive(Clone, Copy)]
+struct Presentation {
+    aspect: Ps1Aspect,
+    integer_scale: bool,
+    filter: ScalingFilter,
+}
+
+/// How the running game is named: `label` is the file name cheats and screenshots
+/// are keyed by, `title` is shown in the window
+struct GameName {
+    label: String,
+    title: String,
+}
+
+/// Region and serial of a disc image, empty for executables and unreadable images
+pub fn disc_info(rom: &Path) -> DiscInfo {
+    let is_exe = rom
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("exe"));
+    if is_exe {
+        return DiscInfo::default();
+    }
+    trapezoid_core::disc_info(rom).unwrap_or_else(|err| {
+        debug!("could not identify {}: {}", rom.display(), err);
+        DiscInfo::default()
+    })
+}
+
+fn window_title(name: &str, serial: Option<&str>) -> String {
+    match serial {
+        Some(serial) => format!("{name} [{serial}]"),
+        None => name.to_string(),
+    }
+}
+
+struct VulkanSetup {
+    event_loop: EventLoop<()>,
+    instance: Arc<Instance>,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+}
+
+impl VulkanSetup {
+    fn new() -> Result<Self> {
+        let event_loop =
+            EventLoop::new().context("failed to create PlayStation window event loop")?;
+        let required_extensions = Surface::required_extensions(&event_loop)
+            .context("failed to query required surface extensions")?;
+        let library = VulkanLibrary::new().context("failed to load Vulkan loader")?;
+        let instance = Instance::new(
+            library,
+            InstanceCreateInfo {
+                enabled_extensions: required_extensions,
+                ..Default::default()
+            },
+        )
+        .context("failed to create Vulkan instance")?;
+
+        let device_extensions = DeviceExtensions {
+            khr_swapchain: true,
+            ..DeviceExtensions::empty()
+        };
+        let (physical_device, queue_family_index) =
+            select_physical_device(&instance, &event_loop, &device_extensions)
+                .context("no Vulkan device can present to this display")?;
+
+        let (device, mut queues) = Device::new(
+            physical_device,
+            DeviceCreateInfo {
+                enabled_extensions: device_extensions,
+                queue_create_infos: vec![QueueCreateInfo {
+                    queue_family_index,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        )
+        .context("failed to create logical device")?;
+        let queue = queues
+            .next()
+            .ok_or_else(|| anyhow!("failed to acquire graphics queue"))?;
+
+        Ok(Self {
+            event_loop,
+            instance,
+            device,
+            queue,
+        })
+    }
+}
+
 struct Ps1App {
     instance: Arc<Instance>,
     device: Arc<Device>,
     queue: Arc<Queue>,
     psx: Psx,
+    memory_cards: MemoryCardSlots,
     audio: AudioPlayer,
     controller: ControllerBridge,
     render_context: Option<RenderContext>,
     frame_future: Option<Box<dyn GpuFuture>>,
     recreate_swapchain: bool,
     frame_timer: FrameTimer,
-    limit_fps: bool,
-    input_latch: InputLatch,
-    rom_label: String,
+    input_latches: Vec<InputLatch>,
+    /// Set by `run` after construction, empty until then
+    keyboard: KeyboardLayout,
+    cheats: CheatList,
+    game: GameName,
     scale: u32,
+    presentation: Presentation,
+    display: DisplayConfig,
     full_vram_display: bool,
+    debugger: Option<Ps1Debugger>,
+    /// Set by `run` after construction when a mouse is plugged in
+    mouse: Option<MouseCapture>,
+    pads: PadOverlay,
+    perf: PerfOverlay,
+    focus: FocusPause,
     pub shutdown_error: Option<Error>,
 }
 
@@ -189,57 +453,92 @@ impl Ps1App {
         device: Arc<Device>,
         queue: Arc<Queue>,
         psx: Psx,
+        memory_cards: MemoryCardSlots,
         audio: AudioPlayer,
         controller: ControllerBridge,
-        rom_label: String,
+        game: GameName,
         scale: u32,
+        presentation: Presentation,
+        display: &DisplayConfig,
         limit_fps: bool,
+        debugger: Option<Ps1Debugger>,
     ) -> Self {
+        let input_latches = InputLatch::for_players(psx.controller_count().min(MAX_PLAYERS));
+        let pads = PadOverlay::new(input_latches.len());
         Self {
             instance,
             device,
             queue,
             psx,
+            memory_cards,
             audio,
             controller,
             render_context: None,
             frame_future: None,
             recreate_swapchain: false,
-            frame_timer: FrameTimer::new(TARGET_FPS),
-            limit_fps,
-            input_latch: InputLatch::new(),
-            rom_label,
+            frame_timer: FrameTimer::new(TARGET_FPS, limit_fps),
+            input_latches,
+            keyboard: KeyboardLayout::default(),
+            cheats: CheatList::load(&game.label),
+            game,
             scale,
+            presentation,
+            display: display.clone(),
             full_vram_display: false,
+            debugger,
+            mouse: None,
+            pads,
+            perf: PerfOverlay::new(Duration::from_secs_f64(1.0 / TARGET_FPS), display),
+            focus: FocusPause::new(display),
             shutdown_error: None,
         }
     }
 
     fn handle_redraw_requested(&mut self, event_loop: &ActiveEventLoop) {
-        if let Err(err) = self.render_cycle() {
-            self.shutdown_error = Some(err);
-            event_loop.exit();
+        match self.render_cycle() {
+            Ok(true) => {}
+            Ok(false) => event_loop.exit(),
+            Err(err) => {
+                self.shutdown_error = Some(err);
+                event_loop.exit();
+            }
         }
     }
 
-    fn render_cycle(&mut self) -> Result<()> {
+    /// Returns `false` when the debugger asked to quit
+    fn render_cycle(&mut self) -> Result<bool> {
         if self.render_context.is_none() {
-            return Ok(());
+            return Ok(true);
         }
-        self.frame_timer.enforce(self.limit_fps);
-        self.controller
-            .sync_inputs(&mut self.input_latch, &mut self.psx);
-
-        let cpu_state = self.psx.clock_full_video_frame();
-        if cpu_state != CpuState::Normal {
-            warn!("PlayStation core reported state {:?}", cpu_state);
+        self.frame_timer.enforce();
+        self.controller.poll_events();
+        if self.focus.is_paused() {
+            focus_pause::idle();
+            return Ok(true);
+        }
+        self.pads.update(&mut self.controller.manager);
+        // the game waits while pads are being reassigned
+        if self.pads.is_open() {
+            self.render_frame()?;
+            return Ok(true);
         }
-        let samples = self.psx.take_audio_buffer();
-        if !samples.is_empty() {
-            self.audio.push_samples(&samples);
+        if !emulate_frame(
+            &mut self.psx,
+            &mut self.controller,
+            &mut self.input_latches,
+            &self.cheats,
+            &mut self.audio,
+            &mut self.memory_cards,
+            self.debugger.as_mut(),
+            self.mouse.as_mut(),
+        ) {
+            return Ok(false);
         }
         self.frame_timer.tick();
-        self.render_frame()
+        self.perf.frame();
+        self.perf.set_audio(&self.audio);
+        self.render_frame()?;
+        Ok(true)
     }
 
     fn render_frame(&mut self) -> Result<()> {
@@ -288,9 +587,16 @@ impl Ps1App {
         }
 
         let image = render.images[image_index as usize].clone();
-        let future = self.psx.blit_to_front(
+        let (offset, extent) = present_area(
+            frame_shape(self.psx.display_mode(), self.full_vram_display),
+            self.presentation,
+            render.swapchain.image_extent(),
+        );
+        let future = self.psx.blit_to_front_area(
             image,
             self.full_vram_display,
+            offset,
+            extent,
             current_future.join(acquire_future).boxed(),
         );
         let submitted = future
@@ -309,11 +615,27 @@ impl Ps1App {
             Err(err) => return Err(anyhow!(err).context("failed to submit PS1 frame")),
         });
 
-        let fps = self.frame_timer.fps();
-        render.window.set_title(&format!(
-            "PlayStation - {} ({:.1} FPS)",
-            self.rom_label, fps
-        ));
+        // the Vulkan renderer has no overlay to draw into, the title shows the pads instead
+        let mut title = if self.pads.is_open() {
+            format!(
+                "PlayStation - {} | PADS: {}",
+                self.game.title,
+                self.pads.lines(&self.controller.manager).join(" | ")
+            )
+        } else if self.perf.is_open() {
+            format!(
+                "PlayStation - {} | {}",
+                self.game.title,
+                self.perf.summary()
+            )
+        } else {
+            let fps = self.frame_timer.fps();
+            format!("PlayStation - {} ({:.1} FPS)", self.game.title, fps)
+        };
+        if let Some(text) = self.audio.osd_text() {
+            title = format!("{title} | {text}");
+        }
+        render.window.set_title(&title);
         Ok(())
     }
 
@@ -328,8 +650,9 @@ impl Ps1App {
             event_loop
                 .create_window(
                     Window::default_attributes()
-                        .with_title(format!("PlayStation - {}", self.rom_label))
-                        .with_inner_size(PhysicalSize::new(width, height))
+                        .with_title(format!("PlayStation - {}", self.game.title))
+                        .with_inner_size(present::winit_size(width, height, &self.display))
+                        .with_fullscreen(present::winit_fullscreen(event_loop))
                         .with_resizable(true),
                 )
                 .context("failed to create PlayStation window")?,
@@ -345,15 +668,32 @@ impl Ps1App {
                 .surface_capabilities(&surface, Default::default())
                 .map_err(|err| anyhow!(err))
                 .context("failed to query surface capabilities")?;
-            let format = self
-                .device
-                .physical_device()
+            let physical_device = self.device.physical_device();
+            let (format, color_space) = physical_device
                 .surface_formats(&surface, Default::default())
                 .map_err(|err| anyhow!(err))
                 .context("failed to query surface formats")?
-                .first()
-                .map(|entry| entry.0)
-                .ok_or_else(|| anyhow!("surface reported no formats"))?;
+                .into_iter()
+                .filter(|(format, _)| {
+                    physical_device
+                        .format_properties(*format)
+                        .is_ok_and(|properties| {
+                            properties
+                                .optimal_tiling_features
+                                .intersects(FormatFeatures::BLIT_DST)
+                        })
+                })
+                .min_by_key(surface_format_rank)
+                .ok_or_else(|| {
+                    anyhow!("surface reported no formats the frame can be blitted to")
+                })?;
+            debug!("PlayStation swapchain format {format:?} in {color_space:?}");
+            if surface_format_rank(&(format, color_space)) > 1 {
+                warn!(
+                    "The display only offers {format:?} in {color_space:?}, colours may look off, \
+                     `gamma` under [ps1] can make up for it"
+                );
+            }
             let present_mode = self
                 .device
                 .physical_device()
@@ -361,7 +701,7 @@ impl Ps1App {
                 .map_err(|err| anyhow!(err))
                 .context("failed to query present modes")?
                 .into_iter()
-                .min_by_key(present_mode_rank)
+                .min_by_key(|mode| present_mode_rank(mode, self.display.vrr))
                 .unwrap_or(PresentMode::Fifo);
             let dimensions: [u32; 2] = window.inner_size().into();
             Swapchain::new(
@@ -370,6 +710,7 @@ impl Ps1App {
                 SwapchainCreateInfo {
                     min_image_count: caps.min_image_count,
                     image_format: format,
+                    image_color_space: color_space,
                     image_extent: dimensions,
                     image_usage: ImageUsage::TRANSFER_DST,
                     composite_alpha: CompositeAlpha::Opaque,
@@ -392,25 +733,84 @@ impl Ps1App {
         Ok(())
     }
 
+    /// Keeps the pointer in the window and hides it while the mouse is captured
+    fn grab_cursor(&self, captured: bool) {
+        let Some(ctx) = &self.render_context else {
+            return;
+        };
+        let grab = if captured {
+            // not every platform can lock the pointer in place
+            ctx.window
+                .set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| ctx.window.set_cursor_grab(CursorGrabMode::Confined))
+        } else {
+            ctx.window.set_cursor_grab(CursorGrabMode::None)
+        };
+        if let Err(err) = grab {
+            warn!("Failed to grab the mouse: {err}");
+        }
+        ctx.window.set_cursor_visible(!captured);
+    }
+
     fn handle_keyboard_input(&mut self, event_loop: &ActiveEventLoop, event: &KeyEvent) {
         if event.repeat {
             return;
         }
         let pressed = event.state == ElementState::Pressed;
         if let PhysicalKey::Code(code) = event.physical_key {
+            if pressed && code == KeyCode::F8 {
+                self.pads.toggle();
+                return;
+            }
+            if pressed
+                && let Some(key) = winit_overlay_key(code)
+                && self.pads.handle_key(key, &mut self.controller.manager)
+            {
+                return;
+            }
             match code {
                 KeyCode::Escape if pressed => {
                     event_loop.exit();
                     return;
                 }
+                // the core hands out one front image per frame, so VRAM cannot go to a second
+                // swapchain like the software renderer's window and replaces the game instead
                 KeyCode::KeyV if pressed => {
                     self.full_vram_display = !self.full_vram_display;
                 }
+                KeyCode::Equal | KeyCode::NumpadAdd if pressed => self.audio.change_volume(true),
+                KeyCode::Minus | KeyCode::NumpadSubtract if pressed => {
+                    self.audio.change_volume(false)
+                }
+                // perf_overlay::HOTKEY as winit names it
+                KeyCode::F3 if pressed => self.perf.toggle(),
+                KeyCode::F9 if pressed => self.cheats.toggle(),
+                KeyCode::F10 if pressed => self.cheats.reload(),
+                KeyCode::F12 if pressed => save_screenshot(&mut self.psx, &self.game.label),
+                KeyCode::F11 if pressed => {
+                    if let Some(mouse) = &mut self.mouse {
+                        let captured = mouse.toggle();
+                        self.grab_cursor(captured);
+                    }
+                }
+                KeyCode::Pause if pressed => {
+                    if let Some(debugger) = &mut self.debugger {
+                        debugger.request_break();
+                    }
+                }
+                KeyCode::Tab if pressed => {
+                    let analog = self.psx.toggle_controller_analog_mode(0);
+                    info!(
+                        "DualShock {} mode",
+                        if analog { "analog" } else { "digital" }
+                    );
+                }
                 _ => {}
             }
-            if let Some(mapped) = map_keyboard_key(code) {
-                self.input_latch
-                    .set(InputSource::Keyboard, mapped, pressed, &mut self.psx);
+            for &(player, mapped) in self.keyboard.winit_key(code) {
+                if let Some(latch) = self.input_latches.get_mut(player) {
+                    latch.set(InputSource::Keyboard, mapped, pressed, &mut self.psx);
+                }
             }
         }
     }
@@ -449,13 +849,441 @@ impl ApplicationHandler for Ps1App {
             WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => {
                 self.recreate_swapchain = true;
             }
+            WindowEvent::Focused(true) => {
+                self.audio.set_focused(true);
+                self.focus.set_focused(true);
+            }
             WindowEvent::Focused(false) => {
-                self.input_latch
-                    .clear_source(InputSource::Keyboard, &mut self.psx);
+                self.audio.set_focused(false);
+                self.focus.set_focused(false);
+                for latch in &mut self.input_latches {
+                    latch.clear_source(InputSource::Keyboard, &mut self.psx);
+                }
+                if let Some(mouse) = &mut self.mouse
+                    && mouse.is_captured()
+                {
+                    mouse.set_captured(false);
+                    self.grab_cursor(false);
+                }
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left => PointerButton::Left,
+                    MouseButton::Right => PointerButton::Right,
+                    _ => return,
+                };
+                if let Some(mouse) = &mut self.mouse {
+                    mouse.button(button, state == ElementState::Pressed);
+                }
             }
             _ => {}
         }
     }
+
+    /// Raw motion, unlike cursor positions it keeps coming while the pointer is locked
+    fn device_event(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+        _device_id: DeviceId,
+        event: DeviceEvent,
+    ) {
+        if let (DeviceEvent::MouseMotion { delta: (dx, dy) }, Some(mouse)) =
+            (event, &mut self.mouse)
+        {
+            mouse.motion(dx.round() as i32, dy.round() as i32);
+        }
+    }
+}
+
+/// Runs one video frame of the core and forwards its side effects, returns `false`
+/// when the debugger asked to quit
+fn emulate_frame(
+    psx: &mut Psx,
+    controller: &mut ControllerBridge,
+    input_latches: &mut [InputLatch],
+    cheats: &CheatList,
+    audio: &mut AudioPlayer,
+    memory_cards: &mut MemoryCardSlots,
+    mut debugger: Option<&mut Ps1Debugger>,
+    mouse: Option<&mut MouseCapture>,
+) -> bool {
+    controller.sync_inputs(input_latches, psx);
+    if let Some(mouse) = mouse {
+        let input = mouse.take();
+        psx.change_mouse_state(
+            0,
+            MouseState {
+                dx: input.dx.clamp(i16::MIN.into(), i16::MAX.into()) as i16,
+                dy: input.dy.clamp(i16::MIN.into(), i16::MAX.into()) as i16,
+                left: input.left,
+                right: input.right,
+            },
+        );
+    }
+    if let Some(debugger) = debugger.as_deref_mut()
+        && !debugger.before_frame(psx)
+    {
+        return false;
+    }
+
+    let cpu_state = psx.clock_full_video_frame();
+    if cpu_state != CpuState::Normal {
+        match debugger {
+            Some(debugger) => {
+                if !debugger.cpu_stopped(psx, cpu_state) {
+                    return false;
+                }
+            }
+            None => warn!("PlayStation core reported state {:?}", cpu_state),
+        }
+    }
+    cheats.apply(psx);
+    controller.forward_rumble(psx, input_latches.len());
+    let samples = psx.take_audio_buffer();
+    if !samples.is_empty() {
+        audio.push_samples(&samples);
+    }
+    follow_audio_queue(psx, audio);
+    memory_cards.flush(psx, false);
+    true
+}
+
+/// Nudges the audio rate to keep the queue around `[audio] latency_ms`, as the frame
+/// pacing and the console's refresh rate never match the audio clock exactly
+fn follow_audio_queue(psx: &mut Psx, audio: &AudioPlayer) {
+    let fill = audio.buffered_seconds() / audio.target_seconds();
+    let adjust =
+        ((1.0 - fill) * AUDIO_MAX_RATE_ADJUST).clamp(-AUDIO_MAX_RATE_ADJUST, AUDIO_MAX_RATE_ADJUST);
+    psx.set_audio_sample_rate(audio.source_rate() * (1.0 + adjust));
+}
+
+/// Saves the frame on screen, read back from the GPU when rendering with Vulkan
+fn save_screenshot(psx: &mut Psx, rom_label: &str) {
+    let Some(frame) = psx.capture_frame() else {
+        warn!("No PlayStation frame has been displayed yet");
+        return;
+    };
+    match screenshot::save(rom_label, frame.width, frame.height, &frame.pixels) {
+        Ok(path) => info!("Saved screenshot {}", path.display()),
+        Err(err) => warn!("Failed to save screenshot: {err:#}"),
+    }
+}
+
+/// Frontend for the software renderer, presenting frames through an SDL canvas
+/// instead of a Vulkan swapchain
+struct SoftwareApp {
+    psx: Psx,
+    memory_cards: MemoryCardSlots,
+    audio: AudioPlayer,
+    controller: ControllerBridge,
+    frame_timer: FrameTimer,
+    input_latches: Vec<InputLatch>,
+    keyboard: KeyboardLayout,
+    cheats: CheatList,
+    presentation: Presentation,
+    display: DisplayConfig,
+    debugger: Option<Ps1Debugger>,
+    mouse: Option<MouseCapture>,
+    pads: PadOverlay,
+    perf: PerfOverlay,
+    /// Follows the VRAM window too, moving to it keeps the game running
+    focus: FocusPause,
+}
+
+impl SoftwareApp {
+    fn run(&mut self, game: &GameName, scale: u32) -> Result<()> {
+        let video = self.controller.sdl.video().map_err(|err| anyhow!(err))?;
+        let (width, height) = present::oriented(
+            DEFAULT_WIDTH.saturating_mul(scale),
+            DEFAULT_HEIGHT.saturating_mul(scale),
+        );
+        let mut window = video
+            .window(&format!("PlayStation - {}", game.title), width, height)
+            .position_centered()
+            .resizable()
+            .allow_highdpi()
+            .build()
+            .context("failed to create PlayStation window")?;
+        present::fit_to_display(&mut window, &self.display);
+        present::apply_fullscreen(&mut window);
+        let mut canvas = window.into_canvas().build().map_err(|err| anyhow!(err))?;
+        let texture_creator = canvas.texture_creator();
+        // read by SDL when textures are created
+        let scale_quality = match self.presentation.filter {
+            ScalingFilter::Nearest => "nearest",
+            ScalingFilter::Bilinear | ScalingFilter::SharpBilinear => "linear",
+        };
+        sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", scale_quality);
+        let bezel = Bezel::load(&canvas, &self.display, GameSystem::Ps1, &game.label);
+        let mut texture: Option<(Texture, u32, u32)> = None;
+        let mut vram_window: Option<VramWindow> = None;
+        let mouse_util = self.controller.sdl.mouse();
+
+        loop {
+            self.frame_timer.enforce();
+            for event in self.controller.poll_events() {
+                self.audio.handle_sdl_event(&event);
+                self.focus.handle_sdl_event(&event);
+                if let Some(mouse) = &mut self.mouse {
+                    mouse.handle_sdl_event(&event, &mouse_util);
+                }
+                match event {
+                    Event::Quit { .. } => return Ok(()),
+                    // with the VRAM window open SDL no longer quits when the game window closes
+                    Event::Window {
+                        window_id,
+                        win_event: SdlWindowEvent::Close,
+                        ..
+                    } => {
+                        if vram_window
+                            .as_ref()
+                            .is_some_and(|vram| vram.id() == window_id)
+                        {
+                            vram_window = None;
+                        } else {
+                            return Ok(());
+                        }
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F8),
+                        repeat: false,
+                        ..
+                    } => self.pads.toggle(),
+                    Event::KeyDown {
+                        keycode: Some(code),
+                        repeat: false,
+                        ..
+                    } if sdl_overlay_key(code).is_some_and(|key| {
+                        self.pads.handle_key(key, &mut self.controller.manager)
+                    }) => {}
+                    Event::KeyDown {
+                        keycode: Some(code),
+                        repeat: false,
+                        ..
+                    } => {
+                        match code {
+                            Keycode::Escape => return Ok(()),
+                            Keycode::V => {
+                                vram_window = match vram_window {
+                                    Some(_) => None,
+                                    None => {
+                                        Some(VramWindow::open(&video, &game.title, &self.display)?)
+                                    }
+                                };
+                            }
+                            perf_overlay::HOTKEY => self.perf.toggle(),
+                            Keycode::F9 => self.cheats.toggle(),
+                            Keycode::F10 => self.cheats.reload(),
+                            Keycode::F12 => save_screenshot(&mut self.psx, &game.label),
+                            Keycode::Pause => {
+                                if let Some(debugger) = &mut self.debugger {
+                                    debugger.request_break();
+                                }
+                            }
+                            Keycode::Tab => {
+                                let analog = self.psx.toggle_controller_analog_mode(0);
+                                info!(
+                                    "DualShock {} mode",
+                                    if analog { "analog" } else { "digital" }
+                                );
+                            }
+                            _ => {}
+                        }
+                        self.set_key(code, true);
+                    }
+                    Event::KeyUp {
+                        keycode: Some(code),
+                        ..
+                    } => {
+                        self.set_key(code, false);
+                    }
+                    Event::Window {
+                        win_event: SdlWindowEvent::FocusLost,
+                        ..
+                    } => {
+                        for latch in &mut self.input_latches {
+                            latch.clear_source(InputSource::Keyboard, &mut self.psx);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            if self.focus.is_paused() {
+                focus_pause::idle();
+                continue;
+            }
+
+            self.pads.update(&mut self.controller.manager);
+            // the game waits while pads are being reassigned
+            if !self.pads.is_open() {
+                if !emulate_frame(
+                    &mut self.psx,
+                    &mut self.controller,
+                    &mut self.input_latches,
+                    &self.cheats,
+                    &mut self.audio,
+                    &mut self.memory_cards,
+                    self.debugger.as_mut(),
+                    self.mouse.as_mut(),
+                ) {
+                    return Ok(());
+                }
+                self.frame_timer.tick();
+                self.perf.frame();
+                self.perf.set_audio(&self.audio);
+            }
+
+            // drawn before the game, so screenshots keep taking the last frame blitted
+            if let Some(vram) = &mut vram_window {
+                vram.present(&mut self.psx)?;
+            }
+            let pixel_aspect = self.psx.display_mode().pixel_aspect;
+            let frame = self
+                .psx
+                .software_frame(false)
+                .ok_or_else(|| anyhow!("PlayStation core is not using the software renderer"))?;
+            if frame.width == 0 || frame.height == 0 {
+                continue;
+            }
+            let (output_width, output_height) = canvas.output_size().map_err(|err| anyhow!(err))?;
+            // laid out on the window turned back, then turned along with the picture
+            let (output_width, output_height) = present::oriented(output_width, output_height);
+            let ([x, y], [width, height]) = present_area(
+                (frame.width, frame.height, pixel_aspect),
+                self.presentation,
+                [output_width, output_height],
+            );
+            // SDL only filters linearly, so sharp bilinear scales up by whole pixels first
+            let factor = match self.presentation.filter {
+                ScalingFilter::SharpBilinear => {
+                    [(width / frame.width).max(1), (height / frame.height).max(1)]
+                }
+                _ => [1, 1],
+            };
+            let texture_size = (frame.width * factor[0], frame.height * factor[1]);
+            if !matches!(texture, Some((_, w, h)) if (w, h) == texture_size) {
+                let created = texture_creator
+                    .create_texture_streaming(
+                        PixelFormatEnum::ARGB8888,
+                        texture_size.0,
+                        texture_size.1,
+                    )
+                    .map_err(|err| anyhow!(err))?;
+                texture = Some((created, texture_size.0, texture_size.1));
+            }
+            let (texture, _, _) = texture.as_mut().unwrap();
+            let mut pixels = if factor == [1, 1] {
+                Cow::Borrowed(&frame.pixels[..])
+            } else {
+                Cow::Owned(integer_prescale(&frame.pixels, frame.width, factor))
+            };
+            if self.pads.is_open() {
+                self.pads.draw(
+                    pixels.to_mut(),
+                    texture_size.0 as usize,
+                    &self.controller.manager,
+                );
+            }
+            if self.perf.is_open() {
+                self.perf.draw(pixels.to_mut(), texture_size.0 as usize);
+            }
+            if let Some(text) = self.audio.osd_text() {
+                osd::draw_message(pixels.to_mut(), texture_size.0 as usize, &text);
+            }
+            texture
+                .update(
+                    None,
+                    bytemuck::cast_slice(&pixels),
+                    texture_size.0 as usize * 4,
+                )
+                .context("failed to upload PS1 frame")?;
+            canvas.clear();
+            if let Some(bezel) = &bezel {
+                bezel.draw(&mut canvas).map_err(|err| anyhow!(err))?;
+            }
+            let (x, y) = present::oriented(x, y);
+            let (area_width, area_height) = present::oriented(width, height);
+            present::copy(
+                &mut canvas,
+                texture,
+                Some(Rect::new(x as i32, y as i32, area_width, area_height)),
+            )
+            .map_err(|err| anyhow!(err))?;
+            canvas.present();
+
+            let fps = self.frame_timer.fps();
+            canvas
+                .window_mut()
+                .set_title(&format!("PlayStation - {} ({:.1} FPS)", game.title, fps))
+                .context("failed to update PlayStation window title")?;
+        }
+    }
+
+    fn set_key(&mut self, code: Keycode, pressed: bool) {
+        for &(player, mapped) in self.keyboard.sdl_key(present::game_arrow_key(code)) {
+            if let Some(latch) = self.input_latches.get_mut(player) {
+                latch.set(InputSource::Keyboard, mapped, pressed, &mut self.psx);
+            }
+        }
+    }
+}
+
+/// Second window of the software renderer showing all of VRAM, opened and closed with V
+struct VramWindow {
+    canvas: Canvas<SdlWindow>,
+    texture: Texture,
+}
+
+impl VramWindow {
+    fn open(video: &VideoSubsystem, title: &str, display: &DisplayConfig) -> Result<Self> {
+        let mut window = video
+            .window(
+                &format!("PlayStation VRAM - {title}"),
+                VRAM_WIDTH,
+                VRAM_HEIGHT,
+            )
+            .resizable()
+            .allow_highdpi()
+            .build()
+            .context("failed to create PlayStation VRAM window")?;
+        present::fit_to_display(&mut window, display);
+        // the game window already paces the frames
+        let mut canvas = window.into_canvas().build().map_err(|err| anyhow!(err))?;
+        canvas
+            .set_logical_size(VRAM_WIDTH, VRAM_HEIGHT)
+            .context("failed to set PlayStation VRAM logical size")?;
+        let texture = canvas
+            .texture_creator()
+            .create_texture_streaming(PixelFormatEnum::ARGB8888, VRAM_WIDTH, VRAM_HEIGHT)
+            .map_err(|err| anyhow!(err))?;
+        Ok(Self { canvas, texture })
+    }
+
+    fn id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    fn present(&mut self, psx: &mut Psx) -> Result<()> {
+        let frame = psx
+            .software_frame(true)
+            .ok_or_else(|| anyhow!("PlayStation core is not using the software renderer"))?;
+        if (frame.width, frame.height) != (VRAM_WIDTH, VRAM_HEIGHT) {
+            return Ok(());
+        }
+        self.texture
+            .update(
+                None,
+                bytemuck::cast_slice(&frame.pixels),
+                VRAM_WIDTH as usize * 4,
+            )
+            .context("failed to upload PlayStation VRAM")?;
+        self.canvas.clear();
+        self.canvas
+            .copy(&self.texture, None, None)
+            .map_err(|err| anyhow!(err))?;
+        self.canvas.present();
+        Ok(())
+    }
 }
 
 struct RenderContext {
@@ -466,36 +1294,22 @@ struct RenderContext {
 }
 
 struct FrameTimer {
-    target: f64,
+    pacer: FramePacer,
     last_frame: Instant,
     average: MovingAverage,
 }
 
 impl FrameTimer {
-    fn new(target: f64) -> Self {
+    fn new(target: f64, limit: bool) -> Self {
         Self {
-            target,
+            pacer: FramePacer::new(Duration::from_secs_f64(1.0 / target), limit),
             last_frame: Instant::now(),
             average: MovingAverage::new(1.0 / target),
         }
     }
 
-    fn enforce(&mut self, limit: bool) {
-        if !limit {
-            return;
-        }
-        let frame_duration = Duration::from_secs_f64(1.0 / self.target);
-        loop {
-            let elapsed = self.last_frame.elapsed();
-            if elapsed >= frame_duration {
-                break;
-            }
-            if frame_duration - elapsed > Duration::from_millis(1) {
-                thread::sleep(frame_duration - elapsed - Duration::from_millis(1));
-            } else {
-                hint::spin_loop();
-            }
-        }
+    fn enforce(&mut self) {
+        self.pacer.wait();
     }
 
     fn tick(&mut self) {
@@ -538,102 +1352,122 @@ impl MovingAverage {
 }
 
 struct ControllerBridge {
-    _sdl: sdl2::Sdl,
+    sdl: sdl2::Sdl,
     manager: ControllerManager,
     event_pump: sdl2::EventPump,
+    rumble: [RumbleState; MAX_PLAYERS],
 }
 
 impl ControllerBridge {
-    fn new() -> Result<Self> {
+    fn new(config: &ControllersConfig) -> Result<Self> {
         let sdl = sdl2::init().map_err(|err| anyhow!(err))?;
-        let manager = ControllerManager::new(&sdl)?;
+        let manager = ControllerManager::new(&sdl, config)?;
         let event_pump = sdl.event_pump().map_err(|err| anyhow!(err))?;
         Ok(Self {
-            _sdl: sdl,
+            sdl,
             manager,
             event_pump,
+            rumble: [RumbleState::default(); MAX_PLAYERS],
         })
     }
 
-    fn sync_inputs(&mut self, latch: &mut InputLatch, psx: &mut Psx) {
-        for event in self.event_pump.poll_iter() {
-            self.manager.handle_event(&event);
+    /// Feeds pending SDL events to the controller manager and returns them for
+    /// window handling
+    fn poll_events(&mut self) -> Vec<Event> {
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        for event in &events {
+            self.manager.handle_event(event);
+        }
+        events
+    }
+
+    /// Gamepads drive the player of their slot in the controller manager
+    fn sync_inputs(&mut self, latches: &mut [InputLatch], psx: &mut Psx) {
+        self.manager.advance_frame();
+        for latch in latches {
+            self.sync_player(latch, psx);
         }
+    }
+
+    fn sync_player(&mut self, latch: &mut InputLatch, psx: &mut Psx) {
+        let pad = latch.player;
+        // in analog mode the left stick is reported as is, not as D-pad presses
+        let analog_mode = psx.controller_analog_mode(pad);
 
         latch.set(
             InputSource::Controller,
             DigitalControllerKey::Start,
-            self.manager.is_pressed(VirtualButton::Start),
+            self.manager.is_pad_pressed(pad, VirtualButton::Start),
             psx,
         );
         latch.set(
             InputSource::Controller,
             DigitalControllerKey::Select,
-            self.manager.is_pressed(VirtualButton::Select),
+            self.manager.is_pad_pressed(pad, VirtualButton::Select),
             psx,
         );
         latch.set(
             InputSource::Controller,
             DigitalControllerKey::Up,
-            self.manager.is_pressed(VirtualButton::Up),
+            self.direction_pressed(pad, VirtualButton::Up, analog_mode),
             psx,
         );
         latch.set(
             InputSource::Controller,
             DigitalControllerKey::Down,
-            self.manager.is_pressed(VirtualButton::Down),
+            self.direction_pressed(pad, VirtualButton::Down, analog_mode),
             psx,
         );
         latch.set(
             InputSource::Controller,
             DigitalControllerKey::Left,
-            self.manager.is_pressed(VirtualButton::Left),
+            self.direction_pressed(pad, VirtualButton::Left, analog_mode),
             psx,
         );
         latch.set(
             InputSource::Controller,
             DigitalControllerKey::Right,
-            self.manager.is_pressed(VirtualButton::Right),
+            self.direction_pressed(pad, VirtualButton::Right, analog_mode),
             psx,
         );
         latch.set(
             InputSource::Controller,
             DigitalControllerKey::X,
-            self.manager.is_pressed(VirtualButton::A),
+            self.manager.is_pad_pressed(pad, VirtualButton::A),
             psx,
         );
         latch.set(
             InputSource::Controller,
             DigitalControllerKey::Circle,
-            self.manager.is_pressed(VirtualButton::B),
+            self.manager.is_pad_pressed(pad, VirtualButton::B),
             psx,
         );
         latch.set(
             InputSource::Controller,
             DigitalControllerKey::Square,
-            self.manager.is_pressed(VirtualButton::X),
+            self.manager.is_pad_pressed(pad, VirtualButton::X),
             psx,
         );
         latch.set(
             InputSource::Controller,
             DigitalControllerKey::Triangle,
-            self.manager.is_pressed(VirtualButton::Y),
+            self.manager.is_pad_pressed(pad, VirtualButton::Y),
             psx,
         );
         latch.set(
             InputSource::Controller,
             DigitalControllerKey::L1,
-            self.manager.is_pressed(VirtualButton::L),
+            self.manager.is_pad_pressed(pad, VirtualButton::L),
             psx,
         );
         latch.set(
             InputSource::Controller,
             DigitalControllerKey::R1,
-            self.manager.is_pressed(VirtualButton::R),
+            self.manager.is_pad_pressed(pad, VirtualButton::R),
             psx,
         );
 
-        let analog = self.manager.analog_state().unwrap_or_default();
+        let analog = self.manager.pad_analog_state(pad).unwrap_or_default();
         latch.set(
             InputSource::Controller,
             DigitalControllerKey::L2,
@@ -646,7 +1480,81 @@ impl ControllerBridge {
             analog.right_trigger > TRIGGER_THRESHOLD,
             psx,
         );
+        latch.set(
+            InputSource::Controller,
+            DigitalControllerKey::L3,
+            self.manager.is_pad_pressed(pad, VirtualButton::LeftStick),
+            psx,
+        );
+        latch.set(
+            InputSource::Controller,
+            DigitalControllerKey::R3,
+            self.manager.is_pad_pressed(pad, VirtualButton::RightStick),
+            psx,
+        );
+        psx.change_controller_analog_state(
+            pad,
+            AnalogSticks {
+                left_x: stick_byte(analog.left_x),
+                left_y: stick_byte(analog.left_y),
+                right_x: stick_byte(analog.right_x),
+                right_y: stick_byte(analog.right_y),
+            },
+        );
+    }
+
+    fn direction_pressed(&self, pad: usize, button: VirtualButton, analog_mode: bool) -> bool {
+        if analog_mode {
+            self.manager.is_pad_button_pressed(pad, button)
+        } else {
+            self.manager.is_pad_pressed(pad, button)
+        }
     }
+
+    /// The large DualShock motor is the low frequency one
+    fn forward_rumble(&mut self, psx: &Psx, players: usize) {
+        let idle = RumbleState::default();
+        for player in 0..players.min(MAX_PLAYERS) {
+            let rumble = psx.controller_rumble(player);
+            if rumble == idle && self.rumble[player] == idle {
+                continue;
+            }
+            self.rumble[player] = rumble;
+            self.manager.set_pad_rumble(
+                player,
+                u16::from(rumble.large_motor) * 0x101,
+                u16::from(rumble.small_motor) * 0x101,
+                RUMBLE_REFRESH_MS,
+            );
+        }
+    }
+}
+
+fn sdl_overlay_key(code: Keycode) -> Option<OverlayKey> {
+    Some(match code {
+        Keycode::Up => OverlayKey::Up,
+        Keycode::Down => OverlayKey::Down,
+        Keycode::Left => OverlayKey::Left,
+        Keycode::Right => OverlayKey::Right,
+        Keycode::Return => OverlayKey::Close,
+        _ => return None,
+    })
+}
+
+fn winit_overlay_key(code: KeyCode) -> Option<OverlayKey> {
+    Some(match code {
+        KeyCode::ArrowUp => OverlayKey::Up,
+        KeyCode::ArrowDown => OverlayKey::Down,
+        KeyCode::ArrowLeft => OverlayKey::Left,
+        KeyCode::ArrowRight => OverlayKey::Right,
+        KeyCode::Enter => OverlayKey::Close,
+        _ => return None,
+    })
+}
+
+/// Maps a normalized stick axis to the DualShock range, where `0x80` is centered
+fn stick_byte(value: f32) -> u8 {
+    ((value.clamp(-1.0, 1.0) + 1.0) * 127.5).round().min(255.0) as u8
 }
 
 #[derive(Clone, Copy)]
@@ -662,18 +1570,24 @@ impl InputSource {
 }
 
 struct InputLatch {
+    player: usize,
     sources: [[bool; DIGITAL_KEY_COUNT]; INPUT_SOURCE_COUNT],
     combined: [bool; DIGITAL_KEY_COUNT],
 }
 
 impl InputLatch {
-    fn new() -> Self {
+    fn new(player: usize) -> Self {
         Self {
+            player,
             sources: [[false; DIGITAL_KEY_COUNT]; INPUT_SOURCE_COUNT],
             combined: [false; DIGITAL_KEY_COUNT],
         }
     }
 
+    fn for_players(count: usize) -> Vec<Self> {
+        (0..count).map(Self::new).collect()
+    }
+
     fn set(
         &mut self,
         source: InputSource,
@@ -686,7 +1600,7 @@ impl InputLatch {
         let next = self.sources.iter().any(|state| state[idx]);
         if next != self.combined[idx] {
             self.combined[idx] = next;
-            psx.change_controller_key_state(key, next);
+            psx.change_controller_key_state(self.player, key, next);
         }
     }
 
@@ -697,80 +1611,109 @@ impl InputLatch {
     }
 }
 
-fn map_keyboard_key(code: KeyCode) -> Option<DigitalControllerKey> {
-    match code {
-        KeyCode::ArrowUp => Some(DigitalControllerKey::Up),
-        KeyCode::ArrowDown => Some(DigitalControllerKey::Down),
-        KeyCode::ArrowLeft => Some(DigitalControllerKey::Left),
-        KeyCode::ArrowRight => Some(DigitalControllerKey::Right),
-        KeyCode::KeyZ => Some(DigitalControllerKey::X),
-        KeyCode::KeyX => Some(DigitalControllerKey::Circle),
-        KeyCode::KeyA => Some(DigitalControllerKey::Square),
-        KeyCode::KeyS => Some(DigitalControllerKey::Triangle),
-        KeyCode::KeyQ => Some(DigitalControllerKey::L1),
-        KeyCode::KeyW => Some(DigitalControllerKey::R1),
-        KeyCode::Digit1 => Some(DigitalControllerKey::L2),
-        KeyCode::Digit2 => Some(DigitalControllerKey::R2),
-        KeyCode::Enter | KeyCode::NumpadEnter => Some(DigitalControllerKey::Start),
-        KeyCode::Space | KeyCode::Backspace | KeyCode::ShiftLeft | KeyCode::ShiftRight => {
-            Some(DigitalControllerKey::Select)
-        }
-        _ => None,
-    }
-}
-
-fn present_mode_rank(mode: &PresentMode) -> u8 {
-    match mode {
-        PresentMode::Mailbox => 0,
-        PresentMode::Immediate => 1,
-        PresentMode::Fifo => 2,
-        PresentMode::FifoRelaxed => 3,
-        _ => 4,
+/// Width, height and pixel aspect of what is shown, the full VRAM view uses square pixels
+fn frame_shape(mode: DisplayMode, full_vram: bool) -> (u32, u32, f32) {
+    if full_vram {
+        (VRAM_WIDTH, VRAM_HEIGHT, 1.0)
+    } else {
+        (mode.width, mode.height, mode.pixel_aspect)
     }
 }
 
-fn resolve_bios_path(bios_override: Option<PathBuf>) -> Result<PathBuf> {
-    if let Some(path) = bios_override {
-        return validate_existing_file(path);
+/// Offset and extent of the picture inside a `window` sized surface, centered with
+/// black bars around it
+fn present_area(
+    (width, height, pixel_aspect): (u32, u32, f32),
+    presentation: Presentation,
+    window: [u32; 2],
+) -> ([u32; 2], [u32; 2]) {
+    let [window_width, window_height] = window;
+    let aspect = presentation.aspect;
+    if width == 0 || height == 0 || aspect == Ps1Aspect::Stretch {
+        return ([0, 0], window);
     }
-    for key in ["PS1_BIOS", "PSX_BIOS"] {
-        if let Ok(value) = env::var(key) {
-            return validate_existing_file(PathBuf::from(&value))
-                .with_context(|| format!("{} points to {}", key, value));
+    let fit = |display_width: f32, display_height: f32| {
+        let scale =
+            (window_width as f32 / display_width).min(window_height as f32 / display_height);
+        [
+            ((display_width * scale).round() as u32).clamp(1, window_width.max(1)),
+            ((display_height * scale).round() as u32).clamp(1, window_height.max(1)),
+        ]
+    };
+    let extent = match aspect {
+        Ps1Aspect::Raw => {
+            let scale = (window_width / width).min(window_height / height);
+            if scale >= 1 {
+                [width * scale, height * scale]
+            } else {
+                fit(width as f32, height as f32)
+            }
         }
-    }
+        // the largest whole multiple of the console's lines whose width still fits
+        _ if presentation.integer_scale => (1..=window_height / height)
+            .rev()
+            .map(|scale| {
+                [
+                    (width as f32 * pixel_aspect * scale as f32).round() as u32,
+                    height * scale,
+                ]
+            })
+            .find(|[display_width, _]| *display_width <= window_width)
+            .unwrap_or_else(|| fit(width as f32 * pixel_aspect, height as f32)),
+        _ => fit(width as f32 * pixel_aspect, height as f32),
+    };
+    (
+        [
+            (window_width - extent[0]) / 2,
+            (window_height - extent[1]) / 2,
+        ],
+        extent,
+    )
+}
 
-    for candidate in default_bios_candidates() {
-        if candidate.is_file() {
-            return Ok(candidate);
+/// Repeats every pixel `factor` times in each direction
+fn integer_prescale(pixels: &[u32], width: u32, [x_factor, y_factor]: [u32; 2]) -> Vec<u32> {
+    let mut scaled = Vec::with_capacity(pixels.len() * (x_factor * y_factor) as usize);
+    for row in pixels.chunks_exact(width as usize) {
+        let start = scaled.len();
+        scaled.extend(
+            row.iter()
+                .flat_map(|&pixel| iter::repeat_n(pixel, x_factor as usize)),
+        );
+        let end = scaled.len();
+        for _ in 1..y_factor {
+            scaled.extend_from_within(start..end);
         }
     }
-
-    bail!(
-        "PlayStation BIOS not found. Pass --ps1-bios, set PS1_BIOS/PSX_BIOS, or place SCPH1001/SCPH5501 under bios/."
-    );
+    scaled
 }
 
-fn default_bios_candidates() -> Vec<PathBuf> {
-    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-    let bases = [
-        PathBuf::from("bios/ps1"),
-        PathBuf::from("bios"),
-        manifest_dir.join("bios/ps1"),
-        manifest_dir.join("bios"),
-    ];
-
-    bases
-        .into_iter()
-        .flat_map(|base| BIOS_CANDIDATE_NAMES.iter().map(move |name| base.join(name)))
-        .collect()
+/// Lower is preferred. Mailbox shows the newest frame on the next fixed refresh, which judders
+/// on a variable refresh display; there adaptive vsync or no vsync lets the display follow the
+/// frame pacing instead.
+fn present_mode_rank(mode: &PresentMode, variable_refresh: bool) -> u8 {
+    match (mode, variable_refresh) {
+        (PresentMode::Mailbox, false) | (PresentMode::FifoRelaxed, true) => 0,
+        (PresentMode::Immediate, _) => 1,
+        (PresentMode::Fifo, _) => 2,
+        (PresentMode::FifoRelaxed, false) | (PresentMode::Mailbox, true) => 3,
+        _ => 4,
+    }
 }
 
-fn validate_existing_file(path: PathBuf) -> Result<PathBuf> {
-    if path.is_file() {
-        Ok(path)
-    } else {
-        bail!("PlayStation BIOS file {} does not exist", path.display())
+/// Lower is preferred. The front image holds colours already encoded for an sRGB display, which
+/// a UNORM format in the sRGB colour space shows unchanged. An `_SRGB` format encodes them a
+/// second time in the blit and washes the picture out, other colour spaces (HDR, extended
+/// linear) make it too dark or too bright.
+fn surface_format_rank((format, color_space): &(Format, ColorSpace)) -> u8 {
+    match (format, color_space) {
+        (Format::B8G8R8A8_UNORM | Format::R8G8B8A8_UNORM, ColorSpace::SrgbNonLinear) => 0,
+        (
+            Format::A2B10G10R10_UNORM_PACK32 | Format::A2R10G10B10_UNORM_PACK32,
+            ColorSpace::SrgbNonLinear,
+        ) => 1,
+        (_, ColorSpace::SrgbNonLinear) => 2,
+        _ => 3,
     }
 }
 
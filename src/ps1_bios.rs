@@ -0,0 +1,192 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use log::{info, warn};
+use trapezoid_core::DiscRegion;
+
+const BIOS_SIZE: u64 = 512 * 1024;
+/// Checked first when several BIOS images match, the best tested revisions
+const PREFERRED_NAMES: [&str; 2] = ["scph1001.bin", "scph5501.bin"];
+/// Later BIOS revisions end their version string with the region letter,
+/// e.g. `System ROM Version 4.1 12/16/97 A`
+const VERSION_PREFIX: &[u8] = b"System ROM Version";
+
+/// Picks the BIOS to boot a disc of `disc_region` with. An explicit `--ps1-bios` or `PS1_BIOS`/`PSX_BIOS`
+/// is always used, otherwise the `bios/` directories are searched for an image
+/// of the disc's region (SCPH-1000/5500/7000 for Japan, x001 for North America,
+/// x002 for Europe).
+pub fn resolve(bios_override: Option<PathBuf>, disc_region: Option<DiscRegion>) -> Result<PathBuf> {
+    if let Some(path) = explicit_bios(bios_override)? {
+        if let Some(disc_region) = disc_region
+            && let Some(bios_region) = bios_region(&path)
+            && bios_region != disc_region
+        {
+            warn!(
+                "{} is a {} BIOS but the disc is {}, the game may refuse to boot",
+                path.display(),
+                region_name(bios_region),
+                region_name(disc_region)
+            );
+        }
+        return Ok(path);
+    }
+
+    let candidates = bios_candidates();
+    let Some(first) = candidates.first() else {
+        bail!(
+            "PlayStation BIOS not found. Pass --ps1-bios, set PS1_BIOS/PSX_BIOS, or place SCPH1001/SCPH5501 under bios/."
+        );
+    };
+    let Some(disc_region) = disc_region else {
+        return Ok(first.clone());
+    };
+
+    if let Some(matching) = candidates
+        .iter()
+        .find(|path| bios_region(path) == Some(disc_region))
+    {
+        info!(
+            "Using {} BIOS {} for this disc",
+            region_name(disc_region),
+            matching.display()
+        );
+        return Ok(matching.clone());
+    }
+    warn!(
+        "No {} BIOS (SCPH-{}) found under bios/, booting with {}; the game may refuse to boot",
+        region_name(disc_region),
+        region_models(disc_region),
+        first.display()
+    );
+    Ok(first.clone())
+}
+
+fn explicit_bios(bios_override: Option<PathBuf>) -> Result<Option<PathBuf>> {
+    if let Some(path) = bios_override {
+        return validate_existing_file(path).map(Some);
+    }
+    for key in ["PS1_BIOS", "PSX_BIOS"] {
+        if let Ok(value) = env::var(key) {
+            return validate_existing_file(PathBuf::from(&value))
+                .map(Some)
+                .with_context(|| format!("{} points to {}", key, value));
+        }
+    }
+    Ok(None)
+}
+
+/// Every 512 KiB `.bin` under the BIOS directories, preferred revisions first
+fn bios_candidates() -> Vec<PathBuf> {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let bases = [
+        PathBuf::from("bios/ps1"),
+        PathBuf::from("bios"),
+        manifest_dir.join("bios/ps1"),
+        manifest_dir.join("bios"),
+    ];
+
+    let mut candidates = Vec::new();
+    for base in bases {
+        let Ok(entries) = fs::read_dir(&base) else {
+            continue;
+        };
+        let mut files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("bin"))
+                    && fs::metadata(path)
+                        .is_ok_and(|meta| meta.is_file() && meta.len() == BIOS_SIZE)
+            })
+            .collect();
+        files.sort_by_key(|path| {
+            let name = file_name_lowercase(path);
+            let rank = PREFERRED_NAMES
+                .iter()
+                .position(|preferred| *preferred == name)
+                .unwrap_or(PREFERRED_NAMES.len());
+            (rank, name)
+        });
+        for file in files {
+            if !candidates.contains(&file) {
+                candidates.push(file);
+            }
+        }
+    }
+    candidates
+}
+
+/// Taken from the version string when the image has one, otherwise from the
+/// last digit of an `scphNNNN` file name
+fn bios_region(path: &Path) -> Option<DiscRegion> {
+    fs::read(path)
+        .ok()
+        .and_then(|image| region_from_version(&image))
+        .or_else(|| region_from_model(&file_name_lowercase(path)))
+}
+
+fn region_from_version(image: &[u8]) -> Option<DiscRegion> {
+    let start = image
+        .windows(VERSION_PREFIX.len())
+        .position(|window| window == VERSION_PREFIX)?;
+    let version = &image[start..];
+    let end = version
+        .iter()
+        .position(|&c| c == 0)
+        .unwrap_or(version.len());
+    match version[..end].trim_ascii_end().last()? {
+        b'J' => Some(DiscRegion::Japan),
+        b'A' => Some(DiscRegion::NorthAmerica),
+        b'E' => Some(DiscRegion::Europe),
+        _ => None,
+    }
+}
+
+fn region_from_model(name: &str) -> Option<DiscRegion> {
+    let model = name.strip_prefix("scph")?.trim_start_matches(['-', '_']);
+    let digits: String = model.chars().take_while(char::is_ascii_digit).collect();
+    if digits.len() < 3 {
+        return None;
+    }
+    match digits.chars().last()? {
+        '0' => Some(DiscRegion::Japan),
+        '1' => Some(DiscRegion::NorthAmerica),
+        '2' => Some(DiscRegion::Europe),
+        _ => None,
+    }
+}
+
+fn file_name_lowercase(path: &Path) -> String {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+fn region_name(region: DiscRegion) -> &'static str {
+    match region {
+        DiscRegion::Japan => "Japanese",
+        DiscRegion::NorthAmerica => "North American",
+        DiscRegion::Europe => "European",
+    }
+}
+
+fn region_models(region: DiscRegion) -> &'static str {
+    match region {
+        DiscRegion::Japan => "1000/5500/7000",
+        DiscRegion::NorthAmerica => "1001/5501/7001",
+        DiscRegion::Europe => "1002/5502/7002",
+    }
+}
+
+fn validate_existing_file(path: PathBuf) -> Result<PathBuf> {
+    if path.is_file() {
+        Ok(path)
+    } else {
+        bail!("PlayStation BIOS file {} does not exist", path.display())
+    }
+}
@@ -0,0 +1,289 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail};
+use log::{info, warn};
+use trapezoid_core::Psx;
+
+pub const CHEAT_DIR: &str = "cheats/ps1";
+/// Codes address the 2 MiB of main RAM, accessed through KSEG0
+const RAM_BASE: u32 = 0x8000_0000;
+const RAM_MASK: u32 = 0x1F_FFFF;
+
+/// GameShark / Action Replay codes for one game, read from `cheats/ps1/<game>.txt`.
+///
+/// Each cheat starts with a name line followed by its `XXXXXXXX YYYY` codes, names
+/// prefixed with `-` start disabled and `#` starts a comment:
+///
+/// ```text
+/// Infinite health
+/// 800A1234 0064
+/// -Max money
+/// 800B0010 270F
+/// ```
+pub struct CheatList {
+    path: PathBuf,
+    cheats: Vec<Cheat>,
+    enabled: bool,
+}
+
+struct Cheat {
+    name: String,
+    enabled: bool,
+    ops: Vec<Op>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Write8(u32, u8),
+    Write16(u32, u16),
+    Add8(u32, u8),
+    Sub8(u32, u8),
+    Add16(u32, u16),
+    Sub16(u32, u16),
+    /// Skips the next code unless the comparison holds (`Dx`/`Ex` codes)
+    If {
+        addr: u32,
+        wide: bool,
+        compare: Compare,
+        value: u16,
+    },
+    /// Skips the rest of the cheat unless the halfword matches (`C0` codes)
+    IfAll(u32, u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compare {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+}
+
+impl CheatList {
+    /// A missing file gives an empty list, a broken one is reported and ignored
+    pub fn load(rom_label: &str) -> Self {
+        let mut list = Self {
+            path: Path::new(CHEAT_DIR).join(format!("{rom_label}.txt")),
+            cheats: Vec::new(),
+            enabled: true,
+        };
+        list.reload();
+        list
+    }
+
+    /// Re-reads the cheat file, keeping the current cheats if it no longer parses
+    pub fn reload(&mut self) {
+        if !self.path.is_file() {
+            self.cheats.clear();
+            return;
+        }
+        match fs::read_to_string(&self.path)
+            .map_err(|err| anyhow!(err))
+            .and_then(|text| parse_cheats(&text))
+            .with_context(|| format!("failed to load cheats from {}", self.path.display()))
+        {
+            Ok(cheats) => {
+                self.cheats = cheats;
+                let names: Vec<&str> = self
+                    .cheats
+                    .iter()
+                    .filter(|cheat| cheat.enabled)
+                    .map(|cheat| cheat.name.as_str())
+                    .collect();
+                info!(
+                    "Loaded {} cheat(s) from {}, enabled: {}",
+                    self.cheats.len(),
+                    self.path.display(),
+                    if names.is_empty() {
+                        "none".to_string()
+                    } else {
+                        names.join(", ")
+                    }
+                );
+            }
+            Err(err) => warn!("{err:#}"),
+        }
+    }
+
+    /// Switches every cheat off or back on without touching the file
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        info!(
+            "Cheats {}",
+            if self.enabled { "enabled" } else { "disabled" }
+        );
+    }
+
+    /// Called once per frame, right after vblank
+    pub fn apply(&self, psx: &mut Psx) {
+        if !self.enabled {
+            return;
+        }
+        for cheat in self.cheats.iter().filter(|cheat| cheat.enabled) {
+            apply_ops(&cheat.ops, psx);
+        }
+    }
+}
+
+fn apply_ops(ops: &[Op], psx: &mut Psx) {
+    let mut ops = ops.iter();
+    while let Some(&op) = ops.next() {
+        // writes only fail on misaligned addresses, which the parser rejects
+        match op {
+            Op::Write8(addr, value) => {
+                let _ = psx.bus_write_u8(addr, value);
+            }
+            Op::Write16(addr, value) => {
+                let _ = psx.bus_write_u16(addr, value);
+            }
+            Op::Add8(addr, value) => {
+                let current = psx.bus_read_u8(addr).unwrap_or_default();
+                let _ = psx.bus_write_u8(addr, current.wrapping_add(value));
+            }
+            Op::Sub8(addr, value) => {
+                let current = psx.bus_read_u8(addr).unwrap_or_default();
+                let _ = psx.bus_write_u8(addr, current.wrapping_sub(value));
+            }
+            Op::Add16(addr, value) => {
+                let current = psx.bus_read_u16(addr).unwrap_or_default();
+                let _ = psx.bus_write_u16(addr, current.wrapping_add(value));
+            }
+            Op::Sub16(addr, value) => {
+                let current = psx.bus_read_u16(addr).unwrap_or_default();
+                let _ = psx.bus_write_u16(addr, current.wrapping_sub(value));
+            }
+            Op::If {
+                addr,
+                wide,
+                compare,
+                value,
+            } => {
+                let current = if wide {
+                    psx.bus_read_u16(addr).unwrap_or_default()
+                } else {
+                    u16::from(psx.bus_read_u8(addr).unwrap_or_default())
+                };
+                let holds = match compare {
+                    Compare::Equal => current == value,
+                    Compare::NotEqual => current != value,
+                    Compare::Less => current < value,
+                    Compare::Greater => current > value,
+                };
+                if !holds {
+                    ops.next();
+                }
+            }
+            Op::IfAll(addr, value) => {
+                if psx.bus_read_u16(addr).unwrap_or_default() != value {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn parse_cheats(text: &str) -> Result<Vec<Cheat>> {
+    let mut cheats: Vec<Cheat> = Vec::new();
+    // codes of a slide (`50` code) wait for the write they repeat
+    let mut slide: Option<(u8, u8, u16)> = None;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((code, value)) = parse_code_line(line) else {
+            let (enabled, name) = match line.strip_prefix('-') {
+                Some(name) => (false, name.trim()),
+                None => (true, line),
+            };
+            if slide.is_some() {
+                bail!("line {}: slide code is not followed by a write", number + 1);
+            }
+            cheats.push(Cheat {
+                name: name.to_string(),
+                enabled,
+                ops: Vec::new(),
+            });
+            continue;
+        };
+
+        let Some(cheat) = cheats.last_mut() else {
+            bail!("line {}: code before the first cheat name", number + 1);
+        };
+        let error = |msg: &str| anyhow!("line {}: {} ({})", number + 1, msg, line);
+        let addr = RAM_BASE | (code & RAM_MASK);
+        let kind = (code >> 24) as u8;
+        let wide = matches!(kind, 0x80 | 0x10 | 0x11 | 0xC0 | 0xD0..=0xD3);
+        if wide && !addr.is_multiple_of(2) {
+            return Err(error("16-bit code on an odd address"));
+        }
+
+        if let Some((count, addr_step, value_step)) = slide.take() {
+            if !matches!(kind, 0x30 | 0x80) {
+                return Err(error("slide codes must be followed by a 30 or 80 write"));
+            }
+            for i in 0..u32::from(count) {
+                let slide_addr = addr.wrapping_add(i * u32::from(addr_step));
+                let slide_value = value.wrapping_add(i as u16 * value_step);
+                cheat.ops.push(if kind == 0x80 {
+                    Op::Write16(slide_addr, slide_value)
+                } else {
+                    Op::Write8(slide_addr, slide_value as u8)
+                });
+            }
+            continue;
+        }
+
+        let compare = |kind: u8| match kind & 0x0F {
+            0 => Compare::Equal,
+            1 => Compare::NotEqual,
+            2 => Compare::Less,
+            _ => Compare::Greater,
+        };
+        let op = match kind {
+            0x30 => Op::Write8(addr, value as u8),
+            0x80 => Op::Write16(addr, value),
+            0x20 => Op::Add8(addr, value as u8),
+            0x21 => Op::Sub8(addr, value as u8),
+            0x10 => Op::Add16(addr, value),
+            0x11 => Op::Sub16(addr, value),
+            0xD0..=0xD3 => Op::If {
+                addr,
+                wide: true,
+                compare: compare(kind),
+                value,
+            },
+            0xE0..=0xE3 => Op::If {
+                addr,
+                wide: false,
+                compare: compare(kind),
+                value: value & 0xFF,
+            },
+            0xC0 => Op::IfAll(addr, value),
+            0x50 => {
+                slide = Some(((code >> 8) as u8, code as u8, value));
+                continue;
+            }
+            _ => return Err(error("unsupported code type")),
+        };
+        cheat.ops.push(op);
+    }
+
+    if slide.is_some() {
+        bail!("the last slide code is not followed by a write");
+    }
+    Ok(cheats)
+}
+
+/// `800A1234 0064` (the space is optional), `None` when the line is a name
+fn parse_code_line(line: &str) -> Option<(u32, u16)> {
+    let digits: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.len() != 12 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let code = u32::from_str_radix(&digits[..8], 16).ok()?;
+    let value = u16::from_str_radix(&digits[8..], 16).ok()?;
+    Some((code, value))
+}
@@ -0,0 +1,320 @@
+use std::io::{self, Write};
+
+use anyhow::{Result, anyhow, bail};
+use log::info;
+use trapezoid_core::Psx;
+use trapezoid_core::cpu::{CpuState, Instruction, RegisterType};
+
+/// Frames `step` runs at most while waiting for the CPU to execute the instruction,
+/// it only waits longer than one frame while a DMA transfer is running
+const STEP_FRAME_LIMIT: u32 = 60;
+const DEFAULT_DUMP_WORDS: u32 = 16;
+const DEFAULT_DISASSEMBLY_LINES: u32 = 8;
+
+const HELP: &str = "\
+Commands (an empty line repeats the last one, addresses and values are hex or register names):
+  c, continue           resume the game
+  s, step [count]       execute instructions and stay paused
+  n, next               step over a function call, then resume until it returns
+  finish                resume until the current function returns
+  b <addr>              break before executing <addr>
+  bw <addr> / br <addr> break after a write to / read from <addr>
+  d <addr>              delete the breakpoints at <addr>
+  bl                    list breakpoints
+  r, regs               show registers
+  set <reg> <value>     change a register
+  x <addr> [words]      dump memory
+  w <addr> <value>      write a word to memory
+  dis [addr] [count]    disassemble, from pc by default
+  bt                    call stack
+  q, quit               exit the emulator";
+
+/// Terminal debugger for the PlayStation CPU, enabled with `--ps1-debug`.
+///
+/// The game starts paused and the prompt is opened again whenever a breakpoint or step
+/// stops the CPU, or `Pause` is pressed in the game window. The window doesn't update
+/// while the prompt is open.
+pub struct Ps1Debugger {
+    break_requested: bool,
+    /// stdin was closed, the game keeps running without prompts
+    detached: bool,
+    last_command: String,
+}
+
+enum Flow {
+    Prompt,
+    Resume,
+    Quit,
+}
+
+impl Ps1Debugger {
+    pub fn new() -> Self {
+        println!("PlayStation debugger, type `help` for commands");
+        Self {
+            break_requested: true,
+            detached: false,
+            last_command: String::new(),
+        }
+    }
+
+    /// Opens the prompt before the next frame
+    pub fn request_break(&mut self) {
+        self.break_requested = true;
+    }
+
+    /// Called before every frame, returns `false` when the emulator should exit
+    pub fn before_frame(&mut self, psx: &mut Psx) -> bool {
+        if !std::mem::take(&mut self.break_requested) {
+            return true;
+        }
+        println!("Paused");
+        self.prompt(psx)
+    }
+
+    /// Called when the core stops the CPU, returns `false` when the emulator should exit
+    pub fn cpu_stopped(&mut self, psx: &mut Psx, state: CpuState) -> bool {
+        println!("{}", describe_state(state));
+        self.prompt(psx)
+    }
+
+    fn prompt(&mut self, psx: &mut Psx) -> bool {
+        if self.detached {
+            return true;
+        }
+        print_location(psx);
+        loop {
+            print!("(ps1) ");
+            let _ = io::stdout().flush();
+            let mut line = String::new();
+            if matches!(io::stdin().read_line(&mut line), Ok(0) | Err(_)) {
+                info!("Debugger input closed, resuming without it");
+                self.detached = true;
+                return true;
+            }
+            let line = match line.trim() {
+                "" => self.last_command.clone(),
+                line => line.to_string(),
+            };
+            self.last_command = line.clone();
+
+            match self.execute(psx, &line) {
+                Ok(Flow::Prompt) => {}
+                Ok(Flow::Resume) => return true,
+                Ok(Flow::Quit) => return false,
+                Err(err) => println!("{err:#}"),
+            }
+        }
+    }
+
+    fn execute(&mut self, psx: &mut Psx, line: &str) -> Result<Flow> {
+        let mut args = line.split_whitespace();
+        let Some(command) = args.next() else {
+            return Ok(Flow::Prompt);
+        };
+        let args: Vec<&str> = args.collect();
+        let arg = |index: usize, name: &str| -> Result<&str> {
+            args.get(index)
+                .copied()
+                .ok_or_else(|| anyhow!("`{command}` needs {name}"))
+        };
+
+        match command {
+            "help" | "h" | "?" => println!("{HELP}"),
+            "c" | "continue" => return Ok(Flow::Resume),
+            "s" | "step" => {
+                let count = match args.first() {
+                    Some(count) => count
+                        .parse::<u32>()
+                        .map_err(|_| anyhow!("invalid count `{count}`"))?,
+                    None => 1,
+                };
+                for _ in 0..count {
+                    let state = step(psx)?;
+                    if state != CpuState::Step {
+                        println!("{}", describe_state(state));
+                        break;
+                    }
+                }
+                print_location(psx);
+            }
+            "n" | "next" => {
+                psx.cpu().debugger().step_over();
+                return Ok(Flow::Resume);
+            }
+            "finish" => {
+                if psx.cpu().debugger().call_stack().is_empty() {
+                    bail!("no function to return from");
+                }
+                psx.cpu().debugger().step_out();
+                return Ok(Flow::Resume);
+            }
+            "b" | "break" => {
+                let addr = parse_value(psx, arg(0, "an address")?)?;
+                psx.cpu().debugger().add_breakpoint(addr);
+            }
+            "bw" => {
+                let addr = parse_value(psx, arg(0, "an address")?)?;
+                psx.cpu().debugger().add_write_breakpoint(addr);
+            }
+            "br" => {
+                let addr = parse_value(psx, arg(0, "an address")?)?;
+                psx.cpu().debugger().add_read_breakpoint(addr);
+            }
+            "d" | "delete" => {
+                let addr = parse_value(psx, arg(0, "an address")?)?;
+                let debugger = psx.cpu().debugger();
+                let removed = [
+                    debugger.remove_breakpoint(addr),
+                    debugger.remove_write_breakpoint(addr),
+                    debugger.remove_read_breakpoint(addr),
+                ];
+                if !removed.contains(&true) {
+                    bail!("no breakpoint at {addr:08X}");
+                }
+            }
+            "bl" => {
+                let debugger = psx.cpu().debugger();
+                let mut breakpoints: Vec<(u32, &str)> = debugger
+                    .instruction_breakpoints()
+                    .into_iter()
+                    .map(|addr| (addr, "execute"))
+                    .chain(debugger.write_breakpoints().iter().map(|&a| (a, "write")))
+                    .chain(debugger.read_breakpoints().iter().map(|&a| (a, "read")))
+                    .collect();
+                breakpoints.sort();
+                if breakpoints.is_empty() {
+                    println!("No breakpoints");
+                }
+                for (addr, kind) in breakpoints {
+                    println!("  {addr:08X} {kind}");
+                }
+            }
+            "r" | "regs" => print_registers(psx),
+            "set" => {
+                let register: RegisterType = arg(0, "a register")?
+                    .parse()
+                    .map_err(|err| anyhow!("{err}"))?;
+                let value = parse_value(psx, arg(1, "a value")?)?;
+                psx.cpu().registers_mut().write(register, value);
+            }
+            "x" => {
+                let addr = parse_value(psx, arg(0, "an address")?)? & !3;
+                let words = optional_count(&args, 1, DEFAULT_DUMP_WORDS)?;
+                for row in 0..words.div_ceil(4) {
+                    let row_addr = addr.wrapping_add(row * 16);
+                    let values: Vec<String> = (0..4.min(words - row * 4))
+                        .map(|i| match psx.bus_read_u32(row_addr.wrapping_add(i * 4)) {
+                            Ok(value) => format!("{value:08X}"),
+                            Err(_) => "????????".to_string(),
+                        })
+                        .collect();
+                    println!("  {row_addr:08X}: {}", values.join(" "));
+                }
+            }
+            "w" => {
+                let addr = parse_value(psx, arg(0, "an address")?)?;
+                let value = parse_value(psx, arg(1, "a value")?)?;
+                psx.bus_write_u32(addr, value)
+                    .map_err(|err| anyhow!("cannot write {addr:08X}: {err}"))?;
+            }
+            "dis" => {
+                let addr = match args.first() {
+                    Some(addr) => parse_value(psx, addr)?,
+                    None => psx.cpu().registers().read(RegisterType::Pc),
+                };
+                let lines = optional_count(&args, 1, DEFAULT_DISASSEMBLY_LINES)?;
+                disassemble(psx, addr & !3, lines);
+            }
+            "bt" => {
+                let pc = psx.cpu().registers().read(RegisterType::Pc);
+                println!("  #0 {pc:08X}");
+                let call_stack = psx.cpu().debugger().call_stack().to_vec();
+                for (depth, addr) in call_stack.iter().rev().enumerate() {
+                    println!("  #{} {addr:08X}", depth + 1);
+                }
+            }
+            "q" | "quit" => return Ok(Flow::Quit),
+            _ => bail!("unknown command `{command}`, type `help` for commands"),
+        }
+        Ok(Flow::Prompt)
+    }
+}
+
+/// Executes one instruction, returns the state the CPU stopped with
+fn step(psx: &mut Psx) -> Result<CpuState> {
+    psx.cpu().debugger().single_step();
+    for _ in 0..STEP_FRAME_LIMIT {
+        let state = psx.clock_full_video_frame();
+        if state != CpuState::Normal {
+            return Ok(state);
+        }
+    }
+    bail!("the CPU did not run for {STEP_FRAME_LIMIT} frames")
+}
+
+fn describe_state(state: CpuState) -> String {
+    match state {
+        CpuState::Normal => "Running".to_string(),
+        CpuState::InstructionBreakpoint(addr) => format!("Breakpoint at {addr:08X}"),
+        CpuState::WriteBreakpoint { addr, bits } => format!("{bits}-bit write to {addr:08X}"),
+        CpuState::ReadBreakpoint { addr, bits } => format!("{bits}-bit read from {addr:08X}"),
+        CpuState::Step => "Stepped".to_string(),
+        CpuState::StepOver => "Stepped over the call".to_string(),
+        CpuState::StepOut => "Returned from the function".to_string(),
+    }
+}
+
+/// Hex with an optional `0x`, or the value of a register
+fn parse_value(psx: &mut Psx, text: &str) -> Result<u32> {
+    let hex = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    if let Ok(value) = u32::from_str_radix(hex, 16) {
+        return Ok(value);
+    }
+    let register: RegisterType = text
+        .parse()
+        .map_err(|_| anyhow!("`{text}` is not a hex number or a register"))?;
+    Ok(psx.cpu().registers().read(register))
+}
+
+fn optional_count(args: &[&str], index: usize, default: u32) -> Result<u32> {
+    match args.get(index) {
+        Some(count) => count
+            .parse()
+            .map_err(|_| anyhow!("invalid count `{count}`")),
+        None => Ok(default),
+    }
+}
+
+fn print_location(psx: &mut Psx) {
+    let pc = psx.cpu().registers().read(RegisterType::Pc);
+    disassemble(psx, pc, 1);
+}
+
+fn disassemble(psx: &mut Psx, addr: u32, lines: u32) {
+    let pc = psx.cpu().registers().read(RegisterType::Pc);
+    for i in 0..lines {
+        let addr = addr.wrapping_add(i * 4);
+        let marker = if addr == pc { "=>" } else { "  " };
+        match psx.bus_read_u32(addr) {
+            Ok(word) => println!(
+                "{marker} {addr:08X}: {word:08X}  {}",
+                Instruction::from_u32(word, addr)
+            ),
+            Err(_) => println!("{marker} {addr:08X}: ????????"),
+        }
+    }
+}
+
+fn print_registers(psx: &mut Psx) {
+    let registers = psx.cpu().registers();
+    for row in RegisterType::ALL.chunks(4) {
+        let line: Vec<String> = row
+            .iter()
+            .map(|&register| format!("{:>4} {:08X}", register, registers.read(register)))
+            .collect();
+        println!("  {}", line.join("  "));
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, bail};
+use sdl2::keyboard::Keycode;
+use trapezoid_core::DigitalControllerKey;
+use winit::keyboard::KeyCode;
+
+use crate::config::{Ps1Config, Ps1KeyboardProfile};
+
+type Binding = (&'static str, DigitalControllerKey);
+
+/// The player 1 layout from before profiles existed
+const ARROWS: &[Binding] = &[
+    ("Up", DigitalControllerKey::Up),
+    ("Down", DigitalControllerKey::Down),
+    ("Left", DigitalControllerKey::Left),
+    ("Right", DigitalControllerKey::Right),
+    ("Z", DigitalControllerKey::X),
+    ("X", DigitalControllerKey::Circle),
+    ("A", DigitalControllerKey::Square),
+    ("S", DigitalControllerKey::Triangle),
+    ("Q", DigitalControllerKey::L1),
+    ("W", DigitalControllerKey::R1),
+    ("1", DigitalControllerKey::L2),
+    ("2", DigitalControllerKey::R2),
+    ("Return", DigitalControllerKey::Start),
+    ("Keypad Enter", DigitalControllerKey::Start),
+    ("Space", DigitalControllerKey::Select),
+    ("Backspace", DigitalControllerKey::Select),
+    ("Left Shift", DigitalControllerKey::Select),
+    ("Right Shift", DigitalControllerKey::Select),
+];
+
+/// The player 2 layout from before profiles existed
+const IJKL: &[Binding] = &[
+    ("I", DigitalControllerKey::Up),
+    ("K", DigitalControllerKey::Down),
+    ("J", DigitalControllerKey::Left),
+    ("L", DigitalControllerKey::Right),
+    ("N", DigitalControllerKey::X),
+    ("M", DigitalControllerKey::Circle),
+    ("H", DigitalControllerKey::Square),
+    ("U", DigitalControllerKey::Triangle),
+    ("Y", DigitalControllerKey::L1),
+    ("O", DigitalControllerKey::R1),
+    ("8", DigitalControllerKey::L2),
+    ("9", DigitalControllerKey::R2),
+    ("P", DigitalControllerKey::Start),
+    ("0", DigitalControllerKey::Select),
+];
+
+/// Left half of the keyboard, the face buttons sit in the T/F/G/H diamond. Pairs with `numpad`.
+const WASD: &[Binding] = &[
+    ("W", DigitalControllerKey::Up),
+    ("S", DigitalControllerKey::Down),
+    ("A", DigitalControllerKey::Left),
+    ("D", DigitalControllerKey::Right),
+    ("G", DigitalControllerKey::X),
+    ("H", DigitalControllerKey::Circle),
+    ("F", DigitalControllerKey::Square),
+    ("T", DigitalControllerKey::Triangle),
+    ("Q", DigitalControllerKey::L1),
+    ("E", DigitalControllerKey::R1),
+    ("1", DigitalControllerKey::L2),
+    ("3", DigitalControllerKey::R2),
+    ("X", DigitalControllerKey::Start),
+    ("Z", DigitalControllerKey::Select),
+];
+
+/// Arrow keys with the face buttons in the keypad 8/4/6/2 diamond. Pairs with `wasd`.
+const NUMPAD: &[Binding] = &[
+    ("Up", DigitalControllerKey::Up),
+    ("Down", DigitalControllerKey::Down),
+    ("Left", DigitalControllerKey::Left),
+    ("Right", DigitalControllerKey::Right),
+    ("Keypad 2", DigitalControllerKey::X),
+    ("Keypad 6", DigitalControllerKey::Circle),
+    ("Keypad 4", DigitalControllerKey::Square),
+    ("Keypad 8", DigitalControllerKey::Triangle),
+    ("Keypad 7", DigitalControllerKey::L1),
+    ("Keypad 9", DigitalControllerKey::R1),
+    ("Keypad 1", DigitalControllerKey::L2),
+    ("Keypad 3", DigitalControllerKey::R2),
+    ("Keypad Enter", DigitalControllerKey::Start),
+    ("Keypad +", DigitalControllerKey::Select),
+];
+
+/// Profiles that can be assigned without defining them in `[ps1.keyboard_profiles]`
+pub fn built_in(name: &str) -> Option<&'static [Binding]> {
+    match name {
+        "arrows" => Some(ARROWS),
+        "ijkl" => Some(IJKL),
+        "wasd" => Some(WASD),
+        "numpad" => Some(NUMPAD),
+        _ => None,
+    }
+}
+
+impl Ps1KeyboardProfile {
+    /// The bound keys by SDL key name
+    pub fn bindings(&self) -> impl Iterator<Item = (&str, DigitalControllerKey)> {
+        [
+            (&self.up, DigitalControllerKey::Up),
+            (&self.down, DigitalControllerKey::Down),
+            (&self.left, DigitalControllerKey::Left),
+            (&self.right, DigitalControllerKey::Right),
+            (&self.cross, DigitalControllerKey::X),
+            (&self.circle, DigitalControllerKey::Circle),
+            (&self.square, DigitalControllerKey::Square),
+            (&self.triangle, DigitalControllerKey::Triangle),
+            (&self.l1, DigitalControllerKey::L1),
+            (&self.r1, DigitalControllerKey::R1),
+            (&self.l2, DigitalControllerKey::L2),
+            (&self.r2, DigitalControllerKey::R2),
+            (&self.start, DigitalControllerKey::Start),
+            (&self.select, DigitalControllerKey::Select),
+        ]
+        .into_iter()
+        .filter_map(|(key, button)| Some((key.as_deref()?, button)))
+    }
+}
+
+/// Which player's button each key presses, from the profiles in `[ps1] keyboard`
+#[derive(Default)]
+pub struct KeyboardLayout {
+    /// Keyed by lowercase SDL key name
+    keys: HashMap<String, Vec<(usize, DigitalControllerKey)>>,
+}
+
+impl KeyboardLayout {
+    pub fn new(settings: &Ps1Config, players: usize) -> Result<Self> {
+        let mut keys: HashMap<String, Vec<_>> = HashMap::new();
+        for (player, name) in settings.keyboard.iter().enumerate().take(players) {
+            let bindings: Vec<(&str, DigitalControllerKey)> =
+                match settings.keyboard_profiles.get(name) {
+                    Some(profile) => profile.bindings().collect(),
+                    None => match built_in(name) {
+                        Some(bindings) => bindings.to_vec(),
+                        None => bail!("unknown keyboard profile `{name}`"),
+                    },
+                };
+            for (key, button) in bindings {
+                keys.entry(key.to_ascii_lowercase())
+                    .or_default()
+                    .push((player, button));
+            }
+        }
+        Ok(Self { keys })
+    }
+
+    /// Buttons a key of the software renderer's SDL window presses
+    pub fn sdl_key(&self, code: Keycode) -> &[(usize, DigitalControllerKey)] {
+        self.lookup(&code.name())
+    }
+
+    /// Buttons a key of the Vulkan renderer's winit window presses
+    pub fn winit_key(&self, code: KeyCode) -> &[(usize, DigitalControllerKey)] {
+        sdl_key_name(code).map_or(&[], |name| self.lookup(&name))
+    }
+
+    fn lookup(&self, name: &str) -> &[(usize, DigitalControllerKey)] {
+        self.keys
+            .get(&name.to_ascii_lowercase())
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+/// The SDL name of a winit key, so profiles name keys the same way for both renderers
+fn sdl_key_name(code: KeyCode) -> Option<String> {
+    let debug = format!("{code:?}");
+    if let Some(key) = debug
+        .strip_prefix("Key")
+        .or_else(|| debug.strip_prefix("Digit"))
+    {
+        return Some(key.to_string());
+    }
+    if let Some(digit) = debug.strip_prefix("Numpad")
+        && digit.len() == 1
+    {
+        return Some(format!("Keypad {digit}"));
+    }
+    let name = match code {
+        KeyCode::ArrowUp => "Up",
+        KeyCode::ArrowDown => "Down",
+        KeyCode::ArrowLeft => "Left",
+        KeyCode::ArrowRight => "Right",
+        KeyCode::Enter => "Return",
+        KeyCode::NumpadEnter => "Keypad Enter",
+        KeyCode::NumpadAdd => "Keypad +",
+        KeyCode::NumpadSubtract => "Keypad -",
+        KeyCode::NumpadMultiply => "Keypad *",
+        KeyCode::NumpadDivide => "Keypad /",
+        KeyCode::NumpadDecimal => "Keypad .",
+        KeyCode::Space => "Space",
+        KeyCode::Backspace => "Backspace",
+        KeyCode::Tab => "Tab",
+        KeyCode::ShiftLeft => "Left Shift",
+        KeyCode::ShiftRight => "Right Shift",
+        KeyCode::ControlLeft => "Left Ctrl",
+        KeyCode::ControlRight => "Right Ctrl",
+        KeyCode::AltLeft => "Left Alt",
+        KeyCode::AltRight => "Right Alt",
+        KeyCode::Comma => ",",
+        KeyCode::Period => ".",
+        KeyCode::Slash => "/",
+        KeyCode::Semicolon => ";",
+        KeyCode::Quote => "'",
+        KeyCode::BracketLeft => "[",
+        KeyCode::BracketRight => "]",
+        KeyCode::Backslash => "\\",
+        KeyCode::Minus => "-",
+        KeyCode::Equal => "=",
+        KeyCode::Backquote => "`",
+        _ => return None,
+    };
+    Some(name.to_string())
+}
@@ -0,0 +1,449 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, anyhow, bail};
+use clap::ValueEnum;
+use log::{info, warn};
+use trapezoid_core::{MEMORY_CARD_SIZE, Psx};
+
+use crate::saves;
+use crate::systems::GameSystem;
+
+const SLOT_COUNT: usize = 2;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+const FRAME_SIZE: usize = 0x80;
+const BLOCK_SIZE: usize = 0x2000;
+const BLOCK_COUNT: usize = 15;
+const NAME_LEN: usize = 20;
+
+const STATE_FIRST: u8 = 0x51;
+const STATE_MIDDLE: u8 = 0x52;
+const STATE_LAST: u8 = 0x53;
+const STATE_FREE: u8 = 0xA0;
+const NO_NEXT_BLOCK: u16 = 0xFFFF;
+
+/// Which `.mcd` files get inserted when a game boots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MemoryCardMode {
+    /// One pair of cards per game, named after the disc image
+    PerGame,
+    /// One pair of cards shared by every game
+    Shared,
+}
+
+/// Keeps the emulated memory cards in sync with their files under `saves/ps1/`.
+pub struct MemoryCardSlots {
+    paths: [PathBuf; SLOT_COUNT],
+    last_flush: Instant,
+}
+
+impl MemoryCardSlots {
+    /// Without a disc (BIOS shell) the shared pair is inserted in either mode.
+    pub fn attach(rom: Option<&Path>, mode: MemoryCardMode, psx: &mut Psx) -> Result<Self> {
+        let dir = saves::prepare(GameSystem::Ps1)?;
+        let per_game = matches!((mode, rom), (MemoryCardMode::PerGame, Some(_)));
+        let stem = match rom.filter(|_| per_game) {
+            Some(rom) => rom
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or("playstation"),
+            None => "shared",
+        };
+        let paths = [1, 2].map(|n| dir.join(format!("{stem}_{n}.mcd")));
+
+        for (slot, path) in paths.iter().enumerate() {
+            // trapezoid used to keep its cards as memcard0/1.mcd in the working directory
+            let legacy = PathBuf::from(format!("memcard{slot}.mcd"));
+            let source = if path.is_file() {
+                path
+            } else if !per_game && legacy.is_file() {
+                info!("Importing {} into {}", legacy.display(), path.display());
+                &legacy
+            } else {
+                continue;
+            };
+            let Some(data) = saves::read(source, Some(MEMORY_CARD_SIZE))? else {
+                continue;
+            };
+            psx.load_memory_card(slot, &data)
+                .map_err(|err| anyhow!(err))
+                .with_context(|| format!("failed to insert {}", source.display()))?;
+            info!("Memory card {} loaded from {}", slot + 1, source.display());
+        }
+
+        Ok(Self {
+            paths,
+            last_flush: Instant::now(),
+        })
+    }
+
+    /// Writes back cards the game has saved to, at most every few seconds unless `force`d.
+    pub fn flush(&mut self, psx: &mut Psx, force: bool) {
+        if !force && self.last_flush.elapsed() < FLUSH_INTERVAL {
+            return;
+        }
+        self.last_flush = Instant::now();
+
+        for (slot, path) in self.paths.iter().enumerate() {
+            if let Some(data) = psx.take_memory_card_changes(slot) {
+                match saves::write(path, data) {
+                    Ok(()) => info!("Memory card {} saved to {}", slot + 1, path.display()),
+                    Err(err) => warn!("Failed to save memory card {}: {err:#}", slot + 1),
+                }
+            }
+        }
+    }
+}
+
+/// A save file spanning one or more blocks on a card.
+struct SaveEntry {
+    name: String,
+    title: String,
+    blocks: Vec<usize>,
+}
+
+struct CardImage {
+    data: Vec<u8>,
+}
+
+impl CardImage {
+    fn open(path: &Path) -> Result<Self> {
+        let data = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        if data.len() != MEMORY_CARD_SIZE {
+            bail!(
+                "{} is not a raw PlayStation memory card ({} bytes, expected {})",
+                path.display(),
+                data.len(),
+                MEMORY_CARD_SIZE
+            );
+        }
+        Ok(Self { data })
+    }
+
+    fn blank() -> Self {
+        let mut card = Self {
+            data: vec![0; MEMORY_CARD_SIZE],
+        };
+        card.data[0] = b'M';
+        card.data[1] = b'C';
+        card.update_checksum(0);
+        for block in 0..BLOCK_COUNT {
+            card.free_block(block);
+        }
+        card
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        saves::write(path, &self.data)
+    }
+
+    /// Directory frame describing data block `block` (0-based, block 0 is the card header).
+    fn frame(&self, block: usize) -> &[u8] {
+        let start = (block + 1) * FRAME_SIZE;
+        &self.data[start..start + FRAME_SIZE]
+    }
+
+    fn frame_mut(&mut self, block: usize) -> &mut [u8] {
+        let start = (block + 1) * FRAME_SIZE;
+        &mut self.data[start..start + FRAME_SIZE]
+    }
+
+    fn block_data(&self, block: usize) -> &[u8] {
+        let start = (block + 1) * BLOCK_SIZE;
+        &self.data[start..start + BLOCK_SIZE]
+    }
+
+    fn update_checksum(&mut self, frame_index: usize) {
+        let start = frame_index * FRAME_SIZE;
+        let frame = &mut self.data[start..start + FRAME_SIZE];
+        frame[FRAME_SIZE - 1] = frame[..FRAME_SIZE - 1].iter().fold(0, |acc, b| acc ^ b);
+    }
+
+    fn is_free(&self, block: usize) -> bool {
+        !matches!(
+            self.frame(block)[0],
+            STATE_FIRST | STATE_MIDDLE | STATE_LAST
+        )
+    }
+
+    fn free_block(&mut self, block: usize) {
+        let frame = self.frame_mut(block);
+        frame.fill(0);
+        frame[0] = STATE_FREE;
+        frame[8..10].copy_from_slice(&NO_NEXT_BLOCK.to_le_bytes());
+        self.update_checksum(block + 1);
+    }
+
+    fn saves(&self) -> Vec<SaveEntry> {
+        (0..BLOCK_COUNT)
+            .filter(|&block| self.frame(block)[0] == STATE_FIRST)
+            .map(|first| {
+                let mut blocks = vec![first];
+                let mut current = first;
+                while blocks.len() < BLOCK_COUNT {
+                    let frame = self.frame(current);
+                    let next = u16::from_le_bytes([frame[8], frame[9]]);
+                    if next == NO_NEXT_BLOCK || next as usize >= BLOCK_COUNT {
+                        break;
+                    }
+                    current = next as usize;
+                    blocks.push(current);
+                }
+                let frame = self.frame(first);
+                let name = String::from_utf8_lossy(&frame[10..10 + NAME_LEN])
+                    .trim_end_matches('\0')
+                    .to_string();
+                SaveEntry {
+                    name,
+                    title: decode_title(&self.block_data(first)[4..68]),
+                    blocks,
+                }
+            })
+            .collect()
+    }
+
+    fn free_blocks(&self) -> Vec<usize> {
+        (0..BLOCK_COUNT)
+            .filter(|&block| self.is_free(block))
+            .collect()
+    }
+
+    fn delete(&mut self, save: &SaveEntry) {
+        for &block in &save.blocks {
+            self.free_block(block);
+        }
+    }
+
+    fn copy_from(&mut self, source: &CardImage, save: &SaveEntry) -> Result<()> {
+        if self
+            .saves()
+            .iter()
+            .any(|existing| existing.name == save.name)
+        {
+            bail!(
+                "the destination card already has a save named {}",
+                save.name
+            );
+        }
+        let free = self.free_blocks();
+        if free.len() < save.blocks.len() {
+            bail!(
+                "the destination card has {} free block(s), {} needed",
+                free.len(),
+                save.blocks.len()
+            );
+        }
+
+        let targets = &free[..save.blocks.len()];
+        for (idx, (&src, &dst)) in save.blocks.iter().zip(targets).enumerate() {
+            let data = source.block_data(src).to_vec();
+            let start = (dst + 1) * BLOCK_SIZE;
+            self.data[start..start + BLOCK_SIZE].copy_from_slice(&data);
+
+            let src_frame = source.frame(src).to_vec();
+            let frame = self.frame_mut(dst);
+            frame.copy_from_slice(&src_frame);
+            frame[0] = if idx == 0 {
+                STATE_FIRST
+            } else if idx + 1 == targets.len() {
+                STATE_LAST
+            } else {
+                STATE_MIDDLE
+            };
+            let next = targets
+                .get(idx + 1)
+                .map_or(NO_NEXT_BLOCK, |&block| block as u16);
+            frame[8..10].copy_from_slice(&next.to_le_bytes());
+            self.update_checksum(dst + 1);
+        }
+        Ok(())
+    }
+}
+
+/// Save titles are stored as Shift-JIS; only the full-width ASCII range is mapped back.
+fn decode_title(raw: &[u8]) -> String {
+    let mut title = String::new();
+    let mut bytes = raw.iter().copied();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            0 => break,
+            0x20..=0x7E => title.push(byte as char),
+            0x81 | 0x82 => {
+                let Some(low) = bytes.next() else { break };
+                let ch = match (byte, low) {
+                    (0x81, 0x40) => ' ',
+                    (0x81, 0x46) => ':',
+                    (0x81, 0x5E) => '/',
+                    (0x81, 0x69) => '(',
+                    (0x81, 0x6A) => ')',
+                    (0x81, 0x7C) => '-',
+                    (0x82, 0x4F..=0x58) => (b'0' + (low - 0x4F)) as char,
+                    (0x82, 0x60..=0x79) => (b'A' + (low - 0x60)) as char,
+                    (0x82, 0x81..=0x9A) => (b'a' + (low - 0x81)) as char,
+                    _ => '?',
+                };
+                title.push(ch);
+            }
+            _ => {
+                bytes.next();
+                title.push('?');
+            }
+        }
+    }
+    title.trim().to_string()
+}
+
+/// Terminal manager for copying and deleting saves between the cards under `saves/ps1/`.
+pub fn manage() -> Result<()> {
+    let dir = saves::prepare(GameSystem::Ps1)?;
+
+    loop {
+        let cards = list_cards(&dir)?;
+        println!("\n=== PlayStation Memory Cards ({}) ===", dir.display());
+        for (idx, path) in cards.iter().enumerate() {
+            let used = CardImage::open(path)
+                .map(|card| format!("{} block(s) free", card.free_blocks().len()))
+                .unwrap_or_else(|err| format!("unreadable: {err}"));
+            println!("{:>2}. {} ({used})", idx + 1, file_name(path));
+        }
+        println!(" n. Create a blank card");
+        println!(" 0. Quit");
+
+        let input = prompt("Select a card: ")?;
+        match input.as_str() {
+            "0" | "q" => return Ok(()),
+            "n" => {
+                let name = prompt("New card name (without .mcd): ")?;
+                if name.is_empty() {
+                    continue;
+                }
+                if let Err(err) = saves::check_file_name(&name) {
+                    println!("Error: {err:#}");
+                    continue;
+                }
+                let path = dir.join(format!("{name}.mcd"));
+                if path.exists() {
+                    println!("{} already exists.", path.display());
+                } else {
+                    CardImage::blank().save(&path)?;
+                    println!("Created {}.", path.display());
+                }
+            }
+            _ => match input.parse::<usize>() {
+                Ok(choice) if (1..=cards.len()).contains(&choice) => {
+                    if let Err(err) = manage_card(&cards[choice - 1], &cards) {
+                        println!("Error: {err:#}");
+                    }
+                }
+                _ => println!("Invalid selection."),
+            },
+        }
+    }
+}
+
+fn manage_card(path: &Path, cards: &[PathBuf]) -> Result<()> {
+    loop {
+        let mut card = CardImage::open(path)?;
+        let saves = card.saves();
+        println!("\n-- {} --", file_name(path));
+        if saves.is_empty() {
+            println!("    (no saves)");
+        }
+        for (idx, save) in saves.iter().enumerate() {
+            println!(
+                "{:>2}. {:<20} {:>2} block(s)  {}",
+                idx + 1,
+                save.name,
+                save.blocks.len(),
+                save.title
+            );
+        }
+        println!("Commands: c <save> copy to another card, d <save> delete, 0 back");
+
+        let input = prompt("> ")?;
+        let mut parts = input.split_whitespace();
+        let command = parts.next().unwrap_or("");
+        if command == "0" || command == "b" {
+            return Ok(());
+        }
+        let Some(save) = parts
+            .next()
+            .and_then(|arg| arg.parse::<usize>().ok())
+            .and_then(|idx| idx.checked_sub(1))
+            .and_then(|idx| saves.get(idx))
+        else {
+            println!("Usage: c <save number> | d <save number> | 0");
+            continue;
+        };
+
+        match command {
+            "d" => {
+                if prompt(&format!("Delete {}? [y/N] ", save.name))?.eq_ignore_ascii_case("y") {
+                    card.delete(save);
+                    card.save(path)?;
+                    println!("Deleted {}.", save.name);
+                }
+            }
+            "c" => {
+                let others: Vec<&PathBuf> = cards.iter().filter(|other| *other != path).collect();
+                if others.is_empty() {
+                    println!("There is no other card to copy to. Create one first.");
+                    continue;
+                }
+                for (idx, other) in others.iter().enumerate() {
+                    println!("{:>2}. {}", idx + 1, file_name(other));
+                }
+                let Some(target) = prompt("Copy to card: ")?
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|idx| idx.checked_sub(1))
+                    .and_then(|idx| others.get(idx))
+                else {
+                    println!("Invalid selection.");
+                    continue;
+                };
+                let mut destination = CardImage::open(target)?;
+                match destination.copy_from(&card, save) {
+                    Ok(()) => {
+                        destination.save(target)?;
+                        println!("Copied {} to {}.", save.name, file_name(target));
+                    }
+                    Err(err) => println!("Error: {err:#}"),
+                }
+            }
+            _ => println!("Unknown command '{command}'."),
+        }
+    }
+}
+
+fn list_cards(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut cards: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("mcd"))
+        })
+        .collect();
+    cards.sort();
+    Ok(cards)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+fn prompt(message: &str) -> Result<String> {
+    print!("{message}");
+    io::stdout().flush().ok();
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input)? == 0 {
+        bail!("standard input closed");
+    }
+    Ok(input.trim().to_string())
+}
@@ -0,0 +1,56 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::systems::GameSystem;
+use crate::{c64_state, n64, ps1};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// The launcher was told to start the game fresh, its resume state is replaced on exit
+static FRESH: AtomicBool = AtomicBool::new(false);
+
+/// Turns auto-resume on, main applies `--auto-resume` or `[saves] auto_resume` at startup
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Starts the next game from power on instead of its resume state
+pub fn start_fresh() {
+    FRESH.store(true, Ordering::Relaxed);
+}
+
+/// Whether `rom` left a resume state behind, the launcher asks whether to use it
+pub fn available(system: GameSystem, rom: &Path) -> bool {
+    enabled() && path(system, rom).is_some_and(|path| path.is_file())
+}
+
+/// The resume state to load as the game starts, if there is one and it is wanted
+pub fn to_load(system: GameSystem, rom: &Path) -> Option<PathBuf> {
+    if FRESH.load(Ordering::Relaxed) || !available(system, rom) {
+        return None;
+    }
+    path(system, rom)
+}
+
+/// Where the game's state goes when it exits, `None` while auto-resume is off
+pub fn to_save(system: GameSystem, rom: &Path) -> Option<PathBuf> {
+    enabled().then(|| path(system, rom)).flatten()
+}
+
+/// `states/<system>/<game>.resume` next to the numbered slots, for the systems with save
+/// states
+fn path(system: GameSystem, rom: &Path) -> Option<PathBuf> {
+    let label = rom
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(system.config_key());
+    match system {
+        GameSystem::C64 => Some(c64_state::resume_path(label)),
+        GameSystem::N64 => Some(n64::resume_path(label)),
+        GameSystem::Ps1 => Some(ps1::resume_path(label)),
+        _ => None,
+    }
+}
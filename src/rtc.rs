@@ -1,7 +1,13 @@
+use std::cell::Cell;
+use std::rc::Rc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use gameboy_core::emulator::traits::RTC;
 
+/// Game Boy clock cycles per frame and per second
+const CYCLES_PER_FRAME: u64 = 70_224;
+const CYCLES_PER_SECOND: u64 = 4_194_304;
+
 pub struct SystemRtc;
 
 impl RTC for SystemRtc {
@@ -12,3 +18,30 @@ impl RTC for SystemRtc {
             .unwrap_or(0)
     }
 }
+
+/// A cartridge clock that only moves on with emulated frames, for input movies and
+/// deterministic runs. Clones share the time, the runner advances it once per frame.
+#[derive(Clone)]
+pub struct FrameClock {
+    start: u64,
+    frames: Rc<Cell<u64>>,
+}
+
+impl FrameClock {
+    pub fn new(start: u64) -> Self {
+        Self {
+            start,
+            frames: Rc::new(Cell::new(0)),
+        }
+    }
+
+    pub fn advance(&self) {
+        self.frames.set(self.frames.get() + 1);
+    }
+}
+
+impl RTC for FrameClock {
+    fn get_current_time(&self) -> u64 {
+        self.start + self.frames.get() * CYCLES_PER_FRAME / CYCLES_PER_SECOND
+    }
+}
@@ -0,0 +1,187 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail, ensure};
+use trapezoid_core::MEMORY_CARD_SIZE;
+
+use crate::save_transfer;
+
+/// Ends DeSmuME's saves, after the footer text and the footer fields
+const DSV_COOKIE: &[u8] = b"|-DESMUME SAVE-|";
+const DSV_FOOTER_TEXT: &[u8] =
+    b"|<--Snip above here to create a raw sav by excluding this DeSmuME savedata footer:";
+/// Actual size, padded size, type, address bytes, memory size and version, little endian
+const DSV_FIELDS: usize = 6 * 4;
+/// DS save chips as DeSmuME numbers them in the footer's type field: bytes and address bytes
+const DS_SAVE_CHIPS: [(usize, u32); 13] = [
+    (512, 1),
+    (8 * 1024, 2),
+    (64 * 1024, 2),
+    (32 * 1024, 2),
+    (256 * 1024, 3),
+    (512 * 1024, 3),
+    (1024 * 1024, 3),
+    (2 * 1024 * 1024, 3),
+    (4 * 1024 * 1024, 3),
+    (8 * 1024 * 1024, 3),
+    (16 * 1024 * 1024, 3),
+    (32 * 1024 * 1024, 3),
+    (64 * 1024 * 1024, 3),
+];
+/// DexDrive cards start with this header, comments for each block included
+const GME_HEADER_SIZE: usize = 3904;
+const GME_MAGIC: &[u8] = b"123-456-STD";
+const PS1_FRAME_SIZE: usize = 0x80;
+const PS1_BLOCKS: usize = 15;
+
+/// Converts the save `input` into `output`, the formats going by their extensions:
+/// `.dsv` is DeSmuME's and any other DS save raw, `.gme` a DexDrive PlayStation card and
+/// `.mcr`/`.mcd` a raw one. Any other pair is a raw save resized to `size` bytes, or to the
+/// save RAM of the cartridge `rom`, for `.srm` files padded or cut by another emulator.
+pub fn convert(input: &Path, output: &Path, rom: Option<&Path>, size: Option<usize>) -> Result<()> {
+    let data = fs::read(input).with_context(|| format!("failed to read {}", input.display()))?;
+    let converted = match (extension(input).as_str(), extension(output).as_str()) {
+        ("dsv", "dsv") => bail!("both saves are DeSmuME saves, nothing to convert"),
+        ("dsv", _) => dsv_to_raw(&data)?,
+        (_, "dsv") => raw_to_dsv(&data)?,
+        ("gme", "mcr" | "mcd") => gme_to_card(&data)?,
+        ("mcr" | "mcd", "gme") => card_to_gme(&data)?,
+        ("mcr" | "mcd", "mcr" | "mcd") => {
+            ensure_card(&data, input)?;
+            data
+        }
+        ("gme", "gme") => bail!("both cards are DexDrive cards, nothing to convert"),
+        ("gme" | "mcr" | "mcd", _) | (_, "gme" | "mcr" | "mcd") => bail!(
+            "PlayStation memory cards convert between .gme, .mcr and .mcd, not to or from .{}",
+            if is_card(input) {
+                extension(output)
+            } else {
+                extension(input)
+            }
+        ),
+        _ => {
+            let size = match (size, rom) {
+                (Some(size), _) => size,
+                (None, Some(rom)) => save_transfer::battery(rom)?.1,
+                (None, None) => bail!(
+                    "pass --rom or --size to resize a raw save, or convert to or from .dsv, .gme, \
+                     .mcr or .mcd"
+                ),
+            };
+            resize(data, size)
+        }
+    };
+    fs::write(output, converted).with_context(|| format!("failed to write {}", output.display()))
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+fn is_card(path: &Path) -> bool {
+    matches!(extension(path).as_str(), "gme" | "mcr" | "mcd")
+}
+
+/// The save ahead of DeSmuME's footer, at the chip size the footer records
+fn dsv_to_raw(data: &[u8]) -> Result<Vec<u8>> {
+    ensure!(
+        data.ends_with(DSV_COOKIE),
+        "not a DeSmuME save, its footer is missing"
+    );
+    let fields_start = data
+        .len()
+        .checked_sub(DSV_COOKIE.len() + DSV_FIELDS)
+        .context("the DeSmuME footer is cut off")?;
+    let field = |index: usize| {
+        let start = fields_start + index * 4;
+        u32::from_le_bytes(data[start..start + 4].try_into().unwrap()) as usize
+    };
+    let (padded, version) = (field(1), field(5));
+    ensure!(version == 0, "unknown DeSmuME save version {version}");
+    ensure!(
+        padded + DSV_FOOTER_TEXT.len() <= fields_start
+            && data[padded..].starts_with(DSV_FOOTER_TEXT),
+        "the DeSmuME footer does not match the save in front of it"
+    );
+    Ok(data[..padded].to_vec())
+}
+
+/// Pads a raw DS save to the next chip size and appends DeSmuME's footer
+fn raw_to_dsv(data: &[u8]) -> Result<Vec<u8>> {
+    ensure!(!data.is_empty(), "the save is empty");
+    let (chip, &(padded, address_bytes)) = DS_SAVE_CHIPS
+        .iter()
+        .enumerate()
+        .filter(|(_, (size, _))| *size >= data.len())
+        .min_by_key(|(_, (size, _))| *size)
+        .context("the save is larger than any DS save chip")?;
+    let mut dsv = data.to_vec();
+    dsv.resize(padded, 0xFF);
+    dsv.extend_from_slice(DSV_FOOTER_TEXT);
+    for field in [
+        data.len() as u32,
+        padded as u32,
+        chip as u32,
+        address_bytes,
+        padded as u32,
+        0,
+    ] {
+        dsv.extend_from_slice(&field.to_le_bytes());
+    }
+    dsv.extend_from_slice(DSV_COOKIE);
+    Ok(dsv)
+}
+
+fn gme_to_card(data: &[u8]) -> Result<Vec<u8>> {
+    ensure!(
+        data.starts_with(GME_MAGIC),
+        "not a DexDrive memory card, its header is missing"
+    );
+    let card = data.get(GME_HEADER_SIZE..).unwrap_or_default();
+    ensure!(
+        card.len() == MEMORY_CARD_SIZE,
+        "the DexDrive card holds {} bytes after its header, expected {MEMORY_CARD_SIZE}",
+        card.len()
+    );
+    Ok(card.to_vec())
+}
+
+/// Puts a DexDrive header in front of a raw card, with the state and size of each block
+/// copied from its directory frame like the DexDrive software writes them
+fn card_to_gme(card: &[u8]) -> Result<Vec<u8>> {
+    ensure_card(card, Path::new("the card"))?;
+    let mut gme = vec![0; GME_HEADER_SIZE];
+    gme[..GME_MAGIC.len()].copy_from_slice(GME_MAGIC);
+    gme[18] = 0x01;
+    gme[20] = 0x01;
+    gme[21] = b'M';
+    for block in 0..PS1_BLOCKS {
+        let frame = &card[(block + 1) * PS1_FRAME_SIZE..];
+        gme[22 + block] = frame[0];
+        gme[38 + block] = frame[8];
+    }
+    gme.extend_from_slice(card);
+    Ok(gme)
+}
+
+fn ensure_card(card: &[u8], path: &Path) -> Result<()> {
+    ensure!(
+        card.len() == MEMORY_CARD_SIZE && card.starts_with(b"MC"),
+        "{} is not a raw PlayStation memory card ({} bytes, expected {MEMORY_CARD_SIZE})",
+        path.display(),
+        card.len()
+    );
+    Ok(())
+}
+
+/// Cuts a raw save to `size` bytes, or pads it with the 0xFF of erased memory
+fn resize(mut data: Vec<u8>, size: usize) -> Vec<u8> {
+    if data.len() != size {
+        log::info!("Resizing the save from {} to {size} bytes", data.len());
+    }
+    data.resize(size, 0xFF);
+    data
+}
@@ -0,0 +1,116 @@
+use std::process::Command;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::config::{SavesConfig, SyncOn};
+
+/// Writes this close together share one run, a core writing a state at the end of its frame
+/// is done by then as well
+const SETTLE: Duration = Duration::from_secs(2);
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+static WORKER: Mutex<Worker> = Mutex::new(Worker {
+    thread: None,
+    running: false,
+    pending: false,
+});
+
+struct Settings {
+    command: String,
+    on: SyncOn,
+}
+
+struct Worker {
+    thread: Option<JoinHandle<()>>,
+    /// The thread is still going, it clears this under the lock as it ends so no write is missed
+    running: bool,
+    /// A save was written since the running command started
+    pending: bool,
+}
+
+/// Takes `[saves] sync_command`, main applies it at startup. Without one nothing is ever run.
+pub fn configure(config: &SavesConfig) {
+    if let Some(command) = config.sync_command.clone().filter(|c| !c.trim().is_empty()) {
+        let _ = SETTINGS.set(Settings {
+            command,
+            on: config.sync_on,
+        });
+    }
+}
+
+/// Reports a save or state written, runs the sync command in the background shortly after
+/// when `[saves] sync_on` is `write`. Writes while it runs make it run once more afterwards.
+pub fn written() {
+    let Some(settings) = SETTINGS.get() else {
+        return;
+    };
+    if settings.on != SyncOn::Write {
+        return;
+    }
+    let mut worker = lock();
+    worker.pending = true;
+    if worker.running {
+        return;
+    }
+    worker.running = true;
+    worker.thread = Some(thread::spawn(|| {
+        loop {
+            thread::sleep(SETTLE);
+            {
+                let mut worker = lock();
+                if !worker.pending {
+                    worker.running = false;
+                    return;
+                }
+                worker.pending = false;
+            }
+            run();
+        }
+    }));
+}
+
+/// Waits for a background run to finish and runs the command a last time, main calls this
+/// when the game has ended so saves the cores wrote themselves go along too
+pub fn finish() {
+    if SETTINGS.get().is_none() {
+        return;
+    }
+    let thread = {
+        let mut worker = lock();
+        worker.pending = false;
+        worker.thread.take()
+    };
+    if let Some(thread) = thread {
+        let _ = thread.join();
+    }
+    run();
+}
+
+fn lock() -> MutexGuard<'static, Worker> {
+    WORKER.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Runs the command through the system shell, so it can use pipes, `&&` and quoting
+fn run() {
+    let Some(settings) = SETTINGS.get() else {
+        return;
+    };
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C");
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    info!("Syncing saves: {}", settings.command);
+    match command.arg(&settings.command).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!("The save sync command failed with {status}"),
+        Err(err) => warn!("Failed to run the save sync command: {err}"),
+    }
+}
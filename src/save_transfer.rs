@@ -0,0 +1,102 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail};
+use gameboy_core::Cartridge;
+use meru_interface::EmulatorCore;
+use super_sabicom::Snes;
+
+use crate::saves;
+use crate::systems::{GameSystem, detect_system};
+
+/// Copies the battery save of `rom` out of `saves/` as the raw SRAM image flashcarts and
+/// save dumpers use, to `output` or `<game>.sav` in the working directory
+pub fn export(rom: &Path, output: Option<&Path>) -> Result<PathBuf> {
+    let (system, size) = battery(rom)?;
+    let save = saves::battery_save(system, rom)?;
+    let data = saves::read(&save, Some(size))?.with_context(|| {
+        format!(
+            "{} has no save yet, {} is missing",
+            rom.display(),
+            save.display()
+        )
+    })?;
+    let output = output.map_or_else(|| default_output(rom), Path::to_path_buf);
+    fs::write(&output, &data[..size])
+        .with_context(|| format!("failed to write {}", output.display()))?;
+    Ok(output)
+}
+
+/// Replaces the battery save of `rom` in `saves/` with a raw SRAM image from a flashcart or a
+/// dumped cartridge. Bytes past the cartridge's SRAM, such as a Game Boy clock footer or
+/// padding, are dropped. The save it replaces is kept in `backups/`.
+pub fn import(rom: &Path, input: &Path) -> Result<PathBuf> {
+    let (system, size) = battery(rom)?;
+    let data = fs::read(input).with_context(|| format!("failed to read {}", input.display()))?;
+    if data.len() < size {
+        bail!(
+            "{} holds {} bytes but the cartridge has {size} bytes of save RAM, it is not a save \
+             of this game",
+            input.display(),
+            data.len()
+        );
+    }
+    if data.len() > size {
+        log::warn!(
+            "Dropping the last {} bytes of {}, the cartridge has {size} bytes of save RAM",
+            data.len() - size,
+            input.display()
+        );
+    }
+    let save = saves::battery_save(system, rom)?;
+    saves::write(&save, &data[..size])?;
+    Ok(save)
+}
+
+/// The system of `rom` and the bytes of battery-backed RAM its cartridge has, as its core
+/// reads and writes them
+pub fn battery(rom: &Path) -> Result<(GameSystem, usize)> {
+    let system = detect_system(rom)?;
+    let bytes = || fs::read(rom).with_context(|| format!("failed to read {}", rom.display()));
+    let size = match system {
+        GameSystem::GameBoy => {
+            let cartridge = Cartridge::from_rom(bytes()?).map_err(|err| anyhow!(err))?;
+            cartridge
+                .has_battery()
+                .then(|| crate::battery_size(&cartridge))
+        }
+        GameSystem::Snes => Snes::try_from_file(&bytes()?, None, &Default::default())
+            .context("failed to initialize SNES core")?
+            .backup()
+            .map(|sram| sram.len()),
+        GameSystem::Ps1 => bail!(
+            "PlayStation saves live on memory cards, copy them with --ps1-memcards or take the \
+             .mcd files from {}",
+            saves::dir(system).display()
+        ),
+        GameSystem::GameCube | GameSystem::Wii => {
+            bail!("GameCube saves live on memory cards, export them as .gci with --gc-memcards")
+        }
+        GameSystem::N64 => bail!(
+            "Nintendo 64 saves are named by the core, copy them from {}",
+            saves::dir(system).display()
+        ),
+        GameSystem::Nds => bail!(
+            "DS saves are kept as raw images, copy them from {}",
+            saves::dir(system).display()
+        ),
+        GameSystem::Nes | GameSystem::C64 => bail!("{system} games have no battery saves here"),
+    };
+    match size {
+        Some(size) if size > 0 => Ok((system, size)),
+        _ => bail!("{} has no battery-backed save RAM", rom.display()),
+    }
+}
+
+fn default_output(rom: &Path) -> PathBuf {
+    let mut name = rom
+        .file_stem()
+        .map_or_else(|| "save".into(), |stem| stem.to_os_string());
+    name.push(".sav");
+    PathBuf::from(name)
+}
@@ -0,0 +1,467 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+use log::{info, warn};
+
+use crate::save_sync;
+use crate::systems::GameSystem;
+
+/// Saves live in `saves/<system>/` unless `[saves] dir` puts them elsewhere
+pub const DEFAULT_DIR: &str = "saves";
+/// Backups kept of each save unless `[saves] backups` says otherwise
+pub const DEFAULT_BACKUPS: u32 = 3;
+/// Backups go in this folder next to the save they were taken of
+const BACKUP_DIR: &str = "backups";
+/// Save states live in `states/<system>/`
+const STATES_DIR: &str = "states";
+/// A profile's saves and states go in a folder of its own in here, under the saves root and
+/// under `states/`
+const PROFILES_DIR: &str = "profiles";
+
+static ROOT: OnceLock<PathBuf> = OnceLock::new();
+/// The profile playing, `None` for the shared saves
+static PROFILE: RwLock<Option<String>> = RwLock::new(None);
+static BACKUPS: AtomicU32 = AtomicU32::new(DEFAULT_BACKUPS);
+/// Saves written to so far this run, only the first write backs up the file it replaces
+static WRITTEN: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Moves every system's saves under `dir`, main applies `[saves] dir` at startup
+pub fn set_root(dir: PathBuf) {
+    let _ = ROOT.set(dir);
+}
+
+/// Backups to keep of each save, main applies `[saves] backups` at startup
+pub fn set_backups(count: u32) {
+    BACKUPS.store(count, Ordering::Relaxed);
+}
+
+/// Switches to the saves and states of profile `name`, `None` goes back to the shared ones.
+/// Main applies `--profile` at startup, the launcher a profile picked in it.
+pub fn set_profile(name: Option<String>) {
+    *PROFILE.write().unwrap_or_else(|err| err.into_inner()) = name;
+}
+
+pub fn profile() -> Option<String> {
+    PROFILE
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone()
+}
+
+/// The profiles that have saves or states, sorted by name
+pub fn profiles() -> Vec<String> {
+    let mut names: Vec<String> = [
+        shared_root().join(PROFILES_DIR),
+        Path::new(STATES_DIR).join(PROFILES_DIR),
+    ]
+    .iter()
+    .filter_map(|dir| fs::read_dir(dir).ok())
+    .flat_map(|entries| entries.flatten())
+    .filter(|entry| entry.path().is_dir())
+    .filter_map(|entry| entry.file_name().into_string().ok())
+    .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Profile names become folder names, so they are kept to letters, digits, spaces, `-` and
+/// `_`
+pub fn check_profile(name: &str) -> Result<()> {
+    let allowed = |c: char| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_');
+    if name.trim().is_empty() || !name.chars().all(allowed) {
+        bail!("profile names may only use letters, digits, spaces, - and _, got {name:?}");
+    }
+    Ok(())
+}
+
+/// Names typed in for new files, like memory cards, have to stay a file in the folder they
+/// are created in
+pub fn check_file_name(name: &str) -> Result<()> {
+    if name.contains(['/', '\\']) || name.contains("..") {
+        bail!("names may not contain /, \\ or .., got {name:?}");
+    }
+    Ok(())
+}
+
+/// The directory holding one folder per system, the profile's own folder while one is picked
+pub fn root() -> PathBuf {
+    match profile() {
+        Some(name) => shared_root().join(PROFILES_DIR).join(name),
+        None => shared_root().to_path_buf(),
+    }
+}
+
+fn shared_root() -> &'static Path {
+    ROOT.get_or_init(|| PathBuf::from(DEFAULT_DIR))
+}
+
+/// `<root>/<system>/`, named like the system's section in `config.toml`
+pub fn dir(system: GameSystem) -> PathBuf {
+    root().join(system.config_key())
+}
+
+/// `states/<system>/`, or `states/profiles/<name>/<system>/` for a profile
+pub fn state_dir(system: GameSystem) -> PathBuf {
+    let states = Path::new(STATES_DIR);
+    match profile() {
+        Some(name) => states.join(PROFILES_DIR).join(name),
+        None => states.to_path_buf(),
+    }
+    .join(system.config_key())
+}
+
+/// Creates the system's save directory and returns it. Saves an older layout left in
+/// `saves/` are moved in first, so they are picked up after `[saves] dir` changes too. They
+/// are shared saves, so a profile leaves them alone.
+pub fn prepare(system: GameSystem) -> Result<PathBuf> {
+    let dir = dir(system);
+    for legacy in legacy_dirs(system) {
+        if profile().is_none() && legacy.is_dir() && !same_dir(&legacy, &dir) {
+            migrate_dir(&legacy, &dir);
+        }
+    }
+    fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// The battery save of the cartridge at `rom`, `<root>/<system>/<game>.sav` named after the
+/// ROM file. A `.sav` kept next to the ROM, as older versions did, is moved to the shared
+/// saves first.
+pub fn battery_save(system: GameSystem, rom: &Path) -> Result<PathBuf> {
+    adopt_sidecar(prepare(system)?, system, rom)
+}
+
+/// The battery save of a cartridge plugged in next to the game, like a GBA cartridge in the
+/// DS's slot-2, `<root>/<system>/<folder>/<cartridge>.sav` so it never takes the name of the
+/// game's own save
+pub fn accessory_save(system: GameSystem, folder: &str, rom: &Path) -> Result<PathBuf> {
+    let dir = prepare(system)?.join(folder);
+    fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    adopt_sidecar(dir, system, rom)
+}
+
+/// `<dir>/<game>.sav`, with the `.sav` next to the ROM moved in when there is none yet
+fn adopt_sidecar(dir: PathBuf, system: GameSystem, rom: &Path) -> Result<PathBuf> {
+    let game = rom
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(system.config_key());
+    let path = dir.join(format!("{game}.sav"));
+    let sidecar = rom.with_extension("sav");
+    if path.exists() || !sidecar.is_file() || profile().is_some() {
+        return Ok(path);
+    }
+    match move_file(&sidecar, &path) {
+        Ok(()) => {
+            info!("Moved the save {} to {}", sidecar.display(), path.display());
+            Ok(path)
+        }
+        Err(err) => {
+            warn!(
+                "Failed to move the save {} to {}, it stays in use where it is: {err}",
+                sidecar.display(),
+                path.display()
+            );
+            Ok(sidecar)
+        }
+    }
+}
+
+/// Reads the save at `path`, `None` when there is none yet. An empty file, or one shorter than
+/// the `expected` size, is refused instead of being handed to the game, which would start
+/// from a blank save and write that over the broken one.
+pub fn read(path: &Path, expected: Option<usize>) -> Result<Option<Vec<u8>>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let data = fs::read(path).with_context(|| format!("failed to read save {}", path.display()))?;
+    if data.is_empty() {
+        bail!(
+            "the save {} is empty, {}",
+            path.display(),
+            restore_hint(path)
+        );
+    }
+    if let Some(expected) = expected
+        && data.len() < expected
+    {
+        bail!(
+            "the save {} is cut off at {} of {expected} bytes, {}",
+            path.display(),
+            data.len(),
+            restore_hint(path)
+        );
+    }
+    Ok(Some(data))
+}
+
+/// Replaces the save at `path` with `data` without ever leaving half a file behind: the data
+/// goes to a temporary file, which is flushed to disk and then renamed over the save. The
+/// first write to a save in a run keeps the file it replaces in `backups/` next to it, named
+/// with the time, and drops the oldest past `[saves] backups`.
+pub fn write(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    if path.is_file()
+        && first_write(path)
+        && let Err(err) = back_up(path)
+    {
+        warn!("Failed to back up {}: {err:#}", path.display());
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    write_synced(&tmp, data).with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("failed to replace {}", path.display()))?;
+    save_sync::written();
+    Ok(())
+}
+
+fn write_synced(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+fn first_write(path: &Path) -> bool {
+    let mut written = WRITTEN.lock().unwrap_or_else(|err| err.into_inner());
+    if written.iter().any(|seen| seen == path) {
+        return false;
+    }
+    written.push(path.to_path_buf());
+    true
+}
+
+/// Saves a core writes itself, which [`write`] never sees. A snapshot of the folder taken
+/// before the game starts stands in for the first write of a run: once the game exits, every
+/// save the core changed gets the version it replaced backed up, and one the core left empty
+/// or shorter than before is reported.
+pub struct CoreSaves {
+    dir: PathBuf,
+    files: Vec<(PathBuf, Vec<u8>)>,
+}
+
+impl CoreSaves {
+    /// Remembers the saves in `dir`, not those in its subfolders such as `backups/`
+    pub fn snapshot(dir: &Path) -> Self {
+        let files = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .filter_map(|path| fs::read(&path).ok().map(|data| (path, data)))
+            .collect();
+        Self {
+            dir: dir.to_path_buf(),
+            files,
+        }
+    }
+
+    /// Backs up what the core replaced and checks what it wrote, once the game has exited
+    pub fn finish(self) {
+        for (path, old) in &self.files {
+            let Ok(new) = fs::read(path) else {
+                warn!("The save {} was removed while the game ran", path.display());
+                continue;
+            };
+            if new == *old {
+                continue;
+            }
+            if new.len() < old.len() {
+                warn!(
+                    "The save {} shrank from {} to {} bytes, {}",
+                    path.display(),
+                    old.len(),
+                    new.len(),
+                    restore_hint(path)
+                );
+            }
+            if let Err(err) = back_up_contents(path, old) {
+                warn!("Failed to back up {}: {err:#}", path.display());
+            }
+        }
+        let created = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| !self.files.iter().any(|(seen, _)| seen == path));
+        for path in created {
+            if fs::metadata(&path).is_ok_and(|meta| meta.is_file() && meta.len() == 0) {
+                warn!("The core left the new save {} empty", path.display());
+            }
+        }
+    }
+}
+
+/// Copies the save to `backups/<name>-<time>.<ext>` and removes its oldest backups past the
+/// configured count
+fn back_up(path: &Path) -> Result<()> {
+    let contents = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    back_up_contents(path, &contents)
+}
+
+/// Keeps `contents`, an earlier version of the save at `path`, as its newest backup
+fn back_up_contents(path: &Path, contents: &[u8]) -> Result<()> {
+    let count = BACKUPS.load(Ordering::Relaxed) as usize;
+    if count == 0 {
+        return Ok(());
+    }
+    let dir = path.with_file_name(BACKUP_DIR);
+    fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let (name, extension) = split_name(path);
+    let backup = dir.join(format!("{name}-{}{extension}", timestamp()));
+    fs::write(&backup, contents)
+        .with_context(|| format!("failed to write {}", backup.display()))?;
+
+    let mut backups: Vec<PathBuf> = fs::read_dir(&dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|candidate| {
+            candidate
+                .file_name()
+                .and_then(|file| file.to_str())
+                .and_then(|file| file.strip_prefix(name.as_str()))
+                .and_then(|rest| rest.strip_prefix('-'))
+                .and_then(|rest| rest.strip_suffix(extension.as_str()))
+                .is_some_and(is_timestamp)
+        })
+        .collect();
+    // the timestamps sort oldest first
+    backups.sort();
+    for old in &backups[..backups.len().saturating_sub(count)] {
+        fs::remove_file(old).with_context(|| format!("failed to remove {}", old.display()))?;
+    }
+    Ok(())
+}
+
+/// The file name without and with its extension, `("Zelda", ".sav")`
+fn split_name(path: &Path) -> (String, String) {
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (name, extension)
+}
+
+/// The current UTC time as `YYYYMMDD-HHMMSS`, which sorts by age
+fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (days, time) = (secs / 86_400, secs % 86_400);
+    // the civil date of a day count, after Howard Hinnant's `civil_from_days`
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+fn is_timestamp(text: &str) -> bool {
+    text.len() == 15
+        && text.bytes().enumerate().all(|(index, byte)| {
+            if index == 8 {
+                byte == b'-'
+            } else {
+                byte.is_ascii_digit()
+            }
+        })
+}
+
+fn restore_hint(path: &Path) -> String {
+    format!(
+        "copy one from {} over it, or delete it to start the game without a save",
+        path.with_file_name(BACKUP_DIR).display()
+    )
+}
+
+/// Where saves of `system` went before, in the default root and under the GameCube's old
+/// short name
+fn legacy_dirs(system: GameSystem) -> Vec<PathBuf> {
+    let default = Path::new(DEFAULT_DIR);
+    let mut dirs = vec![default.join(system.config_key())];
+    if system == GameSystem::GameCube {
+        dirs.push(default.join("gc"));
+    }
+    dirs
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Moves whatever `to` does not have yet over from `from`, and removes `from` once that
+/// leaves it empty. Clashing files stay behind to be sorted out by hand.
+fn migrate_dir(from: &Path, to: &Path) {
+    let entries = match fs::read_dir(from) {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!("Failed to read the old saves in {}: {err}", from.display());
+            return;
+        }
+    };
+    if let Err(err) = fs::create_dir_all(to) {
+        warn!("Failed to create {}: {err}", to.display());
+        return;
+    }
+    let mut moved = 0;
+    for entry in entries.flatten() {
+        let target = to.join(entry.file_name());
+        if target.exists() {
+            warn!(
+                "{} is left where it is, {} already exists",
+                entry.path().display(),
+                target.display()
+            );
+            continue;
+        }
+        match fs::rename(entry.path(), &target) {
+            Ok(()) => moved += 1,
+            Err(err) => warn!(
+                "Failed to move {} to {}: {err}",
+                entry.path().display(),
+                target.display()
+            ),
+        }
+    }
+    if moved > 0 {
+        info!(
+            "Moved {moved} save file(s) from {} to {}",
+            from.display(),
+            to.display()
+        );
+    }
+    // only goes through once everything was moved
+    let _ = fs::remove_dir(from);
+}
+
+/// Renames `from` to `to`, copying it over instead when they are on different drives
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    fs::rename(from, to).or_else(|_| {
+        fs::copy(from, to)?;
+        fs::remove_file(from)
+    })
+}
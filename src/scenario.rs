@@ -0,0 +1,143 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow, bail};
+
+use crate::movie;
+
+/// Input scenario for reproducible bug reports and smoke tests, one step per line or per
+/// `;`, frames counted from 0 at power-on:
+///
+/// ```text
+/// # comments start with #
+/// frame 120: press Start for 2 frames; frame 600: hold Right 30 frames
+/// frame 700: press A+B
+/// frame 900: screenshot
+/// frame 1200: quit
+/// ```
+///
+/// `press` and `hold` are the same and last one frame unless a count is given. Scenario
+/// buttons are held on top of the player's.
+pub struct Scenario {
+    holds: Vec<Hold>,
+    screenshots: BTreeSet<u64>,
+    quit: Option<u64>,
+}
+
+struct Hold {
+    start: u64,
+    frames: u64,
+    /// Bits like an input movie frame
+    buttons: u8,
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let scenario =
+            Self::parse(&text).with_context(|| format!("invalid scenario {}", path.display()))?;
+        log::info!(
+            "Running scenario {} with {} inputs",
+            path.display(),
+            scenario.holds.len()
+        );
+        Ok(scenario)
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let mut scenario = Self {
+            holds: Vec::new(),
+            screenshots: BTreeSet::new(),
+            quit: None,
+        };
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            for step in line
+                .split(';')
+                .map(str::trim)
+                .filter(|step| !step.is_empty())
+            {
+                scenario
+                    .parse_step(step)
+                    .with_context(|| format!("line {}: `{step}`", index + 1))?;
+            }
+        }
+        Ok(scenario)
+    }
+
+    fn parse_step(&mut self, step: &str) -> Result<()> {
+        let (frame, action) = step
+            .strip_prefix("frame")
+            .and_then(|rest| rest.split_once(':'))
+            .ok_or_else(|| anyhow!("steps start with `frame N:`"))?;
+        let frame: u64 = frame
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("`{}` is not a frame number", frame.trim()))?;
+        let words: Vec<String> = action
+            .split_whitespace()
+            .map(str::to_ascii_lowercase)
+            .collect();
+        match words.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            ["screenshot"] => {
+                self.screenshots.insert(frame);
+            }
+            ["quit"] => self.quit = Some(self.quit.map_or(frame, |quit| quit.min(frame))),
+            ["press" | "hold", buttons, ref rest @ ..] => {
+                let count = match rest {
+                    [] => 1,
+                    ["for", count, ..] | [count, ..] => count
+                        .parse()
+                        .map_err(|_| anyhow!("`{count}` is not a number of frames"))?,
+                };
+                let buttons = buttons
+                    .split('+')
+                    .map(button_bit)
+                    .collect::<Result<Vec<_>>>()?
+                    .into_iter()
+                    .fold(0, |mask, bit| mask | bit);
+                self.holds.push(Hold {
+                    start: frame,
+                    frames: count,
+                    buttons,
+                });
+            }
+            _ => bail!("expected press, hold, screenshot or quit"),
+        }
+        Ok(())
+    }
+
+    /// Buttons held during `frame`, in input movie bits
+    pub fn buttons(&self, frame: u64) -> u8 {
+        self.holds
+            .iter()
+            .filter(|hold| (hold.start..hold.start + hold.frames).contains(&frame))
+            .fold(0, |mask, hold| mask | hold.buttons)
+    }
+
+    /// Whether a screenshot is taken once `frame` has run
+    pub fn screenshot_after(&self, frame: u64) -> bool {
+        self.screenshots.contains(&frame)
+    }
+
+    /// Whether the run ends before `frame`
+    pub fn quits_before(&self, frame: u64) -> bool {
+        self.quit.is_some_and(|quit| frame >= quit)
+    }
+}
+
+fn button_bit(name: &str) -> Result<u8> {
+    Ok(match name {
+        "left" => movie::LEFT,
+        "right" => movie::RIGHT,
+        "up" => movie::UP,
+        "down" => movie::DOWN,
+        "a" => movie::A,
+        "b" => movie::B,
+        "start" => movie::START,
+        "select" => movie::SELECT,
+        _ => bail!("unknown button `{name}`"),
+    })
+}
@@ -0,0 +1,68 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, ensure};
+use flate2::Compression;
+use flate2::Crc;
+use flate2::write::ZlibEncoder;
+
+pub const SCREENSHOT_DIR: &str = "screenshots";
+pub const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Writes `0xAARRGGBB` pixels to `screenshots/<label>-NNN.png`, numbered after the
+/// existing screenshots of the same game, and returns the new file.
+pub fn save(label: &str, width: u32, height: u32, pixels: &[u32]) -> Result<PathBuf> {
+    ensure!(
+        pixels.len() == (width * height) as usize,
+        "screenshot is {}x{} but has {} pixels",
+        width,
+        height,
+        pixels.len()
+    );
+    let dir = Path::new(SCREENSHOT_DIR);
+    fs::create_dir_all(dir).context("failed to create screenshots directory")?;
+    let path = (1..)
+        .map(|index| dir.join(format!("{label}-{index:03}.png")))
+        .find(|path| !path.exists())
+        .expect("screenshot numbers never run out");
+    fs::write(&path, encode_png(width, height, pixels)?)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// 8-bit RGB, every row unfiltered
+fn encode_png(width: u32, height: u32, pixels: &[u32]) -> Result<Vec<u8>> {
+    let mut raw = Vec::with_capacity(pixels.len() * 3 + height as usize);
+    for row in pixels.chunks_exact(width.max(1) as usize) {
+        raw.push(0);
+        for &pixel in row {
+            raw.extend_from_slice(&[(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]);
+        }
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&raw)?;
+    let data = encoder.finish()?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // bit depth, color type (RGB), compression, filter, interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &data);
+    write_chunk(&mut png, b"IEND", &[]);
+    Ok(png)
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    png.extend_from_slice(&crc.sum().to_be_bytes());
+}
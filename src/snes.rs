@@ -1,11 +1,10 @@
 use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use anyhow::{Context, Result, anyhow};
 use meru_interface::{EmulatorCore, InputData};
-use sdl2::audio::{AudioQueue, AudioSpecDesired};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
@@ -13,22 +12,29 @@ use sdl2::render::{Canvas, Texture};
 use sdl2::video::Window;
 use super_sabicom::Snes;
 
+use crate::audio::AudioPlayer;
+use crate::bezel::Bezel;
+use crate::config::{Config, DisplayConfig};
 use crate::controller::{ControllerManager, VirtualButton};
+use crate::focus_pause::{self, FocusPause};
+use crate::frame_pacer::FramePacer;
+use crate::osd;
+use crate::perf_overlay::PerfOverlay;
+use crate::present;
+use crate::saves;
+use crate::systems::GameSystem;
 
 const TARGET_FRAME: Duration = Duration::from_micros(16_667);
 const DEFAULT_WIDTH: u32 = 512;
 const DEFAULT_HEIGHT: u32 = 448;
-const AUDIO_SAMPLE_RATE: i32 = 32_000;
-const AUDIO_CHANNELS: u16 = 2;
-const AUDIO_BUFFER_SAMPLES: u16 = 1024;
-const MAX_AUDIO_LATENCY_BYTES: u32 =
-    (AUDIO_SAMPLE_RATE as u32) * (AUDIO_CHANNELS as u32) * std::mem::size_of::<i16>() as u32;
+const AUDIO_SAMPLE_RATE: f64 = 32_000.0;
+const AUDIO_CHANNELS: usize = 2;
 
-pub fn run(rom_path: &Path, scale: u32, limit_fps: bool) -> Result<()> {
+pub fn run(rom_path: &Path, scale: u32, limit_fps: bool, config: &Config) -> Result<()> {
     let rom_bytes = fs::read(rom_path)
         .with_context(|| format!("failed to read SNES ROM {}", rom_path.display()))?;
-    let save_path = rom_path.with_extension("sav");
-    let backup = load_backup(&save_path)?;
+    let save_path = saves::battery_save(GameSystem::Snes, rom_path)?;
+    let backup = saves::read(&save_path, None)?;
     let mut snes = Snes::try_from_file(&rom_bytes, backup.as_deref(), &Default::default())
         .context("failed to initialize SNES core")?;
 
@@ -36,113 +42,99 @@ pub fn run(rom_path: &Path, scale: u32, limit_fps: bool) -> Result<()> {
         .file_stem()
         .and_then(|s| s.to_str())
         .unwrap_or("SNES");
-    let mut frontend = SnesFrontend::new(title, scale.max(1), limit_fps)?;
+    let mut frontend = SnesFrontend::new(title, scale.max(1), limit_fps, config)?;
     frontend.run(&mut snes)?;
 
     if let Some(save) = snes.backup() {
-        save_backup(&save_path, &save)?;
+        saves::write(&save_path, &save)?;
     }
 
     Ok(())
 }
 
-fn load_backup(path: &Path) -> Result<Option<Vec<u8>>> {
-    if path.exists() {
-        Ok(Some(fs::read(path).with_context(|| {
-            format!("failed to read save {}", path.display())
-        })?))
-    } else {
-        Ok(None)
-    }
-}
-
-fn save_backup(path: &Path, data: &[u8]) -> Result<()> {
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)
-            .with_context(|| format!("failed to create directory for {}", parent.display()))?;
-    }
-    fs::write(path, data).with_context(|| format!("failed to write save {}", path.display()))
-}
-
 struct SnesFrontend {
     _sdl: sdl2::Sdl,
     canvas: Canvas<Window>,
     texture: Texture,
+    bezel: Option<Bezel>,
     event_pump: sdl2::EventPump,
-    audio: AudioQueue<i16>,
+    audio: AudioPlayer,
     pressed: HashSet<Keycode>,
     limit_fps: bool,
+    display: DisplayConfig,
     scale: u32,
     argb_buffer: Vec<u32>,
     audio_scratch: Vec<i16>,
     controller: ControllerManager,
+    perf: PerfOverlay,
+    focus: FocusPause,
 }
 
 impl SnesFrontend {
-    fn new(title: &str, scale: u32, limit_fps: bool) -> Result<Self> {
+    fn new(title: &str, scale: u32, limit_fps: bool, config: &Config) -> Result<Self> {
         let sdl = sdl2::init().map_err(|e| anyhow!(e))?;
         let video = sdl.video().map_err(|e| anyhow!(e))?;
-        let audio_subsystem = sdl.audio().map_err(|e| anyhow!(e))?;
 
-        let scaled_w = DEFAULT_WIDTH.saturating_mul(scale.max(1));
-        let scaled_h = DEFAULT_HEIGHT.saturating_mul(scale.max(1));
+        let (scaled_w, scaled_h) = present::oriented(
+            DEFAULT_WIDTH.saturating_mul(scale.max(1)),
+            DEFAULT_HEIGHT.saturating_mul(scale.max(1)),
+        );
         let window = video
             .window(title, scaled_w, scaled_h)
             .position_centered()
             .resizable()
+            .allow_highdpi()
             .build()
             .context("failed to create SNES window")?;
 
-        let mut canvas_builder = window.into_canvas();
-        if limit_fps {
-            canvas_builder = canvas_builder.present_vsync();
-        }
-        let mut canvas = canvas_builder.build().map_err(|e| anyhow!(e))?;
-        canvas
-            .set_logical_size(DEFAULT_WIDTH, DEFAULT_HEIGHT)
+        let mut canvas =
+            present::canvas(window, limit_fps, &config.display).map_err(|e| anyhow!(e))?;
+        present::apply_fullscreen(canvas.window_mut());
+        present::set_logical_size(&mut canvas, DEFAULT_WIDTH, DEFAULT_HEIGHT)
             .context("failed to set SNES logical size")?;
+        let bezel = Bezel::load(&canvas, &config.display, GameSystem::Snes, title);
 
         let texture_creator = canvas.texture_creator();
         let texture = texture_creator
             .create_texture_streaming(PixelFormatEnum::ARGB8888, DEFAULT_WIDTH, DEFAULT_HEIGHT)
             .map_err(|e| anyhow!(e))?;
 
-        let desired = AudioSpecDesired {
-            freq: Some(AUDIO_SAMPLE_RATE),
-            channels: Some(AUDIO_CHANNELS as u8),
-            samples: Some(AUDIO_BUFFER_SAMPLES),
-        };
-        let audio = audio_subsystem
-            .open_queue::<i16, _>(None, &desired)
-            .map_err(|e| anyhow!(e))?;
-        let silence = vec![0i16; (AUDIO_BUFFER_SAMPLES as usize) * (AUDIO_CHANNELS as usize)];
-        audio.queue_audio(&silence).map_err(|e| anyhow!(e))?;
-        audio.resume();
+        let mut audio = AudioPlayer::new(&config.audio, AUDIO_SAMPLE_RATE, AUDIO_CHANNELS)
+            .context("failed to initialize SDL2 audio for SNES")?;
+        audio.prefill();
 
         let event_pump = sdl.event_pump().map_err(|e| anyhow!(e))?;
-        let controller = ControllerManager::new(&sdl)?;
+        let controller = ControllerManager::new(&sdl, &config.controllers)?;
 
         Ok(Self {
             _sdl: sdl,
             canvas,
             texture,
+            bezel,
             event_pump,
             audio,
             pressed: HashSet::new(),
             limit_fps,
+            display: config.display.clone(),
             scale: scale.max(1),
             argb_buffer: vec![0; (DEFAULT_WIDTH as usize) * (DEFAULT_HEIGHT as usize)],
             audio_scratch: Vec::with_capacity(2048),
             controller,
+            perf: PerfOverlay::new(TARGET_FRAME, &config.display),
+            focus: FocusPause::new(&config.display),
         })
     }
 
     fn run(&mut self, snes: &mut Snes) -> Result<()> {
-        let mut last_frame = Instant::now();
+        let mut pacer = FramePacer::new(TARGET_FRAME, self.limit_fps)
+            .vsynced(self.canvas.window(), &self.display);
         let mut running = true;
         while running {
             for event in self.event_pump.poll_iter() {
                 self.controller.handle_event(&event);
+                self.audio.handle_sdl_event(&event);
+                self.perf.handle_sdl_event(&event);
+                self.focus.handle_sdl_event(&event);
                 match event {
                     Event::Quit { .. } => running = false,
                     Event::KeyDown {
@@ -154,32 +146,35 @@ impl SnesFrontend {
                         repeat: false,
                         ..
                     } => {
-                        self.pressed.insert(code);
+                        self.pressed.insert(present::game_arrow_key(code));
                     }
                     Event::KeyUp {
                         keycode: Some(code),
                         ..
                     } => {
-                        self.pressed.remove(&code);
+                        self.pressed.remove(&present::game_arrow_key(code));
                     }
                     _ => {}
                 }
             }
 
+            if self.focus.is_paused() {
+                focus_pause::idle();
+                continue;
+            }
+
+            self.controller.advance_frame();
+            self.controller.advance_macros();
             let input = build_input_data(&self.pressed, &self.controller);
             snes.set_input(&input);
             snes.exec_frame(true);
 
+            self.perf.frame();
+            self.perf.set_audio(&self.audio);
             self.present_frame(snes.frame_buffer())?;
             self.push_audio(snes.audio_buffer());
 
-            if self.limit_fps {
-                let elapsed = last_frame.elapsed();
-                if elapsed < TARGET_FRAME {
-                    std::thread::sleep(TARGET_FRAME - elapsed);
-                }
-            }
-            last_frame = Instant::now();
+            pacer.wait();
         }
         Ok(())
     }
@@ -188,14 +183,17 @@ impl SnesFrontend {
         let expected_len = frame.width.saturating_mul(frame.height) as usize;
         if self.argb_buffer.len() != expected_len {
             self.argb_buffer.resize(expected_len, 0);
-            self.canvas
-                .set_logical_size(frame.width as u32, frame.height as u32)
+            present::set_logical_size(&mut self.canvas, frame.width as u32, frame.height as u32)
                 .context("failed to update SNES logical size")?;
             let width = frame.width as u32;
             let height = frame.height as u32;
-            let new_w = width.saturating_mul(self.scale);
-            let new_h = height.saturating_mul(self.scale);
-            if let Err(err) = self.canvas.window_mut().set_size(new_w, new_h) {
+            let (new_w, new_h) = present::oriented(
+                width.saturating_mul(self.scale),
+                height.saturating_mul(self.scale),
+            );
+            if let Err(err) =
+                present::set_window_size(self.canvas.window_mut(), new_w, new_h, &self.display)
+            {
                 log::debug!("failed to resize SNES window: {err}");
             }
             let texture_creator = self.canvas.texture_creator();
@@ -208,6 +206,12 @@ impl SnesFrontend {
             *dst =
                 0xFF00_0000 | ((color.r as u32) << 16) | ((color.g as u32) << 8) | color.b as u32;
         }
+        if self.perf.is_open() {
+            self.perf.draw(&mut self.argb_buffer, frame.width as usize);
+        }
+        if let Some(text) = self.audio.osd_text() {
+            osd::draw_message(&mut self.argb_buffer, frame.width as usize, &text);
+        }
 
         self.texture
             .update(
@@ -217,9 +221,10 @@ impl SnesFrontend {
             )
             .context("failed to upload SNES frame")?;
         self.canvas.clear();
-        self.canvas
-            .copy(&self.texture, None, None)
-            .map_err(|e| anyhow!(e))?;
+        if let Some(bezel) = &self.bezel {
+            bezel.draw(&mut self.canvas).map_err(|e| anyhow!(e))?;
+        }
+        present::copy(&mut self.canvas, &self.texture, None).map_err(|e| anyhow!(e))?;
         self.canvas.present();
         Ok(())
     }
@@ -236,13 +241,7 @@ impl SnesFrontend {
             self.audio_scratch.push(sample.right);
         }
 
-        if self.audio.size() as u32 > MAX_AUDIO_LATENCY_BYTES {
-            self.audio.clear();
-        }
-
-        if let Err(err) = self.audio.queue_audio(&self.audio_scratch) {
-            eprintln!("SNES audio error: {err}");
-        }
+        self.audio.push_i16(&self.audio_scratch);
     }
 }
 
@@ -344,8 +343,7 @@ fn button_active(
     controller_button: Option<VirtualButton>,
 ) -> bool {
     let keyboard = keys.iter().any(|key| pressed.contains(key));
-    let controller = controller_button
-        .map(|vb| controllers.is_pressed(vb))
-        .unwrap_or(false);
-    keyboard || controller
+    controller_button
+        .map(|vb| controllers.turbo(vb, keyboard) || controllers.is_pressed(vb))
+        .unwrap_or(keyboard)
 }
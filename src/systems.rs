@@ -1,7 +1,17 @@
-use std::{fmt, path::Path};
+use std::{
+    fmt,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
 
 use anyhow::{Result, anyhow};
 
+use crate::gc_disc;
+
+/// Disc header magic of Wii discs at 0x18, GameCube discs have theirs at 0x1C
+const WII_MAGIC: [u8; 4] = [0x5D, 0x1C, 0x9E, 0xA3];
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum GameSystem {
     GameBoy,
@@ -11,9 +21,38 @@ pub enum GameSystem {
     Ps1,
     N64,
     GameCube,
+    Wii,
+    C64,
 }
 
 impl GameSystem {
+    pub const ALL: [GameSystem; 9] = [
+        GameSystem::GameBoy,
+        GameSystem::Nes,
+        GameSystem::Snes,
+        GameSystem::Nds,
+        GameSystem::Ps1,
+        GameSystem::N64,
+        GameSystem::GameCube,
+        GameSystem::Wii,
+        GameSystem::C64,
+    ];
+
+    /// Name of the system in `config.toml`, as in `[ps1]` or `[display.systems.nes]`
+    pub fn config_key(&self) -> &'static str {
+        match self {
+            GameSystem::GameBoy => "gb",
+            GameSystem::Nes => "nes",
+            GameSystem::Snes => "snes",
+            GameSystem::Nds => "nds",
+            GameSystem::Ps1 => "ps1",
+            GameSystem::N64 => "n64",
+            GameSystem::GameCube => "gamecube",
+            GameSystem::Wii => "wii",
+            GameSystem::C64 => "c64",
+        }
+    }
+
     pub fn label(&self) -> &'static str {
         match self {
             GameSystem::GameBoy => "Game Boy / Color",
@@ -23,6 +62,8 @@ impl GameSystem {
             GameSystem::Ps1 => "PlayStation",
             GameSystem::N64 => "Nintendo 64",
             GameSystem::GameCube => "GameCube",
+            GameSystem::Wii => "Wii",
+            GameSystem::C64 => "Commodore 64",
         }
     }
 }
@@ -45,9 +86,45 @@ pub fn detect_system(path: &Path) -> Result<GameSystem> {
         "nes" => Ok(GameSystem::Nes),
         "sfc" | "smc" | "snes" => Ok(GameSystem::Snes),
         "nds" => Ok(GameSystem::Nds),
-        "cue" | "exe" => Ok(GameSystem::Ps1),
-        "n64" | "z64" | "v64" => Ok(GameSystem::N64),
+        "cue" | "exe" | "pbp" => Ok(GameSystem::Ps1),
+        "bin" | "img" if has_iso9660_header(path) => Ok(GameSystem::Ps1),
+        "bin" | "img" => Err(anyhow!("no ISO9660 header, not a PlayStation disc image")),
+        "n64" | "z64" | "v64" | "ndd" => Ok(GameSystem::N64),
+        "wbfs" => Ok(GameSystem::Wii),
+        "iso" | "rvz" if has_wii_magic(path) => Ok(GameSystem::Wii),
+        "iso" if !has_gamecube_magic(path) && has_iso9660_header(path) => Ok(GameSystem::Ps1),
         "iso" | "gcm" | "gcz" | "gcn" | "ciso" | "dol" | "rvz" => Ok(GameSystem::GameCube),
+        "prg" | "t64" | "crt" | "tap" | "p00" | "d64" | "g64" => Ok(GameSystem::C64),
         other => Err(anyhow!("unsupported ROM extension: {}", other)),
     }
 }
+
+fn read_at(path: &Path, offset: u64, buf: &mut [u8]) -> bool {
+    File::open(path)
+        .and_then(|mut file| {
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(buf)
+        })
+        .is_ok()
+}
+
+/// Looks for the ISO9660 primary volume descriptor in sector 16 of a raw
+/// (2352-byte, Mode 2) or cooked (2048-byte) disc image.
+fn has_iso9660_header(path: &Path) -> bool {
+    let mut magic = [0u8; 6];
+    [(16 * 2352 + 24), (16 * 2048)]
+        .into_iter()
+        .any(|offset| read_at(path, offset, &mut magic) && &magic == b"\x01CD001")
+}
+
+fn has_gamecube_magic(path: &Path) -> bool {
+    let mut magic = [0u8; 4];
+    read_at(path, 0x1C, &mut magic) && magic == [0xC2, 0x33, 0x9F, 0x3D]
+}
+
+/// RVZ images keep the disc header uncompressed, so this only reads a few bytes either way
+fn has_wii_magic(path: &Path) -> bool {
+    gc_disc::open(path)
+        .and_then(|mut disc| Ok(gc_disc::read_at(disc.as_mut(), 0x18, 4)?))
+        .is_ok_and(|magic| magic == WII_MAGIC)
+}
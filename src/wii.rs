@@ -0,0 +1,83 @@
+use std::path::Path;
+
+use anyhow::{Result, bail};
+
+use crate::config::{Config, SensorBar, WiiConfig, WiiRemote};
+use crate::gamecube;
+
+const REMOTE_SLOTS: u32 = 4;
+/// `WiimoteSource` values in Dolphin's `WiiPad` settings
+const SOURCE_NONE: u8 = 0;
+const SOURCE_EMULATED: u8 = 1;
+const SOURCE_REAL: u8 = 2;
+
+/// Wii games need Dolphin, either installed or as the libretro core; `[gamecube]` holds
+/// the Dolphin setup both consoles share
+pub fn run(
+    rom_path: &Path,
+    scale: u32,
+    limit_fps: bool,
+    load_state: Option<&Path>,
+    config: &Config,
+) -> Result<()> {
+    if let Some(state) = load_state {
+        anyhow::ensure!(
+            state.is_file(),
+            "save state {} does not exist",
+            state.display()
+        );
+    }
+    if gamecube::try_launch_dolphin(rom_path, load_state, config, || {
+        Ok(dolphin_args(&config.wii))
+    })? {
+        return Ok(());
+    }
+    if load_state.is_some() {
+        log::warn!("--gc-load-state only works with an installed Dolphin, ignoring it");
+    }
+    if gamecube::try_launch_libretro(rom_path, scale, limit_fps, "Wii", config)? {
+        return Ok(());
+    }
+    bail!(
+        "Wii games need Dolphin: install it (or set DOLPHIN_BIN) or place dolphin_libretro in cores/"
+    )
+}
+
+/// Connects the first `remotes` Wii Remotes and sets up the sensor bar
+fn dolphin_args(config: &WiiConfig) -> Vec<String> {
+    let source = match config.remote {
+        WiiRemote::Emulated => SOURCE_EMULATED,
+        WiiRemote::Real => SOURCE_REAL,
+        WiiRemote::None => SOURCE_NONE,
+    };
+    let mut args: Vec<String> = (0..REMOTE_SLOTS)
+        .map(|slot| {
+            let source = if slot < config.remotes {
+                source
+            } else {
+                SOURCE_NONE
+            };
+            format!("--config=WiiPad.Wiimote{}.Source={source}", slot + 1)
+        })
+        .collect();
+    args.push(format!(
+        "--config=SYSCONF.BT.BAR={}",
+        match config.sensor_bar {
+            SensorBar::Bottom => 0,
+            SensorBar::Top => 1,
+        }
+    ));
+    args.push(format!(
+        "--config=Dolphin.Core.WiimoteContinuousScanning={}",
+        dolphin_bool(config.continuous_scanning)
+    ));
+    args.push(format!(
+        "--config=Dolphin.Core.WiimoteEnableSpeaker={}",
+        dolphin_bool(config.speaker)
+    ));
+    args
+}
+
+fn dolphin_bool(value: bool) -> &'static str {
+    if value { "True" } else { "False" }
+}